@@ -32,6 +32,10 @@ fn raw_point(raw: &[u8]) -> proto::gps_::LocationData_::Point {
     }
 }
 
+extern "C" fn keep_decoding() -> bool {
+    true
+}
+
 #[no_panic]
 fn round_trip() -> Result<(proto::gps_::LocationData, proto::gps_::LocationData), &'static str> {
     let mut points = micropb::heapless::Vec::new();
@@ -77,6 +81,7 @@ fn round_trip() -> Result<(proto::gps_::LocationData, proto::gps_::LocationData)
     let data = encoder.into_writer();
 
     let mut decoder = PbDecoder::new(data.as_slice());
+    decoder.set_should_continue(Some(keep_decoding));
     let mut output_location = proto::gps_::LocationData::default();
     output_location
         .decode(&mut decoder, data.len())
@@ -1,6 +1,7 @@
 //! Configuration options for Protobuf types and fields.
 
-use proc_macro2::Span;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
 use syn::Ident;
 
 use crate::generator::sanitized_ident;
@@ -63,6 +64,96 @@ pub enum OptionalRepr {
     Option,
 }
 
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+/// Handling of repeated field elements that don't fit into a fixed-capacity container during
+/// decoding
+pub enum RepeatedOverflow {
+    /// Return `DecodeError::Capacity`, unless `PbDecoder::ignore_repeated_cap_err` is set.
+    ///
+    /// Default behaviour.
+    Error,
+    /// Drop new elements once the container is full, keeping the oldest elements.
+    DropNewest,
+    /// Drop the oldest element to make room for each new element once the container is full,
+    /// keeping the newest elements.
+    ///
+    /// The container type must implement `DerefMut<Target = [T]>`.
+    DropOldest,
+}
+
+impl RepeatedOverflow {
+    pub(crate) fn generate_path(self) -> TokenStream {
+        match self {
+            RepeatedOverflow::Error => quote! { ::micropb::RepeatedOverflow::Error },
+            RepeatedOverflow::DropNewest => quote! { ::micropb::RepeatedOverflow::DropNewest },
+            RepeatedOverflow::DropOldest => quote! { ::micropb::RepeatedOverflow::DropOldest },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+/// Handling of repeated enum field values that aren't declared in the `.proto` file during
+/// decoding
+pub enum UnknownEnum {
+    /// Keep unknown values in the container, like known values.
+    ///
+    /// Default behaviour.
+    Keep,
+    /// Drop unknown values, counting them in `DecodeStats::unknown_enums`.
+    Drop,
+    /// Return `DecodeError::UnknownEnum`.
+    Error,
+}
+
+impl UnknownEnum {
+    pub(crate) fn generate_path(self) -> TokenStream {
+        match self {
+            UnknownEnum::Keep => quote! { ::micropb::UnknownEnum::Keep },
+            UnknownEnum::Drop => quote! { ::micropb::UnknownEnum::Drop },
+            UnknownEnum::Error => quote! { ::micropb::UnknownEnum::Error },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+/// Encoding of fields with implicit presence, such as Proto3 scalar fields without `optional`
+pub enum ImplicitEncode {
+    /// Skip the field when it has its default value, like other Protobuf implementations.
+    ///
+    /// Default behaviour.
+    #[default]
+    SkipDefault,
+    /// Always encode the field, even when it has its default value.
+    Always,
+    /// Never encode the field.
+    Never,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+/// Inlining attribute placed on generated functions
+pub enum Inline {
+    /// `#[inline]`, which suggests inlining to the compiler
+    Hint,
+    /// `#[inline(always)]`
+    Always,
+    /// `#[inline(never)]`
+    Never,
+}
+
+impl Inline {
+    pub(crate) fn attr(self) -> TokenStream {
+        match self {
+            Inline::Hint => quote! { #[inline] },
+            Inline::Always => quote! { #[inline(always)] },
+            Inline::Never => quote! { #[inline(never)] },
+        }
+    }
+}
+
 macro_rules! config_decl {
     ($($(#[$doc:meta])* $([$placeholder:ident])? $field:ident : $([$placeholder2:ident])? Option<$type:ty>,)+) => {
         #[non_exhaustive]
@@ -131,6 +222,9 @@ config_decl! {
     ///
     /// For example, if `vec_type` is `ArrayVec` and `max_len` is 5, then the generated container
     /// type will be `ArrayVec<_, 5>`.
+    ///
+    /// The generated message also exposes the capacity as a constant named after the field, such
+    /// as `Msg::SAMPLES_MAX_LEN`.
     max_len: Option<u32>,
 
     /// Max number of bytes for fixed-capacity `string` and `bytes` fields.
@@ -139,8 +233,69 @@ config_decl! {
     /// [`string_type`](Config::string_type) or [`vec_type`](Config::vec_type) is a fix-capacity
     /// container, because `max_bytes` will be used as the 2nd type parameter of the container in
     /// the generated code.
+    ///
+    /// Like with `max_len`, the capacity is exposed as a constant on the generated message, such
+    /// as `Msg::NAME_MAX_BYTES`. For repeated fields, it's the capacity of each element.
     max_bytes: Option<u32>,
 
+    /// Truncate `string` fields that don't fit into their fixed-capacity container when decoding,
+    /// rather than returning `DecodeError::Capacity`.
+    ///
+    /// The decoded string keeps as many bytes as the container can hold, cut at a UTF-8 character
+    /// boundary, and the rest of the string on the wire is skipped. This is useful for fields such
+    /// as log messages or descriptions, where a shortened value is preferable to failing the
+    /// decoding of the whole message.
+    ///
+    /// Has no effect on fields that aren't strings.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Strings longer than 32 bytes are kept as their first 32 bytes
+    /// gen.configure(
+    ///     ".Log.message",
+    ///     Config::new().string_type("heapless::String").max_bytes(32).truncate_on_overflow(true)
+    /// );
+    /// ```
+    truncate_on_overflow: Option<bool>,
+
+    /// Determine what happens when a fixed-capacity repeated field receives more elements than it
+    /// can hold while decoding.
+    ///
+    /// By default, decoding fails with `DecodeError::Capacity`. Telemetry history fields are a
+    /// common case where [`RepeatedOverflow::DropOldest`] is preferable, since it keeps the most
+    /// recent samples like a ring buffer.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config, config::RepeatedOverflow};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Keep the 16 newest samples
+    /// gen.configure(
+    ///     ".Telemetry.samples",
+    ///     Config::new().max_len(16).repeated_overflow(RepeatedOverflow::DropOldest)
+    /// );
+    /// ```
+    repeated_overflow: Option<RepeatedOverflow>,
+
+    /// Determine what happens when a repeated enum field receives a value that isn't declared in
+    /// the `.proto` file while decoding.
+    ///
+    /// Generated enums can represent any value, so unknown values are kept by default. Firmware
+    /// that switches over the known variants can use [`UnknownEnum::Drop`] to filter out values
+    /// added in newer schema versions, or [`UnknownEnum::Error`] to reject them. Applies to both
+    /// packed and unpacked elements. Can't be combined with a `repeated_overflow` policy other
+    /// than [`RepeatedOverflow::Error`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config, config::UnknownEnum};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".Status.errors", Config::new().unknown_enum(UnknownEnum::Drop));
+    /// ```
+    unknown_enum: Option<UnknownEnum>,
+
     /// Override the integer type of integer fields such as `int32` or `fixed64`.
     ///
     /// Change the integer fields to be 8, 16, 32, or 64 bytes. If the integer type is smaller than
@@ -164,6 +319,26 @@ config_decl! {
     /// altogether.
     int_size: Option<IntSize>,
 
+    /// Encode negative `int32` values as 5-byte varints, for compatibility with legacy peers.
+    ///
+    /// The Protobuf spec sign-extends negative `int32` values to 64 bits, so they always take up
+    /// 10 bytes on the wire. Some very old proto2 implementations instead encoded them as 32-bit
+    /// varints, which take up 5 bytes, and may not accept the standard encoding. With this option,
+    /// `int32` fields are encoded the legacy way. Decoding still accepts both encodings. Only
+    /// applies to `int32` fields, not to `int64` fields or enums.
+    ///
+    /// Values encoded this way are read correctly as `int32` by other implementations, but not
+    /// as `int64`, so only enable this for peers that need it.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Use the legacy int32 encoding for all fields in the `legacy` package
+    /// gen.configure(".legacy", Config::new().legacy_int32(true));
+    /// ```
+    legacy_int32: Option<bool>,
+
     /// Set attributes for message fields.
     ///
     /// The attribute string will be placed before matched fields. The string must be in the syntax
@@ -196,6 +371,10 @@ config_decl! {
     /// `Option<Box<_>>`.
     ///
     /// This config not apply to elements of repeated and `map` fields.
+    ///
+    /// Recursive messages need at least one boxed field along each cycle of singular and oneof
+    /// fields, otherwise they would have infinite size. The generator returns an error naming the
+    /// fields of the cycle if this isn't the case.
     boxed: Option<bool>,
 
     /// Container type that's generated for `bytes` and repeated fields. The provided type must
@@ -218,6 +397,11 @@ config_decl! {
     /// gen.configure(".pkg.Message.bytes_field", Config::new().vec_type("Vec"));
     /// // repeated field configured to `arrayvec::ArrayVec<T, 5>` (fixed-capacity)
     /// gen.configure(".pkg.Message.list", Config::new().vec_type("arrayvec::ArrayVec").max_len(5));
+    /// // `bytes` field with 32-byte aligned storage, for use with DMA
+    /// gen.configure(
+    ///     ".pkg.Message.dma_buf",
+    ///     Config::new().vec_type("::micropb::container::AlignedVec32").max_bytes(64)
+    /// );
     /// ```
     vec_type: [deref] Option<String>,
 
@@ -288,6 +472,51 @@ config_decl! {
     /// ```
     optional_repr: Option<OptionalRepr>,
 
+    /// Store a message field's payload as undecoded bytes in `micropb::container::Opaque`, rather
+    /// than decoding it into the generated message type.
+    ///
+    /// The payload is copied into the container set by [`vec_type`](Config::vec_type), with a
+    /// capacity of [`max_bytes`](Config::max_bytes) if set, and is encoded back onto the wire
+    /// unchanged. It can be decoded later with `Opaque::decode_as`. This avoids decoding large
+    /// message bodies that aren't always needed, such as the payload of an envelope that's routed
+    /// based on its header fields.
+    ///
+    /// Has no effect on fields that aren't messages.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Generates `body: Opaque<heapless::Vec<u8, 256>>`
+    /// gen.configure(
+    ///     ".Envelope.body",
+    ///     Config::new().opaque(true).vec_type("heapless::Vec").max_bytes(256)
+    /// );
+    /// ```
+    opaque: Option<bool>,
+
+    /// Generate `string` and `bytes` fields as `&'a str` and `&'a [u8]`, borrowing directly from
+    /// the buffer that the message is decoded from.
+    ///
+    /// This avoids copying large payloads into containers, and doesn't require
+    /// [`string_type`](Config::string_type) or [`vec_type`](Config::vec_type) to be set. Messages
+    /// with borrowed fields, as well as messages containing them, get a lifetime parameter `'a`.
+    /// Instead of `MessageDecode`, they implement `MessageDecodeBorrowed`, which decodes from a
+    /// byte slice that outlives the message. Custom fields in these messages must also use `'a`
+    /// as their lifetime.
+    ///
+    /// Has no effect on fields that aren't strings or bytes. For repeated and `map` fields, the
+    /// borrowed types are used as the elements.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Generates `payload: &'a [u8]` and `Packet<'a>`
+    /// gen.configure(".Packet.payload", Config::new().borrowed(true));
+    /// ```
+    borrowed: Option<bool>,
+
     /// Replace generated field with an user-provided type. See
     /// [`CustomField`](crate::config::CustomField) for more info.
     ///
@@ -302,6 +531,11 @@ config_decl! {
     /// This configuration applies to normal field and oneof fields, but won't be applied to
     /// `oneof` variants.
     ///
+    /// When applied to a oneof, the custom type replaces the generated oneof enum. Every variant
+    /// on the wire is passed to `FieldDecode::decode_field` along with its tag, so the handler can
+    /// process each variant directly, such as by routing it to a different queue, without the enum
+    /// ever being constructed.
+    ///
     /// # Interaction with other configs
     /// Setting this config option overrides every other config option that affects the field's
     /// generated type, including `optional_repr`, `int_size`, and `boxed` (but not
@@ -322,9 +556,59 @@ config_decl! {
     ///     ".Message.bar",
     ///     Config::new().custom_field(CustomField::Delegate("foo".to_owned()))
     /// );
+    /// // All variants of the oneof `body` will be handled by `crate::BodyRouter`
+    /// gen.configure(
+    ///     ".Message.body",
+    ///     Config::new().custom_field(CustomField::Type("crate::BodyRouter".to_owned()))
+    /// );
     /// ```
     custom_field: Option<CustomField>,
 
+    /// Give a [custom field](Config::custom_field) implicit presence semantics.
+    ///
+    /// By default, custom fields are always passed to `FieldEncode`, which is responsible for
+    /// deciding whether to write anything out. With this option, the custom type must also
+    /// implement `micropb::field::ImplicitPresence`, and the generated code skips the field
+    /// entirely when the value is the default, like with generated Proto3 scalar fields. This
+    /// also applies to `MessageEncode::compute_size` and
+    /// [`encode_changed`](Config::encode_changed).
+    ///
+    /// Has no effect on fields that aren't custom fields of [`CustomField::Type`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config, config::CustomField};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // `Millivolts` isn't encoded when its value is 0
+    /// gen.configure(
+    ///     ".Battery.voltage",
+    ///     Config::new()
+    ///         .custom_field(CustomField::Type("crate::Millivolts".to_owned()))
+    ///         .implicit_presence(true)
+    /// );
+    /// ```
+    implicit_presence: Option<bool>,
+
+    /// Override when fields with implicit presence are encoded.
+    ///
+    /// Proto3 scalar, `string`, and `bytes` fields without `optional` have implicit presence, so
+    /// they're skipped when encoding their default value. Peers that rely on explicit zeros on the
+    /// wire can be served with [`ImplicitEncode::Always`], while [`ImplicitEncode::Never`] leaves
+    /// the field out of the output entirely, such as when a peer rejects it. Both also apply to
+    /// `MessageEncode::compute_size`, while [`encode_changed`](Config::encode_changed) patches
+    /// only respect `Never`. Has no effect on fields with explicit presence.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config, config::ImplicitEncode};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Encode all fields of `Status` even if they're zero
+    /// gen.configure(".Status", Config::new().implicit_encode(ImplicitEncode::Always));
+    /// // Never send `debug_info`
+    /// gen.configure(".Status.debug_info", Config::new().implicit_encode(ImplicitEncode::Never));
+    /// ```
+    implicit_encode: Option<ImplicitEncode>,
+
     /// Rename a field in the generated Rust struct.
     ///
     /// Instead of the protobuf field name, use a different name for the generated field and its
@@ -353,6 +637,33 @@ config_decl! {
     /// smaller than the value on the wire, the value will be truncated to fit.
     enum_int_size: Option<IntSize>,
 
+    /// Generate name lookups for Protobuf enums.
+    ///
+    /// With this option, the generated enum gets a `VALUES` constant containing all of its
+    /// declared values, a `name()` method that returns the Protobuf name of a value, and a
+    /// `from_name()` function that parses a value from its Protobuf name. This is useful for
+    /// command-line or config shells that map user text to enum values. The names are stored as
+    /// string literals, so this is disabled by default to save flash.
+    ///
+    /// If some values are aliases of each other, `VALUES` and `name()` only use the first
+    /// declaration of each number, while `from_name()` accepts all of the names.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".log.Level", Config::new().enum_names(true));
+    /// ```
+    /// The generated code can then be used like this:
+    /// ```ignore
+    /// assert_eq!(Level::from_name("LEVEL_WARN"), Some(Level::Warn));
+    /// assert_eq!(Level::Warn.name(), Some("LEVEL_WARN"));
+    /// for level in Level::VALUES {
+    ///     println!("{}", level.name().unwrap());
+    /// }
+    /// ```
+    enum_names: Option<bool>,
+
     /// Set attributes for generated types, such as messages and enums.
     ///
     /// The attribute string will be placed before type definitions. The string must be in the
@@ -376,6 +687,9 @@ config_decl! {
     type_attributes: [deref] Option<String>,
 
     /// Disable generating `Debug` trait derives for message types.
+    ///
+    /// To remove `Debug` from all generated types, including enums, use
+    /// [`Generator::debug_impls`](crate::Generator::debug_impls) instead.
     no_debug_impl: Option<bool>,
 
     /// Disable generating `Default` trait impl for message types.
@@ -390,6 +704,145 @@ config_decl! {
     /// Disable generating `Clone` trait derives for message types.
     no_clone_impl: Option<bool>,
 
+    /// Generate message types without any fields as unit structs with trivial decode and encode
+    /// logic.
+    ///
+    /// Messages without fields are zero-sized either way, so fields of their types only take up
+    /// space for their presence bits. With this option, the message is declared as `struct Name;`,
+    /// and decoding skips over the message without parsing it. As a result, malformed data inside
+    /// the message isn't reported as an error.
+    ///
+    /// Has no effect on messages with fields, including fields removed with
+    /// [`skip`](Config::skip), or on messages with an [`unknown_handler`](Config::unknown_handler).
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Generate all empty messages, such as `google.protobuf.Empty`, as unit structs
+    /// gen.configure(".", Config::new().unit_struct(true));
+    /// ```
+    unit_struct: Option<bool>,
+
+    /// Generate `MessageEncode::encode_changed` and `MessageEncode::compute_changed_size` for
+    /// message types, which only encode the fields that differ from a base message.
+    ///
+    /// The resulting patch can be applied onto a copy of the base message with
+    /// `MessageDecode::apply_patch`, which is useful for syncing settings without sending the
+    /// whole message. Fields are encoded as follows:
+    /// - Fields are encoded if they're set and differ from the base. Clearing an optional field or
+    ///   resetting an implicit presence field to zero can't be expressed in a patch.
+    /// - Message fields that are set in both messages are patched recursively.
+    /// - Repeated fields only encode the new elements if the base is a prefix of the field.
+    ///   Otherwise, the whole field is encoded, which is appended to the existing elements when
+    ///   applied.
+    /// - `map` fields, oneofs, and [custom fields](Config::custom_field) are encoded in full if
+    ///   they differ. Unknown fields are never encoded.
+    ///
+    /// Comparisons require all field types to implement `PartialEq`, so this option doesn't work
+    /// with [`no_partial_eq_impl`](Config::no_partial_eq_impl). Message fields must also have
+    /// this option enabled to be patched recursively.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".settings.Settings", Config::new().encode_changed(true));
+    /// ```
+    encode_changed: Option<bool>,
+
+    /// Implement `micropb::MessageMerge` for message types, which merges another instance of the
+    /// message into `self` with the Protobuf merge semantics.
+    ///
+    /// Fields are merged as follows:
+    /// - Optional fields that are set in the other message overwrite the values in `self`, while
+    ///   implicit presence fields are only overwritten by non-zero values.
+    /// - Message fields that are set in both messages are merged recursively.
+    /// - Repeated and `map` fields are appended to. Full fixed-capacity containers return an
+    ///   error, unless the field has a [`repeated_overflow`](Config::repeated_overflow) policy,
+    ///   which drops elements the same way as when decoding.
+    /// - Oneofs are overwritten, unless both messages have the same message variant set, which is
+    ///   merged recursively.
+    /// - [Custom fields](Config::custom_field) and unknown fields aren't merged.
+    ///
+    /// Cloning the fields requires all field types to implement `Clone`, so this option doesn't
+    /// work with [`no_clone_impl`](Config::no_clone_impl). Message fields must also have this
+    /// option enabled.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".", Config::new().merge_impl(true));
+    /// ```
+    merge_impl: Option<bool>,
+
+    /// Generate deterministic encoding logic for message types, which produces the same bytes for
+    /// equal messages.
+    ///
+    /// Fields and oneof variants are encoded in ascending field number order, rather than in
+    /// declaration order, and `map` entries are encoded in ascending key order, which requires the
+    /// map key types to implement `Ord`. Sorting map entries doesn't allocate, so it takes
+    /// quadratic time in the number of entries. Unknown fields are still encoded last, in the
+    /// order they were decoded.
+    ///
+    /// Applies to `MessageEncode::encode` and [`encode_changed`](Config::encode_changed). Message
+    /// fields must also have this option enabled for the whole output to be deterministic.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Encode all messages in the package deterministically, so they can be hashed
+    /// gen.configure(".signed", Config::new().deterministic_encode(true));
+    /// ```
+    deterministic_encode: Option<bool>,
+
+    /// Implement `micropb::DefaultInstance` for the message type, which provides a shared static
+    /// default instance of the message.
+    ///
+    /// This allows unset message fields to be read without constructing a default message, such
+    /// as with `Inner::or_default(outer.inner())`, similar to default instances in Protobuf C++.
+    /// The static instance is constructed lazily on first access.
+    ///
+    /// Requires the `Default` impl, so this option doesn't work with
+    /// [`no_default_impl`](Config::no_default_impl). The message type must be `Send + Sync` and
+    /// can't have a lifetime parameter. The target must also support atomic compare-and-swap.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".telemetry.Header", Config::new().default_instance(true));
+    /// ```
+    default_instance: Option<bool>,
+
+    /// Set the inlining attribute of the generated `MessageDecode::decode` function of message
+    /// types.
+    ///
+    /// By default, no inlining attribute is generated, so the compiler decides whether to inline
+    /// the decode logic into its callers. Inlining large decode functions into an interrupt
+    /// handler or other stack-constrained code path can greatly increase its stack usage, which
+    /// can be prevented by setting this to [`Inline::Never`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config, config::Inline};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Never inline the decode logic of any message in the package
+    /// gen.configure(".pkg", Config::new().decode_inline(Inline::Never));
+    /// // Except for this small message
+    /// gen.configure(".pkg.Small", Config::new().decode_inline(Inline::Always));
+    /// ```
+    decode_inline: Option<Inline>,
+
+    /// Set the inlining attribute of the generated `MessageEncode::encode` and
+    /// `MessageEncode::compute_size` functions of message types.
+    ///
+    /// Like [`decode_inline`](Config::decode_inline), no inlining attribute is generated by
+    /// default.
+    encode_inline: Option<Inline>,
+
     /// Add a custom handler on a message struct for handling unknown fields.
     ///
     /// When decoding a message, unknown fields are skipped by default. If a message has
@@ -399,6 +852,86 @@ config_decl! {
     /// like with [`custom_field`](Config::custom_field).
     unknown_handler: [deref] Option<String>,
 
+    /// Preserve the unknown fields of a message, so that they're written back when it's encoded.
+    ///
+    /// Adds a `_unknown` field of type `micropb::callback::UnknownFields`, which stores the raw
+    /// bytes of all unknown fields in the container set by [`vec_type`](Config::vec_type) for
+    /// `_unknown`, with a capacity of [`max_bytes`](Config::max_bytes) if set. This lets proxies
+    /// and firmware forward messages from peers on newer schema versions without losing data.
+    /// Can't be combined with [`unknown_handler`](Config::unknown_handler).
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Generates `_unknown: UnknownFields<heapless::Vec<u8, 64>>`
+    /// gen.configure(".Telemetry", Config::new().unknown_fields(true));
+    /// gen.configure(
+    ///     ".Telemetry._unknown",
+    ///     Config::new().vec_type("heapless::Vec").max_bytes(64)
+    /// );
+    /// ```
+    unknown_fields: Option<bool>,
+
+    /// Reject unknown fields when decoding a message.
+    ///
+    /// By default, fields with numbers that aren't in the message definition are skipped. With
+    /// this option, decoding such a field fails with `DecodeError::UnknownField` instead, which
+    /// carries the field number. This is useful for applications that must only accept input
+    /// matching the compiled schema exactly. Configuring it on a package applies it to all
+    /// messages in the package. Can't be combined with
+    /// [`unknown_handler`](Config::unknown_handler) or [`unknown_fields`](Config::unknown_fields).
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Reject unknown fields in all messages
+    /// gen.configure(".", Config::new().reject_unknown_fields(true));
+    /// ```
+    reject_unknown_fields: Option<bool>,
+
+    /// Enforce the presence of `required` fields when decoding a message.
+    ///
+    /// Messages with `required` fields always get `is_initialized()` and
+    /// `missing_required_field()` methods, which check the presence of those fields. With this
+    /// option, decoding a message also fails with `DecodeError::MissingRequired` if any of its
+    /// `required` fields are missing after decoding, which carries the number of the missing
+    /// field. The fields are checked by `MessageDecode::check_required`, which runs once the
+    /// whole message has been decoded, rather than by `MessageDecode::decode`, which may only be
+    /// given some of the fields. Configuring it on a package applies it to all messages in the
+    /// package.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Check required fields in all messages
+    /// gen.configure(".", Config::new().enforce_required(true));
+    /// ```
+    enforce_required: Option<bool>,
+
+    /// Record the field numbers leading to a decoding failure.
+    ///
+    /// With this option, the generated decode logic calls `PbDecoder::push_error_field` with the
+    /// number of the field that was being decoded whenever decoding fails. When nested messages
+    /// are also generated with this option, the result is the chain of field numbers from the
+    /// outermost message to the failing field, such as `3.7.1`. It can be retrieved by passing
+    /// the error to `PbDecoder::error_ctx`, which returns a `DecodeErrorCtx`. This requires
+    /// the `field-path` feature of `micropb`; without it, the generated calls have no effect.
+    ///
+    /// This adds some code to every decode implementation, so it's disabled by default.
+    /// Configuring it on a package applies it to all messages in the package.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Record field paths on decode errors in all messages
+    /// gen.configure(".", Config::new().field_path_errors(true));
+    /// ```
+    field_path_errors: Option<bool>,
+
     // General configs
 
     /// Skip generating a type or field
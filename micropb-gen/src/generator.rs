@@ -1,13 +1,13 @@
 use std::{
     borrow::{Borrow, Cow},
     cell::RefCell,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     ffi::OsString,
     io,
     path::PathBuf,
 };
 
-use convert_case::{Case, Casing};
+use convert_case::{Boundary, Case, Casing};
 use proc_macro2::{Literal, Span, TokenStream};
 use quote::{format_ident, quote};
 use syn::{Attribute, Ident};
@@ -18,6 +18,7 @@ use crate::{
         DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FileDescriptorProto,
         FileDescriptorSet,
     },
+    hook::{GeneratedItem, Hook, ItemKind},
     pathtree::{Node, PathTree},
     split_pkg_name, EncodeDecode,
 };
@@ -27,19 +28,29 @@ use super::WarningCb;
 
 pub(crate) mod field;
 pub(crate) mod message;
+pub(crate) mod nesting;
 pub(crate) mod oneof;
 pub(crate) mod type_spec;
 
-fn derive_msg_attr(debug: bool, default: bool, partial_eq: bool, clone: bool) -> TokenStream {
+fn derive_msg_attr(
+    debug: bool,
+    default: bool,
+    partial_eq: bool,
+    clone: bool,
+    defmt: bool,
+) -> TokenStream {
     let debug = debug.then(|| quote! { Debug, });
     let default = default.then(|| quote! { Default, });
     let partial_eq = partial_eq.then(|| quote! { PartialEq, });
     let clone = clone.then(|| quote! { Clone, });
-    quote! { #[derive(#debug #default #partial_eq #clone)] }
+    let defmt = defmt.then(|| quote! { ::defmt::Format, });
+    quote! { #[derive(#debug #default #partial_eq #clone #defmt)] }
 }
 
-fn derive_enum_attr() -> TokenStream {
-    quote! { #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)] }
+fn derive_enum_attr(debug: bool, defmt: bool) -> TokenStream {
+    let debug = debug.then(|| quote! { Debug, });
+    let defmt = defmt.then(|| quote! { , ::defmt::Format });
+    quote! { #[derive(#debug Clone, Copy, PartialEq, Eq, Hash #defmt)] }
 }
 
 pub(crate) struct CurrentConfig<'a> {
@@ -159,11 +170,24 @@ pub struct Generator {
     pub(crate) encode_decode: EncodeDecode,
     pub(crate) retain_enum_prefix: bool,
     pub(crate) format: bool,
+    pub(crate) debug_impls: bool,
+    pub(crate) defmt_impls: bool,
+    pub(crate) heap_size_impls: bool,
+    pub(crate) roundtrip_tests: bool,
+    pub(crate) prost_root: Option<TokenStream>,
     pub(crate) fdset_path: Option<PathBuf>,
     pub(crate) protoc_args: Vec<OsString>,
+    pub(crate) out_dir: Option<PathBuf>,
+    pub(crate) verify: bool,
 
     pub(crate) config_tree: PathTree<Box<Config>>,
     pub(crate) extern_paths: HashMap<String, TokenStream>,
+    pub(crate) hooks: Vec<Hook>,
+
+    pub(crate) max_nesting_depth: Option<u32>,
+    pub(crate) nesting_depths: HashMap<String, nesting::Depth>,
+    pub(crate) borrowed_msgs: HashSet<String>,
+    pub(crate) clearable_msgs: HashSet<String>,
 }
 
 impl Generator {
@@ -176,11 +200,37 @@ impl Generator {
 
     pub(crate) fn generate_fdset(&mut self, fdset: &FileDescriptorSet) -> io::Result<TokenStream> {
         let mut mod_tree = PathTree::new(TokenStream::new());
-
-        for file in &fdset.file {
+        let graph = nesting::build_graph(self, fdset);
+        if let Some(cycle) = nesting::find_unboxed_cycle(&graph) {
+            let (msg, _) = cycle[0]
+                .rsplit_once('.')
+                .expect("field path should contain a dot");
+            return Err(io::Error::other(format!(
+                "({msg}) Message contains itself through the fields {} without indirection, so it \
+                 would have infinite size. Set `boxed` on one of these fields, or on the oneof \
+                 containing it.",
+                cycle.join(" -> ")
+            )));
+        }
+        self.nesting_depths = nesting::nesting_depths(&graph);
+        self.borrowed_msgs = nesting::borrowed_msgs(&graph);
+        self.clearable_msgs = nesting::clearable_msgs(&graph);
+        let extern_assertions = self.generate_extern_assertions(nesting::external_refs(&graph));
+
+        // Generate files in name order rather than in the order they were passed to `protoc`, so
+        // that the output doesn't change when the inputs are reordered
+        let mut files: Vec<_> = fdset.file.iter().collect();
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        for file in files {
             let code = self.generate_fdproto(file)?;
             if let Some(pkg_name) = file.package() {
-                *mod_tree.root.add_path(split_pkg_name(pkg_name)).value_mut() = Some(code);
+                // Multiple files can share the same package
+                mod_tree
+                    .root
+                    .add_path(split_pkg_name(pkg_name))
+                    .value_mut()
+                    .get_or_insert_with(TokenStream::new)
+                    .extend([code]);
             } else {
                 mod_tree
                     .root
@@ -191,7 +241,48 @@ impl Generator {
             }
         }
 
-        Ok(generate_mod_tree(&mut mod_tree.root))
+        let code = generate_mod_tree(&mut mod_tree.root);
+        Ok(quote! {
+            #code
+            #extern_assertions
+        })
+    }
+
+    /// Generate compile-time checks that extern message types implement the traits required by
+    /// the generated code, so that missing impls are reported on the extern type's path rather
+    /// than deep inside the generated code.
+    fn generate_extern_assertions<'a>(
+        &self,
+        refs: impl IntoIterator<Item = &'a str>,
+    ) -> Option<TokenStream> {
+        let types: Vec<_> = refs
+            .into_iter()
+            .filter_map(|name| self.extern_paths.get(name))
+            // Can't name types with lifetime parameters outside of a generic context
+            .filter(|path| !path.to_string().contains('\''))
+            .collect();
+        if types.is_empty() {
+            return None;
+        }
+
+        let decode_bounds = self
+            .encode_decode
+            .is_decode()
+            .then(|| quote! { + ::micropb::MessageDecode + ::core::default::Default });
+        let encode_bounds = self
+            .encode_decode
+            .is_encode()
+            .then(|| quote! { + ::micropb::MessageEncode });
+        Some(quote! {
+            const _: () = {
+                #[allow(dead_code)]
+                fn assert_extern_message<T: ?Sized #decode_bounds #encode_bounds>() {}
+                #[allow(dead_code)]
+                fn assert_extern_messages() {
+                    #(assert_extern_message::<#types>();)*
+                }
+            };
+        })
     }
 
     pub(crate) fn generate_fdproto(
@@ -208,31 +299,77 @@ impl Generator {
             .map(|s| split_pkg_name(s).map(ToOwned::to_owned).collect())
             .unwrap_or_default();
         self.pkg = fdproto.package().cloned().unwrap_or_default();
+        let cur_config = self.pkg_config(&self.pkg);
 
+        let mut out = TokenStream::new();
+        for m in &fdproto.message_type {
+            out.extend(self.generate_msg(m, cur_config.next_conf(&m.name))?);
+        }
+        for e in &fdproto.enum_type {
+            out.extend(self.generate_enum(e, cur_config.next_conf(&e.name))?);
+        }
+
+        Ok(out)
+    }
+
+    /// Returns the merged configuration of a package
+    pub(crate) fn pkg_config(&self, pkg: &str) -> CurrentConfig<'_> {
         let root_node = &self.config_tree.root;
         let mut conf = root_node
             .access_value()
             .as_ref()
             .expect("root config should exist")
             .clone();
-        let node = root_node.visit_path(
-            split_pkg_name(fdproto.package().map(String::as_str).unwrap_or("")),
-            |next_conf| conf.merge(next_conf),
-        );
-        let cur_config = CurrentConfig {
+        let node = root_node.visit_path(split_pkg_name(pkg), |next_conf| conf.merge(next_conf));
+        CurrentConfig {
             node,
             config: Cow::Owned(conf),
-        };
-
-        let mut out = TokenStream::new();
-        for m in &fdproto.message_type {
-            out.extend(self.generate_msg(m, cur_config.next_conf(&m.name))?);
         }
-        for e in &fdproto.enum_type {
-            out.extend(self.generate_enum(e, cur_config.next_conf(&e.name))?);
+    }
+
+    /// Returns the fully-qualified Protobuf name of a message or enum in the current type path
+    fn fq_msg_name(&self, name: &str) -> String {
+        let mut fq_name = String::new();
+        for elem in self.pkg_path.iter().chain(self.type_path.borrow().iter()) {
+            fq_name.push('.');
+            fq_name.push_str(elem);
         }
+        fq_name.push('.');
+        fq_name.push_str(name);
+        fq_name
+    }
 
-        Ok(out)
+    /// Returns the path of the prost-generated type of a message in the current type path,
+    /// following the module and type naming of `prost-build`
+    fn prost_type_path(&self, prost_root: &TokenStream, name: &str) -> TokenStream {
+        let type_path = self.type_path.borrow();
+        let mods = self
+            .pkg_path
+            .iter()
+            .chain(type_path.iter())
+            .map(|elem| prost_ident(&prost_case(elem, Case::Snake)));
+        let ty = prost_ident(&prost_case(name, Case::UpperCamel));
+        quote! { #prost_root #(:: #mods)* :: #ty }
+    }
+
+    /// Returns the worst-case nesting depth of a message in the current type path
+    pub(crate) fn nesting_depth(&self, name: &str) -> nesting::Depth {
+        self.nesting_depths
+            .get(&self.fq_msg_name(name))
+            .copied()
+            .unwrap_or(nesting::Depth::Unknown)
+    }
+
+    /// Returns whether a message borrows from the decode buffer, given its fully-qualified
+    /// Protobuf name
+    pub(crate) fn is_borrowed_msg(&self, fq_name: &str) -> bool {
+        self.borrowed_msgs.contains(fq_name)
+    }
+
+    /// Returns whether a message has a generated `clear()` method, given its fully-qualified
+    /// Protobuf name
+    pub(crate) fn is_clearable_msg(&self, fq_name: &str) -> bool {
+        self.clearable_msgs.contains(fq_name)
     }
 
     fn generate_enum_decl(
@@ -241,12 +378,24 @@ impl Generator {
         values: &[EnumValueDescriptorProto],
         enum_int_type: IntSize,
         attrs: &[Attribute],
+        names: bool,
     ) -> TokenStream {
         let nums = values.iter().map(|v| Literal::i32_unsuffixed(v.number));
+        let known_nums = nums.clone();
         let var_names = values.iter().map(|v| self.enum_variant_name(&v.name, name));
         let default_num = Literal::i32_unsuffixed(values[0].number);
-        let derive_enum = derive_enum_attr();
+        let derive_enum = derive_enum_attr(self.debug_impls, self.defmt_impls);
         let itype = enum_int_type.type_name(true);
+        let name_lookups = names.then(|| self.generate_enum_names(name, values));
+        let heap_size = self.heap_size_impls.then(|| {
+            quote! {
+                impl ::micropb::HeapSize for #name {
+                    fn heap_bytes_used(&self) -> usize {
+                        0
+                    }
+                }
+            }
+        });
 
         quote! {
             #derive_enum
@@ -269,6 +418,62 @@ impl Generator {
                     Self(val)
                 }
             }
+
+            impl ::micropb::PbEnum for #name {
+                fn from_i32(val: i32) -> Self {
+                    Self(val as _)
+                }
+
+                fn to_i32(self) -> i32 {
+                    self.0 as _
+                }
+
+                fn is_known(self) -> bool {
+                    matches!(self.0, #(#known_nums)|*)
+                }
+            }
+
+            #name_lookups
+            #heap_size
+        }
+    }
+
+    /// Generate `VALUES`, `name()`, and `from_name()` for an enum. Aliases are left out of
+    /// `VALUES` and `name()`, so that each number only appears once.
+    fn generate_enum_names(
+        &self,
+        name: &Ident,
+        values: &[EnumValueDescriptorProto],
+    ) -> TokenStream {
+        let mut seen = HashSet::new();
+        let unique: Vec<_> = values.iter().filter(|v| seen.insert(v.number)).collect();
+        let unique_vars = unique.iter().map(|v| self.enum_variant_name(&v.name, name));
+        let unique_nums = unique.iter().map(|v| Literal::i32_unsuffixed(v.number));
+        let unique_names = unique.iter().map(|v| &v.name);
+        let all_names = values.iter().map(|v| &v.name);
+        let all_vars = values.iter().map(|v| self.enum_variant_name(&v.name, name));
+
+        quote! {
+            impl #name {
+                /// All values declared in the Protobuf enum
+                pub const VALUES: &'static [Self] = &[#(Self::#unique_vars),*];
+
+                /// Name of the value in the Protobuf enum, or `None` if the value is unknown
+                pub fn name(&self) -> ::core::option::Option<&'static str> {
+                    match self.0 {
+                        #(#unique_nums => ::core::option::Option::Some(#unique_names),)*
+                        _ => ::core::option::Option::None,
+                    }
+                }
+
+                /// Parse a value from its name in the Protobuf enum
+                pub fn from_name(name: &str) -> ::core::option::Option<Self> {
+                    match name {
+                        #(#all_names => ::core::option::Option::Some(Self::#all_vars),)*
+                        _ => ::core::option::Option::None,
+                    }
+                }
+            }
         }
     }
 
@@ -287,8 +492,34 @@ impl Generator {
             .config
             .type_attr_parsed()
             .map_err(|e| msg_error(&self.pkg, &enum_type.name, &e))?;
-        let out = self.generate_enum_decl(&name, &enum_type.value, enum_int_type, attrs);
-        Ok(out)
+        let names = enum_conf.config.enum_names.unwrap_or(false);
+        let out = self.generate_enum_decl(&name, &enum_type.value, enum_int_type, attrs, names);
+        Ok(self.run_hooks(ItemKind::Enum, &enum_type.name, &name, out))
+    }
+
+    /// Append the output of every hook to the generated code of a type
+    fn run_hooks(
+        &self,
+        kind: ItemKind,
+        name: &str,
+        rust_name: &Ident,
+        tokens: TokenStream,
+    ) -> TokenStream {
+        if self.hooks.is_empty() {
+            return tokens;
+        }
+        let proto_name = self.fq_msg_name(name);
+        let item = GeneratedItem {
+            kind,
+            proto_name: &proto_name,
+            rust_name,
+            tokens: &tokens,
+        };
+        let extra: Vec<_> = self.hooks.iter().map(|hook| hook(&item)).collect();
+        quote! {
+            #tokens
+            #(#extra)*
+        }
     }
 
     fn generate_msg_mod(
@@ -316,7 +547,7 @@ impl Generator {
         }
 
         let (hazzer_decl, hazzer_field_attr) = match msg
-            .generate_hazzer_decl(msg_conf.next_conf("_has"))
+            .generate_hazzer_decl(self, msg_conf.next_conf("_has"))
             .map_err(|e| field_error(&self.pkg, msg.name, "_has", &e))?
         {
             Some((d, a)) => (Some(d), Some(a)),
@@ -342,12 +573,31 @@ impl Generator {
         let Some(msg) = Message::from_proto(proto, self, &msg_conf)? else {
             return Ok(quote! {});
         };
+        if let Some(max_depth) = self.max_nesting_depth {
+            let err = match self.nesting_depth(msg.name) {
+                nesting::Depth::Recursive => Some(format!(
+                    "Message is recursive, so its nesting depth can exceed the limit of {max_depth}"
+                )),
+                nesting::Depth::Unknown => Some(format!(
+                    "Message contains a type that isn't in the compiled files, such as an extern \
+                     type, so its nesting depth can't be checked against the limit of {max_depth}"
+                )),
+                nesting::Depth::Bounded(depth) if depth > max_depth => Some(format!(
+                    "Message nesting depth of {depth} exceeds the limit of {max_depth}"
+                )),
+                _ => None,
+            };
+            if let Some(err) = err {
+                return Err(msg_error(&self.pkg, msg.name, &err));
+            }
+        }
+
         let (msg_mod, hazzer_field_attr) = self.generate_msg_mod(&msg, proto, &msg_conf)?;
         let unknown_conf = msg_conf.next_conf("_unknown");
 
         let default = msg.generate_default_impl(self, hazzer_field_attr.is_some())?;
         let decl = msg.generate_decl(self, hazzer_field_attr, &unknown_conf)?;
-        let msg_impl = msg.generate_impl(self);
+        let msg_impl = msg.generate_impl(self)?;
         let decode = self
             .encode_decode
             .is_decode()
@@ -356,15 +606,32 @@ impl Generator {
             .encode_decode
             .is_encode()
             .then(|| msg.generate_encode_trait(self));
+        let heap_size = self.heap_size_impls.then(|| msg.generate_heap_size_impl());
+        let merge = msg.merge_impl.then(|| msg.generate_merge_impl());
+        let roundtrip_test = (self.roundtrip_tests
+            && self.encode_decode.is_encode()
+            && self.encode_decode.is_decode())
+        .then(|| msg.generate_roundtrip_test())
+        .flatten();
+        let prost_conversions = self
+            .prost_root
+            .as_ref()
+            .filter(|_| self.encode_decode.is_encode() && self.encode_decode.is_decode())
+            .and_then(|root| msg.generate_prost_conversions(&self.prost_type_path(root, msg.name)));
 
-        Ok(quote! {
+        let out = quote! {
             #msg_mod
             #decl
             #default
             #msg_impl
             #decode
             #encode
-        })
+            #heap_size
+            #merge
+            #roundtrip_test
+            #prost_conversions
+        };
+        Ok(self.run_hooks(ItemKind::Message, msg.name, &msg.rust_name, out))
     }
 
     fn resolve_type_name(&self, pb_fq_type_name: &str) -> TokenStream {
@@ -403,6 +670,8 @@ impl Generator {
         let stripped = if !self.retain_enum_prefix {
             variant_name_cased
                 .strip_prefix(&enum_name.to_string())
+                // Don't strip variant names that are identical to the enum name
+                .filter(|s| !s.is_empty())
                 .unwrap_or(&variant_name_cased)
         } else {
             &variant_name_cased
@@ -463,6 +732,25 @@ pub(crate) fn sanitized_ident(name: &str) -> Ident {
     }
 }
 
+/// Converts the case of a name like `prost-build` does, which only splits words before a digit if
+/// it's followed by an uppercase letter
+fn prost_case(name: &str, case: Case) -> String {
+    use Boundary::*;
+    name.with_boundaries(&[Underscore, Hyphen, Space, LowerUpper, DigitUpper, Acronym])
+        .to_case(case)
+}
+
+/// Identifier escaped the way `prost-build` escapes it
+fn prost_ident(name: &str) -> Ident {
+    match name {
+        "_" | "super" | "crate" | "self" | "Self" | "extern" => format_ident!("{name}_"),
+        name if name.starts_with(|c: char| c.is_lowercase()) => {
+            Ident::new_raw(name, Span::call_site())
+        }
+        name => Ident::new(name, Span::call_site()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{config::parse_attributes, descriptor::EnumValueDescriptorProto};
@@ -481,6 +769,10 @@ mod tests {
             gen.enum_variant_name("ALIEN", &enum_name).to_string(),
             "Alien"
         );
+        assert_eq!(
+            gen.enum_variant_name("ENUM", &enum_name).to_string(),
+            "Enum"
+        );
 
         gen.retain_enum_prefix = true;
         assert_eq!(
@@ -528,6 +820,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn prost_type_path() {
+        let mut gen = Generator::new();
+        let root = quote! { crate::prost_pb };
+        assert_eq!(
+            gen.prost_type_path(&root, "ZST").to_string(),
+            quote! { crate::prost_pb::Zst }.to_string()
+        );
+
+        gen.pkg_path.push("demo".to_owned());
+        gen.pkg_path.push("v1".to_owned());
+        gen.type_path.borrow_mut().push("SensorReading".to_owned());
+        gen.type_path.borrow_mut().push("Self".to_owned());
+        assert_eq!(
+            gen.prost_type_path(&root, "raw_sample").to_string(),
+            quote! { crate::prost_pb::r#demo::r#v1::r#sensor_reading::self_::RawSample }
+                .to_string()
+        );
+    }
+
     #[test]
     fn enum_basic() {
         let name = Ident::new("Test", Span::call_site());
@@ -541,7 +853,7 @@ mod tests {
         value[1].set_number(2);
         let gen = Generator::new();
 
-        let out = gen.generate_enum_decl(&name, &value, IntSize::S32, &[]);
+        let out = gen.generate_enum_decl(&name, &value, IntSize::S32, &[], false);
         let expected = quote! {
             #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
             #[repr(transparent)]
@@ -563,6 +875,95 @@ mod tests {
                     Self(val)
                 }
             }
+
+            impl ::micropb::PbEnum for Test {
+                fn from_i32(val: i32) -> Self {
+                    Self(val as _)
+                }
+
+                fn to_i32(self) -> i32 {
+                    self.0 as _
+                }
+
+                fn is_known(self) -> bool {
+                    matches!(self.0, 1 | 2)
+                }
+            }
+        };
+        assert_eq!(out.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn enum_no_debug() {
+        let name = Ident::new("Test", Span::call_site());
+        let mut value = vec![EnumValueDescriptorProto::default()];
+        value[0].set_name("ONE".to_owned());
+        value[0].set_number(1);
+        let mut gen = Generator::new();
+        gen.debug_impls(false);
+
+        let out = gen.generate_enum_decl(&name, &value, IntSize::S32, &[], false);
+        let derive = quote! { #[derive(Clone, Copy, PartialEq, Eq, Hash)] };
+        assert!(out.to_string().starts_with(&derive.to_string()));
+    }
+
+    #[test]
+    fn enum_defmt() {
+        let name = Ident::new("Test", Span::call_site());
+        let mut value = vec![EnumValueDescriptorProto::default()];
+        value[0].set_name("ONE".to_owned());
+        value[0].set_number(1);
+        let mut gen = Generator::new();
+        gen.defmt_impls(true);
+
+        let out = gen.generate_enum_decl(&name, &value, IntSize::S32, &[], false);
+        let derive = quote! { #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ::defmt::Format)] };
+        assert!(out.to_string().starts_with(&derive.to_string()));
+    }
+
+    #[test]
+    fn enum_names() {
+        let name = Ident::new("Test", Span::call_site());
+        let mut value: Vec<_> = (0..3)
+            .map(|_| EnumValueDescriptorProto::default())
+            .collect();
+        value[0].set_name("TEST_ONE".to_owned());
+        value[0].set_number(1);
+        value[1].set_name("TEST_TWO".to_owned());
+        value[1].set_number(2);
+        // Alias of TEST_ONE
+        value[2].set_name("TEST_FIRST".to_owned());
+        value[2].set_number(1);
+        let gen = Generator::new();
+
+        let out = gen.generate_enum_decl(&name, &value, IntSize::S32, &[], false);
+        assert!(!out.to_string().contains("VALUES"));
+
+        let out = gen.generate_enum_names(&name, &value);
+        let expected = quote! {
+            impl Test {
+                /// All values declared in the Protobuf enum
+                pub const VALUES: &'static [Self] = &[Self::One, Self::Two];
+
+                /// Name of the value in the Protobuf enum, or `None` if the value is unknown
+                pub fn name(&self) -> ::core::option::Option<&'static str> {
+                    match self.0 {
+                        1 => ::core::option::Option::Some("TEST_ONE"),
+                        2 => ::core::option::Option::Some("TEST_TWO"),
+                        _ => ::core::option::Option::None,
+                    }
+                }
+
+                /// Parse a value from its name in the Protobuf enum
+                pub fn from_name(name: &str) -> ::core::option::Option<Self> {
+                    match name {
+                        "TEST_ONE" => ::core::option::Option::Some(Self::One),
+                        "TEST_TWO" => ::core::option::Option::Some(Self::Two),
+                        "TEST_FIRST" => ::core::option::Option::Some(Self::First),
+                        _ => ::core::option::Option::None,
+                    }
+                }
+            }
         };
         assert_eq!(out.to_string(), expected.to_string());
     }
@@ -583,6 +984,7 @@ mod tests {
             &value,
             IntSize::S8,
             &parse_attributes("#[derive(Serialize)]").unwrap(),
+            false,
         );
         let expected = quote! {
             #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -605,6 +1007,20 @@ mod tests {
                     Self(val)
                 }
             }
+
+            impl ::micropb::PbEnum for Enum {
+                fn from_i32(val: i32) -> Self {
+                    Self(val as _)
+                }
+
+                fn to_i32(self) -> i32 {
+                    self.0 as _
+                }
+
+                fn is_known(self) -> bool {
+                    matches!(self.0, 1)
+                }
+            }
         };
         assert_eq!(out.to_string(), expected.to_string());
     }
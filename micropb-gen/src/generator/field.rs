@@ -1,8 +1,9 @@
+use convert_case::{Case, Casing};
 use proc_macro2::{Literal, Span, TokenStream};
 use quote::{format_ident, quote};
 use syn::{Ident, Lifetime};
 
-use crate::config::OptionalRepr;
+use crate::config::{ImplicitEncode, OptionalRepr, RepeatedOverflow, UnknownEnum};
 use crate::descriptor::{
     DescriptorProto, FieldDescriptorProto,
     FieldDescriptorProto_::{Label, Type},
@@ -28,6 +29,8 @@ pub(crate) enum FieldType {
         val: TypeSpec,
         type_path: syn::Path,
         max_len: Option<u32>,
+        // Encode entries in key order
+        sorted: bool,
     },
     // Implicit presence
     Single(TypeSpec),
@@ -38,6 +41,8 @@ pub(crate) enum FieldType {
         packed: bool,
         type_path: syn::Path,
         max_len: Option<u32>,
+        overflow: RepeatedOverflow,
+        unknown_enum: UnknownEnum,
     },
     Custom(CustomField),
 }
@@ -55,6 +60,12 @@ pub(crate) struct Field<'a> {
     pub(crate) default: Option<&'a str>,
     pub(crate) boxed: bool,
     pub(crate) attrs: Vec<syn::Attribute>,
+    /// Skip encoding custom field if it's the default value, via `ImplicitPresence`
+    pub(crate) custom_implicit_presence: bool,
+    /// Field is labelled `required` in a proto2 file
+    pub(crate) required: bool,
+    /// When the field is encoded if it has implicit presence
+    pub(crate) implicit_encode: ImplicitEncode,
 }
 
 impl<'a> Field<'a> {
@@ -106,20 +117,44 @@ impl<'a> Field<'a> {
                     val,
                     type_path,
                     max_len: field_conf.config.max_len,
+                    sorted: field_conf.config.deterministic_encode.unwrap_or(false),
                 }
             }
 
-            (None, None, Label::Repeated) => FieldType::Repeated {
-                typ: TypeSpec::from_proto(proto, &field_conf.next_conf("elem"))?,
-                type_path: field_conf.config.vec_type_parsed()?.ok_or_else(|| {
-                    "Field is repeated, but vec_type was not configured for it".to_owned()
-                })?,
-                max_len: field_conf.config.max_len,
-                packed: proto
-                    .options()
-                    .and_then(|opt| opt.packed().copied())
-                    .unwrap_or(false),
-            },
+            (None, None, Label::Repeated) => {
+                let typ = TypeSpec::from_proto(proto, &field_conf.next_conf("elem"))?;
+                let overflow = field_conf
+                    .config
+                    .repeated_overflow
+                    .unwrap_or(RepeatedOverflow::Error);
+                let unknown_enum = field_conf.config.unknown_enum.unwrap_or(UnknownEnum::Keep);
+                if matches!(typ, TypeSpec::Enum(_))
+                    && !matches!(unknown_enum, UnknownEnum::Keep)
+                    && !matches!(overflow, RepeatedOverflow::Error)
+                {
+                    return Err(
+                        "unknown_enum can't be combined with a repeated_overflow policy".to_owned(),
+                    );
+                }
+                FieldType::Repeated {
+                    typ,
+                    type_path: field_conf.config.vec_type_parsed()?.ok_or_else(|| {
+                        "Field is repeated, but vec_type was not configured for it".to_owned()
+                    })?,
+                    max_len: field_conf.config.max_len,
+                    overflow,
+                    unknown_enum,
+                    // Repeated scalars are packed by default in proto3, but not in proto2
+                    packed: proto
+                        .options()
+                        .and_then(|opt| opt.packed().copied())
+                        .unwrap_or(syntax == Syntax::Proto3)
+                        && !matches!(
+                            proto.r#type,
+                            Type::String | Type::Bytes | Type::Message | Type::Group
+                        ),
+                }
+            }
 
             (None, None, Label::Required | Label::Optional)
                 if syntax == Syntax::Proto2
@@ -137,6 +172,9 @@ impl<'a> Field<'a> {
             (None, None, _) => FieldType::Single(TypeSpec::from_proto(proto, field_conf)?),
         };
         let attrs = field_conf.config.field_attr_parsed()?;
+        let custom_implicit_presence = matches!(ftype, FieldType::Custom(CustomField::Type(_)))
+            && field_conf.config.implicit_presence.unwrap_or(false);
+        let required = proto.label == Label::Required && matches!(ftype, FieldType::Optional(..));
 
         Ok(Some(Field {
             num,
@@ -147,6 +185,9 @@ impl<'a> Field<'a> {
             default: proto.default_value().map(String::as_str),
             boxed,
             attrs,
+            custom_implicit_presence,
+            required,
+            implicit_encode: field_conf.config.implicit_encode.unwrap_or_default(),
         }))
     }
 
@@ -216,6 +257,33 @@ impl<'a> Field<'a> {
         Ok(quote! { ::core::default::Default::default() })
     }
 
+    /// Local variable tracking the oldest element of a `DropOldest` repeated field, which is
+    /// overwritten next when the container is full
+    fn oldest_var(&self) -> Option<Ident> {
+        matches!(
+            self.ftype,
+            FieldType::Repeated {
+                overflow: RepeatedOverflow::DropOldest,
+                ..
+            }
+        )
+        .then(|| format_ident!("{}_oldest", self.rust_name))
+    }
+
+    /// Declaration of the decoding state of the field, which lives across the whole message
+    pub(crate) fn generate_decode_state(&self) -> Option<TokenStream> {
+        let oldest = self.oldest_var()?;
+        Some(quote! { let mut #oldest = 0usize; })
+    }
+
+    /// Code that runs after all fields of the message have been decoded
+    pub(crate) fn generate_decode_finish(&self, decoder: &Ident) -> Option<TokenStream> {
+        let oldest = self.oldest_var()?;
+        let fname = &self.san_rust_name;
+        let extra_deref = self.boxed.then(|| quote! { * });
+        Some(quote! { #decoder.finish_repeated(&mut #extra_deref self.#fname, &mut #oldest); })
+    }
+
     pub(crate) fn generate_decode_branch(
         &self,
         gen: &Generator,
@@ -239,9 +307,7 @@ impl<'a> Field<'a> {
                         |#mut_ref: &mut #val_type, #decoder| { #val_decode_expr; Ok(()) },
                     )?
                     {
-                        if let (Err(_), false) = (self.#fname.pb_insert(k, v), #decoder.ignore_repeated_cap_err) {
-                            return Err(::micropb::DecodeError::Capacity);
-                        }
+                        #decoder.insert_map_elem(&mut #extra_deref self.#fname, k, v)?;
                     }
                 }
             }
@@ -257,8 +323,18 @@ impl<'a> Field<'a> {
             FieldType::Optional(tspec, OptionalRepr::Hazzer) => {
                 let decode_expr = tspec.generate_decode_mut(gen, false, decoder, &mut_ref);
                 let setter = format_ident!("set_{}", self.rust_name);
+                // Messages are merged into the existing value, which may be left over from before
+                // the field was cleared, so reset it if the field isn't present
+                let reset = matches!(tspec, TypeSpec::Message(_)).then(|| {
+                    quote! {
+                        if !self._has.#fname() {
+                            *#mut_ref = ::core::default::Default::default();
+                        }
+                    }
+                });
                 quote! {
                     let #mut_ref = &mut #extra_deref self.#fname;
+                    #reset
                     { #decode_expr };
                     self._has.#setter();
                 }
@@ -272,17 +348,72 @@ impl<'a> Field<'a> {
                 }
             }
 
+            FieldType::Repeated {
+                typ,
+                overflow: overflow @ (RepeatedOverflow::DropNewest | RepeatedOverflow::DropOldest),
+                ..
+            } => {
+                let overflow = overflow.generate_path();
+                // Only `DropOldest` keeps track of the oldest element
+                let oldest = match self.oldest_var() {
+                    Some(oldest) => quote! { &mut #oldest },
+                    None => quote! { &mut 0 },
+                };
+                if let Some(val) = typ.generate_decode_val(gen, decoder) {
+                    quote! {
+                        if #tag.wire_type() == ::micropb::WIRE_TYPE_LEN {
+                            #decoder.decode_packed_with_overflow(&mut #extra_deref self.#fname, #overflow, #oldest, |#decoder| #val.map(|v| v as _))?;
+                        } else {
+                            let val = #val? as _;
+                            #decoder.push_repeated(&mut #extra_deref self.#fname, val, #overflow, #oldest)?;
+                        }
+                    }
+                } else {
+                    let decode_expr = typ.generate_decode_mut(gen, false, decoder, &mut_ref);
+                    let rust_type = typ.generate_rust_type(gen);
+                    quote! {
+                        let mut val: #rust_type = ::core::default::Default::default();
+                        let #mut_ref = &mut val;
+                        { #decode_expr };
+                        #decoder.push_repeated(&mut #extra_deref self.#fname, val, #overflow, #oldest)?;
+                    }
+                }
+            }
+
+            FieldType::Repeated {
+                typ: TypeSpec::Enum(_),
+                unknown_enum,
+                ..
+            } => {
+                let unknown_enum = unknown_enum.generate_path();
+                quote! {
+                    if #tag.wire_type() == ::micropb::WIRE_TYPE_LEN {
+                        #decoder.decode_packed_enum(&mut #extra_deref self.#fname, #unknown_enum)?;
+                    } else {
+                        #decoder.decode_enum_elem(&mut #extra_deref self.#fname, #unknown_enum)?;
+                    }
+                }
+            }
+
             FieldType::Repeated { typ, .. } => {
                 // Type can be packed and is Copy, so we check the wire type to see if we can
                 // do packed decoding
                 if let Some(val) = typ.generate_decode_val(gen, decoder) {
+                    // Bools are varints, so their wire size is only fixed if they're canonical
+                    let packed = match typ.fixed_size().filter(|_| !matches!(typ, TypeSpec::Bool)) {
+                        Some(elem_len) => quote! {
+                            #decoder.decode_packed_fixed(&mut #extra_deref self.#fname, #elem_len, |#decoder| #val.map(|v| v as _))?;
+                        },
+                        None => quote! {
+                            #decoder.decode_packed(&mut #extra_deref self.#fname, |#decoder| #val.map(|v| v as _))?;
+                        },
+                    };
                     quote! {
                         if #tag.wire_type() == ::micropb::WIRE_TYPE_LEN {
-                            #decoder.decode_packed(&mut #extra_deref self.#fname, |#decoder| #val.map(|v| v as _))?;
+                            #packed
                         } else {
-                            if let (Err(_), false) = (self.#fname.pb_push(#val? as _), #decoder.ignore_repeated_cap_err) {
-                                return Err(::micropb::DecodeError::Capacity);
-                            }
+                            let val = #val? as _;
+                            #decoder.push_elem(&mut #extra_deref self.#fname, val)?;
                         }
                     }
                 } else {
@@ -292,9 +423,7 @@ impl<'a> Field<'a> {
                         let mut val: #rust_type = ::core::default::Default::default();
                         let #mut_ref = &mut val;
                         { #decode_expr };
-                        if let (Err(_), false) = (self.#fname.pb_push(val), #decoder.ignore_repeated_cap_err) {
-                            return Err(::micropb::DecodeError::Capacity);
-                        }
+                        #decoder.push_elem(&mut #extra_deref self.#fname, val)?;
                     }
                 }
             }
@@ -340,7 +469,9 @@ impl<'a> Field<'a> {
         let tag_len = ::micropb::size::sizeof_tag(tag);
 
         let sizeof_code = match &self.ftype {
-            FieldType::Map { key, val, .. } => {
+            FieldType::Map {
+                key, val, sorted, ..
+            } => {
                 let key_sizeof = key.generate_sizeof(gen, &val_ref);
                 let val_sizeof = val.generate_sizeof(gen, &val_ref);
 
@@ -363,8 +494,14 @@ impl<'a> Field<'a> {
                         }
                     }
                 };
+                // Order doesn't matter when computing the size
+                let iter = if *sorted && matches!(func_type, EncodeFunc::Encode(_)) {
+                    quote! { pb_iter_sorted }
+                } else {
+                    quote! { pb_iter }
+                };
                 quote! {
-                    for (k, v) in self.#fname.pb_iter() {
+                    for (k, v) in self.#fname.#iter() {
                         let len = ::micropb::size::sizeof_map_elem(k, v, |#val_ref| { #key_sizeof }, |#val_ref| { #val_sizeof });
                         #stmts
                     }
@@ -375,7 +512,13 @@ impl<'a> Field<'a> {
                 let check = if let FieldType::Optional(..) = self.ftype {
                     quote! { if let Some(#val_ref) = self.#fname() }
                 } else {
-                    let implicit_presence_check = tspec.generate_implicit_presence_check(&val_ref);
+                    let implicit_presence_check = match self.implicit_encode {
+                        ImplicitEncode::SkipDefault => {
+                            tspec.generate_implicit_presence_check(&val_ref)
+                        }
+                        ImplicitEncode::Always => quote! {},
+                        ImplicitEncode::Never => return quote! {},
+                    };
                     quote! {
                         let #val_ref = &#extra_deref self.#fname;
                         #implicit_presence_check
@@ -401,12 +544,63 @@ impl<'a> Field<'a> {
                 }
             }
 
-            FieldType::Repeated {
-                typ, packed: false, ..
-            } => 'expr: {
+            FieldType::Repeated { typ, packed, .. } => self.generate_repeated_encode(
+                gen,
+                func_type,
+                typ,
+                *packed,
+                &quote! { self.#fname },
+                extra_deref.as_ref(),
+            ),
+
+            FieldType::Custom(CustomField::Type(_)) => {
+                let stmts = match &func_type {
+                    EncodeFunc::Sizeof(size) => {
+                        quote! { #size += self.#fname.compute_fields_size(); }
+                    }
+                    EncodeFunc::Encode(encoder) => quote! { self.#fname.encode_fields(#encoder)?; },
+                };
+                if self.custom_implicit_presence {
+                    quote! {
+                        if !::micropb::field::ImplicitPresence::is_default_value(&self.#fname) {
+                            #stmts
+                        }
+                    }
+                } else {
+                    stmts
+                }
+            }
+
+            FieldType::Custom(CustomField::Delegate(_)) => quote! {},
+        };
+
+        quote! {{
+            #sizeof_code
+        }}
+    }
+
+    /// Generate the encode logic of a repeated field, where `elems` derefs to a slice of the
+    /// elements after dereferencing it with `deref`.
+    fn generate_repeated_encode(
+        &self,
+        gen: &Generator,
+        func_type: &EncodeFunc,
+        typ: &TypeSpec,
+        packed: bool,
+        elems: &TokenStream,
+        deref: Option<&TokenStream>,
+    ) -> TokenStream {
+        let val_ref = Ident::new("val_ref", Span::call_site());
+        let wire_type = self.wire_type();
+        let tag = micropb::Tag::from_parts(self.num, wire_type);
+        let tag_val = tag.varint();
+        let tag_len = ::micropb::size::sizeof_tag(tag);
+
+        match packed {
+            false => 'expr: {
                 let stmts = match (&func_type, typ.fixed_size()) {
                     (EncodeFunc::Sizeof(size), Some(fixed)) => {
-                        break 'expr quote! { #size += self.#fname.len() * (#tag_len + #fixed); };
+                        break 'expr quote! { #size += #elems.len() * (#tag_len + #fixed); };
                     }
                     (EncodeFunc::Sizeof(size), None) => {
                         let sizeof_expr = typ.generate_sizeof(gen, &val_ref);
@@ -421,57 +615,386 @@ impl<'a> Field<'a> {
                     }
                 };
                 quote! {
-                    for #val_ref in self.#fname.iter() {
+                    for #val_ref in #elems.iter() {
                         #stmts
                     }
                 }
             }
 
-            FieldType::Repeated {
-                typ, packed: true, ..
-            } => {
+            true => {
+                let enum_elems = matches!(typ, TypeSpec::Enum(_));
                 let len = if let Some(fixed) = typ.fixed_size() {
-                    quote! { self.#fname.len() * #fixed }
+                    quote! { #elems.len() * #fixed }
+                } else if enum_elems {
+                    quote! { ::micropb::size::sizeof_packed_enum(& #deref #elems) }
                 } else {
                     let sizeof_expr = typ.generate_sizeof(gen, &val_ref);
-                    quote! { ::micropb::size::sizeof_packed(& #extra_deref self.#fname, |#val_ref| #sizeof_expr) }
+                    quote! { ::micropb::size::sizeof_packed(& #deref #elems, |#val_ref| #sizeof_expr) }
                 };
                 let stmts = match &func_type {
                     EncodeFunc::Sizeof(size) => {
                         quote! { #size += #tag_len + ::micropb::size::sizeof_len_record(len); }
                     }
+                    EncodeFunc::Encode(encoder) if enum_elems => {
+                        quote! {
+                            #encoder.encode_varint32(#tag_val)?;
+                            #encoder.encode_packed_enum(len, & #deref #elems)?;
+                        }
+                    }
                     EncodeFunc::Encode(encoder) => {
                         let encode_expr = typ.generate_encode_expr(gen, encoder, &val_ref);
                         quote! {
                             #encoder.encode_varint32(#tag_val)?;
-                            #encoder.encode_packed(len, & #extra_deref self.#fname, |#encoder, val| {let #val_ref = &val; #encode_expr})?;
+                            #encoder.encode_packed(len, & #deref #elems, |#encoder, val| {let #val_ref = &val; #encode_expr})?;
                         }
                     }
                 };
                 quote! {
-                    if !self.#fname.is_empty() {
+                    if !#elems.is_empty() {
                         let len = #len;
                         #stmts
                     }
                 }
             }
+        }
+    }
 
-            FieldType::Custom(CustomField::Type(_)) => match &func_type {
-                EncodeFunc::Sizeof(size) => quote! { #size += self.#fname.compute_fields_size(); },
-                EncodeFunc::Encode(encoder) => quote! { self.#fname.encode_fields(#encoder)?; },
-            },
+    /// Generate the logic for encoding the field only if it differs from the field in `base`.
+    pub(crate) fn generate_encode_changed(
+        &self,
+        gen: &Generator,
+        func_type: &EncodeFunc,
+    ) -> TokenStream {
+        let fname = &self.san_rust_name;
+        let val_ref = Ident::new("val_ref", Span::call_site());
+        let base_ref = Ident::new("base_ref", Span::call_site());
+        let extra_deref = self.boxed.then(|| quote! { * });
+        let tag = micropb::Tag::from_parts(self.num, self.wire_type());
+        let tag_val = tag.varint();
+        let tag_len = ::micropb::size::sizeof_tag(tag);
+
+        let changed_code = match &self.ftype {
+            FieldType::Single(_) if matches!(self.implicit_encode, ImplicitEncode::Never) => {
+                return quote! {};
+            }
+
+            FieldType::Map { .. } | FieldType::Custom(CustomField::Type(_)) => {
+                let encode = self.generate_encode(gen, func_type);
+                quote! { if self.#fname != base.#fname #encode }
+            }
+
+            FieldType::Single(tspec) | FieldType::Optional(tspec, _) => {
+                let full_stmts = match &func_type {
+                    EncodeFunc::Sizeof(size) => {
+                        let sizeof_expr = tspec.generate_sizeof(gen, &val_ref);
+                        quote! { #size += #tag_len + #sizeof_expr; }
+                    }
+                    EncodeFunc::Encode(encoder) => {
+                        let encode_expr = tspec.generate_encode_expr(gen, encoder, &val_ref);
+                        quote! {
+                            #encoder.encode_varint32(#tag_val)?;
+                            #encode_expr?;
+                        }
+                    }
+                };
+                // Message fields present in both messages are patched recursively
+                let patch_stmts = match &func_type {
+                    EncodeFunc::Sizeof(size) => {
+                        quote! { #size += #tag_len + ::micropb::size::sizeof_len_record(len); }
+                    }
+                    EncodeFunc::Encode(encoder) => quote! {
+                        #encoder.encode_varint32(#tag_val)?;
+                        #encoder.encode_varint32(len as u32)?;
+                        #val_ref.encode_changed(#base_ref, #encoder)?;
+                    },
+                };
+                let patch = quote! {
+                    let len = #val_ref.compute_changed_size(#base_ref);
+                    if len > 0 {
+                        #patch_stmts
+                    }
+                };
+
+                match (&self.ftype, tspec) {
+                    (FieldType::Optional(..), TypeSpec::Message(_)) => quote! {
+                        if let Some(#val_ref) = self.#fname() {
+                            if let Some(#base_ref) = base.#fname() {
+                                #patch
+                            } else {
+                                #full_stmts
+                            }
+                        }
+                    },
+                    (FieldType::Optional(..), _) => quote! {
+                        if let Some(#val_ref) = self.#fname() {
+                            if base.#fname() != Some(#val_ref) {
+                                #full_stmts
+                            }
+                        }
+                    },
+                    (_, TypeSpec::Message(_)) => quote! {
+                        let #val_ref = &#extra_deref self.#fname;
+                        let #base_ref = &#extra_deref base.#fname;
+                        #patch
+                    },
+                    // Zero values of implicit presence fields are ignored when decoding, so
+                    // they aren't encoded in the patch either
+                    _ => {
+                        let implicit_presence_check =
+                            tspec.generate_implicit_presence_check(&val_ref);
+                        quote! {
+                            let #val_ref = &#extra_deref self.#fname;
+                            if #val_ref != &#extra_deref base.#fname {
+                                #implicit_presence_check {
+                                    #full_stmts
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            FieldType::Repeated { typ, packed, .. } => {
+                let encode = self.generate_repeated_encode(
+                    gen,
+                    func_type,
+                    typ,
+                    *packed,
+                    &quote! { elems },
+                    Some(&quote! { * }),
+                );
+                // If the base is a prefix of the field, only encode the new elements, since
+                // decoding appends to repeated fields
+                quote! {
+                    let elems = if self.#fname.starts_with(&base.#fname[..]) {
+                        self.#fname.get(base.#fname.len()..).unwrap_or_default()
+                    } else {
+                        &self.#fname[..]
+                    };
+                    #encode
+                }
+            }
 
             FieldType::Custom(CustomField::Delegate(_)) => quote! {},
         };
 
         quote! {{
-            #sizeof_code
+            #changed_code
         }}
     }
+
+    /// Generate constants for the capacities configured with `max_len` and `max_bytes`, so that
+    /// application code doesn't need to repeat them
+    pub(crate) fn generate_capacity_consts(&self) -> TokenStream {
+        let (max_len, tspec) = match &self.ftype {
+            FieldType::Map { max_len, .. } => (*max_len, None),
+            FieldType::Repeated { typ, max_len, .. } => (*max_len, Some(typ)),
+            FieldType::Single(tspec) | FieldType::Optional(tspec, _) => (None, Some(tspec)),
+            FieldType::Custom(_) => (None, None),
+        };
+        let max_bytes = tspec.and_then(TypeSpec::max_bytes);
+        let prefix = self.rust_name.to_case(Case::UpperSnake);
+
+        let max_len = max_len.map(|max_len| {
+            let name = format_ident!("{prefix}_MAX_LEN");
+            let doc = format!(" Maximum number of elements in `{}`.", self.rust_name);
+            let max_len = Literal::usize_unsuffixed(max_len as usize);
+            quote! {
+                #[doc = #doc]
+                pub const #name: usize = #max_len;
+            }
+        });
+        let max_bytes = max_bytes.map(|max_bytes| {
+            let name = format_ident!("{prefix}_MAX_BYTES");
+            let doc = if matches!(self.ftype, FieldType::Repeated { .. }) {
+                format!(
+                    " Maximum length in bytes of each element of `{}`.",
+                    self.rust_name
+                )
+            } else {
+                format!(" Maximum length in bytes of `{}`.", self.rust_name)
+            };
+            let max_bytes = Literal::usize_unsuffixed(max_bytes as usize);
+            quote! {
+                #[doc = #doc]
+                pub const #name: usize = #max_bytes;
+            }
+        });
+        quote! { #max_len #max_bytes }
+    }
+
+    /// Generate an expression that's true if the field won't be encoded. Returns `None` for
+    /// delegate fields, which are encoded by the custom field they delegate to.
+    pub(crate) fn generate_is_default(&self) -> Option<TokenStream> {
+        let fname = &self.san_rust_name;
+        let expr = match &self.ftype {
+            FieldType::Map { .. } => quote! { self.#fname.pb_iter().next().is_none() },
+            FieldType::Repeated { .. } => quote! { self.#fname.is_empty() },
+            FieldType::Optional(_, OptionalRepr::Hazzer) => quote! { !self._has.#fname() },
+            FieldType::Optional(_, OptionalRepr::Option) => quote! { self.#fname.is_none() },
+            FieldType::Single(tspec) => {
+                let val_ref = Ident::new("val_ref", Span::call_site());
+                let extra_deref = self.boxed.then(|| quote! { * });
+                match tspec.generate_implicit_presence_cond(&val_ref) {
+                    Some(cond) => quote! {{ let #val_ref = &#extra_deref self.#fname; !(#cond) }},
+                    // Non-optional message fields are always encoded
+                    None => quote! { false },
+                }
+            }
+            FieldType::Custom(CustomField::Type(_)) if self.custom_implicit_presence => {
+                quote! { ::micropb::field::ImplicitPresence::is_default_value(&self.#fname) }
+            }
+            FieldType::Custom(CustomField::Type(_)) => {
+                quote! { self.#fname.compute_fields_size() == 0 }
+            }
+            FieldType::Custom(CustomField::Delegate(_)) => return None,
+        };
+        Some(expr)
+    }
+
+    /// Generate an expression for the heap memory owned by the field. Returns `None` for delegate
+    /// fields, since their memory is owned by another field.
+    pub(crate) fn generate_heap_size(&self) -> Option<TokenStream> {
+        if let FieldType::Custom(CustomField::Delegate(_)) = &self.ftype {
+            return None;
+        }
+        let fname = &self.san_rust_name;
+        Some(quote! { ::micropb::HeapSize::heap_bytes_used(&self.#fname) })
+    }
+
+    /// Generate the logic for resetting the field to its default value. Containers are cleared in
+    /// place to keep their allocations. Returns `None` for delegate fields.
+    pub(crate) fn generate_clear(&self, gen: &Generator) -> Result<Option<TokenStream>, String> {
+        let fname = &self.san_rust_name;
+        let extra_deref = self.boxed.then(|| quote! { * });
+        let clear = match &self.ftype {
+            FieldType::Custom(CustomField::Delegate(_)) => return Ok(None),
+            FieldType::Map { .. } => {
+                quote! { ::micropb::PbMap::pb_clear(&mut #extra_deref self.#fname); }
+            }
+            FieldType::Repeated { .. } => {
+                quote! { ::micropb::PbContainer::pb_clear(&mut #extra_deref self.#fname); }
+            }
+            // Substituted messages and messages without a `Default` impl don't have `clear()`, so
+            // they're overwritten with their default value instead
+            FieldType::Single(TypeSpec::Message(tname))
+            | FieldType::Optional(TypeSpec::Message(tname), OptionalRepr::Hazzer)
+                if gen.is_clearable_msg(tname) =>
+            {
+                quote! { self.#fname.clear(); }
+            }
+            FieldType::Single(TypeSpec::String { .. } | TypeSpec::Bytes { .. })
+            | FieldType::Optional(
+                TypeSpec::String { .. } | TypeSpec::Bytes { .. },
+                OptionalRepr::Hazzer,
+            ) if self.default.is_none() => {
+                quote! { ::micropb::PbContainer::pb_clear(&mut #extra_deref self.#fname); }
+            }
+            _ => {
+                let default = self.generate_default(gen)?;
+                quote! { self.#fname = #default; }
+            }
+        };
+        Ok(Some(clear))
+    }
+
+    /// Generate the logic for merging the field of `other` into `self`. Returns `None` for custom
+    /// fields, which aren't merged.
+    pub(crate) fn generate_merge(&self) -> Option<TokenStream> {
+        let fname = &self.san_rust_name;
+        let val_ref = Ident::new("val_ref", Span::call_site());
+        let extra_deref = self.boxed.then(|| quote! { * });
+
+        let merge_code = match &self.ftype {
+            FieldType::Map { .. } => quote! {
+                for (k, v) in ::micropb::PbMap::pb_iter(&#extra_deref other.#fname) {
+                    ::micropb::PbMap::pb_insert(&mut #extra_deref self.#fname, k.clone(), v.clone())?;
+                }
+            },
+
+            FieldType::Single(TypeSpec::Message(_)) => quote! {
+                ::micropb::MessageMerge::merge(&mut #extra_deref self.#fname, &#extra_deref other.#fname)?;
+            },
+
+            // Zero values of implicit presence fields are ignored, like when decoding
+            FieldType::Single(tspec) => {
+                let implicit_presence_check = tspec.generate_implicit_presence_check(&val_ref);
+                quote! {
+                    let #val_ref = &#extra_deref other.#fname;
+                    #implicit_presence_check {
+                        self.#fname = other.#fname.clone();
+                    }
+                }
+            }
+
+            FieldType::Optional(tspec, _) => {
+                let setter = format_ident!("set_{}", self.rust_name);
+                let muter = format_ident!("mut_{}", self.rust_name);
+                if let TypeSpec::Message(_) = tspec {
+                    quote! {
+                        if let ::core::option::Option::Some(#val_ref) = other.#fname() {
+                            if let ::core::option::Option::Some(mut_ref) = self.#muter() {
+                                ::micropb::MessageMerge::merge(mut_ref, #val_ref)?;
+                            } else {
+                                self.#setter(#val_ref.clone());
+                            }
+                        }
+                    }
+                } else {
+                    quote! {
+                        if let ::core::option::Option::Some(#val_ref) = other.#fname() {
+                            self.#setter(#val_ref.clone());
+                        }
+                    }
+                }
+            }
+
+            FieldType::Repeated { overflow, .. } => {
+                let push = match overflow {
+                    RepeatedOverflow::Error => {
+                        quote! { ::micropb::PbVec::pb_push(vec, elem.clone())?; }
+                    }
+                    RepeatedOverflow::DropNewest => {
+                        quote! { let _ = ::micropb::PbVec::pb_push(vec, elem.clone()); }
+                    }
+                    // Same logic as `PbDecoder::push_repeated`
+                    RepeatedOverflow::DropOldest => quote! {
+                        ::micropb::PbContainer::pb_reserve(vec, 1);
+                        if !::micropb::PbVec::pb_spare_cap(vec).is_empty() {
+                            let _ = ::micropb::PbVec::pb_push(vec, elem.clone());
+                        } else if let ::core::option::Option::Some(slot) = vec.get_mut(oldest) {
+                            *slot = elem.clone();
+                            oldest = (oldest + 1) % vec.len();
+                        }
+                    },
+                };
+                // The oldest element is rotated to the front once all elements are merged
+                let (oldest, finish) = if matches!(overflow, RepeatedOverflow::DropOldest) {
+                    (
+                        Some(quote! { let mut oldest = 0usize; }),
+                        Some(quote! { vec.rotate_left(oldest); }),
+                    )
+                } else {
+                    (None, None)
+                };
+                quote! {
+                    let vec = &mut #extra_deref self.#fname;
+                    #oldest
+                    for elem in other.#fname.iter() {
+                        #push
+                    }
+                    #finish
+                }
+            }
+
+            FieldType::Custom(_) => return None,
+        };
+        Some(merge_code)
+    }
 }
 
 #[cfg(test)]
-pub(crate) fn make_test_field(num: u32, name: &str, boxed: bool, ftype: FieldType) -> Field {
+pub(crate) fn make_test_field(num: u32, name: &str, boxed: bool, ftype: FieldType) -> Field<'_> {
     Field {
         num,
         ftype,
@@ -481,6 +1004,9 @@ pub(crate) fn make_test_field(num: u32, name: &str, boxed: bool, ftype: FieldTyp
         default: None,
         boxed,
         attrs: vec![],
+        custom_implicit_presence: false,
+        required: false,
+        implicit_encode: ImplicitEncode::SkipDefault,
     }
 }
 
@@ -549,6 +1075,9 @@ mod tests {
                 default: None,
                 boxed: false,
                 attrs: vec![],
+                custom_implicit_presence: false,
+                required: false,
+                implicit_encode: ImplicitEncode::SkipDefault,
             }
         );
 
@@ -557,7 +1086,8 @@ mod tests {
             Config::new()
                 .boxed(true)
                 .rename_field("renamed")
-                .field_attributes("#[attr]"),
+                .field_attributes("#[attr]")
+                .implicit_encode(ImplicitEncode::Always),
         );
         let field_conf = CurrentConfig {
             node: None,
@@ -578,6 +1108,9 @@ mod tests {
                 default: Some("true"),
                 boxed: true,
                 attrs: parse_attributes("#[attr]").unwrap(),
+                custom_implicit_presence: false,
+                required: false,
+                implicit_encode: ImplicitEncode::Always,
             }
         );
     }
@@ -605,15 +1138,16 @@ mod tests {
             FieldType::Optional(TypeSpec::Bool, OptionalRepr::Hazzer)
         );
 
-        // Required fields are treated like optionals
+        // Required fields are treated like optionals, but are marked as required
         let field = field_proto(0, "field", Some(Label::Required), false);
+        let required = Field::from_proto(&field, &field_conf, Syntax::Proto2, None)
+            .unwrap()
+            .unwrap();
         assert_eq!(
-            Field::from_proto(&field, &field_conf, Syntax::Proto2, None)
-                .unwrap()
-                .unwrap()
-                .ftype,
+            required.ftype,
             FieldType::Optional(TypeSpec::Bool, OptionalRepr::Hazzer)
         );
+        assert!(required.required);
 
         // In proto3, if proto3_optional is set then field is optional
         let field = field_proto(0, "field", Some(Label::Optional), true);
@@ -695,6 +1229,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn from_proto_custom_implicit_presence() {
+        let config = Box::new(
+            Config::new()
+                .custom_field(crate::config::CustomField::Type("Custom".to_owned()))
+                .implicit_presence(true),
+        );
+        let field_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let field = field_proto(1, "field", None, false);
+        assert!(
+            Field::from_proto(&field, &field_conf, Syntax::Proto3, None)
+                .unwrap()
+                .unwrap()
+                .custom_implicit_presence
+        );
+
+        // Ignored for non-custom fields
+        let config = Box::new(Config::new().implicit_presence(true));
+        let field_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert!(
+            !Field::from_proto(&field, &field_conf, Syntax::Proto3, None)
+                .unwrap()
+                .unwrap()
+                .custom_implicit_presence
+        );
+    }
+
     #[test]
     fn from_proto_repeated() {
         // Repeated fields with custom element int type
@@ -710,7 +1277,7 @@ mod tests {
         let mut field = field_proto(0, "field", Some(Label::Repeated), false);
         field.set_type(Type::Int32);
         assert_eq!(
-            Field::from_proto(&field, &field_conf, Syntax::Proto3, None)
+            Field::from_proto(&field, &field_conf, Syntax::Proto2, None)
                 .unwrap()
                 .unwrap()
                 .ftype,
@@ -718,9 +1285,22 @@ mod tests {
                 typ: TypeSpec::Int(PbInt::Int32, IntSize::S8),
                 packed: false,
                 type_path: syn::parse_str("Vec").unwrap(),
-                max_len: Some(21)
+                max_len: Some(21),
+                overflow: RepeatedOverflow::Error,
+                unknown_enum: UnknownEnum::Keep,
             }
         );
+        // Proto3 packs repeated scalars by default
+        let packed = |field: &FieldDescriptorProto, syntax| {
+            matches!(
+                Field::from_proto(field, &field_conf, syntax, None)
+                    .unwrap()
+                    .unwrap()
+                    .ftype,
+                FieldType::Repeated { packed: true, .. }
+            )
+        };
+        assert!(packed(&field, Syntax::Proto3));
         field.set_options(Default::default());
         field.options.set_packed(true);
         assert_eq!(
@@ -732,9 +1312,73 @@ mod tests {
                 typ: TypeSpec::Int(PbInt::Int32, IntSize::S8),
                 packed: true,
                 type_path: syn::parse_str("Vec").unwrap(),
-                max_len: Some(21)
+                max_len: Some(21),
+                overflow: RepeatedOverflow::Error,
+                unknown_enum: UnknownEnum::Keep,
             }
         );
+        assert!(packed(&field, Syntax::Proto2));
+        field.options.set_packed(false);
+        assert!(!packed(&field, Syntax::Proto3));
+
+        // Length-delimited types are never packed
+        let mut field = field_proto(0, "field", Some(Label::Repeated), false);
+        field.set_type(Type::String);
+        let config = Box::new(Config::new().vec_type("Vec").string_type("String"));
+        let field_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert!(!matches!(
+            Field::from_proto(&field, &field_conf, Syntax::Proto3, None)
+                .unwrap()
+                .unwrap()
+                .ftype,
+            FieldType::Repeated { packed: true, .. }
+        ));
+    }
+
+    #[test]
+    fn from_proto_repeated_enum() {
+        let config = Box::new(
+            Config::new()
+                .vec_type("Vec")
+                .unknown_enum(UnknownEnum::Drop),
+        );
+        let field_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+
+        let mut field = field_proto(0, "field", Some(Label::Repeated), false);
+        field.set_type(Type::Enum);
+        field.set_type_name(".Enum".to_owned());
+        assert_eq!(
+            Field::from_proto(&field, &field_conf, Syntax::Proto2, None)
+                .unwrap()
+                .unwrap()
+                .ftype,
+            FieldType::Repeated {
+                typ: TypeSpec::Enum(".Enum".to_owned()),
+                packed: false,
+                type_path: syn::parse_str("Vec").unwrap(),
+                max_len: None,
+                overflow: RepeatedOverflow::Error,
+                unknown_enum: UnknownEnum::Drop,
+            }
+        );
+
+        let config = Box::new(
+            Config::new()
+                .vec_type("Vec")
+                .unknown_enum(UnknownEnum::Drop)
+                .repeated_overflow(RepeatedOverflow::DropOldest),
+        );
+        let field_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert!(Field::from_proto(&field, &field_conf, Syntax::Proto2, None).is_err());
     }
 
     #[test]
@@ -784,11 +1428,59 @@ mod tests {
                 key: TypeSpec::Int(PbInt::Int32, IntSize::S8),
                 val: TypeSpec::String {
                     type_path: syn::parse_str("std::String").unwrap(),
-                    max_bytes: None
+                    max_bytes: None,
+                    truncate: false
                 },
                 type_path: syn::parse_str("std::Map").unwrap(),
-                max_len: None
+                max_len: None,
+                sorted: false
             }
         );
     }
+
+    #[test]
+    fn capacity_consts() {
+        let string = || TypeSpec::String {
+            type_path: syn::parse_str("String").unwrap(),
+            max_bytes: Some(8),
+            truncate: false,
+        };
+        let field = make_test_field(
+            1,
+            "sample_names",
+            false,
+            FieldType::Repeated {
+                typ: string(),
+                packed: false,
+                type_path: syn::parse_str("Vec").unwrap(),
+                max_len: Some(4),
+                overflow: RepeatedOverflow::Error,
+                unknown_enum: UnknownEnum::Keep,
+            },
+        );
+        assert_eq!(
+            field.generate_capacity_consts().to_string(),
+            quote! {
+                #[doc = " Maximum number of elements in `sample_names`."]
+                pub const SAMPLE_NAMES_MAX_LEN: usize = 4;
+                #[doc = " Maximum length in bytes of each element of `sample_names`."]
+                pub const SAMPLE_NAMES_MAX_BYTES: usize = 8;
+            }
+            .to_string()
+        );
+
+        let field = make_test_field(1, "name", false, FieldType::Single(string()));
+        assert_eq!(
+            field.generate_capacity_consts().to_string(),
+            quote! {
+                #[doc = " Maximum length in bytes of `name`."]
+                pub const NAME_MAX_BYTES: usize = 8;
+            }
+            .to_string()
+        );
+
+        // Nothing is generated for fields without configured capacities
+        let field = make_test_field(1, "num", false, FieldType::Single(TypeSpec::Bool));
+        assert!(field.generate_capacity_consts().is_empty());
+    }
 }
@@ -5,7 +5,7 @@ use quote::{format_ident, quote};
 use syn::Ident;
 
 use crate::{
-    config::OptionalRepr,
+    config::{Inline, OptionalRepr},
     descriptor::DescriptorProto,
     generator::{
         field::{CustomField, FieldType},
@@ -19,7 +19,7 @@ use super::{
     field_error, msg_error,
     oneof::{Oneof, OneofField, OneofType},
     sanitized_ident,
-    type_spec::find_lifetime_from_type,
+    type_spec::{borrowed_lifetime, find_lifetime_from_type},
     CurrentConfig, Generator,
 };
 
@@ -37,7 +37,24 @@ pub(crate) struct Message<'a> {
     pub(crate) derive_clone: bool,
     pub(crate) attrs: Vec<syn::Attribute>,
     pub(crate) unknown_handler: Option<syn::Type>,
+    pub(crate) reject_unknown: bool,
+    pub(crate) enforce_required: bool,
+    pub(crate) field_path_errors: bool,
     pub(crate) lifetime: Option<syn::Lifetime>,
+    /// Borrows from the decode buffer, so it implements `MessageDecodeBorrowed`
+    pub(crate) borrowed: bool,
+    pub(crate) decode_inline: Option<Inline>,
+    pub(crate) encode_inline: Option<Inline>,
+    /// Generate a unit struct, since the message has no fields
+    pub(crate) unit_struct: bool,
+    /// Generate `encode_changed` and `compute_changed_size`
+    pub(crate) encode_changed: bool,
+    /// Generate `MessageMerge` impl
+    pub(crate) merge_impl: bool,
+    /// Encode fields in field number order and map entries in key order
+    pub(crate) deterministic_encode: bool,
+    /// Implement `DefaultInstance`
+    pub(crate) default_instance: bool,
 }
 
 impl<'a> Message<'a> {
@@ -98,10 +115,10 @@ impl<'a> Message<'a> {
                                     fields.push(field);
                                 }
                             }
-                            Some(OneofType::Custom { nums, .. }) => {
-                                if !field_conf.config.skip.unwrap_or(false) {
-                                    nums.push(f.number);
-                                }
+                            Some(OneofType::Custom { nums, .. })
+                                if !field_conf.config.skip.unwrap_or(false) =>
+                            {
+                                nums.push(f.number);
                             }
                             _ => (),
                         }
@@ -132,14 +149,47 @@ impl<'a> Message<'a> {
             .config
             .unknown_handler_parsed()
             .map_err(|e| msg_error(&gen.pkg, msg_name, &e))?;
+        let unknown_handler = match (unknown_handler, msg_conf.config.unknown_fields) {
+            (Some(_), Some(true)) => {
+                return Err(msg_error(
+                    &gen.pkg,
+                    msg_name,
+                    "unknown_fields can't be combined with unknown_handler",
+                ))
+            }
+            (None, Some(true)) => Some(
+                unknown_fields_type(&msg_conf.next_conf("_unknown"))
+                    .map_err(|e| field_error(&gen.pkg, msg_name, "_unknown", &e))?,
+            ),
+            (handler, _) => handler,
+        };
+        let reject_unknown = msg_conf.config.reject_unknown_fields.unwrap_or(false);
+        if reject_unknown && unknown_handler.is_some() {
+            return Err(msg_error(
+                &gen.pkg,
+                msg_name,
+                "reject_unknown_fields can't be combined with unknown_handler or unknown_fields",
+            ));
+        }
 
-        // Find any lifetime in the message definition (we only need one)
-        let lifetime = fields
-            .iter()
-            .find_map(|f| f.find_lifetime())
-            .or_else(|| oneofs.iter().find_map(|o| o.find_lifetime()))
-            .or_else(|| unknown_handler.as_ref().and_then(find_lifetime_from_type))
-            .cloned();
+        // Find any lifetime in the message definition (we only need one). Borrowed messages
+        // always use the lifetime of their borrowed fields.
+        let borrowed = gen.is_borrowed_msg(&gen.fq_msg_name(msg_name));
+        let lifetime = if borrowed {
+            Some(borrowed_lifetime())
+        } else {
+            fields
+                .iter()
+                .find_map(|f| f.find_lifetime())
+                .or_else(|| oneofs.iter().find_map(|o| o.find_lifetime()))
+                .or_else(|| unknown_handler.as_ref().and_then(find_lifetime_from_type))
+                .cloned()
+        };
+
+        let unit_struct = msg_conf.config.unit_struct.unwrap_or(false)
+            && fields.is_empty()
+            && oneofs.is_empty()
+            && unknown_handler.is_none();
 
         Ok(Some(Self {
             name: msg_name,
@@ -152,17 +202,29 @@ impl<'a> Message<'a> {
             derive_clone: msg_conf.derive_clone(),
             attrs,
             unknown_handler,
+            reject_unknown,
+            enforce_required: msg_conf.config.enforce_required.unwrap_or(false),
+            field_path_errors: msg_conf.config.field_path_errors.unwrap_or(false),
             lifetime,
+            borrowed,
+            decode_inline: msg_conf.config.decode_inline,
+            encode_inline: msg_conf.config.encode_inline,
+            unit_struct,
+            encode_changed: msg_conf.config.encode_changed.unwrap_or(false),
+            merge_impl: msg_conf.config.merge_impl.unwrap_or(false),
+            deterministic_encode: msg_conf.config.deterministic_encode.unwrap_or(false),
+            default_instance: msg_conf.config.default_instance.unwrap_or(false),
         }))
     }
 
     pub(crate) fn generate_hazzer_decl(
         &self,
+        gen: &Generator,
         conf: CurrentConfig,
     ) -> Result<Option<(TokenStream, Vec<syn::Attribute>)>, String> {
         let hazzer_name = Ident::new("_Hazzer", Span::call_site());
         let attrs = &conf.config.type_attr_parsed()?;
-        let derive_msg = derive_msg_attr(true, true, true, true);
+        let derive_msg = derive_msg_attr(gen.debug_impls, true, true, true, gen.defmt_impls);
 
         let hazzers = self.fields.iter().filter(|f| f.is_hazzer());
         let count = hazzers.clone().count();
@@ -221,6 +283,12 @@ impl<'a> Message<'a> {
 
             impl #hazzer_name {
                 #(#methods)*
+
+                /// Clear presence of all fields
+                #[inline]
+                pub fn clear(&mut self) {
+                    self.0 = [0; #bytes];
+                }
             }
         };
         Ok(Some((decl, conf.config.field_attr_parsed()?)))
@@ -253,13 +321,22 @@ impl<'a> Message<'a> {
         };
 
         let derive_msg = derive_msg_attr(
-            self.derive_dbg,
+            self.derive_dbg && gen.debug_impls,
             false,
             self.derive_partial_eq,
             self.derive_clone,
+            gen.defmt_impls,
         );
         let attrs = &self.attrs;
 
+        if self.unit_struct {
+            return Ok(quote! {
+                #derive_msg
+                #(#attrs)*
+                pub struct #rust_name;
+            });
+        }
+
         Ok(quote! {
             #derive_msg
             #(#attrs)*
@@ -313,6 +390,26 @@ impl<'a> Message<'a> {
         let rust_name = &self.rust_name;
         let lifetime = &self.lifetime;
 
+        let default_instance = if self.default_instance {
+            if lifetime.is_some() {
+                return Err(msg_error(
+                    &gen.pkg,
+                    self.name,
+                    "Message with a lifetime can't have a default instance",
+                ));
+            }
+            Some(quote! {
+                impl ::micropb::DefaultInstance for #rust_name {
+                    fn default_instance() -> &'static Self {
+                        static INSTANCE: ::micropb::StaticDefault<#rust_name> = ::micropb::StaticDefault::new();
+                        INSTANCE.get()
+                    }
+                }
+            })
+        } else {
+            None
+        };
+
         Ok(quote! {
             impl<#lifetime> ::core::default::Default for #rust_name<#lifetime> {
                 fn default() -> Self {
@@ -324,10 +421,47 @@ impl<'a> Message<'a> {
                     }
                 }
             }
+
+            #default_instance
+        })
+    }
+
+    /// Generate `clear()`, which resets the message to its default value in place. Only generated
+    /// along with the `Default` impl, since custom fields are reset with `Default::default()`.
+    fn generate_clear_func(&self, gen: &Generator) -> io::Result<TokenStream> {
+        if !self.impl_default {
+            return Ok(quote! {});
+        }
+
+        let mut stmts = TokenStream::new();
+        for f in &self.fields {
+            let clear = f
+                .generate_clear(gen)
+                .map_err(|e| field_error(&gen.pkg, self.name, f.name, &e))?;
+            stmts.extend(clear);
+        }
+        for o in &self.oneofs {
+            stmts.extend(o.generate_clear());
+        }
+        if self.fields.iter().any(|f| f.is_hazzer()) {
+            stmts.extend(quote! { self._has.clear(); });
+        }
+        if self.unknown_handler.is_some() {
+            stmts.extend(quote! { self._unknown = ::core::default::Default::default(); });
+        }
+
+        Ok(quote! {
+            /// Reset all fields to their default values.
+            ///
+            /// Unlike overwriting the message with `Default::default()`, containers are cleared in
+            /// place, so their allocations are kept for reuse.
+            pub fn clear(&mut self) {
+                #stmts
+            }
         })
     }
 
-    pub(crate) fn generate_impl(&self, gen: &Generator) -> TokenStream {
+    pub(crate) fn generate_impl(&self, gen: &Generator) -> io::Result<TokenStream> {
         let accessors = self.fields.iter().map(|f| {
             if let FieldType::Optional(type_spec, opt) = &f.ftype {
                 let type_name = type_spec.generate_rust_type(gen);
@@ -409,13 +543,96 @@ impl<'a> Message<'a> {
             }
         });
 
+        let depth = match gen.nesting_depth(self.name).bounded() {
+            Some(d) => {
+                let d = Literal::usize_unsuffixed(d as usize);
+                quote! { ::core::option::Option::Some(#d) }
+            }
+            None => quote! { ::core::option::Option::None },
+        };
+
         let name = &self.rust_name;
         let lifetime = &self.lifetime;
-        quote! {
+        let required_checks = self.generate_required_checks();
+        let capacity_consts = self.fields.iter().map(|f| f.generate_capacity_consts());
+        let clear_func = self.generate_clear_func(gen)?;
+        Ok(quote! {
             impl<#lifetime> #name<#lifetime> {
+                /// Worst-case nesting depth of this message, which is the number of levels of
+                /// nested messages that valid input can have, counting this message. This is a
+                /// depth, not a stack size. `None` if the message is recursive or contains a type
+                /// of unknown depth, such as an extern type.
+                pub const MAX_NESTING_DEPTH: ::core::option::Option<usize> = #depth;
+
+                #(#capacity_consts)*
+
                 #(#accessors)*
+
+                #clear_func
+
+                #required_checks
             }
+        })
+    }
+
+    fn has_required(&self) -> bool {
+        self.fields.iter().any(|f| f.required)
+    }
+
+    /// Presence checks of `required` fields, only generated for messages that have them
+    fn generate_required_checks(&self) -> Option<TokenStream> {
+        if !self.has_required() {
+            return None;
         }
+        let checks = self.fields.iter().filter(|f| f.required).map(|f| {
+            let num = Literal::u32_unsuffixed(f.num);
+            let missing = f.generate_is_default();
+            quote! {
+                if #missing {
+                    return ::core::option::Option::Some(#num);
+                }
+            }
+        });
+
+        Some(quote! {
+            /// Returns the field number of the first `required` field that isn't present, or
+            /// `None` if all of them are present.
+            ///
+            /// Only the fields of this message are checked, not those of nested messages.
+            pub fn missing_required_field(&self) -> ::core::option::Option<u32> {
+                #(#checks)*
+                ::core::option::Option::None
+            }
+
+            /// Returns `true` if all `required` fields of the message are present.
+            #[inline]
+            pub fn is_initialized(&self) -> bool {
+                self.missing_required_field().is_none()
+            }
+        })
+    }
+
+    /// Container traits imported by the generated decode and encode logic. `PbMap` and `PbString`
+    /// are only imported if the message has `map` or `string` fields, since they're gated behind
+    /// the `maps` and `strings` features of `micropb`.
+    fn container_traits(&self) -> TokenStream {
+        let has_map = self
+            .fields
+            .iter()
+            .any(|f| matches!(f.ftype, FieldType::Map { .. }));
+        let has_string = self.fields.iter().any(|f| match &f.ftype {
+            FieldType::Map { key, val, .. } => key.is_string() || val.is_string(),
+            FieldType::Single(t)
+            | FieldType::Optional(t, _)
+            | FieldType::Repeated { typ: t, .. } => t.is_string(),
+            FieldType::Custom(_) => false,
+        }) || self.oneofs.iter().any(|o| match &o.otype {
+            OneofType::Enum { fields, .. } => fields.iter().any(|f| f.tspec.is_string()),
+            OneofType::Custom { .. } => false,
+        });
+        let map = has_map.then(|| quote! { PbMap, });
+        let string = has_string.then(|| quote! { PbString, });
+        quote! { PbVec, #map #string }
     }
 
     pub(crate) fn generate_decode_trait(&self, gen: &Generator) -> TokenStream {
@@ -424,6 +641,25 @@ impl<'a> Message<'a> {
         let tag = Ident::new("tag", Span::call_site());
         let decoder = Ident::new("decoder", Span::call_site());
         let mod_name = resolve_path_elem(self.name);
+        let inline = self.decode_inline.map(Inline::attr);
+        let container_traits = self.container_traits();
+
+        if self.unit_struct {
+            // Message has no fields, so its contents can be skipped without being parsed
+            return quote! {
+                impl ::micropb::MessageDecode for #name {
+                    #inline
+                    fn decode<IMPL_MICROPB_READ: ::micropb::PbRead>(
+                        &mut self,
+                        #decoder: &mut ::micropb::PbDecoder<IMPL_MICROPB_READ>,
+                        len: usize,
+                    ) -> Result<(), ::micropb::DecodeError<IMPL_MICROPB_READ::Error>>
+                    {
+                        #decoder.skip_bytes(len)
+                    }
+                }
+            };
+        }
 
         let field_branches = self
             .fields
@@ -437,32 +673,118 @@ impl<'a> Message<'a> {
         let unknown_branch = if self.unknown_handler.is_some() {
             // If the unknown handler can't handle a field, skip it
             quote! { if !self._unknown.decode_field(#tag, #decoder)? { #decoder.skip_wire_value(#tag.wire_type())?; } }
+        } else if self.reject_unknown {
+            quote! { return Err(::micropb::DecodeError::UnknownField(#tag.field_num())); }
         } else {
             quote! { #decoder.skip_wire_value(#tag.wire_type())?; }
         };
 
+        // Required fields are checked by the decoding entry points once the whole message has
+        // been decoded, since `decode` may only be given some of the fields
+        let required_check = (self.enforce_required && self.has_required()).then(|| {
+            quote! {
+                if let ::core::option::Option::Some(num) = self.missing_required_field() {
+                    return Err(::micropb::DecodeError::MissingRequired(num));
+                }
+                Ok(())
+            }
+        });
+
+        let field_match = quote! {
+            match #tag.field_num() {
+                0 => return Err(::micropb::DecodeError::ZeroField),
+                #(#field_branches)*
+                #(#oneof_branches)*
+                _ => { #unknown_branch }
+            }
+        };
+        let field_decode = if self.field_path_errors {
+            // Run the field decode logic in a closure so that the field number can be recorded
+            // on every error it returns, no matter where it came from
+            let err_type = if self.borrowed {
+                quote! { ::micropb::DecodeError<<&#lifetime [u8] as ::micropb::PbRead>::Error> }
+            } else {
+                quote! { ::micropb::DecodeError<IMPL_MICROPB_READ::Error> }
+            };
+            quote! {
+                let res = (|| -> Result<(), #err_type> {
+                    #field_match
+                    Ok(())
+                })();
+                if let Err(e) = res {
+                    #decoder.push_error_field(#tag.field_num());
+                    return Err(e);
+                }
+            }
+        } else {
+            field_match
+        };
+
+        let decode_state = self.fields.iter().filter_map(|f| f.generate_decode_state());
+        let decode_finish = self
+            .fields
+            .iter()
+            .filter_map(|f| f.generate_decode_finish(&decoder));
+        let body = quote! {
+            #(#decode_state)*
+            let before = #decoder.bytes_read();
+            while #decoder.bytes_read() - before < len {
+                let #tag = #decoder.decode_tag()?;
+                #field_decode
+            }
+            #(#decode_finish)*
+            Ok(())
+        };
+
+        if self.borrowed {
+            let check_required_borrowed = required_check.map(|check| {
+                quote! {
+                    fn check_required_borrowed(
+                        &self,
+                    ) -> Result<(), ::micropb::DecodeError<<&#lifetime [u8] as ::micropb::PbRead>::Error>>
+                    {
+                        #check
+                    }
+                }
+            });
+            // Borrowed fields can only be decoded from a slice that outlives the message
+            return quote! {
+                impl<#lifetime> ::micropb::MessageDecodeBorrowed<#lifetime> for #name<#lifetime> {
+                    #inline
+                    fn decode_borrowed(
+                        &mut self,
+                        #decoder: &mut ::micropb::PbDecoder<&#lifetime [u8]>,
+                        len: usize,
+                    ) -> Result<(), ::micropb::DecodeError<<&#lifetime [u8] as ::micropb::PbRead>::Error>>
+                    {
+                        use ::micropb::{#container_traits FieldDecode, MessageDecode, MessageDecodeBorrowed};
+                        #body
+                    }
+                    #check_required_borrowed
+                }
+            };
+        }
+
+        let check_required = required_check.map(|check| {
+            quote! {
+                fn check_required<IMPL_MICROPB_ERR>(&self) -> Result<(), ::micropb::DecodeError<IMPL_MICROPB_ERR>> {
+                    #check
+                }
+            }
+        });
         quote! {
             impl<#lifetime> ::micropb::MessageDecode for #name<#lifetime> {
+                #inline
                 fn decode<IMPL_MICROPB_READ: ::micropb::PbRead>(
                     &mut self,
                     #decoder: &mut ::micropb::PbDecoder<IMPL_MICROPB_READ>,
                     len: usize,
                 ) -> Result<(), ::micropb::DecodeError<IMPL_MICROPB_READ::Error>>
                 {
-                    use ::micropb::{PbVec, PbMap, PbString, FieldDecode};
-
-                    let before = #decoder.bytes_read();
-                    while #decoder.bytes_read() - before < len {
-                        let #tag = #decoder.decode_tag()?;
-                        match #tag.field_num() {
-                            0 => return Err(::micropb::DecodeError::ZeroField),
-                            #(#field_branches)*
-                            #(#oneof_branches)*
-                            _ => { #unknown_branch }
-                        }
-                    }
-                    Ok(())
+                    use ::micropb::{#container_traits FieldDecode};
+                    #body
                 }
+                #check_required
             }
         }
     }
@@ -470,14 +792,31 @@ impl<'a> Message<'a> {
     fn generate_encode_func(&self, gen: &Generator, func_type: &EncodeFunc) -> TokenStream {
         let mod_name = resolve_path_elem(self.name);
 
-        let field_logic = self
-            .fields
-            .iter()
-            .map(|f| f.generate_encode(gen, func_type));
-        let oneof_logic = self
-            .oneofs
-            .iter()
-            .map(|o| o.generate_encode(gen, &mod_name, func_type));
+        let encode_logic = if self.deterministic_encode {
+            let parts = self
+                .fields
+                .iter()
+                .map(|f| (f.num, f.generate_encode(gen, func_type)))
+                .chain(
+                    self.oneofs
+                        .iter()
+                        .flat_map(|o| o.generate_encode_parts(gen, &mod_name, func_type)),
+                );
+            Self::sort_encode_parts(parts)
+        } else {
+            let field_logic = self
+                .fields
+                .iter()
+                .map(|f| f.generate_encode(gen, func_type));
+            let oneof_logic = self
+                .oneofs
+                .iter()
+                .map(|o| o.generate_encode(gen, &mod_name, func_type));
+            quote! {
+                #(#field_logic)*
+                #(#oneof_logic)*
+            }
+        };
 
         let unknown_logic = if self.unknown_handler.is_some() {
             match func_type {
@@ -490,16 +829,339 @@ impl<'a> Message<'a> {
             quote! {}
         };
 
+        quote! {
+            #encode_logic
+            #unknown_logic
+        }
+    }
+
+    /// Concatenate the encoding logic of each field in ascending field number order
+    fn sort_encode_parts(parts: impl Iterator<Item = (u32, TokenStream)>) -> TokenStream {
+        let mut parts: Vec<_> = parts.collect();
+        // Stable sort, so parts with the same number keep their relative order
+        parts.sort_by_key(|(num, _)| *num);
+        let logic = parts.into_iter().map(|(_, logic)| logic);
+        quote! { #(#logic)* }
+    }
+
+    fn generate_encode_changed_func(&self, gen: &Generator, func_type: &EncodeFunc) -> TokenStream {
+        let mod_name = resolve_path_elem(self.name);
+
+        if self.deterministic_encode {
+            let parts = self
+                .fields
+                .iter()
+                .map(|f| (f.num, f.generate_encode_changed(gen, func_type)))
+                .chain(
+                    self.oneofs
+                        .iter()
+                        .flat_map(|o| o.generate_encode_changed_parts(gen, &mod_name, func_type)),
+                );
+            return Self::sort_encode_parts(parts);
+        }
+
+        let field_logic = self
+            .fields
+            .iter()
+            .map(|f| f.generate_encode_changed(gen, func_type));
+        let oneof_logic = self
+            .oneofs
+            .iter()
+            .map(|o| o.generate_encode_changed(gen, &mod_name, func_type));
+
         quote! {
             #(#field_logic)*
             #(#oneof_logic)*
-            #unknown_logic
         }
     }
 
+    fn generate_encode_changed_methods(&self, gen: &Generator) -> TokenStream {
+        if !self.encode_changed {
+            return quote! {};
+        }
+
+        let inline = self.encode_inline.map(Inline::attr);
+        let container_traits = self.container_traits();
+        let sizeof = self.generate_encode_changed_func(
+            gen,
+            &EncodeFunc::Sizeof(Ident::new("size", Span::call_site())),
+        );
+        let encode = self.generate_encode_changed_func(
+            gen,
+            &EncodeFunc::Encode(Ident::new("encoder", Span::call_site())),
+        );
+
+        quote! {
+            #inline
+            fn encode_changed<IMPL_MICROPB_WRITE: ::micropb::PbWrite>(
+                &self,
+                base: &Self,
+                encoder: &mut ::micropb::PbEncoder<IMPL_MICROPB_WRITE>,
+            ) -> Result<(), IMPL_MICROPB_WRITE::Error>
+            {
+                use ::micropb::{#container_traits FieldEncode, MessageEncode};
+                #encode
+                Ok(())
+            }
+
+            #inline
+            fn compute_changed_size(&self, base: &Self) -> usize {
+                use ::micropb::{#container_traits FieldEncode, MessageEncode};
+                let mut size = 0;
+                #sizeof
+                size
+            }
+        }
+    }
+
+    fn generate_field_size_funcs(&self, gen: &Generator) -> TokenStream {
+        let mod_name = resolve_path_elem(self.name);
+        let size = Ident::new("size", Span::call_site());
+        let func_type = EncodeFunc::Sizeof(size.clone());
+        let container_traits = self.container_traits();
+
+        let field_funcs = self
+            .fields
+            .iter()
+            .filter(|f| !matches!(f.ftype, FieldType::Custom(CustomField::Delegate(_))))
+            .map(|f| (f.rust_name.clone(), f.generate_encode(gen, &func_type)));
+        let oneof_funcs = self
+            .oneofs
+            .iter()
+            .filter(|o| {
+                !matches!(
+                    o.otype,
+                    OneofType::Custom {
+                        field: CustomField::Delegate(_),
+                        ..
+                    }
+                )
+            })
+            .map(|o| {
+                let name = o.san_rust_name.to_string();
+                let name = name.trim_start_matches("r#").to_owned();
+                (name, o.generate_encode(gen, &mod_name, &func_type))
+            });
+
+        let funcs = field_funcs.chain(oneof_funcs).map(|(name, sizeof)| {
+            let func_name = format_ident!("field_size_{name}");
+            let doc = format!(
+                "Compute the encoded size of `{name}`, including its tag. Returns 0 if the field won't be encoded."
+            );
+            quote! {
+                #[doc = #doc]
+                pub fn #func_name(&self) -> usize {
+                    use ::micropb::{#container_traits FieldEncode, MessageEncode};
+                    let mut #size = 0;
+                    #sizeof
+                    #size
+                }
+            }
+        });
+        quote! { #(#funcs)* }
+    }
+
+    fn generate_is_default_func(&self) -> TokenStream {
+        let container_traits = self.container_traits();
+        let field_checks = self.fields.iter().filter_map(|f| f.generate_is_default());
+        let oneof_checks = self.oneofs.iter().filter_map(|o| o.generate_is_default());
+        let unknown_check = self
+            .unknown_handler
+            .as_ref()
+            .map(|_| quote! { self._unknown.compute_fields_size() == 0 });
+        let checks = field_checks.chain(oneof_checks).chain(unknown_check);
+
+        quote! {
+            /// Returns `true` if none of the message's fields will be encoded, meaning that the
+            /// message encodes to zero bytes.
+            ///
+            /// Checks the presence of each field directly, without constructing a default instance
+            /// of the message.
+            pub fn is_default(&self) -> bool {
+                use ::micropb::{#container_traits FieldEncode};
+                true #(&& #checks)*
+            }
+        }
+    }
+
+    pub(crate) fn generate_heap_size_impl(&self) -> TokenStream {
+        let name = &self.rust_name;
+        if self.unit_struct {
+            return quote! {
+                impl ::micropb::HeapSize for #name {
+                    fn heap_bytes_used(&self) -> usize {
+                        0
+                    }
+                }
+            };
+        }
+
+        let lifetime = &self.lifetime;
+        let field_sizes = self.fields.iter().filter_map(|f| f.generate_heap_size());
+        let oneof_sizes = self.oneofs.iter().filter_map(|o| o.generate_heap_size());
+        let unknown_size = self
+            .unknown_handler
+            .as_ref()
+            .map(|_| quote! { ::micropb::HeapSize::heap_bytes_used(&self._unknown) });
+        let sizes = field_sizes.chain(oneof_sizes).chain(unknown_size);
+
+        quote! {
+            impl<#lifetime> ::micropb::HeapSize for #name<#lifetime> {
+                fn heap_bytes_used(&self) -> usize {
+                    0 #(+ #sizes)*
+                }
+            }
+        }
+    }
+
+    pub(crate) fn generate_merge_impl(&self) -> TokenStream {
+        let name = &self.rust_name;
+        if self.unit_struct {
+            return quote! {
+                impl ::micropb::MessageMerge for #name {
+                    fn merge(&mut self, _other: &Self) -> Result<(), ()> {
+                        Ok(())
+                    }
+                }
+            };
+        }
+
+        let lifetime = &self.lifetime;
+        let mod_name = resolve_path_elem(self.name);
+        let field_merges = self.fields.iter().filter_map(|f| f.generate_merge());
+        let oneof_merges = self
+            .oneofs
+            .iter()
+            .filter_map(|o| o.generate_merge(&mod_name));
+
+        quote! {
+            impl<#lifetime> ::micropb::MessageMerge for #name<#lifetime> {
+                fn merge(&mut self, other: &Self) -> Result<(), ()> {
+                    #(#field_merges)*
+                    #(#oneof_merges)*
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Generate a round-trip test that encodes the default value of the message into a fixed
+    /// buffer and decodes it back. Only uses `core` APIs, so it also runs in `no_std` crates.
+    pub(crate) fn generate_roundtrip_test(&self) -> Option<TokenStream> {
+        if !(self.impl_default && self.derive_partial_eq) {
+            return None;
+        }
+        let name = &self.rust_name;
+        let test_name = format_ident!("_micropb_roundtrip_{}", self.rust_name);
+        let decode = if self.borrowed {
+            quote! { ::micropb::MessageDecodeBorrowed::decode_borrowed }
+        } else {
+            quote! { ::micropb::MessageDecode::decode }
+        };
+        let assert = if self.derive_dbg {
+            quote! { assert_eq!(decoded, msg); }
+        } else {
+            quote! { assert!(decoded == msg); }
+        };
+
+        Some(quote! {
+            #[cfg(test)]
+            #[test]
+            #[allow(non_snake_case)]
+            fn #test_name() {
+                let msg = #name::default();
+                let size = ::micropb::MessageEncode::compute_size(&msg);
+                let mut encoder =
+                    ::micropb::PbEncoder::new(::micropb::container::FixedVec::<u8, 1024>::new());
+                ::micropb::MessageEncode::encode(&msg, &mut encoder)
+                    .expect("encoded message exceeds test buffer");
+                let buf = encoder.into_writer();
+                assert_eq!(buf.as_slice().len(), size);
+
+                let mut decoded = #name::default();
+                let mut decoder = ::micropb::PbDecoder::new(buf.as_slice());
+                #decode(&mut decoded, &mut decoder, size).unwrap();
+                #assert
+            }
+        })
+    }
+
+    /// Conversions to and from the prost-generated message at `prost_type`, which go through the
+    /// wire format
+    pub(crate) fn generate_prost_conversions(
+        &self,
+        prost_type: &TokenStream,
+    ) -> Option<TokenStream> {
+        // Borrowed messages can't outlive the buffer encoded from the prost message
+        if !self.impl_default || self.lifetime.is_some() {
+            return None;
+        }
+        let name = &self.rust_name;
+
+        Some(quote! {
+            impl ::core::convert::TryFrom<&#name> for #prost_type {
+                type Error = ::prost::DecodeError;
+
+                fn try_from(msg: &#name) -> Result<Self, Self::Error> {
+                    let size = ::micropb::MessageEncode::compute_size(msg);
+                    let mut encoder = ::micropb::PbEncoder::new(::alloc::vec::Vec::with_capacity(size));
+                    if let Err(never) = ::micropb::MessageEncode::encode(msg, &mut encoder) {
+                        match never {}
+                    }
+                    <Self as ::prost::Message>::decode(encoder.into_writer().as_slice())
+                }
+            }
+
+            impl ::core::convert::TryFrom<&#prost_type> for #name {
+                type Error = ::micropb::DecodeError<<&'static [u8] as ::micropb::PbRead>::Error>;
+
+                fn try_from(msg: &#prost_type) -> Result<Self, Self::Error> {
+                    let buf = ::prost::Message::encode_to_vec(msg);
+                    let mut decoded = Self::default();
+                    // Also checks the required fields, which prost doesn't enforce
+                    ::micropb::MessageDecode::merge_from_slice(&mut decoded, &buf)?;
+                    Ok(decoded)
+                }
+            }
+        })
+    }
+
     pub(crate) fn generate_encode_trait(&self, gen: &Generator) -> TokenStream {
         let name = &self.rust_name;
         let lifetime = &self.lifetime;
+        let inline = self.encode_inline.map(Inline::attr);
+        let container_traits = self.container_traits();
+
+        if self.unit_struct {
+            return quote! {
+                impl ::micropb::MessageEncode for #name {
+                    #inline
+                    fn encode<IMPL_MICROPB_WRITE: ::micropb::PbWrite>(
+                        &self,
+                        _encoder: &mut ::micropb::PbEncoder<IMPL_MICROPB_WRITE>,
+                    ) -> Result<(), IMPL_MICROPB_WRITE::Error>
+                    {
+                        Ok(())
+                    }
+
+                    #inline
+                    fn compute_size(&self) -> usize {
+                        0
+                    }
+                }
+
+                impl #name {
+                    /// Always returns `true`, since the message has no fields.
+                    pub fn is_default(&self) -> bool {
+                        true
+                    }
+                }
+            };
+        }
+
+        let field_size_funcs = self.generate_field_size_funcs(gen);
+        let is_default_func = self.generate_is_default_func();
+        let encode_changed_methods = self.generate_encode_changed_methods(gen);
         let sizeof = self.generate_encode_func(
             gen,
             &EncodeFunc::Sizeof(Ident::new("size", Span::call_site())),
@@ -511,27 +1173,52 @@ impl<'a> Message<'a> {
 
         quote! {
             impl<#lifetime> ::micropb::MessageEncode for #name<#lifetime> {
+                #inline
                 fn encode<IMPL_MICROPB_WRITE: ::micropb::PbWrite>(
                     &self,
                     encoder: &mut ::micropb::PbEncoder<IMPL_MICROPB_WRITE>,
                 ) -> Result<(), IMPL_MICROPB_WRITE::Error>
                 {
-                    use ::micropb::{PbVec, PbMap, PbString, FieldEncode};
+                    use ::micropb::{#container_traits FieldEncode};
                     #encode
                     Ok(())
                 }
 
+                #inline
                 fn compute_size(&self) -> usize {
-                    use ::micropb::{PbVec, PbMap, PbString, FieldEncode};
+                    use ::micropb::{#container_traits FieldEncode};
                     let mut size = 0;
                     #sizeof
                     size
                 }
+
+                #encode_changed_methods
+            }
+
+            impl<#lifetime> #name<#lifetime> {
+                #field_size_funcs
+                #is_default_func
             }
         }
     }
 }
 
+/// Type of the `_unknown` field generated by the `unknown_fields` config, which stores the fields
+/// in the container configured for `_unknown`.
+fn unknown_fields_type(unknown_conf: &CurrentConfig) -> Result<syn::Type, String> {
+    let type_path = unknown_conf.config.vec_type_parsed()?.ok_or_else(|| {
+        "Message has unknown_fields enabled, but vec_type was not configured for it".to_owned()
+    })?;
+    let max_bytes = unknown_conf
+        .config
+        .max_bytes
+        .map(Literal::u32_unsuffixed)
+        .into_iter();
+    Ok(syn::parse_quote! {
+        ::micropb::callback::UnknownFields<#type_path <u8 #(, #max_bytes)* >>
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
@@ -651,7 +1338,18 @@ mod tests {
             derive_clone: true,
             attrs: vec![],
             unknown_handler: None,
+            reject_unknown: false,
+            enforce_required: false,
+            field_path_errors: false,
             lifetime: None,
+            borrowed: false,
+            decode_inline: None,
+            encode_inline: None,
+            unit_struct: false,
+            encode_changed: false,
+            merge_impl: false,
+            deterministic_encode: false,
+            default_instance: false,
         };
         let config = Box::new(Config::new());
         let mut node = Node::default();
@@ -703,7 +1401,8 @@ mod tests {
                 .type_attributes("#[derive(Self)]")
                 .no_debug_impl(true)
                 .no_default_impl(true)
-                .unknown_handler("UnknownType"),
+                .unknown_handler("UnknownType")
+                .decode_inline(Inline::Never),
         );
         let mut node = Node::default();
         *node.add_path(["bool_field"].into_iter()).value_mut() =
@@ -771,7 +1470,8 @@ mod tests {
                             key: TypeSpec::Int(PbInt::Int64, IntSize::S16),
                             val: TypeSpec::Int(PbInt::Uint64, IntSize::S16),
                             type_path: syn::parse_str("Map").unwrap(),
-                            max_len: None
+                            max_len: None,
+                            sorted: false
                         }
                     ),
                 ],
@@ -781,7 +1481,18 @@ mod tests {
                 derive_clone: true,
                 attrs: parse_attributes("#[derive(Self)]").unwrap(),
                 unknown_handler: Some(syn::parse_str("UnknownType").unwrap()),
-                lifetime: None
+                reject_unknown: false,
+                enforce_required: false,
+                field_path_errors: false,
+                lifetime: None,
+                borrowed: false,
+                decode_inline: Some(Inline::Never),
+                encode_inline: None,
+                unit_struct: false,
+                encode_changed: false,
+                merge_impl: false,
+                deterministic_encode: false,
+                default_instance: false,
             }
         )
     }
@@ -832,13 +1543,25 @@ mod tests {
                 derive_clone: true,
                 attrs: vec![],
                 unknown_handler: None,
-                lifetime: None
+                reject_unknown: false,
+                enforce_required: false,
+                field_path_errors: false,
+                lifetime: None,
+                borrowed: false,
+                decode_inline: None,
+                encode_inline: None,
+                unit_struct: false,
+                encode_changed: false,
+                merge_impl: false,
+                deterministic_encode: false,
+                default_instance: false,
             }
         )
     }
 
     #[test]
     fn hazzer_empty() {
+        let gen = Generator::new();
         let config = CurrentConfig {
             node: None,
             config: Cow::Owned(Box::new(Config::new())),
@@ -862,8 +1585,596 @@ mod tests {
             derive_clone: true,
             attrs: vec![],
             unknown_handler: None,
+            reject_unknown: false,
+            enforce_required: false,
+            field_path_errors: false,
             lifetime: None,
+            borrowed: false,
+            decode_inline: None,
+            encode_inline: None,
+            unit_struct: false,
+            encode_changed: false,
+            merge_impl: false,
+            deterministic_encode: false,
+            default_instance: false,
         };
-        assert!(msg.generate_hazzer_decl(config).unwrap().is_none());
+        assert!(msg.generate_hazzer_decl(&gen, config).unwrap().is_none());
+    }
+
+    #[test]
+    fn no_debug_impls() {
+        let mut gen = Generator::new();
+        gen.debug_impls(false);
+        let config = CurrentConfig {
+            node: None,
+            config: Cow::Owned(Box::new(Config::new())),
+        };
+        let msg = Message {
+            name: "msg",
+            rust_name: Ident::new("msg", Span::call_site()),
+            oneofs: vec![],
+            fields: vec![make_test_field(
+                1,
+                "field1",
+                false,
+                FieldType::Optional(TypeSpec::Bool, OptionalRepr::Hazzer),
+            )],
+            derive_dbg: true,
+            impl_default: true,
+            derive_partial_eq: true,
+            derive_clone: true,
+            attrs: vec![],
+            unknown_handler: None,
+            reject_unknown: false,
+            enforce_required: false,
+            field_path_errors: false,
+            lifetime: None,
+            borrowed: false,
+            decode_inline: None,
+            encode_inline: None,
+            unit_struct: false,
+            encode_changed: false,
+            merge_impl: false,
+            deterministic_encode: false,
+            default_instance: false,
+        };
+        let decl = msg.generate_decl(&gen, None, &config).unwrap().to_string();
+        assert!(decl.contains("derive (PartialEq , Clone ,)"));
+        assert!(!decl.contains("Debug"));
+        let (hazzer, _) = msg.generate_hazzer_decl(&gen, config).unwrap().unwrap();
+        assert!(!hazzer.to_string().contains("Debug"));
+    }
+
+    #[test]
+    fn defmt_impls() {
+        let mut gen = Generator::new();
+        gen.defmt_impls(true);
+        let config = CurrentConfig {
+            node: None,
+            config: Cow::Owned(Box::new(Config::new())),
+        };
+        let msg = Message {
+            name: "msg",
+            rust_name: Ident::new("msg", Span::call_site()),
+            oneofs: vec![],
+            fields: vec![make_test_field(
+                1,
+                "field1",
+                false,
+                FieldType::Optional(TypeSpec::Bool, OptionalRepr::Hazzer),
+            )],
+            derive_dbg: true,
+            impl_default: true,
+            derive_partial_eq: true,
+            derive_clone: true,
+            attrs: vec![],
+            unknown_handler: None,
+            reject_unknown: false,
+            enforce_required: false,
+            field_path_errors: false,
+            lifetime: None,
+            borrowed: false,
+            decode_inline: None,
+            encode_inline: None,
+            unit_struct: false,
+            encode_changed: false,
+            merge_impl: false,
+            deterministic_encode: false,
+            default_instance: false,
+        };
+        let decl = msg.generate_decl(&gen, None, &config).unwrap().to_string();
+        assert!(decl.contains("derive (Debug , PartialEq , Clone , :: defmt :: Format ,)"));
+        let (hazzer, _) = msg.generate_hazzer_decl(&gen, config).unwrap().unwrap();
+        assert!(hazzer.to_string().contains(":: defmt :: Format"));
+    }
+
+    #[test]
+    fn container_traits() {
+        let gen = Generator::new();
+        let mut proto = test_msg_proto();
+        let config = Box::new(Config::new().map_type("Map").string_type("String"));
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let mut msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            msg.container_traits().to_string(),
+            quote! { PbVec, PbMap, }.to_string()
+        );
+        msg.fields.clear();
+        assert_eq!(
+            msg.container_traits().to_string(),
+            quote! { PbVec, }.to_string()
+        );
+
+        proto.field.push({
+            let mut f = FieldDescriptorProto::default();
+            f.set_number(5);
+            f.set_name("string_field".to_owned());
+            f.set_type(Type::String);
+            f.set_oneof_index(0);
+            f
+        });
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            msg.container_traits().to_string(),
+            quote! { PbVec, PbMap, PbString, }.to_string()
+        );
+    }
+
+    #[test]
+    fn inline_attrs() {
+        let gen = Generator::new();
+        let mut msg = Message {
+            name: "msg",
+            rust_name: Ident::new("msg", Span::call_site()),
+            oneofs: vec![],
+            fields: vec![],
+            derive_dbg: true,
+            impl_default: true,
+            derive_partial_eq: true,
+            derive_clone: true,
+            attrs: vec![],
+            unknown_handler: None,
+            reject_unknown: false,
+            enforce_required: false,
+            field_path_errors: false,
+            lifetime: None,
+            borrowed: false,
+            decode_inline: None,
+            encode_inline: None,
+            unit_struct: false,
+            encode_changed: false,
+            merge_impl: false,
+            deterministic_encode: false,
+            default_instance: false,
+        };
+        let inline_never = quote! { #[inline(never)] }.to_string();
+        let inline_always = quote! { #[inline(always)] }.to_string();
+        assert!(!msg
+            .generate_decode_trait(&gen)
+            .to_string()
+            .contains("inline"));
+        assert!(!msg
+            .generate_encode_trait(&gen)
+            .to_string()
+            .contains("inline"));
+
+        msg.decode_inline = Some(Inline::Never);
+        msg.encode_inline = Some(Inline::Always);
+        let decode = msg.generate_decode_trait(&gen).to_string();
+        assert_eq!(decode.matches(&inline_never).count(), 1);
+        let encode = msg.generate_encode_trait(&gen).to_string();
+        // Applied to both encode and compute_size
+        assert_eq!(encode.matches(&inline_always).count(), 2);
+    }
+
+    #[test]
+    fn unit_struct() {
+        let gen = Generator::new();
+        let config = Box::new(Config::new().unit_struct(true));
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+
+        let mut proto = DescriptorProto::default();
+        proto.set_name("Empty".to_owned());
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(msg.unit_struct);
+        let decl = msg.generate_decl(&gen, None, &msg_conf).unwrap();
+        assert!(decl.to_string().contains("pub struct Empty ;"));
+        let decode = msg.generate_decode_trait(&gen).to_string();
+        assert!(decode.contains("skip_bytes (len)"));
+        assert!(!decode.contains("decode_tag"));
+        let encode = msg.generate_encode_trait(&gen).to_string();
+        assert!(encode.contains("fn compute_size (& self) -> usize { 0 }"));
+
+        // Only applies to messages without fields
+        proto.field.push({
+            let mut f = FieldDescriptorProto::default();
+            f.set_number(1);
+            f.set_name("flag".to_owned());
+            f.set_type(Type::Bool);
+            f
+        });
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(!msg.unit_struct);
+    }
+
+    #[test]
+    fn encode_changed() {
+        let mut gen = Generator::new();
+        gen.syntax = Syntax::Proto3;
+        let mut proto = DescriptorProto::default();
+        proto.set_name("Settings".to_owned());
+        proto.field.push({
+            let mut f = FieldDescriptorProto::default();
+            f.set_number(1);
+            f.set_name("flag".to_owned());
+            f.set_type(Type::Bool);
+            f
+        });
+
+        let config = Box::new(Config::new());
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(!msg.encode_changed);
+        let encode = msg.generate_encode_trait(&gen).to_string();
+        assert!(!encode.contains("encode_changed"));
+
+        let config = Box::new(Config::new().encode_changed(true));
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(msg.encode_changed);
+        let encode = msg.generate_encode_trait(&gen).to_string();
+        assert!(encode.contains("fn encode_changed"));
+        assert!(encode.contains("fn compute_changed_size"));
+        assert!(encode.contains("if val_ref != & base . r#flag"));
+    }
+
+    #[test]
+    fn heap_size_impl() {
+        let gen = Generator::new();
+        let mut proto = DescriptorProto::default();
+        proto.set_name("Msg".to_owned());
+        for (num, name) in [(1, "flag"), (2, "other")] {
+            let mut f = FieldDescriptorProto::default();
+            f.set_number(num);
+            f.set_name(name.to_owned());
+            f.set_type(Type::Bool);
+            proto.field.push(f);
+        }
+
+        let config = Box::new(Config::new());
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        let heap_size = msg.generate_heap_size_impl().to_string();
+        assert!(heap_size.contains(":: micropb :: HeapSize for Msg"));
+        assert!(heap_size.contains(
+            "0 + :: micropb :: HeapSize :: heap_bytes_used (& self . r#flag) \
+             + :: micropb :: HeapSize :: heap_bytes_used (& self . r#other)"
+        ));
+    }
+
+    #[test]
+    fn clear_func() {
+        let gen = Generator::new();
+        let mut proto = DescriptorProto::default();
+        proto.set_name("Msg".to_owned());
+        let mut f = FieldDescriptorProto::default();
+        f.set_number(1);
+        f.set_name("flag".to_owned());
+        f.set_type(Type::Bool);
+        proto.field.push(f);
+
+        let config = Box::new(Config::new());
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        let msg_impl = msg.generate_impl(&gen).unwrap().to_string();
+        assert!(msg_impl.contains(
+            "pub fn clear (& mut self) { \
+             self . r#flag = :: core :: default :: Default :: default () ; \
+             self . _has . clear () ; }"
+        ));
+        let (hazzer, _) = msg.generate_hazzer_decl(&gen, msg_conf).unwrap().unwrap();
+        assert!(hazzer
+            .to_string()
+            .contains("pub fn clear (& mut self) { self . 0 = [0 ; 1] ; }"));
+
+        // Messages without a Default impl don't get `clear()`
+        let config = Box::new(Config::new().no_default_impl(true));
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(!msg
+            .generate_impl(&gen)
+            .unwrap()
+            .to_string()
+            .contains("fn clear ("));
+    }
+
+    #[test]
+    fn merge_impl() {
+        let gen = Generator::new();
+        let mut proto = DescriptorProto::default();
+        proto.set_name("Msg".to_owned());
+        let mut f = FieldDescriptorProto::default();
+        f.set_number(1);
+        f.set_name("flag".to_owned());
+        f.set_type(Type::Bool);
+        proto.field.push(f);
+
+        let config = Box::new(Config::new().merge_impl(true));
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(msg.merge_impl);
+        let merge = msg.generate_merge_impl().to_string();
+        assert!(merge.contains(":: micropb :: MessageMerge for Msg"));
+        assert!(merge
+            .contains("if let :: core :: option :: Option :: Some (val_ref) = other . r#flag ()"));
+        assert!(merge.contains("self . set_flag (val_ref . clone ())"));
+    }
+
+    #[test]
+    fn deterministic_encode() {
+        let mut gen = Generator::new();
+        gen.syntax = Syntax::Proto3;
+        let mut proto = DescriptorProto::default();
+        proto.set_name("Msg".to_owned());
+        // Declared in reverse field number order
+        for (num, name) in [(2, "second"), (1, "first")] {
+            let mut f = FieldDescriptorProto::default();
+            f.set_number(num);
+            f.set_name(name.to_owned());
+            f.set_type(Type::Bool);
+            proto.field.push(f);
+        }
+
+        let config = Box::new(Config::new());
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(!msg.deterministic_encode);
+        let encode = msg.generate_encode_trait(&gen).to_string();
+        assert!(encode.find("r#second").unwrap() < encode.find("r#first").unwrap());
+
+        let config = Box::new(Config::new().deterministic_encode(true));
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(msg.deterministic_encode);
+        let encode = msg.generate_encode_trait(&gen).to_string();
+        assert!(encode.find("r#first").unwrap() < encode.find("r#second").unwrap());
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let gen = Generator::new();
+        let mut proto = DescriptorProto::default();
+        proto.set_name("Msg".to_owned());
+
+        let config = Box::new(Config::new().unknown_fields(true));
+        let mut node = Node::default();
+        *node.add_path(["_unknown"].into_iter()).value_mut() =
+            Some(Box::new(Config::new().vec_type("Vec").max_bytes(8)));
+        let msg_conf = CurrentConfig {
+            node: Some(&node),
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            msg.unknown_handler,
+            Some(syn::parse_str("::micropb::callback::UnknownFields<Vec<u8, 8>>").unwrap())
+        );
+
+        let config = Box::new(
+            Config::new()
+                .unknown_fields(true)
+                .unknown_handler("UnknownType"),
+        );
+        let msg_conf = CurrentConfig {
+            node: Some(&node),
+            config: Cow::Borrowed(&config),
+        };
+        assert!(Message::from_proto(&proto, &gen, &msg_conf).is_err());
+
+        // Container type must be configured
+        let config = Box::new(Config::new().unknown_fields(true));
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert!(Message::from_proto(&proto, &gen, &msg_conf).is_err());
+    }
+
+    #[test]
+    fn reject_unknown_fields() {
+        let gen = Generator::new();
+        let mut proto = DescriptorProto::default();
+        proto.set_name("Msg".to_owned());
+
+        let config = Box::new(Config::new().reject_unknown_fields(true));
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(msg.reject_unknown);
+        let decode = msg.generate_decode_trait(&gen).to_string();
+        assert!(decode.contains(
+            &quote! { return Err(::micropb::DecodeError::UnknownField(tag.field_num())); }
+                .to_string()
+        ));
+
+        let config = Box::new(
+            Config::new()
+                .reject_unknown_fields(true)
+                .unknown_handler("UnknownType"),
+        );
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert!(Message::from_proto(&proto, &gen, &msg_conf).is_err());
+    }
+
+    #[test]
+    fn field_path_errors() {
+        let gen = Generator::new();
+        let mut proto = DescriptorProto::default();
+        proto.set_name("Msg".to_owned());
+
+        let config = Box::new(Config::new());
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(!msg.field_path_errors);
+        let decode = msg.generate_decode_trait(&gen).to_string();
+        assert!(!decode.contains("push_error_field"));
+
+        let config = Box::new(Config::new().field_path_errors(true));
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(msg.field_path_errors);
+        let decode = msg.generate_decode_trait(&gen).to_string();
+        assert!(decode.contains(
+            &quote! {
+                if let Err(e) = res {
+                    decoder.push_error_field(tag.field_num());
+                    return Err(e);
+                }
+            }
+            .to_string()
+        ));
+    }
+
+    #[test]
+    fn prost_conversions() {
+        let gen = Generator::new();
+        let mut proto = DescriptorProto::default();
+        proto.set_name("Msg".to_owned());
+        let config = Box::new(Config::new());
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        let out = msg
+            .generate_prost_conversions(&quote! { prost_pb::Msg })
+            .unwrap()
+            .to_string();
+        assert!(out.contains(
+            &quote! { impl ::core::convert::TryFrom<&Msg> for prost_pb::Msg }.to_string()
+        ));
+        assert!(out.contains(
+            &quote! { impl ::core::convert::TryFrom<&prost_pb::Msg> for Msg }.to_string()
+        ));
+
+        // Messages without Default impls can't be decoded into
+        let config = Box::new(Config::new().no_default_impl(true));
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(msg
+            .generate_prost_conversions(&quote! { prost_pb::Msg })
+            .is_none());
+    }
+
+    #[test]
+    fn roundtrip_test() {
+        let gen = Generator::new();
+        let mut proto = DescriptorProto::default();
+        proto.set_name("Msg".to_owned());
+
+        let config = Box::new(Config::new());
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        let test = msg.generate_roundtrip_test().unwrap().to_string();
+        assert!(test.contains("# [cfg (test)] # [test]"));
+        assert!(test.contains("fn _micropb_roundtrip_Msg ()"));
+        assert!(test.contains(":: micropb :: container :: FixedVec :: < u8 , 1024 >"));
+        assert!(test.contains(":: micropb :: MessageDecode :: decode (& mut decoded"));
+        assert!(test.contains("assert_eq ! (decoded , msg)"));
+
+        let config = Box::new(Config::new().no_partial_eq_impl(true));
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(msg.generate_roundtrip_test().is_none());
     }
 }
@@ -0,0 +1,416 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::descriptor::{
+    DescriptorProto,
+    FieldDescriptorProto_::{Label, Type},
+    FileDescriptorProto, FileDescriptorSet,
+};
+
+use super::{CurrentConfig, Generator};
+
+/// Message type in the nesting graph, keyed by its fully-qualified Protobuf name
+#[derive(Debug, Default)]
+pub(crate) struct MsgNode {
+    /// Map entries are decoded inline by their parent, so they don't add a level of nesting
+    map_entry: bool,
+    /// Fully-qualified names of all message types directly contained in this message
+    children: Vec<String>,
+    /// Names and types of the non-repeated message fields that are stored inline, without a `Box`
+    unboxed: Vec<(String, String)>,
+    /// Whether any `string` or `bytes` field borrows from the decode buffer
+    borrowed: bool,
+    /// Whether the message implements `Default`, which also gives it a `clear()` method
+    impl_default: bool,
+}
+
+/// Builds a graph of the message types in the file descriptor set, following the same
+/// configuration as the code generator. Skipped fields and custom fields aren't part of the graph,
+/// since they aren't decoded by the generated code.
+pub(crate) fn build_graph(gen: &Generator, fdset: &FileDescriptorSet) -> HashMap<String, MsgNode> {
+    let mut graph = HashMap::new();
+    for file in &fdset.file {
+        add_file(gen, file, &mut graph);
+    }
+    graph
+}
+
+fn add_file(gen: &Generator, file: &FileDescriptorProto, graph: &mut HashMap<String, MsgNode>) {
+    let pkg = file.package().map(String::as_str).unwrap_or("");
+    let prefix = if pkg.is_empty() {
+        String::new()
+    } else {
+        format!(".{pkg}")
+    };
+    let cur_config = gen.pkg_config(pkg);
+    for m in &file.message_type {
+        add_msg(m, &prefix, cur_config.next_conf(&m.name), graph);
+    }
+}
+
+fn add_msg(
+    proto: &DescriptorProto,
+    prefix: &str,
+    msg_conf: CurrentConfig,
+    graph: &mut HashMap<String, MsgNode>,
+) {
+    if msg_conf.config.skip.unwrap_or(false) {
+        return;
+    }
+
+    let name = format!("{prefix}.{}", proto.name);
+    let mut node = MsgNode {
+        map_entry: proto.options().map(|o| o.map_entry).unwrap_or(false),
+        children: vec![],
+        unboxed: vec![],
+        borrowed: false,
+        impl_default: msg_conf.impl_default(),
+    };
+    for f in &proto.field {
+        let field_conf = msg_conf.next_conf(&f.name);
+        if field_conf.config.skip.unwrap_or(false) || field_conf.config.custom_field.is_some() {
+            continue;
+        }
+        let mut boxed = field_conf.config.boxed.unwrap_or(false);
+        // Custom oneofs apply to all of their fields
+        if let Some(oneof) = f
+            .oneof_index()
+            .and_then(|&idx| proto.oneof_decl.get(idx as usize))
+        {
+            let oneof_conf = msg_conf.next_conf(&oneof.name);
+            if oneof_conf.config.skip.unwrap_or(false) || oneof_conf.config.custom_field.is_some() {
+                continue;
+            }
+            boxed |= oneof_conf.config.boxed.unwrap_or(false);
+        }
+
+        match f.r#type {
+            Type::String | Type::Bytes => {
+                let elem_conf = if f.label == Label::Repeated {
+                    field_conf.next_conf("elem")
+                } else {
+                    field_conf
+                };
+                node.borrowed |= elem_conf.config.borrowed.unwrap_or(false);
+            }
+            // Opaque messages are stored as bytes
+            Type::Message if field_conf.config.opaque.unwrap_or(false) => {}
+            Type::Message => {
+                let map_entry = proto.nested_type.iter().find(|m| {
+                    m.options().map(|o| o.map_entry).unwrap_or(false)
+                        && f.type_name.rsplit_once('.').map(|(_, n)| n) == Some(&m.name)
+                });
+                if let Some(entry) = map_entry {
+                    // Map keys and values are configured through the map field itself
+                    for (conf_name, kv) in ["key", "value"].into_iter().zip(&entry.field) {
+                        if matches!(kv.r#type, Type::String | Type::Bytes) {
+                            node.borrowed |= field_conf
+                                .next_conf(conf_name)
+                                .config
+                                .borrowed
+                                .unwrap_or(false);
+                        }
+                    }
+                }
+                // Elements of repeated and map fields live in containers, which may or may not
+                // store them inline, so only singular fields are considered
+                if !boxed && f.label != Label::Repeated {
+                    node.unboxed.push((f.name.clone(), f.type_name.clone()));
+                }
+                node.children.push(f.type_name.clone());
+            }
+            _ => {}
+        }
+    }
+
+    for m in &proto.nested_type {
+        add_msg(m, &name, msg_conf.next_conf(&m.name), graph);
+    }
+    graph.insert(name, node);
+}
+
+/// Returns the fully-qualified names of the message types that are referenced by messages in the
+/// graph but aren't part of the graph themselves, such as extern types.
+pub(crate) fn external_refs(graph: &HashMap<String, MsgNode>) -> BTreeSet<&str> {
+    graph
+        .values()
+        .flat_map(|node| &node.children)
+        .filter(|child| !graph.contains_key(*child))
+        .map(String::as_str)
+        .collect()
+}
+
+/// Returns the fully-qualified names of the generated messages that have a `clear()` method.
+pub(crate) fn clearable_msgs(graph: &HashMap<String, MsgNode>) -> HashSet<String> {
+    graph
+        .iter()
+        .filter(|(_, node)| node.impl_default)
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+/// Returns the fully-qualified names of the messages that borrow from the decode buffer, either
+/// through their own fields or through the messages they contain.
+pub(crate) fn borrowed_msgs(graph: &HashMap<String, MsgNode>) -> HashSet<String> {
+    let mut borrowed: HashSet<String> = graph
+        .iter()
+        .filter(|(_, node)| node.borrowed)
+        .map(|(name, _)| name.clone())
+        .collect();
+    loop {
+        let containers: Vec<_> = graph
+            .iter()
+            .filter(|(name, node)| {
+                !borrowed.contains(*name) && node.children.iter().any(|c| borrowed.contains(c))
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        if containers.is_empty() {
+            return borrowed;
+        }
+        borrowed.extend(containers);
+    }
+}
+
+/// Finds a cycle of messages that contain each other through unboxed fields. Such messages would
+/// have infinite size, so they can't be represented in Rust. Returns the fully-qualified names of
+/// the fields that form the cycle, in order.
+///
+/// Messages are visited in name order, so the same cycle is always reported for a given graph.
+pub(crate) fn find_unboxed_cycle(graph: &HashMap<String, MsgNode>) -> Option<Vec<String>> {
+    let mut names: Vec<_> = graph.keys().map(String::as_str).collect();
+    names.sort_unstable();
+    let mut done = HashSet::new();
+    let mut path = vec![];
+    names
+        .into_iter()
+        .find_map(|name| visit_unboxed(name, graph, &mut path, &mut done))
+}
+
+fn visit_unboxed<'a>(
+    name: &'a str,
+    graph: &'a HashMap<String, MsgNode>,
+    path: &mut Vec<(&'a str, &'a str)>,
+    done: &mut HashSet<&'a str>,
+) -> Option<Vec<String>> {
+    if done.contains(name) {
+        return None;
+    }
+    // Message types that aren't in the graph, such as extern types, can't contain our messages
+    let node = graph.get(name)?;
+    for (field, child) in &node.unboxed {
+        path.push((name, field));
+        if let Some(start) = path.iter().position(|(msg, _)| *msg == child) {
+            return Some(
+                path[start..]
+                    .iter()
+                    .map(|(msg, field)| format!("{msg}.{field}"))
+                    .collect(),
+            );
+        }
+        if let Some(cycle) = visit_unboxed(child, graph, path, done) {
+            return Some(cycle);
+        }
+        path.pop();
+    }
+    done.insert(name);
+    None
+}
+
+/// Worst-case nesting depth of a message, which is the number of levels of nested messages that
+/// valid input can have, counting the message itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Depth {
+    /// Depth is bounded by the schema
+    Bounded(u32),
+    /// Message can contain itself, so its depth is unbounded
+    Recursive,
+    /// Message contains a type that isn't in the graph, such as an extern type, whose depth isn't
+    /// known to the generator
+    Unknown,
+}
+
+impl Depth {
+    /// Returns the depth if it's bounded
+    pub(crate) fn bounded(self) -> Option<u32> {
+        match self {
+            Depth::Bounded(d) => Some(d),
+            Depth::Recursive | Depth::Unknown => None,
+        }
+    }
+}
+
+/// Calculates the worst-case nesting depth of every message in the graph. A message with no
+/// message fields has a depth of 1.
+///
+/// If any message reachable from a message is recursive, that message is also recursive.
+/// Otherwise, if any reachable message type isn't in the graph, its depth is unknown.
+pub(crate) fn nesting_depths(graph: &HashMap<String, MsgNode>) -> HashMap<String, Depth> {
+    let mut depths = HashMap::new();
+    let mut visiting = HashSet::new();
+    for name in graph.keys() {
+        calc_depth(name, graph, &mut depths, &mut visiting);
+    }
+    depths
+}
+
+fn calc_depth<'a>(
+    name: &'a str,
+    graph: &'a HashMap<String, MsgNode>,
+    depths: &mut HashMap<String, Depth>,
+    visiting: &mut HashSet<&'a str>,
+) -> Depth {
+    if let Some(depth) = depths.get(name) {
+        return *depth;
+    }
+    let Some(node) = graph.get(name) else {
+        return Depth::Unknown;
+    };
+    // Reaching a message that's already being visited means we've found a cycle. Every message
+    // along the current path can reach the cycle, so they all have unbounded depth.
+    if !visiting.insert(name) {
+        return Depth::Recursive;
+    }
+
+    let mut max_child = Depth::Bounded(0);
+    for child in &node.children {
+        max_child = match (max_child, calc_depth(child, graph, depths, visiting)) {
+            (Depth::Recursive, _) | (_, Depth::Recursive) => Depth::Recursive,
+            (Depth::Unknown, _) | (_, Depth::Unknown) => Depth::Unknown,
+            (Depth::Bounded(a), Depth::Bounded(b)) => Depth::Bounded(a.max(b)),
+        };
+    }
+    let depth = match max_child {
+        Depth::Bounded(d) if !node.map_entry => Depth::Bounded(d + 1),
+        d => d,
+    };
+
+    visiting.remove(name);
+    depths.insert(name.to_owned(), depth);
+    depth
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(map_entry: bool, children: &[&str]) -> MsgNode {
+        MsgNode {
+            map_entry,
+            children: children.iter().map(|&s| s.to_owned()).collect(),
+            unboxed: vec![],
+            borrowed: false,
+            impl_default: true,
+        }
+    }
+
+    fn unboxed_node(fields: &[(&str, &str)]) -> MsgNode {
+        MsgNode {
+            map_entry: false,
+            children: fields.iter().map(|(_, t)| t.to_string()).collect(),
+            unboxed: fields
+                .iter()
+                .map(|(f, t)| (f.to_string(), t.to_string()))
+                .collect(),
+            borrowed: false,
+            impl_default: true,
+        }
+    }
+
+    #[test]
+    fn depths() {
+        let graph = HashMap::from([
+            (".Leaf".to_owned(), node(false, &[])),
+            (".Single".to_owned(), node(false, &[".Leaf"])),
+            (".Double".to_owned(), node(false, &[".Leaf", ".Single"])),
+            (".Extern".to_owned(), node(false, &[".ext.Type"])),
+            (".Map".to_owned(), node(false, &[".Map.Entry"])),
+            (".Map.Entry".to_owned(), node(true, &[".Single"])),
+            (".Rec".to_owned(), node(false, &[".Leaf", ".Rec"])),
+            (".RecA".to_owned(), node(false, &[".RecB"])),
+            (".RecB".to_owned(), node(false, &[".RecA"])),
+            (".HasRec".to_owned(), node(false, &[".RecB", ".Leaf"])),
+            (
+                ".RecExtern".to_owned(),
+                node(false, &[".ext.Type", ".RecExtern"]),
+            ),
+        ]);
+        let depths = nesting_depths(&graph);
+        assert_eq!(depths[".Leaf"], Depth::Bounded(1));
+        assert_eq!(depths[".Single"], Depth::Bounded(2));
+        assert_eq!(depths[".Double"], Depth::Bounded(3));
+        assert_eq!(depths[".Extern"], Depth::Unknown);
+        assert_eq!(depths[".Map"], Depth::Bounded(3));
+        assert_eq!(depths[".Map.Entry"], Depth::Bounded(2));
+        assert_eq!(depths[".Rec"], Depth::Recursive);
+        assert_eq!(depths[".RecA"], Depth::Recursive);
+        assert_eq!(depths[".RecB"], Depth::Recursive);
+        assert_eq!(depths[".HasRec"], Depth::Recursive);
+        // Recursion takes precedence over unknown depth
+        assert_eq!(depths[".RecExtern"], Depth::Recursive);
+
+        assert_eq!(external_refs(&graph), BTreeSet::from([".ext.Type"]));
+    }
+
+    #[test]
+    fn unboxed_cycles() {
+        // Recursion through boxed or repeated fields is fine
+        let graph = HashMap::from([
+            (".Leaf".to_owned(), unboxed_node(&[])),
+            (".Tree".to_owned(), node(false, &[".Tree", ".Leaf"])),
+            (".Outer".to_owned(), unboxed_node(&[("tree", ".Tree")])),
+        ]);
+        assert_eq!(find_unboxed_cycle(&graph), None);
+
+        let graph = HashMap::from([
+            (".Leaf".to_owned(), unboxed_node(&[])),
+            (
+                ".Rec".to_owned(),
+                unboxed_node(&[("leaf", ".Leaf"), ("rec", ".Rec")]),
+            ),
+        ]);
+        assert_eq!(
+            find_unboxed_cycle(&graph),
+            Some(vec![".Rec.rec".to_owned()])
+        );
+
+        let graph = HashMap::from([
+            (".Entry".to_owned(), unboxed_node(&[("a", ".A")])),
+            (
+                ".A".to_owned(),
+                unboxed_node(&[("b", ".B"), ("ext", ".ext.Type")]),
+            ),
+            (".B".to_owned(), unboxed_node(&[("c", ".C")])),
+            (".C".to_owned(), unboxed_node(&[("a", ".A")])),
+        ]);
+        assert_eq!(
+            find_unboxed_cycle(&graph),
+            Some(vec![
+                ".A.b".to_owned(),
+                ".B.c".to_owned(),
+                ".C.a".to_owned()
+            ])
+        );
+    }
+
+    #[test]
+    fn borrowed() {
+        let mut graph = HashMap::from([
+            (".Leaf".to_owned(), node(false, &[])),
+            (".Str".to_owned(), node(false, &[])),
+            (".Outer".to_owned(), node(false, &[".Leaf", ".Map"])),
+            (".Map".to_owned(), node(false, &[".Map.Entry"])),
+            (".Map.Entry".to_owned(), node(true, &[".Str"])),
+            (".Rec".to_owned(), node(false, &[".Rec", ".Leaf"])),
+        ]);
+        graph.get_mut(".Str").unwrap().borrowed = true;
+        assert_eq!(
+            borrowed_msgs(&graph),
+            HashSet::from([
+                ".Str".to_owned(),
+                ".Map.Entry".to_owned(),
+                ".Map".to_owned(),
+                ".Outer".to_owned()
+            ])
+        );
+    }
+}
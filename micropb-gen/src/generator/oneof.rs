@@ -7,7 +7,7 @@ use super::{
     derive_msg_attr,
     field::CustomField,
     sanitized_ident,
-    type_spec::{find_lifetime_from_type, TypeSpec},
+    type_spec::{borrowed_lifetime, find_lifetime_from_type, TypeSpec},
     CurrentConfig, EncodeFunc, Generator,
 };
 
@@ -179,6 +179,16 @@ impl<'a> Oneof<'a> {
         }
     }
 
+    /// Lifetime of the oneof enum, which is only needed if any of its fields are borrowed
+    fn enum_lifetime(&self, gen: &Generator) -> Option<Lifetime> {
+        match &self.otype {
+            OneofType::Enum { fields, .. } if fields.iter().any(|f| f.tspec.is_borrowed(gen)) => {
+                Some(borrowed_lifetime())
+            }
+            _ => None,
+        }
+    }
+
     pub(crate) fn from_proto(
         proto: &'a OneofDescriptorProto,
         oneof_conf: CurrentConfig,
@@ -220,23 +230,46 @@ impl<'a> Oneof<'a> {
     }
 
     pub(crate) fn generate_decl(&self, gen: &Generator) -> TokenStream {
-        if let OneofType::Enum { type_name, fields } = &self.otype {
-            assert!(!fields.is_empty(), "empty enums should have been filtered");
-            let fields = fields.iter().map(|f| f.generate_field(gen));
+        if let OneofType::Enum {
+            type_name,
+            fields: variants,
+        } = &self.otype
+        {
+            assert!(
+                !variants.is_empty(),
+                "empty enums should have been filtered"
+            );
+            let fields = variants.iter().map(|f| f.generate_field(gen));
             let derive_msg = derive_msg_attr(
-                self.derive_dbg,
+                self.derive_dbg && gen.debug_impls,
                 false,
                 self.derive_partial_eq,
                 self.derive_clone,
+                gen.defmt_impls,
             );
             let attrs = &self.type_attrs;
+            let lifetime = self.enum_lifetime(gen);
+            let heap_size = gen.heap_size_impls.then(|| {
+                let variants = variants.iter().map(|f| &f.rust_name);
+                quote! {
+                    impl<#lifetime> ::micropb::HeapSize for #type_name<#lifetime> {
+                        fn heap_bytes_used(&self) -> usize {
+                            match self {
+                                #(Self::#variants(val) => ::micropb::HeapSize::heap_bytes_used(val),)*
+                            }
+                        }
+                    }
+                }
+            });
 
             quote! {
                 #derive_msg
                 #(#attrs)*
-                pub enum #type_name {
+                pub enum #type_name<#lifetime> {
                     #(#fields)*
                 }
+
+                #heap_size
             }
         } else {
             quote! {}
@@ -247,7 +280,12 @@ impl<'a> Oneof<'a> {
         let name = &self.san_rust_name;
         let oneof_type = match &self.otype {
             OneofType::Enum { type_name, .. } => {
-                gen.wrapped_type(quote! { #msg_mod_name::#type_name }, self.boxed, true)
+                let lifetime = self.enum_lifetime(gen);
+                gen.wrapped_type(
+                    quote! { #msg_mod_name::#type_name<#lifetime> },
+                    self.boxed,
+                    true,
+                )
             }
             OneofType::Custom {
                 field: CustomField::Type(type_path),
@@ -338,6 +376,198 @@ impl<'a> Oneof<'a> {
             } => quote! {},
         }
     }
+
+    /// Generate the encoding logic of the oneof split by field number, so it can be ordered along
+    /// with the other fields for deterministic encoding.
+    pub(crate) fn generate_encode_parts(
+        &self,
+        gen: &Generator,
+        msg_mod_name: &Ident,
+        func_type: &EncodeFunc,
+    ) -> Vec<(u32, TokenStream)> {
+        let name = &self.san_rust_name;
+        match &self.otype {
+            // Emit each variant at its own position, since other fields may have numbers between
+            // the variant numbers
+            OneofType::Enum { type_name, fields } if fields.len() > 1 => {
+                let oneof_type = quote! { #msg_mod_name::#type_name };
+                let extra_deref = self.boxed.then(|| quote! { * });
+                fields
+                    .iter()
+                    .map(|f| {
+                        let branch = f.generate_encode_branch(&oneof_type, gen, func_type);
+                        let logic = quote! {
+                            if let Some(oneof) = &self.#name {
+                                match &#extra_deref *oneof {
+                                    #branch
+                                    _ => {}
+                                }
+                            }
+                        };
+                        (f.num, logic)
+                    })
+                    .collect()
+            }
+
+            OneofType::Enum { fields, .. } => fields
+                .first()
+                .map(|f| (f.num, self.generate_encode(gen, msg_mod_name, func_type)))
+                .into_iter()
+                .collect(),
+
+            // Custom oneofs encode all their fields at once, so emit them at the lowest number
+            OneofType::Custom {
+                field: CustomField::Type(_),
+                nums,
+            } => {
+                let num = nums.iter().min().copied().unwrap_or_default() as u32;
+                vec![(num, self.generate_encode(gen, msg_mod_name, func_type))]
+            }
+
+            OneofType::Custom {
+                field: CustomField::Delegate(_),
+                ..
+            } => vec![],
+        }
+    }
+
+    /// Same as [`generate_encode_parts`](Self::generate_encode_parts), but each part is only
+    /// encoded if the oneof differs from the oneof in `base`.
+    pub(crate) fn generate_encode_changed_parts(
+        &self,
+        gen: &Generator,
+        msg_mod_name: &Ident,
+        func_type: &EncodeFunc,
+    ) -> Vec<(u32, TokenStream)> {
+        let name = &self.san_rust_name;
+        let mut parts = self.generate_encode_parts(gen, msg_mod_name, func_type);
+        for (_, logic) in &mut parts {
+            *logic = quote! {
+                if self.#name != base.#name {
+                    #logic
+                }
+            };
+        }
+        parts
+    }
+
+    /// Generate the logic for encoding the oneof only if it differs from the oneof in `base`.
+    pub(crate) fn generate_encode_changed(
+        &self,
+        gen: &Generator,
+        msg_mod_name: &Ident,
+        func_type: &EncodeFunc,
+    ) -> TokenStream {
+        if let OneofType::Custom {
+            field: CustomField::Delegate(_),
+            ..
+        } = &self.otype
+        {
+            return quote! {};
+        }
+        let name = &self.san_rust_name;
+        let encode = self.generate_encode(gen, msg_mod_name, func_type);
+        quote! {
+            if self.#name != base.#name {
+                #encode
+            }
+        }
+    }
+
+    /// Generate an expression that's true if the oneof won't be encoded. Returns `None` for
+    /// delegate oneofs.
+    pub(crate) fn generate_is_default(&self) -> Option<TokenStream> {
+        let name = &self.san_rust_name;
+        match &self.otype {
+            OneofType::Enum { .. } => Some(quote! { self.#name.is_none() }),
+            OneofType::Custom {
+                field: CustomField::Type(_),
+                ..
+            } => Some(quote! { self.#name.compute_fields_size() == 0 }),
+            OneofType::Custom {
+                field: CustomField::Delegate(_),
+                ..
+            } => None,
+        }
+    }
+
+    /// Generate the logic for resetting the oneof. Returns `None` for delegate oneofs.
+    pub(crate) fn generate_clear(&self) -> Option<TokenStream> {
+        let name = &self.san_rust_name;
+        match &self.otype {
+            OneofType::Enum { .. } => Some(quote! { self.#name = ::core::option::Option::None; }),
+            OneofType::Custom {
+                field: CustomField::Type(_),
+                ..
+            } => Some(quote! { self.#name = ::core::default::Default::default(); }),
+            OneofType::Custom {
+                field: CustomField::Delegate(_),
+                ..
+            } => None,
+        }
+    }
+
+    /// Generate the logic for merging the oneof of `other` into `self`. Returns `None` for custom
+    /// oneofs, which aren't merged.
+    pub(crate) fn generate_merge(&self, msg_mod_name: &Ident) -> Option<TokenStream> {
+        let OneofType::Enum { type_name, fields } = &self.otype else {
+            return None;
+        };
+        let name = &self.san_rust_name;
+        let oneof_type = quote! { #msg_mod_name::#type_name };
+        let extra_deref_of = self.boxed.then(|| quote! { * });
+
+        // Message variants that are set in both oneofs are merged, everything else is overwritten
+        let branches: Vec<_> = fields
+            .iter()
+            .filter(|f| matches!(f.tspec, TypeSpec::Message(_)))
+            .map(|f| {
+                let variant_name = &f.rust_name;
+                let extra_deref_var = f.boxed.then(|| quote! { * });
+                quote! {
+                    (#oneof_type::#variant_name(mut_ref), #oneof_type::#variant_name(val_ref)) => {
+                        ::micropb::MessageMerge::merge(&mut #extra_deref_var *mut_ref, &#extra_deref_var *val_ref)?;
+                        merged = true;
+                    }
+                }
+            })
+            .collect();
+        if branches.is_empty() {
+            return Some(quote! {
+                if other.#name.is_some() {
+                    self.#name = other.#name.clone();
+                }
+            });
+        }
+        Some(quote! {
+            if let ::core::option::Option::Some(val_ref) = &other.#name {
+                let mut merged = false;
+                if let ::core::option::Option::Some(mut_ref) = &mut self.#name {
+                    match (&mut #extra_deref_of *mut_ref, &#extra_deref_of *val_ref) {
+                        #(#branches)*
+                        _ => {}
+                    }
+                }
+                if !merged {
+                    self.#name = other.#name.clone();
+                }
+            }
+        })
+    }
+
+    /// Generate an expression for the heap memory owned by the oneof. Returns `None` for delegate
+    /// oneofs.
+    pub(crate) fn generate_heap_size(&self) -> Option<TokenStream> {
+        if let OneofType::Custom {
+            field: CustomField::Delegate(_),
+            ..
+        } = &self.otype
+        {
+            return None;
+        }
+        let name = &self.san_rust_name;
+        Some(quote! { ::micropb::HeapSize::heap_bytes_used(&self.#name) })
+    }
 }
 
 #[cfg(test)]
@@ -346,7 +576,7 @@ pub(crate) fn make_test_oneof_field(
     name: &str,
     boxed: bool,
     tspec: TypeSpec,
-) -> OneofField {
+) -> OneofField<'_> {
     OneofField {
         num,
         name,
@@ -15,6 +15,8 @@ use super::{CurrentConfig, Generator};
 #[cfg_attr(test, derive(Debug, PartialEq, Eq))]
 pub(crate) enum PbInt {
     Int32,
+    /// `int32` with negative values encoded as 5-byte varints, as configured by `legacy_int32`
+    Int32Legacy,
     Int64,
     Uint32,
     Uint64,
@@ -31,6 +33,7 @@ impl PbInt {
         matches!(
             self,
             PbInt::Int32
+                | PbInt::Int32Legacy
                 | PbInt::Int64
                 | PbInt::Sint32
                 | PbInt::Sint64
@@ -47,7 +50,7 @@ impl PbInt {
             PbInt::Fixed64 if matches!(int_size, IntSize::S64) => "decode_fixed64",
             PbInt::Sfixed64 if matches!(int_size, IntSize::S64) => "decode_sfixed64",
 
-            PbInt::Int32 | PbInt::Int64 => "decode_int32",
+            PbInt::Int32 | PbInt::Int32Legacy | PbInt::Int64 => "decode_int32",
             PbInt::Uint32 | PbInt::Uint64 => "decode_varint32",
             PbInt::Sint32 | PbInt::Sint64 => "decode_sint32",
             PbInt::Sfixed32 => "decode_sfixed32",
@@ -73,6 +76,9 @@ impl PbInt {
             PbInt::Int32 | PbInt::Int64 => {
                 quote! { ::micropb::size::sizeof_int32(* #val_ref as _) }
             }
+            PbInt::Int32Legacy => {
+                quote! { ::micropb::size::sizeof_int32_legacy(* #val_ref as _) }
+            }
             PbInt::Uint32 | PbInt::Uint64 => {
                 quote! { ::micropb::size::sizeof_varint32(* #val_ref as _) }
             }
@@ -96,6 +102,7 @@ impl PbInt {
             PbInt::Fixed64 if matches!(int_size, IntSize::S64) => "encode_fixed64",
 
             PbInt::Int32 | PbInt::Int64 => "encode_int32",
+            PbInt::Int32Legacy => "encode_int32_legacy",
             PbInt::Uint32 | PbInt::Uint64 => "encode_varint32",
             PbInt::Sint32 | PbInt::Sint64 => "encode_sint32",
             PbInt::Sfixed32 => "encode_sfixed32",
@@ -107,6 +114,11 @@ impl PbInt {
     }
 }
 
+/// Lifetime of borrowed `string` and `bytes` fields, and of the messages containing them
+pub(crate) fn borrowed_lifetime() -> Lifetime {
+    Lifetime::new("'a", Span::call_site())
+}
+
 /// Find the first lifetime embedded in a type
 pub(crate) fn find_lifetime_from_type(ty: &syn::Type) -> Option<&Lifetime> {
     match ty {
@@ -146,11 +158,19 @@ pub(crate) enum TypeSpec {
     String {
         type_path: syn::Path,
         max_bytes: Option<u32>,
+        /// Truncate strings that exceed the container's capacity instead of failing
+        truncate: bool,
     },
     Bytes {
         type_path: syn::Path,
         max_bytes: Option<u32>,
+        /// Undecoded message payload, wrapped in `Opaque`
+        opaque: bool,
     },
+    /// `string` borrowed from the decode buffer as `&'a str`
+    BorrowedString,
+    /// `bytes` borrowed from the decode buffer as `&'a [u8]`
+    BorrowedBytes,
 }
 
 impl TypeSpec {
@@ -163,6 +183,14 @@ impl TypeSpec {
         }
     }
 
+    /// Configured capacity of `string` and `bytes` containers
+    pub(crate) fn max_bytes(&self) -> Option<u32> {
+        match self {
+            TypeSpec::String { max_bytes, .. } | TypeSpec::Bytes { max_bytes, .. } => *max_bytes,
+            _ => None,
+        }
+    }
+
     pub(crate) fn from_proto(
         proto: &FieldDescriptorProto,
         type_conf: &CurrentConfig,
@@ -173,24 +201,38 @@ impl TypeSpec {
             Type::Double => TypeSpec::Double,
             Type::Float => TypeSpec::Float,
             Type::Bool => TypeSpec::Bool,
+            Type::String if conf.borrowed.unwrap_or(false) => TypeSpec::BorrowedString,
+            Type::Bytes if conf.borrowed.unwrap_or(false) => TypeSpec::BorrowedBytes,
             Type::String => TypeSpec::String {
                 type_path: conf.string_type_parsed()?.ok_or_else(|| {
                     "Field is of type `string`, but string_type was not configured for it"
                         .to_owned()
                 })?,
                 max_bytes: conf.max_bytes,
+                truncate: conf.truncate_on_overflow.unwrap_or(false),
             },
             Type::Bytes => TypeSpec::Bytes {
                 type_path: conf.vec_type_parsed()?.ok_or_else(|| {
                     "Field is of type `bytes`, but vec_type was not configured for it".to_owned()
                 })?,
                 max_bytes: conf.max_bytes,
+                opaque: false,
+            },
+            Type::Message if conf.opaque.unwrap_or(false) => TypeSpec::Bytes {
+                type_path: conf.vec_type_parsed()?.ok_or_else(|| {
+                    "Field is an opaque message, but vec_type was not configured for it".to_owned()
+                })?,
+                max_bytes: conf.max_bytes,
+                opaque: true,
             },
             Type::Message => TypeSpec::Message(proto.type_name.clone()),
             Type::Enum => TypeSpec::Enum(proto.type_name.clone()),
             Type::Uint32 => TypeSpec::Int(PbInt::Uint32, conf.int_size.unwrap_or(IntSize::S32)),
             Type::Int64 => TypeSpec::Int(PbInt::Int64, conf.int_size.unwrap_or(IntSize::S64)),
             Type::Uint64 => TypeSpec::Int(PbInt::Uint64, conf.int_size.unwrap_or(IntSize::S64)),
+            Type::Int32 if conf.legacy_int32.unwrap_or(false) => {
+                TypeSpec::Int(PbInt::Int32Legacy, conf.int_size.unwrap_or(IntSize::S32))
+            }
             Type::Int32 => TypeSpec::Int(PbInt::Int32, conf.int_size.unwrap_or(IntSize::S32)),
             Type::Fixed64 => TypeSpec::Int(PbInt::Fixed64, conf.int_size.unwrap_or(IntSize::S64)),
             Type::Fixed32 => TypeSpec::Int(PbInt::Fixed32, conf.int_size.unwrap_or(IntSize::S32)),
@@ -203,6 +245,20 @@ impl TypeSpec {
         Ok(res)
     }
 
+    /// Whether the type is a `string` stored in a `PbString` container.
+    pub(crate) fn is_string(&self) -> bool {
+        matches!(self, TypeSpec::String { .. })
+    }
+
+    /// Whether the type borrows from the decode buffer, either directly or through a message
+    pub(crate) fn is_borrowed(&self, gen: &Generator) -> bool {
+        match self {
+            TypeSpec::BorrowedString | TypeSpec::BorrowedBytes => true,
+            TypeSpec::Message(tname) => gen.is_borrowed_msg(tname),
+            _ => false,
+        }
+    }
+
     pub(crate) fn generate_rust_type(&self, gen: &Generator) -> TokenStream {
         match self {
             TypeSpec::Int(pbint, itype) => {
@@ -215,6 +271,7 @@ impl TypeSpec {
             TypeSpec::String {
                 type_path,
                 max_bytes,
+                ..
             } => {
                 let max_bytes = max_bytes.map(Literal::u32_unsuffixed).into_iter();
                 quote! { #type_path #(<#max_bytes>)* }
@@ -222,11 +279,33 @@ impl TypeSpec {
             TypeSpec::Bytes {
                 type_path,
                 max_bytes,
+                opaque,
             } => {
                 let max_bytes = max_bytes.map(Literal::u32_unsuffixed).into_iter();
-                quote! { #type_path <u8 #(, #max_bytes)* > }
+                let typ = quote! { #type_path <u8 #(, #max_bytes)* > };
+                if *opaque {
+                    quote! { ::micropb::container::Opaque<#typ> }
+                } else {
+                    typ
+                }
+            }
+            TypeSpec::BorrowedString => {
+                let lifetime = borrowed_lifetime();
+                quote! { &#lifetime str }
+            }
+            TypeSpec::BorrowedBytes => {
+                let lifetime = borrowed_lifetime();
+                quote! { &#lifetime [u8] }
             }
-            TypeSpec::Message(tname) | TypeSpec::Enum(tname) => {
+            TypeSpec::Message(tname) => {
+                let rust_type = gen.resolve_type_name(tname);
+                let lifetime = gen
+                    .is_borrowed_msg(tname)
+                    .then(borrowed_lifetime)
+                    .into_iter();
+                quote! { #rust_type #(<#lifetime>)* }
+            }
+            TypeSpec::Enum(tname) => {
                 let rust_type = gen.resolve_type_name(tname);
                 quote! { #rust_type }
             }
@@ -257,6 +336,13 @@ impl TypeSpec {
                 }
             }
 
+            TypeSpec::BorrowedString => quote! { #default },
+
+            TypeSpec::BorrowedBytes => {
+                let default_bytes = Literal::byte_string(&unescape_c_escape_string(default));
+                quote! { &#default_bytes[..] }
+            }
+
             TypeSpec::Message(_) => {
                 unreachable!("message fields shouldn't have custom defaults")
             }
@@ -269,6 +355,27 @@ impl TypeSpec {
                 quote! { #enum_path::#variant }
             }
 
+            TypeSpec::Float | TypeSpec::Double => {
+                let ftype = self.generate_rust_type(gen);
+                match default {
+                    "inf" => quote! { #ftype::INFINITY },
+                    "-inf" => quote! { #ftype::NEG_INFINITY },
+                    "nan" => quote! { #ftype::NAN },
+                    _ => {
+                        // Integer literals such as "-0" lose the sign of zero when cast, so
+                        // always emit a float literal
+                        let default = if default.contains(['.', 'e', 'E']) {
+                            default.to_owned()
+                        } else {
+                            format!("{default}.0")
+                        };
+                        let default: TokenStream =
+                            syn::parse_str(&default).expect("default value tokenization error");
+                        quote! { #default as _ }
+                    }
+                }
+            }
+
             _ => {
                 let default: TokenStream =
                     syn::parse_str(default).expect("default value tokenization error");
@@ -290,6 +397,7 @@ impl TypeSpec {
             | TypeSpec::Bool
             | TypeSpec::Int(
                 PbInt::Int32
+                | PbInt::Int32Legacy
                 | PbInt::Int64
                 | PbInt::Uint32
                 | PbInt::Uint64
@@ -297,21 +405,34 @@ impl TypeSpec {
                 | PbInt::Sint64,
                 _,
             ) => micropb::WIRE_TYPE_VARINT,
-            TypeSpec::Message(_) | TypeSpec::String { .. } | TypeSpec::Bytes { .. } => {
-                micropb::WIRE_TYPE_LEN
-            }
+            TypeSpec::Message(_)
+            | TypeSpec::String { .. }
+            | TypeSpec::Bytes { .. }
+            | TypeSpec::BorrowedString
+            | TypeSpec::BorrowedBytes => micropb::WIRE_TYPE_LEN,
         }
     }
 
     pub(crate) fn generate_implicit_presence_check(&self, val_ref: &Ident) -> TokenStream {
+        match self.generate_implicit_presence_cond(val_ref) {
+            Some(cond) => quote! { if #cond },
+            None => quote! {},
+        }
+    }
+
+    /// Condition under which an implicit-presence value is encoded. Messages are always encoded.
+    pub(crate) fn generate_implicit_presence_cond(&self, val_ref: &Ident) -> Option<TokenStream> {
         match self {
-            TypeSpec::Message(_) => quote! {},
-            TypeSpec::Enum(_) => quote! { if #val_ref.0 != 0 },
-            TypeSpec::Float | TypeSpec::Double => quote! { if *#val_ref != 0.0 },
-            TypeSpec::Bool => quote! { if *#val_ref },
-            TypeSpec::Int(_, _) => quote! { if *#val_ref != 0 },
-            TypeSpec::String { .. } => quote! { if !#val_ref.is_empty() },
-            TypeSpec::Bytes { .. } => quote! { if !#val_ref.is_empty() },
+            TypeSpec::Message(_) => None,
+            TypeSpec::Enum(_) => Some(quote! { #val_ref.0 != 0 }),
+            // Compare bits so that -0.0 is encoded, since it's not the default value
+            TypeSpec::Float | TypeSpec::Double => Some(quote! { #val_ref.to_bits() != 0 }),
+            TypeSpec::Bool => Some(quote! { *#val_ref }),
+            TypeSpec::Int(_, _) => Some(quote! { *#val_ref != 0 }),
+            TypeSpec::String { .. }
+            | TypeSpec::Bytes { .. }
+            | TypeSpec::BorrowedString
+            | TypeSpec::BorrowedBytes => Some(quote! { !#val_ref.is_empty() }),
         }
     }
 
@@ -353,6 +474,9 @@ impl TypeSpec {
         let presence_ident = Ident::new(presence, Span::call_site());
 
         match self {
+            TypeSpec::Message(tname) if gen.is_borrowed_msg(tname) => {
+                quote! { #mut_ref.decode_len_delimited_borrowed(#decoder)?; }
+            }
             TypeSpec::Message(_) => quote! { #mut_ref.decode_len_delimited(#decoder)?; },
             TypeSpec::Enum(_)
             | TypeSpec::Float
@@ -379,12 +503,19 @@ impl TypeSpec {
                     #setter
                 }
             }
-            TypeSpec::String { .. } => {
-                quote! { #decoder.decode_string(#mut_ref, ::micropb::Presence::#presence_ident)?; }
+            TypeSpec::String { truncate, .. } => {
+                let decode_fn = if *truncate {
+                    quote! { decode_string_truncated }
+                } else {
+                    quote! { decode_string }
+                };
+                quote! { #decoder.#decode_fn(#mut_ref, ::micropb::Presence::#presence_ident)?; }
             }
             TypeSpec::Bytes { .. } => {
                 quote! { #decoder.decode_bytes(#mut_ref, ::micropb::Presence::#presence_ident)?; }
             }
+            TypeSpec::BorrowedString => quote! { *#mut_ref = #decoder.decode_str_borrowed()?; },
+            TypeSpec::BorrowedBytes => quote! { *#mut_ref = #decoder.decode_bytes_borrowed()?; },
         }
     }
 
@@ -401,7 +532,9 @@ impl TypeSpec {
             TypeSpec::String { .. } => {
                 quote! { ::micropb::size::sizeof_len_record(#val_ref.len()) }
             }
-            TypeSpec::Bytes { .. } => quote! { ::micropb::size::sizeof_len_record(#val_ref.len()) },
+            TypeSpec::Bytes { .. } | TypeSpec::BorrowedString | TypeSpec::BorrowedBytes => {
+                quote! { ::micropb::size::sizeof_len_record(#val_ref.len()) }
+            }
         }
     }
 
@@ -421,8 +554,12 @@ impl TypeSpec {
                 let func = pbint.generate_encode_func(int_size);
                 quote! { #encoder.#func(* #val_ref as _) }
             }
-            TypeSpec::String { .. } => quote! { #encoder.encode_string(#val_ref) },
-            TypeSpec::Bytes { .. } => quote! { #encoder.encode_bytes(#val_ref) },
+            TypeSpec::String { .. } | TypeSpec::BorrowedString => {
+                quote! { #encoder.encode_string(#val_ref) }
+            }
+            TypeSpec::Bytes { .. } | TypeSpec::BorrowedBytes => {
+                quote! { #encoder.encode_bytes(#val_ref) }
+            }
         }
     }
 }
@@ -491,14 +628,16 @@ mod tests {
             TypeSpec::from_proto(&field_proto(Type::String, ""), &type_conf).unwrap(),
             TypeSpec::String {
                 type_path: syn::parse_str("string::String").unwrap(),
-                max_bytes: Some(10)
+                max_bytes: Some(10),
+                truncate: false
             }
         );
         assert_eq!(
             TypeSpec::from_proto(&field_proto(Type::Bytes, ""), &type_conf).unwrap(),
             TypeSpec::Bytes {
                 type_path: syn::parse_str("vec::Vec").unwrap(),
-                max_bytes: Some(10)
+                max_bytes: Some(10),
+                opaque: false
             }
         );
         assert_eq!(
@@ -519,16 +658,65 @@ mod tests {
             TypeSpec::from_proto(&field_proto(Type::String, ""), &type_conf).unwrap(),
             TypeSpec::String {
                 type_path: syn::parse_str("string::String").unwrap(),
-                max_bytes: None
+                max_bytes: None,
+                truncate: false
             }
         );
         assert_eq!(
             TypeSpec::from_proto(&field_proto(Type::Bytes, ""), &type_conf).unwrap(),
             TypeSpec::Bytes {
                 type_path: syn::parse_str("vec::Vec").unwrap(),
-                max_bytes: None
+                max_bytes: None,
+                opaque: false
+            }
+        );
+
+        config.opaque = Some(true);
+        let type_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert_eq!(
+            TypeSpec::from_proto(&field_proto(Type::Message, ".msg.Message"), &type_conf).unwrap(),
+            TypeSpec::Bytes {
+                type_path: syn::parse_str("vec::Vec").unwrap(),
+                max_bytes: None,
+                opaque: true
+            }
+        );
+        // Only applies to message fields
+        assert_eq!(
+            TypeSpec::from_proto(&field_proto(Type::Bool, ""), &type_conf).unwrap(),
+            TypeSpec::Bool
+        );
+
+        config.truncate_on_overflow = Some(true);
+        let type_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert_eq!(
+            TypeSpec::from_proto(&field_proto(Type::String, ""), &type_conf).unwrap(),
+            TypeSpec::String {
+                type_path: syn::parse_str("string::String").unwrap(),
+                max_bytes: None,
+                truncate: true
             }
         );
+
+        config.borrowed = Some(true);
+        let type_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert_eq!(
+            TypeSpec::from_proto(&field_proto(Type::String, ""), &type_conf).unwrap(),
+            TypeSpec::BorrowedString
+        );
+        assert_eq!(
+            TypeSpec::from_proto(&field_proto(Type::Bytes, ""), &type_conf).unwrap(),
+            TypeSpec::BorrowedBytes
+        );
     }
 
     #[test]
@@ -568,6 +756,22 @@ mod tests {
             TypeSpec::from_proto(&field_proto(Type::Uint64, ""), &type_conf).unwrap(),
             TypeSpec::Int(PbInt::Uint64, IntSize::S8)
         );
+
+        config.int_size = None;
+        config.legacy_int32 = Some(true);
+        let type_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert_eq!(
+            TypeSpec::from_proto(&field_proto(Type::Int32, ""), &type_conf).unwrap(),
+            TypeSpec::Int(PbInt::Int32Legacy, IntSize::S32)
+        );
+        // Other types are unaffected
+        assert_eq!(
+            TypeSpec::from_proto(&field_proto(Type::Int64, ""), &type_conf).unwrap(),
+            TypeSpec::Int(PbInt::Int64, IntSize::S64)
+        );
     }
 
     #[test]
@@ -601,6 +805,34 @@ mod tests {
                 .to_string(),
             quote! { -4.1 as _ }.to_string()
         );
+        assert_eq!(
+            TypeSpec::Double
+                .generate_default("-0", &gen)
+                .unwrap()
+                .to_string(),
+            quote! { -0.0 as _ }.to_string()
+        );
+        assert_eq!(
+            TypeSpec::Float
+                .generate_default("1e+10", &gen)
+                .unwrap()
+                .to_string(),
+            quote! { 1e+10 as _ }.to_string()
+        );
+        assert_eq!(
+            TypeSpec::Float
+                .generate_default("-inf", &gen)
+                .unwrap()
+                .to_string(),
+            quote! { f32::NEG_INFINITY }.to_string()
+        );
+        assert_eq!(
+            TypeSpec::Double
+                .generate_default("nan", &gen)
+                .unwrap()
+                .to_string(),
+            quote! { f64::NAN }.to_string()
+        );
         assert_eq!(
             TypeSpec::Int(PbInt::Int32, IntSize::S8)
                 .generate_default("-99", &gen)
@@ -611,7 +843,8 @@ mod tests {
         assert_eq!(
             TypeSpec::String {
                 type_path: syn::parse_str("Vec").unwrap(),
-                max_bytes: None
+                max_bytes: None,
+                truncate: false
             }
             .generate_default("abc\n\tddd", &gen)
             .unwrap()
@@ -622,7 +855,8 @@ mod tests {
         assert_eq!(
             TypeSpec::Bytes {
                 type_path: syn::parse_str("Vec").unwrap(),
-                max_bytes: None
+                max_bytes: None,
+                opaque: false
             }
             .generate_default("abc\\n\\t\\a\\xA0ddd", &gen)
             .unwrap()
@@ -630,5 +864,19 @@ mod tests {
             quote! { ::micropb::PbVec::pb_from_slice(b"abc\n\t\x07\xA0ddd").unwrap_or_default() }
                 .to_string()
         );
+        assert_eq!(
+            TypeSpec::BorrowedString
+                .generate_default("abc", &gen)
+                .unwrap()
+                .to_string(),
+            quote! { "abc" }.to_string()
+        );
+        assert_eq!(
+            TypeSpec::BorrowedBytes
+                .generate_default("abc\\x01", &gen)
+                .unwrap()
+                .to_string(),
+            quote! { &b"abc\x01"[..] }.to_string()
+        );
     }
 }
@@ -0,0 +1,60 @@
+//! Hooks for adding custom code to the generated output.
+//!
+//! A hook registered with [`Generator::add_hook`](crate::Generator::add_hook) is called with every
+//! generated message and enum, and returns extra code that's placed right after the type in the
+//! same module, such as trait impls or conversions. This avoids post-processing the generated file
+//! with text substitutions, which breaks easily when the output changes.
+//!
+//! Hooks produce [`TokenStream`]s, so build scripts that use them need `proc-macro2` and `quote`
+//! as build dependencies.
+
+use proc_macro2::{Ident, TokenStream};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Kind of type passed to a hook
+pub enum ItemKind {
+    /// Struct generated from a Protobuf message
+    Message,
+    /// Open enum generated from a Protobuf enum
+    Enum,
+}
+
+#[derive(Debug)]
+/// Generated type passed to a [`Hook`]
+pub struct GeneratedItem<'a> {
+    /// Whether the type is a message or an enum
+    pub kind: ItemKind,
+    /// Fully-qualified Protobuf name of the type, such as `.pkg.Message.Nested`
+    pub proto_name: &'a str,
+    /// Name of the generated Rust type. The hook's output is in the same module as the type, so
+    /// this name refers to the type directly.
+    pub rust_name: &'a Ident,
+    /// Generated code of the type, including its trait impls and its submodule
+    pub tokens: &'a TokenStream,
+}
+
+/// Function that returns code to add after a generated type. Return an empty [`TokenStream`] to
+/// leave a type unchanged.
+///
+/// # Example
+/// ```no_run
+/// use micropb_gen::hook::{GeneratedItem, ItemKind};
+/// use quote::quote;
+///
+/// fn impl_schema_name(item: &GeneratedItem) -> proc_macro2::TokenStream {
+///     if item.kind != ItemKind::Message {
+///         return quote! {};
+///     }
+///     let name = item.rust_name;
+///     let proto_name = item.proto_name;
+///     quote! {
+///         impl crate::SchemaName for #name {
+///             const SCHEMA_NAME: &'static str = #proto_name;
+///         }
+///     }
+/// }
+///
+/// let mut gen = micropb_gen::Generator::new();
+/// gen.add_hook(impl_schema_name);
+/// ```
+pub type Hook = fn(&GeneratedItem) -> TokenStream;
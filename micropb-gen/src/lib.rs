@@ -3,6 +3,7 @@
 
 pub mod config;
 mod generator;
+pub mod hook;
 mod pathtree;
 mod utils;
 
@@ -18,17 +19,21 @@ mod descriptor {
 use std::{
     env,
     ffi::OsStr,
-    fmt, fs,
-    io::{self, Write},
+    fmt, fs, io,
     path::{Path, PathBuf},
     process::Command,
 };
 
 pub use config::Config;
 pub use generator::Generator;
+use hook::Hook;
 use micropb::{MessageDecode, PbDecoder};
 use pathtree::PathTree;
 
+/// First line of every generated file. `@generated` tells tools such as code review systems to
+/// treat the file as generated code.
+const GENERATED_HEADER: &str = "// @generated by micropb-gen. Do not edit.";
+
 #[derive(Debug, Clone, Copy, Default)]
 /// Whether to include encode and decode logic
 pub enum EncodeDecode {
@@ -82,11 +87,25 @@ impl Generator {
             encode_decode: Default::default(),
             retain_enum_prefix: Default::default(),
             format: true,
+            debug_impls: true,
+            defmt_impls: Default::default(),
+            heap_size_impls: Default::default(),
+            roundtrip_tests: Default::default(),
+            prost_root: Default::default(),
             fdset_path: Default::default(),
             protoc_args: Default::default(),
+            out_dir: Default::default(),
+            verify: Default::default(),
 
             config_tree,
             extern_paths: Default::default(),
+
+            hooks: Default::default(),
+
+            max_nesting_depth: Default::default(),
+            nesting_depths: Default::default(),
+            borrowed_msgs: Default::default(),
+            clearable_msgs: Default::default(),
         }
     }
 
@@ -218,6 +237,33 @@ impl Generator {
         self
     }
 
+    /// Configure the generator to generate `micropb`'s own fixed-capacity containers for Protobuf
+    /// `string`, `bytes`, and repeated fields.
+    ///
+    /// Specifically, `micropb::container::FixedString` is generated for `string` fields, and
+    /// `micropb::container::FixedVec` is generated for `bytes` and repeated fields. These
+    /// containers don't require any `micropb` feature flags. Unlike `heapless` containers, they
+    /// aren't zeroed on construction, so creating default messages with large fields only costs
+    /// as much as the number of fields, rather than the total capacity of the containers. This
+    /// uses [`configure`](Self::configure) under the hood, so configurations set by this call can
+    /// all be overriden by future configurations.
+    ///
+    /// # Note
+    /// No container is configured for `map` fields. If the .proto files contain `map` fields,
+    /// [`map_type`](Config::map_type) needs to be configured separately.
+    ///
+    /// Since these containers are fixed size, [`max_len`](Config::max_len) or
+    /// [`max_bytes`](Config::max_bytes) must be set for all fields that generate these containers.
+    pub fn use_container_fixed(&mut self) -> &mut Self {
+        self.configure(
+            ".",
+            Config::new()
+                .vec_type("::micropb::container::FixedVec")
+                .string_type("::micropb::container::FixedString"),
+        );
+        self
+    }
+
     /// Configure the generator to generate `alloc` containers for Protobuf `string`, `bytes`,
     /// repeated, and `map` fields.
     ///
@@ -268,8 +314,58 @@ impl Generator {
         self
     }
 
+    /// Substitute Rust types for the Protobuf well-known wrapper types, `Duration`, and
+    /// `Timestamp`, using the message implementations in `micropb::well_known`.
+    ///
+    /// Wrappers such as `google.protobuf.Int32Value` are replaced with the scalar they wrap, so
+    /// `Int32Value` fields are generated as optional `i32` fields. `google.protobuf.Duration` is
+    /// replaced with `core::time::Duration`, and `google.protobuf.Timestamp` is replaced with
+    /// `core::time::Duration` since the Unix epoch. This uses
+    /// [`extern_type_path`](Self::extern_type_path) under the hood.
+    ///
+    /// # Note
+    /// `google.protobuf.StringValue` and `google.protobuf.BytesValue` aren't substituted, since
+    /// their Rust types depend on the container types in use. Substitute them separately with
+    /// `extern_type_path`.
+    ///
+    /// If using this option with `Int64Value`, `UInt64Value`, `Duration`, or `Timestamp`,
+    /// `micropb` should have the `enable-64bit` feature enabled.
+    ///
+    /// # Example
+    /// ```no_run
+    /// let mut gen = micropb_gen::Generator::new();
+    /// gen.use_container_heapless()
+    ///     .use_well_known_types()
+    ///     .extern_type_path(".google.protobuf.StringValue", "::micropb::heapless::String<32>");
+    /// ```
+    pub fn use_well_known_types(&mut self) -> &mut Self {
+        for (proto_path, rust_path) in [
+            (".google.protobuf.DoubleValue", "f64"),
+            (".google.protobuf.FloatValue", "f32"),
+            (".google.protobuf.Int64Value", "i64"),
+            (".google.protobuf.UInt64Value", "u64"),
+            (".google.protobuf.Int32Value", "i32"),
+            (".google.protobuf.UInt32Value", "u32"),
+            (".google.protobuf.BoolValue", "bool"),
+            (".google.protobuf.Duration", "::core::time::Duration"),
+            (".google.protobuf.Timestamp", "::core::time::Duration"),
+        ] {
+            self.extern_type_path(proto_path, rust_path);
+        }
+        self
+    }
+
     /// Compile `.proto` files into a single Rust file.
     ///
+    /// The output only depends on the contents of the `.proto` files and the generator
+    /// configuration, not on the order of `protos`, and the file is left untouched if its
+    /// contents are unchanged. This makes it practical to write the output into the source tree
+    /// and check it in, rather than writing it to `OUT_DIR`.
+    ///
+    /// Identical inputs always produce byte-identical output. No timestamps or paths of the
+    /// build machine are embedded, and nothing is emitted in hash map order, so builds stay
+    /// reproducible and build caches such as `sccache` can reuse artifacts across machines.
+    ///
     /// # Example
     /// ```no_run
     /// // build.rs
@@ -301,10 +397,10 @@ impl Generator {
 
         let output = cmd.output()?;
         if !output.status.success() {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("protoc failed: {}", String::from_utf8_lossy(&output.stderr)),
-            ));
+            return Err(io::Error::other(format!(
+                "protoc failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
         }
 
         self.compile_fdset_file(fdset_file, out_filename)
@@ -340,10 +436,26 @@ impl Generator {
         #[cfg(not(feature = "format"))]
         let output = code.to_string();
 
-        let mut file = fs::File::create(out_filename)?;
-        file.write_all(output.as_bytes())?;
-
-        Ok(())
+        let output = format!("{GENERATED_HEADER}\n{output}");
+        let out_filename = match &self.out_dir {
+            Some(dir) => dir.join(out_filename),
+            None => out_filename.as_ref().to_owned(),
+        };
+        // Only write the file if its contents changed, so that checked-in output and anything
+        // watching its modification time aren't disturbed by regenerating identical code
+        if fs::read(&out_filename).ok().as_deref() == Some(output.as_bytes()) {
+            return Ok(());
+        }
+        if self.verify {
+            return Err(io::Error::other(format!(
+                "generated file {} is out of date, regenerate it without verify mode",
+                out_filename.display()
+            )));
+        }
+        if let Some(dir) = &self.out_dir {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(out_filename, output)
     }
 
     /// Determine whether the generator strips enum names from variant names.
@@ -364,6 +476,93 @@ impl Generator {
         self
     }
 
+    /// Determine whether the generator derives `Debug` on generated types.
+    ///
+    /// `Debug` impls embed the names of types and fields into the binary, which can take up a lot
+    /// of flash once anything formats a message. Setting this to `false` removes the `Debug`
+    /// derives from all generated messages, oneofs, hazzers, and enums, regardless of
+    /// [`Config::no_debug_impl`](crate::Config::no_debug_impl). Doc comments aside, field
+    /// names don't appear in the generated code in any other form, so string literals are only
+    /// emitted for the default values of `string` and `bytes` fields. Enabled by default.
+    ///
+    /// To keep `Debug` impls for development builds only, the switch can be tied to the build
+    /// profile:
+    /// ```no_run
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.debug_impls(std::env::var("PROFILE").as_deref() == Ok("debug"));
+    /// ```
+    pub fn debug_impls(&mut self, debug_impls: bool) -> &mut Self {
+        self.debug_impls = debug_impls;
+        self
+    }
+
+    /// Determine whether the generator derives `defmt::Format` on generated types.
+    ///
+    /// When enabled, all generated messages, oneofs, hazzers, and enums derive `defmt::Format`, so
+    /// they can be logged with [`defmt`](https://docs.rs/defmt) on targets where `Debug` impls
+    /// are too expensive. The generated code refers to `::defmt` directly, so the crate must
+    /// depend on `defmt`, with the `defmt` feature of `micropb` enabled. The types of all fields
+    /// must also implement `defmt::Format`. This is the case for `heapless` containers once the
+    /// `defmt` feature is enabled, while `alloc` containers require the `alloc` feature of
+    /// `defmt`. Disabled by default.
+    pub fn defmt_impls(&mut self, defmt_impls: bool) -> &mut Self {
+        self.defmt_impls = defmt_impls;
+        self
+    }
+
+    /// Determine whether the generator implements `micropb::HeapSize` on generated types.
+    ///
+    /// When enabled, every generated message gets a `heap_bytes_used()` method that sums the heap
+    /// memory owned by its fields, recursing into nested messages and oneofs. This allows
+    /// applications that buffer decoded messages to enforce a memory budget. Disabled by default.
+    ///
+    /// Custom fields, unknown handlers, and extern types must also implement `HeapSize`.
+    /// Delegate fields aren't counted, since their memory is owned by another field.
+    pub fn heap_size_impls(&mut self, heap_size_impls: bool) -> &mut Self {
+        self.heap_size_impls = heap_size_impls;
+        self
+    }
+
+    /// Determine whether the generator emits round-trip tests for generated messages.
+    ///
+    /// When enabled, every message that implements `Default` and `PartialEq` gets a
+    /// `#[cfg(test)]` test that encodes its default value into a fixed-capacity buffer, checks
+    /// the encoded length against `compute_size()`, and decodes it back. The tests don't use any
+    /// host-specific APIs, so schema changes can be sanity-checked by running `cargo test` on the
+    /// crate that includes the generated code, even if it's `no_std`. Only takes effect if both
+    /// encoders and decoders are generated. Disabled by default.
+    pub fn roundtrip_tests(&mut self, roundtrip_tests: bool) -> &mut Self {
+        self.roundtrip_tests = roundtrip_tests;
+        self
+    }
+
+    /// Generate conversions between each message and its `prost`-generated counterpart.
+    ///
+    /// `prost_root` is the Rust path of the module containing the output of `prost-build` for the
+    /// same `.proto` files, such as `crate::prost_pb`. Each message gets `TryFrom<&Msg>` impls in
+    /// both directions, which convert by encoding the message and decoding the result, so host
+    /// code using `prost` can share test fixtures with firmware using `micropb`. Converting into a
+    /// `micropb` message fails if it exceeds the capacity of a fixed container.
+    ///
+    /// The paths of `prost` types follow the default naming of `prost-build`, with packages and
+    /// parent messages as snake-case modules and messages in upper camel case. The generated code
+    /// requires `prost` as a dependency, as well as the `alloc` feature of `micropb`. It refers to
+    /// `::alloc`, so crates that use `std` must also declare `extern crate alloc;` at their root.
+    /// Only takes effect if both encoders and decoders are generated, and borrowed messages are
+    /// skipped.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // `prost-build` output is included in `crate::prost_pb`
+    /// gen.prost_conversions("crate::prost_pb");
+    /// ```
+    pub fn prost_conversions(&mut self, prost_root: &str) -> &mut Self {
+        self.prost_root =
+            Some(syn::parse_str(prost_root).expect("failed to tokenize prost root path"));
+        self
+    }
+
     /// Determine whether to generate logic for encoding and decoding Protobuf messages.
     ///
     /// Some applications don't need to support both encoding and decoding. This setting allows
@@ -376,6 +575,29 @@ impl Generator {
         self
     }
 
+    /// Limit the worst-case nesting depth of generated message types.
+    ///
+    /// Decoding and encoding a message recurses once for every level of nested message fields, so
+    /// the stack usage of the generated code grows with the nesting depth of the schema. Every
+    /// generated message has a `MAX_NESTING_DEPTH` constant containing its worst-case nesting
+    /// depth, where a message without any message fields has a depth of 1. Recursive messages, and
+    /// messages containing types that aren't in the compiled files, such as extern types, have no
+    /// known depth (`None`).
+    ///
+    /// When this limit is set, the generator returns an error if any message can exceed the limit
+    /// or has no known depth, which guarantees that the number of nested message levels is known
+    /// at build time. This bounds the recursion of the generated code, but isn't a stack size,
+    /// since the stack used by each level depends on the target and the messages involved.
+    /// Skipped fields and custom fields are not counted.
+    ///
+    /// There is no iterative decode mode, so the generated code always recurses into nested
+    /// messages. For deeply nested schemas on targets with small stacks, this limit is how the
+    /// recursion is kept in check.
+    pub fn max_nesting_depth(&mut self, max_depth: u32) -> &mut Self {
+        self.max_nesting_depth = Some(max_depth);
+        self
+    }
+
     /// When set, the file descriptor set generated by `protoc` is written to the provided path,
     /// instead of a temporary directory.
     pub fn file_descriptor_set_path<P: Into<PathBuf>>(&mut self, path: P) -> &mut Self {
@@ -383,6 +605,49 @@ impl Generator {
         self
     }
 
+    /// Write output files into `dir`, which is created if it doesn't exist.
+    ///
+    /// Relative output filenames passed to [`compile_protos`](Self::compile_protos) and
+    /// [`compile_fdset_file`](Self::compile_fdset_file) are resolved against `dir` instead of the
+    /// current directory. This is intended for checking generated code into the source tree, such
+    /// as under `src/proto`, so that the crate can be built on machines without `protoc`.
+    pub fn write_to<P: Into<PathBuf>>(&mut self, dir: P) -> &mut Self {
+        self.out_dir = Some(dir.into());
+        self
+    }
+
+    /// Check that output files are up to date instead of writing them.
+    ///
+    /// When set, compiling returns an error if an output file is missing or differs from the
+    /// newly generated code, and no files are written. This lets CI catch checked-in generated
+    /// code that's stale relative to its `.proto` files.
+    ///
+    /// # Example
+    /// ```no_run
+    /// // src/bin/gen_proto.rs, run with `cargo run --bin gen_proto` to regenerate the code, or
+    /// // with `cargo run --bin gen_proto -- --verify` in CI
+    /// let verify = std::env::args().any(|arg| arg == "--verify");
+    /// let mut gen = micropb_gen::Generator::new();
+    /// gen.write_to("src/proto")
+    ///     .verify(verify)
+    ///     .compile_protos(&["app.proto"], "mod.rs")
+    ///     .unwrap();
+    /// ```
+    pub fn verify(&mut self, verify: bool) -> &mut Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Register a hook that's called with every generated message and enum.
+    ///
+    /// The code returned by the hook is added right after the type in the output, so it can
+    /// implement custom traits or conversions for generated types. Hooks are called in the order
+    /// they were added. Skipped types aren't passed to hooks. See [`hook`] for details.
+    pub fn add_hook(&mut self, hook: Hook) -> &mut Self {
+        self.hooks.push(hook);
+        self
+    }
+
     /// Add an argument to the `protoc` invocation when compiling Protobuf files.
     pub fn add_protoc_arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
         self.protoc_args.push(arg.as_ref().to_owned());
@@ -449,7 +714,10 @@ impl Generator {
     /// # Note
     /// It's technically possible to substitute in Rust types that aren't generated by `micropb-gen`.
     /// However, the generated code expects substituted messages to implement `MessageDecode` and
-    /// `MessageEncode`, and substituted enums to have the "open-enum" structure.
+    /// `MessageEncode`, and substituted enums to have the "open-enum" structure. The generated code
+    /// contains compile-time assertions that each substituted message implements `Default` and
+    /// the enabled message traits, so a mismatched type is reported at its path rather than deep
+    /// inside the generated decode or encode logic.
     pub fn extern_type_path<P1: AsRef<str>, P2: AsRef<str>>(
         &mut self,
         proto_path: P1,
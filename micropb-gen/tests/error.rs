@@ -303,6 +303,40 @@ fn parse_custom_type_delegate() {
     assert!(err.contains("Failed to parse custom delegate"));
 }
 
+#[test]
+fn nesting_depth_limit() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.max_nesting_depth(0);
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg)"));
+    assert!(err.contains("nesting depth of 1 exceeds the limit of 0"));
+}
+
+#[test]
+fn unboxed_recursion() {
+    let file = NamedTempFile::new().unwrap();
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    let err = gen
+        .compile_protos(&["tests/recursive.proto"], file.path())
+        .unwrap_err()
+        .into_inner()
+        .unwrap()
+        .to_string();
+    dbg!(&err);
+    assert!(err.contains("(.rec.Edge)"));
+    assert!(err.contains(".rec.Edge.node -> .rec.Node.edge"));
+
+    // Boxing the oneof breaks the cycle
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(".rec.Edge.target", Config::new().boxed(true));
+    gen.compile_protos(&["tests/recursive.proto"], file.path())
+        .unwrap();
+}
+
 #[test]
 #[should_panic = "Unused configuration path: \".Msg\""]
 fn warn_unused_config() {
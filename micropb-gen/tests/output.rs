@@ -0,0 +1,136 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use micropb_gen::{
+    hook::{GeneratedItem, ItemKind},
+    Generator,
+};
+use quote::quote;
+
+use tempfile::NamedTempFile;
+
+fn compile(protos: &[&str], out: &NamedTempFile) -> String {
+    let mut gen = Generator::new();
+    gen.use_container_alloc();
+    gen.configure(".rec.Edge.target", micropb_gen::Config::new().boxed(true));
+    gen.compile_protos(protos, out.path()).unwrap();
+    fs::read_to_string(out.path()).unwrap()
+}
+
+#[test]
+fn stable_output() {
+    let file = NamedTempFile::new().unwrap();
+    let output = compile(&["tests/test.proto", "tests/recursive.proto"], &file);
+    assert!(output.starts_with("// @generated by micropb-gen."));
+    // Files are generated in name order
+    assert!(output.find("pub mod rec_").unwrap() < output.find("pub mod test_").unwrap());
+
+    let mtime = fs::metadata(file.path()).unwrap().modified().unwrap();
+    let reordered = compile(&["tests/recursive.proto", "tests/test.proto"], &file);
+    assert_eq!(output, reordered);
+    // Identical output isn't rewritten
+    assert_eq!(
+        fs::metadata(file.path()).unwrap().modified().unwrap(),
+        mtime
+    );
+}
+
+#[test]
+fn verify_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let out_dir = dir.path().join("proto");
+    let compile = |verify| {
+        let mut gen = Generator::new();
+        gen.use_container_alloc();
+        gen.write_to(&out_dir)
+            .verify(verify)
+            .compile_protos(&["tests/test.proto"], "mod.rs")
+    };
+    let out_file = out_dir.join("mod.rs");
+
+    // Missing output is stale
+    let err = compile(true).unwrap_err();
+    assert!(err.to_string().contains("out of date"));
+    assert!(!out_file.exists());
+
+    compile(false).unwrap();
+    let output = fs::read_to_string(&out_file).unwrap();
+    compile(true).unwrap();
+
+    // Edited output is stale, and isn't overwritten in verify mode
+    fs::write(&out_file, output.replace("pub mod", "mod")).unwrap();
+    compile(true).unwrap_err();
+    assert_ne!(fs::read_to_string(&out_file).unwrap(), output);
+}
+
+#[test]
+fn reproducible_output() {
+    let generate = |include_dir: &Path, protos: &[PathBuf]| {
+        let out_dir = tempfile::tempdir().unwrap();
+        let mut gen = Generator::new();
+        gen.use_container_alloc()
+            .heap_size_impls(true)
+            .add_protoc_arg("-I")
+            .add_protoc_arg(include_dir);
+        gen.configure(".rec.Edge.target", micropb_gen::Config::new().boxed(true));
+        gen.write_to(out_dir.path())
+            .compile_protos(protos, "mod.rs")
+            .unwrap();
+        fs::read_to_string(out_dir.path().join("mod.rs")).unwrap()
+    };
+
+    // Separate generators with different output directories, and with the inputs referred to by
+    // relative and absolute paths, produce identical output
+    let relative = generate(
+        Path::new("tests"),
+        &["tests/test.proto".into(), "tests/recursive.proto".into()],
+    );
+    let abs_dir = fs::canonicalize("tests").unwrap();
+    let absolute = generate(
+        &abs_dir,
+        &[abs_dir.join("recursive.proto"), abs_dir.join("test.proto")],
+    );
+    assert_eq!(relative, absolute);
+    assert!(!absolute.contains(abs_dir.to_str().unwrap()));
+}
+
+#[test]
+fn hooks() {
+    fn marker(item: &GeneratedItem) -> proc_macro2::TokenStream {
+        let name = item.rust_name;
+        match item.kind {
+            ItemKind::Message => quote! { impl crate::Marker for #name {} },
+            ItemKind::Enum => quote! {},
+        }
+    }
+    fn proto_name(item: &GeneratedItem) -> proc_macro2::TokenStream {
+        let name = item.rust_name;
+        let proto_name = item.proto_name;
+        quote! {
+            impl #name {
+                pub const PROTO_NAME: &'static str = #proto_name;
+            }
+        }
+    }
+
+    let file = NamedTempFile::new().unwrap();
+    let mut gen = Generator::new();
+    gen.use_container_alloc()
+        .add_hook(marker)
+        .add_hook(proto_name)
+        .compile_protos(&["tests/test.proto"], file.path())
+        .unwrap();
+    let output = fs::read_to_string(file.path()).unwrap();
+
+    assert!(output.contains("impl crate::Marker for Msg {}"));
+    assert!(!output.contains("impl crate::Marker for Enum {}"));
+    assert!(output.contains(r#"pub const PROTO_NAME: &'static str = ".test.Msg";"#));
+    assert!(output.contains(r#"pub const PROTO_NAME: &'static str = ".test.Enum";"#));
+    // Hook output comes after the type, in the order of the hooks
+    let decl = output.find("pub struct Msg").unwrap();
+    let marker = output.find("impl crate::Marker for Msg").unwrap();
+    assert!(decl < marker);
+    assert!(marker < output.find(r#"".test.Msg""#).unwrap());
+}
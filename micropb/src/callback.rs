@@ -0,0 +1,594 @@
+//! Ready-made custom field types for common decoding and encoding patterns.
+//!
+//! These types implement `FieldDecode` and `FieldEncode`, so they can be substituted into
+//! generated messages with `Config::custom_field` in `micropb-gen`, without writing a custom field
+//! type by hand.
+//!
+//! # Example
+//!
+//! Count the elements of a repeated field instead of storing them:
+//! ```ignore
+//! use micropb_gen::{Config, config::CustomField};
+//!
+//! gen.configure(
+//!     ".Log.entries",
+//!     Config::new().custom_field(CustomField::Type("::micropb::callback::Count".to_owned())),
+//! );
+//! ```
+
+#[cfg(feature = "decode")]
+use core::ops::ControlFlow;
+
+#[cfg(feature = "decode")]
+use never::Never;
+
+use crate::PbVec;
+#[cfg(any(feature = "decode", feature = "encode"))]
+use crate::Tag;
+#[cfg(feature = "decode")]
+use crate::{DecodeError, FieldDecode, PbDecoder, PbRead};
+#[cfg(feature = "encode")]
+use crate::{FieldEncode, PbEncoder, PbWrite, WIRE_TYPE_LEN};
+
+/// Field that skips over its values on the wire and is never encoded.
+///
+/// Useful for fields that the application doesn't care about, but that should still be accepted
+/// on the wire without taking up space in the message struct.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Ignore;
+
+#[cfg(feature = "decode")]
+impl FieldDecode for Ignore {
+    fn decode_field<R: PbRead>(
+        &mut self,
+        tag: Tag,
+        decoder: &mut PbDecoder<R>,
+    ) -> Result<bool, DecodeError<R::Error>> {
+        decoder.skip_wire_value(tag.wire_type())?;
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "encode")]
+impl FieldEncode for Ignore {
+    fn encode_fields<W: PbWrite>(&self, _encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+        Ok(())
+    }
+
+    fn compute_fields_size(&self) -> usize {
+        0
+    }
+}
+
+/// Field that counts the number of times it occurs on the wire, without storing its values.
+///
+/// Each occurrence of the field on the wire is counted once, so the count of an unpacked repeated
+/// field is the number of elements, while a packed repeated field is counted once per packed
+/// record. The count is never encoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Count(pub usize);
+
+#[cfg(feature = "decode")]
+impl FieldDecode for Count {
+    fn decode_field<R: PbRead>(
+        &mut self,
+        tag: Tag,
+        decoder: &mut PbDecoder<R>,
+    ) -> Result<bool, DecodeError<R::Error>> {
+        decoder.skip_wire_value(tag.wire_type())?;
+        self.0 = self.0.saturating_add(1);
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "encode")]
+impl FieldEncode for Count {
+    fn encode_fields<W: PbWrite>(&self, _encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+        Ok(())
+    }
+
+    fn compute_fields_size(&self) -> usize {
+        0
+    }
+}
+
+/// `bytes` or `string` field with field number `NUM` that's decoded into a caller-provided
+/// buffer.
+///
+/// Decoding copies as much of the value as fits into the buffer and skips the rest, marking the
+/// value as truncated instead of returning an error. Like other `bytes` fields, each occurrence
+/// on the wire replaces the previous value. When encoding, the contents of the buffer are written
+/// as a single field, unless the value is empty.
+///
+/// Since the buffer is mutably borrowed, messages containing this field can't derive `Clone`, so
+/// `Config::no_clone_impl` should be set on them in `micropb-gen`. String contents aren't checked
+/// for valid UTF-8, since truncation may split a character.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CopyToSlice<'a, const NUM: u32> {
+    buf: &'a mut [u8],
+    len: usize,
+    truncated: bool,
+}
+
+impl<'a, const NUM: u32> CopyToSlice<'a, NUM> {
+    /// Create an empty field that decodes into `buf`.
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    /// Create a field that encodes the first `len` bytes of `buf`. `len` is clamped to the length
+    /// of `buf`.
+    pub fn with_len(buf: &'a mut [u8], len: usize) -> Self {
+        let len = len.min(buf.len());
+        Self {
+            buf,
+            len,
+            truncated: false,
+        }
+    }
+
+    /// Contents of the value, which is the part of the decoded value that fit into the buffer.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.buf.get(..self.len).unwrap_or_default()
+    }
+
+    /// Length of the contents in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the contents are empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if the last decoded value didn't fit into the buffer.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Get back the underlying buffer.
+    pub fn into_buf(self) -> &'a mut [u8] {
+        self.buf
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<const NUM: u32> FieldDecode for CopyToSlice<'_, NUM> {
+    fn decode_field<R: PbRead>(
+        &mut self,
+        _tag: Tag,
+        decoder: &mut PbDecoder<R>,
+    ) -> Result<bool, DecodeError<R::Error>> {
+        let len = decoder.decode_varint32()? as usize;
+        let mut pos = 0;
+        decoder.read_raw_bytes(len, |chunk| {
+            let dest = self.buf.get_mut(pos..).unwrap_or_default();
+            let n = chunk.len().min(dest.len());
+            if let (Some(dest), Some(src)) = (dest.get_mut(..n), chunk.get(..n)) {
+                dest.copy_from_slice(src);
+            }
+            pos += n;
+        })?;
+        self.len = pos;
+        self.truncated = pos < len;
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<const NUM: u32> FieldEncode for CopyToSlice<'_, NUM> {
+    fn encode_fields<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+        if !self.is_empty() {
+            encoder.encode_tag(Tag::from_parts(NUM, WIRE_TYPE_LEN))?;
+            encoder.encode_bytes(self.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn compute_fields_size(&self) -> usize {
+        if self.is_empty() {
+            0
+        } else {
+            crate::size::sizeof_tag(Tag::from_parts(NUM, WIRE_TYPE_LEN))
+                + crate::size::sizeof_len_record(self.len)
+        }
+    }
+}
+
+#[cfg(feature = "encode")]
+/// Field whose raw wire bytes, including the tag, are forwarded to a [`PbWrite`] as they're
+/// decoded.
+///
+/// This lets a proxy pass fields through to another destination without decoding them or storing
+/// them in the message. If the writer returns an error, such as when a fixed-capacity buffer is
+/// full, the rest of the data is skipped and [`overflowed`](Self::overflowed) is set. The field
+/// itself is never encoded, since its data lives in the writer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Forward<W> {
+    /// Destination of the forwarded bytes
+    pub writer: W,
+    /// Set if the writer returned an error, meaning that some bytes weren't forwarded
+    pub overflowed: bool,
+}
+
+#[cfg(feature = "encode")]
+impl<W: PbWrite> Forward<W> {
+    /// Create a field that forwards its bytes to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            overflowed: false,
+        }
+    }
+
+    #[cfg(feature = "decode")]
+    fn forward(&mut self, bytes: &[u8]) {
+        if !self.overflowed && self.writer.pb_write(bytes).is_err() {
+            self.overflowed = true;
+        }
+    }
+
+    #[cfg(feature = "decode")]
+    fn forward_tag(&mut self, tag: Tag) {
+        if !self.overflowed && PbEncoder::new(&mut self.writer).encode_tag(tag).is_err() {
+            self.overflowed = true;
+        }
+    }
+}
+
+#[cfg(all(feature = "encode", feature = "decode"))]
+impl<W: PbWrite> FieldDecode for Forward<W> {
+    fn decode_field<R: PbRead>(
+        &mut self,
+        tag: Tag,
+        decoder: &mut PbDecoder<R>,
+    ) -> Result<bool, DecodeError<R::Error>> {
+        self.forward_tag(tag);
+        decoder.read_raw_value(tag.wire_type(), |chunk| self.forward(chunk))?;
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<W> FieldEncode for Forward<W> {
+    fn encode_fields<W2: PbWrite>(&self, _encoder: &mut PbEncoder<W2>) -> Result<(), W2::Error> {
+        Ok(())
+    }
+
+    fn compute_fields_size(&self) -> usize {
+        0
+    }
+}
+
+/// Field that stores its undecoded wire bytes, including the tag, and writes them back verbatim
+/// when encoded.
+///
+/// Useful for pass-through proxies that must forward certain fields without reinterpreting them.
+/// Every occurrence of the field on the wire is appended to the container `V`, so repeated fields
+/// keep all of their elements in their original order. Since the generated decoder has already
+/// parsed the tag, the tag is stored in its canonical encoding, but the rest of the field is
+/// stored exactly as it appeared on the wire.
+///
+/// `RawField` can also be used as an `unknown_handler` in `micropb-gen` to preserve all unknown
+/// fields of a message.
+///
+/// # Errors
+///
+/// If the container's fixed capacity is exceeded, decoding fails with `DecodeError::Capacity`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RawField<V>(pub V);
+
+impl<V: PbVec<u8>> RawField<V> {
+    /// Raw wire bytes of all occurrences of the field.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns `true` if the field hasn't been seen on the wire.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Clear the stored bytes.
+    pub fn clear(&mut self) {
+        self.0.pb_clear()
+    }
+}
+
+#[cfg(feature = "decode")]
+/// Append the tag and the raw value of a field to `vec`, returning `DecodeError::Capacity` after
+/// the value has been read if it didn't fit.
+fn store_raw_field<V: PbVec<u8>, R: PbRead>(
+    vec: &mut V,
+    tag: Tag,
+    decoder: &mut PbDecoder<R>,
+) -> Result<(), DecodeError<R::Error>> {
+    let mut overflow = false;
+    let mut push = |bytes: &[u8]| {
+        for &b in bytes {
+            if overflow || vec.pb_push(b).is_err() {
+                overflow = true;
+                return;
+            }
+        }
+    };
+
+    let mut varint = tag.varint();
+    while varint >= 0x80 {
+        push(&[(varint as u8) | 0x80]);
+        varint >>= 7;
+    }
+    push(&[varint as u8]);
+    decoder.read_raw_value(tag.wire_type(), push)?;
+
+    if overflow {
+        return Err(DecodeError::Capacity);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "decode")]
+impl<V: PbVec<u8>> FieldDecode for RawField<V> {
+    fn decode_field<R: PbRead>(
+        &mut self,
+        tag: Tag,
+        decoder: &mut PbDecoder<R>,
+    ) -> Result<bool, DecodeError<R::Error>> {
+        store_raw_field(&mut self.0, tag, decoder)?;
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<V: PbVec<u8>> FieldEncode for RawField<V> {
+    fn encode_fields<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+        encoder.write(&self.0)
+    }
+
+    fn compute_fields_size(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Storage for the unknown fields of a message, which are written back when the message is
+/// encoded.
+///
+/// This is the type of the `_unknown` field generated by the `unknown_fields` config in
+/// `micropb-gen`. Proxies and firmware that forward messages from peers on newer schema versions
+/// can use it to pass on fields they don't know about, instead of silently dropping them. Like
+/// [`RawField`], each field is stored with its tag in the container `V`, in the order it appeared
+/// on the wire.
+///
+/// # Errors
+///
+/// Fields that don't fit into the container's fixed capacity are handled with
+/// `PbDecoder::handle_repeated_overflow`, so decoding fails with `DecodeError::Capacity` unless
+/// `ignore_repeated_cap_err` is set. In that case, the field is dropped as a whole, so the stored
+/// bytes always consist of complete fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UnknownFields<V>(pub V);
+
+impl<V: PbVec<u8>> UnknownFields<V> {
+    /// Raw wire bytes of all unknown fields.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns `true` if no unknown fields have been stored.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Clear the stored fields.
+    pub fn clear(&mut self) {
+        self.0.pb_clear()
+    }
+
+    #[cfg(feature = "decode")]
+    /// Call `visitor` on each stored field, as done by [`decode_fields`](crate::decode_fields).
+    pub fn visit<'a, B, F: FnMut(Tag, crate::WireValue<'a>) -> ControlFlow<B>>(
+        &'a self,
+        visitor: F,
+    ) -> Result<ControlFlow<B>, DecodeError<Never>> {
+        crate::decode_fields(&self.0, visitor)
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<V: PbVec<u8>> FieldDecode for UnknownFields<V> {
+    fn decode_field<R: PbRead>(
+        &mut self,
+        tag: Tag,
+        decoder: &mut PbDecoder<R>,
+    ) -> Result<bool, DecodeError<R::Error>> {
+        let start = self.0.len();
+        match store_raw_field(&mut self.0, tag, decoder) {
+            Err(DecodeError::Capacity) => {
+                // Drop the partially stored field
+                // SAFETY: Shrinking the container keeps all remaining elements initialized
+                unsafe { self.0.pb_set_len(start) };
+                decoder.handle_repeated_overflow()?;
+            }
+            res => res?,
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<V: PbVec<u8>> FieldEncode for UnknownFields<V> {
+    fn encode_fields<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+        encoder.write(&self.0)
+    }
+
+    fn compute_fields_size(&self) -> usize {
+        self.0.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrayvec::ArrayVec;
+
+    use super::*;
+
+    fn decode<F: FieldDecode>(field: &mut F, bytes: &[u8]) {
+        let mut decoder = PbDecoder::new(bytes);
+        while decoder.bytes_read() < bytes.len() {
+            let tag = decoder.decode_tag().unwrap();
+            assert!(field.decode_field(tag, &mut decoder).unwrap());
+        }
+    }
+
+    fn encode<F: FieldEncode>(field: &F) -> ArrayVec<u8, 16> {
+        let mut encoder = PbEncoder::new(ArrayVec::new());
+        field.encode_fields(&mut encoder).unwrap();
+        let out = encoder.into_writer();
+        assert_eq!(out.len(), field.compute_fields_size());
+        out
+    }
+
+    // Occurrences of different wire types: varint, fixed32, len, fixed64
+    const FIELDS: &[u8] = &[
+        0x08, 0x96, 0x01, 0x0D, 1, 2, 3, 4, 0x12, 0x02, b'a', b'b', 0x19, 1, 2, 3, 4, 5, 6, 7, 8,
+    ];
+
+    #[test]
+    fn ignore() {
+        let mut field = Ignore;
+        decode(&mut field, FIELDS);
+        assert!(encode(&field).is_empty());
+    }
+
+    #[test]
+    fn count() {
+        let mut field = Count::default();
+        decode(&mut field, FIELDS);
+        assert_eq!(field, Count(4));
+        assert!(encode(&field).is_empty());
+    }
+
+    #[test]
+    fn copy_to_slice() {
+        let mut buf = [0; 4];
+        let mut field = CopyToSlice::<3>::new(&mut buf);
+        assert!(encode(&field).is_empty());
+
+        decode(&mut field, &[0x1A, 0x03, b'a', b'b', b'c']);
+        assert_eq!(field.as_bytes(), b"abc");
+        assert!(!field.is_truncated());
+        assert_eq!(encode(&field).as_slice(), [0x1A, 0x03, b'a', b'b', b'c']);
+
+        // Value is truncated to fit the buffer, replacing the previous value
+        decode(&mut field, &[0x1A, 0x05, b'1', b'2', b'3', b'4', b'5']);
+        assert_eq!(field.as_bytes(), b"1234");
+        assert!(field.is_truncated());
+        assert_eq!(
+            encode(&field).as_slice(),
+            [0x1A, 0x04, b'1', b'2', b'3', b'4']
+        );
+
+        let mut buf = *b"xyz";
+        let field = CopyToSlice::<1>::with_len(&mut buf, 5);
+        assert_eq!(encode(&field).as_slice(), [0x0A, 0x03, b'x', b'y', b'z']);
+    }
+
+    #[test]
+    fn forward() {
+        let mut field = Forward::new(ArrayVec::<u8, 32>::new());
+        decode(&mut field, FIELDS);
+        assert_eq!(field.writer.as_slice(), FIELDS);
+        assert!(!field.overflowed);
+        assert!(encode(&field).is_empty());
+
+        // Non-canonical length prefixes are forwarded as-is
+        let mut field = Forward::new(ArrayVec::<u8, 32>::new());
+        decode(&mut field, &[0x12, 0x81, 0x00, b'a']);
+        assert_eq!(field.writer.as_slice(), [0x12, 0x81, 0x00, b'a']);
+
+        let mut field = Forward::new(ArrayVec::<u8, 4>::new());
+        decode(&mut field, FIELDS);
+        assert!(field.overflowed);
+    }
+
+    #[test]
+    fn raw_field() {
+        let mut field = RawField(ArrayVec::<u8, 32>::new());
+        assert!(field.is_empty());
+        assert!(encode(&field).is_empty());
+
+        decode(&mut field, FIELDS);
+        assert_eq!(field.as_bytes(), FIELDS);
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 32>::new());
+        field.encode_fields(&mut encoder).unwrap();
+        assert_eq!(encoder.as_writer().as_slice(), FIELDS);
+        assert_eq!(field.compute_fields_size(), FIELDS.len());
+
+        // Non-canonical varints are preserved, and multi-byte tags are re-encoded
+        field.clear();
+        decode(&mut field, &[0x80, 0x01, 0x80, 0x00, 0x12, 0x80, 0x00]);
+        assert_eq!(field.as_bytes(), [0x80, 0x01, 0x80, 0x00, 0x12, 0x80, 0x00]);
+
+        let mut field = RawField(ArrayVec::<u8, 4>::new());
+        let mut decoder = PbDecoder::new(FIELDS);
+        let tag = decoder.decode_tag().unwrap();
+        field.decode_field(tag, &mut decoder).unwrap();
+        let tag = decoder.decode_tag().unwrap();
+        assert_eq!(
+            field.decode_field(tag, &mut decoder),
+            Err(DecodeError::Capacity)
+        );
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let mut field = UnknownFields(ArrayVec::<u8, 32>::new());
+        assert!(field.is_empty());
+        decode(&mut field, FIELDS);
+        assert_eq!(field.as_bytes(), FIELDS);
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 32>::new());
+        field.encode_fields(&mut encoder).unwrap();
+        assert_eq!(encoder.as_writer().as_slice(), FIELDS);
+        assert_eq!(field.compute_fields_size(), FIELDS.len());
+
+        let mut nums = vec![];
+        let res = field.visit(|tag, _| {
+            nums.push(tag.field_num());
+            ControlFlow::<()>::Continue(())
+        });
+        assert_eq!(res, Ok(ControlFlow::Continue(())));
+        assert_eq!(nums, [1, 1, 2, 3]);
+
+        // Fields that don't fit are dropped whole if capacity errors are ignored
+        let mut field = UnknownFields(ArrayVec::<u8, 8>::new());
+        let mut decoder = PbDecoder::new(FIELDS);
+        decoder.ignore_repeated_cap_err = true;
+        while decoder.bytes_read() < FIELDS.len() {
+            let tag = decoder.decode_tag().unwrap();
+            assert!(field.decode_field(tag, &mut decoder).unwrap());
+        }
+        assert_eq!(field.as_bytes(), &FIELDS[..8]);
+        #[cfg(feature = "decode-stats")]
+        assert_eq!(decoder.stats().dropped_elements, 2);
+
+        let mut field = UnknownFields(ArrayVec::<u8, 4>::new());
+        let mut decoder = PbDecoder::new(FIELDS);
+        let tag = decoder.decode_tag().unwrap();
+        field.decode_field(tag, &mut decoder).unwrap();
+        let tag = decoder.decode_tag().unwrap();
+        assert_eq!(
+            field.decode_field(tag, &mut decoder),
+            Err(DecodeError::Capacity)
+        );
+        assert_eq!(field.as_bytes(), &FIELDS[..3]);
+    }
+}
@@ -18,18 +18,33 @@
 //! - For `alloc`, [`PbVec`], [`PbString`], and [`PbMap`] are implemented on `Vec`, `String`,
 //!   and `BTreeMap` respectively. If `std` is enabled, [`PbMap`] is also implemented for
 //!   `HashMap`.
+//! - For `bytes`, [`PbVec`] is implemented on `bytes::BytesMut`. `SharedBytes` is a `bytes`
+//!   field container backed by `bytes::Bytes`, which can be cloned without copying the payload.
+//!
+//! - [`AlignedVec`] is a fixed-capacity vector with a guaranteed storage alignment, provided
+//!   without any feature flags. It's intended for `bytes` fields that are accessed by DMA.
+//! - [`FixedVec`] and [`FixedString`] are fixed-capacity containers provided without any feature
+//!   flags. Unlike the `heapless` containers, creating them doesn't zero their storage, which
+//!   makes them a better fit for large fields in messages that are created often.
+//! - `InternedString` is a `string` container that deduplicates decoded strings through a
+//!   user-provided intern pool. It requires `alloc`.
+//! - [`Opaque`] wraps a byte container to hold the undecoded payload of a message field, so that
+//!   it can be decoded later on demand.
 //!
 //! It is also possible to use other types as containers if the container traits are implemented.
 
 #![allow(clippy::result_unit_err)]
 
 use core::{
-    mem::MaybeUninit, 
-    ops::Deref, 
-    marker::{ Copy, Sized }, 
-    result::Result 
+    fmt,
+    marker::{Copy, Sized},
+    mem::MaybeUninit,
+    ops::{Deref, DerefMut},
+    result::Result,
 };
 
+use crate::heap::HeapSize;
+
 /// Basic container trait required for all multi-element containers, except for maps.
 pub trait PbContainer: Sized {
     /// Sets length of container (number of elements).
@@ -62,6 +77,21 @@ pub trait PbVec<T>: PbContainer + Deref<Target = [T]> {
     /// [`pb_set_len`](PbContainer::pb_set_len).
     fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<T>];
 
+    /// Reserve capacity for `n` more elements that are known to be on the wire, such as the bytes
+    /// of a `bytes` field or the elements of a packed fixed-size field.
+    ///
+    /// Dynamic containers grow once instead of once per element. Returns error if the spare
+    /// capacity is still smaller than `n`, which lets fixed-capacity containers fail before any
+    /// elements are decoded.
+    fn pb_reserve_hint(&mut self, n: usize) -> Result<(), ()> {
+        self.pb_reserve(n);
+        if self.pb_spare_cap().len() < n {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
     /// Construct a vector from a slice.
     ///
     /// Returns error if the slice is longer than the fixed capacity of the vector type.
@@ -70,6 +100,7 @@ pub trait PbVec<T>: PbContainer + Deref<Target = [T]> {
         T: Copy;
 }
 
+#[cfg(feature = "strings")]
 /// UTF-8 string that stores characters.
 ///
 /// Represents Protobuf `string` field.
@@ -84,12 +115,27 @@ pub trait PbString: PbContainer + Deref<Target = str> {
     /// bytes, the entirety of the new string must be valid UTF-8.
     fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<u8>];
 
+    /// Reserve capacity for `n` more bytes that are known to be on the wire.
+    ///
+    /// Dynamic containers grow once to fit the whole string. Returns error if the spare capacity
+    /// is still smaller than `n`, which lets fixed-capacity containers fail before any bytes are
+    /// decoded.
+    fn pb_reserve_hint(&mut self, n: usize) -> Result<(), ()> {
+        self.pb_reserve(n);
+        if self.pb_spare_cap().len() < n {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
     /// Constructs a string from a string slice.
     ///
     /// Returns error if the slice is longer than the fixed capacity of the string type.
     fn pb_from_str(s: &str) -> Result<Self, ()>;
 }
 
+#[cfg(feature = "maps")]
 /// Map that stores key-value pairs.
 ///
 /// Represents Protobuf `map` field.
@@ -108,122 +154,1026 @@ pub trait PbMap<K, V> {
 
     /// Iterates through each key-value pair in the map. Order is unspecified.
     fn pb_iter(&self) -> Self::Iter<'_>;
+
+    /// Returns `true` if the map contains `key`.
+    ///
+    /// The default implementation searches the map with [`pb_iter`](Self::pb_iter), so maps with
+    /// faster lookups should override it.
+    fn pb_contains_key(&self, key: &K) -> bool
+    where
+        K: PartialEq,
+    {
+        self.pb_iter().any(|(k, _)| k == key)
+    }
+
+    /// Removes all key-value pairs from the map.
+    ///
+    /// The default implementation replaces the map with an empty one, so maps that can be cleared
+    /// while keeping their allocation should override it.
+    fn pb_clear(&mut self)
+    where
+        Self: Default,
+    {
+        *self = Self::default();
+    }
+
+    /// Iterates through each key-value pair in the map in ascending key order.
+    ///
+    /// Used for deterministic encoding. Since the iterator doesn't allocate, it scans the whole map
+    /// for each pair, so iterating over the entire map takes quadratic time.
+    fn pb_iter_sorted(&self) -> SortedIter<'_, K, V, Self>
+    where
+        K: Ord,
+    {
+        SortedIter {
+            map: self,
+            last: None,
+            _val: core::marker::PhantomData,
+        }
+    }
 }
 
-#[cfg(feature = "container-arrayvec")]
-mod impl_arrayvec {
-    use core::ops::DerefMut;
+#[cfg(feature = "maps")]
+/// Iterator over the pairs of a [`PbMap`] in ascending key order, returned by
+/// [`PbMap::pb_iter_sorted`].
+pub struct SortedIter<'a, K, V, M: ?Sized> {
+    map: &'a M,
+    last: Option<&'a K>,
+    _val: core::marker::PhantomData<&'a V>,
+}
 
-    use super::*;
+#[cfg(feature = "maps")]
+impl<'a, K: Ord, V, M: PbMap<K, V> + ?Sized> Iterator for SortedIter<'a, K, V, M> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let last = self.last;
+        // Keys are unique, so the next pair has the smallest key that's above the last one
+        let next = self
+            .map
+            .pb_iter()
+            .filter(|(k, _)| last.map_or(true, |last| *k > last))
+            .min_by(|(a, _), (b, _)| a.cmp(b))?;
+        self.last = Some(next.0);
+        Some(next)
+    }
+}
 
-    use arrayvec::{ArrayString, ArrayVec};
+macro_rules! align_marker {
+    ($($name:ident = $n:literal),+) => {$(
+        #[doc = concat!("Zero-sized marker type with an alignment of ", stringify!($n), " bytes, for use with [`AlignedVec`].")]
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+        #[repr(align($n))]
+        pub struct $name;
+    )+};
+}
 
-    impl<T, const N: usize> PbContainer for ArrayVec<T, N> {
-        #[inline]
-        unsafe fn pb_set_len(&mut self, len: usize) {
-            self.set_len(len)
+align_marker!(
+    Align1 = 1,
+    Align4 = 4,
+    Align8 = 8,
+    Align16 = 16,
+    Align32 = 32,
+    Align64 = 64
+);
+
+/// Fixed-capacity vector whose storage is aligned to the alignment of the marker type `A`.
+///
+/// The first element of the vector is always located at an address that's a multiple of
+/// `align_of::<A>()`. This is useful for `bytes` fields whose contents are passed directly to DMA
+/// engines or cache-maintenance routines, which often require 4-byte or cache-line aligned
+/// buffers. Any zero-sized type can be used as the alignment marker, though the
+/// [`Align4`]..[`Align64`] types cover the common cases.
+///
+/// To use this container for a field in generated code, set the field's `vec_type` in the
+/// generator config to `::micropb::container::AlignedVec` (4-byte alignment) or one of the
+/// aliases such as [`AlignedVec32`].
+///
+/// # Example
+/// ```
+/// use micropb::container::{AlignedVec, Align32, PbVec};
+///
+/// let mut buf = AlignedVec::<u8, 16, Align32>::new();
+/// buf.pb_push(1).unwrap();
+/// assert_eq!(buf.as_ptr() as usize % 32, 0);
+/// assert_eq!(buf.as_slice(), &[1]);
+/// ```
+#[repr(C)]
+pub struct AlignedVec<T, const N: usize, A = Align4> {
+    // Zero-length array of the marker raises the alignment of the struct without taking up space.
+    // Since `repr(C)` lays out fields in order, `buf` is always at offset 0.
+    _align: [A; 0],
+    buf: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+/// [`AlignedVec`] with 8-byte alignment.
+pub type AlignedVec8<T, const N: usize> = AlignedVec<T, N, Align8>;
+/// [`AlignedVec`] with 16-byte alignment.
+pub type AlignedVec16<T, const N: usize> = AlignedVec<T, N, Align16>;
+/// [`AlignedVec`] with 32-byte alignment.
+pub type AlignedVec32<T, const N: usize> = AlignedVec<T, N, Align32>;
+/// [`AlignedVec`] with 64-byte alignment.
+pub type AlignedVec64<T, const N: usize> = AlignedVec<T, N, Align64>;
+/// [`AlignedVec`] without any extra alignment, for use as a general fixed-capacity vector.
+///
+/// Unlike `heapless::Vec`, constructing an empty `FixedVec` never writes to its storage, so
+/// creating a default message with a large `FixedVec` field takes constant time.
+pub type FixedVec<T, const N: usize> = AlignedVec<T, N, Align1>;
+
+impl<T, const N: usize, A> AlignedVec<T, N, A> {
+    /// Creates an empty vector.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            _align: [],
+            // SAFETY: An array of `MaybeUninit` doesn't require initialization
+            buf: unsafe { MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init() },
+            len: 0,
         }
+    }
 
-        #[inline]
-        fn pb_clear(&mut self) {
-            self.clear()
+    /// Returns the fixed capacity of the vector.
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns a pointer to the start of the aligned storage.
+    #[inline]
+    pub const fn as_ptr(&self) -> *const T {
+        self.buf.as_ptr() as *const T
+    }
+
+    /// Returns a mutable pointer to the start of the aligned storage.
+    #[inline]
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.buf.as_mut_ptr() as *mut T
+    }
+
+    /// Returns the initialized elements as a slice.
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: The first `len` elements are always initialized
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+
+    /// Returns the initialized elements as a mutable slice.
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: The first `len` elements are always initialized
+        unsafe { core::slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
+    }
+
+    /// Appends an element to the back of the vector.
+    ///
+    /// Returns the element back if the vector is full.
+    #[inline]
+    pub fn push(&mut self, elem: T) -> Result<(), T> {
+        if let Some(slot) = self.buf.get_mut(self.len) {
+            slot.write(elem);
+            self.len += 1;
+            Ok(())
+        } else {
+            Err(elem)
         }
     }
 
-    impl<const N: usize> PbContainer for ArrayString<N> {
-        #[inline]
-        unsafe fn pb_set_len(&mut self, len: usize) {
-            self.set_len(len)
+    /// Shortens the vector to `len` elements, dropping the rest. No-op if `len` is larger than the
+    /// current length.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            let tail = core::ptr::slice_from_raw_parts_mut(
+                // SAFETY: `len` is smaller than the current length, so it's within the buffer
+                unsafe { self.as_mut_ptr().add(len) },
+                self.len - len,
+            );
+            // Set the length first so that a panicking destructor can't cause a double drop
+            self.len = len;
+            // SAFETY: Elements in the tail are initialized and no longer reachable
+            unsafe { core::ptr::drop_in_place(tail) };
         }
+    }
 
-        #[inline]
-        fn pb_clear(&mut self) {
-            self.clear()
+    /// Removes all elements from the vector.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+}
+
+impl<T, const N: usize, A> Drop for AlignedVec<T, N, A> {
+    fn drop(&mut self) {
+        self.clear()
+    }
+}
+
+impl<T, const N: usize, A> Default for AlignedVec<T, N, A> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize, A> Deref for AlignedVec<T, N, A> {
+    type Target = [T];
+
+    #[inline]
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize, A> DerefMut for AlignedVec<T, N, A> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T: Clone, const N: usize, A> Clone for AlignedVec<T, N, A> {
+    fn clone(&self) -> Self {
+        let mut new = Self::new();
+        for elem in self.iter() {
+            // Can't fail, since the new vector has the same capacity
+            let _ = new.push(elem.clone());
         }
+        new
     }
+}
 
-    impl<T, const N: usize> PbVec<T> for ArrayVec<T, N> {
-        #[inline]
-        fn pb_push(&mut self, elem: T) -> Result<(), ()> {
-            self.try_push(elem).map_err(drop)
+impl<T: fmt::Debug, const N: usize, A> fmt::Debug for AlignedVec<T, N, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_slice().fmt(f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T: defmt::Format, const N: usize, A> defmt::Format for AlignedVec<T, N, A> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}", self.as_slice())
+    }
+}
+
+impl<T: PartialEq, const N: usize, const M: usize, A, B> PartialEq<AlignedVec<T, M, B>>
+    for AlignedVec<T, N, A>
+{
+    #[inline]
+    fn eq(&self, other: &AlignedVec<T, M, B>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Eq, const N: usize, A> Eq for AlignedVec<T, N, A> {}
+
+impl<T: core::hash::Hash, const N: usize, A> core::hash::Hash for AlignedVec<T, N, A> {
+    #[inline]
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl<T, const N: usize, A> PbContainer for AlignedVec<T, N, A> {
+    #[inline]
+    unsafe fn pb_set_len(&mut self, len: usize) {
+        pb_debug_assert!(len <= N);
+        self.len = len;
+    }
+
+    #[inline]
+    fn pb_clear(&mut self) {
+        self.clear()
+    }
+}
+
+impl<T: HeapSize, const N: usize, A> HeapSize for AlignedVec<T, N, A> {
+    fn heap_bytes_used(&self) -> usize {
+        self.iter().map(T::heap_bytes_used).sum()
+    }
+}
+
+impl<T, const N: usize, A> PbVec<T> for AlignedVec<T, N, A> {
+    #[inline]
+    fn pb_push(&mut self, elem: T) -> Result<(), ()> {
+        self.push(elem).map_err(drop)
+    }
+
+    #[inline]
+    fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<T>] {
+        self.buf.get_mut(self.len..).unwrap_or(&mut [])
+    }
+
+    #[inline]
+    fn pb_from_slice(s: &[T]) -> Result<Self, ()>
+    where
+        T: Copy,
+    {
+        if s.len() > N {
+            return Err(());
         }
+        let mut new = Self::new();
+        crate::misc::maybe_uninit_write_slice(&mut new.buf, s);
+        new.len = s.len();
+        Ok(new)
+    }
+}
 
-        #[inline]
-        fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<T>] {
-            let len = self.len();
-            // SAFETY: Underlying storage is static array of size N, so it's safe to create a slice
-            // of N values
-            let slice = unsafe {
-                core::slice::from_raw_parts_mut(self.as_mut_ptr() as *mut MaybeUninit<T>, N)
-            };
-            slice.get_mut(len..).unwrap_or(&mut [])
+#[cfg(feature = "strings")]
+/// Fixed-capacity UTF-8 string, backed by a [`FixedVec`].
+///
+/// Like [`FixedVec`], constructing an empty string never writes to its storage, so default
+/// messages with large `FixedString` fields are cheap to create. To use this container for
+/// `string` fields in generated code, set `string_type` to `::micropb::container::FixedString`.
+///
+/// # Example
+/// ```
+/// use micropb::container::FixedString;
+///
+/// let mut s = FixedString::<8>::new();
+/// s.push_str("abc").unwrap();
+/// assert_eq!(s, "abc");
+/// assert!(s.push_str("defghi").is_err());
+/// ```
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct FixedString<const N: usize>(FixedVec<u8, N>);
+
+#[cfg(feature = "strings")]
+impl<const N: usize> FixedString<N> {
+    /// Creates an empty string.
+    #[inline]
+    pub const fn new() -> Self {
+        Self(FixedVec::new())
+    }
+
+    /// Returns the fixed capacity of the string in bytes.
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the contents as a string slice.
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        // SAFETY: The bytes of the string are always valid UTF-8
+        unsafe { core::str::from_utf8_unchecked(&self.0) }
+    }
+
+    /// Appends a string slice to the back of the string.
+    ///
+    /// Returns error without modifying the string if there isn't enough capacity left.
+    pub fn push_str(&mut self, s: &str) -> Result<(), ()> {
+        let len = self.0.len();
+        let spare = self.0.buf.get_mut(len..).unwrap_or(&mut []);
+        if s.len() > spare.len() {
+            return Err(());
         }
+        crate::misc::maybe_uninit_write_slice(spare, s.as_bytes());
+        self.0.len += s.len();
+        Ok(())
+    }
 
-        #[inline]
-        fn pb_from_slice(s: &[T]) -> Result<Self, ()>
-        where
-            T: Copy,
-        {
-            Self::try_from(s).map_err(drop)
+    /// Removes all characters from the string.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+}
+
+#[cfg(feature = "strings")]
+impl<const N: usize> Deref for FixedString<N> {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(feature = "strings")]
+impl<const N: usize> fmt::Debug for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[cfg(all(feature = "strings", feature = "defmt"))]
+impl<const N: usize> defmt::Format for FixedString<N> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=str}", self.as_str())
+    }
+}
+
+#[cfg(feature = "strings")]
+impl<const N: usize> fmt::Display for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[cfg(feature = "strings")]
+impl<const N: usize> PartialOrd for FixedString<N> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(feature = "strings")]
+impl<const N: usize> Ord for FixedString<N> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+#[cfg(feature = "strings")]
+impl<const N: usize> PartialEq<str> for FixedString<N> {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+#[cfg(feature = "strings")]
+impl<const N: usize> PartialEq<&str> for FixedString<N> {
+    #[inline]
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+#[cfg(feature = "strings")]
+impl<const N: usize> PbContainer for FixedString<N> {
+    #[inline]
+    unsafe fn pb_set_len(&mut self, len: usize) {
+        self.0.pb_set_len(len)
+    }
+
+    #[inline]
+    fn pb_clear(&mut self) {
+        self.clear()
+    }
+}
+
+#[cfg(feature = "strings")]
+impl<const N: usize> HeapSize for FixedString<N> {
+    #[inline]
+    fn heap_bytes_used(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(feature = "strings")]
+impl<const N: usize> PbString for FixedString<N> {
+    #[inline]
+    fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<u8>] {
+        self.0.pb_spare_cap()
+    }
+
+    #[inline]
+    fn pb_from_str(s: &str) -> Result<Self, ()> {
+        let mut new = Self::new();
+        new.push_str(s)?;
+        Ok(new)
+    }
+}
+
+/// Undecoded payload of a message field, stored in the byte container `V`.
+///
+/// Message fields configured with `Config::opaque` in `micropb-gen` are stored as `Opaque`
+/// instead of being decoded, which is useful for routing messages based on other fields without
+/// paying to decode large bodies. The payload is encoded back onto the wire as-is, and can be
+/// decoded into a message later with `decode_as`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Opaque<V>(pub V);
+
+impl<V: PbVec<u8>> Opaque<V> {
+    #[cfg(feature = "decode")]
+    /// Decode the payload as a message of type `M`.
+    pub fn decode_as<M: crate::MessageDecode + Default>(
+        &self,
+    ) -> Result<M, crate::DecodeError<never::Never>> {
+        let mut msg = M::default();
+        msg.merge_from_slice(&self.0)?;
+        Ok(msg)
+    }
+
+    #[cfg(feature = "encode")]
+    /// Encode `msg` as the new payload, replacing the existing contents.
+    pub fn set_message<M: crate::MessageEncode>(&mut self, msg: &M) -> Result<(), V::Error>
+    where
+        V: crate::PbWrite,
+    {
+        self.0.pb_clear();
+        self.0.pb_reserve(msg.compute_size());
+        msg.encode(&mut crate::PbEncoder::new(&mut self.0))
+    }
+}
+
+impl<V: PbVec<u8>> Deref for Opaque<V> {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<V: PbVec<u8>> PbContainer for Opaque<V> {
+    #[inline]
+    unsafe fn pb_set_len(&mut self, len: usize) {
+        self.0.pb_set_len(len)
+    }
+
+    #[inline]
+    fn pb_reserve(&mut self, additional: usize) {
+        self.0.pb_reserve(additional)
+    }
+
+    #[inline]
+    fn pb_clear(&mut self) {
+        self.0.pb_clear()
+    }
+}
+
+impl<V: HeapSize> HeapSize for Opaque<V> {
+    #[inline]
+    fn heap_bytes_used(&self) -> usize {
+        self.0.heap_bytes_used()
+    }
+}
+
+impl<V: PbVec<u8>> PbVec<u8> for Opaque<V> {
+    #[inline]
+    fn pb_push(&mut self, elem: u8) -> Result<(), ()> {
+        self.0.pb_push(elem)
+    }
+
+    #[inline]
+    fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<u8>] {
+        self.0.pb_spare_cap()
+    }
+
+    #[inline]
+    fn pb_from_slice(s: &[u8]) -> Result<Self, ()> {
+        V::pb_from_slice(s).map(Self)
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+/// `bytes` container backed by [`bytes::Bytes`], so that cloning it only increments a reference
+/// count instead of copying the payload.
+///
+/// Once a `bytes` field is decoded into `SharedBytes`, its payload is frozen into a single
+/// reference-counted buffer. Cloning the field or handing the payload off with
+/// [`to_bytes`](Self::to_bytes) then shares that buffer, which is useful when a decoded payload is
+/// fanned out to multiple consumers. Writing to a shared payload copies it first.
+///
+/// To generate `bytes` fields as `SharedBytes`, use
+/// `Config::vec_type("::micropb::container::SharedBytes")` in `micropb-gen`. The type parameter
+/// only exists so that it can be used as a `vec_type`, and is always `u8`.
+pub struct SharedBytes<T = u8> {
+    // Holds the payload while it's being written. If `frozen` is set, this is empty.
+    buf: bytes::BytesMut,
+    frozen: Option<bytes::Bytes>,
+    _elem: core::marker::PhantomData<T>,
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl SharedBytes {
+    /// Creates an empty container without allocating.
+    #[inline]
+    pub fn new() -> Self {
+        Self::from(bytes::Bytes::new())
+    }
+
+    /// Returns the payload as [`bytes::Bytes`].
+    ///
+    /// This is cheap for payloads that were decoded or converted from `Bytes`, since the buffer
+    /// is shared rather than copied.
+    pub fn to_bytes(&self) -> bytes::Bytes {
+        match &self.frozen {
+            Some(frozen) => frozen.clone(),
+            None => bytes::Bytes::copy_from_slice(&self.buf),
         }
     }
 
-    impl<const N: usize> PbString for ArrayString<N> {
-        #[inline]
-        fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<u8>] {
-            let len = self.len();
-            // Works in Miri with tree borrows, but not stack borrows due to provenance issues with
-            // `deref_mut`
-            self.clear();
-            let s = self.deref_mut().as_mut_ptr();
-            // SAFETY: Underlying storage is array of N bytes, so the slice is valid
-            let slice = unsafe { core::slice::from_raw_parts_mut(s as *mut MaybeUninit<u8>, N) };
-            slice.get_mut(len..).unwrap_or(&mut [])
+    /// Converts the container into [`bytes::Bytes`] without copying.
+    pub fn into_bytes(self) -> bytes::Bytes {
+        match self.frozen {
+            Some(frozen) => frozen,
+            None => self.buf.freeze(),
         }
+    }
 
-        #[inline]
-        fn pb_from_str(s: &str) -> Result<Self, ()> {
-            Self::try_from(s).map_err(drop)
+    /// Appends `data` to the payload.
+    pub fn extend_from_slice(&mut self, data: &[u8]) {
+        self.make_mut().extend_from_slice(data);
+        self.freeze();
+    }
+
+    // Moves the payload into the writable buffer, which copies it only if it's shared
+    fn make_mut(&mut self) -> &mut bytes::BytesMut {
+        if let Some(frozen) = self.frozen.take() {
+            self.buf = bytes::BytesMut::from(frozen);
+        }
+        &mut self.buf
+    }
+
+    fn freeze(&mut self) {
+        self.frozen = Some(core::mem::take(&mut self.buf).freeze());
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl From<bytes::Bytes> for SharedBytes {
+    #[inline]
+    fn from(frozen: bytes::Bytes) -> Self {
+        Self {
+            buf: bytes::BytesMut::new(),
+            frozen: Some(frozen),
+            _elem: core::marker::PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl From<SharedBytes> for bytes::Bytes {
+    #[inline]
+    fn from(shared: SharedBytes) -> Self {
+        shared.into_bytes()
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl Default for SharedBytes {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl Clone for SharedBytes {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self::from(self.to_bytes())
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl Deref for SharedBytes {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        match &self.frozen {
+            Some(frozen) => frozen,
+            None => &self.buf,
+        }
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl fmt::Debug for SharedBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedBytes").field(&&**self).finish()
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl PartialEq for SharedBytes {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl Eq for SharedBytes {}
+
+#[cfg(feature = "container-bytes-1")]
+impl core::hash::Hash for SharedBytes {
+    #[inline]
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl PbContainer for SharedBytes {
+    #[inline]
+    unsafe fn pb_set_len(&mut self, len: usize) {
+        self.make_mut().set_len(len);
+        // Setting the length finishes a write, so the payload can be shared from now on
+        self.freeze();
+    }
+
+    #[inline]
+    fn pb_reserve(&mut self, additional: usize) {
+        self.make_mut().reserve(additional)
+    }
+
+    #[inline]
+    fn pb_clear(&mut self) {
+        // Drop the frozen payload instead of copying it into the writable buffer
+        self.frozen = None;
+        self.buf.clear();
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+/// Shared payloads are counted in full by every container sharing them.
+impl HeapSize for SharedBytes {
+    #[inline]
+    fn heap_bytes_used(&self) -> usize {
+        match &self.frozen {
+            Some(frozen) => frozen.len(),
+            None => self.buf.capacity(),
+        }
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl PbVec<u8> for SharedBytes {
+    #[inline]
+    fn pb_push(&mut self, elem: u8) -> Result<(), ()> {
+        self.extend_from_slice(&[elem]);
+        Ok(())
+    }
+
+    #[inline]
+    fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<u8>] {
+        self.make_mut().spare_capacity_mut()
+    }
+
+    #[inline]
+    fn pb_from_slice(s: &[u8]) -> Result<Self, ()> {
+        Ok(Self::from(bytes::Bytes::copy_from_slice(s)))
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+/// Pool that decoded strings are interned into, used by [`InternedString`].
+///
+/// The pool is global rather than being passed to the decoder, since containers have no access
+/// to decoder state. It's usually a `static` or thread-local set of shared strings.
+pub trait InternPool {
+    /// Shared handle to a string in the pool, such as `Rc<str>` or `Arc<str>`.
+    type Handle: Deref<Target = str> + Clone;
+
+    /// Returns the handle to the pooled string equal to `s`, adding `s` to the pool if it isn't
+    /// there yet.
+    fn intern(s: &str) -> Self::Handle;
+}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+/// `string` container that deduplicates decoded strings through the intern pool `P`.
+///
+/// After a string is decoded, it's replaced by a handle from `P`, so identical strings across
+/// many decoded messages, such as device IDs or enum-like strings, share a single allocation. To
+/// generate `string` fields as `InternedString`, define an alias with the pool filled in, such as
+/// `type Str = micropb::container::InternedString<MyPool>;`, and pass its path to
+/// `Config::string_type` in `micropb-gen`.
+///
+/// # Example
+/// ```
+/// use std::{cell::RefCell, collections::HashSet, rc::Rc};
+/// use micropb::{container::{InternPool, InternedString}, PbDecoder, Presence};
+///
+/// struct Pool;
+/// impl InternPool for Pool {
+///     type Handle = Rc<str>;
+///
+///     fn intern(s: &str) -> Rc<str> {
+///         thread_local!(static POOL: RefCell<HashSet<Rc<str>>> = RefCell::default());
+///         POOL.with_borrow_mut(|pool| match pool.get(s) {
+///             Some(handle) => handle.clone(),
+///             None => {
+///                 let handle: Rc<str> = s.into();
+///                 pool.insert(handle.clone());
+///                 handle
+///             }
+///         })
+///     }
+/// }
+///
+/// let mut a = InternedString::<Pool>::default();
+/// let mut b = InternedString::<Pool>::default();
+/// let mut decoder = PbDecoder::new([2, b'i', b'd', 2, b'i', b'd'].as_slice());
+/// decoder.decode_string(&mut a, Presence::Explicit).unwrap();
+/// decoder.decode_string(&mut b, Presence::Explicit).unwrap();
+/// assert_eq!(&*a, "id");
+/// assert!(std::ptr::eq(a.as_ptr(), b.as_ptr()));
+/// ```
+pub struct InternedString<P: InternPool> {
+    // Holds the string while it's being written. If `interned` is set, this is empty.
+    buf: alloc::string::String,
+    interned: Option<P::Handle>,
+}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+impl<P: InternPool> InternedString<P> {
+    /// Creates a container holding an interned string.
+    #[inline]
+    pub fn from_handle(handle: P::Handle) -> Self {
+        Self {
+            buf: alloc::string::String::new(),
+            interned: Some(handle),
         }
     }
+
+    /// Returns the handle to the interned string, or `None` if the string isn't interned.
+    ///
+    /// Strings are interned once they're decoded or created with `pb_from_str`.
+    #[inline]
+    pub fn handle(&self) -> Option<&P::Handle> {
+        self.interned.as_ref()
+    }
+
+    // Moves the interned string back into the writable buffer
+    fn make_mut(&mut self) -> &mut alloc::string::String {
+        if let Some(interned) = self.interned.take() {
+            self.buf.push_str(&interned);
+        }
+        &mut self.buf
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+impl<P: InternPool> Default for InternedString<P> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            buf: alloc::string::String::new(),
+            interned: None,
+        }
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+impl<P: InternPool> Clone for InternedString<P> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            buf: self.buf.clone(),
+            interned: self.interned.clone(),
+        }
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+impl<P: InternPool> Deref for InternedString<P> {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        match &self.interned {
+            Some(interned) => interned,
+            None => &self.buf,
+        }
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+impl<P: InternPool> fmt::Debug for InternedString<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+impl<P: InternPool> PartialEq for InternedString<P> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+impl<P: InternPool> Eq for InternedString<P> {}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+impl<P: InternPool> PartialOrd for InternedString<P> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+impl<P: InternPool> Ord for InternedString<P> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+impl<P: InternPool> core::hash::Hash for InternedString<P> {
+    #[inline]
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+impl<P: InternPool> PbContainer for InternedString<P> {
+    #[inline]
+    unsafe fn pb_set_len(&mut self, len: usize) {
+        self.make_mut().as_mut_vec().set_len(len);
+        // Setting the length finishes a write, so intern the string and free the buffer
+        self.interned = Some(P::intern(&self.buf));
+        self.buf = alloc::string::String::new();
+    }
+
+    #[inline]
+    fn pb_reserve(&mut self, additional: usize) {
+        self.make_mut().reserve(additional)
+    }
+
+    #[inline]
+    fn pb_clear(&mut self) {
+        self.interned = None;
+        self.buf.clear();
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "strings"))]
+/// Interned strings are owned by the pool, so only the writable buffer is counted.
+impl<P: InternPool> HeapSize for InternedString<P> {
+    #[inline]
+    fn heap_bytes_used(&self) -> usize {
+        self.buf.capacity()
+    }
 }
 
-#[cfg(feature = "container-heapless")]
-mod impl_heapless {
+#[cfg(all(feature = "alloc", feature = "strings"))]
+impl<P: InternPool> PbString for InternedString<P> {
+    #[inline]
+    fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<u8>] {
+        // SAFETY: spare_capacity_mut() is a safe call, since it doesn't change any bytes
+        unsafe { self.make_mut().as_mut_vec().spare_capacity_mut() }
+    }
+
+    #[inline]
+    fn pb_from_str(s: &str) -> Result<Self, ()> {
+        Ok(Self::from_handle(P::intern(s)))
+    }
+}
+
+#[cfg(feature = "container-arrayvec-0_7")]
+mod impl_arrayvec {
     use super::*;
 
-    use core::hash::{BuildHasher, Hash};
+    #[cfg(feature = "strings")]
+    use arrayvec::ArrayString;
+    use arrayvec::ArrayVec;
 
-    use heapless::{IndexMap, IndexMapIter, String, Vec};
+    impl<T, const N: usize> PbContainer for ArrayVec<T, N> {
+        #[inline]
+        unsafe fn pb_set_len(&mut self, len: usize) {
+            self.set_len(len)
+        }
 
-    impl<T, const N: usize> PbContainer for Vec<T, N> {
         #[inline]
         fn pb_clear(&mut self) {
             self.clear()
         }
+    }
 
+    #[cfg(feature = "strings")]
+    impl<const N: usize> PbContainer for ArrayString<N> {
         #[inline]
         unsafe fn pb_set_len(&mut self, len: usize) {
             self.set_len(len)
         }
-    }
 
-    impl<const N: usize> PbContainer for String<N> {
         #[inline]
         fn pb_clear(&mut self) {
             self.clear()
         }
+    }
 
+    impl<T: HeapSize, const N: usize> HeapSize for ArrayVec<T, N> {
+        fn heap_bytes_used(&self) -> usize {
+            self.iter().map(T::heap_bytes_used).sum()
+        }
+    }
+
+    #[cfg(feature = "strings")]
+    impl<const N: usize> HeapSize for ArrayString<N> {
         #[inline]
-        unsafe fn pb_set_len(&mut self, len: usize) {
-            self.as_mut_vec().set_len(len)
+        fn heap_bytes_used(&self) -> usize {
+            0
         }
     }
 
-    impl<T, const N: usize> PbVec<T> for Vec<T, N> {
+    impl<T, const N: usize> PbVec<T> for ArrayVec<T, N> {
         #[inline]
         fn pb_push(&mut self, elem: T) -> Result<(), ()> {
-            self.push(elem).map_err(drop)
+            self.try_push(elem).map_err(drop)
         }
 
         #[inline]
@@ -246,17 +1196,17 @@ mod impl_heapless {
         }
     }
 
-    impl<const N: usize> PbString for String<N> {
+    #[cfg(feature = "strings")]
+    impl<const N: usize> PbString for ArrayString<N> {
         #[inline]
         fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<u8>] {
             let len = self.len();
+            // Works in Miri with tree borrows, but not stack borrows due to provenance issues with
+            // `deref_mut`
+            self.clear();
+            let s = self.deref_mut().as_mut_ptr();
             // SAFETY: Underlying storage is array of N bytes, so the slice is valid
-            let slice = unsafe {
-                core::slice::from_raw_parts_mut(
-                    self.as_mut_vec().as_mut_ptr() as *mut MaybeUninit<u8>,
-                    N,
-                )
-            };
+            let slice = unsafe { core::slice::from_raw_parts_mut(s as *mut MaybeUninit<u8>, N) };
             slice.get_mut(len..).unwrap_or(&mut [])
         }
 
@@ -265,33 +1215,170 @@ mod impl_heapless {
             Self::try_from(s).map_err(drop)
         }
     }
+}
 
-    impl<K: Eq + Hash, V, S: BuildHasher, const N: usize> PbMap<K, V> for IndexMap<K, V, S, N> {
-        type Iter<'a> = IndexMapIter<'a, K, V> where S: 'a, K: 'a, V: 'a;
-
-        #[inline]
-        fn pb_insert(&mut self, key: K, val: V) -> Result<(), ()> {
-            self.insert(key, val).map_err(drop)?;
-            Ok(())
-        }
-
-        #[inline]
-        fn pb_iter(&self) -> Self::Iter<'_> {
-            self.iter()
+/// Implements the container traits on the types of a version of `heapless`
+#[cfg(any(
+    feature = "container-heapless-0_7",
+    feature = "container-heapless-0_8",
+    feature = "container-heapless-0_9"
+))]
+macro_rules! impl_heapless {
+    ($mod_name:ident, $heapless:ident, $($hash:ident)::+) => {
+        mod $mod_name {
+            use super::*;
+
+            use $($hash)::+::{BuildHasher, Hash};
+
+            use $heapless::{IndexMap, String, Vec};
+
+            impl<T, const N: usize> PbContainer for Vec<T, N> {
+                #[inline]
+                fn pb_clear(&mut self) {
+                    self.clear()
+                }
+
+                #[inline]
+                unsafe fn pb_set_len(&mut self, len: usize) {
+                    self.set_len(len)
+                }
+            }
+
+            #[cfg(feature = "strings")]
+            impl<const N: usize> PbContainer for String<N> {
+                #[inline]
+                fn pb_clear(&mut self) {
+                    self.clear()
+                }
+
+                #[inline]
+                unsafe fn pb_set_len(&mut self, len: usize) {
+                    self.as_mut_vec().set_len(len)
+                }
+            }
+
+            impl<T: HeapSize, const N: usize> HeapSize for Vec<T, N> {
+                fn heap_bytes_used(&self) -> usize {
+                    self.iter().map(T::heap_bytes_used).sum()
+                }
+            }
+
+            impl<const N: usize> HeapSize for String<N> {
+                #[inline]
+                fn heap_bytes_used(&self) -> usize {
+                    0
+                }
+            }
+
+            impl<K: Eq + Hash + HeapSize, V: HeapSize, S: BuildHasher, const N: usize> HeapSize
+                for IndexMap<K, V, S, N>
+            {
+                fn heap_bytes_used(&self) -> usize {
+                    self.iter()
+                        .map(|(k, v)| k.heap_bytes_used() + v.heap_bytes_used())
+                        .sum()
+                }
+            }
+
+            impl<T, const N: usize> PbVec<T> for Vec<T, N> {
+                #[inline]
+                fn pb_push(&mut self, elem: T) -> Result<(), ()> {
+                    self.push(elem).map_err(drop)
+                }
+
+                #[inline]
+                fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<T>] {
+                    let len = self.len();
+                    // SAFETY: Underlying storage is static array of size N, so it's safe to create a slice
+                    // of N values
+                    let slice = unsafe {
+                        core::slice::from_raw_parts_mut(self.as_mut_ptr() as *mut MaybeUninit<T>, N)
+                    };
+                    slice.get_mut(len..).unwrap_or(&mut [])
+                }
+
+                #[inline]
+                fn pb_from_slice(s: &[T]) -> Result<Self, ()>
+                where
+                    T: Copy,
+                {
+                    Self::try_from(s).map_err(drop)
+                }
+            }
+
+            #[cfg(feature = "strings")]
+            impl<const N: usize> PbString for String<N> {
+                #[inline]
+                fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<u8>] {
+                    let len = self.len();
+                    // SAFETY: Underlying storage is array of N bytes, so the slice is valid
+                    let slice = unsafe {
+                        core::slice::from_raw_parts_mut(
+                            self.as_mut_vec().as_mut_ptr() as *mut MaybeUninit<u8>,
+                            N,
+                        )
+                    };
+                    slice.get_mut(len..).unwrap_or(&mut [])
+                }
+
+                #[inline]
+                fn pb_from_str(s: &str) -> Result<Self, ()> {
+                    let mut string = Self::new();
+                    string.push_str(s).map_err(drop)?;
+                    Ok(string)
+                }
+            }
+
+            #[cfg(feature = "maps")]
+            impl<K: Eq + Hash, V, S: BuildHasher, const N: usize> PbMap<K, V> for IndexMap<K, V, S, N> {
+                // Iterator type isn't exported by all versions of heapless
+                type Iter<'a> = <&'a IndexMap<K, V, S, N> as IntoIterator>::IntoIter
+                where
+                    S: 'a,
+                    K: 'a,
+                    V: 'a;
+
+                #[inline]
+                fn pb_insert(&mut self, key: K, val: V) -> Result<(), ()> {
+                    self.insert(key, val).map_err(drop)?;
+                    Ok(())
+                }
+
+                #[inline]
+                fn pb_iter(&self) -> Self::Iter<'_> {
+                    self.iter()
+                }
+
+                #[inline]
+                fn pb_contains_key(&self, key: &K) -> bool {
+                    self.contains_key(key)
+                }
+
+                #[inline]
+                fn pb_clear(&mut self) {
+                    self.clear()
+                }
+            }
         }
-    }
+    };
 }
 
+#[cfg(feature = "container-heapless-0_7")]
+impl_heapless!(impl_heapless_0_7, heapless_0_7, hash32_0_2);
+#[cfg(feature = "container-heapless-0_8")]
+impl_heapless!(impl_heapless_0_8, heapless, core::hash);
+#[cfg(feature = "container-heapless-0_9")]
+impl_heapless!(impl_heapless_0_9, heapless_0_9, core::hash);
+
 #[cfg(feature = "alloc")]
 mod impl_alloc {
     use super::*;
 
-    use alloc::{
-        borrow::ToOwned,
-        collections::{btree_map, BTreeMap},
-        string::String,
-        vec::Vec,
-    };
+    #[cfg(feature = "strings")]
+    use alloc::borrow::ToOwned;
+    #[cfg(feature = "maps")]
+    use alloc::collections::btree_map;
+    use alloc::{collections::BTreeMap, string::String, vec::Vec};
 
     impl<T> PbContainer for Vec<T> {
         #[inline]
@@ -327,6 +1414,7 @@ mod impl_alloc {
     //}
     //}
 
+    #[cfg(feature = "strings")]
     impl PbContainer for String {
         #[inline]
         fn pb_clear(&mut self) {
@@ -358,6 +1446,44 @@ mod impl_alloc {
     //}
     //}
 
+    impl<T: HeapSize> HeapSize for Vec<T> {
+        fn heap_bytes_used(&self) -> usize {
+            self.capacity() * core::mem::size_of::<T>()
+                + self.iter().map(T::heap_bytes_used).sum::<usize>()
+        }
+    }
+
+    impl HeapSize for String {
+        #[inline]
+        fn heap_bytes_used(&self) -> usize {
+            self.capacity()
+        }
+    }
+
+    /// `BTreeMap` doesn't expose its node layout, so only the inline size of each entry is
+    /// counted, which underestimates the actual usage.
+    impl<K: HeapSize, V: HeapSize> HeapSize for BTreeMap<K, V> {
+        fn heap_bytes_used(&self) -> usize {
+            self.iter()
+                .map(|(k, v)| {
+                    core::mem::size_of::<(K, V)>() + k.heap_bytes_used() + v.heap_bytes_used()
+                })
+                .sum()
+        }
+    }
+
+    /// Only the inline size of each entry slot is counted, not the hash table's control bytes.
+    #[cfg(feature = "std")]
+    impl<K: HeapSize, V: HeapSize, S> HeapSize for std::collections::HashMap<K, V, S> {
+        fn heap_bytes_used(&self) -> usize {
+            self.capacity() * core::mem::size_of::<(K, V)>()
+                + self
+                    .iter()
+                    .map(|(k, v)| k.heap_bytes_used() + v.heap_bytes_used())
+                    .sum::<usize>()
+        }
+    }
+
     impl<T> PbVec<T> for Vec<T> {
         #[inline]
         fn pb_push(&mut self, elem: T) -> Result<(), ()> {
@@ -393,6 +1519,7 @@ mod impl_alloc {
     //}
     //}
 
+    #[cfg(feature = "strings")]
     impl PbString for String {
         #[inline]
         fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<u8>] {
@@ -413,6 +1540,7 @@ mod impl_alloc {
     //}
     //}
 
+    #[cfg(feature = "maps")]
     impl<K: Ord, V> PbMap<K, V> for BTreeMap<K, V> {
         type Iter<'a> = btree_map::Iter<'a, K, V> where K: 'a, V: 'a;
 
@@ -426,9 +1554,19 @@ mod impl_alloc {
         fn pb_iter(&self) -> Self::Iter<'_> {
             self.iter()
         }
+
+        #[inline]
+        fn pb_contains_key(&self, key: &K) -> bool {
+            self.contains_key(key)
+        }
+
+        #[inline]
+        fn pb_clear(&mut self) {
+            self.clear()
+        }
     }
 
-    #[cfg(feature = "std")]
+    #[cfg(all(feature = "std", feature = "maps"))]
     impl<K: Eq + core::hash::Hash, V> PbMap<K, V> for std::collections::HashMap<K, V> {
         type Iter<'a> = std::collections::hash_map::Iter<'a, K, V> where K: 'a, V: 'a;
 
@@ -442,5 +1580,64 @@ mod impl_alloc {
         fn pb_iter(&self) -> Self::Iter<'_> {
             self.iter()
         }
+
+        #[inline]
+        fn pb_contains_key(&self, key: &K) -> bool {
+            self.contains_key(key)
+        }
+
+        #[inline]
+        fn pb_clear(&mut self) {
+            self.clear()
+        }
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+mod impl_bytes {
+    use super::*;
+
+    use bytes::BytesMut;
+
+    impl PbContainer for BytesMut {
+        #[inline]
+        fn pb_clear(&mut self) {
+            self.clear()
+        }
+
+        #[inline]
+        unsafe fn pb_set_len(&mut self, len: usize) {
+            self.set_len(len)
+        }
+
+        #[inline]
+        fn pb_reserve(&mut self, additional: usize) {
+            self.reserve(additional)
+        }
+    }
+
+    impl HeapSize for BytesMut {
+        #[inline]
+        fn heap_bytes_used(&self) -> usize {
+            self.capacity()
+        }
+    }
+
+    impl PbVec<u8> for BytesMut {
+        #[inline]
+        fn pb_push(&mut self, elem: u8) -> Result<(), ()> {
+            self.extend_from_slice(&[elem]);
+            Ok(())
+        }
+
+        #[inline]
+        fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<u8>] {
+            self.spare_capacity_mut()
+        }
+
+        #[inline]
+        fn pb_from_slice(s: &[u8]) -> Result<Self, ()> {
+            Ok(Self::from(s))
+        }
     }
 }
@@ -1,15 +1,20 @@
 use core::{
+    cmp::Ordering,
+    fmt,
     mem::MaybeUninit,
+    ops::{ControlFlow, DerefMut},
     str::{from_utf8, Utf8Error},
 };
 
+#[cfg(feature = "strings")]
+use crate::container::PbString;
 use crate::{
-    container::{PbString, PbVec},
+    container::PbVec,
     misc::{
-        maybe_uninit_slice_assume_init_ref, maybe_uninit_write_slice,
+        load_le_u32, load_le_u64, maybe_uninit_slice_assume_init_ref, maybe_uninit_write_slice,
         maybe_ununit_array_assume_init,
     },
-    MessageDecode, Presence, Tag, WIRE_TYPE_I32, WIRE_TYPE_I64, WIRE_TYPE_LEN, WIRE_TYPE_VARINT,
+    wire, MessageDecode, PbEnum, Presence, Tag, WireType, WIRE_TYPE_LEN,
 };
 
 use never::Never;
@@ -39,6 +44,32 @@ pub enum DecodeError<E> {
     Capacity,
     /// Actual length of length-delimited record differs from value of length prefix
     WrongLen,
+    /// Decoded value can't be represented by the Rust type it's decoded into
+    OutOfRange,
+    /// Exceeded the decoder's [memory budget](PbDecoder::charge_memory)
+    MemoryBudget,
+    /// Decoding was aborted by the decoder's [`should_continue`](PbDecoder::check_continue)
+    /// hook
+    Cancelled,
+    /// Messages are nested deeper than the decoder's maximum depth, which is set with
+    /// `PbDecoder::set_max_depth` when the `decode-limits` feature is enabled
+    DepthLimit,
+    /// Length-delimited value or total input exceeded the decoder's length limits, which are set
+    /// with `PbDecoder::set_max_len_field` and `PbDecoder::set_max_total_bytes` when the
+    /// `decode-limits` feature is enabled
+    LenLimit,
+    /// Repeated enum field contained a value that isn't declared in the `.proto` file, and the
+    /// field's [`UnknownEnum`] policy is [`Error`](UnknownEnum::Error)
+    UnknownEnum,
+    /// Message contained a field number that isn't in the schema, and the message rejects unknown
+    /// fields
+    UnknownField(u32),
+    /// Field labelled `required` wasn't present in the decoded message, and the message enforces
+    /// required fields
+    MissingRequired(u32),
+    /// `map` entry had a key that was already in the map, and the decoder's
+    /// [`duplicate_keys`](PbDecoder::duplicate_keys) policy is [`Error`](DuplicateKey::Error)
+    DuplicateKey,
     /// Error returned from reader
     Reader(E),
 }
@@ -49,6 +80,263 @@ impl<E> From<Utf8Error> for DecodeError<E> {
     }
 }
 
+impl<E: fmt::Display> fmt::Display for DecodeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::VarIntLimit => f.write_str("varint exceeded 10 bytes"),
+            DecodeError::UnexpectedEof => f.write_str("unexpected end of input"),
+            DecodeError::Deprecation => f.write_str("deprecated wire type"),
+            DecodeError::UnknownWireType => f.write_str("unknown wire type"),
+            DecodeError::ZeroField => f.write_str("field number of 0"),
+            DecodeError::CustomField => f.write_str("custom field rejected its field number"),
+            DecodeError::Utf8 => f.write_str("string is not valid UTF-8"),
+            DecodeError::Capacity => f.write_str("fixed container capacity exceeded"),
+            DecodeError::WrongLen => f.write_str("record length differs from its length prefix"),
+            DecodeError::OutOfRange => f.write_str("value out of range of the target type"),
+            DecodeError::MemoryBudget => f.write_str("decoder memory budget exceeded"),
+            DecodeError::Cancelled => f.write_str("decoding cancelled"),
+            DecodeError::DepthLimit => f.write_str("message nesting depth limit exceeded"),
+            DecodeError::LenLimit => f.write_str("decoder length limit exceeded"),
+            DecodeError::UnknownEnum => f.write_str("unknown enum value"),
+            DecodeError::UnknownField(num) => write!(f, "unknown field number {num}"),
+            DecodeError::MissingRequired(num) => write!(f, "missing required field number {num}"),
+            DecodeError::DuplicateKey => f.write_str("duplicate map key"),
+            DecodeError::Reader(e) => write!(f, "reader error: {e}"),
+        }
+    }
+}
+
+/// Formats the same messages as the `Display` impl. The reader error isn't printed, so that
+/// errors from readers that don't implement `defmt::Format`, such as byte slices, can still be
+/// logged.
+#[cfg(feature = "defmt")]
+impl<E> defmt::Format for DecodeError<E> {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            DecodeError::VarIntLimit => defmt::write!(f, "varint exceeded 10 bytes"),
+            DecodeError::UnexpectedEof => defmt::write!(f, "unexpected end of input"),
+            DecodeError::Deprecation => defmt::write!(f, "deprecated wire type"),
+            DecodeError::UnknownWireType => defmt::write!(f, "unknown wire type"),
+            DecodeError::ZeroField => defmt::write!(f, "field number of 0"),
+            DecodeError::CustomField => defmt::write!(f, "custom field rejected its field number"),
+            DecodeError::Utf8 => defmt::write!(f, "string is not valid UTF-8"),
+            DecodeError::Capacity => defmt::write!(f, "fixed container capacity exceeded"),
+            DecodeError::WrongLen => {
+                defmt::write!(f, "record length differs from its length prefix")
+            }
+            DecodeError::OutOfRange => defmt::write!(f, "value out of range of the target type"),
+            DecodeError::MemoryBudget => defmt::write!(f, "decoder memory budget exceeded"),
+            DecodeError::Cancelled => defmt::write!(f, "decoding cancelled"),
+            DecodeError::DepthLimit => defmt::write!(f, "message nesting depth limit exceeded"),
+            DecodeError::LenLimit => defmt::write!(f, "decoder length limit exceeded"),
+            DecodeError::UnknownEnum => defmt::write!(f, "unknown enum value"),
+            DecodeError::UnknownField(num) => defmt::write!(f, "unknown field number {=u32}", num),
+            DecodeError::MissingRequired(num) => {
+                defmt::write!(f, "missing required field number {=u32}", num)
+            }
+            DecodeError::DuplicateKey => defmt::write!(f, "duplicate map key"),
+            DecodeError::Reader(_) => defmt::write!(f, "reader error"),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl<E: crate::ErrorTrait + 'static> crate::ErrorTrait for DecodeError<E> {
+    fn source(&self) -> Option<&(dyn crate::ErrorTrait + 'static)> {
+        match self {
+            DecodeError::Reader(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+/// Converts decoding errors into [`std::io::ErrorKind::InvalidData`] errors, except for
+/// [`DecodeError::UnexpectedEof`], which becomes [`std::io::ErrorKind::UnexpectedEof`].
+///
+/// Reader errors that are already [`std::io::Error`], such as those from [`StdReader`], are
+/// returned as-is. Other reader errors are wrapped with [`std::io::ErrorKind::Other`].
+impl<E: std::error::Error + Send + Sync + 'static> From<DecodeError<E>> for std::io::Error {
+    fn from(err: DecodeError<E>) -> Self {
+        match err {
+            DecodeError::Reader(e) => {
+                let e: Box<dyn std::error::Error + Send + Sync> = Box::new(e);
+                match e.downcast::<std::io::Error>() {
+                    Ok(e) => *e,
+                    Err(e) => std::io::Error::other(e),
+                }
+            }
+            DecodeError::UnexpectedEof => {
+                std::io::Error::new(std::io::ErrorKind::UnexpectedEof, err)
+            }
+            _ => std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+/// [`DecodeError`] annotated with where in the input it occurred.
+///
+/// Created with [`PbDecoder::error_at`] right after a decode call fails, to help debugging
+/// corrupted frames by telling where decoding stopped.
+pub struct DecodeErrorAt<E> {
+    /// The decode error
+    pub error: DecodeError<E>,
+    /// Number of bytes consumed from the input when the error occurred
+    pub offset: usize,
+    /// Offset of the tag of the innermost field that was being decoded when the error occurred
+    pub field_offset: usize,
+}
+
+impl<E: fmt::Display> fmt::Display for DecodeErrorAt<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at byte {} (in field starting at byte {})",
+            self.error, self.offset, self.field_offset
+        )
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<E> defmt::Format for DecodeErrorAt<E> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "{} at byte {=usize} (in field starting at byte {=usize})",
+            self.error,
+            self.offset,
+            self.field_offset
+        )
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl<E: crate::ErrorTrait + 'static> crate::ErrorTrait for DecodeErrorAt<E> {
+    fn source(&self) -> Option<&(dyn crate::ErrorTrait + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Maximum number of field numbers stored in a [`FieldPath`].
+pub const MAX_FIELD_PATH: usize = 16;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Chain of field numbers leading from the outermost message to the field where decoding failed.
+///
+/// Displayed as the field numbers separated by dots, such as `3.7.1`. If the failure is nested
+/// more than [`MAX_FIELD_PATH`] levels deep, only the innermost field numbers are kept and the
+/// path is displayed with a leading `...`.
+pub struct FieldPath {
+    // Stored from innermost to outermost, since errors are annotated as they propagate outwards
+    nums: [u32; MAX_FIELD_PATH],
+    len: usize,
+    truncated: bool,
+}
+
+impl FieldPath {
+    /// Number of field numbers in the path.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the path has no field numbers.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if the outermost field numbers were dropped because the path was too long.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Iterate over the field numbers, from the outermost message to the innermost field.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.nums[..self.len].iter().rev().copied()
+    }
+
+    #[cfg(feature = "field-path")]
+    fn push_outer(&mut self, num: u32) {
+        if self.len < MAX_FIELD_PATH {
+            self.nums[self.len] = num;
+            self.len += 1;
+        } else {
+            self.truncated = true;
+        }
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.truncated {
+            f.write_str("...")?;
+        }
+        for (i, num) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            write!(f, "{num}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for FieldPath {
+    fn format(&self, f: defmt::Formatter) {
+        if self.truncated {
+            defmt::write!(f, "...");
+        }
+        for (i, num) in self.iter().enumerate() {
+            if i > 0 {
+                defmt::write!(f, ".");
+            }
+            defmt::write!(f, "{=u32}", num);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+/// [`DecodeError`] annotated with the field path at which it occurred.
+///
+/// Created with `PbDecoder::error_ctx` after a decode call fails, which requires the `field-path`
+/// feature. The path is only recorded by messages generated with the `field_path_errors` option of
+/// `micropb-gen`, so it's empty for other messages.
+pub struct DecodeErrorCtx<E> {
+    /// The decode error
+    pub error: DecodeError<E>,
+    /// Field numbers leading to the field where the error occurred
+    pub path: FieldPath,
+}
+
+impl<E: fmt::Display> fmt::Display for DecodeErrorCtx<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.error)
+        } else {
+            write!(f, "{} in field {}", self.error, self.path)
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<E> defmt::Format for DecodeErrorCtx<E> {
+    fn format(&self, f: defmt::Formatter) {
+        if self.path.is_empty() {
+            defmt::write!(f, "{}", self.error)
+        } else {
+            defmt::write!(f, "{} in field {}", self.error, self.path)
+        }
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl<E: crate::ErrorTrait + 'static> crate::ErrorTrait for DecodeErrorCtx<E> {
+    fn source(&self) -> Option<&(dyn crate::ErrorTrait + 'static)> {
+        Some(&self.error)
+    }
+}
+
 /// A reader from which Protobuf data is read, similar to [`std::io::BufRead`].
 ///
 /// Like [`std::io::BufRead`], this trait assumes that the reader uses an underlying buffer.
@@ -100,7 +388,7 @@ pub trait PbRead {
             pos += n;
         }
 
-        debug_assert_eq!(pos, buf.len());
+        pb_debug_assert!(pos == buf.len());
         Ok(pos)
     }
 }
@@ -145,18 +433,307 @@ impl PbRead for &[u8] {
     }
 }
 
+#[cfg(feature = "container-bytes-1")]
+impl PbRead for bytes::Bytes {
+    type Error = Never;
+
+    #[inline]
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        Ok(self)
+    }
+
+    #[inline]
+    fn pb_advance(&mut self, bytes: usize) {
+        bytes::Buf::advance(self, bytes.min(self.len()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Policy for handling trailing bytes in a length-delimited record.
+///
+/// Trailing bytes are left over when decoding a record consumes fewer bytes than its length
+/// prefix, which usually happens when a custom message or field decoder stops early. Records that
+/// consume more bytes than their length prefix always return [`DecodeError::WrongLen`].
+pub enum TrailingBytes {
+    /// Return [`DecodeError::WrongLen`].
+    #[default]
+    Error,
+    /// Skip the trailing bytes.
+    Skip,
+    /// Skip the trailing bytes and add their count to [`PbDecoder::trailing_bytes_skipped`].
+    #[cfg(feature = "decode-stats")]
+    Report,
+}
+
+#[cfg(any(
+    feature = "container-heapless-0_7",
+    feature = "container-heapless-0_8",
+    feature = "container-heapless-0_9"
+))]
+macro_rules! impl_spsc_read {
+    ([$($generics:tt)*] $consumer:ty) => {
+        /// Reads the bytes that are currently in the queue, one byte at a time. The queue running
+        /// empty counts as EOF, so only decode data once it has been fully enqueued, such as after
+        /// the producer has received a complete frame.
+        impl<$($generics)*> PbRead for $consumer {
+            type Error = Never;
+
+            #[inline]
+            fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+                Ok(self.peek().map(core::slice::from_ref).unwrap_or(&[]))
+            }
+
+            #[inline]
+            fn pb_advance(&mut self, bytes: usize) {
+                for _ in 0..bytes {
+                    self.dequeue();
+                }
+            }
+        }
+    };
+}
+
+#[cfg(all(feature = "container-heapless-0_8", target_has_atomic = "ptr"))]
+impl_spsc_read!([const N: usize] heapless::spsc::Consumer<'_, u8, N>);
+#[cfg(all(feature = "container-heapless-0_7", target_has_atomic = "ptr"))]
+impl_spsc_read!([const N: usize] heapless_0_7::spsc::Consumer<'_, u8, N>);
+#[cfg(all(feature = "container-heapless-0_9", target_has_atomic = "ptr"))]
+impl_spsc_read!([] heapless_0_9::spsc::Consumer<'_, u8>);
+
+#[derive(Debug, Clone)]
+/// Reader over input that's split across multiple non-contiguous buffers.
+///
+/// Messages that arrive in several network packets or DMA descriptors can be decoded in place
+/// with this reader, without first copying the pieces into one contiguous buffer. Values that
+/// straddle two buffers are handled by the decoder. The buffers are yielded by an iterator, which
+/// can be a slice iterator or something that produces them lazily, and empty buffers are skipped.
+///
+/// # Example
+///
+/// ```
+/// use micropb::{ChunkedReader, PbDecoder};
+///
+/// // A varint split across two packets
+/// let packets: [&[u8]; 2] = [&[0x96], &[0x01]];
+/// let mut decoder = PbDecoder::new(ChunkedReader::new(packets));
+/// assert_eq!(decoder.decode_varint32(), Ok(150));
+/// ```
+pub struct ChunkedReader<'a, I> {
+    chunk: &'a [u8],
+    chunks: I,
+}
+
+impl<'a, I: Iterator<Item = &'a [u8]>> ChunkedReader<'a, I> {
+    #[inline]
+    /// Construct a reader that reads each buffer yielded by `chunks` in order.
+    pub fn new<C: IntoIterator<IntoIter = I>>(chunks: C) -> Self {
+        Self {
+            chunk: &[],
+            chunks: chunks.into_iter(),
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = &'a [u8]>> PbRead for ChunkedReader<'a, I> {
+    type Error = Never;
+
+    #[inline]
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        while self.chunk.is_empty() {
+            match self.chunks.next() {
+                Some(chunk) => self.chunk = chunk,
+                None => break,
+            }
+        }
+        Ok(self.chunk)
+    }
+
+    #[inline]
+    fn pb_advance(&mut self, bytes: usize) {
+        self.chunk.pb_advance(bytes)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Reader over the readable region of a ring buffer, which may wrap around the end of the ring.
+///
+/// Ring buffer consumers, such as `bbqueue`'s split read grants, typically expose their readable
+/// data as two slices: the bytes up to the end of the ring and the bytes that wrapped around to
+/// its start. This reader yields both in order, so data pushed by a UART ISR can be decoded
+/// straight out of the ring. Afterwards, [`consumed`](Self::consumed) tells how many bytes to
+/// release back to the ring.
+///
+/// # Example
+///
+/// ```
+/// use micropb::{PbDecoder, RingReader};
+///
+/// // The varint 150 was written at the end of the ring and wrapped around to its start
+/// let ring = [0x01, 0x00, 0x00, 0x96];
+/// let mut decoder = PbDecoder::new(RingReader::new(&ring[3..], &ring[..1]));
+/// assert_eq!(decoder.decode_varint32(), Ok(150));
+/// // Release 2 bytes back to the ring buffer
+/// assert_eq!(decoder.as_reader().consumed(), 2);
+/// ```
+pub struct RingReader<'a> {
+    head: &'a [u8],
+    tail: &'a [u8],
+    consumed: usize,
+}
+
+impl<'a> RingReader<'a> {
+    #[inline]
+    /// Construct a reader that reads `head`, the bytes before the wrap-around point, and then
+    /// `tail`, the bytes after it.
+    pub fn new(head: &'a [u8], tail: &'a [u8]) -> Self {
+        Self {
+            head,
+            tail,
+            consumed: 0,
+        }
+    }
+
+    #[inline]
+    /// Get the number of bytes consumed from the ring buffer.
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    #[inline]
+    /// Get the number of readable bytes that haven't been consumed yet.
+    pub fn remaining(&self) -> usize {
+        self.head.len() + self.tail.len()
+    }
+}
+
+impl PbRead for RingReader<'_> {
+    type Error = Never;
+
+    #[inline]
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        if self.head.is_empty() {
+            Ok(self.tail)
+        } else {
+            Ok(self.head)
+        }
+    }
+
+    #[inline]
+    fn pb_advance(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.remaining());
+        self.consumed += bytes;
+        if self.head.is_empty() {
+            self.tail.pb_advance(bytes);
+        } else {
+            self.head.pb_advance(bytes);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Policy for handling `map` entries with a key that's already in the map.
+pub enum DuplicateKey {
+    /// Replace the existing value, which matches the upstream Protobuf semantics.
+    #[default]
+    LastWins,
+    /// Keep the existing value and ignore the new entry.
+    FirstWins,
+    /// Return [`DecodeError::DuplicateKey`].
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Policy for handling elements of a repeated field that don't fit into a fixed-capacity
+/// container.
+pub enum RepeatedOverflow {
+    /// Return [`DecodeError::Capacity`], unless [`PbDecoder::ignore_repeated_cap_err`] is set, in
+    /// which case the new element is dropped.
+    #[default]
+    Error,
+    /// Drop the new element, keeping the oldest elements in the container.
+    DropNewest,
+    /// Drop the oldest element in the container to make room for the new element, keeping the
+    /// most recent elements.
+    DropOldest,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Policy for handling values of a repeated enum field that aren't declared in the `.proto` file.
+pub enum UnknownEnum {
+    /// Keep the unknown value in the container, like any other value.
+    #[default]
+    Keep,
+    /// Drop the unknown value, counting it in [`DecodeStats::unknown_enums`].
+    Drop,
+    /// Return [`DecodeError::UnknownEnum`].
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Counts of data discarded by lossy decoding policies, returned by `PbDecoder::stats` when the
+/// `decode-stats` feature is enabled.
+pub struct DecodeStats {
+    /// Number of repeated and `map` field elements that were dropped because their container was
+    /// full, either due to a [`RepeatedOverflow`] policy or because
+    /// [`PbDecoder::ignore_repeated_cap_err`] is set.
+    pub dropped_elements: usize,
+    /// Number of strings cut short by [`PbDecoder::decode_string_truncated`].
+    pub truncated_strings: usize,
+    /// Number of unknown enum values dropped due to [`UnknownEnum::Drop`].
+    pub unknown_enums: usize,
+}
+
 #[cfg(feature = "std")]
 #[derive(Debug, Clone)]
 /// Adapter that implements [`PbRead`] for all implementers of [`std::io::BufRead`], allowing the
 /// decoder to read from `std` readers.
+///
+/// Readers that only implement [`std::io::Read`], such as files and sockets, can be wrapped with
+/// [`StdReader::buffered`]. Reads interrupted by signals are retried, and all other I/O errors
+/// are returned as [`DecodeError::Reader`].
+///
+/// ```no_run
+/// use micropb::{MessageDecode, PbDecoder, StdReader};
+///
+/// # #[derive(Default)]
+/// # struct ProtoMessage;
+/// # impl micropb::MessageDecode for ProtoMessage {
+/// #   fn decode<R: micropb::PbRead>(&mut self, decoder: &mut PbDecoder<R>, len: usize) -> Result<(), micropb::DecodeError<R::Error>> { todo!() }
+/// # }
+/// let file = std::fs::File::open("message.bin")?;
+/// let len = file.metadata()?.len() as usize;
+/// // Decode the message without reading the whole file into memory first
+/// let mut decoder = PbDecoder::new(StdReader::buffered(file));
+/// let mut message = ProtoMessage::default();
+/// message.decode(&mut decoder, len)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
 pub struct StdReader<R>(pub R);
 
+#[cfg(feature = "std")]
+impl<R: std::io::Read> StdReader<std::io::BufReader<R>> {
+    /// Wraps a [`std::io::Read`] in a [`std::io::BufReader`], so that it can be used as a
+    /// [`PbRead`].
+    #[inline]
+    pub fn buffered(reader: R) -> Self {
+        Self(std::io::BufReader::new(reader))
+    }
+}
+
 #[cfg(feature = "std")]
 impl<R: std::io::BufRead> PbRead for StdReader<R> {
     type Error = std::io::Error;
 
     #[inline]
     fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        loop {
+            match self.0.fill_buf() {
+                Ok(_) => break,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        // Filling the buffer again is cheap, since the data is already buffered
         self.0.fill_buf()
     }
 
@@ -209,8 +786,118 @@ pub struct PbDecoder<R: PbRead> {
     /// elements on the wire. The decoder will still report capacity errors when decoding `bytes`
     /// and `string` values that exceed their fixed containers.
     pub ignore_repeated_cap_err: bool,
+    /// Determines what happens when a length-delimited record is decoded without consuming all of
+    /// its bytes. Defaults to [`TrailingBytes::Error`].
+    pub trailing_bytes: TrailingBytes,
+    /// Determines what happens when a `map` entry has a key that's already in the map. Only
+    /// applies to maps decoded with [`insert_map_elem`](Self::insert_map_elem), which includes
+    /// all generated `map` fields. Defaults to [`DuplicateKey::LastWins`].
+    pub duplicate_keys: DuplicateKey,
+    field_offset: usize,
+    assume_utf8: bool,
+    #[cfg(feature = "field-path")]
+    error_path: FieldPath,
+    pub(crate) extras: Extras,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Optional limits and counters of a decoder. Each part is only stored if its feature is enabled,
+/// to keep decoders small.
+///
+/// The buffering decoders carry this between the decoders they create for each field, so that
+/// the limits apply to the whole message and the counters add up.
+pub(crate) struct Extras {
+    #[cfg(feature = "decode-stats")]
+    pub(crate) trailing_bytes_skipped: usize,
+    #[cfg(feature = "decode-stats")]
+    pub(crate) stats: DecodeStats,
+    #[cfg(feature = "decode-limits")]
+    pub(crate) limits: Limits,
+}
+
+impl Extras {
+    /// Zero the counters, keeping the limits
+    pub(crate) fn reset(&mut self) {
+        #[cfg(feature = "decode-stats")]
+        {
+            self.trailing_bytes_skipped = 0;
+            self.stats = DecodeStats::default();
+        }
+        #[cfg(feature = "decode-limits")]
+        {
+            self.limits.depth = 0;
+        }
+    }
+
+    /// Copy of the state for the decoder of a buffered field that starts `offset` bytes into the
+    /// input. The field's decoder counts bytes from the start of the field, so the total byte
+    /// limit is moved back by `offset`.
+    pub(crate) fn for_field(&self, offset: usize) -> Self {
+        #[cfg(feature = "decode-limits")]
+        {
+            let mut extras = *self;
+            extras.limits.max_total_bytes = self
+                .limits
+                .max_total_bytes
+                .map(|max| max.saturating_sub(offset));
+            extras
+        }
+        #[cfg(not(feature = "decode-limits"))]
+        {
+            let _ = offset;
+            *self
+        }
+    }
+
+    /// Take back the counters from the decoder of a buffered field, keeping our own limits.
+    pub(crate) fn update_from_field(&mut self, field: &Self) {
+        #[cfg(feature = "decode-limits")]
+        let max_total_bytes = self.limits.max_total_bytes;
+        *self = *field;
+        #[cfg(feature = "decode-limits")]
+        {
+            self.limits.max_total_bytes = max_total_bytes;
+        }
+    }
 }
 
+#[cfg(feature = "decode-limits")]
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Limits {
+    pub(crate) memory_budget: Option<usize>,
+    pub(crate) should_continue: Option<extern "C" fn() -> bool>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) depth: usize,
+    pub(crate) max_len_field: Option<usize>,
+    pub(crate) max_total_bytes: Option<usize>,
+}
+
+#[cfg(feature = "decode-limits")]
+impl Limits {
+    /// Whether a length-delimited value of `len` bytes, whose payload starts `idx` bytes into the
+    /// input, exceeds the length limits
+    pub(crate) fn exceeds_len(&self, idx: usize, len: usize) -> bool {
+        self.max_len_field.is_some_and(|max| len > max)
+            || self
+                .max_total_bytes
+                .is_some_and(|max| idx.saturating_add(len) > max)
+    }
+
+    /// Whether a field starting `idx` bytes into the input is past the total byte limit
+    pub(crate) fn exceeds_total(&self, idx: usize) -> bool {
+        self.max_total_bytes.is_some_and(|max| idx >= max)
+    }
+}
+
+// Without any optional state, the decoder is its reader, two offsets, and its policy flags
+#[cfg(not(any(
+    feature = "field-path",
+    feature = "decode-stats",
+    feature = "decode-limits"
+)))]
+const _: () =
+    assert!(core::mem::size_of::<PbDecoder<&[u8]>>() <= 5 * core::mem::size_of::<usize>());
+
 impl<R: PbRead> PbDecoder<R> {
     #[inline]
     /// Construct a new decoder from a [`PbRead`].
@@ -219,6 +906,13 @@ impl<R: PbRead> PbDecoder<R> {
             reader,
             idx: 0,
             ignore_repeated_cap_err: false,
+            trailing_bytes: TrailingBytes::Error,
+            duplicate_keys: DuplicateKey::LastWins,
+            field_offset: 0,
+            assume_utf8: false,
+            #[cfg(feature = "field-path")]
+            error_path: FieldPath::default(),
+            extras: Extras::default(),
         }
     }
 
@@ -234,12 +928,371 @@ impl<R: PbRead> PbDecoder<R> {
         &self.reader
     }
 
+    #[inline]
+    /// Replace the underlying reader, returning the previous one.
+    ///
+    /// Only the reader is replaced, so the decoder's settings and counters, including
+    /// [`bytes_read`](Self::bytes_read), carry over to the new reader. Use [`reset`](Self::reset)
+    /// to start decoding a new, unrelated input instead.
+    pub fn set_reader(&mut self, reader: R) -> R {
+        core::mem::replace(&mut self.reader, reader)
+    }
+
+    #[inline]
+    /// Replace the underlying reader and reset the decoder's position, returning the previous
+    /// reader.
+    ///
+    /// This lets a configured decoder be reused for every received frame instead of being
+    /// rebuilt each time. The settings, such as [`trailing_bytes`](Self::trailing_bytes), the
+    /// length and depth limits, and the `should_continue` hook, are kept, while
+    /// [`bytes_read`](Self::bytes_read), the statistics, and the count of skipped trailing bytes
+    /// are zeroed, and the recorded error field path is cleared. The remaining
+    /// [memory budget](Self::charge_memory) isn't restored, so set it again if it applies per
+    /// frame.
+    pub fn reset(&mut self, reader: R) -> R {
+        self.idx = 0;
+        self.field_offset = 0;
+        #[cfg(feature = "field-path")]
+        {
+            self.error_path = FieldPath::default();
+        }
+        self.extras.reset();
+        self.set_reader(reader)
+    }
+
     #[inline]
     /// Get the number of bytes that the decoder has consumed from the reader.
     pub fn bytes_read(&self) -> usize {
         self.idx
     }
 
+    #[inline]
+    /// Get the offset of the most recently decoded tag, which is the start of the innermost field
+    /// being decoded.
+    pub fn field_offset(&self) -> usize {
+        self.field_offset
+    }
+
+    #[inline]
+    /// Annotate an error returned by the decoder with the current [`bytes_read`](Self::bytes_read)
+    /// and [`field_offset`](Self::field_offset), telling where decoding failed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use micropb::{DecodeError, PbDecoder, Presence};
+    ///
+    /// // Length prefix of the second field is larger than the input
+    /// let data = [0x08, 0x01, 0x12, 0x05, b'a'];
+    /// let mut decoder = PbDecoder::new(data.as_slice());
+    /// decoder.decode_tag().unwrap();
+    /// decoder.decode_varint32().unwrap();
+    /// decoder.decode_tag().unwrap();
+    ///
+    /// let mut buf = Vec::new();
+    /// let err = decoder
+    ///     .decode_bytes(&mut buf, Presence::Explicit)
+    ///     .map_err(|e| decoder.error_at(e))
+    ///     .unwrap_err();
+    /// assert_eq!(err.error, DecodeError::UnexpectedEof);
+    /// assert_eq!(err.field_offset, 2);
+    /// ```
+    pub fn error_at(&self, error: DecodeError<R::Error>) -> DecodeErrorAt<R::Error> {
+        DecodeErrorAt {
+            error,
+            offset: self.idx,
+            field_offset: self.field_offset,
+        }
+    }
+
+    #[inline]
+    /// Record that an error occurred while decoding the field with number `num`.
+    ///
+    /// Called by generated messages with the `field_path_errors` option as errors propagate
+    /// outwards, so the calls are made from the innermost field to the outermost one. The
+    /// recorded path is retrieved with `error_ctx`. Does nothing unless the `field-path` feature
+    /// is enabled, since the path is only stored with that feature.
+    pub fn push_error_field(&mut self, num: u32) {
+        #[cfg(feature = "field-path")]
+        self.error_path.push_outer(num);
+        #[cfg(not(feature = "field-path"))]
+        let _ = num;
+    }
+
+    #[cfg(feature = "field-path")]
+    #[inline]
+    /// Annotate an error returned by the decoder with the field path recorded by
+    /// [`push_error_field`](Self::push_error_field), telling which field decoding failed in.
+    ///
+    /// The recorded path is cleared, so this should be called after every failed decode. If the
+    /// error came from a message that doesn't record field paths, the path is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use micropb::{DecodeError, PbDecoder};
+    ///
+    /// let mut decoder = PbDecoder::new([0x00].as_slice());
+    /// // Usually done by generated code
+    /// decoder.push_error_field(1);
+    /// decoder.push_error_field(3);
+    ///
+    /// let err = decoder.error_ctx(DecodeError::ZeroField);
+    /// assert_eq!(err.path.iter().collect::<Vec<_>>(), [3, 1]);
+    /// assert_eq!(err.to_string(), "field number of 0 in field 3.1");
+    /// assert!(decoder.error_ctx(DecodeError::ZeroField).path.is_empty());
+    /// ```
+    pub fn error_ctx(&mut self, error: DecodeError<R::Error>) -> DecodeErrorCtx<R::Error> {
+        DecodeErrorCtx {
+            error,
+            path: core::mem::take(&mut self.error_path),
+        }
+    }
+
+    #[inline]
+    /// Limit the total number of bytes that the decoder stores into containers, or remove the
+    /// limit with `None`.
+    ///
+    /// Capacity limits on individual fields don't stop a message with many medium-sized fields
+    /// from taking up a lot of memory in total. The budget is charged with the length of every
+    /// `string` and `bytes` payload on the wire, and with the inline size of every element of a
+    /// repeated or `map` field. Once the budget is exceeded, decoding fails with
+    /// [`DecodeError::MemoryBudget`]. Payloads are charged before their containers are grown, so a
+    /// large length prefix can't cause an allocation that exceeds the budget.
+    ///
+    /// The budget is shared by all decode calls made with the decoder, so set it again before
+    /// each message to enforce a per-message budget.
+    #[cfg(feature = "decode-limits")]
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+        self.extras.limits.memory_budget = budget;
+    }
+
+    #[inline]
+    /// Get the remaining memory budget, or `None` if the decoder doesn't have a budget.
+    #[cfg(feature = "decode-limits")]
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.extras.limits.memory_budget
+    }
+
+    #[inline]
+    /// Charge `bytes` to the memory budget, returning [`DecodeError::MemoryBudget`] if the budget
+    /// is exceeded. Does nothing if the decoder doesn't have a budget, which is always the case
+    /// without the `decode-limits` feature.
+    pub fn charge_memory(&mut self, bytes: usize) -> Result<(), DecodeError<R::Error>> {
+        #[cfg(feature = "decode-limits")]
+        if let Some(budget) = &mut self.extras.limits.memory_budget {
+            *budget = budget.checked_sub(bytes).ok_or(DecodeError::MemoryBudget)?;
+        }
+        #[cfg(not(feature = "decode-limits"))]
+        let _ = bytes;
+        Ok(())
+    }
+
+    #[inline]
+    /// Limit the length of every length-delimited value on the wire, such as `string`, `bytes`,
+    /// message, and packed fields, or remove the limit with `None`.
+    ///
+    /// A length prefix that exceeds the limit returns [`DecodeError::LenLimit`] as soon as it's
+    /// read, before any container is grown or any of the value is consumed. This stops hostile
+    /// input from triggering huge allocations with a single large length prefix.
+    #[cfg(feature = "decode-limits")]
+    pub fn set_max_len_field(&mut self, max_len: Option<usize>) {
+        self.extras.limits.max_len_field = max_len;
+    }
+
+    #[inline]
+    /// Get the maximum length of length-delimited values, or `None` if it isn't limited.
+    #[cfg(feature = "decode-limits")]
+    pub fn max_len_field(&self) -> Option<usize> {
+        self.extras.limits.max_len_field
+    }
+
+    #[inline]
+    /// Limit the total number of bytes the decoder consumes from the reader, as reported by
+    /// [`bytes_read`](Self::bytes_read), or remove the limit with `None`.
+    ///
+    /// The limit is checked before each field is decoded and whenever a length prefix is read, so
+    /// decoding fails with [`DecodeError::LenLimit`] as soon as the input is known to exceed it.
+    /// A length-delimited value that would end past the limit fails before its payload is
+    /// consumed, so only the tag and scalar value of the last field can be read beyond the limit.
+    #[cfg(feature = "decode-limits")]
+    pub fn set_max_total_bytes(&mut self, max_bytes: Option<usize>) {
+        self.extras.limits.max_total_bytes = max_bytes;
+    }
+
+    #[inline]
+    /// Get the limit on the total number of bytes consumed, or `None` if it isn't limited.
+    #[cfg(feature = "decode-limits")]
+    pub fn max_total_bytes(&self) -> Option<usize> {
+        self.extras.limits.max_total_bytes
+    }
+
+    #[inline]
+    /// Check `len` bytes of an upcoming length-delimited value against the length limits.
+    fn check_len(&self, len: usize) -> Result<(), DecodeError<R::Error>> {
+        #[cfg(feature = "decode-limits")]
+        if self.extras.limits.exceeds_len(self.idx, len) {
+            return Err(DecodeError::LenLimit);
+        }
+        #[cfg(not(feature = "decode-limits"))]
+        let _ = len;
+        Ok(())
+    }
+
+    #[inline]
+    /// Decode the length prefix of a length-delimited value and check it against the length
+    /// limits.
+    fn decode_len(&mut self) -> Result<usize, DecodeError<R::Error>> {
+        let len = self.decode_varint32()? as usize;
+        self.check_len(len)?;
+        Ok(len)
+    }
+
+    #[inline]
+    /// Limit how deeply length-delimited messages can be nested, or remove the limit with `None`.
+    ///
+    /// Each message decoded as a length-delimited record, including a top-level message decoded
+    /// with [`MessageDecode::decode_len_delimited`], is one level deeper than the message containing
+    /// it. Decoding a message beyond the maximum depth returns [`DecodeError::DepthLimit`] before
+    /// any of its fields are decoded. Since submessages are decoded recursively, this bounds the
+    /// stack usage of maliciously nested payloads.
+    #[cfg(feature = "decode-limits")]
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.extras.limits.max_depth = max_depth;
+    }
+
+    #[inline]
+    /// Get the maximum nesting depth, or `None` if the depth isn't limited.
+    #[cfg(feature = "decode-limits")]
+    pub fn max_depth(&self) -> Option<usize> {
+        self.extras.limits.max_depth
+    }
+
+    #[inline]
+    /// Set a hook that is called before each field and each packed element is decoded, or remove
+    /// the hook with `None`.
+    ///
+    /// If the hook returns `false`, decoding is aborted with [`DecodeError::Cancelled`]. This
+    /// allows long decodes from slow readers to be stopped by a watchdog or cancellation flag,
+    /// such as a static `AtomicBool`, instead of blocking indefinitely. The hook can't interrupt a
+    /// single read from the reader, so readers that can block forever should have their own
+    /// timeouts.
+    ///
+    /// The hook is an `extern "C"` function, which isn't allowed to unwind, so calling it doesn't
+    /// add a panic path to the decoder. This keeps decoders with hooks usable under
+    /// [`no_panic`](https://docs.rs/no-panic).
+    ///
+    /// # Panics
+    ///
+    /// The hook must never panic. From Rust 1.81, a panic escaping an `extern "C"` function aborts
+    /// the program, but on older compilers, down to the minimum supported version of 1.74, it's
+    /// undefined behavior.
+    #[cfg(feature = "decode-limits")]
+    pub fn set_should_continue(&mut self, hook: Option<extern "C" fn() -> bool>) {
+        self.extras.limits.should_continue = hook;
+    }
+
+    #[inline]
+    /// Call the `should_continue` hook, returning [`DecodeError::Cancelled`] if it returns
+    /// `false`. Does nothing if the decoder doesn't have a hook, which is always the case without
+    /// the `decode-limits` feature.
+    ///
+    /// Custom field decoders that loop over many values should call this between values.
+    pub fn check_continue(&self) -> Result<(), DecodeError<R::Error>> {
+        #[cfg(feature = "decode-limits")]
+        if let Some(hook) = self.extras.limits.should_continue {
+            if !hook() {
+                return Err(DecodeError::Cancelled);
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    /// Skip UTF-8 validation of decoded `string` values if `assume` is `true`.
+    ///
+    /// Validation can be a noticeable part of decoding string-heavy messages, such as log
+    /// records. If the input comes from a trusted source that only produces valid UTF-8, such as
+    /// firmware encoding its own messages, the bytes can be written into string containers
+    /// directly. Strings decoded
+    /// with [`decode_string_truncated`](Self::decode_string_truncated) are still validated, since
+    /// truncation needs to find character boundaries. In debug builds, the strings are still
+    /// checked with a debug assertion.
+    ///
+    /// # Safety
+    ///
+    /// While this is set, every `string` value decoded by this decoder must be valid UTF-8.
+    /// Otherwise, string containers that assume their contents are UTF-8, such as `String`, will
+    /// contain invalid data, which is undefined behaviour.
+    pub unsafe fn set_assume_utf8(&mut self, assume: bool) {
+        self.assume_utf8 = assume;
+    }
+
+    #[inline]
+    /// Returns `true` if UTF-8 validation of decoded strings is
+    /// [skipped](Self::set_assume_utf8).
+    pub fn assume_utf8(&self) -> bool {
+        self.assume_utf8
+    }
+
+    #[cfg(feature = "strings")]
+    #[inline]
+    /// Validate that a decoded string is UTF-8, unless validation is skipped.
+    fn check_utf8(&self, bytes: &[u8]) -> Result<(), DecodeError<R::Error>> {
+        if self.assume_utf8 {
+            pb_debug_assert!(from_utf8(bytes).is_ok());
+        } else {
+            from_utf8(bytes)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    /// Get the total number of trailing bytes skipped under [`TrailingBytes::Report`].
+    #[cfg(feature = "decode-stats")]
+    pub fn trailing_bytes_skipped(&self) -> usize {
+        self.extras.trailing_bytes_skipped
+    }
+
+    #[inline]
+    /// Get the counts of data discarded by lossy decoding since the decoder was created or since
+    /// the last call to [`take_stats`](Self::take_stats).
+    #[cfg(feature = "decode-stats")]
+    pub fn stats(&self) -> DecodeStats {
+        self.extras.stats
+    }
+
+    #[inline]
+    /// Get the counts of data discarded by lossy decoding and reset them to zero.
+    ///
+    /// Calling this after each decode call gives per-message counts that can be reported as
+    /// data-loss telemetry.
+    #[cfg(feature = "decode-stats")]
+    pub fn take_stats(&mut self) -> DecodeStats {
+        core::mem::take(&mut self.extras.stats)
+    }
+
+    #[inline]
+    /// Update the statistics, which are only stored with the `decode-stats` feature.
+    fn count(&mut self, _update: impl FnOnce(&mut DecodeStats)) {
+        #[cfg(feature = "decode-stats")]
+        _update(&mut self.extras.stats);
+    }
+
+    #[inline]
+    /// Handle an element of a repeated or `map` field that doesn't fit into its container.
+    ///
+    /// If `ignore_repeated_cap_err` is set, the element is counted as dropped in
+    /// [`DecodeStats::dropped_elements`]. Otherwise, return [`DecodeError::Capacity`].
+    pub fn handle_repeated_overflow(&mut self) -> Result<(), DecodeError<R::Error>> {
+        if self.ignore_repeated_cap_err {
+            self.count(|stats| stats.dropped_elements += 1);
+            Ok(())
+        } else {
+            Err(DecodeError::Capacity)
+        }
+    }
+
     #[inline]
     fn advance(&mut self, bytes: usize) {
         self.reader.pb_advance(bytes);
@@ -280,9 +1333,14 @@ impl<R: PbRead> PbDecoder<R> {
         Err(DecodeError::VarIntLimit)
     }
 
+    #[inline]
     #[cfg(feature = "enable-64bit")]
     /// Decode an `uint64`.
     pub fn decode_varint64(&mut self) -> Result<u64, DecodeError<R::Error>> {
+        self.read_varint64()
+    }
+
+    fn read_varint64(&mut self) -> Result<u64, DecodeError<R::Error>> {
         let b = self.get_byte()?;
         // Single byte case
         if b & 0x80 == 0 {
@@ -319,16 +1377,14 @@ impl<R: PbRead> PbDecoder<R> {
     #[inline]
     /// Decode an `sint32`.
     pub fn decode_sint32(&mut self) -> Result<i32, DecodeError<R::Error>> {
-        self.decode_varint32()
-            .map(|u| ((u >> 1) as i32) ^ -((u & 1) as i32))
+        self.decode_varint32().map(wire::zigzag_decode32)
     }
 
     #[inline]
     #[cfg(feature = "enable-64bit")]
     /// Decode an `sint64`.
     pub fn decode_sint64(&mut self) -> Result<i64, DecodeError<R::Error>> {
-        self.decode_varint64()
-            .map(|u| ((u >> 1) as i64) ^ -((u & 1) as i64))
+        self.decode_varint64().map(wire::zigzag_decode64)
     }
 
     #[inline]
@@ -353,6 +1409,15 @@ impl<R: PbRead> PbDecoder<R> {
     #[inline]
     /// Decode a `fixed32`.
     pub fn decode_fixed32(&mut self) -> Result<u32, DecodeError<R::Error>> {
+        // Fast path for when the whole value is in the current chunk
+        let chunk = self.reader.pb_read_chunk().map_err(DecodeError::Reader)?;
+        if chunk.len() >= 4 {
+            // SAFETY: chunk has at least 4 bytes
+            let n = unsafe { load_le_u32(chunk.as_ptr()) };
+            self.advance(4);
+            return Ok(n);
+        }
+
         let mut data = [MaybeUninit::uninit(); 4];
         self.read_exact(&mut data)?;
         // SAFETY: read_exact is guaranteed to write to the whole buffer
@@ -364,6 +1429,20 @@ impl<R: PbRead> PbDecoder<R> {
     #[cfg(feature = "enable-64bit")]
     /// Decode a `fixed64`.
     pub fn decode_fixed64(&mut self) -> Result<u64, DecodeError<R::Error>> {
+        self.read_fixed64()
+    }
+
+    #[inline(always)]
+    fn read_fixed64(&mut self) -> Result<u64, DecodeError<R::Error>> {
+        // Fast path for when the whole value is in the current chunk
+        let chunk = self.reader.pb_read_chunk().map_err(DecodeError::Reader)?;
+        if chunk.len() >= 8 {
+            // SAFETY: chunk has at least 8 bytes
+            let n = unsafe { load_le_u64(chunk.as_ptr()) };
+            self.advance(8);
+            return Ok(n);
+        }
+
         let mut data = [MaybeUninit::uninit(); 8];
         self.read_exact(&mut data)?;
         // SAFETY: read_exact is guaranteed to write to the whole buffer
@@ -408,23 +1487,32 @@ impl<R: PbRead> PbDecoder<R> {
 
     #[inline]
     /// Decode a `float`.
+    ///
+    /// The bit pattern is preserved exactly, including NaN payloads, signed zeros, and subnormals.
     pub fn decode_float(&mut self) -> Result<f32, DecodeError<R::Error>> {
         self.decode_fixed32().map(f32::from_bits)
     }
 
     #[inline]
     /// Decode a `double`.
+    ///
+    /// The bit pattern is preserved exactly, including NaN payloads, signed zeros, and subnormals.
     pub fn decode_double(&mut self) -> Result<f64, DecodeError<R::Error>> {
-        let mut data = [MaybeUninit::uninit(); 8];
-        self.read_exact(&mut data)?;
-        // SAFETY: read_exact is guaranteed to write to the whole buffer
-        let data = unsafe { maybe_ununit_array_assume_init(data) };
-        Ok(f64::from_le_bytes(data))
+        self.read_fixed64().map(f64::from_bits)
     }
 
     #[inline(always)]
     /// Decode a Protobuf tag.
+    ///
+    /// Calls the [`should_continue`](Self::check_continue) hook first, since a tag marks the
+    /// start of a new field.
     pub fn decode_tag(&mut self) -> Result<Tag, DecodeError<R::Error>> {
+        self.check_continue()?;
+        #[cfg(feature = "decode-limits")]
+        if self.extras.limits.exceeds_total(self.idx) {
+            return Err(DecodeError::LenLimit);
+        }
+        self.field_offset = self.idx;
         self.decode_varint32().map(Tag)
     }
 
@@ -434,15 +1522,13 @@ impl<R: PbRead> PbDecoder<R> {
         buf: &'a mut [MaybeUninit<u8>],
         len: usize,
     ) -> Result<&'a [u8], DecodeError<R::Error>> {
-        if buf.len() < len {
-            return Err(DecodeError::Capacity);
-        }
-        let target = &mut buf[..len];
+        let target = buf.get_mut(..len).ok_or(DecodeError::Capacity)?;
         self.read_exact(target)?;
         // SAFETY: read_exact guarantees that all bytes of target have been initialized
         Ok(unsafe { maybe_uninit_slice_assume_init_ref(target) })
     }
 
+    #[cfg(feature = "strings")]
     /// Decode a `string` into a [`PbString`] container.
     ///
     /// The string container's existing contents will be replaced by the string decoded from the
@@ -459,25 +1545,75 @@ impl<R: PbRead> PbDecoder<R> {
         string: &mut S,
         presence: Presence,
     ) -> Result<(), DecodeError<R::Error>> {
-        let len = self.decode_varint32()? as usize;
+        let len = self.decode_len()?;
         // With implicit presence, ignore empty strings
         if len == 0 && presence == Presence::Implicit {
             return Ok(());
         }
 
+        self.charge_memory(len)?;
         string.pb_clear();
-        string.pb_reserve(len);
+        string
+            .pb_reserve_hint(len)
+            .map_err(|_| DecodeError::Capacity)?;
         let spare_cap = string.pb_spare_cap();
         let written = self.read_into_buf(spare_cap, len)?;
 
         // Check UTF8 validity
-        from_utf8(written)?;
+        self.check_utf8(written)?;
         // SAFETY: read_into_buf guarantees that `len` bytes have been written into the string.
-        // Also, we just checked the UTF-8 validity of the written bytes, so the string is valid.
+        // Also, we just checked the UTF-8 validity of the written bytes, or the caller of
+        // set_assume_utf8 guaranteed it, so the string is valid.
         unsafe { string.pb_set_len(len) };
         Ok(())
     }
 
+    #[cfg(feature = "strings")]
+    /// Decode a `string` into a [`PbString`] container, truncating it if it exceeds the capacity
+    /// of the container.
+    ///
+    /// Behaves like [`decode_string`](Self::decode_string), except that strings that are too long
+    /// are cut off after the last whole character that fits instead of returning
+    /// [`DecodeError::Capacity`]. The truncated bytes are skipped without being checked for
+    /// UTF-8 validity.
+    ///
+    /// # Errors
+    ///
+    /// If the part of the string that fits in the container is not UTF-8, return
+    /// [`DecodeError::Utf8`].
+    pub fn decode_string_truncated<S: PbString>(
+        &mut self,
+        string: &mut S,
+        presence: Presence,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let len = self.decode_len()?;
+        // With implicit presence, ignore empty strings
+        if len == 0 && presence == Presence::Implicit {
+            return Ok(());
+        }
+
+        self.charge_memory(len)?;
+        string.pb_clear();
+        string.pb_reserve(len);
+        let spare_cap = string.pb_spare_cap();
+        let n = len.min(spare_cap.len());
+        let written = self.read_into_buf(spare_cap, n)?;
+
+        let valid_len = match from_utf8(written) {
+            Ok(_) => n,
+            // Invalid bytes at the end could be a character that was cut off by the truncation
+            Err(e) if n < len && e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(e.into()),
+        };
+        // SAFETY: read_into_buf guarantees that `n` bytes have been written into the string, and
+        // the first `valid_len` bytes were just checked to be valid UTF-8.
+        unsafe { string.pb_set_len(valid_len) };
+        if valid_len < len {
+            self.count(|stats| stats.truncated_strings += 1);
+        }
+        self.skip_bytes(len - n)
+    }
+
     /// Decode a `bytes` into a [`PbVec<u8>`](crate::PbVec<u8>) container.
     ///
     /// The byte container's existing contents will be replaced by the bytes decoded from the
@@ -493,14 +1629,17 @@ impl<R: PbRead> PbDecoder<R> {
         bytes: &mut S,
         presence: Presence,
     ) -> Result<(), DecodeError<R::Error>> {
-        let len = self.decode_varint32()? as usize;
+        let len = self.decode_len()?;
         // With implicit presence, ignore empty strings
         if len == 0 && presence == Presence::Implicit {
             return Ok(());
         }
 
+        self.charge_memory(len)?;
         bytes.pb_clear();
-        bytes.pb_reserve(len);
+        bytes
+            .pb_reserve_hint(len)
+            .map_err(|_| DecodeError::Capacity)?;
         let spare_cap = bytes.pb_spare_cap();
         self.read_into_buf(spare_cap, len)?;
         // SAFETY: read_into_buf guarantees that `len` bytes have been written into the buffer
@@ -508,6 +1647,34 @@ impl<R: PbRead> PbDecoder<R> {
         Ok(())
     }
 
+    /// Decode a length-delimited message with `decoder`, one nesting level deeper than the current
+    /// message.
+    pub(crate) fn decode_nested<
+        F: FnOnce(usize, &mut Self) -> Result<(), DecodeError<R::Error>>,
+    >(
+        &mut self,
+        decoder: F,
+    ) -> Result<(), DecodeError<R::Error>> {
+        #[cfg(feature = "decode-limits")]
+        {
+            if self
+                .extras
+                .limits
+                .max_depth
+                .is_some_and(|max| self.extras.limits.depth >= max)
+            {
+                return Err(DecodeError::DepthLimit);
+            }
+            self.extras.limits.depth += 1;
+        }
+        let res = self.decode_len_record(|len, _, this| decoder(len, this));
+        #[cfg(feature = "decode-limits")]
+        {
+            self.extras.limits.depth -= 1;
+        }
+        res
+    }
+
     pub(crate) fn decode_len_record<
         T,
         F: FnOnce(usize, usize, &mut Self) -> Result<T, DecodeError<R::Error>>,
@@ -515,14 +1682,23 @@ impl<R: PbRead> PbDecoder<R> {
         &mut self,
         decoder: F,
     ) -> Result<T, DecodeError<R::Error>> {
-        let len = self.decode_varint32()? as usize;
+        let len = self.decode_len()?;
         let before = self.bytes_read();
         let val = decoder(len, before, self)?;
         let actual_len = self.bytes_read() - before;
-        if actual_len != len {
-            Err(DecodeError::WrongLen)
-        } else {
-            Ok(val)
+        match (actual_len.cmp(&len), self.trailing_bytes) {
+            (Ordering::Equal, _) => Ok(val),
+            (Ordering::Less, TrailingBytes::Skip) => {
+                self.skip_bytes(len - actual_len)?;
+                Ok(val)
+            }
+            #[cfg(feature = "decode-stats")]
+            (Ordering::Less, TrailingBytes::Report) => {
+                self.skip_bytes(len - actual_len)?;
+                self.extras.trailing_bytes_skipped += len - actual_len;
+                Ok(val)
+            }
+            _ => Err(DecodeError::WrongLen),
         }
     }
 
@@ -538,15 +1714,197 @@ impl<R: PbRead> PbDecoder<R> {
     >(
         &mut self,
         vec: &mut S,
-        decoder: F,
+        decoder: F,
+    ) -> Result<(), DecodeError<R::Error>> {
+        self.decode_len_record(|len, before, this| {
+            while this.bytes_read() - before < len {
+                this.check_continue()?;
+                let val = decoder(this)?;
+                this.push_elem(vec, val)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Decode a repeated packed field whose elements all take up `elem_len` bytes on the wire,
+    /// such as `fixed32` or `float`, and append the elements to a [`PbVec`] container.
+    ///
+    /// Since the number of elements is known from the length prefix, space for all of them is
+    /// reserved upfront with [`pb_reserve_hint`](PbVec::pb_reserve_hint), and they're charged to
+    /// the [memory budget](Self::charge_memory) at once. If they don't fit into the
+    /// container, return [`DecodeError::Capacity`] before decoding any of them, unless the
+    /// `ignore_repeated_cap_err` flag is set. If the length isn't a multiple of `elem_len`, return
+    /// [`DecodeError::WrongLen`].
+    pub fn decode_packed_fixed<
+        T: Copy,
+        S: PbVec<T>,
+        F: Fn(&mut Self) -> Result<T, DecodeError<R::Error>>,
+    >(
+        &mut self,
+        vec: &mut S,
+        elem_len: usize,
+        decoder: F,
+    ) -> Result<(), DecodeError<R::Error>> {
+        self.decode_len_record(|len, before, this| {
+            if elem_len == 0 || len % elem_len != 0 {
+                return Err(DecodeError::WrongLen);
+            }
+            let count = len / elem_len;
+            this.charge_memory(count * core::mem::size_of::<T>())?;
+            if vec.pb_reserve_hint(count).is_err() && !this.ignore_repeated_cap_err {
+                return Err(DecodeError::Capacity);
+            }
+            while this.bytes_read() - before < len {
+                this.check_continue()?;
+                let val = decoder(this)?;
+                if vec.pb_push(val).is_err() {
+                    this.handle_repeated_overflow()?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Append an element of a repeated field to a [`PbVec`] container.
+    ///
+    /// The element is charged to the [memory budget](Self::charge_memory). If the container
+    /// is full, the element is handled by
+    /// [`handle_repeated_overflow`](Self::handle_repeated_overflow).
+    pub fn push_elem<T, S: PbVec<T>>(
+        &mut self,
+        vec: &mut S,
+        elem: T,
+    ) -> Result<(), DecodeError<R::Error>> {
+        self.charge_memory(core::mem::size_of::<T>())?;
+        if vec.pb_push(elem).is_err() {
+            self.handle_repeated_overflow()?;
+        }
+        Ok(())
+    }
+
+    /// Append an element of a repeated field to a [`PbVec`] container, handling a full container
+    /// according to `overflow`.
+    ///
+    /// With [`RepeatedOverflow::DropOldest`], a full container is used as a ring buffer. The new
+    /// element overwrites the oldest element, which is at index `oldest`, and `oldest` moves on to
+    /// the next element. `oldest` must start out as 0, and once all elements of the field have been
+    /// pushed, [`finish_repeated`](Self::finish_repeated) puts the elements back in order.
+    pub fn push_repeated<T, S: PbVec<T> + DerefMut<Target = [T]>>(
+        &mut self,
+        vec: &mut S,
+        elem: T,
+        overflow: RepeatedOverflow,
+        oldest: &mut usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        match overflow {
+            RepeatedOverflow::Error => self.push_elem(vec, elem)?,
+            RepeatedOverflow::DropNewest => {
+                self.charge_memory(core::mem::size_of::<T>())?;
+                if vec.pb_push(elem).is_err() {
+                    self.count(|stats| stats.dropped_elements += 1);
+                }
+            }
+            RepeatedOverflow::DropOldest => {
+                self.charge_memory(core::mem::size_of::<T>())?;
+                // Dynamic containers will always have spare capacity after reserving
+                vec.pb_reserve(1);
+                if !vec.pb_spare_cap().is_empty() {
+                    let _ = vec.pb_push(elem);
+                } else {
+                    if let Some(slot) = vec.get_mut(*oldest) {
+                        *slot = elem;
+                        *oldest = (*oldest + 1) % vec.len();
+                    }
+                    self.count(|stats| stats.dropped_elements += 1);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Put the elements of a repeated field appended by [`push_repeated`](Self::push_repeated) back
+    /// in order, by rotating the oldest element to the front. `oldest` is reset to 0.
+    ///
+    /// This is a single rotation of the container no matter how many elements were dropped.
+    pub fn finish_repeated<T, S: DerefMut<Target = [T]>>(
+        &mut self,
+        vec: &mut S,
+        oldest: &mut usize,
+    ) {
+        vec.rotate_left(*oldest);
+        *oldest = 0;
+    }
+
+    /// Decode a repeated packed field and append the elements to a [`PbVec`] container, handling
+    /// a full container according to `overflow`.
+    ///
+    /// Same as [`decode_packed`](Self::decode_packed), except that elements that overflow the
+    /// container are handled like in [`push_repeated`](Self::push_repeated). The elements are put
+    /// back in order at the end of the field, even if decoding fails.
+    pub fn decode_packed_with_overflow<
+        T: Copy,
+        S: PbVec<T> + DerefMut<Target = [T]>,
+        F: Fn(&mut Self) -> Result<T, DecodeError<R::Error>>,
+    >(
+        &mut self,
+        vec: &mut S,
+        overflow: RepeatedOverflow,
+        oldest: &mut usize,
+        decoder: F,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let res = self.decode_len_record(|len, before, this| {
+            while this.bytes_read() - before < len {
+                this.check_continue()?;
+                let val = decoder(this)?;
+                this.push_repeated(vec, val, overflow, oldest)?;
+            }
+            Ok(())
+        });
+        self.finish_repeated(vec, oldest);
+        res
+    }
+
+    #[inline]
+    /// Decode an enum value of type `E`, which may be unknown.
+    pub fn decode_enum<E: PbEnum>(&mut self) -> Result<E, DecodeError<R::Error>> {
+        self.decode_int32().map(E::from_i32)
+    }
+
+    /// Decode a single element of a repeated enum field and append it to a [`PbVec`] container,
+    /// handling values that aren't known to `E` according to `unknown`.
+    pub fn decode_enum_elem<E: PbEnum, S: PbVec<E>>(
+        &mut self,
+        vec: &mut S,
+        unknown: UnknownEnum,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let val: E = self.decode_enum()?;
+        if val.is_known() {
+            return self.push_elem(vec, val);
+        }
+        match unknown {
+            UnknownEnum::Keep => self.push_elem(vec, val),
+            UnknownEnum::Drop => {
+                self.count(|stats| stats.unknown_enums += 1);
+                Ok(())
+            }
+            UnknownEnum::Error => Err(DecodeError::UnknownEnum),
+        }
+    }
+
+    /// Decode a packed repeated enum field and append the elements to a [`PbVec`] container,
+    /// handling values that aren't known to `E` according to `unknown`.
+    ///
+    /// Same as [`decode_packed`](Self::decode_packed) with [`decode_enum`](Self::decode_enum) as
+    /// the element decoder, except for the handling of unknown values.
+    pub fn decode_packed_enum<E: PbEnum, S: PbVec<E>>(
+        &mut self,
+        vec: &mut S,
+        unknown: UnknownEnum,
     ) -> Result<(), DecodeError<R::Error>> {
-        let ignore_repeated_cap_err = self.ignore_repeated_cap_err;
         self.decode_len_record(|len, before, this| {
             while this.bytes_read() - before < len {
-                let val = decoder(this)?;
-                if let (Err(_), false) = (vec.pb_push(val), ignore_repeated_cap_err) {
-                    return Err(DecodeError::Capacity);
-                }
+                this.check_continue()?;
+                this.decode_enum_elem(vec, unknown)?;
             }
             Ok(())
         })
@@ -587,6 +1945,7 @@ impl<R: PbRead> PbDecoder<R> {
     //Ok(())
     //}
 
+    #[cfg(feature = "maps")]
     /// Decode a Protobuf map key-value pair from the decoder.
     ///
     /// According the the Protobuf spec, the key-value pair is formatted as a Protobuf message with
@@ -625,6 +1984,32 @@ impl<R: PbRead> PbDecoder<R> {
         }
     }
 
+    #[cfg(feature = "maps")]
+    /// Insert a `map` entry decoded by [`decode_map_elem`](Self::decode_map_elem) into `map`.
+    ///
+    /// If the key is already in the map, the entry is handled according to the decoder's
+    /// [`duplicate_keys`](Self::duplicate_keys) policy. The memory of the entry is charged to the
+    /// [memory budget](Self::charge_memory), and entries that don't fit into a fixed-capacity
+    /// map are handled like excess elements of repeated fields.
+    pub fn insert_map_elem<K: PartialEq, V, M: crate::container::PbMap<K, V>>(
+        &mut self,
+        map: &mut M,
+        key: K,
+        val: V,
+    ) -> Result<(), DecodeError<R::Error>> {
+        if self.duplicate_keys != DuplicateKey::LastWins && map.pb_contains_key(&key) {
+            if self.duplicate_keys == DuplicateKey::Error {
+                return Err(DecodeError::DuplicateKey);
+            }
+            return Ok(());
+        }
+        self.charge_memory(core::mem::size_of_val(&key) + core::mem::size_of_val(&val))?;
+        if map.pb_insert(key, val).is_err() {
+            self.handle_repeated_overflow()?;
+        }
+        Ok(())
+    }
+
     fn skip_varint(&mut self) -> Result<(), DecodeError<R::Error>> {
         for _ in 0..10 {
             let b = self.get_byte()?;
@@ -650,7 +2035,7 @@ impl<R: PbRead> PbDecoder<R> {
             self.advance(n);
             total += n;
         }
-        debug_assert_eq!(total, bytes);
+        pb_debug_assert!(total == bytes);
         Ok(())
     }
 
@@ -659,16 +2044,84 @@ impl<R: PbRead> PbDecoder<R> {
     /// The type of the Protobuf payload is determined by `wire_type`, which must be a valid
     /// Protobuf wire type. This is mainly used to skip unknown fields.
     pub fn skip_wire_value(&mut self, wire_type: u8) -> Result<(), DecodeError<R::Error>> {
+        let wire_type = WireType::from_u8(wire_type).ok_or(DecodeError::UnknownWireType)?;
+        self.skip_value(wire_type)
+    }
+
+    /// Skip the next Protobuf value/payload on the wire, with the type of payload determined by a
+    /// typed [`WireType`].
+    ///
+    /// Same as [`skip_wire_value`](Self::skip_wire_value), except that invalid wire types are
+    /// ruled out by the caller.
+    pub fn skip_value(&mut self, wire_type: WireType) -> Result<(), DecodeError<R::Error>> {
         match wire_type {
-            WIRE_TYPE_VARINT => self.skip_varint()?,
-            WIRE_TYPE_I64 => self.skip_bytes(8)?,
-            WIRE_TYPE_LEN => {
-                let len = self.decode_varint32()? as usize;
+            WireType::Varint => self.skip_varint()?,
+            WireType::I64 => self.skip_bytes(8)?,
+            WireType::Len => {
+                let len = self.decode_len()?;
                 self.skip_bytes(len)?;
             }
-            3 | 4 => return Err(DecodeError::Deprecation),
-            WIRE_TYPE_I32 => self.skip_bytes(4)?,
-            _ => return Err(DecodeError::UnknownWireType),
+            WireType::StartGroup | WireType::EndGroup => return Err(DecodeError::Deprecation),
+            WireType::I32 => self.skip_bytes(4)?,
+        }
+        Ok(())
+    }
+
+    /// Consume `bytes` from the reader, passing them to `sink` one chunk at a time.
+    pub(crate) fn read_raw_bytes<F: FnMut(&[u8])>(
+        &mut self,
+        bytes: usize,
+        mut sink: F,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let mut total = 0;
+        while total < bytes {
+            let chunk = self.reader.pb_read_chunk().map_err(DecodeError::Reader)?;
+            if chunk.is_empty() {
+                return Err(DecodeError::UnexpectedEof);
+            }
+            let n = chunk.len().min(bytes - total);
+            sink(chunk.get(..n).unwrap_or_default());
+            self.advance(n);
+            total += n;
+        }
+        Ok(())
+    }
+
+    /// Consume the next Protobuf value on the wire without decoding it, passing its raw bytes to
+    /// `sink` one chunk at a time. Length prefixes are passed along exactly as they appear on the
+    /// wire.
+    pub(crate) fn read_raw_value<F: FnMut(&[u8])>(
+        &mut self,
+        wire_type: u8,
+        mut sink: F,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let wire_type = WireType::from_u8(wire_type).ok_or(DecodeError::UnknownWireType)?;
+        match wire_type {
+            WireType::Varint | WireType::Len => {
+                let mut len: u32 = 0;
+                let mut done = false;
+                for i in 0..10 {
+                    let b = self.get_byte()?;
+                    sink(&[b]);
+                    if i < 5 {
+                        len |= ((b & !0x80) as u32) << (i * 7);
+                    }
+                    if b & 0x80 == 0 {
+                        done = true;
+                        break;
+                    }
+                }
+                if !done {
+                    return Err(DecodeError::VarIntLimit);
+                }
+                if wire_type == WireType::Len {
+                    self.check_len(len as usize)?;
+                    self.read_raw_bytes(len as usize, sink)?;
+                }
+            }
+            WireType::I64 => self.read_raw_bytes(8, sink)?,
+            WireType::StartGroup | WireType::EndGroup => return Err(DecodeError::Deprecation),
+            WireType::I32 => self.read_raw_bytes(4, sink)?,
         }
         Ok(())
     }
@@ -680,14 +2133,342 @@ impl<R: PbRead> PbDecoder<R> {
     ) -> Result<M, DecodeError<R::Error>> {
         let mut msg = M::default();
         msg.decode(self, len)?;
+        msg.check_required()?;
+        Ok(msg)
+    }
+
+    /// Decode a new length-delimited message from the wire, starting with its length prefix.
+    ///
+    /// Exactly as many bytes as the prefix specifies are decoded. If the message reads past the end
+    /// of its record, [`DecodeError::WrongLen`] is returned. If it stops before the end, the
+    /// remaining bytes are handled according to [`trailing_bytes`](Self::trailing_bytes).
+    ///
+    /// ```
+    /// use core::time::Duration;
+    /// use micropb::PbDecoder;
+    ///
+    /// let bytes = [0x02, 0x08, 0x05, 0x02, 0x10, 0x07];
+    /// let mut decoder = PbDecoder::new(bytes.as_slice());
+    /// let first: Duration = decoder.decode_message_delimited().unwrap();
+    /// let second: Duration = decoder.decode_message_delimited().unwrap();
+    /// assert_eq!(first, Duration::from_secs(5));
+    /// assert_eq!(second, Duration::from_nanos(7));
+    /// ```
+    pub fn decode_message_delimited<M: MessageDecode + Default>(
+        &mut self,
+    ) -> Result<M, DecodeError<R::Error>> {
+        let mut msg = M::default();
+        msg.decode_len_delimited(self)?;
         Ok(msg)
     }
+
+    /// Decode `len` bytes of a scalar field with field number `field_num`, without an enclosing
+    /// message.
+    ///
+    /// This is the counterpart of `PbEncoder::encode_scalar_field`. The `decoder` callback
+    /// determines how the value is decoded from the wire. Fields with other field numbers are
+    /// skipped. If the field appears multiple times, the last value is returned, and if it doesn't
+    /// appear at all, `None` is returned.
+    ///
+    /// ```
+    /// use micropb::PbDecoder;
+    ///
+    /// let bytes = [0x08, 0x96, 0x01];
+    /// let mut decoder = PbDecoder::new(bytes.as_slice());
+    /// let val = decoder
+    ///     .decode_scalar_field(1, bytes.len(), PbDecoder::decode_varint32)
+    ///     .unwrap();
+    /// assert_eq!(val, Some(150));
+    /// ```
+    pub fn decode_scalar_field<T, F: FnMut(&mut Self) -> Result<T, DecodeError<R::Error>>>(
+        &mut self,
+        field_num: u32,
+        len: usize,
+        mut decoder: F,
+    ) -> Result<Option<T>, DecodeError<R::Error>> {
+        let mut val = None;
+        let before = self.bytes_read();
+        while self.bytes_read() - before < len {
+            let tag = self.decode_tag()?;
+            match tag.field_num() {
+                0 => return Err(DecodeError::ZeroField),
+                n if n == field_num => val = Some(decoder(self)?),
+                _ => self.skip_wire_value(tag.wire_type())?,
+            }
+        }
+        Ok(val)
+    }
+
+    /// Decode `len` bytes of a repeated scalar field with field number `field_num`, appending each
+    /// element to `vec`.
+    ///
+    /// This is the counterpart of `PbEncoder::encode_packed_field`, but accepts both packed and
+    /// unpacked elements like a repeated field in a message. Fields with other field numbers are
+    /// skipped.
+    ///
+    /// # Errors
+    ///
+    /// If the number of elements on the wire exceeds the fixed capacity of `vec` and the
+    /// `ignore_repeated_cap_err` flag is not set, return [`DecodeError::Capacity`].
+    pub fn decode_packed_field<
+        T: Copy,
+        S: PbVec<T>,
+        F: Fn(&mut Self) -> Result<T, DecodeError<R::Error>>,
+    >(
+        &mut self,
+        field_num: u32,
+        vec: &mut S,
+        len: usize,
+        decoder: F,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let before = self.bytes_read();
+        while self.bytes_read() - before < len {
+            let tag = self.decode_tag()?;
+            match tag.field_num() {
+                0 => return Err(DecodeError::ZeroField),
+                n if n == field_num && tag.wire_type() == WIRE_TYPE_LEN => {
+                    self.decode_packed(vec, &decoder)?
+                }
+                n if n == field_num => {
+                    let val = decoder(self)?;
+                    self.push_elem(vec, val)?;
+                }
+                _ => self.skip_wire_value(tag.wire_type())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode `len` bytes of a repeated message field with field number `field_num`, appending
+    /// each message to `vec`.
+    ///
+    /// This is the counterpart of `PbEncoder::encode_repeated_messages`, and behaves the same as
+    /// decoding a message that only contains `vec` as a repeated field. Fields with other field
+    /// numbers are skipped.
+    ///
+    /// # Errors
+    ///
+    /// If the number of messages on the wire exceeds the fixed capacity of `vec` and the
+    /// `ignore_repeated_cap_err` flag is not set, return [`DecodeError::Capacity`].
+    pub fn decode_repeated_messages<M: MessageDecode + Default, S: PbVec<M>>(
+        &mut self,
+        field_num: u32,
+        vec: &mut S,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let before = self.bytes_read();
+        while self.bytes_read() - before < len {
+            let tag = self.decode_tag()?;
+            match tag.field_num() {
+                0 => return Err(DecodeError::ZeroField),
+                n if n == field_num => {
+                    let mut msg = M::default();
+                    msg.decode_len_delimited(self)?;
+                    self.push_elem(vec, msg)?;
+                }
+                _ => self.skip_wire_value(tag.wire_type())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> PbDecoder<&'a [u8]> {
+    /// Decode a `bytes` as a slice that borrows from the decoder's input buffer, without copying.
+    ///
+    /// Used by messages that implement [`MessageDecodeBorrowed`](crate::MessageDecodeBorrowed).
+    pub fn decode_bytes_borrowed(&mut self) -> Result<&'a [u8], DecodeError<Never>> {
+        let len = self.decode_len()?;
+        let reader: &'a [u8] = self.reader;
+        if reader.len() < len {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        let (bytes, rest) = reader.split_at(len);
+        self.reader = rest;
+        self.idx += len;
+        Ok(bytes)
+    }
+
+    #[cfg(feature = "strings")]
+    /// Decode a `string` as a `str` that borrows from the decoder's input buffer, without
+    /// copying.
+    ///
+    /// # Errors
+    ///
+    /// If the string on the wire if not UTF-8, return [`DecodeError::Utf8`].
+    pub fn decode_str_borrowed(&mut self) -> Result<&'a str, DecodeError<Never>> {
+        let bytes = self.decode_bytes_borrowed()?;
+        self.check_utf8(bytes)?;
+        // SAFETY: The bytes were just checked to be UTF-8, or the caller of set_assume_utf8
+        // guaranteed it
+        Ok(unsafe { core::str::from_utf8_unchecked(bytes) })
+    }
+
+    /// Decode the next Protobuf value on the wire as a raw [`WireValue`], with the type of value
+    /// determined by `wire_type`.
+    ///
+    /// Length-delimited values borrow from the decoder's input buffer, without copying.
+    pub fn decode_wire_value(
+        &mut self,
+        wire_type: WireType,
+    ) -> Result<WireValue<'a>, DecodeError<Never>> {
+        Ok(match wire_type {
+            WireType::Varint => WireValue::Varint(self.read_varint64()?),
+            WireType::I64 => WireValue::I64(self.read_fixed64()?),
+            WireType::Len => WireValue::Len(self.decode_bytes_borrowed()?),
+            WireType::StartGroup | WireType::EndGroup => return Err(DecodeError::Deprecation),
+            WireType::I32 => WireValue::I32(self.decode_fixed32()?),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Undecoded payload of a Protobuf field, passed to the visitor of [`decode_fields`].
+///
+/// Since the field's schema isn't known, the payload is left in its wire representation. For
+/// example, `sint32` values are still zigzag-encoded and `float` values are still in their bit
+/// representation.
+pub enum WireValue<'a> {
+    /// Varint, used by integer, `bool`, and enum fields.
+    Varint(u64),
+    /// Fixed 64-bit value, used by `fixed64`, `sfixed64`, and `double` fields.
+    I64(u64),
+    /// Length-delimited record, used by `string`, `bytes`, message, and packed fields. Borrows
+    /// from the input buffer.
+    Len(&'a [u8]),
+    /// Fixed 32-bit value, used by `fixed32`, `sfixed32`, and `float` fields.
+    I32(u32),
+}
+
+impl<'a> WireValue<'a> {
+    #[inline]
+    /// Get the wire type of the value.
+    pub const fn wire_type(&self) -> WireType {
+        match self {
+            WireValue::Varint(_) => WireType::Varint,
+            WireValue::I64(_) => WireType::I64,
+            WireValue::Len(_) => WireType::Len,
+            WireValue::I32(_) => WireType::I32,
+        }
+    }
+
+    #[inline]
+    /// Get the value of a varint, or `None` if the value isn't a varint.
+    pub const fn varint(&self) -> Option<u64> {
+        match self {
+            WireValue::Varint(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    /// Get the value of a fixed 64-bit value, or `None` if the value isn't a fixed 64-bit value.
+    pub const fn fixed64(&self) -> Option<u64> {
+        match self {
+            WireValue::I64(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    /// Get the value of a fixed 32-bit value, or `None` if the value isn't a fixed 32-bit value.
+    pub const fn fixed32(&self) -> Option<u32> {
+        match self {
+            WireValue::I32(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    /// Get the bytes of a length-delimited record, or `None` if the value isn't length-delimited.
+    pub const fn bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            WireValue::Len(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    /// Get the bytes of a length-delimited record as a string, or `None` if the value isn't
+    /// length-delimited or isn't UTF-8.
+    pub fn str(&self) -> Option<&'a str> {
+        self.bytes().and_then(|b| from_utf8(b).ok())
+    }
+}
+
+/// Walk the fields of an encoded Protobuf message in `buf`, calling `visitor` with the tag and
+/// undecoded payload of each field, in wire order.
+///
+/// This reads fields without a generated message type, which is useful for picking out a few
+/// fields from messages with large or unknown schemas. Nested messages can be walked by calling
+/// `decode_fields` on the bytes of their [`WireValue::Len`] payload. Length-delimited payloads
+/// borrow from `buf`, so nothing is copied.
+///
+/// The visitor can stop the walk early by returning [`ControlFlow::Break`], whose value is
+/// returned. Otherwise, the walk continues to the end of `buf` and returns
+/// [`ControlFlow::Continue`].
+///
+/// # Errors
+///
+/// Returns an error if `buf` isn't valid Protobuf wire data, such as when a field is cut short or
+/// has an unknown wire type. Groups return [`DecodeError::Deprecation`]. Fields visited before
+/// the error have already been passed to the visitor.
+///
+/// # Example
+///
+/// ```
+/// use core::ops::ControlFlow;
+/// use micropb::{decode_fields, WireValue};
+///
+/// // Field 1 is the varint 150, field 2 is the string "hi"
+/// let buf = [0x08, 0x96, 0x01, 0x12, 0x02, b'h', b'i'];
+/// let name = decode_fields(&buf, |tag, value| match (tag.field_num(), value) {
+///     (2, WireValue::Len(_)) => ControlFlow::Break(value.str()),
+///     _ => ControlFlow::Continue(()),
+/// })
+/// .unwrap();
+/// assert_eq!(name, ControlFlow::Break(Some("hi")));
+/// ```
+pub fn decode_fields<'a, B, F: FnMut(Tag, WireValue<'a>) -> ControlFlow<B>>(
+    buf: &'a [u8],
+    mut visitor: F,
+) -> Result<ControlFlow<B>, DecodeError<Never>> {
+    let mut decoder = PbDecoder::new(buf);
+    while decoder.bytes_read() < buf.len() {
+        let tag = decoder.decode_tag()?;
+        let wire_type = tag.typed_wire_type().ok_or(DecodeError::UnknownWireType)?;
+        let value = decoder.decode_wire_value(wire_type)?;
+        if let ControlFlow::Break(b) = visitor(tag, value) {
+            return Ok(ControlFlow::Break(b));
+        }
+    }
+    Ok(ControlFlow::Continue(()))
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl PbDecoder<bytes::Bytes> {
+    /// Decode a `bytes` as a [`bytes::Bytes`] that shares the decoder's input buffer, without
+    /// copying.
+    pub fn decode_bytes_shared(&mut self) -> Result<bytes::Bytes, DecodeError<Never>> {
+        let len = self.decode_len()?;
+        if self.reader.len() < len {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        let bytes = self.reader.split_to(len);
+        self.idx += len;
+        Ok(bytes)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use arrayvec::{ArrayString, ArrayVec};
 
+    use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+    use crate::container::{AlignedVec, AlignedVec32, FixedString, InternedString, SharedBytes};
+    use crate::{WIRE_TYPE_I32, WIRE_TYPE_I64, WIRE_TYPE_VARINT};
+
     use super::*;
 
     struct Multichunk<'a>(&'a [u8]);
@@ -925,6 +2706,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn fixed_unaligned() {
+        let buf = [
+            0xFF, 0x12, 0x32, 0x98, 0xF4, 0x3B, 0xAA, 0x50, 0x99, 0x12, 0x32, 0x98, 0xF4,
+        ];
+        // Decode from every offset within a word to cover unaligned loads
+        for offset in 0..4 {
+            let mut decoder = PbDecoder::new(&buf[offset..]);
+            let expected = u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap());
+            assert_eq!(decoder.decode_fixed32(), Ok(expected));
+            assert_eq!(decoder.bytes_read(), 4);
+
+            let mut decoder = PbDecoder::new(&buf[offset..]);
+            let expected = u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap());
+            assert_eq!(decoder.decode_fixed64(), Ok(expected));
+            assert_eq!(decoder.bytes_read(), 8);
+        }
+
+        let mut decoder = PbDecoder::new(&buf[1..]);
+        assert_eq!(decoder.decode_fixed64(), Ok(0x9950AA3BF4983212));
+        assert_eq!(decoder.decode_fixed32(), Ok(0xF4983212));
+        assert!(decoder.as_reader().is_empty());
+    }
+
     #[test]
     fn sfixed() {
         assert_decode!(
@@ -986,6 +2791,101 @@ mod tests {
         );
     }
 
+    #[test]
+    fn byte_order() {
+        // Wire values are little-endian regardless of the target's byte order, so these must pass
+        // unchanged on big-endian targets
+        assert_decode!(Ok(0x04030201), [0x01, 0x02, 0x03, 0x04], decode_fixed32());
+        assert_decode!(
+            Ok(0x0807060504030201),
+            [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08],
+            decode_fixed64()
+        );
+        assert_decode!(Ok(-2), [0xFE, 0xFF, 0xFF, 0xFF], decode_sfixed32());
+        assert_decode!(
+            Ok(-2),
+            [0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+            decode_sfixed64()
+        );
+        assert_decode!(
+            Ok(0x04030201),
+            [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08],
+            decode_fixed64_as_32()
+        );
+        assert_decode!(Ok(1.0), [0x00, 0x00, 0x80, 0x3F], decode_float());
+        assert_decode!(Ok(-2.5), [0x00, 0x00, 0x20, 0xC0], decode_float());
+        assert_decode!(
+            Ok(1.0),
+            [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF0, 0x3F],
+            decode_double()
+        );
+        assert_decode!(
+            Ok(-2.5),
+            [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0xC0],
+            decode_double()
+        );
+
+        // Bit patterns must survive the trip, including ones that aren't ordinary numbers
+        let mut decoder = PbDecoder::new([0x01, 0x00, 0xC0, 0x7F].as_slice());
+        assert_eq!(decoder.decode_float().unwrap().to_bits(), 0x7FC00001);
+        let mut decoder = PbDecoder::new([0x00, 0x00, 0x00, 0x80].as_slice());
+        assert_eq!(decoder.decode_float().unwrap().to_bits(), 0x80000000);
+        let mut decoder =
+            PbDecoder::new([0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF8, 0x7F].as_slice());
+        assert_eq!(
+            decoder.decode_double().unwrap().to_bits(),
+            0x7FF8000000000001
+        );
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn padded_varint() {
+        use crate::PbEncoder;
+
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 64>::new());
+        for width in 1..=10 {
+            encoder.encode_varint32_padded(150, width).unwrap();
+        }
+        let buf = encoder.into_writer();
+        // Widths of 1 and 2 both produce the shortest encoding
+        assert_eq!(buf.len(), 2 + (2..=10).sum::<usize>());
+        let mut decoder = PbDecoder::new(buf.as_slice());
+        for _ in 1..=10 {
+            assert_eq!(decoder.decode_varint32(), Ok(150));
+        }
+        assert!(decoder.as_reader().is_empty());
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn byte_order_roundtrip() {
+        use crate::PbEncoder;
+
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 64>::new());
+        encoder.encode_fixed32(0xDEADBEEF).unwrap();
+        encoder.encode_fixed64(0x0123456789ABCDEF).unwrap();
+        encoder.encode_sfixed32(-0x12345678).unwrap();
+        encoder.encode_sfixed64(-0x0123456789ABCDEF).unwrap();
+        encoder.encode_float(f32::from_bits(0x3FC00001)).unwrap();
+        encoder
+            .encode_double(f64::from_bits(0xC00921FB54442D18))
+            .unwrap();
+        let buf = encoder.into_writer();
+
+        let mut decoder = PbDecoder::new(buf.as_slice());
+        assert_eq!(decoder.decode_fixed32(), Ok(0xDEADBEEF));
+        assert_eq!(decoder.decode_fixed64(), Ok(0x0123456789ABCDEF));
+        assert_eq!(decoder.decode_sfixed32(), Ok(-0x12345678));
+        assert_eq!(decoder.decode_sfixed64(), Ok(-0x0123456789ABCDEF));
+        assert_eq!(decoder.decode_float().unwrap().to_bits(), 0x3FC00001);
+        assert_eq!(
+            decoder.decode_double().unwrap().to_bits(),
+            0xC00921FB54442D18
+        );
+        assert!(decoder.as_reader().is_empty());
+    }
+
     #[test]
     fn skip() {
         assert_decode!(
@@ -1035,6 +2935,15 @@ mod tests {
         assert_decode!(Err(DecodeError::Deprecation), [], skip_wire_value(3));
         assert_decode!(Err(DecodeError::Deprecation), [], skip_wire_value(4));
         assert_decode!(Err(DecodeError::UnknownWireType), [], skip_wire_value(10));
+
+        assert_decode!(Ok(()), [0x96, 0x01], skip_value(WireType::Varint));
+        assert_decode!(Ok(()), [0x01, 0x02, 0x03, 0x04], skip_value(WireType::I32));
+        assert_decode!(Ok(()), [0x01, 0xAB], skip_value(WireType::Len));
+        assert_decode!(
+            Err(DecodeError::Deprecation),
+            [],
+            skip_value(WireType::StartGroup)
+        );
     }
 
     macro_rules! assert_decode_vec {
@@ -1070,6 +2979,13 @@ mod tests {
                 $test::<$container>($fixed_cap);
             }
         };
+
+        ($test:ident, $name:ident, $container:ty) => {
+            #[test]
+            fn $name() {
+                $test::<$container>();
+            }
+        };
     }
 
     fn string<S: PbString + Default>(fixed_cap: bool) {
@@ -1099,27 +3015,244 @@ mod tests {
         );
         assert_decode_vec!(
             Err(DecodeError::UnexpectedEof),
-            [4, b'b', b'c', b'd'],
-            decode_string(string, Presence::Explicit)
+            [4, b'b', b'c', b'd'],
+            decode_string(string, Presence::Explicit)
+        );
+        if fixed_cap {
+            assert_decode_vec!(
+                Err(DecodeError::Capacity),
+                [5, b'a', b'b', b'c', b'd', b'e'],
+                decode_string(string, Presence::Explicit)
+            );
+        }
+        assert_decode_vec!(
+            Err(DecodeError::Utf8),
+            [4, 0x80, 0x80, 0x80, 0x80],
+            decode_string(string, Presence::Explicit)
+        );
+    }
+
+    container_test!(string, string_arrayvec, ArrayString::<4>, true);
+    container_test!(string, string_heapless, heapless::String::<4>, true);
+    container_test!(string, string_fixed, FixedString::<4>, true);
+
+    fn string_truncated<S: PbString + Default>() {
+        let mut string = S::default();
+        assert_decode_vec!(
+            Ok("abcd"),
+            [4, b'a', b'b', b'c', b'd'],
+            decode_string_truncated(string, Presence::Explicit)
+        );
+        assert_decode_vec!(
+            Ok("abcd"),
+            [6, b'a', b'b', b'c', b'd', b'e', b'f'],
+            decode_string_truncated(string, Presence::Explicit)
+        );
+        assert_decode_vec!(
+            Ok("abcd"),
+            [0],
+            decode_string_truncated(string, Presence::Implicit)
+        );
+        assert_decode_vec!(
+            Ok(""),
+            [0],
+            decode_string_truncated(string, Presence::Explicit)
+        );
+        // Multi-byte character cut off at the capacity boundary
+        assert_decode_vec!(
+            Ok("aЗ"),
+            [5, b'a', 208, 151, 208, 180],
+            decode_string_truncated(string, Presence::Implicit)
+        );
+        // Invalid bytes past the capacity are skipped without validation
+        assert_decode_vec!(
+            Ok("abcd"),
+            [5, b'a', b'b', b'c', b'd', 0x80],
+            decode_string_truncated(string, Presence::Implicit)
+        );
+
+        assert_decode_vec!(
+            Err(DecodeError::UnexpectedEof),
+            [6, b'a', b'b', b'c', b'd', b'e'],
+            decode_string_truncated(string, Presence::Explicit)
         );
-        if fixed_cap {
-            assert_decode_vec!(
-                Err(DecodeError::Capacity),
-                [5, b'a', b'b', b'c', b'd', b'e'],
-                decode_string(string, Presence::Explicit)
-            );
-        }
         assert_decode_vec!(
             Err(DecodeError::Utf8),
-            [4, 0x80, 0x80, 0x80, 0x80],
-            decode_string(string, Presence::Explicit)
+            [5, b'a', 0x80, b'c', b'd', b'e'],
+            decode_string_truncated(string, Presence::Explicit)
         );
     }
 
-    container_test!(string, string_arrayvec, ArrayString::<4>, true);
-    container_test!(string, string_heapless, heapless::String::<4>, true);
+    container_test!(
+        string_truncated,
+        string_truncated_arrayvec,
+        ArrayString::<4>
+    );
+    container_test!(
+        string_truncated,
+        string_truncated_heapless,
+        heapless::String::<4>
+    );
+    container_test!(string_truncated, string_truncated_fixed, FixedString::<4>);
+    container_test!(string, string_heapless_0_7, heapless_0_7::String::<4>, true);
+    #[cfg(feature = "container-heapless-0_9")]
+    container_test!(string, string_heapless_0_9, heapless_0_9::String::<4>, true);
     container_test!(string, string_alloc, String, false);
 
+    struct TestPool;
+
+    impl crate::container::InternPool for TestPool {
+        type Handle = std::rc::Rc<str>;
+
+        fn intern(s: &str) -> Self::Handle {
+            std::thread_local!(static POOL: RefCell<HashSet<Rc<str>>> = RefCell::default());
+            POOL.with_borrow_mut(|pool| match pool.get(s) {
+                Some(handle) => handle.clone(),
+                None => {
+                    let handle: Rc<str> = s.into();
+                    pool.insert(handle.clone());
+                    handle
+                }
+            })
+        }
+    }
+
+    container_test!(string, string_interned, InternedString<TestPool>, false);
+
+    #[test]
+    fn string_interned_dedup() {
+        let mut strings: [InternedString<TestPool>; 3] = Default::default();
+        let mut decoder =
+            PbDecoder::new([2, b'i', b'd', 2, b'i', b'd', 3, b'i', b'd', b'2'].as_slice());
+        for string in &mut strings {
+            decoder.decode_string(string, Presence::Explicit).unwrap();
+        }
+        assert_eq!(&*strings[0], "id");
+        assert_eq!(&*strings[2], "id2");
+        assert!(Rc::ptr_eq(
+            strings[0].handle().unwrap(),
+            strings[1].handle().unwrap()
+        ));
+        // Strings created from `&str` are interned too
+        let from_str = InternedString::<TestPool>::pb_from_str("id2").unwrap();
+        assert!(Rc::ptr_eq(
+            from_str.handle().unwrap(),
+            strings[2].handle().unwrap()
+        ));
+    }
+
+    #[test]
+    fn assume_utf8() {
+        let buf = [3, b'a', b'b', b'c', 2, b'x', b'y'];
+        let mut decoder = PbDecoder::new(buf.as_slice());
+        assert!(!decoder.assume_utf8());
+        // SAFETY: All strings in the input are UTF-8
+        unsafe { decoder.set_assume_utf8(true) };
+        assert!(decoder.assume_utf8());
+        let mut string = String::new();
+        decoder
+            .decode_string(&mut string, Presence::Explicit)
+            .unwrap();
+        assert_eq!(string, "abc");
+        assert_eq!(decoder.decode_str_borrowed().unwrap(), "xy");
+    }
+
+    #[test]
+    #[cfg(all(debug_assertions, not(feature = "panic-free")))]
+    #[should_panic]
+    fn assume_utf8_debug_check() {
+        let mut decoder = PbDecoder::new([2, 0x80, 0x80].as_slice());
+        // SAFETY: Not actually safe, but the debug assertion panics before the string is used
+        unsafe { decoder.set_assume_utf8(true) };
+        let _ = decoder.decode_str_borrowed();
+    }
+
+    #[test]
+    fn borrowed() {
+        let buf = [3, b'a', b'b', b'c', 0, 2, 0x80, 0x80, 2, b'x'];
+        let mut decoder = PbDecoder::new(buf.as_slice());
+        let s = decoder.decode_str_borrowed().unwrap();
+        assert_eq!(s, "abc");
+        assert!(core::ptr::eq(s.as_ptr(), &buf[1]));
+        assert_eq!(decoder.decode_bytes_borrowed().unwrap(), b"");
+        assert_eq!(decoder.bytes_read(), 5);
+        assert_eq!(decoder.decode_str_borrowed(), Err(DecodeError::Utf8));
+        assert_eq!(decoder.bytes_read(), 8);
+        assert_eq!(
+            decoder.decode_bytes_borrowed(),
+            Err(DecodeError::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn visit_fields() {
+        let buf = [
+            0x08, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // 1: -1
+            0x11, 1, 2, 3, 4, 5, 6, 7, 8, // 2: fixed64
+            0x1A, 0x02, 0x20, 0x03, // 3: nested message with 4: 3
+            0x25, 0x00, 0x00, 0x80, 0x3F, // 4: 1.0f32
+        ];
+        let mut fields = vec![];
+        let res = decode_fields::<(), _>(&buf, |tag, value| {
+            fields.push((tag.field_num(), value));
+            ControlFlow::Continue(())
+        });
+        assert_eq!(res, Ok(ControlFlow::Continue(())));
+        assert_eq!(
+            fields,
+            [
+                (1, WireValue::Varint(u64::MAX)),
+                (2, WireValue::I64(0x0807060504030201)),
+                (3, WireValue::Len(&[0x20, 0x03])),
+                (4, WireValue::I32(1.0f32.to_bits())),
+            ]
+        );
+        assert_eq!(fields[2].1.wire_type(), WireType::Len);
+        assert_eq!(fields[0].1.varint(), Some(u64::MAX));
+        assert_eq!(fields[0].1.fixed32(), None);
+
+        // Walk the nested message and stop at the first field
+        let nested = decode_fields(fields[2].1.bytes().unwrap(), |tag, value| {
+            ControlFlow::Break((tag, value))
+        });
+        assert_eq!(
+            nested,
+            Ok(ControlFlow::Break((
+                Tag::from_parts(4, WIRE_TYPE_VARINT),
+                WireValue::Varint(3)
+            )))
+        );
+
+        assert_eq!(
+            decode_fields::<(), _>(&[], |_, _| panic!()),
+            Ok(ControlFlow::Continue(()))
+        );
+    }
+
+    #[test]
+    fn visit_fields_error() {
+        let mut visited = 0;
+        let mut visit = |_, _| {
+            visited += 1;
+            ControlFlow::<()>::Continue(())
+        };
+        assert_eq!(
+            decode_fields(&[0x08, 0x01, 0x12, 0x03, b'a'], &mut visit),
+            Err(DecodeError::UnexpectedEof)
+        );
+        assert_eq!(
+            decode_fields(&[0x0B], &mut visit),
+            Err(DecodeError::Deprecation)
+        );
+        assert_eq!(
+            decode_fields(&[0x0E, 0x00], &mut visit),
+            Err(DecodeError::UnknownWireType)
+        );
+        assert_eq!(visited, 1);
+        assert_eq!(WireValue::Len(&[0xFF]).str(), None);
+        assert_eq!(WireValue::Len(b"ab").str(), Some("ab"));
+    }
+
     fn bytes<S: PbVec<u8> + Default>(fixed_cap: bool) {
         let mut bytes = S::default();
         assert_decode_vec!(Ok(&[]), [0], decode_bytes(bytes, Presence::Explicit));
@@ -1156,7 +3289,67 @@ mod tests {
 
     container_test!(bytes, bytes_arrayvec, ArrayVec::<_, 3>, true);
     container_test!(bytes, bytes_heapless, heapless::Vec::<_, 3>, true);
+    container_test!(bytes, bytes_heapless_0_7, heapless_0_7::Vec::<_, 3>, true);
+    #[cfg(feature = "container-heapless-0_9")]
+    container_test!(bytes, bytes_heapless_0_9, heapless_0_9::Vec::<_, 3>, true);
     container_test!(bytes, bytes_alloc, Vec<_>, false);
+    container_test!(bytes, bytes_aligned, AlignedVec::<_, 3>, true);
+    container_test!(bytes, bytes_aligned32, AlignedVec32::<_, 3>, true);
+    container_test!(bytes, bytes_bytes_mut, ::bytes::BytesMut, false);
+    container_test!(bytes, bytes_shared, SharedBytes, false);
+
+    #[test]
+    fn bytes_shared_clone() {
+        let mut bytes = SharedBytes::new();
+        let mut decoder = PbDecoder::new([3, 1, 2, 3].as_slice());
+        decoder
+            .decode_bytes(&mut bytes, Presence::Explicit)
+            .unwrap();
+        // Decoded payload is shared by clones instead of being copied
+        let clone = bytes.clone();
+        assert_eq!(&*clone, &[1, 2, 3]);
+        assert!(core::ptr::eq(clone.as_ptr(), bytes.as_ptr()));
+        assert!(core::ptr::eq(bytes.to_bytes().as_ptr(), bytes.as_ptr()));
+
+        // Writing to a shared payload leaves the clones untouched
+        bytes.extend_from_slice(&[4]);
+        assert_eq!(&*bytes, &[1, 2, 3, 4]);
+        assert_eq!(&*clone, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn bytes_shared_reader() {
+        let buf = ::bytes::Bytes::from_static(&[2, 0xA, 0xB, 0, 3, 0xC]);
+        let mut decoder = PbDecoder::new(buf.clone());
+        let bytes = decoder.decode_bytes_shared().unwrap();
+        assert_eq!(bytes, [0xA, 0xB].as_slice());
+        assert!(core::ptr::eq(bytes.as_ptr(), &buf[1]));
+        assert_eq!(decoder.decode_bytes_shared().unwrap(), b"".as_slice());
+        assert_eq!(decoder.bytes_read(), 4);
+        assert_eq!(
+            decoder.decode_bytes_shared(),
+            Err(DecodeError::UnexpectedEof)
+        );
+
+        let mut decoder = PbDecoder::new(buf);
+        let mut bytes = Vec::new();
+        decoder
+            .decode_bytes(&mut bytes, Presence::Explicit)
+            .unwrap();
+        assert_eq!(bytes, [0xA, 0xB]);
+        assert_eq!(decoder.bytes_read(), 3);
+    }
+
+    #[test]
+    fn bytes_aligned_addr() {
+        let mut bytes = AlignedVec32::<u8, 8>::new();
+        let mut decoder = PbDecoder::new([4, 1, 2, 3, 4].as_slice());
+        decoder
+            .decode_bytes(&mut bytes, Presence::Explicit)
+            .unwrap();
+        assert_eq!(bytes.as_slice(), &[1, 2, 3, 4]);
+        assert_eq!(bytes.as_ptr() as usize % 32, 0);
+    }
 
     fn packed<S: PbVec<u32> + Default>(fixed_cap: bool) {
         let mut vec1 = S::default();
@@ -1196,7 +3389,286 @@ mod tests {
 
     container_test!(packed, packed_arrayvec, ArrayVec::<_, 5>, true);
     container_test!(packed, packed_heapless, heapless::Vec::<_, 5>, true);
+    container_test!(packed, packed_heapless_0_7, heapless_0_7::Vec::<_, 5>, true);
+    #[cfg(feature = "container-heapless-0_9")]
+    container_test!(packed, packed_heapless_0_9, heapless_0_9::Vec::<_, 5>, true);
     container_test!(packed, packed_alloc, Vec<_>, false);
+    container_test!(packed, packed_aligned, AlignedVec::<_, 5>, true);
+
+    fn packed_overflow<S: PbVec<u32> + DerefMut<Target = [u32]> + Default>(fixed_cap: bool) {
+        let mut vec1 = S::default();
+        let mut vec2 = S::default();
+        assert_decode_vec!(
+            Ok(&[1, 2, 3]),
+            [3, 0x01, 0x02, 0x03],
+            decode_packed_with_overflow(vec1 | vec2, RepeatedOverflow::DropOldest, &mut 0, |rd| rd
+                .decode_varint32())
+        );
+        if fixed_cap {
+            assert_decode_vec!(
+                Ok(&[3, 4, 5]),
+                [2, 0x04, 0x05],
+                decode_packed_with_overflow(
+                    vec1 | vec2,
+                    RepeatedOverflow::DropOldest,
+                    &mut 0,
+                    |rd| rd.decode_varint32()
+                )
+            );
+            assert_decode_vec!(
+                Ok(&[3, 4, 5]),
+                [2, 0x06, 0x07],
+                decode_packed_with_overflow(
+                    vec1 | vec2,
+                    RepeatedOverflow::DropNewest,
+                    &mut 0,
+                    |rd| rd.decode_varint32()
+                )
+            );
+            assert_decode_vec!(
+                Err(DecodeError::Capacity),
+                [1, 0x06],
+                decode_packed_with_overflow(vec1 | vec2, RepeatedOverflow::Error, &mut 0, |rd| rd
+                    .decode_varint32())
+            );
+            // Overflowing by more than the capacity keeps the last elements in order
+            assert_decode_vec!(
+                Ok(&[9, 10, 11]),
+                [4, 0x08, 0x09, 0x0A, 0x0B],
+                decode_packed_with_overflow(
+                    vec1 | vec2,
+                    RepeatedOverflow::DropOldest,
+                    &mut 0,
+                    |rd| rd.decode_varint32()
+                )
+            );
+        } else {
+            assert_decode_vec!(
+                Ok(&[1, 2, 3, 4, 5]),
+                [2, 0x04, 0x05],
+                decode_packed_with_overflow(
+                    vec1 | vec2,
+                    RepeatedOverflow::DropOldest,
+                    &mut 0,
+                    |rd| rd.decode_varint32()
+                )
+            );
+        }
+    }
+
+    container_test!(
+        packed_overflow,
+        packed_overflow_arrayvec,
+        ArrayVec::<_, 3>,
+        true
+    );
+    container_test!(
+        packed_overflow,
+        packed_overflow_heapless,
+        heapless::Vec::<_, 3>,
+        true
+    );
+    container_test!(packed_overflow, packed_overflow_alloc, Vec<_>, false);
+
+    #[test]
+    fn packed_fixed() {
+        let data = [8, 1, 0, 0, 0, 2, 0, 0, 0];
+        let mut vec = Vec::<u32>::new();
+        let mut decoder = PbDecoder::new(data.as_slice());
+        decoder
+            .decode_packed_fixed(&mut vec, 4, PbDecoder::decode_fixed32)
+            .unwrap();
+        assert_eq!(vec, [1, 2]);
+        assert!(vec.capacity() >= 2);
+
+        // Capacity is checked before any element is decoded
+        let mut vec = ArrayVec::<u32, 1>::new();
+        let mut decoder = PbDecoder::new(data.as_slice());
+        assert_eq!(
+            decoder.decode_packed_fixed(&mut vec, 4, PbDecoder::decode_fixed32),
+            Err(DecodeError::Capacity)
+        );
+        assert!(vec.is_empty());
+
+        let mut decoder = PbDecoder::new(data.as_slice());
+        decoder.ignore_repeated_cap_err = true;
+        decoder
+            .decode_packed_fixed(&mut vec, 4, PbDecoder::decode_fixed32)
+            .unwrap();
+        assert_eq!(vec.as_slice(), [1]);
+        #[cfg(feature = "decode-stats")]
+        assert_eq!(decoder.stats().dropped_elements, 1);
+
+        let mut decoder = PbDecoder::new([3, 1, 0, 0].as_slice());
+        assert_eq!(
+            decoder.decode_packed_fixed(&mut Vec::new(), 4, PbDecoder::decode_fixed32),
+            Err(DecodeError::WrongLen)
+        );
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct TestEnum(i32);
+
+    impl PbEnum for TestEnum {
+        fn from_i32(val: i32) -> Self {
+            Self(val)
+        }
+
+        fn to_i32(self) -> i32 {
+            self.0
+        }
+
+        fn is_known(self) -> bool {
+            matches!(self.0, 0 | 1)
+        }
+    }
+
+    #[test]
+    fn packed_enum() {
+        let data = [4, 0x01, 0x05, 0x00, 0x02];
+        let mut vec = Vec::<TestEnum>::new();
+        let mut decoder = PbDecoder::new(data.as_slice());
+        decoder
+            .decode_packed_enum(&mut vec, UnknownEnum::Keep)
+            .unwrap();
+        assert_eq!(vec, [TestEnum(1), TestEnum(5), TestEnum(0), TestEnum(2)]);
+        #[cfg(feature = "decode-stats")]
+        assert_eq!(decoder.stats().unknown_enums, 0);
+
+        vec.clear();
+        let mut decoder = PbDecoder::new(data.as_slice());
+        decoder
+            .decode_packed_enum(&mut vec, UnknownEnum::Drop)
+            .unwrap();
+        assert_eq!(vec, [TestEnum(1), TestEnum(0)]);
+        #[cfg(feature = "decode-stats")]
+        assert_eq!(decoder.stats().unknown_enums, 2);
+
+        vec.clear();
+        let mut decoder = PbDecoder::new(data.as_slice());
+        assert_eq!(
+            decoder.decode_packed_enum(&mut vec, UnknownEnum::Error),
+            Err(DecodeError::UnknownEnum)
+        );
+        assert_eq!(vec, [TestEnum(1)]);
+
+        // Unpacked elements follow the same policy
+        let mut decoder = PbDecoder::new([0x07, 0x00].as_slice());
+        decoder
+            .decode_enum_elem(&mut vec, UnknownEnum::Drop)
+            .unwrap();
+        decoder
+            .decode_enum_elem(&mut vec, UnknownEnum::Error)
+            .unwrap();
+        assert_eq!(vec, [TestEnum(1), TestEnum(0)]);
+
+        // Negative values take 10 bytes on the wire
+        let data = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        assert_eq!(decoder.decode_enum::<TestEnum>(), Ok(TestEnum(-1)));
+    }
+
+    #[test]
+    fn push_repeated() {
+        let mut decoder = PbDecoder::new([].as_slice());
+        let mut vec = ArrayVec::<u32, 2>::new();
+        let mut oldest = 0;
+        for i in 0..4 {
+            decoder
+                .push_repeated(&mut vec, i, RepeatedOverflow::DropOldest, &mut oldest)
+                .unwrap();
+        }
+        decoder.finish_repeated(&mut vec, &mut oldest);
+        assert_eq!(vec.as_slice(), &[2, 3]);
+        #[cfg(feature = "decode-stats")]
+        assert_eq!(decoder.stats().dropped_elements, 2);
+        assert_eq!(
+            decoder.push_repeated(&mut vec, 4, RepeatedOverflow::Error, &mut oldest),
+            Err(DecodeError::Capacity)
+        );
+        decoder.ignore_repeated_cap_err = true;
+        decoder
+            .push_repeated(&mut vec, 4, RepeatedOverflow::Error, &mut oldest)
+            .unwrap();
+        decoder
+            .push_repeated(&mut vec, 4, RepeatedOverflow::DropNewest, &mut oldest)
+            .unwrap();
+        assert_eq!(vec.as_slice(), &[2, 3]);
+        assert_eq!(oldest, 0);
+        #[cfg(feature = "decode-stats")]
+        assert_eq!(
+            decoder.take_stats(),
+            DecodeStats {
+                dropped_elements: 4,
+                truncated_strings: 0,
+                unknown_enums: 0,
+            }
+        );
+        #[cfg(feature = "decode-stats")]
+        assert_eq!(decoder.stats(), DecodeStats::default());
+
+        // Zero-capacity containers drop everything
+        let mut vec = ArrayVec::<u32, 0>::new();
+        decoder
+            .push_repeated(&mut vec, 1, RepeatedOverflow::DropOldest, &mut oldest)
+            .unwrap();
+        decoder.finish_repeated(&mut vec, &mut oldest);
+        assert!(vec.is_empty());
+        #[cfg(feature = "decode-stats")]
+        assert_eq!(decoder.stats().dropped_elements, 1);
+    }
+
+    #[test]
+    fn error_conversion() {
+        use std::error::Error;
+        use std::io;
+
+        let err = DecodeError::<Never>::WrongLen;
+        assert_eq!(
+            err.to_string(),
+            "record length differs from its length prefix"
+        );
+        assert!(err.source().is_none());
+        let io_err = io::Error::from(err);
+        assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            io_err
+                .into_inner()
+                .unwrap()
+                .downcast_ref::<DecodeError<Never>>(),
+            Some(&DecodeError::WrongLen)
+        );
+
+        let io_err = io::Error::from(DecodeError::<Never>::UnexpectedEof);
+        assert_eq!(io_err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // IO errors from the reader are passed through
+        let err = DecodeError::Reader(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert!(err.source().is_some());
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::BrokenPipe);
+
+        let err = DecodeError::Reader(core::fmt::Error);
+        assert_eq!(
+            err.to_string(),
+            "reader error: an error occurred when formatting an argument"
+        );
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::Other);
+    }
+
+    #[test]
+    #[cfg(feature = "decode-stats")]
+    fn truncated_string_stats() {
+        let bytes = [3, b'a', b'b', b'c', 2, b'd', b'e', 1, b'f'];
+        let mut decoder = PbDecoder::new(bytes.as_slice());
+        let mut string = ArrayString::<2>::new();
+        for _ in 0..3 {
+            decoder
+                .decode_string_truncated(&mut string, Presence::Explicit)
+                .unwrap();
+        }
+        assert_eq!(string.as_str(), "f");
+        assert_eq!(decoder.stats().truncated_strings, 1);
+    }
 
     //#[cfg(target_endian = "little")]
     //fn packed_fixed<S: PbVec<u32>>(fixed_cap: bool) {
@@ -1308,4 +3780,490 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn duplicate_map_keys() {
+        // Two entries with key 1, followed by an entry with key 2
+        let bytes = [
+            4, 0x08, 1, 0x10, 10, 4, 0x08, 1, 0x10, 20, 4, 0x08, 2, 0x10, 30,
+        ];
+        let decode_all = |policy| {
+            let mut decoder = PbDecoder::new(bytes.as_slice());
+            decoder.duplicate_keys = policy;
+            let mut map = std::collections::BTreeMap::new();
+            while decoder.bytes_read() < bytes.len() {
+                let (k, v) = decoder
+                    .decode_map_elem(
+                        |v: &mut u32, rd| rd.decode_varint32().map(|u| *v = u),
+                        |v: &mut u32, rd| rd.decode_varint32().map(|u| *v = u),
+                    )?
+                    .unwrap();
+                decoder.insert_map_elem(&mut map, k, v)?;
+            }
+            Ok(map.into_iter().collect::<Vec<_>>())
+        };
+        assert_eq!(
+            decode_all(DuplicateKey::LastWins),
+            Ok(vec![(1, 20), (2, 30)])
+        );
+        assert_eq!(
+            decode_all(DuplicateKey::FirstWins),
+            Ok(vec![(1, 10), (2, 30)])
+        );
+        assert_eq!(
+            decode_all(DuplicateKey::Error),
+            Err(DecodeError::DuplicateKey)
+        );
+    }
+
+    #[test]
+    fn scalar_field() {
+        let bytes = [0x08, 0x03, 0x10, 0x05, 0x08, 0x01];
+        let mut decoder = PbDecoder::new(bytes.as_slice());
+        assert_eq!(
+            decoder.decode_scalar_field(1, bytes.len(), PbDecoder::decode_sint32),
+            Ok(Some(-1))
+        );
+        let mut decoder = PbDecoder::new(bytes.as_slice());
+        assert_eq!(
+            decoder.decode_scalar_field(3, bytes.len(), PbDecoder::decode_sint32),
+            Ok(None)
+        );
+        let mut decoder = PbDecoder::new([0x00, 0x01].as_slice());
+        assert_eq!(
+            decoder.decode_scalar_field(1, 2, PbDecoder::decode_varint32),
+            Err(DecodeError::ZeroField)
+        );
+    }
+
+    #[test]
+    fn packed_field() {
+        // Packed and unpacked elements, with another field in between
+        let bytes = [0x12, 3, 0x01, 0x96, 0x01, 0x18, 0x07, 0x10, 0x02];
+        let mut vec = ArrayVec::<u32, 3>::new();
+        let mut decoder = PbDecoder::new(bytes.as_slice());
+        decoder
+            .decode_packed_field(2, &mut vec, bytes.len(), PbDecoder::decode_varint32)
+            .unwrap();
+        assert_eq!(vec.as_slice(), &[1, 150, 2]);
+
+        let mut decoder = PbDecoder::new(bytes.as_slice());
+        assert_eq!(
+            decoder.decode_packed_field(2, &mut vec, bytes.len(), PbDecoder::decode_varint32),
+            Err(DecodeError::Capacity)
+        );
+        // Excess elements are dropped when capacity errors are ignored
+        let mut decoder = PbDecoder::new(bytes.as_slice());
+        decoder.ignore_repeated_cap_err = true;
+        decoder
+            .decode_packed_field(2, &mut vec, bytes.len(), PbDecoder::decode_varint32)
+            .unwrap();
+        decoder
+            .decode_packed_field(2, &mut vec, 0, PbDecoder::decode_varint32)
+            .unwrap();
+        assert_eq!(vec.as_slice(), &[1, 150, 2]);
+    }
+
+    /// Message that only decodes the first varint and ignores the rest of its record
+    #[derive(Default)]
+    struct FirstVarint(u32);
+
+    impl MessageDecode for FirstVarint {
+        fn decode<R: PbRead>(
+            &mut self,
+            decoder: &mut PbDecoder<R>,
+            _len: usize,
+        ) -> Result<(), DecodeError<R::Error>> {
+            self.0 = decoder.decode_varint32()?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let bytes = [3, 0x01, 0x02, 0x03, 0x04];
+
+        let mut decoder = PbDecoder::new(bytes.as_slice());
+        assert_eq!(
+            FirstVarint::default().decode_len_delimited(&mut decoder),
+            Err(DecodeError::WrongLen)
+        );
+
+        let mut decoder = PbDecoder::new(bytes.as_slice());
+        decoder.trailing_bytes = TrailingBytes::Skip;
+        let mut msg = FirstVarint::default();
+        msg.decode_len_delimited(&mut decoder).unwrap();
+        assert_eq!(msg.0, 1);
+        assert_eq!(decoder.bytes_read(), 4);
+        #[cfg(feature = "decode-stats")]
+        assert_eq!(decoder.trailing_bytes_skipped(), 0);
+
+        #[cfg(feature = "decode-stats")]
+        {
+            let mut decoder = PbDecoder::new(bytes.as_slice());
+            decoder.trailing_bytes = TrailingBytes::Report;
+            FirstVarint::default()
+                .decode_len_delimited(&mut decoder)
+                .unwrap();
+            assert_eq!(decoder.decode_varint32(), Ok(4));
+            assert_eq!(decoder.trailing_bytes_skipped(), 2);
+        }
+
+        // Trailing bytes that run past the end of the input
+        let mut decoder = PbDecoder::new([5, 0x01, 0x02].as_slice());
+        decoder.trailing_bytes = TrailingBytes::Skip;
+        assert_eq!(
+            FirstVarint::default().decode_len_delimited(&mut decoder),
+            Err(DecodeError::UnexpectedEof)
+        );
+
+        // Over-consumption is always an error
+        let mut decoder = PbDecoder::new([1, 0x81, 0x01].as_slice());
+        decoder.trailing_bytes = TrailingBytes::Skip;
+        assert_eq!(
+            FirstVarint::default().decode_len_delimited(&mut decoder),
+            Err(DecodeError::WrongLen)
+        );
+    }
+
+    #[test]
+    fn message_delimited() {
+        let mut decoder = PbDecoder::new([1, 0x05, 2, 0x96, 0x01].as_slice());
+        let msg: FirstVarint = decoder.decode_message_delimited().unwrap();
+        assert_eq!(msg.0, 5);
+        let msg: FirstVarint = decoder.decode_message_delimited().unwrap();
+        assert_eq!(msg.0, 150);
+        assert_eq!(decoder.bytes_read(), 5);
+
+        // Under-run
+        let mut decoder = PbDecoder::new([2, 0x05, 0x06].as_slice());
+        assert_eq!(
+            decoder.decode_message_delimited::<FirstVarint>().err(),
+            Some(DecodeError::WrongLen)
+        );
+        // Over-run
+        let mut decoder = PbDecoder::new([1, 0x96, 0x01].as_slice());
+        assert_eq!(
+            decoder.decode_message_delimited::<FirstVarint>().err(),
+            Some(DecodeError::WrongLen)
+        );
+        // Truncated
+        let mut decoder = PbDecoder::new([1].as_slice());
+        assert_eq!(
+            decoder.decode_message_delimited::<FirstVarint>().err(),
+            Some(DecodeError::UnexpectedEof)
+        );
+    }
+
+    /// Unbuffered reader that returns one byte per read, interrupting every other read
+    struct Trickle<'a> {
+        data: &'a [u8],
+        interrupt: bool,
+    }
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            match self.data.split_first() {
+                Some((b, rest)) if !buf.is_empty() => {
+                    buf[0] = *b;
+                    self.data = rest;
+                    Ok(1)
+                }
+                Some(_) => Ok(0),
+                None => Err(std::io::ErrorKind::ConnectionReset.into()),
+            }
+        }
+    }
+
+    #[test]
+    fn std_reader_unbuffered() {
+        let data = [0x96, 0x01, 0x03, b'a', b'b', b'c'];
+        let reader = Trickle {
+            data: &data,
+            interrupt: false,
+        };
+        let mut decoder = PbDecoder::new(StdReader(std::io::BufReader::with_capacity(1, reader)));
+        assert_eq!(decoder.decode_varint32().unwrap(), 150);
+        let mut string = String::new();
+        decoder
+            .decode_string(&mut string, Presence::Explicit)
+            .unwrap();
+        assert_eq!(string, "abc");
+        assert_eq!(decoder.bytes_read(), data.len());
+
+        // Errors other than interruptions are returned, and convert back into I/O errors
+        let err = decoder.decode_varint32().unwrap_err();
+        assert!(
+            matches!(&err, DecodeError::Reader(e) if e.kind() == std::io::ErrorKind::ConnectionReset)
+        );
+        let err: std::io::Error = err.into();
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionReset);
+
+        let mut decoder = PbDecoder::new(StdReader::buffered([0x96].as_slice()));
+        let err: std::io::Error = decoder.decode_varint32().unwrap_err().into();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    #[cfg(feature = "decode-limits")]
+    fn memory_budget() {
+        let mut decoder = PbDecoder::new([3, b'a', b'b', b'c', 2, 0x01, 0x02].as_slice());
+        assert_eq!(decoder.memory_budget(), None);
+        decoder.set_memory_budget(Some(4));
+        let mut string = String::new();
+        decoder
+            .decode_string(&mut string, Presence::Explicit)
+            .unwrap();
+        assert_eq!(decoder.memory_budget(), Some(1));
+        let mut vec: Vec<u8> = Vec::new();
+        assert_eq!(
+            decoder.decode_bytes(&mut vec, Presence::Explicit),
+            Err(DecodeError::MemoryBudget)
+        );
+        // Nothing is read or allocated once the budget is exceeded
+        assert_eq!(vec.capacity(), 0);
+        assert_eq!(decoder.bytes_read(), 5);
+
+        let mut decoder = PbDecoder::new([4, 0x01, 0x02, 0x03, 0x04].as_slice());
+        decoder.set_memory_budget(Some(7));
+        let mut vec: Vec<u16> = Vec::new();
+        assert_eq!(
+            decoder.decode_packed(&mut vec, |d| d.decode_varint32().map(|v| v as u16)),
+            Err(DecodeError::MemoryBudget)
+        );
+        assert_eq!(vec, &[1, 2, 3]);
+    }
+
+    #[test]
+    #[cfg(feature = "decode-limits")]
+    fn len_limits() {
+        let buf = [3, b'a', b'b', b'c', 4, 1, 2, 3, 4];
+        let mut decoder = PbDecoder::new(buf.as_slice());
+        decoder.set_max_len_field(Some(3));
+        assert_eq!(decoder.max_len_field(), Some(3));
+        let mut string = String::new();
+        decoder
+            .decode_string(&mut string, Presence::Explicit)
+            .unwrap();
+        let mut vec: Vec<u8> = Vec::new();
+        assert_eq!(
+            decoder.decode_bytes(&mut vec, Presence::Explicit),
+            Err(DecodeError::LenLimit)
+        );
+        // Fails before the payload is read or the container is grown
+        assert_eq!(decoder.bytes_read(), 5);
+        assert_eq!(vec.capacity(), 0);
+
+        // Skipped values are limited too
+        let mut decoder = PbDecoder::new(buf.as_slice());
+        decoder.set_max_len_field(Some(2));
+        assert_eq!(
+            decoder.skip_wire_value(WIRE_TYPE_LEN),
+            Err(DecodeError::LenLimit)
+        );
+
+        let mut decoder = PbDecoder::new(buf.as_slice());
+        decoder.set_max_total_bytes(Some(8));
+        assert_eq!(decoder.max_total_bytes(), Some(8));
+        decoder.skip_wire_value(WIRE_TYPE_LEN).unwrap();
+        assert_eq!(
+            decoder.decode_bytes(&mut vec, Presence::Explicit),
+            Err(DecodeError::LenLimit)
+        );
+        assert_eq!(decoder.bytes_read(), 5);
+
+        // Fields aren't decoded once the limit is reached
+        let buf = [0x08, 0x01, 0x08, 0x02];
+        let mut decoder = PbDecoder::new(buf.as_slice());
+        decoder.set_max_total_bytes(Some(2));
+        assert_eq!(
+            decoder.decode_scalar_field(1, buf.len(), PbDecoder::decode_varint32),
+            Err(DecodeError::LenLimit)
+        );
+        assert_eq!(decoder.bytes_read(), 2);
+    }
+
+    #[test]
+    fn chunked_reader() {
+        // Tag, a varint, and a string, with boundaries in the middle of each value
+        let chunks: [&[u8]; 5] = [&[0x08, 0x96], &[], &[0x01, 0x12], &[3, b'a'], b"bc"];
+        let mut decoder = PbDecoder::new(ChunkedReader::new(chunks));
+        assert_eq!(
+            decoder.decode_tag(),
+            Ok(Tag::from_parts(1, WIRE_TYPE_VARINT))
+        );
+        assert_eq!(decoder.decode_varint32(), Ok(150));
+        assert_eq!(decoder.decode_tag(), Ok(Tag::from_parts(2, WIRE_TYPE_LEN)));
+        let mut s = ArrayString::<5>::new();
+        decoder.decode_string(&mut s, Presence::Explicit).unwrap();
+        assert_eq!(s.as_str(), "abc");
+        assert_eq!(decoder.bytes_read(), 8);
+        assert_eq!(decoder.decode_tag(), Err(DecodeError::UnexpectedEof));
+
+        // Works with iterators too
+        let frame = [0x08, 0x96, 0x01];
+        let mut decoder = PbDecoder::new(ChunkedReader::new(frame.chunks(1)));
+        assert_eq!(
+            decoder.decode_scalar_field(1, frame.len(), PbDecoder::decode_varint32),
+            Ok(Some(150))
+        );
+    }
+
+    #[test]
+    fn ring_reader() {
+        // Ring buffer where a length-delimited string wraps around the end
+        let ring = [b'c', b'd', 0x00, 0x00, 0x00, 4, b'a', b'b'];
+        let mut decoder = PbDecoder::new(RingReader::new(&ring[5..], &ring[..2]));
+        assert_eq!(decoder.as_reader().remaining(), 5);
+        let mut s = ArrayString::<5>::new();
+        decoder.decode_string(&mut s, Presence::Explicit).unwrap();
+        assert_eq!(s.as_str(), "abcd");
+        assert_eq!(decoder.as_reader().consumed(), 5);
+        assert_eq!(decoder.as_reader().remaining(), 0);
+        assert_eq!(decoder.decode_varint32(), Err(DecodeError::UnexpectedEof));
+
+        // Wrap-around in the middle of a value that's read in one go
+        let ring = [0x00, 0x00, 0x00, 0x80];
+        let mut decoder = PbDecoder::new(RingReader::new(&ring[1..], &ring[..1]));
+        assert_eq!(decoder.decode_fixed32(), Ok(0x0080_0000));
+        assert_eq!(decoder.as_reader().consumed(), 4);
+    }
+
+    #[test]
+    fn spsc_consumer() {
+        let mut queue = heapless::spsc::Queue::<u8, 8>::new();
+        let (mut producer, mut consumer) = queue.split();
+        for b in [0x08, 0x96, 0x01, 0x10, 0x05] {
+            producer.enqueue(b).unwrap();
+        }
+        let mut decoder = PbDecoder::new(&mut consumer);
+        assert_eq!(
+            decoder.decode_scalar_field(1, 3, PbDecoder::decode_varint32),
+            Ok(Some(150))
+        );
+        assert_eq!(consumer.len(), 2);
+
+        // Bytes enqueued after decoding started are read too
+        producer.enqueue(0x00).unwrap();
+        producer.enqueue(0x00).unwrap();
+        let mut decoder = PbDecoder::new(&mut consumer);
+        assert_eq!(decoder.decode_fixed32(), Ok(0x0510));
+        assert_eq!(decoder.decode_varint32(), Err(DecodeError::UnexpectedEof));
+
+        let mut queue = heapless_0_7::spsc::Queue::<u8, 4>::new();
+        let (mut producer, mut consumer) = queue.split();
+        producer.enqueue(0x05).unwrap();
+        let mut decoder = PbDecoder::new(&mut consumer);
+        assert_eq!(decoder.decode_sint32(), Ok(-3));
+
+        #[cfg(feature = "container-heapless-0_9")]
+        {
+            let mut queue = heapless_0_9::spsc::Queue::<u8, 4>::new();
+            let (mut producer, mut consumer) = queue.split();
+            producer.enqueue(0x05).unwrap();
+            let mut decoder = PbDecoder::new(&mut consumer);
+            assert_eq!(decoder.decode_sint32(), Ok(-3));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "decode-limits")]
+    fn reset() {
+        let frame1 = [0x08, 0x01, 0x08, 0x02];
+        let frame2 = [0x08, 0x03];
+        let mut decoder = PbDecoder::new(frame1.as_slice());
+        decoder.set_max_total_bytes(Some(4));
+        assert_eq!(
+            decoder.decode_scalar_field(1, frame1.len(), PbDecoder::decode_varint32),
+            Ok(Some(2))
+        );
+        assert_eq!(decoder.bytes_read(), 4);
+
+        // Swapping the reader keeps the position, so the total byte limit is already reached
+        let old = decoder.set_reader(frame2.as_slice());
+        assert!(old.is_empty());
+        assert_eq!(
+            decoder.decode_scalar_field(1, frame2.len(), PbDecoder::decode_varint32),
+            Err(DecodeError::LenLimit)
+        );
+
+        // Resetting starts over while keeping the limit
+        decoder.reset(frame2.as_slice());
+        assert_eq!(decoder.bytes_read(), 0);
+        assert_eq!(decoder.max_total_bytes(), Some(4));
+        assert_eq!(
+            decoder.decode_scalar_field(1, frame2.len(), PbDecoder::decode_varint32),
+            Ok(Some(3))
+        );
+        assert_eq!(decoder.bytes_read(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "decode-limits")]
+    fn should_continue() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        // Allow a fixed number of checks before cancelling
+        static CHECKS_LEFT: AtomicUsize = AtomicUsize::new(0);
+        extern "C" fn countdown() -> bool {
+            CHECKS_LEFT
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                .is_ok()
+        }
+
+        let buf = [0x08, 0x01, 0x08, 0x02, 0x0A, 0x03, 0x03, 0x04, 0x05];
+        let mut decoder = PbDecoder::new(buf.as_slice());
+        decoder.set_should_continue(Some(countdown));
+        CHECKS_LEFT.store(2, Ordering::Relaxed);
+        let mut vec: Vec<u32> = Vec::new();
+        assert_eq!(
+            decoder.decode_packed_field(1, &mut vec, buf.len(), PbDecoder::decode_varint32),
+            Err(DecodeError::Cancelled)
+        );
+        // Cancelled before the third tag
+        assert_eq!(vec, &[1, 2]);
+        assert_eq!(decoder.bytes_read(), 4);
+
+        // Packed elements are checked individually
+        let mut decoder = PbDecoder::new(buf.as_slice());
+        decoder.set_should_continue(Some(countdown));
+        CHECKS_LEFT.store(5, Ordering::Relaxed);
+        vec.clear();
+        assert_eq!(
+            decoder.decode_packed_field(1, &mut vec, buf.len(), PbDecoder::decode_varint32),
+            Err(DecodeError::Cancelled)
+        );
+        assert_eq!(vec, &[1, 2, 3, 4]);
+
+        decoder.set_should_continue(None);
+        assert!(decoder.check_continue().is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "field-path")]
+    fn field_path() {
+        let mut decoder = PbDecoder::new([].as_slice());
+        let err = decoder.error_ctx(DecodeError::UnexpectedEof);
+        assert!(err.path.is_empty());
+        assert_eq!(err.to_string(), "unexpected end of input");
+
+        for num in 0..MAX_FIELD_PATH as u32 + 2 {
+            decoder.push_error_field(num);
+        }
+        let err = decoder.error_ctx(DecodeError::UnexpectedEof);
+        assert_eq!(err.path.len(), MAX_FIELD_PATH);
+        assert!(err.path.is_truncated());
+        // Innermost field numbers are kept
+        assert_eq!(err.path.iter().last(), Some(0));
+        assert_eq!(err.path.iter().next(), Some(MAX_FIELD_PATH as u32 - 1));
+        assert!(err.path.to_string().starts_with("...15.14."));
+
+        decoder.push_error_field(2);
+        decoder.reset([].as_slice());
+        assert!(decoder.error_ctx(DecodeError::ZeroField).path.is_empty());
+    }
 }
@@ -0,0 +1,678 @@
+use never::Never;
+
+use crate::{
+    decode::Extras, DecodeError, DuplicateKey, MessageDecode, PbDecoder, TrailingBytes, WireType,
+};
+
+/// An async reader from which Protobuf data is read.
+///
+/// This is the async counterpart of [`PbRead`](crate::PbRead), used by [`AsyncPbDecoder`]. It's
+/// implemented for all [`embedded_io_async::Read`] types, such as UARTs and TCP sockets in
+/// Embassy.
+#[allow(async_fn_in_trait)]
+pub trait AsyncPbRead {
+    /// I/O error returned on read failure.
+    type Error;
+
+    /// Try to read exactly the number of bytes needed to fill `buf`.
+    ///
+    /// Returns the number of bytes read, which will be at most the size of `buf`. If the return is
+    /// less than `buf`, then the reader reached EOF before filling `buf`.
+    async fn pb_read_exact(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+impl<R: embedded_io_async::Read> AsyncPbRead for R {
+    type Error = R::Error;
+
+    async fn pb_read_exact(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut pos = 0;
+        while pos < buf.len() {
+            match self.read(&mut buf[pos..]).await? {
+                0 => break,
+                n => pos += n,
+            }
+        }
+        Ok(pos)
+    }
+}
+
+/// Decoder that reads Protobuf messages from an [`AsyncPbRead`].
+///
+/// Messages are decoded as their bytes arrive, so the whole message never needs to be in memory.
+/// Instead, each field is read into a scratch buffer and then decoded into the message with
+/// [`MessageDecode`]. Fields are merged into the message one at a time, which gives the same
+/// result as decoding the whole message at once. As a result, every message that implements
+/// [`MessageDecode`] can be decoded asynchronously, through
+/// [`MessageDecodeAsync`](crate::MessageDecodeAsync).
+///
+/// The scratch buffer must be large enough for the largest field on the wire, including its tag
+/// and length prefix. Note that a submessage field is buffered as a whole. Fields that don't fit
+/// cause [`DecodeError::Capacity`].
+///
+/// # Example
+///
+/// ```no_run
+/// use micropb::{AsyncPbDecoder, MessageDecodeAsync};
+///
+/// # #[derive(Default)]
+/// # struct ProtoMessage;
+/// # impl micropb::MessageDecode for ProtoMessage {
+/// #   fn decode<R: micropb::PbRead>(&mut self, decoder: &mut micropb::PbDecoder<R>, len: usize) -> Result<(), micropb::DecodeError<R::Error>> { todo!() }
+/// # }
+/// # async fn example(uart: impl embedded_io_async::Read) -> Result<(), ()> {
+/// let mut scratch = [0; 64];
+/// let mut decoder = AsyncPbDecoder::new(uart, &mut scratch);
+///
+/// let mut message = ProtoMessage::default();
+/// message.decode_len_delimited_async(&mut decoder).await.map_err(|_| ())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct AsyncPbDecoder<'s, R: AsyncPbRead> {
+    reader: R,
+    scratch: &'s mut [u8],
+    idx: usize,
+    /// Same as [`PbDecoder::ignore_repeated_cap_err`].
+    pub ignore_repeated_cap_err: bool,
+    /// Same as [`PbDecoder::trailing_bytes`].
+    pub trailing_bytes: TrailingBytes,
+    /// Same as [`PbDecoder::duplicate_keys`].
+    pub duplicate_keys: DuplicateKey,
+    assume_utf8: bool,
+    extras: Extras,
+}
+
+impl<'s, R: AsyncPbRead> AsyncPbDecoder<'s, R> {
+    #[inline]
+    /// Construct a new decoder from an [`AsyncPbRead`] and the scratch buffer that fields are read
+    /// into.
+    pub fn new(reader: R, scratch: &'s mut [u8]) -> Self {
+        Self {
+            reader,
+            scratch,
+            idx: 0,
+            ignore_repeated_cap_err: false,
+            trailing_bytes: TrailingBytes::Error,
+            duplicate_keys: DuplicateKey::LastWins,
+            assume_utf8: false,
+            extras: Extras::default(),
+        }
+    }
+
+    #[inline]
+    /// Transform the decoder into the underlying reader.
+    pub fn into_reader(self) -> R {
+        self.reader
+    }
+
+    #[inline]
+    /// Get reference to underlying reader.
+    pub fn as_reader(&self) -> &R {
+        &self.reader
+    }
+
+    #[inline]
+    /// Same as [`PbDecoder::set_reader`].
+    pub fn set_reader(&mut self, reader: R) -> R {
+        core::mem::replace(&mut self.reader, reader)
+    }
+
+    #[inline]
+    /// Same as [`PbDecoder::reset`]. The scratch buffer is kept.
+    pub fn reset(&mut self, reader: R) -> R {
+        self.idx = 0;
+        self.extras.reset();
+        self.set_reader(reader)
+    }
+
+    #[inline]
+    /// Get the number of bytes that the decoder has consumed from the reader.
+    pub fn bytes_read(&self) -> usize {
+        self.idx
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::set_memory_budget`].
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+        self.extras.limits.memory_budget = budget;
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::memory_budget`].
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.extras.limits.memory_budget
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::set_max_depth`], except that the top-level message isn't counted,
+    /// since it's decoded one field at a time instead of recursively.
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.extras.limits.max_depth = max_depth;
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::max_depth`].
+    pub fn max_depth(&self) -> Option<usize> {
+        self.extras.limits.max_depth
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::set_max_len_field`]. Length prefixes of top-level fields are checked
+    /// before the field is read into the scratch buffer.
+    pub fn set_max_len_field(&mut self, max_len: Option<usize>) {
+        self.extras.limits.max_len_field = max_len;
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::max_len_field`].
+    pub fn max_len_field(&self) -> Option<usize> {
+        self.extras.limits.max_len_field
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::set_max_total_bytes`], counting the bytes reported by
+    /// [`bytes_read`](Self::bytes_read).
+    pub fn set_max_total_bytes(&mut self, max_bytes: Option<usize>) {
+        self.extras.limits.max_total_bytes = max_bytes;
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::max_total_bytes`].
+    pub fn max_total_bytes(&self) -> Option<usize> {
+        self.extras.limits.max_total_bytes
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::set_should_continue`]. The hook is called while each buffered field
+    /// is decoded, not while waiting for input.
+    ///
+    /// # Panics
+    ///
+    /// The hook must never panic, since a panic escaping it is undefined behavior before Rust 1.81.
+    pub fn set_should_continue(&mut self, hook: Option<extern "C" fn() -> bool>) {
+        self.extras.limits.should_continue = hook;
+    }
+
+    #[inline]
+    /// Same as [`PbDecoder::set_assume_utf8`].
+    ///
+    /// # Safety
+    ///
+    /// While this is set, every `string` value decoded by this decoder must be valid UTF-8.
+    pub unsafe fn set_assume_utf8(&mut self, assume: bool) {
+        self.assume_utf8 = assume;
+    }
+
+    #[inline]
+    /// Same as [`PbDecoder::assume_utf8`].
+    pub fn assume_utf8(&self) -> bool {
+        self.assume_utf8
+    }
+
+    #[cfg(feature = "decode-stats")]
+    #[inline]
+    /// Get the total number of trailing bytes skipped under [`TrailingBytes::Report`].
+    pub fn trailing_bytes_skipped(&self) -> usize {
+        self.extras.trailing_bytes_skipped
+    }
+
+    #[cfg(feature = "decode-stats")]
+    #[inline]
+    /// Get the counts of data discarded by lossy decoding since the decoder was created or since
+    /// the last call to [`take_stats`](Self::take_stats).
+    pub fn stats(&self) -> crate::DecodeStats {
+        self.extras.stats
+    }
+
+    #[cfg(feature = "decode-stats")]
+    #[inline]
+    /// Get the counts of data discarded by lossy decoding and reset them to zero.
+    pub fn take_stats(&mut self) -> crate::DecodeStats {
+        core::mem::take(&mut self.extras.stats)
+    }
+
+    async fn read_into(&mut self, buf: &mut [u8]) -> Result<(), DecodeError<R::Error>> {
+        let n = self
+            .reader
+            .pb_read_exact(buf)
+            .await
+            .map_err(DecodeError::Reader)?;
+        self.idx += n;
+        if n < buf.len() {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        Ok(())
+    }
+
+    /// Read a varint into `buf` at `pos`, advancing `pos` past it. Returns the lower 32 bits of
+    /// the varint.
+    async fn read_varint32_into(
+        &mut self,
+        buf: &mut [u8],
+        pos: &mut usize,
+    ) -> Result<u32, DecodeError<R::Error>> {
+        let mut varint = 0;
+        for i in 0..10 {
+            let b = buf.get_mut(*pos..*pos + 1).ok_or(DecodeError::Capacity)?;
+            self.read_into(b).await?;
+            *pos += 1;
+            // Bytes after the 5th are truncated anyways
+            if i < 5 {
+                varint |= ((b[0] & !0x80) as u32) << (i * 7);
+            }
+            if b[0] & 0x80 == 0 {
+                return Ok(varint);
+            }
+        }
+        Err(DecodeError::VarIntLimit)
+    }
+
+    #[inline]
+    /// Check the length prefix of a value that's about to be read against the length limits.
+    fn check_len(&self, len: usize) -> Result<(), DecodeError<R::Error>> {
+        #[cfg(feature = "decode-limits")]
+        if self.extras.limits.exceeds_len(self.idx, len) {
+            return Err(DecodeError::LenLimit);
+        }
+        #[cfg(not(feature = "decode-limits"))]
+        let _ = len;
+        Ok(())
+    }
+
+    /// Decode an `uint32`.
+    pub async fn decode_varint32(&mut self) -> Result<u32, DecodeError<R::Error>> {
+        let mut buf = [0; 10];
+        self.read_varint32_into(&mut buf, &mut 0).await
+    }
+
+    /// Decode a message of `len` bytes from the reader and merge it into `msg`.
+    ///
+    /// Used by [`MessageDecodeAsync::decode_async`](crate::MessageDecodeAsync::decode_async).
+    pub async fn decode_message<M: MessageDecode + ?Sized>(
+        &mut self,
+        msg: &mut M,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        // Move the scratch buffer out of the decoder so that we can read into it
+        let scratch = core::mem::take(&mut self.scratch);
+        let res = self.decode_fields(msg, len, scratch).await;
+        self.scratch = scratch;
+        res?;
+        // Fields are decoded one at a time, so the message can only be checked at the end
+        msg.check_required()
+    }
+
+    /// Decode a length-delimited message from the reader, starting with a length prefix, and
+    /// merge it into `msg`.
+    ///
+    /// Used by
+    /// [`MessageDecodeAsync::decode_len_delimited_async`](crate::MessageDecodeAsync::decode_len_delimited_async).
+    pub async fn decode_len_delimited_message<M: MessageDecode + ?Sized>(
+        &mut self,
+        msg: &mut M,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let len = self.decode_varint32().await? as usize;
+        self.check_len(len)?;
+        let before = self.bytes_read();
+        self.decode_message(msg, len).await?;
+        if self.bytes_read() - before != len {
+            return Err(DecodeError::WrongLen);
+        }
+        Ok(())
+    }
+
+    async fn decode_fields<M: MessageDecode + ?Sized>(
+        &mut self,
+        msg: &mut M,
+        len: usize,
+        scratch: &mut [u8],
+    ) -> Result<(), DecodeError<R::Error>> {
+        let before = self.bytes_read();
+        while self.bytes_read() - before < len {
+            let start = self.bytes_read();
+            #[cfg(feature = "decode-limits")]
+            if self.extras.limits.exceeds_total(start) {
+                return Err(DecodeError::LenLimit);
+            }
+            // Read the tag and value of the next field into the scratch buffer
+            let mut pos = 0;
+            let tag = self.read_varint32_into(scratch, &mut pos).await?;
+            match WireType::from_u8((tag & 0b111) as u8) {
+                Some(WireType::Varint) => {
+                    self.read_varint32_into(scratch, &mut pos).await?;
+                }
+                Some(WireType::I64) => self.read_value(scratch, &mut pos, 8).await?,
+                Some(WireType::I32) => self.read_value(scratch, &mut pos, 4).await?,
+                Some(WireType::Len) => {
+                    let len = self.read_varint32_into(scratch, &mut pos).await? as usize;
+                    self.check_len(len)?;
+                    self.read_value(scratch, &mut pos, len).await?;
+                }
+                Some(WireType::StartGroup | WireType::EndGroup) => {
+                    return Err(DecodeError::Deprecation)
+                }
+                None => return Err(DecodeError::UnknownWireType),
+            }
+
+            let mut decoder = PbDecoder::new(&scratch[..pos]);
+            decoder.ignore_repeated_cap_err = self.ignore_repeated_cap_err;
+            decoder.trailing_bytes = self.trailing_bytes;
+            decoder.duplicate_keys = self.duplicate_keys;
+            // Carry the limits and counters through the field's decoder
+            decoder.extras = self.extras.for_field(start);
+            // SAFETY: The caller of our set_assume_utf8 guaranteed that all strings are UTF-8
+            unsafe { decoder.set_assume_utf8(self.assume_utf8) };
+            let res = msg.decode(&mut decoder, pos);
+            self.extras.update_from_field(&decoder.extras);
+            res.map_err(widen_error)?;
+        }
+        Ok(())
+    }
+
+    async fn read_value(
+        &mut self,
+        buf: &mut [u8],
+        pos: &mut usize,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let target = pos
+            .checked_add(len)
+            .and_then(|end| buf.get_mut(*pos..end))
+            .ok_or(DecodeError::Capacity)?;
+        self.read_into(target).await?;
+        *pos += len;
+        Ok(())
+    }
+}
+
+/// Converts an error from decoding a slice into an error of any reader type.
+fn widen_error<E>(err: DecodeError<Never>) -> DecodeError<E> {
+    match err {
+        DecodeError::VarIntLimit => DecodeError::VarIntLimit,
+        DecodeError::UnexpectedEof => DecodeError::UnexpectedEof,
+        DecodeError::Deprecation => DecodeError::Deprecation,
+        DecodeError::UnknownWireType => DecodeError::UnknownWireType,
+        DecodeError::ZeroField => DecodeError::ZeroField,
+        DecodeError::CustomField => DecodeError::CustomField,
+        DecodeError::Utf8 => DecodeError::Utf8,
+        DecodeError::Capacity => DecodeError::Capacity,
+        DecodeError::WrongLen => DecodeError::WrongLen,
+        DecodeError::OutOfRange => DecodeError::OutOfRange,
+        DecodeError::MemoryBudget => DecodeError::MemoryBudget,
+        DecodeError::Cancelled => DecodeError::Cancelled,
+        DecodeError::DepthLimit => DecodeError::DepthLimit,
+        DecodeError::LenLimit => DecodeError::LenLimit,
+        DecodeError::UnknownEnum => DecodeError::UnknownEnum,
+        DecodeError::UnknownField(num) => DecodeError::UnknownField(num),
+        DecodeError::MissingRequired(num) => DecodeError::MissingRequired(num),
+        DecodeError::DuplicateKey => DecodeError::DuplicateKey,
+        DecodeError::Reader(never) => match never {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        convert::Infallible,
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+        time::Duration,
+    };
+
+    use crate::{MessageDecodeAsync, PbRead};
+
+    use super::*;
+
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        fn raw_waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker {
+                raw_waker()
+            }
+            fn noop(_: *const ()) {}
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(core::ptr::null(), &VTABLE)
+        }
+        // SAFETY: The waker functions don't use the data pointer
+        let waker = unsafe { Waker::from_raw(raw_waker()) };
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = pin!(fut);
+        loop {
+            if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+                return out;
+            }
+        }
+    }
+
+    /// Reader that returns one byte per read and yields before each read, like a slow UART
+    struct Trickle<'a> {
+        data: &'a [u8],
+        pending: bool,
+    }
+
+    impl embedded_io_async::ErrorType for Trickle<'_> {
+        type Error = Infallible;
+    }
+
+    impl embedded_io_async::Read for Trickle<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            core::future::poll_fn(|_| {
+                self.pending = !self.pending;
+                if self.pending {
+                    Poll::Pending
+                } else {
+                    Poll::Ready(())
+                }
+            })
+            .await;
+            let Some((&b, rest)) = self.data.split_first() else {
+                return Ok(0);
+            };
+            if let Some(first) = buf.first_mut() {
+                *first = b;
+                self.data = rest;
+                return Ok(1);
+            }
+            Ok(0)
+        }
+    }
+
+    fn trickle(data: &[u8]) -> Trickle<'_> {
+        Trickle {
+            data,
+            pending: false,
+        }
+    }
+
+    #[test]
+    fn decode_message() {
+        // Duration of 300 seconds and 5 nanoseconds, with the seconds repeated
+        let data = [0x7, 0x08, 0x01, 0x08, 0xAC, 0x02, 0x10, 0x05];
+        let mut scratch = [0; 3];
+        let mut decoder = AsyncPbDecoder::new(trickle(&data), &mut scratch);
+        let mut dur = Duration::default();
+        block_on(dur.decode_len_delimited_async(&mut decoder)).unwrap();
+        assert_eq!(dur, Duration::new(300, 5));
+        assert_eq!(decoder.bytes_read(), data.len());
+
+        let mut sync = Duration::default();
+        sync.merge_from_slice(&data[1..]).unwrap();
+        assert_eq!(dur, sync);
+    }
+
+    #[test]
+    fn decode_len_fields() {
+        let data = [0x0A, 0x02, b'a', b'b', 0x0A, 0x01, b'c'];
+        let mut scratch = [0; 4];
+        let mut decoder = AsyncPbDecoder::new(data.as_slice(), &mut scratch);
+        let mut string = String::new();
+        block_on(string.decode_async(&mut decoder, data.len())).unwrap();
+        assert_eq!(string, "c");
+
+        // Field doesn't fit into the scratch buffer
+        let mut scratch = [0; 3];
+        let mut decoder = AsyncPbDecoder::new(data.as_slice(), &mut scratch);
+        assert_eq!(
+            block_on(string.decode_async(&mut decoder, data.len())),
+            Err(DecodeError::Capacity)
+        );
+    }
+
+    #[test]
+    fn decode_errors() {
+        let mut scratch = [0; 16];
+        let mut dur = Duration::default();
+
+        let mut decoder = AsyncPbDecoder::new(trickle(&[0x08, 0x80]), &mut scratch);
+        assert_eq!(
+            block_on(dur.decode_async(&mut decoder, 2)),
+            Err(DecodeError::UnexpectedEof)
+        );
+
+        // Field extends past the end of the record
+        let mut decoder = AsyncPbDecoder::new(trickle(&[0x01, 0x08, 0x01]), &mut scratch);
+        assert_eq!(
+            block_on(dur.decode_len_delimited_async(&mut decoder)),
+            Err(DecodeError::WrongLen)
+        );
+
+        let mut decoder = AsyncPbDecoder::new(trickle(&[0x0B, 0x00]), &mut scratch);
+        assert_eq!(
+            block_on(dur.decode_async(&mut decoder, 2)),
+            Err(DecodeError::Deprecation)
+        );
+
+        let mut decoder = AsyncPbDecoder::new(trickle(&[0x00, 0x00]), &mut scratch);
+        assert_eq!(
+            block_on(dur.decode_async(&mut decoder, 2)),
+            Err(DecodeError::ZeroField)
+        );
+    }
+
+    #[test]
+    fn required_fields() {
+        /// Message with two required `uint32` fields, checked like an `enforce_required` message
+        #[derive(Default)]
+        struct Pair(Option<u32>, Option<u32>);
+
+        impl MessageDecode for Pair {
+            fn decode<R: PbRead>(
+                &mut self,
+                decoder: &mut PbDecoder<R>,
+                len: usize,
+            ) -> Result<(), DecodeError<R::Error>> {
+                let before = decoder.bytes_read();
+                while decoder.bytes_read() - before < len {
+                    let tag = decoder.decode_tag()?;
+                    match tag.field_num() {
+                        1 => self.0 = Some(decoder.decode_varint32()?),
+                        2 => self.1 = Some(decoder.decode_varint32()?),
+                        _ => decoder.skip_wire_value(tag.wire_type())?,
+                    }
+                }
+                Ok(())
+            }
+
+            fn check_required<E>(&self) -> Result<(), DecodeError<E>> {
+                match self {
+                    Pair(None, _) => Err(DecodeError::MissingRequired(1)),
+                    Pair(_, None) => Err(DecodeError::MissingRequired(2)),
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        // Each field is decoded separately, but the message is only checked once all are present
+        let mut scratch = [0; 2];
+        let data = [0x4, 0x08, 0x01, 0x10, 0x02];
+        let mut decoder = AsyncPbDecoder::new(trickle(&data), &mut scratch);
+        let mut pair = Pair::default();
+        block_on(pair.decode_len_delimited_async(&mut decoder)).unwrap();
+        assert_eq!((pair.0, pair.1), (Some(1), Some(2)));
+
+        let mut decoder = AsyncPbDecoder::new(trickle(&data[1..3]), &mut scratch);
+        assert_eq!(
+            block_on(Pair::default().decode_async(&mut decoder, 2)),
+            Err(DecodeError::MissingRequired(2))
+        );
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[test]
+    fn len_limits() {
+        let data = [0x4, 0x0A, 0x02, b'a', b'b'];
+        let mut scratch = [0; 16];
+        let mut string = String::new();
+
+        // The length prefix of the message is checked too
+        let mut decoder = AsyncPbDecoder::new(trickle(&data), &mut scratch);
+        decoder.set_max_len_field(Some(3));
+        assert_eq!(decoder.max_len_field(), Some(3));
+        assert_eq!(
+            block_on(string.decode_len_delimited_async(&mut decoder)),
+            Err(DecodeError::LenLimit)
+        );
+        assert_eq!(decoder.bytes_read(), 1);
+
+        // Length-delimited fields are rejected before they're read into the scratch buffer
+        let mut decoder = AsyncPbDecoder::new(trickle(&data[1..]), &mut scratch);
+        decoder.set_max_len_field(Some(1));
+        assert_eq!(
+            block_on(string.decode_async(&mut decoder, 4)),
+            Err(DecodeError::LenLimit)
+        );
+        assert_eq!(decoder.bytes_read(), 2);
+
+        // The total byte count includes the fields that were already decoded
+        let data = [0x4, 0x08, 0x01, 0x10, 0x05];
+        let mut dur = Duration::default();
+        let mut decoder = AsyncPbDecoder::new(trickle(&data[1..]), &mut scratch);
+        decoder.set_max_total_bytes(Some(2));
+        assert_eq!(decoder.max_total_bytes(), Some(2));
+        assert_eq!(
+            block_on(dur.decode_async(&mut decoder, 4)),
+            Err(DecodeError::LenLimit)
+        );
+        assert_eq!(dur, Duration::from_secs(1));
+
+        // A message that would end past the limit fails before any of it is read
+        let mut decoder = AsyncPbDecoder::new(trickle(&data), &mut scratch);
+        decoder.set_max_total_bytes(Some(4));
+        assert_eq!(
+            block_on(dur.decode_len_delimited_async(&mut decoder)),
+            Err(DecodeError::LenLimit)
+        );
+        assert_eq!(decoder.bytes_read(), 1);
+
+        let mut decoder = AsyncPbDecoder::new(trickle(&data), &mut scratch);
+        decoder.set_max_total_bytes(Some(5));
+        block_on(dur.decode_len_delimited_async(&mut decoder)).unwrap();
+        assert_eq!(dur, Duration::new(1, 5));
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[test]
+    fn should_continue() {
+        extern "C" fn stop() -> bool {
+            false
+        }
+
+        let mut scratch = [0; 16];
+        let mut decoder = AsyncPbDecoder::new(trickle(&[0x2, 0x08, 0x01]), &mut scratch);
+        decoder.set_should_continue(Some(stop));
+        assert_eq!(
+            block_on(Duration::default().decode_len_delimited_async(&mut decoder)),
+            Err(DecodeError::Cancelled)
+        );
+    }
+}
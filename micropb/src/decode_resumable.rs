@@ -0,0 +1,659 @@
+use crate::{
+    decode::Extras, DecodeError, DuplicateKey, MessageDecode, PbDecoder, TrailingBytes, WireType,
+};
+
+use never::Never;
+
+/// Progress of a [`ResumablePbDecoder`] after it's been fed a chunk of input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeStatus {
+    /// The whole chunk was consumed, but the message isn't complete yet.
+    NeedMoreData,
+    /// The message is complete. `consumed` is the number of bytes of the last chunk that belong
+    /// to the message, so the rest of the chunk is the start of the next message.
+    Done {
+        /// Number of bytes of the chunk that were consumed
+        consumed: usize,
+    },
+}
+
+/// Partially read varint
+#[derive(Debug, Clone, Copy, Default)]
+struct Varint {
+    value: u64,
+    len: u8,
+}
+
+impl Varint {
+    /// Add a byte to the varint, returning the value once the last byte is added.
+    fn push(&mut self, b: u8) -> Result<Option<u64>, DecodeError<Never>> {
+        if self.len >= 10 {
+            return Err(DecodeError::VarIntLimit);
+        }
+        // Bits beyond 64 are truncated
+        let shift = self.len as u32 * 7;
+        if shift < 64 {
+            self.value |= ((b & 0x7F) as u64) << shift;
+        }
+        self.len += 1;
+        Ok((b & 0x80 == 0).then_some(self.value))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Reading the length prefix of the message
+    Prefix(Varint),
+    /// Reading the tag of the next field
+    Tag(Varint),
+    /// Reading the value of a varint field
+    Varint(Varint),
+    /// Reading the length of a length-delimited field
+    Len(Varint),
+    /// Reading the remaining bytes of a field
+    Bytes(usize),
+    Done,
+}
+
+/// Decoder that decodes a Protobuf message from chunks of input, pausing whenever it runs out of
+/// data.
+///
+/// Unlike [`PbDecoder`], which needs the whole message to be readable at once, this decoder is
+/// fed one chunk at a time with [`feed`](Self::feed), such as each UART DMA transfer. If the
+/// chunk ends in the middle of a message, the decoder returns [`DecodeStatus::NeedMoreData`] and
+/// saves its progress, including partially read varints, so that decoding continues where it
+/// left off once the next chunk arrives.
+///
+/// Like with `AsyncPbDecoder`, each field is collected into a scratch buffer and then merged into
+/// the message with [`MessageDecode`], so every generated message can be decoded this way. The
+/// scratch buffer must be large enough for the largest field on the wire, including its tag and
+/// length prefix. Note that a submessage field is buffered as a whole. Fields that don't fit
+/// cause [`DecodeError::Capacity`].
+///
+/// After an error, the message may be partially decoded, and the decoder must be
+/// [`reset`](Self::reset) before decoding another message.
+///
+/// # Example
+///
+/// ```no_run
+/// use micropb::{DecodeStatus, ResumablePbDecoder};
+///
+/// # #[derive(Default)]
+/// # struct ProtoMessage;
+/// # impl micropb::MessageDecode for ProtoMessage {
+/// #   fn decode<R: micropb::PbRead>(&mut self, decoder: &mut micropb::PbDecoder<R>, len: usize) -> Result<(), micropb::DecodeError<R::Error>> { todo!() }
+/// # }
+/// # fn handle(_: ProtoMessage) {}
+/// # let chunks: &[&[u8]] = &[];
+/// let mut scratch = [0; 64];
+/// // Decode length-delimited messages
+/// let mut decoder = ResumablePbDecoder::new(&mut scratch);
+/// let mut message = ProtoMessage::default();
+///
+/// for mut chunk in chunks.iter().copied() {
+///     // A chunk can contain the end of one message and the start of the next
+///     while let DecodeStatus::Done { consumed } = decoder.feed(&mut message, chunk)? {
+///         handle(core::mem::take(&mut message));
+///         decoder.reset(None);
+///         chunk = &chunk[consumed..];
+///     }
+/// }
+/// # Ok::<(), micropb::DecodeError<never::Never>>(())
+/// ```
+#[derive(Debug)]
+pub struct ResumablePbDecoder<'s> {
+    scratch: &'s mut [u8],
+    // Number of bytes of the current field in the scratch buffer
+    pos: usize,
+    state: State,
+    // Remaining bytes of the message, or `None` while the length prefix is being read
+    remaining: Option<usize>,
+    idx: usize,
+    /// Same as [`PbDecoder::ignore_repeated_cap_err`].
+    pub ignore_repeated_cap_err: bool,
+    /// Same as [`PbDecoder::trailing_bytes`].
+    pub trailing_bytes: TrailingBytes,
+    /// Same as [`PbDecoder::duplicate_keys`].
+    pub duplicate_keys: DuplicateKey,
+    assume_utf8: bool,
+    extras: Extras,
+}
+
+impl<'s> ResumablePbDecoder<'s> {
+    #[inline]
+    /// Construct a decoder for a length-delimited message, which starts with a length prefix.
+    pub fn new(scratch: &'s mut [u8]) -> Self {
+        Self::with_len(scratch, None)
+    }
+
+    #[inline]
+    /// Construct a decoder for a message of `len` bytes if `len` is set. Otherwise, the message
+    /// is expected to start with a length prefix.
+    pub fn with_len(scratch: &'s mut [u8], len: Option<usize>) -> Self {
+        let mut decoder = Self {
+            scratch,
+            pos: 0,
+            state: State::Done,
+            remaining: None,
+            idx: 0,
+            ignore_repeated_cap_err: false,
+            trailing_bytes: TrailingBytes::Error,
+            duplicate_keys: DuplicateKey::LastWins,
+            assume_utf8: false,
+            extras: Extras::default(),
+        };
+        decoder.reset(len);
+        decoder
+    }
+
+    /// Discard the progress on the current message and start decoding a new message of `len`
+    /// bytes if `len` is set, or a length-delimited message otherwise.
+    ///
+    /// The settings, statistics, and byte count of the decoder are kept.
+    pub fn reset(&mut self, len: Option<usize>) {
+        self.pos = 0;
+        self.remaining = len;
+        self.state = match len {
+            Some(_) => State::Tag(Varint::default()),
+            None => State::Prefix(Varint::default()),
+        };
+    }
+
+    #[inline]
+    /// Get the number of bytes that the decoder has consumed from all chunks.
+    pub fn bytes_read(&self) -> usize {
+        self.idx
+    }
+
+    #[inline]
+    /// Returns `true` if the current message has been decoded completely.
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::set_memory_budget`].
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+        self.extras.limits.memory_budget = budget;
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::memory_budget`].
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.extras.limits.memory_budget
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::set_max_depth`], except that the top-level message isn't counted,
+    /// since it's decoded one field at a time instead of recursively.
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.extras.limits.max_depth = max_depth;
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::max_depth`].
+    pub fn max_depth(&self) -> Option<usize> {
+        self.extras.limits.max_depth
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::set_max_len_field`]. Length prefixes of top-level fields are checked
+    /// before the field is read into the scratch buffer.
+    pub fn set_max_len_field(&mut self, max_len: Option<usize>) {
+        self.extras.limits.max_len_field = max_len;
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::max_len_field`].
+    pub fn max_len_field(&self) -> Option<usize> {
+        self.extras.limits.max_len_field
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::set_max_total_bytes`], counting the bytes reported by
+    /// [`bytes_read`](Self::bytes_read).
+    pub fn set_max_total_bytes(&mut self, max_bytes: Option<usize>) {
+        self.extras.limits.max_total_bytes = max_bytes;
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::max_total_bytes`].
+    pub fn max_total_bytes(&self) -> Option<usize> {
+        self.extras.limits.max_total_bytes
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[inline]
+    /// Same as [`PbDecoder::set_should_continue`]. The hook is called while each buffered field
+    /// is decoded, rather than for every chunk.
+    ///
+    /// # Panics
+    ///
+    /// The hook must never panic, since a panic escaping it is undefined behavior before Rust 1.81.
+    pub fn set_should_continue(&mut self, hook: Option<extern "C" fn() -> bool>) {
+        self.extras.limits.should_continue = hook;
+    }
+
+    #[inline]
+    /// Same as [`PbDecoder::set_assume_utf8`].
+    ///
+    /// # Safety
+    ///
+    /// While this is set, every `string` value decoded by this decoder must be valid UTF-8.
+    pub unsafe fn set_assume_utf8(&mut self, assume: bool) {
+        self.assume_utf8 = assume;
+    }
+
+    #[inline]
+    /// Same as [`PbDecoder::assume_utf8`].
+    pub fn assume_utf8(&self) -> bool {
+        self.assume_utf8
+    }
+
+    #[cfg(feature = "decode-stats")]
+    #[inline]
+    /// Get the total number of trailing bytes skipped under [`TrailingBytes::Report`].
+    pub fn trailing_bytes_skipped(&self) -> usize {
+        self.extras.trailing_bytes_skipped
+    }
+
+    #[cfg(feature = "decode-stats")]
+    #[inline]
+    /// Get the counts of data discarded by lossy decoding since the decoder was created or since
+    /// the last call to [`take_stats`](Self::take_stats).
+    pub fn stats(&self) -> crate::DecodeStats {
+        self.extras.stats
+    }
+
+    #[cfg(feature = "decode-stats")]
+    #[inline]
+    /// Get the counts of data discarded by lossy decoding and reset them to zero.
+    pub fn take_stats(&mut self) -> crate::DecodeStats {
+        core::mem::take(&mut self.extras.stats)
+    }
+
+    /// Decode the next chunk of input and merge its fields into `msg`.
+    ///
+    /// Returns [`DecodeStatus::Done`] once the message is complete, along with the number of
+    /// bytes of `chunk` that belong to the message. Afterwards, the decoder doesn't consume any
+    /// more input until it's [`reset`](Self::reset). If the chunk ends before the message does,
+    /// [`DecodeStatus::NeedMoreData`] is returned, and the same message must be passed to the next
+    /// call.
+    pub fn feed<M: MessageDecode + ?Sized>(
+        &mut self,
+        msg: &mut M,
+        chunk: &[u8],
+    ) -> Result<DecodeStatus, DecodeError<Never>> {
+        let mut used = 0;
+        loop {
+            if let State::Tag(Varint { len: 0, .. }) = self.state {
+                if self.remaining == Some(0) {
+                    self.state = State::Done;
+                    // Fields are decoded one at a time, so the message can only be checked once
+                    // it's complete
+                    msg.check_required()?;
+                }
+            }
+            if let State::Done = self.state {
+                return Ok(DecodeStatus::Done { consumed: used });
+            }
+
+            let data = &chunk[used..];
+            if data.is_empty() {
+                return Ok(DecodeStatus::NeedMoreData);
+            }
+            // The message ended in the middle of a field
+            if self.remaining == Some(0) {
+                return Err(DecodeError::WrongLen);
+            }
+
+            if let State::Bytes(left) = self.state {
+                let n = left
+                    .min(data.len())
+                    .min(self.remaining.unwrap_or(usize::MAX));
+                self.buffer(&data[..n])?;
+                used += n;
+                self.state = State::Bytes(left - n);
+                if left == n {
+                    self.end_field(msg)?;
+                }
+                continue;
+            }
+
+            let b = data[0];
+            used += 1;
+            match &mut self.state {
+                State::Prefix(varint) => {
+                    self.idx += 1;
+                    if let Some(len) = varint.push(b)? {
+                        let len = len as u32 as usize;
+                        self.check_len(len)?;
+                        self.remaining = Some(len);
+                        self.state = State::Tag(Varint::default());
+                    }
+                }
+                State::Tag(varint) => {
+                    #[cfg(feature = "decode-limits")]
+                    if varint.len == 0 && self.extras.limits.exceeds_total(self.idx) {
+                        return Err(DecodeError::LenLimit);
+                    }
+                    let tag = varint.push(b)?;
+                    self.buffer(&[b])?;
+                    if let Some(tag) = tag {
+                        self.state = match WireType::from_u8((tag & 0b111) as u8) {
+                            Some(WireType::Varint) => State::Varint(Varint::default()),
+                            Some(WireType::I64) => State::Bytes(8),
+                            Some(WireType::I32) => State::Bytes(4),
+                            Some(WireType::Len) => State::Len(Varint::default()),
+                            Some(WireType::StartGroup | WireType::EndGroup) => {
+                                return Err(DecodeError::Deprecation)
+                            }
+                            None => return Err(DecodeError::UnknownWireType),
+                        };
+                    }
+                }
+                State::Varint(varint) => {
+                    let val = varint.push(b)?;
+                    self.buffer(&[b])?;
+                    if val.is_some() {
+                        self.end_field(msg)?;
+                    }
+                }
+                State::Len(varint) => {
+                    let len = varint.push(b)?;
+                    self.buffer(&[b])?;
+                    if let Some(len) = len {
+                        let len = len as u32 as usize;
+                        self.check_len(len)?;
+                        if len > self.scratch.len() - self.pos {
+                            return Err(DecodeError::Capacity);
+                        }
+                        self.state = State::Bytes(len);
+                        if len == 0 {
+                            self.end_field(msg)?;
+                        }
+                    }
+                }
+                State::Bytes(_) | State::Done => {}
+            }
+        }
+    }
+
+    #[inline]
+    /// Check the length prefix of a value that's about to be buffered against the length limits.
+    fn check_len(&self, len: usize) -> Result<(), DecodeError<Never>> {
+        #[cfg(feature = "decode-limits")]
+        if self.extras.limits.exceeds_len(self.idx, len) {
+            return Err(DecodeError::LenLimit);
+        }
+        #[cfg(not(feature = "decode-limits"))]
+        let _ = len;
+        Ok(())
+    }
+
+    /// Append bytes of the message body to the current field in the scratch buffer.
+    fn buffer(&mut self, data: &[u8]) -> Result<(), DecodeError<Never>> {
+        let end = self.pos + data.len();
+        self.scratch
+            .get_mut(self.pos..end)
+            .ok_or(DecodeError::Capacity)?
+            .copy_from_slice(data);
+        self.pos = end;
+        self.idx += data.len();
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= data.len();
+        }
+        Ok(())
+    }
+
+    /// Decode the field in the scratch buffer into the message.
+    fn end_field<M: MessageDecode + ?Sized>(
+        &mut self,
+        msg: &mut M,
+    ) -> Result<(), DecodeError<Never>> {
+        let len = core::mem::take(&mut self.pos);
+        self.state = State::Tag(Varint::default());
+
+        let mut decoder = PbDecoder::new(&self.scratch[..len]);
+        decoder.ignore_repeated_cap_err = self.ignore_repeated_cap_err;
+        decoder.trailing_bytes = self.trailing_bytes;
+        decoder.duplicate_keys = self.duplicate_keys;
+        // Carry the limits and counters through the field's decoder
+        decoder.extras = self.extras.for_field(self.idx - len);
+        // SAFETY: The caller of our set_assume_utf8 guaranteed that all strings are UTF-8
+        unsafe { decoder.set_assume_utf8(self.assume_utf8) };
+        let res = msg.decode(&mut decoder, len);
+        self.extras.update_from_field(&decoder.extras);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::*;
+
+    /// Feed `data` to the decoder in chunks of `chunk_len` bytes
+    fn feed_chunks<M: MessageDecode>(
+        decoder: &mut ResumablePbDecoder,
+        msg: &mut M,
+        data: &[u8],
+        chunk_len: usize,
+    ) -> Result<DecodeStatus, DecodeError<Never>> {
+        let mut status = DecodeStatus::NeedMoreData;
+        for chunk in data.chunks(chunk_len) {
+            status = decoder.feed(msg, chunk)?;
+        }
+        Ok(status)
+    }
+
+    #[test]
+    fn decode_chunks() {
+        // Duration of 300 seconds and 5 nanoseconds, with the seconds repeated
+        let data = [0x7, 0x08, 0x01, 0x08, 0xAC, 0x02, 0x10, 0x05];
+        for chunk_len in 1..=data.len() {
+            let mut scratch = [0; 3];
+            let mut decoder = ResumablePbDecoder::new(&mut scratch);
+            let mut dur = Duration::default();
+            let status = feed_chunks(&mut decoder, &mut dur, &data, chunk_len).unwrap();
+            assert!(matches!(status, DecodeStatus::Done { .. }));
+            assert!(decoder.is_done());
+            assert_eq!(dur, Duration::new(300, 5));
+            assert_eq!(decoder.bytes_read(), data.len());
+        }
+
+        // Message of a known length
+        let mut scratch = [0; 3];
+        let mut decoder = ResumablePbDecoder::with_len(&mut scratch, Some(5));
+        let mut dur = Duration::default();
+        assert_eq!(
+            decoder.feed(&mut dur, &data[3..5]),
+            Ok(DecodeStatus::NeedMoreData)
+        );
+        assert_eq!(
+            decoder.feed(&mut dur, &data[5..]),
+            Ok(DecodeStatus::Done { consumed: 3 })
+        );
+        assert_eq!(dur, Duration::new(300, 5));
+    }
+
+    #[test]
+    fn decode_consecutive() {
+        // Two messages in one chunk, followed by the start of a third
+        let data = [0x2, 0x08, 0x01, 0x0, 0x2, 0x10];
+        let mut scratch = [0; 3];
+        let mut decoder = ResumablePbDecoder::new(&mut scratch);
+        let mut dur = Duration::default();
+
+        assert_eq!(
+            decoder.feed(&mut dur, &data),
+            Ok(DecodeStatus::Done { consumed: 3 })
+        );
+        assert_eq!(dur, Duration::from_secs(1));
+        // No more input is consumed until the decoder is reset
+        assert_eq!(
+            decoder.feed(&mut dur, &data[3..]),
+            Ok(DecodeStatus::Done { consumed: 0 })
+        );
+
+        decoder.reset(None);
+        let mut dur = Duration::default();
+        assert_eq!(
+            decoder.feed(&mut dur, &data[3..]),
+            Ok(DecodeStatus::Done { consumed: 1 })
+        );
+        assert_eq!(dur, Duration::ZERO);
+
+        decoder.reset(None);
+        assert_eq!(
+            decoder.feed(&mut dur, &data[4..]),
+            Ok(DecodeStatus::NeedMoreData)
+        );
+        assert_eq!(
+            decoder.feed(&mut dur, &[0x05]),
+            Ok(DecodeStatus::Done { consumed: 1 })
+        );
+        assert_eq!(dur, Duration::from_nanos(5));
+        assert_eq!(decoder.bytes_read(), data.len() + 1);
+    }
+
+    #[test]
+    fn decode_len_fields() {
+        let data = [0x6, 0x0A, 0x04, b'a', b'b', b'c', b'd'];
+        for chunk_len in 1..=data.len() {
+            let mut scratch = [0; 6];
+            let mut decoder = ResumablePbDecoder::new(&mut scratch);
+            let mut string = String::new();
+            struct Msg<'a>(&'a mut String);
+            impl MessageDecode for Msg<'_> {
+                fn decode<R: crate::PbRead>(
+                    &mut self,
+                    decoder: &mut PbDecoder<R>,
+                    len: usize,
+                ) -> Result<(), DecodeError<R::Error>> {
+                    decoder
+                        .decode_scalar_field(1, len, |d| {
+                            d.decode_string(self.0, crate::Presence::Explicit)
+                        })
+                        .map(drop)
+                }
+            }
+            let status = feed_chunks(&mut decoder, &mut Msg(&mut string), &data, chunk_len);
+            assert!(matches!(status, Ok(DecodeStatus::Done { .. })));
+            assert_eq!(string, "abcd");
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let mut dur = Duration::default();
+
+        // Field too large for the scratch buffer
+        let mut scratch = [0; 2];
+        let mut decoder = ResumablePbDecoder::new(&mut scratch);
+        assert_eq!(
+            decoder.feed(&mut dur, &[0x3, 0x08, 0xAC, 0x02]),
+            Err(DecodeError::Capacity)
+        );
+
+        // Field runs past the end of the message
+        let mut scratch = [0; 3];
+        let mut decoder = ResumablePbDecoder::new(&mut scratch);
+        assert_eq!(
+            decoder.feed(&mut dur, &[0x2, 0x08, 0xAC, 0x02]),
+            Err(DecodeError::WrongLen)
+        );
+
+        // Overlong varint split across chunks
+        let mut scratch = [0; 16];
+        let mut decoder = ResumablePbDecoder::new(&mut scratch);
+        assert_eq!(
+            decoder.feed(&mut dur, &[0x20, 0x08, 0x80, 0x80, 0x80, 0x80]),
+            Ok(DecodeStatus::NeedMoreData)
+        );
+        assert_eq!(
+            decoder.feed(&mut dur, &[0x80; 7]),
+            Err(DecodeError::VarIntLimit)
+        );
+
+        // Invalid wire type
+        let mut decoder = ResumablePbDecoder::new(&mut scratch);
+        assert_eq!(
+            decoder.feed(&mut dur, &[0x1, 0x0B]),
+            Err(DecodeError::Deprecation)
+        );
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[test]
+    fn len_limits() {
+        let mut dur = Duration::default();
+        let mut scratch = [0; 16];
+
+        // The length prefix of the message is checked too
+        let mut decoder = ResumablePbDecoder::new(&mut scratch);
+        decoder.set_max_len_field(Some(1));
+        assert_eq!(decoder.max_len_field(), Some(1));
+        assert_eq!(
+            decoder.feed(&mut dur, &[0x2, 0x08, 0x01]),
+            Err(DecodeError::LenLimit)
+        );
+
+        // Length-delimited fields are rejected before they're buffered
+        let mut decoder = ResumablePbDecoder::with_len(&mut scratch, Some(4));
+        decoder.set_max_len_field(Some(1));
+        assert_eq!(
+            decoder.feed(&mut dur, &[0x0A, 0x02]),
+            Err(DecodeError::LenLimit)
+        );
+
+        // The total byte count includes the fields that were already decoded
+        let data = [0x4, 0x08, 0x01, 0x10, 0x05];
+        let mut decoder = ResumablePbDecoder::with_len(&mut scratch, Some(4));
+        decoder.set_max_total_bytes(Some(2));
+        assert_eq!(decoder.max_total_bytes(), Some(2));
+        assert_eq!(
+            feed_chunks(&mut decoder, &mut dur, &data[1..], 1),
+            Err(DecodeError::LenLimit)
+        );
+        assert_eq!(dur, Duration::from_secs(1));
+
+        // A message that would end past the limit fails at its length prefix
+        let mut decoder = ResumablePbDecoder::new(&mut scratch);
+        decoder.set_max_total_bytes(Some(4));
+        assert_eq!(decoder.feed(&mut dur, &data), Err(DecodeError::LenLimit));
+
+        let mut decoder = ResumablePbDecoder::new(&mut scratch);
+        decoder.set_max_total_bytes(Some(5));
+        assert_eq!(
+            feed_chunks(&mut decoder, &mut dur, &data, 1),
+            Ok(DecodeStatus::Done { consumed: 1 })
+        );
+        assert_eq!(dur, Duration::new(1, 5));
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[test]
+    fn should_continue() {
+        extern "C" fn stop() -> bool {
+            false
+        }
+
+        let mut scratch = [0; 16];
+        let mut decoder = ResumablePbDecoder::new(&mut scratch);
+        decoder.set_should_continue(Some(stop));
+        assert_eq!(
+            decoder.feed(&mut Duration::default(), &[0x2, 0x08, 0x01]),
+            Err(DecodeError::Cancelled)
+        );
+    }
+}
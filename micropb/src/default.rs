@@ -0,0 +1,171 @@
+#[cfg(target_has_atomic = "8")]
+use core::{
+    cell::UnsafeCell,
+    hint::spin_loop,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// Message type with a shared, immutable default instance.
+///
+/// Reading through an unset message field normally requires constructing a default instance of
+/// the field's type, which is wasteful for large messages. With this trait, unset fields can be
+/// read through a reference to a single static instance instead, similar to default instances in
+/// Protobuf C++. Implementations are generated by `micropb-gen` when `default_instance` is
+/// enabled in the message config.
+///
+/// # Example
+/// ```no_run
+/// # use micropb::DefaultInstance;
+/// # #[derive(Default)] struct Inner { val: u32 }
+/// # impl DefaultInstance for Inner { fn default_instance() -> &'static Self { todo!() } }
+/// # struct Outer { inner: Option<Inner> }
+/// # impl Outer { fn inner(&self) -> Option<&Inner> { self.inner.as_ref() } }
+/// # let outer = Outer { inner: None };
+/// // No default `Inner` is constructed if `outer.inner` is unset
+/// let val = Inner::or_default(outer.inner()).val;
+/// ```
+pub trait DefaultInstance: Default + 'static {
+    /// Returns a reference to the shared default instance of the type.
+    fn default_instance() -> &'static Self;
+
+    /// Returns the value in `opt`, or the shared default instance if `opt` is `None`.
+    #[inline]
+    fn or_default(opt: Option<&Self>) -> &Self {
+        opt.unwrap_or_else(|| Self::default_instance())
+    }
+}
+
+#[cfg(target_has_atomic = "8")]
+const UNINIT: u8 = 0;
+#[cfg(target_has_atomic = "8")]
+const RUNNING: u8 = 1;
+#[cfg(target_has_atomic = "8")]
+const DONE: u8 = 2;
+
+/// Lazily-initialized static storage for the default instance of a type.
+///
+/// Used by generated [`DefaultInstance`] implementations. The instance is constructed with
+/// `Default::default` on first access, without requiring `std` or an allocator. Only available on
+/// targets with atomic compare-and-swap.
+///
+/// # Note
+/// If the first access is interrupted by another access to the same instance on the same core,
+/// such as from an interrupt handler, the second access will spin forever. To avoid this, access
+/// the default instance once during startup, before enabling interrupts.
+#[cfg(target_has_atomic = "8")]
+pub struct StaticDefault<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// SAFETY: The value is only written once, before `state` is set to DONE with release ordering.
+// Afterwards it's only accessed through shared references, so sharing it across threads is sound
+// if `T` can be shared and sent between threads.
+#[cfg(target_has_atomic = "8")]
+unsafe impl<T: Send + Sync> Sync for StaticDefault<T> {}
+
+#[cfg(target_has_atomic = "8")]
+impl<T: Default> StaticDefault<T> {
+    /// Creates new, uninitialized storage.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(UNINIT),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Returns a reference to the default instance, constructing it if needed.
+    pub fn get(&self) -> &T {
+        if self.state.load(Ordering::Acquire) != DONE {
+            self.init();
+        }
+        // SAFETY: DONE is only set after the value is written, and the value is never modified
+        // afterwards
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+
+    #[cold]
+    fn init(&self) {
+        match self
+            .state
+            .compare_exchange(UNINIT, RUNNING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                // SAFETY: Only the caller that moved the state out of UNINIT can get here, and
+                // nobody reads the value until the state becomes DONE
+                unsafe { (*self.value.get()).write(T::default()) };
+                self.state.store(DONE, Ordering::Release);
+            }
+            Err(_) => {
+                while self.state.load(Ordering::Acquire) != DONE {
+                    spin_loop();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_has_atomic = "8")]
+impl<T: Default> Default for StaticDefault<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(target_has_atomic = "8")]
+impl<T> Drop for StaticDefault<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == DONE {
+            // SAFETY: The value was initialized
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default, Debug, PartialEq)]
+    struct Msg {
+        val: u32,
+        list: Vec<u32>,
+    }
+
+    impl DefaultInstance for Msg {
+        fn default_instance() -> &'static Self {
+            static INSTANCE: StaticDefault<Msg> = StaticDefault::new();
+            INSTANCE.get()
+        }
+    }
+
+    #[test]
+    fn default_instance() {
+        let a = Msg::default_instance();
+        let b = Msg::default_instance();
+        assert_eq!(a, &Msg::default());
+        assert!(core::ptr::eq(a, b));
+
+        let set = Msg {
+            val: 3,
+            list: vec![1],
+        };
+        assert_eq!(Msg::or_default(Some(&set)).val, 3);
+        assert!(core::ptr::eq(Msg::or_default(None), a));
+    }
+
+    #[test]
+    fn default_instance_threads() {
+        static INSTANCE: StaticDefault<Msg> = StaticDefault::new();
+        let addrs: Vec<usize> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| s.spawn(|| INSTANCE.get() as *const Msg as usize))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(addrs.iter().all(|&a| a == addrs[0]));
+    }
+}
@@ -1,4 +1,13 @@
-use crate::{MessageEncode, Tag, VarInt};
+#[cfg(feature = "strings")]
+use core::fmt;
+
+#[cfg(feature = "strings")]
+use crate::size::fmt_len;
+use crate::{
+    size::{sizeof_packed, sizeof_varint32},
+    wire::{self, MAX_VARINT_LEN},
+    MessageEncode, PbEnum, Tag, VarInt, WIRE_TYPE_LEN,
+};
 
 /// A writer to which Protobuf data is written, similar to [`std::io::Write`].
 ///
@@ -14,6 +23,16 @@ pub trait PbWrite {
     ///
     /// This is analogous to [`std::io::Write::write_all`].
     fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Returns the number of bytes that can still be written, or `None` if the writer doesn't
+    /// have a fixed capacity.
+    ///
+    /// Used by [`MessageExt::encode_checked`](crate::MessageExt::encode_checked) to report
+    /// [capacity errors](CapacityError) before anything is written.
+    #[inline]
+    fn pb_remaining(&self) -> Option<usize> {
+        None
+    }
 }
 
 impl<W: PbWrite> PbWrite for &mut W {
@@ -23,9 +42,14 @@ impl<W: PbWrite> PbWrite for &mut W {
     fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
         (*self).pb_write(data)
     }
+
+    #[inline]
+    fn pb_remaining(&self) -> Option<usize> {
+        (**self).pb_remaining()
+    }
 }
 
-#[cfg(feature = "container-arrayvec")]
+#[cfg(feature = "container-arrayvec-0_7")]
 impl<const N: usize> PbWrite for arrayvec::ArrayVec<u8, N> {
     type Error = arrayvec::CapacityError;
 
@@ -33,9 +57,14 @@ impl<const N: usize> PbWrite for arrayvec::ArrayVec<u8, N> {
     fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
         self.try_extend_from_slice(data)
     }
+
+    #[inline]
+    fn pb_remaining(&self) -> Option<usize> {
+        Some(self.remaining_capacity())
+    }
 }
 
-#[cfg(feature = "container-heapless")]
+#[cfg(feature = "container-heapless-0_8")]
 impl<const N: usize> PbWrite for heapless::Vec<u8, N> {
     type Error = ();
 
@@ -43,6 +72,100 @@ impl<const N: usize> PbWrite for heapless::Vec<u8, N> {
     fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
         self.extend_from_slice(data)
     }
+
+    #[inline]
+    fn pb_remaining(&self) -> Option<usize> {
+        Some(self.capacity() - self.len())
+    }
+}
+
+#[cfg(feature = "container-heapless-0_7")]
+impl<const N: usize> PbWrite for heapless_0_7::Vec<u8, N> {
+    type Error = ();
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.extend_from_slice(data)
+    }
+
+    #[inline]
+    fn pb_remaining(&self) -> Option<usize> {
+        Some(self.capacity() - self.len())
+    }
+}
+
+#[cfg(feature = "container-heapless-0_9")]
+impl<const N: usize> PbWrite for heapless_0_9::Vec<u8, N> {
+    type Error = ();
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.extend_from_slice(data).map_err(drop)
+    }
+
+    #[inline]
+    fn pb_remaining(&self) -> Option<usize> {
+        Some(self.capacity() - self.len())
+    }
+}
+
+#[cfg(any(
+    feature = "container-heapless-0_7",
+    feature = "container-heapless-0_8",
+    feature = "container-heapless-0_9"
+))]
+macro_rules! impl_spsc_write {
+    ([$($generics:tt)*] $producer:ty) => {
+        /// Enqueues the written bytes. Writes that don't fit into the free space of the queue fail
+        /// without enqueuing anything.
+        impl<$($generics)*> PbWrite for $producer {
+            type Error = ();
+
+            fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+                // The consumer can only free up space, so the bytes will fit after this check
+                if self.capacity() - self.len() < data.len() {
+                    return Err(());
+                }
+                for &b in data {
+                    self.enqueue(b).map_err(|_| ())?;
+                }
+                Ok(())
+            }
+
+            fn pb_remaining(&self) -> Option<usize> {
+                Some(self.capacity() - self.len())
+            }
+        }
+    };
+}
+
+#[cfg(all(feature = "container-heapless-0_8", target_has_atomic = "ptr"))]
+impl_spsc_write!([const N: usize] heapless::spsc::Producer<'_, u8, N>);
+#[cfg(all(feature = "container-heapless-0_7", target_has_atomic = "ptr"))]
+impl_spsc_write!([const N: usize] heapless_0_7::spsc::Producer<'_, u8, N>);
+#[cfg(all(feature = "container-heapless-0_9", target_has_atomic = "ptr"))]
+impl_spsc_write!([] heapless_0_9::spsc::Producer<'_, u8>);
+
+impl<const N: usize, A> PbWrite for crate::container::AlignedVec<u8, N, A> {
+    type Error = ();
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        let spare = crate::container::PbVec::pb_spare_cap(self);
+        if data.len() > spare.len() {
+            return Err(());
+        }
+        crate::misc::maybe_uninit_write_slice(spare, data);
+        let len = self.len() + data.len();
+        // SAFETY: The spare capacity up to the new length was just written
+        unsafe { crate::container::PbContainer::pb_set_len(self, len) };
+        Ok(())
+    }
+
+    #[inline]
+    fn pb_remaining(&self) -> Option<usize> {
+        Some(N - self.len())
+    }
 }
 
 #[cfg(feature = "alloc")]
@@ -56,12 +179,174 @@ impl PbWrite for alloc::vec::Vec<u8> {
     }
 }
 
+/// A [`PbWrite`] backed by a contiguous buffer, which allows bytes to be modified after they've
+/// been written.
+///
+/// [`PbEncoder`] uses this trait to backfill length prefixes in
+/// [`end_len_delimited`](PbEncoder::end_len_delimited).
+pub trait PbBufWrite: PbWrite {
+    /// Returns all bytes that have been written to the buffer.
+    fn pb_written_mut(&mut self) -> &mut [u8];
+
+    /// Shortens the buffer to `len` bytes. Does nothing if `len` is greater than the number of
+    /// written bytes.
+    fn pb_truncate(&mut self, len: usize);
+}
+
+impl<W: PbBufWrite> PbBufWrite for &mut W {
+    #[inline]
+    fn pb_written_mut(&mut self) -> &mut [u8] {
+        (*self).pb_written_mut()
+    }
+
+    #[inline]
+    fn pb_truncate(&mut self, len: usize) {
+        (*self).pb_truncate(len)
+    }
+}
+
+#[cfg(feature = "container-arrayvec-0_7")]
+impl<const N: usize> PbBufWrite for arrayvec::ArrayVec<u8, N> {
+    #[inline]
+    fn pb_written_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+
+    #[inline]
+    fn pb_truncate(&mut self, len: usize) {
+        self.truncate(len)
+    }
+}
+
+#[cfg(feature = "container-heapless-0_8")]
+impl<const N: usize> PbBufWrite for heapless::Vec<u8, N> {
+    #[inline]
+    fn pb_written_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+
+    #[inline]
+    fn pb_truncate(&mut self, len: usize) {
+        self.truncate(len)
+    }
+}
+
+#[cfg(feature = "container-heapless-0_7")]
+impl<const N: usize> PbBufWrite for heapless_0_7::Vec<u8, N> {
+    #[inline]
+    fn pb_written_mut(&mut self) -> &mut [u8] {
+        &mut self[..]
+    }
+
+    #[inline]
+    fn pb_truncate(&mut self, len: usize) {
+        self.truncate(len)
+    }
+}
+
+#[cfg(feature = "container-heapless-0_9")]
+impl<const N: usize> PbBufWrite for heapless_0_9::Vec<u8, N> {
+    #[inline]
+    fn pb_written_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+
+    #[inline]
+    fn pb_truncate(&mut self, len: usize) {
+        self.truncate(len)
+    }
+}
+
+impl<const N: usize, A> PbBufWrite for crate::container::AlignedVec<u8, N, A> {
+    #[inline]
+    fn pb_written_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+
+    #[inline]
+    fn pb_truncate(&mut self, len: usize) {
+        self.truncate(len)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl PbBufWrite for alloc::vec::Vec<u8> {
+    #[inline]
+    fn pb_written_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+
+    #[inline]
+    fn pb_truncate(&mut self, len: usize) {
+        self.truncate(len)
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl PbWrite for bytes::BytesMut {
+    type Error = never::Never;
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl PbBufWrite for bytes::BytesMut {
+    #[inline]
+    fn pb_written_mut(&mut self) -> &mut [u8] {
+        &mut self[..]
+    }
+
+    #[inline]
+    fn pb_truncate(&mut self, len: usize) {
+        self.truncate(len)
+    }
+}
+
+#[cfg(feature = "container-bytes-1")]
+impl PbWrite for crate::container::SharedBytes {
+    type Error = never::Never;
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.extend_from_slice(data);
+        Ok(())
+    }
+}
+
 #[cfg(feature = "std")]
 #[derive(Debug, Clone)]
 /// Adapter that implements [`PbWrite`] for all implementers of [`std::io::Write`], allowing the
 /// encoder to write to `std` writers.
+///
+/// The encoder issues many small writes, so unbuffered writers such as files and sockets should
+/// be wrapped with [`StdWriter::buffered`]. Writes interrupted by signals are retried.
+///
+/// ```no_run
+/// use std::io::Write;
+/// use micropb::{PbEncoder, StdWriter};
+///
+/// let file = std::fs::File::create("message.bin")?;
+/// let mut encoder = PbEncoder::new(StdWriter::buffered(file));
+/// encoder.encode_varint32(150)?;
+/// // Buffered data must be flushed explicitly to observe write errors
+/// encoder.into_writer().0.flush()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
 pub struct StdWriter<W>(pub W);
 
+#[cfg(feature = "std")]
+impl<W: std::io::Write> StdWriter<std::io::BufWriter<W>> {
+    /// Wraps a [`std::io::Write`] in a [`std::io::BufWriter`] to batch the encoder's writes.
+    #[inline]
+    pub fn buffered(writer: W) -> Self {
+        Self(std::io::BufWriter::new(writer))
+    }
+}
+
 #[cfg(feature = "std")]
 impl<W: std::io::Write> PbWrite for StdWriter<W> {
     type Error = std::io::Error;
@@ -72,6 +357,275 @@ impl<W: std::io::Write> PbWrite for StdWriter<W> {
     }
 }
 
+#[cfg(feature = "strings")]
+const SPACES: &[u8; 8] = b"        ";
+
+#[cfg(feature = "strings")]
+/// Writes formatted output into the encoder, up to a fixed number of bytes.
+struct FmtEncoder<'a, W: PbWrite> {
+    encoder: &'a mut PbEncoder<W>,
+    remaining: usize,
+    error: Option<W::Error>,
+}
+
+#[cfg(feature = "strings")]
+impl<W: PbWrite> fmt::Write for FmtEncoder<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // Only write whole characters, so the output is still valid UTF-8 if it's cut off
+        let mut n = s.len().min(self.remaining);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        self.remaining -= n;
+        self.encoder
+            .write(s.as_bytes().get(..n).unwrap_or_default())
+            .map_err(|e| {
+                self.error = Some(e);
+                fmt::Error
+            })?;
+        if n < s.len() {
+            // Stop formatting once the output is cut off, and pad the rest with spaces
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+/// Error returned when an encoded message doesn't fit into a fixed-capacity buffer.
+pub struct CapacityError {
+    /// Number of bytes the message needed beyond the capacity of the buffer
+    pub needed: usize,
+    /// Number of the top-level field that would have been cut off by the end of the buffer, or
+    /// `None` if the encoded message couldn't be parsed
+    pub field_num: Option<u32>,
+}
+
+impl CapacityError {
+    /// Computes the error for encoding `msg`, which takes up `size` bytes, into a buffer with
+    /// `capacity` bytes left.
+    pub(crate) fn new<M: MessageEncode + ?Sized>(msg: &M, capacity: usize, size: usize) -> Self {
+        let mut scanner = FieldScanner::new(capacity);
+        // The scanner returns an error to stop encoding once it finds the field
+        let _ = msg.encode(&mut PbEncoder::new(&mut scanner));
+        Self {
+            needed: size.saturating_sub(capacity),
+            field_num: scanner.field_num,
+        }
+    }
+}
+
+impl core::fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "buffer is {} bytes too small", self.needed)?;
+        if let Some(num) = self.field_num {
+            write!(f, " at field {num}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl crate::ErrorTrait for CapacityError {}
+
+#[derive(Debug, PartialEq, Eq)]
+/// Error returned by [`MessageExt::encode_checked`](crate::MessageExt::encode_checked).
+pub enum EncodeError<E> {
+    /// Message doesn't fit into the remaining capacity of the writer
+    Capacity(CapacityError),
+    /// Error returned from writer
+    Writer(E),
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for EncodeError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EncodeError::Capacity(e) => e.fmt(f),
+            EncodeError::Writer(e) => write!(f, "writer error: {e}"),
+        }
+    }
+}
+
+/// Formats the same messages as the `Display` impl, except that the writer error isn't printed.
+#[cfg(feature = "defmt")]
+impl<E> defmt::Format for EncodeError<E> {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            EncodeError::Capacity(e) => e.format(f),
+            EncodeError::Writer(_) => defmt::write!(f, "writer error"),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl<E: crate::ErrorTrait + 'static> crate::ErrorTrait for EncodeError<E> {
+    fn source(&self) -> Option<&(dyn crate::ErrorTrait + 'static)> {
+        match self {
+            EncodeError::Capacity(e) => Some(e),
+            EncodeError::Writer(e) => Some(e),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ScanState {
+    Tag { val: u32, shift: u32 },
+    Varint,
+    Len { val: usize, shift: u32 },
+    Skip(usize),
+}
+
+impl ScanState {
+    const NEXT_TAG: Self = ScanState::Tag { val: 0, shift: 0 };
+}
+
+/// Writer that parses the top-level fields of an encoded message without storing them, to find
+/// the field containing the byte at `limit`
+struct FieldScanner {
+    limit: usize,
+    pos: usize,
+    state: ScanState,
+    /// Number of the field whose payload is being parsed
+    current: u32,
+    /// Set once the byte at `limit` is part of the tag being parsed
+    reached: bool,
+    field_num: Option<u32>,
+}
+
+impl FieldScanner {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            pos: 0,
+            state: ScanState::NEXT_TAG,
+            current: 0,
+            reached: false,
+            field_num: None,
+        }
+    }
+
+    /// Parses one byte, returning `true` once the field has been found
+    fn scan(&mut self, b: u8) -> bool {
+        let at_limit = self.pos == self.limit;
+        self.pos += 1;
+        let more = b & 0x80 != 0;
+        let bits = b & 0x7F;
+
+        if let ScanState::Tag { val, shift } = self.state {
+            self.reached |= at_limit;
+            let val = val | (bits as u32).checked_shl(shift).unwrap_or(0);
+            if more {
+                self.state = ScanState::Tag {
+                    val,
+                    shift: shift + 7,
+                };
+                return false;
+            }
+            let tag = Tag(val);
+            if self.reached {
+                self.field_num = Some(tag.field_num());
+                return true;
+            }
+            self.current = tag.field_num();
+            self.state = match tag.wire_type() {
+                crate::WIRE_TYPE_VARINT => ScanState::Varint,
+                crate::WIRE_TYPE_I64 => ScanState::Skip(8),
+                WIRE_TYPE_LEN => ScanState::Len { val: 0, shift: 0 },
+                crate::WIRE_TYPE_I32 => ScanState::Skip(4),
+                // Group tags have no payload
+                _ => ScanState::NEXT_TAG,
+            };
+            return false;
+        }
+
+        if at_limit {
+            self.field_num = Some(self.current);
+            return true;
+        }
+        self.state = match self.state {
+            ScanState::Varint if more => ScanState::Varint,
+            ScanState::Len { val, shift } => {
+                let val = val | (bits as usize).checked_shl(shift).unwrap_or(0);
+                match (more, val) {
+                    (true, _) => ScanState::Len {
+                        val,
+                        shift: shift + 7,
+                    },
+                    (false, 0) => ScanState::NEXT_TAG,
+                    (false, len) => ScanState::Skip(len),
+                }
+            }
+            ScanState::Skip(n) if n > 1 => ScanState::Skip(n - 1),
+            _ => ScanState::NEXT_TAG,
+        };
+        false
+    }
+}
+
+impl PbWrite for FieldScanner {
+    type Error = ();
+
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        for &b in data {
+            if self.scan(b) {
+                return Err(());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Maximum number of bytes in a `uint32` varint.
+const MAX_VARINT32_LEN: usize = 5;
+
+#[derive(Debug)]
+#[must_use = "the length prefix is only filled in by `end_len_delimited`"]
+/// Length prefix reserved by [`PbEncoder::start_len_delimited`].
+pub struct LenSlot {
+    /// Position of the length prefix in the writer's buffer
+    pos: usize,
+}
+
+/// Write `u` into `buf` as a varint padded to exactly `buf.len()` bytes.
+///
+/// The varint is padded with continuation bits, so it decodes to the same value as the shortest
+/// encoding of `u`. This is useful for patching a length prefix that was reserved with
+/// [`PbEncoder::encode_varint32_padded`] before the length was known.
+///
+/// Returns `false` without writing anything if `buf` is longer than 10 bytes or too short to
+/// hold `u`.
+///
+/// ```
+/// use micropb::{size::sizeof_varint32, write_padded_varint32, PbEncoder};
+///
+/// let mut encoder = PbEncoder::new(vec![]);
+/// // Reserve 2 bytes for the length prefix before streaming out the record
+/// encoder.encode_varint32_padded(0, 2).unwrap();
+/// let start = encoder.bytes_written();
+/// encoder.encode_string("abc").unwrap();
+/// let len = encoder.bytes_written() - start;
+///
+/// let mut buf = encoder.into_writer();
+/// assert!(write_padded_varint32(&mut buf[..2], len as u32));
+/// assert_eq!(buf, &[0x84, 0x00, 3, b'a', b'b', b'c']);
+/// ```
+pub fn write_padded_varint32(buf: &mut [u8], u: u32) -> bool {
+    let width = buf.len();
+    if width == 0 || width > MAX_VARINT_LEN || width < sizeof_varint32(u) {
+        return false;
+    }
+    let mut varint = u as u64;
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (varint & 0x7F) as u8;
+        if i + 1 < width {
+            *b |= 0x80;
+        }
+        varint >>= 7;
+    }
+    true
+}
+
 #[derive(Debug)]
 /// Encoder that serializes Rust types into Protobuf messages and values.
 ///
@@ -102,6 +656,29 @@ impl<W: std::io::Write> PbWrite for StdWriter<W> {
 /// # Ok::<(), ()>(())
 /// ```
 ///
+/// Encoding Protobuf values by hand, without any generated code:
+/// ```
+/// use micropb::{PbEncoder, Tag, WIRE_TYPE_LEN, WIRE_TYPE_VARINT};
+/// use micropb::size::{sizeof_len_record, sizeof_sint32, sizeof_tag};
+/// use micropb::heapless::Vec;
+///
+/// let mut encoder = PbEncoder::new(Vec::<u8, 16>::new());
+/// // Field 1 is a `string`
+/// encoder.encode_tag(Tag::from_parts(1, WIRE_TYPE_LEN))?;
+/// encoder.encode_string("abc")?;
+/// // Field 2 is a submessage containing a `sint32` in field 1
+/// let inner_tag = Tag::from_parts(1, WIRE_TYPE_VARINT);
+/// let inner_len = sizeof_tag(inner_tag) + sizeof_sint32(-2);
+/// encoder.encode_tag(Tag::from_parts(2, WIRE_TYPE_LEN))?;
+/// encoder.encode_varint32(inner_len as u32)?;
+/// encoder.encode_tag(inner_tag)?;
+/// encoder.encode_sint32(-2)?;
+///
+/// assert_eq!(encoder.as_writer(), &[0x0A, 3, b'a', b'b', b'c', 0x12, 2, 0x08, 0x03]);
+/// assert_eq!(encoder.bytes_written(), 1 + sizeof_len_record(3) + 1 + sizeof_len_record(inner_len));
+/// # Ok::<(), ()>(())
+/// ```
+///
 /// # Reducing Code Size
 ///
 /// To prevent multiple monomorphizations and increased code size, make sure you instantiate
@@ -109,13 +686,14 @@ impl<W: std::io::Write> PbWrite for StdWriter<W> {
 /// be supported, wrap them in an enum or use a trait object.
 pub struct PbEncoder<W: PbWrite> {
     writer: W,
+    idx: usize,
 }
 
 impl<W: PbWrite> PbEncoder<W> {
     #[inline]
     /// Construct a new encoder from a [`PbWrite`].
     pub fn new(writer: W) -> Self {
-        Self { writer }
+        Self { writer, idx: 0 }
     }
 
     #[inline]
@@ -131,8 +709,36 @@ impl<W: PbWrite> PbEncoder<W> {
     }
 
     #[inline]
-    fn write(&mut self, bytes: &[u8]) -> Result<(), W::Error> {
+    /// Get mutable reference to underlying writer.
+    ///
+    /// Bytes written directly to the writer aren't counted by
+    /// [`bytes_written`](Self::bytes_written). The writer shouldn't be modified while a
+    /// [`LenSlot`] is outstanding, since the slot refers to a position in the writer's output.
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    #[inline]
+    /// Replace the underlying writer, returning the previous one.
+    ///
+    /// This lets a long-lived encoder switch between output buffers, such as the two halves of a
+    /// double-buffered transmitter, without being reconstructed. The count returned by
+    /// [`bytes_written`](Self::bytes_written) isn't reset, so it keeps counting across writers.
+    /// Writers should only be replaced between messages, not while a [`LenSlot`] is outstanding.
+    pub fn replace_writer(&mut self, writer: W) -> W {
+        core::mem::replace(&mut self.writer, writer)
+    }
+
+    #[inline]
+    /// Get the number of bytes that the encoder has written to the writer.
+    pub fn bytes_written(&self) -> usize {
+        self.idx
+    }
+
+    #[inline]
+    pub(crate) fn write(&mut self, bytes: &[u8]) -> Result<(), W::Error> {
         self.writer.pb_write(bytes)?;
+        self.idx += bytes.len();
         Ok(())
     }
 
@@ -180,6 +786,23 @@ impl<W: PbWrite> PbEncoder<W> {
         self.encode_varint(u, false)
     }
 
+    /// Encode an `uint32` as a varint padded to `width` bytes.
+    ///
+    /// The varint is padded with continuation bits, so it decodes to the same value as the
+    /// shortest encoding. This allows a length prefix to be reserved before the length is known,
+    /// and then patched with [`write_padded_varint32`] once the record has been streamed out.
+    ///
+    /// `width` is clamped between the shortest encoding of `u` and the maximum varint length of 10
+    /// bytes.
+    pub fn encode_varint32_padded(&mut self, u: u32, width: usize) -> Result<(), W::Error> {
+        let mut buf = [0; MAX_VARINT_LEN];
+        let buf = buf
+            .get_mut(..width.clamp(sizeof_varint32(u), MAX_VARINT_LEN))
+            .unwrap_or_default();
+        write_padded_varint32(buf, u);
+        self.write(buf)
+    }
+
     #[inline]
     #[cfg(feature = "enable-64bit")]
     /// Encode an `uint64`.
@@ -189,10 +812,24 @@ impl<W: PbWrite> PbEncoder<W> {
 
     #[inline]
     /// Encode an `int32`.
+    ///
+    /// Negative values are sign-extended to 64 bits, so they always take up 10 bytes.
     pub fn encode_int32(&mut self, i: i32) -> Result<(), W::Error> {
         self.encode_varint(i as u32, i < 0)
     }
 
+    #[inline]
+    /// Encode an `int32` the way some old proto2 implementations do, with negative values encoded
+    /// as 32-bit varints that take up 5 bytes instead of being sign-extended to 10 bytes.
+    ///
+    /// This only exists for talking to legacy peers that expect this encoding. Conforming decoders
+    /// read such values as `int32` correctly, including [`PbDecoder`](crate::PbDecoder), but they
+    /// aren't valid `int64` values. Use [`sizeof_int32_legacy`](crate::size::sizeof_int32_legacy)
+    /// to calculate the encoded size.
+    pub fn encode_int32_legacy(&mut self, i: i32) -> Result<(), W::Error> {
+        self.encode_varint32(i as u32)
+    }
+
     #[inline]
     #[cfg(feature = "enable-64bit")]
     /// Encode an `int64`.
@@ -201,16 +838,16 @@ impl<W: PbWrite> PbEncoder<W> {
     }
 
     #[inline]
-    /// Encode an `sint32`.
+    /// Encode an `sint32` using ZigZag encoding.
     pub fn encode_sint32(&mut self, i: i32) -> Result<(), W::Error> {
-        self.encode_varint32(((i << 1) ^ (i >> 31)) as u32)
+        self.encode_varint32(wire::zigzag_encode32(i))
     }
 
     #[inline]
     #[cfg(feature = "enable-64bit")]
-    /// Encode an `sint64`.
+    /// Encode an `sint64` using ZigZag encoding.
     pub fn encode_sint64(&mut self, i: i64) -> Result<(), W::Error> {
-        self.encode_varint64(((i << 1) ^ (i >> 63)) as u64)
+        self.encode_varint64(wire::zigzag_encode64(i))
     }
 
     #[inline]
@@ -236,9 +873,8 @@ impl<W: PbWrite> PbEncoder<W> {
     ///
     /// Avoids 64-bit operations, which can have benefits on 32-bit architectures.
     pub fn encode_fixed64_as_32(&mut self, u: u32) -> Result<(), W::Error> {
-        let mut bytes = [0; 8];
-        bytes[..4].copy_from_slice(&u.to_le_bytes());
-        self.write(&bytes)
+        let [b0, b1, b2, b3] = u.to_le_bytes();
+        self.write(&[b0, b1, b2, b3, 0, 0, 0, 0])
     }
 
     #[inline]
@@ -259,19 +895,23 @@ impl<W: PbWrite> PbEncoder<W> {
     /// Avoids 64-bit operations, which can have benefits on 32-bit architectures.
     pub fn encode_sfixed64_as_32(&mut self, i: i32) -> Result<(), W::Error> {
         // If i is -ve, then write the extra bits to 1
-        let mut bytes = if i < 0 { [0xFF; 8] } else { [0; 8] };
-        bytes[..4].copy_from_slice(&i.to_le_bytes());
-        self.write(&bytes)
+        let ext = if i < 0 { 0xFF } else { 0 };
+        let [b0, b1, b2, b3] = i.to_le_bytes();
+        self.write(&[b0, b1, b2, b3, ext, ext, ext, ext])
     }
 
     #[inline]
     /// Encode a `float`.
+    ///
+    /// The bit pattern is preserved exactly, including NaN payloads, signed zeros, and subnormals.
     pub fn encode_float(&mut self, f: f32) -> Result<(), W::Error> {
         self.write(&f.to_le_bytes())
     }
 
     #[inline]
     /// Encode a `double`.
+    ///
+    /// The bit pattern is preserved exactly, including NaN payloads, signed zeros, and subnormals.
     pub fn encode_double(&mut self, f: f64) -> Result<(), W::Error> {
         self.write(&f.to_le_bytes())
     }
@@ -282,18 +922,61 @@ impl<W: PbWrite> PbEncoder<W> {
         self.encode_varint32(tag.varint())
     }
 
-    /// Encode a `bytes` field.
+    /// Encode a `bytes` field, prefixed by its length.
     pub fn encode_bytes(&mut self, bytes: &[u8]) -> Result<(), W::Error> {
         self.encode_varint32(bytes.len() as u32)?;
         self.write(bytes)
     }
 
     #[inline]
-    /// Encode a `string` field.
+    #[cfg(feature = "strings")]
+    /// Encode a `string` field, prefixed by its length.
     pub fn encode_string(&mut self, string: &str) -> Result<(), W::Error> {
         self.encode_bytes(string.as_bytes())
     }
 
+    #[cfg(feature = "strings")]
+    /// Encode a `string` field from formatted output, prefixed by its length.
+    ///
+    /// The string is formatted straight into the writer, without a temporary buffer. Since the
+    /// length prefix comes first, the arguments are formatted twice: once to compute the length,
+    /// and once to write the string. As such, the formatted output must be the same both times.
+    /// If it's not, or if formatting fails, the string is cut off or padded with spaces so that
+    /// it matches the length prefix.
+    ///
+    /// Use [`sizeof_string_fmt`](crate::size::sizeof_string_fmt) to compute the size of the
+    /// output.
+    ///
+    /// ```
+    /// use micropb::PbEncoder;
+    ///
+    /// let mut encoder = PbEncoder::new(vec![]);
+    /// encoder
+    ///     .encode_string_fmt(format_args!("temp={}C", 23))
+    ///     .unwrap();
+    /// assert_eq!(encoder.into_writer(), b"\x08temp=23C");
+    /// ```
+    pub fn encode_string_fmt(&mut self, args: fmt::Arguments) -> Result<(), W::Error> {
+        let len = fmt_len(args);
+        self.encode_varint32(len as u32)?;
+
+        let mut writer = FmtEncoder {
+            encoder: self,
+            remaining: len,
+            error: None,
+        };
+        let _ = fmt::write(&mut writer, args);
+        if let Some(err) = writer.error {
+            return Err(err);
+        }
+        while writer.remaining > 0 {
+            let n = writer.remaining.min(SPACES.len());
+            writer.remaining -= n;
+            writer.encoder.write(SPACES.get(..n).unwrap_or_default())?;
+        }
+        Ok(())
+    }
+
     //pub fn encode_packed_fixed<T: Copy>(&mut self, elems: &[T]) -> Result<(), W::Error> {
     //// O(1) operation that gets total size of slice
     //let len = sizeof_packed_fixed(elems);
@@ -318,7 +1001,26 @@ impl<W: PbWrite> PbEncoder<W> {
         Ok(())
     }
 
+    #[inline]
+    /// Encode an enum value.
+    pub fn encode_enum<E: PbEnum>(&mut self, val: E) -> Result<(), W::Error> {
+        self.encode_int32(val.to_i32())
+    }
+
+    /// Encode a packed repeated enum field from a slice of enum values.
+    ///
+    /// `len` is the length of the packed record on the wire, which is computed by
+    /// [`sizeof_packed_enum`](crate::size::sizeof_packed_enum).
+    pub fn encode_packed_enum<E: PbEnum>(
+        &mut self,
+        len: usize,
+        elems: &[E],
+    ) -> Result<(), W::Error> {
+        self.encode_packed(len, elems, Self::encode_enum)
+    }
+
     #[allow(clippy::too_many_arguments)]
+    #[cfg(feature = "maps")]
     /// Encode a Protobuf map key-value pair onto the wire.
     ///
     /// The key-value pair is encoded as a Protobuf message with the key in field 1 and value in
@@ -355,13 +1057,147 @@ impl<W: PbWrite> PbEncoder<W> {
     pub fn encode_message<M: MessageEncode>(&mut self, msg: &M) -> Result<(), W::Error> {
         msg.encode(self)
     }
+
+    /// Encode a single scalar value as a field with field number `field_num`, without an
+    /// enclosing message.
+    ///
+    /// The `encoder` callback determines how the value is encoded onto the wire, and `wire_type`
+    /// must match it. Use [`sizeof_scalar_field`](crate::size::sizeof_scalar_field) to compute the
+    /// size of the output, and `PbDecoder::decode_scalar_field` to decode it.
+    ///
+    /// ```
+    /// use micropb::{PbEncoder, WIRE_TYPE_VARINT};
+    ///
+    /// let mut encoder = PbEncoder::new(vec![]);
+    /// encoder
+    ///     .encode_scalar_field(1, WIRE_TYPE_VARINT, 150, PbEncoder::encode_varint32)
+    ///     .unwrap();
+    /// assert_eq!(encoder.into_writer(), &[0x08, 0x96, 0x01]);
+    /// ```
+    pub fn encode_scalar_field<T, F: FnOnce(&mut Self, T) -> Result<(), W::Error>>(
+        &mut self,
+        field_num: u32,
+        wire_type: u8,
+        val: T,
+        encoder: F,
+    ) -> Result<(), W::Error> {
+        self.encode_tag(Tag::from_parts(field_num, wire_type))?;
+        encoder(self, val)
+    }
+
+    /// Encode a slice of scalar values as a packed field with field number `field_num`, without an
+    /// enclosing message.
+    ///
+    /// The `encoder` callback determines how each element is encoded onto the wire. Use
+    /// [`sizeof_packed_field`](crate::size::sizeof_packed_field) to compute the size of the
+    /// output, and `PbDecoder::decode_packed_field` to decode it.
+    pub fn encode_packed_field<
+        T: Copy,
+        S: Fn(&T) -> usize,
+        F: FnMut(&mut Self, T) -> Result<(), W::Error>,
+    >(
+        &mut self,
+        field_num: u32,
+        elems: &[T],
+        sizer: S,
+        encoder: F,
+    ) -> Result<(), W::Error> {
+        self.encode_tag(Tag::from_parts(field_num, WIRE_TYPE_LEN))?;
+        self.encode_packed(sizeof_packed(elems, sizer), elems, encoder)
+    }
+
+    /// Encode a slice of messages as a repeated message field with field number `field_num`.
+    ///
+    /// The output is identical to encoding a message that only contains `msgs` as a repeated
+    /// field, except that no enclosing message type is needed. Use
+    /// [`sizeof_repeated_messages`](crate::size::sizeof_repeated_messages) to compute the size of
+    /// the output, and `PbDecoder::decode_repeated_messages` to decode it.
+    pub fn encode_repeated_messages<M: MessageEncode>(
+        &mut self,
+        field_num: u32,
+        msgs: &[M],
+    ) -> Result<(), W::Error> {
+        let tag = Tag::from_parts(field_num, WIRE_TYPE_LEN);
+        for msg in msgs {
+            self.encode_tag(tag)?;
+            msg.encode_len_delimited(self)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: PbBufWrite> PbEncoder<W> {
+    /// Reserve a length prefix for a length-delimited record whose length isn't known yet.
+    ///
+    /// After writing the contents of the record, call
+    /// [`end_len_delimited`](Self::end_len_delimited) with the returned slot to fill in the
+    /// length prefix. This avoids computing the size of the record ahead of time. Slots can be
+    /// nested, but must be ended in the reverse order that they were started.
+    ///
+    /// ```
+    /// use micropb::{PbEncoder, Tag, WIRE_TYPE_LEN, WIRE_TYPE_VARINT};
+    ///
+    /// let mut encoder = PbEncoder::new(vec![]);
+    /// encoder.encode_tag(Tag::from_parts(1, WIRE_TYPE_LEN)).unwrap();
+    /// let slot = encoder.start_len_delimited().unwrap();
+    /// encoder.encode_tag(Tag::from_parts(1, WIRE_TYPE_VARINT)).unwrap();
+    /// encoder.encode_varint32(150).unwrap();
+    /// encoder.end_len_delimited(slot);
+    /// assert_eq!(encoder.into_writer(), &[0x0A, 3, 0x08, 0x96, 0x01]);
+    /// ```
+    pub fn start_len_delimited(&mut self) -> Result<LenSlot, W::Error> {
+        let pos = self.writer.pb_written_mut().len();
+        self.write(&[0x80, 0x80, 0x80, 0x80, 0x00])?;
+        Ok(LenSlot { pos })
+    }
+
+    /// Fill in the length prefix reserved by [`start_len_delimited`](Self::start_len_delimited)
+    /// with the number of bytes written since then.
+    ///
+    /// The contents of the record are shifted back so that the length prefix has its shortest
+    /// encoding, which makes the output identical to encoding the record with a precomputed size.
+    pub fn end_len_delimited(&mut self, slot: LenSlot) {
+        let buf = self.writer.pb_written_mut();
+        let total = buf.len();
+        let start = slot.pos + MAX_VARINT32_LEN;
+        if start > total {
+            pb_debug_assert!(false);
+            return;
+        }
+        let len = (total - start) as u32;
+        let prefix_len = sizeof_varint32(len);
+        if let Some(prefix) = buf.get_mut(slot.pos..slot.pos + prefix_len) {
+            write_padded_varint32(prefix, len);
+        }
+        buf.copy_within(start.., slot.pos + prefix_len);
+        let shift = MAX_VARINT32_LEN - prefix_len;
+        self.writer.pb_truncate(total - shift);
+        self.idx -= shift;
+    }
+
+    /// Fill in the length prefix reserved by [`start_len_delimited`](Self::start_len_delimited)
+    /// without shifting the contents of the record.
+    ///
+    /// The length prefix is always padded to 5 bytes, which is valid Protobuf but takes up more
+    /// space than [`end_len_delimited`](Self::end_len_delimited). This avoids moving the contents
+    /// of large records.
+    pub fn end_len_delimited_padded(&mut self, slot: LenSlot) {
+        let buf = self.writer.pb_written_mut();
+        let start = slot.pos + MAX_VARINT32_LEN;
+        let len = buf.len().saturating_sub(start) as u32;
+        if let Some(prefix) = buf.get_mut(slot.pos..start) {
+            write_padded_varint32(prefix, len);
+        } else {
+            pb_debug_assert!(false);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use arrayvec::ArrayVec;
 
-    use crate::{size::*, WIRE_TYPE_LEN, WIRE_TYPE_VARINT};
+    use crate::{size::*, WIRE_TYPE_I32, WIRE_TYPE_LEN, WIRE_TYPE_VARINT};
 
     use super::*;
 
@@ -462,6 +1298,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn int_legacy() {
+        assert_encode!(&[0x96, 0x01], encode_int32_legacy(150), sizeof_int32_legacy);
+        assert_encode!(
+            &[0xFE, 0xFF, 0xFF, 0xFF, 0x0F],
+            encode_int32_legacy(-2),
+            sizeof_int32_legacy
+        );
+        assert_encode!(
+            &[0x80, 0x80, 0x80, 0x80, 0x08],
+            encode_int32_legacy(i32::MIN),
+            sizeof_int32_legacy
+        );
+
+        // Both encodings decode to the same value
+        #[cfg(feature = "decode")]
+        for bytes in [
+            &[0xFE, 0xFF, 0xFF, 0xFF, 0x0F][..],
+            &[0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+        ] {
+            let mut decoder = crate::PbDecoder::new(bytes);
+            assert_eq!(decoder.decode_int32().unwrap(), -2);
+        }
+    }
+
     #[test]
     fn sint32() {
         assert_encode!(&[0x00], encode_sint32(0), sizeof_sint32);
@@ -555,6 +1416,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn bytes_written() {
+        let mut encoder = PbEncoder::new(ArrayVec::<_, 20>::new());
+        assert_eq!(encoder.bytes_written(), 0);
+        encoder.encode_varint32(150).unwrap();
+        assert_eq!(encoder.bytes_written(), 2);
+        encoder.encode_int32(-1).unwrap();
+        assert_eq!(encoder.bytes_written(), 12);
+        encoder.encode_string("abc").unwrap();
+        assert_eq!(encoder.bytes_written(), 16);
+        // Failed writes aren't counted
+        encoder.encode_bytes(&[0; 10]).unwrap_err();
+        assert_eq!(encoder.bytes_written(), 17);
+        assert_eq!(encoder.bytes_written(), encoder.as_writer().len());
+    }
+
+    #[test]
+    fn byte_order() {
+        // Wire values are little-endian regardless of the target's byte order, so these must pass
+        // unchanged on big-endian targets
+        assert_encode_nosize!(&[0x01, 0x02, 0x03, 0x04], encode_fixed32(0x04030201));
+        assert_encode_nosize!(
+            &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08],
+            encode_fixed64(0x0807060504030201)
+        );
+        assert_encode_nosize!(&[0xFE, 0xFF, 0xFF, 0xFF], encode_sfixed32(-2));
+        assert_encode_nosize!(
+            &[0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+            encode_sfixed64(-2)
+        );
+        assert_encode_nosize!(
+            &[0x01, 0x02, 0x03, 0x04, 0x00, 0x00, 0x00, 0x00],
+            encode_fixed64_as_32(0x04030201)
+        );
+        assert_encode_nosize!(&[0x00, 0x00, 0x80, 0x3F], encode_float(1.0));
+        assert_encode_nosize!(&[0x00, 0x00, 0x20, 0xC0], encode_float(-2.5));
+        assert_encode_nosize!(
+            &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF0, 0x3F],
+            encode_double(1.0)
+        );
+        assert_encode_nosize!(
+            &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0xC0],
+            encode_double(-2.5)
+        );
+        assert_encode_nosize!(
+            &[0x01, 0x00, 0xC0, 0x7F],
+            encode_float(f32::from_bits(0x7FC00001))
+        );
+        assert_encode_nosize!(&[0x00, 0x00, 0x00, 0x80], encode_float(-0.0));
+    }
+
     #[test]
     fn bytes_string() {
         assert_encode_nosize!(&[0], encode_bytes(b""));
@@ -598,6 +1510,36 @@ mod tests {
         assert_eq!(4, sizeof_len_record(len));
     }
 
+    #[test]
+    fn packed_enum() {
+        #[derive(Clone, Copy)]
+        struct TestEnum(i8);
+
+        impl PbEnum for TestEnum {
+            fn from_i32(val: i32) -> Self {
+                Self(val as _)
+            }
+
+            fn to_i32(self) -> i32 {
+                self.0 as _
+            }
+
+            fn is_known(self) -> bool {
+                true
+            }
+        }
+
+        let elems = [TestEnum(1), TestEnum(-1), TestEnum(0)];
+        let mut encoder = PbEncoder::new(ArrayVec::<_, 20>::new());
+        let len = crate::size::sizeof_packed_enum(&elems);
+        assert_eq!(len, 12);
+        encoder.encode_packed_enum(len, &elems).unwrap();
+        assert_eq!(
+            [12, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x00],
+            encoder.writer.as_slice()
+        );
+    }
+
     macro_rules! assert_encode_map_elem {
         ($expected:expr, $key:expr, $val:expr) => {
             let mut encoder = PbEncoder::new(ArrayVec::<_, 20>::new());
@@ -634,4 +1576,346 @@ mod tests {
         );
         assert_encode_map_elem!([5, 0x08, 0x96, 0x01, 0x12, 0], &150, "");
     }
+
+    #[test]
+    fn aligned_writer() {
+        use crate::container::{Align16, AlignedVec};
+
+        let mut encoder = PbEncoder::new(AlignedVec::<u8, 4, Align16>::new());
+        encoder.encode_varint32(150).unwrap();
+        encoder.encode_bool(true).unwrap();
+        assert_eq!(encoder.writer.as_slice(), &[0x96, 0x01, 0x01]);
+        assert_eq!(encoder.writer.as_ptr() as usize % 16, 0);
+        // Writes that don't fit in the remaining capacity are rejected without partial writes
+        assert_eq!(encoder.encode_fixed32(1), Err(()));
+        assert_eq!(encoder.writer.len(), 3);
+    }
+
+    #[test]
+    fn scalar_field() {
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 20>::new());
+        encoder
+            .encode_scalar_field(1, WIRE_TYPE_VARINT, -2, PbEncoder::encode_sint32)
+            .unwrap();
+        encoder
+            .encode_scalar_field(16, WIRE_TYPE_I32, 1.0, PbEncoder::encode_float)
+            .unwrap();
+        assert_eq!(
+            encoder.writer.as_slice(),
+            &[0x08, 0x03, 0x85, 0x01, 0x00, 0x00, 0x80, 0x3F]
+        );
+        assert_eq!(sizeof_scalar_field(1, -2, sizeof_sint32), 2);
+        assert_eq!(sizeof_scalar_field(16, 1.0, |_| 4), 6);
+    }
+
+    #[test]
+    fn packed_field() {
+        let elems = [1, 150, 0];
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 20>::new());
+        encoder
+            .encode_packed_field(
+                2,
+                &elems,
+                |v| sizeof_varint32(*v),
+                PbEncoder::encode_varint32,
+            )
+            .unwrap();
+        encoder
+            .encode_packed_field(3, &[], |_| 4, PbEncoder::encode_fixed32)
+            .unwrap();
+        assert_eq!(
+            encoder.writer.as_slice(),
+            &[0x12, 4, 0x01, 0x96, 0x01, 0x00, 0x1A, 0]
+        );
+        assert_eq!(sizeof_packed_field(2, &elems, |v| sizeof_varint32(*v)), 6);
+        assert_eq!(sizeof_packed_field::<u32, _>(3, &[], |_| 4), 2);
+    }
+
+    #[test]
+    fn varint32_padded() {
+        assert_encode_nosize!(&[0x00], encode_varint32_padded(0, 1));
+        assert_encode_nosize!(&[0x80, 0x80, 0x00], encode_varint32_padded(0, 3));
+        assert_encode_nosize!(&[0x96, 0x81, 0x00], encode_varint32_padded(150, 3));
+        // Width is clamped to the size of the varint and to 10 bytes
+        assert_encode_nosize!(&[0x96, 0x01], encode_varint32_padded(150, 1));
+        assert_encode_nosize!(
+            &[0xFF, 0xFF, 0xFF, 0xFF, 0x8F, 0x80, 0x80, 0x80, 0x80, 0x00],
+            encode_varint32_padded(u32::MAX, 20)
+        );
+
+        let mut buf = [0xAA; 5];
+        assert!(write_padded_varint32(&mut buf, 300));
+        assert_eq!(buf, [0xAC, 0x82, 0x80, 0x80, 0x00]);
+        assert!(!write_padded_varint32(&mut buf[..1], 300));
+        assert!(!write_padded_varint32(&mut [], 0));
+        assert!(!write_padded_varint32(&mut [0; 11], 0));
+        assert_eq!(buf, [0xAC, 0x82, 0x80, 0x80, 0x00]);
+    }
+
+    #[test]
+    fn len_delimited_slot() {
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 300>::new());
+        let slot = encoder.start_len_delimited().unwrap();
+        encoder.end_len_delimited(slot);
+        assert_eq!(encoder.writer.as_slice(), &[0]);
+        assert_eq!(encoder.bytes_written(), 1);
+
+        // Nested records
+        let outer = encoder.start_len_delimited().unwrap();
+        encoder
+            .encode_tag(Tag::from_parts(1, WIRE_TYPE_LEN))
+            .unwrap();
+        let inner = encoder.start_len_delimited().unwrap();
+        encoder.encode_bytes(&[0xAB; 200]).unwrap();
+        encoder.end_len_delimited(inner);
+        encoder.encode_bool(true).unwrap();
+        encoder.end_len_delimited(outer);
+
+        let mut expected = ArrayVec::<u8, 300>::new();
+        expected.extend([0, 0xCE, 0x01, 0x0A, 0xCA, 0x01, 0xC8, 0x01]);
+        expected.extend([0xAB; 200]);
+        expected.push(0x01);
+        assert_eq!(encoder.writer, expected);
+        assert_eq!(encoder.bytes_written(), expected.len());
+    }
+
+    #[test]
+    fn len_delimited_slot_padded() {
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 20>::new());
+        let slot = encoder.start_len_delimited().unwrap();
+        encoder.encode_string("abc").unwrap();
+        encoder.end_len_delimited_padded(slot);
+        assert_eq!(
+            encoder.writer.as_slice(),
+            &[0x84, 0x80, 0x80, 0x80, 0x00, 3, b'a', b'b', b'c']
+        );
+        assert_eq!(encoder.bytes_written(), 9);
+
+        // Reserving the slot fails if there's no space for it
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 4>::new());
+        assert!(encoder.start_len_delimited().is_err());
+    }
+
+    #[test]
+    fn replace_writer() {
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 4>::new());
+        encoder.encode_varint32(150).unwrap();
+        let first = encoder.replace_writer(ArrayVec::new());
+        assert_eq!(first.as_slice(), &[0x96, 0x01]);
+
+        encoder.encode_bool(true).unwrap();
+        encoder.writer_mut().push(0xFF);
+        assert_eq!(encoder.as_writer().as_slice(), &[0x01, 0xFF]);
+        // Direct writes aren't counted
+        assert_eq!(encoder.bytes_written(), 3);
+    }
+
+    #[test]
+    fn string_fmt() {
+        use core::{cell::Cell, fmt};
+
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 20>::new());
+        encoder
+            .encode_string_fmt(format_args!("{}-{:02}é", "ab", 7))
+            .unwrap();
+        assert_eq!(encoder.writer.as_slice(), "\x07ab-07é".as_bytes());
+        assert_eq!(sizeof_string_fmt(format_args!("{}-{:02}é", "ab", 7)), 8);
+
+        // Output that changes between formatting passes
+        struct Growing(Cell<usize>);
+        impl fmt::Display for Growing {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.set(self.0.get() + 1);
+                for _ in 0..self.0.get() {
+                    f.write_str("é")?;
+                }
+                Ok(())
+            }
+        }
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 20>::new());
+        encoder
+            .encode_string_fmt(format_args!("{}", Growing(Cell::new(1))))
+            .unwrap();
+        // First pass has 2 characters, and the second pass with 3 characters is cut off
+        assert_eq!(encoder.writer.as_slice(), "\x04éé".as_bytes());
+
+        struct Shrinking(Cell<usize>);
+        impl fmt::Display for Shrinking {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.set(self.0.get() - 1);
+                for _ in 0..self.0.get() {
+                    f.write_str("x")?;
+                }
+                Ok(())
+            }
+        }
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 20>::new());
+        encoder
+            .encode_string_fmt(format_args!("{}", Shrinking(Cell::new(12))))
+            .unwrap();
+        // Shorter second pass is padded with spaces
+        assert_eq!(encoder.writer.as_slice(), b"\x0Bxxxxxxxxxx ");
+
+        struct Failing;
+        impl fmt::Display for Failing {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("ab")?;
+                Err(fmt::Error)
+            }
+        }
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 20>::new());
+        encoder
+            .encode_string_fmt(format_args!("{}{}", Failing, "cd"))
+            .unwrap();
+        assert_eq!(encoder.writer.as_slice(), b"\x02ab");
+
+        // Writer errors are returned
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 4>::new());
+        assert!(encoder
+            .encode_string_fmt(format_args!("{}", 12345))
+            .is_err());
+    }
+
+    #[test]
+    fn heapless_versions() {
+        use crate::PbMap;
+
+        let mut encoder = PbEncoder::new(heapless_0_7::Vec::<u8, 8>::new());
+        let slot = encoder.start_len_delimited().unwrap();
+        encoder.encode_varint32(150).unwrap();
+        encoder.end_len_delimited(slot);
+        assert_eq!(encoder.as_writer(), &[2, 0x96, 0x01]);
+        assert!(encoder.encode_bytes(&[0; 5]).is_err());
+
+        let mut map = heapless_0_7::FnvIndexMap::<u32, u32, 2>::new();
+        map.pb_insert(1, 2).unwrap();
+        map.pb_insert(3, 4).unwrap();
+        assert!(map.pb_insert(5, 6).is_err());
+        assert!(map.pb_iter().eq([(&1, &2), (&3, &4)]));
+
+        #[cfg(feature = "container-heapless-0_9")]
+        {
+            let mut encoder = PbEncoder::new(heapless_0_9::Vec::<u8, 4>::new());
+            encoder.encode_string("ab").unwrap();
+            assert_eq!(encoder.as_writer(), &[2, b'a', b'b']);
+            assert!(encoder.encode_bytes(&[0; 2]).is_err());
+
+            let mut map = heapless_0_9::index_map::FnvIndexMap::<u32, u32, 2>::new();
+            map.pb_insert(1, 2).unwrap();
+            map.pb_insert(3, 4).unwrap();
+            assert!(map.pb_insert(5, 6).is_err());
+            assert!(map.pb_iter().eq([(&1, &2), (&3, &4)]));
+        }
+    }
+
+    #[test]
+    fn std_writer() {
+        use std::io::Write;
+
+        let mut encoder = PbEncoder::new(StdWriter::buffered(Vec::new()));
+        encoder.encode_varint32(150).unwrap();
+        encoder.encode_string("abc").unwrap();
+        let mut writer = encoder.into_writer().0;
+        writer.flush().unwrap();
+        assert_eq!(writer.get_ref(), &[0x96, 0x01, 0x03, b'a', b'b', b'c']);
+
+        // Writer errors are returned as is
+        let mut buf = [0; 2];
+        let mut encoder = PbEncoder::new(StdWriter(buf.as_mut_slice()));
+        let err = encoder.encode_string("abc").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn spsc_producer() {
+        // Queue holds 7 bytes
+        let mut queue = heapless::spsc::Queue::<u8, 8>::new();
+        let (mut producer, mut consumer) = queue.split();
+        let mut encoder = PbEncoder::new(&mut producer);
+        encoder.encode_varint32(150).unwrap();
+        encoder.encode_string("abcd").unwrap();
+        // Doesn't fit, so nothing is enqueued
+        assert_eq!(encoder.encode_string("e"), Err(()));
+        assert_eq!(producer.len(), 7);
+        consumer.dequeue().unwrap();
+        producer.pb_write(&[0xFF]).unwrap();
+
+        let bytes: Vec<u8> = core::iter::from_fn(|| consumer.dequeue()).collect();
+        assert_eq!(bytes, [0x01, 0x04, b'a', b'b', b'c', b'd', 0xFF]);
+
+        let mut queue = heapless_0_7::spsc::Queue::<u8, 4>::new();
+        let (mut producer, _) = queue.split();
+        PbEncoder::new(&mut producer).encode_sint32(-3).unwrap();
+        assert_eq!(producer.len(), 1);
+
+        #[cfg(feature = "container-heapless-0_9")]
+        {
+            let mut queue = heapless_0_9::spsc::Queue::<u8, 4>::new();
+            let (mut producer, _) = queue.split();
+            PbEncoder::new(&mut producer).encode_sint32(-3).unwrap();
+            assert_eq!(producer.len(), 1);
+        }
+    }
+
+    #[test]
+    fn capacity_error() {
+        // Encodes pre-encoded fields
+        struct Raw(&'static [u8]);
+        impl MessageEncode for Raw {
+            fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+                encoder.write(self.0)
+            }
+
+            fn compute_size(&self) -> usize {
+                self.0.len()
+            }
+        }
+
+        let msg = Raw(&[
+            0x08, 0x96, 0x01, // Field 1 (varint)
+            0x92, 0x01, 0x02, 0x0A, 0x0B, // Field 18 (len)
+            0x1D, 0x01, 0x02, 0x03, 0x04, // Field 3 (I32)
+            0x20, 0x00, // Field 4 (varint)
+        ]);
+        let field_nums = [1, 1, 1, 18, 18, 18, 18, 18, 3, 3, 3, 3, 3, 4, 4];
+        for (capacity, num) in field_nums.into_iter().enumerate() {
+            assert_eq!(
+                CapacityError::new(&msg, capacity, msg.0.len()),
+                CapacityError {
+                    needed: msg.0.len() - capacity,
+                    field_num: Some(num)
+                },
+                "capacity {capacity}"
+            );
+        }
+        assert_eq!(CapacityError::new(&msg, 15, 15).field_num, None);
+
+        // Checked in advance, so nothing is written
+        let mut encoder = PbEncoder::new(heapless::Vec::<u8, 12>::new());
+        encoder.encode_bool(true).unwrap();
+        assert_eq!(
+            crate::MessageExt::encode_checked(&msg, &mut encoder),
+            Err(EncodeError::Capacity(CapacityError {
+                needed: 4,
+                field_num: Some(3)
+            }))
+        );
+        assert_eq!(encoder.as_writer(), &[0x01]);
+        assert_eq!(encoder.as_writer().pb_remaining(), Some(11),);
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 15>::new());
+        crate::MessageExt::encode_checked(&msg, &mut encoder).unwrap();
+        assert_eq!(encoder.as_writer().as_slice(), msg.0);
+
+        // Unbounded writers don't check the size
+        let mut encoder = PbEncoder::new(Vec::new());
+        crate::MessageExt::encode_checked(&msg, &mut encoder).unwrap();
+        assert_eq!(
+            CapacityError {
+                needed: 3,
+                field_num: Some(7)
+            }
+            .to_string(),
+            "buffer is 3 bytes too small at field 7"
+        );
+    }
 }
@@ -0,0 +1,304 @@
+use never::Never;
+
+use crate::{MessageEncode, PbEncoder, PbWrite};
+
+/// An async writer to which Protobuf data is written.
+///
+/// This is the async counterpart of [`PbWrite`], used by [`AsyncPbEncoder`]. It's implemented for
+/// all [`embedded_io_async::Write`] types, such as UARTs and TCP sockets in Embassy.
+#[allow(async_fn_in_trait)]
+pub trait AsyncPbWrite {
+    /// I/O error returned on write failure.
+    type Error;
+
+    /// Writes all bytes in `data`.
+    async fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+impl<W: embedded_io_async::Write> AsyncPbWrite for W {
+    type Error = W::Error;
+
+    #[inline]
+    async fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.write_all(data).await
+    }
+}
+
+/// Length of the buffer used in place of an empty scratch buffer.
+const MIN_SCRATCH_LEN: usize = 16;
+
+/// Encoder that writes Protobuf messages to an [`AsyncPbWrite`].
+///
+/// Messages are streamed to the writer through a scratch buffer, so the encoded message never
+/// needs to be in memory as a whole. Each time the scratch buffer is filled, it's written out
+/// before the rest of the message is encoded. Every message that implements [`MessageEncode`] can
+/// be encoded this way, through [`MessageEncodeAsync`](crate::MessageEncodeAsync).
+///
+/// Encoding can't be paused midway, so the message is encoded once for every scratch buffer's
+/// worth of output, with bytes outside the current window being discarded. This trades CPU time
+/// for memory, so the scratch buffer should be as large as memory allows. Messages that fit into
+/// the scratch buffer are only encoded once.
+///
+/// # Example
+///
+/// ```no_run
+/// use micropb::{AsyncPbEncoder, MessageEncodeAsync};
+///
+/// # struct ProtoMessage;
+/// # impl micropb::MessageEncode for ProtoMessage {
+/// #   fn encode<W: micropb::PbWrite>(&self, encoder: &mut micropb::PbEncoder<W>) -> Result<(), W::Error> { todo!() }
+/// #   fn compute_size(&self) -> usize { todo!() }
+/// # }
+/// # async fn example(uart: impl embedded_io_async::Write, message: ProtoMessage) -> Result<(), ()> {
+/// let mut scratch = [0; 64];
+/// let mut encoder = AsyncPbEncoder::new(uart, &mut scratch);
+///
+/// message.encode_len_delimited_async(&mut encoder).await.map_err(|_| ())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct AsyncPbEncoder<'s, W: AsyncPbWrite> {
+    writer: W,
+    scratch: &'s mut [u8],
+    idx: usize,
+}
+
+impl<'s, W: AsyncPbWrite> AsyncPbEncoder<'s, W> {
+    #[inline]
+    /// Construct a new encoder from an [`AsyncPbWrite`] and the scratch buffer that messages are
+    /// encoded into. If `scratch` is empty, a small buffer on the stack is used instead.
+    pub fn new(writer: W, scratch: &'s mut [u8]) -> Self {
+        Self {
+            writer,
+            scratch,
+            idx: 0,
+        }
+    }
+
+    #[inline]
+    /// Transform the encoder into the underlying writer.
+    pub fn into_writer(self) -> W {
+        self.writer
+    }
+
+    #[inline]
+    /// Get reference to underlying writer.
+    pub fn as_writer(&self) -> &W {
+        &self.writer
+    }
+
+    #[inline]
+    /// Get mutable reference to underlying writer.
+    ///
+    /// Bytes written directly to the writer aren't counted by
+    /// [`bytes_written`](Self::bytes_written).
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    #[inline]
+    /// Replace the underlying writer, returning the previous one. The scratch buffer is kept, and
+    /// the count returned by [`bytes_written`](Self::bytes_written) isn't reset.
+    pub fn replace_writer(&mut self, writer: W) -> W {
+        core::mem::replace(&mut self.writer, writer)
+    }
+
+    #[inline]
+    /// Get the number of bytes the encoder has written to the writer.
+    pub fn bytes_written(&self) -> usize {
+        self.idx
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), W::Error> {
+        self.writer.pb_write(data).await?;
+        self.idx += data.len();
+        Ok(())
+    }
+
+    /// Encode an `uint32`.
+    pub async fn encode_varint32(&mut self, mut varint: u32) -> Result<(), W::Error> {
+        let mut buf = [0; 5];
+        let mut len = 0;
+        loop {
+            let b = (varint & 0x7F) as u8;
+            varint >>= 7;
+            if varint == 0 {
+                buf[len] = b;
+                len += 1;
+                break;
+            }
+            buf[len] = b | 0x80;
+            len += 1;
+        }
+        self.write(&buf[..len]).await
+    }
+
+    /// Encode a message to the writer.
+    ///
+    /// Used by [`MessageEncodeAsync::encode_async`](crate::MessageEncodeAsync::encode_async).
+    pub async fn encode_message<M: MessageEncode + ?Sized>(
+        &mut self,
+        msg: &M,
+    ) -> Result<(), W::Error> {
+        let size = msg.compute_size();
+        let mut fallback = [0; MIN_SCRATCH_LEN];
+        // Move the scratch buffer out of the encoder so that we can write from it
+        let scratch = core::mem::take(&mut self.scratch);
+        let buf = if scratch.is_empty() {
+            &mut fallback[..]
+        } else {
+            &mut *scratch
+        };
+
+        let mut res = Ok(());
+        let mut start = 0;
+        while start < size {
+            let mut window = Window {
+                buf: &mut *buf,
+                start,
+                pos: 0,
+            };
+            if let Err(never) = msg.encode(&mut PbEncoder::new(&mut window)) {
+                match never {}
+            }
+            let len = buf.len().min(size - start);
+            res = self.write(&buf[..len]).await;
+            if res.is_err() {
+                break;
+            }
+            start += len;
+        }
+        self.scratch = scratch;
+        res
+    }
+
+    /// Encode a message to the writer as a length-delimited record, starting with a length
+    /// prefix.
+    ///
+    /// Used by
+    /// [`MessageEncodeAsync::encode_len_delimited_async`](crate::MessageEncodeAsync::encode_len_delimited_async).
+    pub async fn encode_len_delimited_message<M: MessageEncode + ?Sized>(
+        &mut self,
+        msg: &M,
+    ) -> Result<(), W::Error> {
+        self.encode_varint32(msg.compute_size() as u32).await?;
+        self.encode_message(msg).await
+    }
+}
+
+/// Writer that only keeps the bytes written at offsets `start..start + buf.len()`, discarding
+/// everything else.
+struct Window<'a> {
+    buf: &'a mut [u8],
+    start: usize,
+    pos: usize,
+}
+
+impl PbWrite for Window<'_> {
+    type Error = Never;
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        let end = self.pos + data.len();
+        let win_end = self.start + self.buf.len();
+        if end > self.start && self.pos < win_end {
+            let from = self.start.max(self.pos);
+            let to = win_end.min(end);
+            self.buf[from - self.start..to - self.start]
+                .copy_from_slice(&data[from - self.pos..to - self.pos]);
+        }
+        self.pos = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        convert::Infallible,
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+        time::Duration,
+    };
+
+    use crate::MessageEncodeAsync;
+
+    use super::*;
+
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        fn raw_waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker {
+                raw_waker()
+            }
+            fn noop(_: *const ()) {}
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(core::ptr::null(), &VTABLE)
+        }
+        // SAFETY: The waker functions don't use the data pointer
+        let waker = unsafe { Waker::from_raw(raw_waker()) };
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = pin!(fut);
+        loop {
+            if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+                return out;
+            }
+        }
+    }
+
+    /// Writer that records each write separately
+    #[derive(Default)]
+    struct Recorder {
+        writes: Vec<Vec<u8>>,
+    }
+
+    impl embedded_io_async::ErrorType for Recorder {
+        type Error = Infallible;
+    }
+
+    impl embedded_io_async::Write for Recorder {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.writes.push(buf.to_vec());
+            Ok(buf.len())
+        }
+    }
+
+    fn encode_sync<M: MessageEncode>(msg: &M) -> Vec<u8> {
+        let mut encoder = PbEncoder::new(vec![]);
+        msg.encode_len_delimited(&mut encoder).unwrap();
+        encoder.into_writer()
+    }
+
+    #[test]
+    fn encode_windows() {
+        let msg = b"abcdefghij".to_vec();
+        let expected = encode_sync(&msg);
+        for scratch_len in [0, 1, 3, 5, 12, 64] {
+            let mut scratch = vec![0; scratch_len];
+            let mut encoder = AsyncPbEncoder::new(Recorder::default(), &mut scratch);
+            block_on(msg.encode_len_delimited_async(&mut encoder)).unwrap();
+            assert_eq!(encoder.bytes_written(), expected.len());
+
+            let writes = encoder.into_writer().writes;
+            assert_eq!(writes.concat(), expected);
+            // Length prefix and then one write per window
+            let window = if scratch_len == 0 {
+                MIN_SCRATCH_LEN
+            } else {
+                scratch_len
+            };
+            assert_eq!(writes.len(), 1 + (expected.len() - 1).div_ceil(window));
+        }
+    }
+
+    #[test]
+    fn encode_empty() {
+        let mut scratch = [0; 4];
+        let mut encoder = AsyncPbEncoder::new(Recorder::default(), &mut scratch);
+        block_on(Duration::ZERO.encode_async(&mut encoder)).unwrap();
+        block_on(Duration::new(300, 5).encode_async(&mut encoder)).unwrap();
+        let writes = encoder.into_writer().writes;
+        assert_eq!(writes.concat(), [0x08, 0xAC, 0x02, 0x10, 0x05]);
+    }
+}
@@ -12,7 +12,8 @@ use crate::encode::{PbEncoder, PbWrite};
 #[cfg(feature = "decode")]
 /// One or more Protobuf fields that can be decoded from the wire.
 ///
-/// If multiple fields are included, then the fields will be decoded one at a time.
+/// If multiple fields are included, then the fields will be decoded one at a time. This includes
+/// custom fields that replace a oneof, which receive each variant as a separate field.
 ///
 /// # Example
 ///
@@ -159,3 +160,86 @@ impl<T: FieldEncode> FieldEncode for Option<T> {
         0
     }
 }
+
+/// Custom field type with implicit presence semantics.
+///
+/// If `implicit_presence` is enabled for a custom field in `micropb-gen`, the generated message
+/// code won't encode the field at all if [`is_default_value`](Self::is_default_value) returns
+/// `true`, just like the generated fields of Proto3 scalars. This is mainly useful for custom
+/// newtypes over scalar values.
+///
+/// # Example
+/// ```
+/// use micropb::field::ImplicitPresence;
+///
+/// struct Millivolts(u16);
+///
+/// impl ImplicitPresence for Millivolts {
+///     fn is_default_value(&self) -> bool {
+///         self.0.is_default_value()
+///     }
+/// }
+///
+/// assert!(Millivolts(0).is_default_value());
+/// assert!(!Millivolts(3300).is_default_value());
+/// ```
+pub trait ImplicitPresence {
+    /// Returns `true` if the value is the default value, in which case it isn't encoded.
+    fn is_default_value(&self) -> bool;
+}
+
+impl<T: ImplicitPresence + ?Sized> ImplicitPresence for &T {
+    #[inline]
+    fn is_default_value(&self) -> bool {
+        (*self).is_default_value()
+    }
+}
+
+impl ImplicitPresence for bool {
+    #[inline]
+    fn is_default_value(&self) -> bool {
+        !*self
+    }
+}
+
+macro_rules! impl_implicit_presence_int {
+    ($($t:ty),+) => {
+        $(impl ImplicitPresence for $t {
+            #[inline]
+            fn is_default_value(&self) -> bool {
+                *self == 0
+            }
+        })+
+    };
+}
+
+impl_implicit_presence_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+// Compare bits so that -0.0 isn't treated as the default value, same as generated fields
+impl ImplicitPresence for f32 {
+    #[inline]
+    fn is_default_value(&self) -> bool {
+        self.to_bits() == 0
+    }
+}
+
+impl ImplicitPresence for f64 {
+    #[inline]
+    fn is_default_value(&self) -> bool {
+        self.to_bits() == 0
+    }
+}
+
+impl ImplicitPresence for str {
+    #[inline]
+    fn is_default_value(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<T> ImplicitPresence for [T] {
+    #[inline]
+    fn is_default_value(&self) -> bool {
+        self.is_empty()
+    }
+}
@@ -0,0 +1,123 @@
+//! Accounting of the heap memory owned by Protobuf messages.
+//!
+//! Messages that use heap-allocated containers such as `Vec`, `String`, and `BTreeMap` can grow
+//! unboundedly when decoded from untrusted input. [`HeapSize`] reports how much heap memory a
+//! value owns, so that an application buffering many decoded messages, such as a gateway, can
+//! enforce a memory budget across them.
+//!
+//! `micropb-gen` implements [`HeapSize`] on generated messages, oneofs, and enums if
+//! `Generator::heap_size_impls` is enabled. This module and [`container`](crate::container)
+//! implement it on primitives, `Box`, `Option`, arrays, and all supported container types.
+
+use crate::callback::{Count, Ignore, RawField, UnknownFields};
+
+/// Types that can report the heap memory they own.
+pub trait HeapSize {
+    /// Returns the number of heap bytes owned by the value, including the heap memory owned by
+    /// its elements and fields.
+    ///
+    /// Only allocations owned by the value are counted, so the inline size of the value itself
+    /// isn't included. Allocated capacity counts even if it's unused. Fixed-capacity containers
+    /// don't own any heap memory, so they only report the heap memory of their elements.
+    fn heap_bytes_used(&self) -> usize;
+}
+
+macro_rules! impl_no_heap {
+    ($($ty:ty),*) => {
+        $(
+            impl HeapSize for $ty {
+                #[inline]
+                fn heap_bytes_used(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+impl_no_heap!(
+    (),
+    bool,
+    u8,
+    u16,
+    u32,
+    u64,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    isize,
+    f32,
+    f64,
+    core::time::Duration,
+    Ignore,
+    Count
+);
+
+/// References don't own the memory they point to, so they report zero.
+impl<T: ?Sized> HeapSize for &T {
+    #[inline]
+    fn heap_bytes_used(&self) -> usize {
+        0
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    #[inline]
+    fn heap_bytes_used(&self) -> usize {
+        self.as_ref().map_or(0, T::heap_bytes_used)
+    }
+}
+
+impl<T: HeapSize, const N: usize> HeapSize for [T; N] {
+    fn heap_bytes_used(&self) -> usize {
+        self.iter().map(T::heap_bytes_used).sum()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: HeapSize> HeapSize for alloc::boxed::Box<T> {
+    #[inline]
+    fn heap_bytes_used(&self) -> usize {
+        core::mem::size_of::<T>() + T::heap_bytes_used(self)
+    }
+}
+
+impl<V: HeapSize> HeapSize for RawField<V> {
+    #[inline]
+    fn heap_bytes_used(&self) -> usize {
+        self.0.heap_bytes_used()
+    }
+}
+
+impl<V: HeapSize> HeapSize for UnknownFields<V> {
+    #[inline]
+    fn heap_bytes_used(&self) -> usize {
+        self.0.heap_bytes_used()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primitives() {
+        assert_eq!(5u32.heap_bytes_used(), 0);
+        assert_eq!("abc".heap_bytes_used(), 0);
+        assert_eq!(Some(1.0f32).heap_bytes_used(), 0);
+        assert_eq!([Count(1); 3].heap_bytes_used(), 0);
+    }
+
+    #[test]
+    fn boxed() {
+        let b = Box::new(Some(Box::new(3u64)));
+        assert_eq!(
+            b.heap_bytes_used(),
+            core::mem::size_of::<Option<Box<u64>>>() + 8
+        );
+        let none: Option<Box<u32>> = None;
+        assert_eq!(none.heap_bytes_used(), 0);
+    }
+}
@@ -7,63 +7,218 @@ extern crate alloc;
 
 use num_traits::{AsPrimitive, PrimInt};
 
+// `core::error::Error` was only stabilized in Rust 1.81, so it's behind a feature to keep the MSRV.
+// `std::error::Error` is a re-export of the same trait on newer compilers.
+#[cfg(feature = "core-error")]
+pub(crate) use core::error::Error as ErrorTrait;
+#[cfg(all(feature = "std", not(feature = "core-error")))]
+pub(crate) use std::error::Error as ErrorTrait;
+
+/// Internal sanity check that's equivalent to `debug_assert!`, except that it's compiled out
+/// entirely with the `panic-free` feature, so that debug builds don't link in panic formatting.
+macro_rules! pb_debug_assert {
+    ($cond:expr) => {
+        #[cfg(not(feature = "panic-free"))]
+        debug_assert!($cond);
+    };
+}
+
+pub mod callback;
 pub mod container;
 #[cfg(feature = "decode")]
 mod decode;
+#[cfg(feature = "decode-async")]
+mod decode_async;
+#[cfg(feature = "decode")]
+mod decode_resumable;
+mod default;
 #[cfg(feature = "encode")]
 mod encode;
+#[cfg(feature = "encode-async")]
+mod encode_async;
 pub mod field;
+pub mod heap;
 mod message;
 mod misc;
+#[cfg(target_has_atomic = "8")]
+mod pool;
 #[cfg(feature = "encode")]
 pub mod size;
+#[cfg(feature = "encode")]
+pub mod split;
+#[cfg(feature = "storage")]
+pub mod storage;
+pub mod text;
+#[cfg(all(feature = "encode", feature = "decode", feature = "enable-64bit"))]
+pub mod transcode;
+#[cfg(feature = "strings")]
+pub mod value;
+pub mod well_known;
+pub mod wire;
 
-#[cfg(feature = "container-arrayvec")]
+#[cfg(feature = "container-arrayvec-0_7")]
 pub use ::arrayvec;
-#[cfg(feature = "container-heapless")]
+#[cfg(feature = "container-bytes-1")]
+pub use ::bytes;
+#[cfg(feature = "container-heapless-0_8")]
 pub use ::heapless;
+#[cfg(feature = "container-heapless-0_7")]
+pub use ::heapless_0_7;
+#[cfg(feature = "container-heapless-0_9")]
+pub use ::heapless_0_9;
 
-pub use container::{PbContainer, PbMap, PbString, PbVec};
+#[cfg(feature = "maps")]
+pub use container::PbMap;
+#[cfg(feature = "strings")]
+pub use container::PbString;
+pub use container::{PbContainer, PbVec};
+#[cfg(all(feature = "decode", feature = "std"))]
+pub use decode::StdReader;
+#[cfg(feature = "decode")]
+pub use decode::{
+    decode_fields, ChunkedReader, DecodeError, DecodeErrorAt, DecodeErrorCtx, DecodeStats,
+    DuplicateKey, FieldPath, PbDecoder, PbRead, RepeatedOverflow, RingReader, TrailingBytes,
+    UnknownEnum, WireValue, MAX_FIELD_PATH,
+};
+#[cfg(feature = "decode-async")]
+pub use decode_async::{AsyncPbDecoder, AsyncPbRead};
 #[cfg(feature = "decode")]
-pub use decode::{DecodeError, PbDecoder, PbRead};
+pub use decode_resumable::{DecodeStatus, ResumablePbDecoder};
+pub use default::DefaultInstance;
+#[cfg(target_has_atomic = "8")]
+pub use default::StaticDefault;
+#[cfg(all(feature = "encode", feature = "std"))]
+pub use encode::StdWriter;
 #[cfg(feature = "encode")]
-pub use encode::{PbEncoder, PbWrite};
+pub use encode::{
+    write_padded_varint32, CapacityError, EncodeError, LenSlot, PbBufWrite, PbEncoder, PbWrite,
+};
+#[cfg(feature = "encode-async")]
+pub use encode_async::{AsyncPbEncoder, AsyncPbWrite};
 #[cfg(feature = "decode")]
 pub use field::FieldDecode;
 #[cfg(feature = "encode")]
 pub use field::FieldEncode;
-#[cfg(feature = "decode")]
-pub use message::MessageDecode;
+pub use heap::HeapSize;
+#[cfg(feature = "decode-async")]
+pub use message::MessageDecodeAsync;
 #[cfg(feature = "encode")]
 pub use message::MessageEncode;
+#[cfg(feature = "encode-async")]
+pub use message::MessageEncodeAsync;
+#[cfg(any(feature = "encode", feature = "decode"))]
+pub use message::MessageExt;
+pub use message::MessageMerge;
+#[cfg(feature = "decode")]
+pub use message::{MessageDecode, MessageDecodeBorrowed};
+#[cfg(target_has_atomic = "8")]
+pub use pool::{MessagePool, PoolReset, Pooled, ResetDefault};
 
 /// Protobuf wire type for varints.
-pub const WIRE_TYPE_VARINT: u8 = 0;
+pub const WIRE_TYPE_VARINT: u8 = WireType::Varint as u8;
 /// Protobuf wire type for fixed 64-bit values.
-pub const WIRE_TYPE_I64: u8 = 1;
+pub const WIRE_TYPE_I64: u8 = WireType::I64 as u8;
 /// Protobuf wire type for length-delimited records.
-pub const WIRE_TYPE_LEN: u8 = 2;
+pub const WIRE_TYPE_LEN: u8 = WireType::Len as u8;
 /// Protobuf wire type for fixed 32-bit values.
-pub const WIRE_TYPE_I32: u8 = 5;
+pub const WIRE_TYPE_I32: u8 = WireType::I32 as u8;
+
+/// Protobuf wire type, which determines how a field's payload is laid out on the wire.
+///
+/// Typed alternative to the `WIRE_TYPE_*` constants. The discriminant of each variant is its
+/// value on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum WireType {
+    /// Varint
+    Varint = 0,
+    /// Fixed 64-bit value
+    I64 = 1,
+    /// Length-delimited record
+    Len = 2,
+    /// Start of a group. Groups are deprecated and not supported by `micropb`.
+    StartGroup = 3,
+    /// End of a group. Groups are deprecated and not supported by `micropb`.
+    EndGroup = 4,
+    /// Fixed 32-bit value
+    I32 = 5,
+}
+
+impl WireType {
+    #[inline]
+    /// Convert the wire value of a wire type into a [`WireType`], returning `None` if the value
+    /// isn't a valid wire type.
+    pub const fn from_u8(val: u8) -> Option<Self> {
+        match val {
+            0 => Some(WireType::Varint),
+            1 => Some(WireType::I64),
+            2 => Some(WireType::Len),
+            3 => Some(WireType::StartGroup),
+            4 => Some(WireType::EndGroup),
+            5 => Some(WireType::I32),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    /// Return the wire value of the wire type.
+    pub const fn as_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+impl TryFrom<u8> for WireType {
+    type Error = TagError;
+
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        WireType::from_u8(val).ok_or(TagError::UnknownWireType)
+    }
+}
+
+impl From<WireType> for u8 {
+    #[inline]
+    fn from(wire_type: WireType) -> Self {
+        wire_type.as_u8()
+    }
+}
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
 /// Protobuf tag, consisting of the field number and wire type.
+///
+/// Tags are ordered by field number first, then by wire type.
 pub struct Tag(u32);
 
 impl Tag {
     #[inline]
     /// Create a tag from a field number and wire type.
     pub const fn from_parts(field_num: u32, wire_type: u8) -> Self {
-        debug_assert!(wire_type <= 7);
+        pb_debug_assert!(wire_type <= 7);
         Self((field_num << 3) | (wire_type as u32))
     }
 
+    #[inline]
+    /// Create a tag from a field number and a typed wire type.
+    pub const fn new(field_num: u32, wire_type: WireType) -> Self {
+        Self::from_parts(field_num, wire_type.as_u8())
+    }
+
     #[inline]
     /// Get the wire type of the tag.
     pub const fn wire_type(&self) -> u8 {
         (self.0 & 0b111) as u8
     }
 
+    #[inline]
+    /// Get the wire type of the tag as a [`WireType`], returning `None` if the tag has an invalid
+    /// wire type.
+    ///
+    /// Tags decoded from the wire aren't validated, so they may contain invalid wire types.
+    pub const fn typed_wire_type(&self) -> Option<WireType> {
+        WireType::from_u8(self.wire_type())
+    }
+
     #[inline]
     /// Get the field number of the tag.
     pub const fn field_num(&self) -> u32 {
@@ -77,18 +232,79 @@ impl Tag {
     }
 }
 
-trait VarInt: PrimInt + From<u8> + AsPrimitive<u8> {
-    const BYTES: u8;
+#[cfg(feature = "defmt")]
+impl defmt::Format for Tag {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Tag {{ field_num: {=u32}, wire_type: {=u8} }}",
+            self.field_num(),
+            self.wire_type()
+        )
+    }
+}
+
+/// Error returned when converting an invalid integer into a [`Tag`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TagError {
+    /// Field number is 0, which isn't allowed by Protobuf
+    ZeroFieldNum,
+    /// Wire type isn't one of the supported wire types
+    UnknownWireType,
 }
 
-impl VarInt for u32 {
-    const BYTES: u8 = 5;
+impl core::fmt::Display for TagError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TagError::ZeroFieldNum => f.write_str("field number is 0"),
+            TagError::UnknownWireType => f.write_str("unknown wire type"),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl ErrorTrait for TagError {}
+
+impl TryFrom<u32> for Tag {
+    type Error = TagError;
+
+    /// Convert the integer representation of a tag into a [`Tag`], rejecting field number 0 and
+    /// wire types other than varint, I64, LEN, and I32.
+    fn try_from(varint: u32) -> Result<Self, Self::Error> {
+        let tag = Tag(varint);
+        if tag.field_num() == 0 {
+            return Err(TagError::ZeroFieldNum);
+        }
+        match tag.typed_wire_type() {
+            Some(WireType::Varint | WireType::I64 | WireType::Len | WireType::I32) => Ok(tag),
+            _ => Err(TagError::UnknownWireType),
+        }
+    }
 }
 
-impl VarInt for u64 {
-    const BYTES: u8 = 10;
+/// Protobuf enum types.
+///
+/// Implemented by all enums generated by `micropb-gen`, which are open newtypes over an integer,
+/// so they can hold values that aren't declared in the `.proto` file. Used by the packed enum
+/// helpers of `PbDecoder`, `PbEncoder`, and `size`.
+pub trait PbEnum: Copy {
+    /// Create an enum from its value on the wire, which may be unknown.
+    fn from_i32(val: i32) -> Self;
+
+    /// Return the value of the enum on the wire.
+    fn to_i32(self) -> i32;
+
+    /// Return `true` if the value is one of the variants declared in the `.proto` file.
+    fn is_known(self) -> bool;
 }
 
+trait VarInt: PrimInt + From<u8> + AsPrimitive<u8> {}
+
+impl VarInt for u32 {}
+
+impl VarInt for u64 {}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// Field presence discipline
 pub enum Presence {
@@ -119,4 +335,78 @@ mod tests {
         assert_eq!(tag.field_num(), 0);
         assert_eq!(tag.wire_type(), 0);
     }
+
+    #[test]
+    fn tag_const() {
+        const TAG: Tag = Tag::from_parts(3, WIRE_TYPE_LEN);
+        const FIELD_NUM: u32 = TAG.field_num();
+        assert_eq!(FIELD_NUM, 3);
+        assert_eq!(TAG.varint(), 0x1A);
+    }
+
+    #[test]
+    fn tag_try_from() {
+        assert_eq!(
+            Tag::try_from(0x08),
+            Ok(Tag::from_parts(1, WIRE_TYPE_VARINT))
+        );
+        assert_eq!(Tag::try_from(0x11), Ok(Tag::from_parts(2, WIRE_TYPE_I64)));
+        assert_eq!(Tag::try_from(0x1A), Ok(Tag::from_parts(3, WIRE_TYPE_LEN)));
+        assert_eq!(Tag::try_from(0x25), Ok(Tag::from_parts(4, WIRE_TYPE_I32)));
+        assert_eq!(
+            Tag::try_from(!0b111),
+            Ok(Tag::from_parts(0x1FFFFFFF, WIRE_TYPE_VARINT))
+        );
+
+        assert_eq!(Tag::try_from(0x02), Err(TagError::ZeroFieldNum));
+        assert_eq!(Tag::try_from(0), Err(TagError::ZeroFieldNum));
+        for wire_type in [3, 4, 6, 7] {
+            assert_eq!(
+                Tag::try_from(0x08 | wire_type),
+                Err(TagError::UnknownWireType)
+            );
+        }
+    }
+
+    #[test]
+    fn wire_type() {
+        assert_eq!(WireType::from_u8(WIRE_TYPE_VARINT), Some(WireType::Varint));
+        assert_eq!(WireType::from_u8(WIRE_TYPE_I64), Some(WireType::I64));
+        assert_eq!(WireType::from_u8(WIRE_TYPE_LEN), Some(WireType::Len));
+        assert_eq!(WireType::from_u8(3), Some(WireType::StartGroup));
+        assert_eq!(WireType::from_u8(4), Some(WireType::EndGroup));
+        assert_eq!(WireType::from_u8(WIRE_TYPE_I32), Some(WireType::I32));
+        assert_eq!(WireType::from_u8(6), None);
+        assert_eq!(WireType::try_from(7), Err(TagError::UnknownWireType));
+        assert_eq!(u8::from(WireType::I32), 5);
+
+        let tag = Tag::new(4, WireType::Len);
+        assert_eq!(tag, Tag::from_parts(4, WIRE_TYPE_LEN));
+        assert_eq!(tag.typed_wire_type(), Some(WireType::Len));
+        assert_eq!(Tag::from_parts(4, 6).typed_wire_type(), None);
+    }
+
+    #[test]
+    fn tag_ord_hash() {
+        use std::collections::HashSet;
+
+        let mut tags = vec![
+            Tag::from_parts(2, WIRE_TYPE_VARINT),
+            Tag::from_parts(1, WIRE_TYPE_LEN),
+            Tag::from_parts(1, WIRE_TYPE_VARINT),
+        ];
+        tags.sort();
+        assert_eq!(
+            tags,
+            [
+                Tag::from_parts(1, WIRE_TYPE_VARINT),
+                Tag::from_parts(1, WIRE_TYPE_LEN),
+                Tag::from_parts(2, WIRE_TYPE_VARINT),
+            ]
+        );
+
+        let set: HashSet<Tag> = tags.iter().copied().collect();
+        assert!(set.contains(&Tag::from_parts(1, WIRE_TYPE_LEN)));
+        assert!(!set.contains(&Tag::from_parts(2, WIRE_TYPE_LEN)));
+    }
 }
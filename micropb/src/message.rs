@@ -1,7 +1,13 @@
 #[cfg(feature = "decode")]
 use crate::decode::{DecodeError, PbDecoder, PbRead};
+#[cfg(feature = "decode-async")]
+use crate::decode_async::{AsyncPbDecoder, AsyncPbRead};
 #[cfg(feature = "encode")]
-use crate::encode::{PbEncoder, PbWrite};
+use crate::encode::{CapacityError, EncodeError, PbEncoder, PbWrite};
+#[cfg(feature = "encode-async")]
+use crate::encode_async::{AsyncPbEncoder, AsyncPbWrite};
+#[cfg(feature = "decode")]
+use never::Never;
 
 #[cfg(feature = "decode")]
 /// Protobuf message that can be decoded from the wire.
@@ -10,20 +16,56 @@ use crate::encode::{PbEncoder, PbWrite};
 pub trait MessageDecode {
     /// Decode an instance of the message from the decoder and merge it into `self`.
     ///
-    /// Length of the message needs to be known beforehand.
+    /// Length of the message needs to be known beforehand. Since `len` may only cover some of the
+    /// message's fields, this doesn't run [`check_required`](Self::check_required). The other
+    /// decoding methods run it once the whole message has been decoded.
     fn decode<R: PbRead>(
         &mut self,
         decoder: &mut PbDecoder<R>,
         len: usize,
     ) -> Result<(), DecodeError<R::Error>>;
 
+    /// Check that the message is complete after it's been decoded, returning
+    /// [`DecodeError::MissingRequired`] if it's missing a `required` field.
+    ///
+    /// Only messages generated with `Config::enforce_required` check their fields. The default
+    /// implementation always succeeds.
+    #[inline]
+    fn check_required<E>(&self) -> Result<(), DecodeError<E>> {
+        Ok(())
+    }
+
     /// Decode an instance of the message from the decoder as a length-delimited record, starting with a length
     /// prefix.
     fn decode_len_delimited<R: PbRead>(
         &mut self,
         decoder: &mut PbDecoder<R>,
     ) -> Result<(), DecodeError<R::Error>> {
-        decoder.decode_len_record(|len, _, decoder| self.decode(decoder, len))
+        decoder.decode_nested(|len, decoder| self.decode(decoder, len))?;
+        self.check_required()
+    }
+
+    /// Merge the encoded message in `bytes` into `self`.
+    ///
+    /// Follows the Protobuf merge semantics: singular fields present in `bytes` overwrite the
+    /// existing values, repeated fields are appended to, and submessages are merged recursively.
+    /// This is equivalent to decoding the concatenation of the encoding of `self` and `bytes`.
+    fn merge_from_slice(&mut self, bytes: &[u8]) -> Result<(), DecodeError<Never>> {
+        let mut decoder = PbDecoder::new(bytes);
+        self.decode(&mut decoder, bytes.len())?;
+        self.check_required()
+    }
+
+    /// Apply a patch containing a partial message onto `self`, such as one produced by
+    /// `MessageEncode::encode_changed`.
+    ///
+    /// Only the fields present in `patch` are modified, following the same merge semantics as
+    /// [`merge_from_slice`](Self::merge_from_slice). If decoding fails, the fields that were
+    /// decoded before the error remain applied. Since the patch only contains some of the fields,
+    /// `self` isn't checked with [`check_required`](Self::check_required).
+    fn apply_patch(&mut self, patch: &[u8]) -> Result<(), DecodeError<Never>> {
+        let mut decoder = PbDecoder::new(patch);
+        self.decode(&mut decoder, patch.len())
     }
 }
 
@@ -37,6 +79,10 @@ impl<T: MessageDecode> MessageDecode for &mut T {
         (*self).decode(decoder, len)
     }
 
+    fn check_required<E>(&self) -> Result<(), DecodeError<E>> {
+        (**self).check_required()
+    }
+
     fn decode_len_delimited<R: PbRead>(
         &mut self,
         decoder: &mut PbDecoder<R>,
@@ -45,6 +91,113 @@ impl<T: MessageDecode> MessageDecode for &mut T {
     }
 }
 
+#[cfg(feature = "decode")]
+/// Protobuf message that borrows data from the buffer it's decoded from.
+///
+/// Unlike [`MessageDecode`], decoding always reads from a byte slice that outlives the message,
+/// so `string` and `bytes` fields can point into the slice instead of being copied into
+/// containers. Implementations are auto-generated by `micropb` for messages with borrowed fields,
+/// which have a lifetime parameter. Every message that implements `MessageDecode` also implements
+/// this trait.
+pub trait MessageDecodeBorrowed<'a> {
+    /// Decode an instance of the message from the decoder and merge it into `self`.
+    ///
+    /// Length of the message needs to be known beforehand. Same as [`MessageDecode::decode`],
+    /// this doesn't run [`check_required_borrowed`](Self::check_required_borrowed).
+    fn decode_borrowed(
+        &mut self,
+        decoder: &mut PbDecoder<&'a [u8]>,
+        len: usize,
+    ) -> Result<(), DecodeError<Never>>;
+
+    /// Same as [`MessageDecode::check_required`].
+    #[inline]
+    fn check_required_borrowed(&self) -> Result<(), DecodeError<Never>> {
+        Ok(())
+    }
+
+    /// Decode an instance of the message from the decoder as a length-delimited record, starting
+    /// with a length prefix.
+    fn decode_len_delimited_borrowed(
+        &mut self,
+        decoder: &mut PbDecoder<&'a [u8]>,
+    ) -> Result<(), DecodeError<Never>> {
+        decoder.decode_nested(|len, decoder| self.decode_borrowed(decoder, len))?;
+        self.check_required_borrowed()
+    }
+
+    /// Merge the encoded message in `bytes` into `self`, borrowing from `bytes`.
+    fn merge_from_borrowed(&mut self, bytes: &'a [u8]) -> Result<(), DecodeError<Never>> {
+        let mut decoder = PbDecoder::new(bytes);
+        self.decode_borrowed(&mut decoder, bytes.len())?;
+        self.check_required_borrowed()
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<'a, T: MessageDecode> MessageDecodeBorrowed<'a> for T {
+    fn decode_borrowed(
+        &mut self,
+        decoder: &mut PbDecoder<&'a [u8]>,
+        len: usize,
+    ) -> Result<(), DecodeError<Never>> {
+        self.decode(decoder, len)
+    }
+
+    fn check_required_borrowed(&self) -> Result<(), DecodeError<Never>> {
+        self.check_required()
+    }
+
+    fn decode_len_delimited_borrowed(
+        &mut self,
+        decoder: &mut PbDecoder<&'a [u8]>,
+    ) -> Result<(), DecodeError<Never>> {
+        self.decode_len_delimited(decoder)
+    }
+}
+
+#[cfg(feature = "decode-async")]
+/// Protobuf message that can be decoded from an async reader.
+///
+/// Every message that implements [`MessageDecode`] also implements this trait. See
+/// [`AsyncPbDecoder`] for how messages are read.
+#[allow(async_fn_in_trait)]
+pub trait MessageDecodeAsync {
+    /// Decode an instance of the message from the async decoder and merge it into `self`.
+    ///
+    /// Length of the message needs to be known beforehand.
+    async fn decode_async<R: AsyncPbRead>(
+        &mut self,
+        decoder: &mut AsyncPbDecoder<'_, R>,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>>;
+
+    /// Decode an instance of the message from the async decoder as a length-delimited record,
+    /// starting with a length prefix.
+    async fn decode_len_delimited_async<R: AsyncPbRead>(
+        &mut self,
+        decoder: &mut AsyncPbDecoder<'_, R>,
+    ) -> Result<(), DecodeError<R::Error>>;
+}
+
+#[cfg(feature = "decode-async")]
+impl<T: MessageDecode> MessageDecodeAsync for T {
+    async fn decode_async<R: AsyncPbRead>(
+        &mut self,
+        decoder: &mut AsyncPbDecoder<'_, R>,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        decoder.decode_message(self, len).await
+    }
+
+    async fn decode_len_delimited_async<R: AsyncPbRead>(
+        &mut self,
+        decoder: &mut AsyncPbDecoder<'_, R>,
+    ) -> Result<(), DecodeError<R::Error>> {
+        decoder.decode_len_delimited_message(self).await
+    }
+}
+
 #[cfg(feature = "encode")]
 /// Protobuf message that can be encoded onto the wire.
 ///
@@ -61,6 +214,29 @@ pub trait MessageEncode {
 
     /// Compute the size of this message on the wire.
     fn compute_size(&self) -> usize;
+
+    /// Encode only the parts of this message that differ from `base`, producing a patch that
+    /// turns `base` into `self` when applied with `MessageDecode::apply_patch`.
+    ///
+    /// Since patches are applied by merging, changes that merging can't express aren't included,
+    /// such as clearing an optional field or removing elements from a repeated field or `map`.
+    ///
+    /// By default, this encodes the whole message. Generated messages only encode the changed
+    /// fields if `Config::encode_changed` is set in `micropb-gen`.
+    fn encode_changed<W: PbWrite>(
+        &self,
+        base: &Self,
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), W::Error> {
+        let _ = base;
+        self.encode(encoder)
+    }
+
+    /// Compute the size of the patch produced by [`encode_changed`](Self::encode_changed).
+    fn compute_changed_size(&self, base: &Self) -> usize {
+        let _ = base;
+        self.compute_size()
+    }
 }
 
 #[cfg(feature = "encode")]
@@ -76,4 +252,232 @@ impl<T: MessageEncode> MessageEncode for &T {
     fn encode_len_delimited<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
         (*self).encode_len_delimited(encoder)
     }
+
+    fn encode_changed<W: PbWrite>(
+        &self,
+        base: &Self,
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), W::Error> {
+        (*self).encode_changed(*base, encoder)
+    }
+
+    fn compute_changed_size(&self, base: &Self) -> usize {
+        (*self).compute_changed_size(*base)
+    }
+}
+
+#[cfg(feature = "encode-async")]
+/// Protobuf message that can be encoded to an async writer.
+///
+/// Every message that implements [`MessageEncode`] also implements this trait. See
+/// [`AsyncPbEncoder`] for how messages are written.
+#[allow(async_fn_in_trait)]
+pub trait MessageEncodeAsync {
+    /// Encode this message using the async encoder.
+    async fn encode_async<W: AsyncPbWrite>(
+        &self,
+        encoder: &mut AsyncPbEncoder<'_, W>,
+    ) -> Result<(), W::Error>;
+
+    /// Encode this message using the async encoder as a length-delimited record, starting with a
+    /// length prefix.
+    async fn encode_len_delimited_async<W: AsyncPbWrite>(
+        &self,
+        encoder: &mut AsyncPbEncoder<'_, W>,
+    ) -> Result<(), W::Error>;
+}
+
+#[cfg(feature = "encode-async")]
+impl<T: MessageEncode> MessageEncodeAsync for T {
+    async fn encode_async<W: AsyncPbWrite>(
+        &self,
+        encoder: &mut AsyncPbEncoder<'_, W>,
+    ) -> Result<(), W::Error> {
+        encoder.encode_message(self).await
+    }
+
+    async fn encode_len_delimited_async<W: AsyncPbWrite>(
+        &self,
+        encoder: &mut AsyncPbEncoder<'_, W>,
+    ) -> Result<(), W::Error> {
+        encoder.encode_len_delimited_message(self).await
+    }
+}
+
+/// Protobuf message that can be merged with another instance of the same message.
+///
+/// Implementations are generated by `micropb-gen` when `merge_impl` is enabled in the message
+/// config. Merging follows the Protobuf merge semantics, with the same result as decoding the
+/// encoding of `other` on top of `self`.
+pub trait MessageMerge {
+    #[allow(clippy::result_unit_err)]
+    /// Merge `other` into `self`.
+    ///
+    /// Singular fields that are set in `other` overwrite the values in `self`, repeated and `map`
+    /// fields are appended to, and message fields that are set in both are merged recursively.
+    ///
+    /// Returns an error if a fixed-capacity container in `self` runs out of space, in which case
+    /// the fields merged before the error stay merged.
+    fn merge(&mut self, other: &Self) -> Result<(), ()>;
+}
+
+#[cfg(any(feature = "encode", feature = "decode"))]
+/// Shorthands for decoding and encoding whole messages from and to byte buffers, without setting
+/// up a [`PbDecoder`] or [`PbEncoder`].
+///
+/// Implemented for all types, with each method available on the messages that implement
+/// [`MessageDecode`] or [`MessageEncode`].
+///
+/// ```
+/// use core::time::Duration;
+/// use micropb::MessageExt;
+///
+/// let mut buf = [0; 8];
+/// let len = Duration::from_secs(300).encode_to_slice(&mut buf).unwrap();
+/// assert_eq!(&buf[..len], &[0x08, 0xAC, 0x02]);
+/// assert_eq!(Duration::decode_from_slice(&buf[..len]), Ok(Duration::from_secs(300)));
+/// ```
+pub trait MessageExt {
+    #[cfg(feature = "decode")]
+    /// Decode a new message from `bytes`, which contains exactly one encoded message.
+    fn decode_from_slice(bytes: &[u8]) -> Result<Self, DecodeError<Never>>
+    where
+        Self: MessageDecode + Default + Sized,
+    {
+        let mut msg = Self::default();
+        msg.merge_from_slice(bytes)?;
+        Ok(msg)
+    }
+
+    #[cfg(feature = "encode")]
+    /// Encode this message to the start of `buf`, returning the number of bytes written.
+    ///
+    /// Returns an error without writing anything if the message doesn't fit into `buf`.
+    fn encode_to_slice(&self, buf: &mut [u8]) -> Result<usize, CapacityError>
+    where
+        Self: MessageEncode,
+    {
+        let len = self.compute_size();
+        let capacity = buf.len();
+        let Some(buf) = buf.get_mut(..len) else {
+            return Err(CapacityError::new(self, capacity, len));
+        };
+        let mut encoder = PbEncoder::new(SliceWriter { buf, pos: 0 });
+        match self.encode(&mut encoder) {
+            Ok(()) => Ok(len),
+            // Only happens if `compute_size` is wrong
+            Err(()) => Err(CapacityError::new(self, capacity, len)),
+        }
+    }
+
+    #[cfg(feature = "encode")]
+    /// Encode this message into `encoder`, checking first that it fits into the remaining
+    /// capacity of the writer.
+    ///
+    /// If the writer has a fixed capacity, as reported by [`PbWrite::pb_remaining`], and the
+    /// message doesn't fit, a [`CapacityError`] is returned without writing anything. The error
+    /// contains the number of missing bytes and the top-level field that would have been cut off,
+    /// which helps with sizing buffers. Otherwise, the message is encoded normally.
+    ///
+    /// ```
+    /// use core::time::Duration;
+    /// use micropb::{heapless::Vec, CapacityError, EncodeError, MessageExt, PbEncoder};
+    ///
+    /// let mut encoder = PbEncoder::new(Vec::<u8, 4>::new());
+    /// let err = Duration::new(300, 5).encode_checked(&mut encoder).unwrap_err();
+    /// // The nanoseconds in field 2 don't fit
+    /// assert_eq!(err, EncodeError::Capacity(CapacityError { needed: 1, field_num: Some(2) }));
+    /// assert!(encoder.as_writer().is_empty());
+    /// ```
+    fn encode_checked<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), EncodeError<W::Error>>
+    where
+        Self: MessageEncode,
+    {
+        if let Some(remaining) = encoder.as_writer().pb_remaining() {
+            let size = self.compute_size();
+            if size > remaining {
+                return Err(EncodeError::Capacity(CapacityError::new(
+                    self, remaining, size,
+                )));
+            }
+        }
+        self.encode(encoder).map_err(EncodeError::Writer)
+    }
+
+    #[cfg(all(feature = "encode", feature = "alloc"))]
+    /// Encode this message into a new `Vec`.
+    fn encode_to_vec(&self) -> alloc::vec::Vec<u8>
+    where
+        Self: MessageEncode,
+    {
+        let mut encoder = PbEncoder::new(alloc::vec::Vec::with_capacity(self.compute_size()));
+        match self.encode(&mut encoder) {
+            Ok(()) => encoder.into_writer(),
+            Err(never) => match never {},
+        }
+    }
+}
+
+#[cfg(any(feature = "encode", feature = "decode"))]
+impl<T: ?Sized> MessageExt for T {}
+
+#[cfg(feature = "encode")]
+/// Writer into a slice that's large enough for the entire message
+struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+#[cfg(feature = "encode")]
+impl PbWrite for SliceWriter<'_> {
+    type Error = ();
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        let end = self.pos + data.len();
+        self.buf
+            .get_mut(self.pos..end)
+            .ok_or(())?
+            .copy_from_slice(data);
+        self.pos = end;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "encode", feature = "decode"))]
+mod tests {
+    use core::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn message_ext() {
+        let msg = Duration::new(300, 5);
+        let encoded = [0x08, 0xAC, 0x02, 0x10, 0x05];
+        assert_eq!(msg.encode_to_vec(), encoded);
+
+        let mut buf = [0xFF; 6];
+        assert_eq!(msg.encode_to_slice(&mut buf), Ok(5));
+        assert_eq!(buf, [0x08, 0xAC, 0x02, 0x10, 0x05, 0xFF]);
+        // Nothing is written if the buffer is too small
+        let mut buf = [0xFF; 4];
+        assert_eq!(
+            msg.encode_to_slice(&mut buf),
+            Err(CapacityError {
+                needed: 1,
+                field_num: Some(2)
+            })
+        );
+        assert_eq!(buf, [0xFF; 4]);
+        assert_eq!(Duration::ZERO.encode_to_slice(&mut []), Ok(0));
+
+        assert_eq!(Duration::decode_from_slice(&encoded), Ok(msg));
+        assert_eq!(
+            Duration::decode_from_slice(&encoded[..4]),
+            Err(DecodeError::UnexpectedEof)
+        );
+    }
 }
@@ -10,7 +10,10 @@ where
     let uninit_src: &[MaybeUninit<T>] = unsafe { mem::transmute(src) };
 
     let n = this.len().min(uninit_src.len());
-    this[..n].copy_from_slice(&uninit_src[..n]);
+    // SAFETY: `n` doesn't exceed the length of either slice, and the two slices can't overlap
+    // since `this` is a mutable reference. Copying through pointers also avoids the bounds and
+    // length checks of slice indexing, which would otherwise leave panic paths in the binary.
+    unsafe { core::ptr::copy_nonoverlapping(uninit_src.as_ptr(), this.as_mut_ptr(), n) };
     n
 }
 
@@ -34,3 +37,49 @@ pub(crate) unsafe fn maybe_uninit_slice_assume_init_ref<T>(slice: &[MaybeUninit<
     // reference and thus guaranteed to be valid for reads.
     unsafe { &*(slice as *const [MaybeUninit<T>] as *const [T]) }
 }
+
+/// Load a little-endian `u32` from a potentially unaligned pointer.
+///
+/// # Safety
+/// `ptr` must be valid for reads of 4 bytes.
+#[inline(always)]
+pub(crate) unsafe fn load_le_u32(ptr: *const u8) -> u32 {
+    // A byte array has no alignment requirement, so the compiler can use a single word load on
+    // targets with unaligned access and byte loads on the others
+    u32::from_le_bytes(core::ptr::read(ptr as *const [u8; 4]))
+}
+
+/// Load a little-endian `u64` from a potentially unaligned pointer.
+///
+/// # Safety
+/// `ptr` must be valid for reads of 8 bytes.
+#[inline(always)]
+pub(crate) unsafe fn load_le_u64(ptr: *const u8) -> u64 {
+    u64::from_le_bytes(core::ptr::read(ptr as *const [u8; 8]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_le() {
+        let buf = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A];
+        // Load from every offset to cover unaligned pointers
+        for offset in 0..=2 {
+            let b = &buf[offset..];
+            let expected32 = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+            let expected64 = u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]);
+            // SAFETY: b has at least 8 bytes
+            unsafe {
+                assert_eq!(load_le_u32(b.as_ptr()), expected32);
+                assert_eq!(load_le_u64(b.as_ptr()), expected64);
+            }
+        }
+        // SAFETY: buf has at least 8 bytes
+        unsafe {
+            assert_eq!(load_le_u32(buf.as_ptr()), 0x04030201);
+            assert_eq!(load_le_u64(buf[1..].as_ptr()), 0x0908070605040302);
+        }
+    }
+}
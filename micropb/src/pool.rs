@@ -0,0 +1,288 @@
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+const EMPTY: u8 = 0;
+const TAKEN: u8 = 1;
+const FREE: u8 = 2;
+
+/// How a [`MessagePool`] resets messages when they're returned.
+///
+/// Implement this on a marker type to reset messages in place, such as with the `clear` method
+/// generated by `micropb-gen`, and pass the type as the third parameter of the pool.
+pub trait PoolReset<M> {
+    /// Reset `msg` so that it can be checked out again.
+    fn reset(msg: &mut M);
+}
+
+/// Resets messages by overwriting them with `Default::default`. This is the default resetter of
+/// [`MessagePool`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResetDefault;
+
+impl<M: Default> PoolReset<M> for ResetDefault {
+    #[inline]
+    fn reset(msg: &mut M) {
+        *msg = M::default();
+    }
+}
+
+/// Fixed-size pool of reusable message instances.
+///
+/// High-rate decode loops can check a message out of the pool, decode into it, and drop it once
+/// it's been processed, which returns it to the pool. Returned messages are reset and kept for the
+/// next checkout, so containers that were reset in place don't need to be reallocated and large
+/// messages don't need to be constructed for every frame. Slots are initialized with
+/// `Default::default` on their first checkout, so the pool can be created in a `static`.
+///
+/// Checkout and return are lock-free and never block, so the pool can be shared between threads,
+/// or between tasks and interrupt handlers. Only available on targets with atomic
+/// compare-and-swap.
+///
+/// By default, returned messages are reset by overwriting them with `Default::default`. To keep
+/// the capacity of heap-allocated containers, pass a [`PoolReset`] type that clears the message in
+/// place, such as with the `clear` method generated by `micropb-gen`.
+///
+/// # Example
+/// ```
+/// use micropb::{MessageDecode, MessagePool, PoolReset};
+/// # #[derive(Default)]
+/// # struct ProtoMessage(Vec<u8>);
+/// # impl MessageDecode for ProtoMessage {
+/// #   fn decode<R: micropb::PbRead>(&mut self, decoder: &mut micropb::PbDecoder<R>, len: usize) -> Result<(), micropb::DecodeError<R::Error>> { Ok(()) }
+/// # }
+///
+/// struct Clear;
+///
+/// impl PoolReset<ProtoMessage> for Clear {
+///     fn reset(msg: &mut ProtoMessage) {
+///         msg.0.clear();
+///     }
+/// }
+///
+/// static POOL: MessagePool<ProtoMessage, 4, Clear> = MessagePool::new();
+///
+/// let frame = [0x08, 0x01];
+/// let mut msg = POOL.checkout().expect("all messages are in use");
+/// msg.merge_from_slice(&frame).unwrap();
+/// // Dropping the message returns it to the pool
+/// drop(msg);
+/// ```
+pub struct MessagePool<M, const N: usize, R = ResetDefault> {
+    states: [AtomicU8; N],
+    slots: [UnsafeCell<MaybeUninit<M>>; N],
+    _reset: PhantomData<fn() -> R>,
+}
+
+// SAFETY: Each slot is only accessed by the owner of its `Pooled` handle, which is handed out to
+// one caller at a time through compare-and-swap on the slot state. Messages can be checked out and
+// returned on different threads, so they need to be `Send`.
+unsafe impl<M: Send, const N: usize, R> Sync for MessagePool<M, N, R> {}
+
+impl<M: Default, const N: usize, R: PoolReset<M>> MessagePool<M, N, R> {
+    // Used to initialize the arrays, since inline `const` blocks are newer than the MSRV
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY_STATE: AtomicU8 = AtomicU8::new(EMPTY);
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY_SLOT: UnsafeCell<MaybeUninit<M>> = UnsafeCell::new(MaybeUninit::uninit());
+
+    /// Creates a pool of `N` messages, which are reset with `R` when returned.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            states: [Self::EMPTY_STATE; N],
+            slots: [Self::EMPTY_SLOT; N],
+            _reset: PhantomData,
+        }
+    }
+
+    /// Checks a message out of the pool, or returns `None` if all messages are in use.
+    ///
+    /// The message is in its reset state. It's returned to the pool when the handle is dropped.
+    pub fn checkout(&self) -> Option<Pooled<'_, M, N, R>> {
+        // Prefer messages that have been used before, so that new slots are only initialized
+        // when needed
+        if let Some(idx) = self.claim(FREE) {
+            return Some(Pooled::new(self, idx));
+        }
+        let idx = self.claim(EMPTY)?;
+        // SAFETY: The slot was just claimed, so no one else is accessing it
+        unsafe { (*self.slots[idx].get()).write(M::default()) };
+        Some(Pooled::new(self, idx))
+    }
+}
+
+impl<M, const N: usize, R> MessagePool<M, N, R> {
+    fn claim(&self, from: u8) -> Option<usize> {
+        self.states.iter().position(|state| {
+            state
+                .compare_exchange(from, TAKEN, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        })
+    }
+
+    /// Returns the number of messages in the pool, including the ones that are checked out.
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of messages that are currently checked out.
+    pub fn in_use(&self) -> usize {
+        self.states
+            .iter()
+            .filter(|state| state.load(Ordering::Relaxed) == TAKEN)
+            .count()
+    }
+}
+
+impl<M: Default, const N: usize, R: PoolReset<M>> Default for MessagePool<M, N, R> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M, const N: usize, R> fmt::Debug for MessagePool<M, N, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessagePool")
+            .field("capacity", &N)
+            .field("in_use", &self.in_use())
+            .finish()
+    }
+}
+
+impl<M, const N: usize, R> Drop for MessagePool<M, N, R> {
+    fn drop(&mut self) {
+        for (state, slot) in self.states.iter_mut().zip(&mut self.slots) {
+            // No slot can be TAKEN, since handles borrow the pool
+            if *state.get_mut() == FREE {
+                // SAFETY: FREE slots are initialized
+                unsafe { slot.get_mut().assume_init_drop() };
+            }
+        }
+    }
+}
+
+/// Message checked out of a [`MessagePool`], which is returned to the pool when dropped.
+pub struct Pooled<'a, M, const N: usize, R: PoolReset<M> = ResetDefault> {
+    pool: &'a MessagePool<M, N, R>,
+    idx: usize,
+    // Gives the handle the same auto traits as `&mut M`
+    _msg: PhantomData<&'a mut M>,
+}
+
+impl<'a, M, const N: usize, R: PoolReset<M>> Pooled<'a, M, N, R> {
+    fn new(pool: &'a MessagePool<M, N, R>, idx: usize) -> Self {
+        Self {
+            pool,
+            idx,
+            _msg: PhantomData,
+        }
+    }
+}
+
+impl<M, const N: usize, R: PoolReset<M>> Deref for Pooled<'_, M, N, R> {
+    type Target = M;
+
+    #[inline]
+    fn deref(&self) -> &M {
+        // SAFETY: The slot is initialized and exclusively owned by this handle
+        unsafe { (*self.pool.slots[self.idx].get()).assume_init_ref() }
+    }
+}
+
+impl<M, const N: usize, R: PoolReset<M>> DerefMut for Pooled<'_, M, N, R> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut M {
+        // SAFETY: The slot is initialized and exclusively owned by this handle
+        unsafe { (*self.pool.slots[self.idx].get()).assume_init_mut() }
+    }
+}
+
+impl<M: fmt::Debug, const N: usize, R: PoolReset<M>> fmt::Debug for Pooled<'_, M, N, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<M, const N: usize, R: PoolReset<M>> Drop for Pooled<'_, M, N, R> {
+    fn drop(&mut self) {
+        R::reset(self);
+        self.pool.states[self.idx].store(FREE, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkout() {
+        let pool = MessagePool::<Vec<u32>, 2>::new();
+        let mut a = pool.checkout().unwrap();
+        let mut b = pool.checkout().unwrap();
+        assert!(pool.checkout().is_none());
+        assert_eq!(pool.in_use(), 2);
+
+        a.push(1);
+        b.push(2);
+        assert_eq!(*a, [1]);
+        assert_eq!(*b, [2]);
+        drop(a);
+        assert_eq!(pool.in_use(), 1);
+
+        // Returned messages are reset
+        let c = pool.checkout().unwrap();
+        assert!(c.is_empty());
+        assert_eq!(pool.capacity(), 2);
+    }
+
+    /// Clears vectors in place, keeping their allocation
+    struct Clear;
+
+    impl PoolReset<Vec<u32>> for Clear {
+        fn reset(msg: &mut Vec<u32>) {
+            msg.clear();
+        }
+    }
+
+    #[test]
+    fn custom_reset() {
+        let pool = MessagePool::<Vec<u32>, 1, Clear>::new();
+        let mut msg = pool.checkout().unwrap();
+        msg.extend([1, 2, 3]);
+        let ptr = msg.as_ptr();
+        drop(msg);
+
+        // Allocation is reused
+        let msg = pool.checkout().unwrap();
+        assert!(msg.is_empty());
+        assert!(msg.capacity() >= 3);
+        assert_eq!(msg.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn threads() {
+        static POOL: MessagePool<Vec<u32>, 4, Clear> = MessagePool::new();
+        std::thread::scope(|s| {
+            for i in 0..8 {
+                s.spawn(move || {
+                    for _ in 0..1000 {
+                        if let Some(mut msg) = POOL.checkout() {
+                            assert!(msg.is_empty());
+                            msg.push(i);
+                            assert_eq!(*msg, [i]);
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(POOL.in_use(), 0);
+    }
+}
@@ -1,33 +1,38 @@
 //! Functions for calculating the size of Protobuf values on the wire, which is necessary for
 //! encoding Protobuf messages.
+//!
+//! There's a function for every scalar type, as well as for tags and length-delimited records.
+//! They match the output of the corresponding `PbEncoder` methods, so hand-written
+//! [`FieldEncode::compute_fields_size`](crate::FieldEncode::compute_fields_size) implementations
+//! can use them instead of computing varint lengths themselves.
+//!
+//! ```
+//! use micropb::size::*;
+//!
+//! // Field 1 is a `sint32` with the value -100, field 16 is a `string` containing "hello", and
+//! // field 3 is a `fixed32`
+//! let size = sizeof_field_tag(1) + sizeof_sint32(-100)
+//!     + sizeof_field_tag(16) + sizeof_len_record("hello".len())
+//!     + sizeof_field_tag(3) + sizeof_fixed32(7);
+//! assert_eq!(size, (1 + 2) + (2 + 6) + (1 + 4));
+//! ```
 
-use crate::Tag;
+#[cfg(feature = "strings")]
+use core::fmt;
 
+use crate::{wire, MessageEncode, PbEnum, Tag, WIRE_TYPE_LEN};
+
+#[inline]
 /// Calculate size of `uint32` on the wire.
 pub const fn sizeof_varint32(v: u32) -> usize {
-    match v {
-        0x0..=0x7F => 1,
-        0x80..=0x3FFF => 2,
-        0x4000..=0x1FFFFF => 3,
-        0x200000..=0xFFFFFFF => 4,
-        _ => 5,
-    }
+    wire::varint32_len(v)
 }
 
+#[inline]
 #[cfg(feature = "enable-64bit")]
 /// Calculate size of `uint64` on the wire.
 pub const fn sizeof_varint64(v: u64) -> usize {
-    const U32_MAX: u64 = u32::MAX as u64;
-    const U32_OVER_MAX: u64 = U32_MAX + 1;
-    match v {
-        0x0..=U32_MAX => sizeof_varint32(v as u32),
-        U32_OVER_MAX..=0x7FFFFFFFF => 5,
-        0x0800000000..=0x3FFFFFFFFFF => 6,
-        0x040000000000..=0x1FFFFFFFFFFFF => 7,
-        0x02000000000000..=0xFFFFFFFFFFFFFF => 8,
-        0x0100000000000000..=0x7FFFFFFFFFFFFFFF => 9,
-        _ => 10,
-    }
+    wire::varint64_len(v)
 }
 
 #[inline]
@@ -40,6 +45,13 @@ pub const fn sizeof_int32(i: i32) -> usize {
     }
 }
 
+#[inline]
+/// Calculate size of `int32` on the wire when encoded with
+/// [`PbEncoder::encode_int32_legacy`](crate::PbEncoder::encode_int32_legacy).
+pub const fn sizeof_int32_legacy(i: i32) -> usize {
+    sizeof_varint32(i as u32)
+}
+
 #[inline]
 #[cfg(feature = "enable-64bit")]
 /// Calculate size of `int64` on the wire.
@@ -50,14 +62,14 @@ pub const fn sizeof_int64(i: i64) -> usize {
 #[inline]
 /// Calculate size of `sint32` on the wire.
 pub const fn sizeof_sint32(i: i32) -> usize {
-    sizeof_varint32(((i << 1) ^ (i >> 31)) as u32)
+    sizeof_varint32(wire::zigzag_encode32(i))
 }
 
 #[inline]
 #[cfg(feature = "enable-64bit")]
 /// Calculate size of `sint64` on the wire.
 pub const fn sizeof_sint64(i: i64) -> usize {
-    sizeof_varint64(((i << 1) ^ (i >> 63)) as u64)
+    sizeof_varint64(wire::zigzag_encode64(i))
 }
 
 #[inline]
@@ -66,6 +78,63 @@ pub const fn sizeof_tag(tag: Tag) -> usize {
     sizeof_varint32(tag.varint())
 }
 
+#[inline]
+/// Calculate size of the tag of a field with field number `field_num` on the wire.
+///
+/// The wire type doesn't affect the size of the tag, so this is the same as [`sizeof_tag`] for
+/// any tag with this field number.
+pub const fn sizeof_field_tag(field_num: u32) -> usize {
+    sizeof_varint32(field_num << 3)
+}
+
+#[inline]
+/// Calculate size of `bool` on the wire.
+pub const fn sizeof_bool(_b: bool) -> usize {
+    1
+}
+
+#[inline]
+/// Calculate size of `fixed32` on the wire.
+pub const fn sizeof_fixed32(_u: u32) -> usize {
+    4
+}
+
+#[inline]
+/// Calculate size of `fixed64` on the wire.
+pub const fn sizeof_fixed64(_u: u64) -> usize {
+    8
+}
+
+#[inline]
+/// Calculate size of `sfixed32` on the wire.
+pub const fn sizeof_sfixed32(_i: i32) -> usize {
+    4
+}
+
+#[inline]
+/// Calculate size of `sfixed64` on the wire.
+pub const fn sizeof_sfixed64(_i: i64) -> usize {
+    8
+}
+
+#[inline]
+/// Calculate size of `float` on the wire.
+pub const fn sizeof_float(_f: f32) -> usize {
+    4
+}
+
+#[inline]
+/// Calculate size of `double` on the wire.
+pub const fn sizeof_double(_f: f64) -> usize {
+    8
+}
+
+#[inline]
+/// Calculate size of an enum value on the wire. Like `int32`, negative values take up 10 bytes.
+pub fn sizeof_enum<E: PbEnum>(e: E) -> usize {
+    sizeof_int32(e.to_i32())
+}
+
 /// Calculate size of a repeated packed field on the wire. Does not include the length prefix.
 ///
 /// ```
@@ -81,13 +150,22 @@ pub fn sizeof_packed<T: Copy, F: Fn(&T) -> usize>(elems: &[T], sizer: F) -> usiz
     elems.iter().map(sizer).sum()
 }
 
+/// Calculate size of a packed repeated enum field on the wire, excluding the length prefix.
+pub fn sizeof_packed_enum<E: PbEnum>(elems: &[E]) -> usize {
+    sizeof_packed(elems, |e| sizeof_enum(*e))
+}
+
 #[inline]
 /// Calculate size of length-delimited record on the wire, including the length prefix.
+///
+/// `len` is the length of the record's contents, such as the number of bytes in a `string` or
+/// `bytes` value, or the encoded size of a submessage.
 pub const fn sizeof_len_record(len: usize) -> usize {
     len + sizeof_varint32(len as u32)
 }
 
 #[inline]
+#[cfg(feature = "maps")]
 /// Calculate size of a key-value pair in a map. Does not include the length prefix.
 pub fn sizeof_map_elem<K: ?Sized, V: ?Sized, FK: FnMut(&K) -> usize, FV: FnMut(&V) -> usize>(
     key: &K,
@@ -99,3 +177,60 @@ pub fn sizeof_map_elem<K: ?Sized, V: ?Sized, FK: FnMut(&K) -> usize, FV: FnMut(&
     // sizes are 1 each
     2 + key_sizer(key) + val_sizer(val)
 }
+
+/// Calculate size of a slice of messages encoded as a repeated message field with field number
+/// `field_num`, as done by [`PbEncoder::encode_repeated_messages`](crate::PbEncoder::encode_repeated_messages).
+pub fn sizeof_repeated_messages<M: MessageEncode>(field_num: u32, msgs: &[M]) -> usize {
+    let tag_size = sizeof_tag(Tag::from_parts(field_num, WIRE_TYPE_LEN));
+    msgs.iter()
+        .map(|m| tag_size + sizeof_len_record(m.compute_size()))
+        .sum()
+}
+
+#[inline]
+/// Calculate size of a scalar value encoded as a field with field number `field_num`, as done by
+/// [`PbEncoder::encode_scalar_field`](crate::PbEncoder::encode_scalar_field).
+pub fn sizeof_scalar_field<T, F: FnOnce(T) -> usize>(field_num: u32, val: T, sizer: F) -> usize {
+    // Wire type doesn't affect the size of the tag
+    sizeof_tag(Tag::from_parts(field_num, WIRE_TYPE_LEN)) + sizer(val)
+}
+
+/// Calculate size of a slice of scalar values encoded as a packed field with field number
+/// `field_num`, as done by [`PbEncoder::encode_packed_field`](crate::PbEncoder::encode_packed_field).
+pub fn sizeof_packed_field<T: Copy, F: Fn(&T) -> usize>(
+    field_num: u32,
+    elems: &[T],
+    sizer: F,
+) -> usize {
+    sizeof_tag(Tag::from_parts(field_num, WIRE_TYPE_LEN))
+        + sizeof_len_record(sizeof_packed(elems, sizer))
+}
+
+#[cfg(feature = "strings")]
+/// Counts the number of bytes of formatted output.
+struct FmtCounter(usize);
+
+#[cfg(feature = "strings")]
+impl fmt::Write for FmtCounter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "strings")]
+/// Number of bytes of formatted output, ignoring formatting errors.
+pub(crate) fn fmt_len(args: fmt::Arguments) -> usize {
+    let mut counter = FmtCounter(0);
+    let _ = fmt::write(&mut counter, args);
+    counter.0
+}
+
+#[inline]
+#[cfg(feature = "strings")]
+/// Calculate size of a formatted `string` value on the wire, as done by
+/// [`PbEncoder::encode_string_fmt`](crate::PbEncoder::encode_string_fmt). Includes the length
+/// prefix.
+pub fn sizeof_string_fmt(args: fmt::Arguments) -> usize {
+    sizeof_len_record(fmt_len(args))
+}
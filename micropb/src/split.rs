@@ -0,0 +1,195 @@
+//! Utilities for splitting a message with a large repeated field into multiple messages that each
+//! fit within a size limit, such as the MTU of a transport.
+//!
+//! Elements of a repeated field are partitioned into consecutive chunks based on their encoded
+//! sizes, so each message only needs to be encoded once.
+//!
+//! # Example
+//!
+//! Split the `readings` field of a message into multiple messages, each at most 64 bytes long:
+//! ```ignore
+//! use micropb::{split, MessageEncode, PbEncoder};
+//!
+//! // Copy of the message without any readings, which is sent along with every chunk
+//! let mut header = batch.clone();
+//! header.readings.clear();
+//!
+//! for chunk in split::message_chunks(1, &batch.readings, header.compute_size(), 64) {
+//!     let chunk = chunk.expect("reading doesn't fit in a single packet");
+//!     header.readings = chunk.try_into().unwrap();
+//!
+//!     let mut encoder = PbEncoder::new(micropb::heapless::Vec::<u8, 64>::new());
+//!     header.encode(&mut encoder).unwrap();
+//!     send_packet(encoder.as_writer());
+//! }
+//! ```
+
+use core::fmt;
+
+use crate::{
+    size::{sizeof_len_record, sizeof_tag},
+    MessageEncode, Tag, WIRE_TYPE_LEN,
+};
+
+/// Error returned when a single element of a repeated field is too large to fit in a message on
+/// its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElementTooLarge {
+    /// Index of the element in the repeated field
+    pub index: usize,
+    /// Size of the message containing only the offending element
+    pub size: usize,
+}
+
+impl fmt::Display for ElementTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "element {} of repeated field needs {} bytes on its own",
+            self.index, self.size
+        )
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl crate::ErrorTrait for ElementTooLarge {}
+
+/// Iterator over consecutive chunks of a repeated field, returned by [`chunks_by_size`] and
+/// [`message_chunks`].
+///
+/// Each chunk is as large as possible without exceeding the size limit. If an element can't fit
+/// on its own, the iterator yields [`ElementTooLarge`] and then stops.
+#[derive(Debug, Clone)]
+pub struct Chunks<'a, T, F> {
+    elems: &'a [T],
+    index: usize,
+    base_size: usize,
+    max_size: usize,
+    elem_size: F,
+}
+
+impl<'a, T, F: FnMut(&T) -> usize> Iterator for Chunks<'a, T, F> {
+    type Item = Result<&'a [T], ElementTooLarge>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.elems.is_empty() {
+            return None;
+        }
+
+        let mut size = self.base_size;
+        let mut count = 0;
+        for elem in self.elems {
+            let elem_size = (self.elem_size)(elem);
+            if size + elem_size > self.max_size {
+                if count == 0 {
+                    let err = ElementTooLarge {
+                        index: self.index,
+                        size: size + elem_size,
+                    };
+                    self.elems = &[];
+                    return Some(Err(err));
+                }
+                break;
+            }
+            size += elem_size;
+            count += 1;
+        }
+
+        let (chunk, rest) = self.elems.split_at(count);
+        self.elems = rest;
+        self.index += count;
+        Some(Ok(chunk))
+    }
+}
+
+/// Split the elements of a repeated field into consecutive chunks, such that a message containing
+/// each chunk is at most `max_size` bytes long.
+///
+/// `base_size` is the size of the message without any elements in the repeated field, which is
+/// usually computed with [`MessageEncode::compute_size`]. `elem_size` returns the number of bytes
+/// each element adds to the message, including its tag.
+///
+/// This works for unpacked repeated fields, where each element is encoded independently. For
+/// packed fields, the elements share a single tag and length prefix, so `base_size` should also
+/// account for them.
+///
+/// No chunks are returned if `elems` is empty.
+pub fn chunks_by_size<T, F: FnMut(&T) -> usize>(
+    elems: &[T],
+    base_size: usize,
+    max_size: usize,
+    elem_size: F,
+) -> Chunks<'_, T, F> {
+    Chunks {
+        elems,
+        index: 0,
+        base_size,
+        max_size,
+        elem_size,
+    }
+}
+
+/// Split the elements of a repeated message field with field number `field_num` into
+/// consecutive chunks, such that a message containing each chunk is at most `max_size` bytes long.
+///
+/// `base_size` is the size of the message without any elements in the repeated field. See
+/// [`chunks_by_size`] for details.
+pub fn message_chunks<M: MessageEncode>(
+    field_num: u32,
+    elems: &[M],
+    base_size: usize,
+    max_size: usize,
+) -> Chunks<'_, M, impl FnMut(&M) -> usize> {
+    let tag_len = sizeof_tag(Tag::from_parts(field_num, WIRE_TYPE_LEN));
+    chunks_by_size(elems, base_size, max_size, move |m| {
+        tag_len + sizeof_len_record(m.compute_size())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use arrayvec::ArrayVec;
+
+    use super::*;
+
+    fn collect<F: FnMut(&u8) -> usize>(
+        chunks: Chunks<'_, u8, F>,
+    ) -> ArrayVec<Result<&[u8], ElementTooLarge>, 8> {
+        chunks.collect()
+    }
+
+    #[test]
+    fn chunks() {
+        let elems = [1, 2, 3, 4, 5];
+        let size = |&e: &u8| e as usize;
+        assert!(collect(chunks_by_size(&[], 0, 10, size))
+            .as_slice()
+            .is_empty());
+        assert_eq!(
+            collect(chunks_by_size(&elems, 0, 15, size)).as_slice(),
+            [Ok(elems.as_slice())]
+        );
+        assert_eq!(
+            collect(chunks_by_size(&elems, 2, 8, size)).as_slice(),
+            [Ok([1, 2, 3].as_slice()), Ok(&[4]), Ok(&[5])]
+        );
+        assert_eq!(
+            collect(chunks_by_size(&elems, 0, 5, size)).as_slice(),
+            [Ok([1, 2].as_slice()), Ok(&[3]), Ok(&[4]), Ok(&[5])]
+        );
+
+        // Iteration stops after an element that doesn't fit
+        assert_eq!(
+            collect(chunks_by_size(&elems, 1, 4, size)).as_slice(),
+            [
+                Ok([1, 2].as_slice()),
+                Ok(&[3]),
+                Err(ElementTooLarge { index: 3, size: 5 })
+            ]
+        );
+        assert_eq!(
+            collect(chunks_by_size(&elems, 5, 4, size)).as_slice(),
+            [Err(ElementTooLarge { index: 0, size: 6 })]
+        );
+    }
+}
@@ -0,0 +1,1072 @@
+//! Persisting a message in flash memory, such as a device's configuration.
+//!
+//! [`ConfigStore`] stores a single encoded message in a region of flash, along with a small header
+//! containing a schema ID, the length of the message, and a CRC-32 of the header and message. On
+//! load, the CRC is checked before the message is decoded, so a corrupted or partially-written
+//! message is never decoded. The schema ID is chosen by the application and should change whenever
+//! the stored message type changes incompatibly, so that stale data is rejected instead of being
+//! misinterpreted.
+//!
+//! The message is written before its header, so if power is lost while storing, the header is
+//! left erased and the region reads back as [`LoadError::Empty`]. The previous contents of the
+//! region are lost in that case. [`DualConfigStore`] avoids this by alternating between two
+//! regions, so the previous message is only invalidated once the new one has been verified.
+//!
+//! Flash is accessed through the [`Flash`] trait, which mirrors `NorFlash` from
+//! [`embedded-storage`](https://docs.rs/embedded-storage), so flash drivers implementing that
+//! trait can be adapted by forwarding each method.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use micropb::storage::{ConfigStore, LoadError};
+//!
+//! // Config is stored in the 4 KiB flash sector at 0x3F000
+//! let mut store: ConfigStore<_> = ConfigStore::new(flash, 0x3F000, 0x1000, CONFIG_SCHEMA);
+//! let mut config = Config::default();
+//! match store.load(&mut config) {
+//!     Ok(()) => {}
+//!     // Never stored, so fall back to the default config
+//!     Err(LoadError::Empty) => {}
+//!     Err(e) => log_error(e),
+//! }
+//!
+//! config.set_brightness(80);
+//! store.store(&config).unwrap();
+//! ```
+
+use core::fmt;
+
+use crate::{DecodeError, MessageDecode, MessageEncode, PbDecoder, PbEncoder, PbRead, PbWrite};
+
+/// NOR flash memory, modeled after `NorFlash` from `embedded-storage`.
+///
+/// Offsets are relative to the start of the flash. Erased flash reads as `0xFF`.
+pub trait Flash {
+    /// Error returned on flash access failure.
+    type Error;
+
+    /// Alignment and granularity of reads, in bytes.
+    const READ_SIZE: usize;
+    /// Alignment and granularity of writes, in bytes.
+    const WRITE_SIZE: usize;
+    /// Size of the smallest erasable unit, in bytes.
+    const ERASE_SIZE: usize;
+
+    /// Read `bytes.len()` bytes starting at `offset`.
+    ///
+    /// `offset` and `bytes.len()` are multiples of [`READ_SIZE`](Self::READ_SIZE).
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write `bytes` starting at `offset`, which has been erased beforehand.
+    ///
+    /// `offset` and `bytes.len()` are multiples of [`WRITE_SIZE`](Self::WRITE_SIZE).
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error>;
+
+    /// Erase the flash from `from` up to `to`, exclusive.
+    ///
+    /// `from` and `to` are multiples of [`ERASE_SIZE`](Self::ERASE_SIZE).
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error>;
+}
+
+impl<F: Flash> Flash for &mut F {
+    type Error = F::Error;
+
+    const READ_SIZE: usize = F::READ_SIZE;
+    const WRITE_SIZE: usize = F::WRITE_SIZE;
+    const ERASE_SIZE: usize = F::ERASE_SIZE;
+
+    #[inline]
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        (*self).read(offset, bytes)
+    }
+
+    #[inline]
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        (*self).write(offset, bytes)
+    }
+
+    #[inline]
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        (*self).erase(from, to)
+    }
+}
+
+/// Length of the header stored in front of the message, in bytes.
+///
+/// The header consists of a magic number, the schema ID, a sequence number, the message length,
+/// and the CRC, each stored as a little-endian `u32`. If the flash's write size doesn't divide the
+/// header length, the message starts at the next multiple of the write size instead.
+pub const HEADER_LEN: usize = 20;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"uPB1");
+
+/// Error returned when loading a stored message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoadError<E> {
+    /// No message is stored in the region
+    Empty,
+    /// Header or message is corrupted, possibly by an interrupted write
+    Corrupted,
+    /// Stored message is intact, but was written with a different schema ID
+    SchemaMismatch {
+        /// Schema ID in the header
+        found: u32,
+    },
+    /// Message passed the CRC check, but couldn't be decoded
+    Decode(DecodeError<E>),
+    /// Error returned from the flash
+    Flash(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for LoadError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Empty => f.write_str("no message stored"),
+            LoadError::Corrupted => f.write_str("stored message is corrupted"),
+            LoadError::SchemaMismatch { found } => {
+                write!(f, "stored message has schema ID {found:#x}")
+            }
+            LoadError::Decode(e) => write!(f, "failed to decode stored message: {e:?}"),
+            LoadError::Flash(e) => write!(f, "flash error: {e:?}"),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl<E: fmt::Debug> crate::ErrorTrait for LoadError<E> {}
+
+/// Error returned when storing a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreError<E> {
+    /// Encoded message doesn't fit in the region along with its header
+    TooLarge {
+        /// Encoded size of the message
+        size: usize,
+    },
+    /// Message couldn't be read back intact after being written
+    Verify,
+    /// Error returned from the flash
+    Flash(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for StoreError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::TooLarge { size } => {
+                write!(f, "message of {size} bytes doesn't fit in the region")
+            }
+            StoreError::Verify => f.write_str("stored message failed verification"),
+            StoreError::Flash(e) => write!(f, "flash error: {e:?}"),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl<E: fmt::Debug> crate::ErrorTrait for StoreError<E> {}
+
+/// CRC-32 (IEEE 802.3), computed bitwise to avoid the code size of a lookup table.
+#[derive(Clone, Copy)]
+struct Crc(u32);
+
+impl Crc {
+    fn new() -> Self {
+        Self(!0)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.0 ^= b as u32;
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+    }
+
+    fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+#[inline]
+const fn round_up(n: usize, align: usize) -> usize {
+    n.div_ceil(align) * align
+}
+
+/// Contents of the header, other than the magic number.
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    schema: u32,
+    seq: u32,
+    len: u32,
+    crc: u32,
+}
+
+impl Header {
+    fn crc(schema: u32, seq: u32, len: u32) -> Crc {
+        let mut crc = Crc::new();
+        crc.update(&schema.to_le_bytes());
+        crc.update(&seq.to_le_bytes());
+        crc.update(&len.to_le_bytes());
+        crc
+    }
+
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        for (dst, word) in
+            bytes
+                .chunks_exact_mut(4)
+                .zip([MAGIC, self.schema, self.seq, self.len, self.crc])
+        {
+            dst.copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes<E>(bytes: &[u8]) -> Result<Self, LoadError<E>> {
+        let mut words = bytes
+            .chunks_exact(4)
+            .map(|w| u32::from_le_bytes(w.try_into().unwrap_or_default()));
+        let mut next = || words.next().unwrap_or_default();
+        let (magic, schema, seq, len, crc) = (next(), next(), next(), next(), next());
+        if magic != MAGIC {
+            return if bytes.iter().take(HEADER_LEN).all(|&b| b == 0xFF) {
+                Err(LoadError::Empty)
+            } else {
+                Err(LoadError::Corrupted)
+            };
+        }
+        Ok(Self {
+            schema,
+            seq,
+            len,
+            crc,
+        })
+    }
+
+    /// Whether this header was written after `other`, accounting for wraparound.
+    fn is_newer_than(&self, other: &Header) -> bool {
+        (self.seq.wrapping_sub(other.seq) as i32) > 0
+    }
+}
+
+/// Writes the message to flash in chunks of `BUF` bytes, padding the end with `0xFF`.
+struct FlashWriter<'a, F, const BUF: usize> {
+    flash: &'a mut F,
+    pos: u32,
+    buf: [u8; BUF],
+    filled: usize,
+    crc: Crc,
+}
+
+impl<F: Flash, const BUF: usize> FlashWriter<'_, F, BUF> {
+    fn flush(&mut self) -> Result<(), F::Error> {
+        let len = round_up(self.filled, F::WRITE_SIZE);
+        if let Some(chunk) = self.buf.get_mut(..len) {
+            if let Some(pad) = chunk.get_mut(self.filled..) {
+                pad.fill(0xFF);
+            }
+            self.flash.write(self.pos, chunk)?;
+        }
+        self.pos += len as u32;
+        self.filled = 0;
+        Ok(())
+    }
+}
+
+impl<F: Flash, const BUF: usize> PbWrite for FlashWriter<'_, F, BUF> {
+    type Error = F::Error;
+
+    fn pb_write(&mut self, mut data: &[u8]) -> Result<(), Self::Error> {
+        self.crc.update(data);
+        while !data.is_empty() {
+            let space = self.buf.get_mut(self.filled..).unwrap_or_default();
+            let n = space.len().min(data.len());
+            let (now, rest) = data.split_at(n);
+            if let Some(dst) = space.get_mut(..n) {
+                dst.copy_from_slice(now);
+            }
+            self.filled += n;
+            data = rest;
+            if self.filled == BUF {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the stored message from flash in chunks of at most `BUF` bytes.
+struct FlashReader<'a, F, const BUF: usize> {
+    flash: &'a mut F,
+    pos: u32,
+    /// Number of message bytes that haven't been read from flash yet
+    remaining: usize,
+    buf: [u8; BUF],
+    start: usize,
+    end: usize,
+}
+
+impl<'a, F: Flash, const BUF: usize> FlashReader<'a, F, BUF> {
+    fn new(flash: &'a mut F, pos: u32, len: usize) -> Self {
+        Self {
+            flash,
+            pos,
+            remaining: len,
+            buf: [0; BUF],
+            start: 0,
+            end: 0,
+        }
+    }
+}
+
+impl<F: Flash, const BUF: usize> PbRead for FlashReader<'_, F, BUF> {
+    type Error = F::Error;
+
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        if self.start == self.end && self.remaining > 0 {
+            let len = round_up(self.remaining, F::READ_SIZE).min(BUF);
+            if let Some(chunk) = self.buf.get_mut(..len) {
+                self.flash.read(self.pos, chunk)?;
+            }
+            self.pos += len as u32;
+            self.start = 0;
+            self.end = len.min(self.remaining);
+            self.remaining -= self.end;
+        }
+        Ok(self.buf.get(self.start..self.end).unwrap_or_default())
+    }
+
+    fn pb_advance(&mut self, bytes: usize) {
+        self.start = (self.start + bytes).min(self.end);
+    }
+}
+
+/// Compile-time check of the buffer size used for accessing the flash.
+struct BufCheck<F, const BUF: usize>(F);
+
+impl<F: Flash, const BUF: usize> BufCheck<F, BUF> {
+    const VALID: () = assert!(
+        BUF >= HEADER_LEN && BUF % F::READ_SIZE == 0 && BUF % F::WRITE_SIZE == 0,
+        "buffer size must be at least HEADER_LEN and a multiple of the read and write sizes"
+    );
+}
+
+/// Region of flash holding one copy of the stored message.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    offset: u32,
+    size: u32,
+}
+
+impl Slot {
+    const fn msg_offset<F: Flash>() -> usize {
+        round_up(HEADER_LEN, F::WRITE_SIZE)
+    }
+
+    fn msg_pos<F: Flash>(&self) -> u32 {
+        self.offset + Self::msg_offset::<F>() as u32
+    }
+
+    fn read_header<F: Flash, const BUF: usize>(
+        &self,
+        flash: &mut F,
+    ) -> Result<Header, LoadError<F::Error>> {
+        let mut buf = [0; BUF];
+        let len = round_up(HEADER_LEN, F::READ_SIZE);
+        let bytes = buf.get_mut(..len).unwrap_or_default();
+        flash.read(self.offset, bytes).map_err(LoadError::Flash)?;
+        let header = Header::from_bytes(bytes)?;
+
+        let stored_len =
+            Self::msg_offset::<F>() as u64 + round_up(header.len as usize, F::READ_SIZE) as u64;
+        if stored_len > self.size as u64 {
+            return Err(LoadError::Corrupted);
+        }
+        Ok(header)
+    }
+
+    /// Read the header and check the CRC of the message, regardless of its schema ID.
+    fn check<F: Flash, const BUF: usize>(
+        &self,
+        flash: &mut F,
+    ) -> Result<Header, LoadError<F::Error>> {
+        let header = self.read_header::<F, BUF>(flash)?;
+        let mut crc = Header::crc(header.schema, header.seq, header.len);
+        let mut reader =
+            FlashReader::<_, BUF>::new(flash, self.msg_pos::<F>(), header.len as usize);
+        loop {
+            let chunk = reader.pb_read_chunk().map_err(LoadError::Flash)?;
+            if chunk.is_empty() {
+                break;
+            }
+            crc.update(chunk);
+            let n = chunk.len();
+            reader.pb_advance(n);
+        }
+
+        if crc.finish() != header.crc {
+            return Err(LoadError::Corrupted);
+        }
+        Ok(header)
+    }
+
+    /// Decode the message after its header has been checked.
+    fn decode<F: Flash, M: MessageDecode, const BUF: usize>(
+        &self,
+        flash: &mut F,
+        header: &Header,
+        schema: u32,
+        msg: &mut M,
+    ) -> Result<(), LoadError<F::Error>> {
+        if header.schema != schema {
+            return Err(LoadError::SchemaMismatch {
+                found: header.schema,
+            });
+        }
+        let len = header.len as usize;
+        let reader = FlashReader::<_, BUF>::new(flash, self.msg_pos::<F>(), len);
+        msg.decode(&mut PbDecoder::new(reader), len)
+            .and_then(|()| msg.check_required())
+            .map_err(|e| match e {
+                DecodeError::Reader(e) => LoadError::Flash(e),
+                e => LoadError::Decode(e),
+            })
+    }
+
+    /// Erase the slot and write `msg` into it, followed by the header.
+    fn write<F: Flash, M: MessageEncode, const BUF: usize>(
+        &self,
+        flash: &mut F,
+        schema: u32,
+        seq: u32,
+        msg: &M,
+    ) -> Result<(), StoreError<F::Error>> {
+        let len = msg.compute_size();
+        let total = Self::msg_offset::<F>() + round_up(len, F::WRITE_SIZE);
+        if total > self.size as usize {
+            return Err(StoreError::TooLarge { size: len });
+        }
+
+        let erase_end = self.offset + round_up(total, F::ERASE_SIZE) as u32;
+        flash
+            .erase(self.offset, erase_end)
+            .map_err(StoreError::Flash)?;
+
+        let mut writer = FlashWriter::<_, BUF> {
+            flash: &mut *flash,
+            pos: self.msg_pos::<F>(),
+            buf: [0; BUF],
+            filled: 0,
+            crc: Header::crc(schema, seq, len as u32),
+        };
+        let mut encoder = PbEncoder::new(&mut writer);
+        msg.encode(&mut encoder).map_err(StoreError::Flash)?;
+        writer.flush().map_err(StoreError::Flash)?;
+        let crc = writer.crc.finish();
+
+        // Write the header last, so the message is only recognized once it's fully written
+        let header = Header {
+            schema,
+            seq,
+            len: len as u32,
+            crc,
+        };
+        let mut buf = [0xFF; BUF];
+        if let Some(dst) = buf.get_mut(..HEADER_LEN) {
+            dst.copy_from_slice(&header.to_bytes());
+        }
+        let header_bytes = buf.get(..Self::msg_offset::<F>()).unwrap_or_default();
+        flash
+            .write(self.offset, header_bytes)
+            .map_err(StoreError::Flash)
+    }
+
+    /// Erase the header, which invalidates the stored message.
+    fn erase<F: Flash>(&self, flash: &mut F) -> Result<(), F::Error> {
+        let end = self.offset + round_up(Self::msg_offset::<F>(), F::ERASE_SIZE) as u32;
+        flash.erase(self.offset, end)
+    }
+}
+
+/// Stores a single message in a region of flash, protected by a CRC and tagged with a schema ID.
+///
+/// The region starts at `offset` and is `size` bytes long, both of which must be multiples of the
+/// flash's erase size. `BUF` is the size of the buffer used for accessing the flash, which must be
+/// at least [`HEADER_LEN`] and a multiple of the flash's read and write sizes. Otherwise, using the
+/// store fails to compile.
+///
+/// If power is lost while storing, the previous message is lost too. Use [`DualConfigStore`] to
+/// always keep a decodable copy.
+///
+/// See the [module-level docs](self) for details.
+#[derive(Debug)]
+pub struct ConfigStore<F, const BUF: usize = 32> {
+    flash: F,
+    slot: Slot,
+    schema: u32,
+}
+
+impl<F: Flash, const BUF: usize> ConfigStore<F, BUF> {
+    /// Create a store over the region of `flash` starting at `offset` that's `size` bytes long.
+    ///
+    /// Messages are stored and loaded with the ID `schema`.
+    pub fn new(flash: F, offset: u32, size: u32, schema: u32) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = BufCheck::<F, BUF>::VALID;
+        Self {
+            flash,
+            slot: Slot { offset, size },
+            schema,
+        }
+    }
+
+    /// Get a reference to the underlying flash.
+    pub fn flash(&self) -> &F {
+        &self.flash
+    }
+
+    /// Get a mutable reference to the underlying flash.
+    pub fn flash_mut(&mut self) -> &mut F {
+        &mut self.flash
+    }
+
+    /// Destroy the store and return the underlying flash.
+    pub fn into_flash(self) -> F {
+        self.flash
+    }
+
+    /// Check that the region contains an intact message with the expected schema ID, without
+    /// decoding it.
+    ///
+    /// Returns the length of the encoded message.
+    pub fn validate(&mut self) -> Result<usize, LoadError<F::Error>> {
+        let header = self.slot.check::<F, BUF>(&mut self.flash)?;
+        if header.schema != self.schema {
+            return Err(LoadError::SchemaMismatch {
+                found: header.schema,
+            });
+        }
+        Ok(header.len as usize)
+    }
+
+    /// Load the stored message into `msg`.
+    ///
+    /// The message is validated before being decoded, so `msg` is left untouched if the stored
+    /// message is missing, corrupted, or has the wrong schema ID. Like
+    /// [`MessageDecode::decode`], decoding merges the stored message into `msg`.
+    pub fn load<M: MessageDecode>(&mut self, msg: &mut M) -> Result<(), LoadError<F::Error>> {
+        let header = self.slot.check::<F, BUF>(&mut self.flash)?;
+        self.slot
+            .decode::<F, M, BUF>(&mut self.flash, &header, self.schema, msg)
+    }
+
+    /// Encode `msg` and store it in the region, replacing the previously stored message.
+    ///
+    /// Only the erase blocks needed for the new message are erased.
+    pub fn store<M: MessageEncode>(&mut self, msg: &M) -> Result<(), StoreError<F::Error>> {
+        self.slot
+            .write::<F, M, BUF>(&mut self.flash, self.schema, 0, msg)
+    }
+
+    /// Erase the stored message, so that loading returns [`LoadError::Empty`].
+    pub fn erase(&mut self) -> Result<(), F::Error> {
+        self.slot.erase(&mut self.flash)
+    }
+}
+
+/// Stores a message in two regions of flash, so that a decodable copy survives a power loss at any
+/// point while storing.
+///
+/// Each store writes the new message into the region that doesn't hold the current message,
+/// verifies it by reading it back, and only then invalidates the old copy. Each copy's header
+/// contains a sequence number, so if power is lost before the old copy is invalidated, loading
+/// picks the newer one. If the newer copy is corrupted, the older one is loaded instead.
+///
+/// Both regions are `size` bytes long and must not overlap. Otherwise, the requirements are the
+/// same as [`ConfigStore`].
+#[derive(Debug)]
+pub struct DualConfigStore<F, const BUF: usize = 32> {
+    flash: F,
+    slots: [Slot; 2],
+    schema: u32,
+}
+
+impl<F: Flash, const BUF: usize> DualConfigStore<F, BUF> {
+    /// Create a store over two regions of `flash` starting at `offset_a` and `offset_b`, each of
+    /// which is `size` bytes long.
+    ///
+    /// Messages are stored and loaded with the ID `schema`.
+    pub fn new(flash: F, offset_a: u32, offset_b: u32, size: u32, schema: u32) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = BufCheck::<F, BUF>::VALID;
+        Self {
+            flash,
+            slots: [
+                Slot {
+                    offset: offset_a,
+                    size,
+                },
+                Slot {
+                    offset: offset_b,
+                    size,
+                },
+            ],
+            schema,
+        }
+    }
+
+    /// Get a reference to the underlying flash.
+    pub fn flash(&self) -> &F {
+        &self.flash
+    }
+
+    /// Get a mutable reference to the underlying flash.
+    pub fn flash_mut(&mut self) -> &mut F {
+        &mut self.flash
+    }
+
+    /// Destroy the store and return the underlying flash.
+    pub fn into_flash(self) -> F {
+        self.flash
+    }
+
+    /// Get the region at `idx` and the other region.
+    fn slot_pair(&self, idx: usize) -> (Slot, Slot) {
+        let [a, b] = self.slots;
+        if idx == 0 {
+            (a, b)
+        } else {
+            (b, a)
+        }
+    }
+
+    /// Check both regions, returning the index and header of the newest intact copy.
+    fn find_newest(&mut self) -> Result<(usize, Header), LoadError<F::Error>> {
+        let [a, b] = self.slots;
+        let a = a.check::<F, BUF>(&mut self.flash);
+        let b = b.check::<F, BUF>(&mut self.flash);
+        match (a, b) {
+            // Don't fall back to the other copy if the flash itself is failing
+            (Err(LoadError::Flash(e)), _) | (_, Err(LoadError::Flash(e))) => {
+                Err(LoadError::Flash(e))
+            }
+            (Ok(a), Ok(b)) if b.is_newer_than(&a) => Ok((1, b)),
+            (Ok(a), _) => Ok((0, a)),
+            (Err(_), Ok(b)) => Ok((1, b)),
+            (Err(LoadError::Empty), Err(LoadError::Empty)) => Err(LoadError::Empty),
+            (Err(_), Err(_)) => Err(LoadError::Corrupted),
+        }
+    }
+
+    /// Check that one of the regions contains an intact message with the expected schema ID,
+    /// without decoding it.
+    ///
+    /// Returns the length of the newest encoded message.
+    pub fn validate(&mut self) -> Result<usize, LoadError<F::Error>> {
+        let (_, header) = self.find_newest()?;
+        if header.schema != self.schema {
+            return Err(LoadError::SchemaMismatch {
+                found: header.schema,
+            });
+        }
+        Ok(header.len as usize)
+    }
+
+    /// Load the newest intact copy of the stored message into `msg`.
+    ///
+    /// Like [`ConfigStore::load`], `msg` is left untouched if no copy can be loaded.
+    pub fn load<M: MessageDecode>(&mut self, msg: &mut M) -> Result<(), LoadError<F::Error>> {
+        let (idx, header) = self.find_newest()?;
+        let (slot, _) = self.slot_pair(idx);
+        slot.decode::<F, M, BUF>(&mut self.flash, &header, self.schema, msg)
+    }
+
+    /// Encode `msg` and store it, replacing the previously stored message.
+    ///
+    /// The message is written to the region that doesn't hold the newest copy, and read back to
+    /// verify it. Only after it's verified are any older copies invalidated.
+    pub fn store<M: MessageEncode>(&mut self, msg: &M) -> Result<(), StoreError<F::Error>> {
+        let (old, new, seq) = match self.find_newest() {
+            Ok((idx, header)) => {
+                let (old, new) = self.slot_pair(idx);
+                (old, new, header.seq.wrapping_add(1))
+            }
+            Err(LoadError::Flash(e)) => return Err(StoreError::Flash(e)),
+            // No intact copy, so start with the first region
+            Err(_) => {
+                let (new, old) = self.slot_pair(0);
+                (old, new, 0)
+            }
+        };
+
+        new.write::<F, M, BUF>(&mut self.flash, self.schema, seq, msg)?;
+        match new.check::<F, BUF>(&mut self.flash) {
+            Ok(header) if header.seq == seq => {}
+            Err(LoadError::Flash(e)) => return Err(StoreError::Flash(e)),
+            _ => return Err(StoreError::Verify),
+        }
+        old.erase(&mut self.flash).map_err(StoreError::Flash)
+    }
+
+    /// Erase both copies of the stored message, so that loading returns [`LoadError::Empty`].
+    pub fn erase(&mut self) -> Result<(), F::Error> {
+        let [a, b] = self.slots;
+        a.erase(&mut self.flash)?;
+        b.erase(&mut self.flash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrayvec::ArrayVec;
+
+    use super::*;
+    use crate::{size::sizeof_len_record, Presence};
+
+    /// RAM-backed NOR flash that checks alignment and only allows writes to erased bytes
+    struct MockFlash<const N: usize, const W: usize> {
+        mem: [u8; N],
+        /// Number of successful writes and erases
+        ops: usize,
+        /// Fail once this many writes and erases have succeeded, simulating a power loss
+        fail_after: Option<usize>,
+        /// Flip a bit at the start of every write, simulating worn-out flash
+        flip_writes: bool,
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum MockError {
+        Alignment,
+        NotErased,
+        PowerLoss,
+    }
+
+    impl<const N: usize, const W: usize> MockFlash<N, W> {
+        fn new() -> Self {
+            Self {
+                mem: [0xFF; N],
+                ops: 0,
+                fail_after: None,
+                flip_writes: false,
+            }
+        }
+
+        fn op(&mut self) -> Result<(), MockError> {
+            if self.fail_after == Some(self.ops) {
+                return Err(MockError::PowerLoss);
+            }
+            self.ops += 1;
+            Ok(())
+        }
+    }
+
+    impl<const N: usize, const W: usize> Flash for MockFlash<N, W> {
+        type Error = MockError;
+
+        const READ_SIZE: usize = 1;
+        const WRITE_SIZE: usize = W;
+        const ERASE_SIZE: usize = 64;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.mem[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            if offset % W != 0 || bytes.len() % W != 0 {
+                return Err(MockError::Alignment);
+            }
+            let dst = &mut self.mem[offset..offset + bytes.len()];
+            if dst.iter().any(|&b| b != 0xFF) {
+                return Err(MockError::NotErased);
+            }
+            self.op()?;
+            let dst = &mut self.mem[offset..offset + bytes.len()];
+            dst.copy_from_slice(bytes);
+            if self.flip_writes {
+                dst[0] ^= 0x80;
+            }
+            Ok(())
+        }
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            if from % 64 != 0 || to % 64 != 0 {
+                return Err(MockError::Alignment);
+            }
+            self.op()?;
+            self.mem[from as usize..to as usize].fill(0xFF);
+            Ok(())
+        }
+    }
+
+    /// Message with a single string field
+    #[derive(Debug, Default, PartialEq)]
+    struct Msg(ArrayVec<u8, 100>);
+
+    impl Msg {
+        fn new(len: usize) -> Self {
+            Self((0..len).map(|i| b'a' + (i % 26) as u8).collect())
+        }
+    }
+
+    impl MessageEncode for Msg {
+        fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+            encoder.encode_varint32(0x0A)?;
+            encoder.encode_bytes(&self.0)
+        }
+
+        fn compute_size(&self) -> usize {
+            1 + sizeof_len_record(self.0.len())
+        }
+    }
+
+    impl MessageDecode for Msg {
+        fn decode<R: PbRead>(
+            &mut self,
+            decoder: &mut PbDecoder<R>,
+            len: usize,
+        ) -> Result<(), DecodeError<R::Error>> {
+            let before = decoder.bytes_read();
+            while decoder.bytes_read() - before < len {
+                let tag = decoder.decode_tag()?;
+                if tag.field_num() == 1 {
+                    decoder.decode_bytes(&mut self.0, Presence::Explicit)?;
+                } else {
+                    decoder.skip_wire_value(tag.wire_type())?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn load<F: Flash, const BUF: usize>(
+        store: &mut ConfigStore<F, BUF>,
+    ) -> Result<Msg, LoadError<F::Error>> {
+        let mut msg = Msg::default();
+        store.load(&mut msg).map(|()| msg)
+    }
+
+    fn load_dual<F: Flash, const BUF: usize>(
+        store: &mut DualConfigStore<F, BUF>,
+    ) -> Result<Msg, LoadError<F::Error>> {
+        let mut msg = Msg::default();
+        store.load(&mut msg).map(|()| msg)
+    }
+
+    type DualStore = DualConfigStore<MockFlash<256, 4>>;
+
+    fn dual_store() -> DualStore {
+        DualConfigStore::new(MockFlash::new(), 0, 128, 128, 1)
+    }
+
+    fn is_erased(store: &DualStore, offset: usize) -> bool {
+        store.flash().mem[offset..offset + HEADER_LEN]
+            .iter()
+            .all(|&b| b == 0xFF)
+    }
+
+    #[test]
+    fn crc() {
+        let mut crc = Crc::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xCBF43926);
+    }
+
+    #[test]
+    fn store_load() {
+        let mut store: ConfigStore<_> = ConfigStore::new(MockFlash::<256, 4>::new(), 64, 128, 7);
+        assert_eq!(load(&mut store), Err(LoadError::Empty));
+
+        for len in [0, 1, 31, 70] {
+            let msg = Msg::new(len);
+            store.store(&msg).unwrap();
+            assert_eq!(store.validate(), Ok(msg.compute_size()));
+            assert_eq!(load(&mut store).unwrap(), msg);
+        }
+        // Flash outside of the region is untouched
+        let mem = &store.flash().mem;
+        assert!(mem[..64].iter().chain(&mem[192..]).all(|&b| b == 0xFF));
+
+        store.erase().unwrap();
+        assert_eq!(load(&mut store), Err(LoadError::Empty));
+
+        let mut small: ConfigStore<_> = ConfigStore::new(MockFlash::<64, 4>::new(), 0, 64, 7);
+        assert_eq!(
+            small.store(&Msg::new(50)),
+            Err(StoreError::TooLarge { size: 52 })
+        );
+        small.store(&Msg::new(40)).unwrap();
+    }
+
+    #[test]
+    fn large_write_size() {
+        // Message starts after the header padded to the write size
+        let mut store: ConfigStore<_, 64> =
+            ConfigStore::new(MockFlash::<128, 32>::new(), 0, 128, 1);
+        let msg = Msg::new(40);
+        store.store(&msg).unwrap();
+        assert_eq!(store.flash().mem[32..34], [0x0A, 40]);
+        assert_eq!(load(&mut store).unwrap(), msg);
+    }
+
+    #[test]
+    fn schema_mismatch() {
+        let mut flash = MockFlash::<128, 4>::new();
+        ConfigStore::<_>::new(&mut flash, 0, 128, 1)
+            .store(&Msg::new(5))
+            .unwrap();
+
+        let mut store = ConfigStore::<_>::new(&mut flash, 0, 128, 2);
+        let mut msg = Msg::new(3);
+        assert_eq!(
+            store.load(&mut msg),
+            Err(LoadError::SchemaMismatch { found: 1 })
+        );
+        assert_eq!(msg, Msg::new(3));
+    }
+
+    #[test]
+    fn corrupted() {
+        let mut store: ConfigStore<_> = ConfigStore::new(MockFlash::<128, 4>::new(), 0, 128, 1);
+        store.store(&Msg::new(20)).unwrap();
+
+        // Flip a bit in the message
+        store.flash_mut().mem[HEADER_LEN + 5] ^= 0x01;
+        assert_eq!(load(&mut store), Err(LoadError::Corrupted));
+        store.flash_mut().mem[HEADER_LEN + 5] ^= 0x01;
+        assert!(load(&mut store).is_ok());
+
+        // Length in the header points past the end of the region
+        store.flash_mut().mem[12] = 0xF0;
+        assert_eq!(load(&mut store), Err(LoadError::Corrupted));
+        // Header isn't recognized
+        store.flash_mut().mem[0] = 0;
+        assert_eq!(load(&mut store), Err(LoadError::Corrupted));
+    }
+
+    #[test]
+    fn interrupted_store() {
+        let mut store: ConfigStore<_> = ConfigStore::new(MockFlash::<128, 4>::new(), 0, 128, 1);
+        store.store(&Msg::new(10)).unwrap();
+
+        // Power is lost after the region is erased and the message is written, but before the
+        // header is written
+        let ops = store.flash().ops;
+        store.flash_mut().fail_after = Some(ops + 2);
+        assert_eq!(
+            store.store(&Msg::new(20)),
+            Err(StoreError::Flash(MockError::PowerLoss))
+        );
+        assert_eq!(load(&mut store), Err(LoadError::Empty));
+    }
+
+    #[test]
+    fn sequence_wraparound() {
+        let header = |seq| Header {
+            schema: 0,
+            seq,
+            len: 0,
+            crc: 0,
+        };
+        assert!(header(1).is_newer_than(&header(0)));
+        assert!(!header(0).is_newer_than(&header(1)));
+        assert!(!header(5).is_newer_than(&header(5)));
+        assert!(header(0).is_newer_than(&header(u32::MAX)));
+    }
+
+    #[test]
+    fn dual_store_load() {
+        let mut store = dual_store();
+        assert_eq!(load_dual(&mut store), Err(LoadError::Empty));
+
+        for len in 0..5 {
+            let msg = Msg::new(len);
+            store.store(&msg).unwrap();
+            assert_eq!(store.validate(), Ok(msg.compute_size()));
+            assert_eq!(load_dual(&mut store).unwrap(), msg);
+            // Stores alternate between the regions, and the older copy is always invalidated
+            let (new, old) = if len % 2 == 0 { (0, 128) } else { (128, 0) };
+            assert!(!is_erased(&store, new));
+            assert!(is_erased(&store, old));
+        }
+
+        store.erase().unwrap();
+        assert_eq!(load_dual(&mut store), Err(LoadError::Empty));
+    }
+
+    #[test]
+    fn dual_interrupted_write() {
+        let mut store = dual_store();
+        store.store(&Msg::new(1)).unwrap();
+        store.store(&Msg::new(2)).unwrap();
+
+        // Power is lost before the header of the new copy is written
+        let ops = store.flash().ops;
+        store.flash_mut().fail_after = Some(ops + 2);
+        assert_eq!(
+            store.store(&Msg::new(3)),
+            Err(StoreError::Flash(MockError::PowerLoss))
+        );
+        assert_eq!(load_dual(&mut store).unwrap(), Msg::new(2));
+
+        store.flash_mut().fail_after = None;
+        store.store(&Msg::new(3)).unwrap();
+        assert_eq!(load_dual(&mut store).unwrap(), Msg::new(3));
+    }
+
+    #[test]
+    fn dual_interrupted_invalidate() {
+        let mut store = dual_store();
+        store.store(&Msg::new(1)).unwrap();
+
+        // Power is lost after the new copy is verified, but before the old copy is invalidated
+        let ops = store.flash().ops;
+        store.flash_mut().fail_after = Some(ops + 3);
+        assert_eq!(
+            store.store(&Msg::new(2)),
+            Err(StoreError::Flash(MockError::PowerLoss))
+        );
+        store.flash_mut().fail_after = None;
+        assert!(!is_erased(&store, 0) && !is_erased(&store, 128));
+        // Newer copy wins
+        assert_eq!(load_dual(&mut store).unwrap(), Msg::new(2));
+
+        // If the newer copy is corrupted, fall back to the older one
+        store.flash_mut().mem[128 + HEADER_LEN + 1] ^= 0x01;
+        assert_eq!(load_dual(&mut store).unwrap(), Msg::new(1));
+        store.store(&Msg::new(3)).unwrap();
+        assert_eq!(load_dual(&mut store).unwrap(), Msg::new(3));
+        assert!(is_erased(&store, 0));
+    }
+
+    #[test]
+    fn dual_verify() {
+        let mut store = dual_store();
+        store.store(&Msg::new(1)).unwrap();
+
+        store.flash_mut().flip_writes = true;
+        assert_eq!(store.store(&Msg::new(2)), Err(StoreError::Verify));
+        // Old copy is kept if the new copy can't be verified
+        assert_eq!(load_dual(&mut store).unwrap(), Msg::new(1));
+    }
+
+    #[test]
+    fn dual_schema_mismatch() {
+        let mut store = dual_store();
+        store.store(&Msg::new(1)).unwrap();
+
+        let mut store = DualConfigStore::<_>::new(store.into_flash(), 0, 128, 128, 2);
+        assert_eq!(
+            load_dual(&mut store),
+            Err(LoadError::SchemaMismatch { found: 1 })
+        );
+        // Storing with the new schema replaces the old message
+        store.store(&Msg::new(2)).unwrap();
+        assert_eq!(load_dual(&mut store).unwrap(), Msg::new(2));
+    }
+}
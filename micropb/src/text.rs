@@ -0,0 +1,709 @@
+//! Hex and base64 text encodings of Protobuf data, for sending messages over text-only channels
+//! such as serial consoles or AT command interfaces.
+//!
+//! [`HexWriter`] and [`Base64Writer`] implement `PbWrite` on top of any [`core::fmt::Write`],
+//! converting the encoded bytes into text on the fly. [`decode_hex`] and [`decode_base64`] convert
+//! the text back into bytes. In the other direction, `HexReader` and `Base64Reader` implement
+//! `PbRead` on top of a reader that returns text, so the decoder can read messages directly from a
+//! text stream.
+//!
+//! # Example
+//!
+//! ```
+//! use micropb::{PbEncoder, text::{decode_hex, HexWriter}};
+//!
+//! let mut encoder = PbEncoder::new(HexWriter::new(String::new()));
+//! encoder.encode_varint32(150).unwrap();
+//! let hex = encoder.into_writer().into_inner();
+//! assert_eq!(hex, "9601");
+//!
+//! let mut buf = [0; 4];
+//! let len = decode_hex(&hex, &mut buf).unwrap();
+//! assert_eq!(&buf[..len], &[0x96, 0x01]);
+//! ```
+
+use core::fmt;
+
+#[cfg(feature = "decode")]
+use crate::PbRead;
+
+#[cfg(feature = "encode")]
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Error returned when converting hex or base64 text into bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextError {
+    /// Text contains a character that isn't part of the encoding
+    InvalidChar,
+    /// Text ends in the middle of an encoded byte
+    InvalidLength,
+    /// Output buffer is too small for the decoded bytes
+    Capacity,
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextError::InvalidChar => f.write_str("invalid character"),
+            TextError::InvalidLength => f.write_str("text ends in the middle of a byte"),
+            TextError::Capacity => f.write_str("output buffer too small"),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl crate::ErrorTrait for TextError {}
+
+#[inline]
+pub(crate) fn hex_val(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[inline]
+pub(crate) fn base64_val(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+fn write_ascii<W: fmt::Write>(writer: &mut W, out: &[u8]) -> fmt::Result {
+    // Output only contains characters from the hex and base64 alphabets, so it's valid UTF-8
+    match core::str::from_utf8(out) {
+        Ok(s) => writer.write_str(s),
+        Err(_) => Err(fmt::Error),
+    }
+}
+
+/// Adapter that implements `PbWrite` for any [`fmt::Write`], writing each byte
+/// as two lowercase hex digits.
+#[derive(Debug, Clone, Default)]
+pub struct HexWriter<W>(W);
+
+impl<W: fmt::Write> HexWriter<W> {
+    /// Create a hex writer that outputs text to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self(writer)
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn as_inner(&self) -> &W {
+        &self.0
+    }
+
+    /// Consume the hex writer and return the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.0
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<W: fmt::Write> crate::PbWrite for HexWriter<W> {
+    type Error = fmt::Error;
+
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        let mut out = [0; 32];
+        for chunk in data.chunks(out.len() / 2) {
+            for (b, digits) in chunk.iter().zip(out.chunks_exact_mut(2)) {
+                if let [hi, lo] = digits {
+                    *hi = HEX_DIGITS[(b >> 4) as usize];
+                    *lo = HEX_DIGITS[(b & 0xF) as usize];
+                }
+            }
+            write_ascii(&mut self.0, out.get(..chunk.len() * 2).unwrap_or_default())?;
+        }
+        Ok(())
+    }
+}
+
+/// Adapter that implements `PbWrite` for any [`fmt::Write`], writing the bytes
+/// as standard base64 with padding.
+///
+/// Base64 converts groups of 3 bytes at a time, so up to 2 bytes are buffered between writes.
+/// Call [`finish`](Self::finish) after encoding to write out the final group.
+#[derive(Debug, Clone, Default)]
+pub struct Base64Writer<W> {
+    writer: W,
+    pending: [u8; 2],
+    pending_len: usize,
+}
+
+fn base64_group(bytes: &[u8], out: &mut [u8; 4]) {
+    let (b0, b1, b2) = match *bytes {
+        [b0, b1, b2] => (b0, b1, b2),
+        [b0, b1] => (b0, b1, 0),
+        [b0] => (b0, 0, 0),
+        _ => (0, 0, 0),
+    };
+    let n = ((b0 as u32) << 16) | ((b1 as u32) << 8) | b2 as u32;
+    for (i, c) in out.iter_mut().enumerate() {
+        *c = if i <= bytes.len() {
+            BASE64_ALPHABET[((n >> (18 - 6 * i)) & 0x3F) as usize]
+        } else {
+            b'='
+        };
+    }
+}
+
+impl<W: fmt::Write> Base64Writer<W> {
+    /// Create a base64 writer that outputs text to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            pending: [0; 2],
+            pending_len: 0,
+        }
+    }
+
+    /// Get a reference to the underlying writer.
+    ///
+    /// Up to 2 bytes that have been written may not have been output yet.
+    pub fn as_inner(&self) -> &W {
+        &self.writer
+    }
+
+    /// Write out any buffered bytes along with padding, and return the underlying writer.
+    pub fn finish(mut self) -> Result<W, fmt::Error> {
+        if self.pending_len > 0 {
+            let mut out = [0; 4];
+            base64_group(
+                self.pending.get(..self.pending_len).unwrap_or_default(),
+                &mut out,
+            );
+            write_ascii(&mut self.writer, &out)?;
+        }
+        Ok(self.writer)
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<W: fmt::Write> crate::PbWrite for Base64Writer<W> {
+    type Error = fmt::Error;
+
+    fn pb_write(&mut self, mut data: &[u8]) -> Result<(), Self::Error> {
+        // Complete the pending group first
+        if self.pending_len > 0 {
+            let needed = 3 - self.pending_len;
+            if data.len() < needed {
+                for &b in data {
+                    if let Some(p) = self.pending.get_mut(self.pending_len) {
+                        *p = b;
+                        self.pending_len += 1;
+                    }
+                }
+                return Ok(());
+            }
+            let (head, rest) = data.split_at(needed);
+            let mut group = [0; 3];
+            for (g, &b) in group
+                .iter_mut()
+                .zip(self.pending.iter().take(self.pending_len).chain(head))
+            {
+                *g = b;
+            }
+            let mut out = [0; 4];
+            base64_group(&group, &mut out);
+            write_ascii(&mut self.writer, &out)?;
+            self.pending_len = 0;
+            data = rest;
+        }
+
+        let rem = data.chunks_exact(3).remainder();
+        let full = data.get(..data.len() - rem.len()).unwrap_or_default();
+        let mut out = [0; 32];
+        for batch in full.chunks(out.len() / 4 * 3) {
+            let mut len = 0;
+            for (group, chars) in batch.chunks_exact(3).zip(out.chunks_exact_mut(4)) {
+                if let Ok(chars) = chars.try_into() {
+                    base64_group(group, chars);
+                    len += 4;
+                }
+            }
+            write_ascii(&mut self.writer, out.get(..len).unwrap_or_default())?;
+        }
+
+        for (p, &b) in self.pending.iter_mut().zip(rem) {
+            *p = b;
+        }
+        self.pending_len = rem.len();
+        Ok(())
+    }
+}
+
+/// Incremental decoder that converts text into bytes one character at a time
+trait TextState: Default {
+    /// Process a non-whitespace character, returning a byte if one has been completed
+    fn push(&mut self, c: u8) -> Result<Option<u8>, TextError>;
+
+    /// Check that the text doesn't end in the middle of a byte
+    fn finish(&self) -> Result<(), TextError>;
+}
+
+#[derive(Debug, Clone, Default)]
+struct HexState {
+    hi: Option<u8>,
+}
+
+impl TextState for HexState {
+    #[inline]
+    fn push(&mut self, c: u8) -> Result<Option<u8>, TextError> {
+        let val = hex_val(c).ok_or(TextError::InvalidChar)?;
+        Ok(match self.hi.take() {
+            None => {
+                self.hi = Some(val);
+                None
+            }
+            Some(hi) => Some((hi << 4) | val),
+        })
+    }
+
+    fn finish(&self) -> Result<(), TextError> {
+        match self.hi {
+            Some(_) => Err(TextError::InvalidLength),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Base64State {
+    acc: u32,
+    bits: u32,
+    padding: bool,
+}
+
+impl TextState for Base64State {
+    #[inline]
+    fn push(&mut self, c: u8) -> Result<Option<u8>, TextError> {
+        if c == b'=' {
+            self.padding = true;
+            return Ok(None);
+        }
+        // Padding is only allowed at the end
+        if self.padding {
+            return Err(TextError::InvalidChar);
+        }
+        self.acc = (self.acc << 6) | base64_val(c).ok_or(TextError::InvalidChar)? as u32;
+        self.bits += 6;
+        if self.bits >= 8 {
+            self.bits -= 8;
+            return Ok(Some((self.acc >> self.bits) as u8));
+        }
+        Ok(None)
+    }
+
+    fn finish(&self) -> Result<(), TextError> {
+        // A single leftover character doesn't contain a full byte
+        if self.bits >= 6 {
+            return Err(TextError::InvalidLength);
+        }
+        Ok(())
+    }
+}
+
+fn decode_text<S: TextState>(text: &str, out: &mut [u8]) -> Result<usize, TextError> {
+    let mut state = S::default();
+    let mut len = 0;
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        if let Some(b) = state.push(c)? {
+            *out.get_mut(len).ok_or(TextError::Capacity)? = b;
+            len += 1;
+        }
+    }
+    state.finish()?;
+    Ok(len)
+}
+
+/// Decode hex text into `out`, returning the number of bytes written. Both uppercase and
+/// lowercase digits are accepted, and ASCII whitespace is ignored.
+pub fn decode_hex(text: &str, out: &mut [u8]) -> Result<usize, TextError> {
+    decode_text::<HexState>(text, out)
+}
+
+/// Decode standard base64 text into `out`, returning the number of bytes written. Padding is
+/// optional, and ASCII whitespace is ignored.
+pub fn decode_base64(text: &str, out: &mut [u8]) -> Result<usize, TextError> {
+    decode_text::<Base64State>(text, out)
+}
+
+/// Error returned from [`HexReader`] and [`Base64Reader`].
+#[cfg(feature = "decode")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextReadError<E> {
+    /// Text is not valid hex or base64
+    Text(TextError),
+    /// Error returned from the underlying reader
+    Reader(E),
+}
+
+#[cfg(feature = "decode")]
+impl<E: fmt::Display> fmt::Display for TextReadError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextReadError::Text(e) => e.fmt(f),
+            TextReadError::Reader(e) => e.fmt(f),
+        }
+    }
+}
+
+#[cfg(all(feature = "decode", any(feature = "std", feature = "core-error")))]
+impl<E: crate::ErrorTrait + 'static> crate::ErrorTrait for TextReadError<E> {
+    fn source(&self) -> Option<&(dyn crate::ErrorTrait + 'static)> {
+        match self {
+            TextReadError::Text(e) => Some(e),
+            TextReadError::Reader(e) => Some(e),
+        }
+    }
+}
+
+/// Size of the buffer holding decoded bytes in the text readers
+#[cfg(feature = "decode")]
+const READ_BUF_LEN: usize = 32;
+
+#[cfg(feature = "decode")]
+#[derive(Debug, Clone)]
+struct TextReader<R, S> {
+    reader: R,
+    state: S,
+    buf: [u8; READ_BUF_LEN],
+    start: usize,
+    end: usize,
+}
+
+#[cfg(feature = "decode")]
+impl<R: PbRead, S: TextState> TextReader<R, S> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            state: S::default(),
+            buf: [0; READ_BUF_LEN],
+            start: 0,
+            end: 0,
+        }
+    }
+
+    fn read_chunk(&mut self) -> Result<&[u8], TextReadError<R::Error>> {
+        // Only refill the buffer once all decoded bytes have been consumed
+        while self.start >= self.end {
+            self.start = 0;
+            self.end = 0;
+            let text = self.reader.pb_read_chunk().map_err(TextReadError::Reader)?;
+            if text.is_empty() {
+                self.state.finish().map_err(TextReadError::Text)?;
+                break;
+            }
+
+            let mut consumed = 0;
+            for &c in text {
+                if self.end >= self.buf.len() {
+                    break;
+                }
+                consumed += 1;
+                if c.is_ascii_whitespace() {
+                    continue;
+                }
+                if let Some(b) = self.state.push(c).map_err(TextReadError::Text)? {
+                    if let Some(slot) = self.buf.get_mut(self.end) {
+                        *slot = b;
+                        self.end += 1;
+                    }
+                }
+            }
+            self.reader.pb_advance(consumed);
+        }
+        Ok(self.buf.get(self.start..self.end).unwrap_or_default())
+    }
+
+    fn advance(&mut self, bytes: usize) {
+        self.start = (self.start + bytes).min(self.end);
+    }
+}
+
+macro_rules! text_reader {
+    ($name:ident, $state:ident, $desc:literal) => {
+        #[cfg(feature = "decode")]
+        #[doc = concat!("Adapter that implements [`PbRead`] on top of another [`PbRead`] containing ", $desc, " text, decoding the text on the fly.")]
+        ///
+        /// Only a small buffer of decoded bytes is kept, so the full binary message never needs to be
+        /// stored. ASCII whitespace in the text is ignored.
+        #[derive(Debug, Clone)]
+        pub struct $name<R>(TextReader<R, $state>);
+
+        #[cfg(feature = "decode")]
+        impl<R: PbRead> $name<R> {
+            #[doc = concat!("Create a reader that decodes ", $desc, " text from `reader`.")]
+            pub fn new(reader: R) -> Self {
+                Self(TextReader::new(reader))
+            }
+
+            /// Consume the adapter and return the underlying reader.
+            ///
+            /// Text that has been read from the underlying reader but not returned from the
+            /// adapter is lost.
+            pub fn into_inner(self) -> R {
+                self.0.reader
+            }
+        }
+
+        #[cfg(feature = "decode")]
+        impl<R: PbRead> PbRead for $name<R> {
+            type Error = TextReadError<R::Error>;
+
+            #[inline]
+            fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+                self.0.read_chunk()
+            }
+
+            #[inline]
+            fn pb_advance(&mut self, bytes: usize) {
+                self.0.advance(bytes)
+            }
+        }
+    };
+}
+
+text_reader!(HexReader, HexState, "hex");
+text_reader!(Base64Reader, Base64State, "base64");
+
+#[cfg(test)]
+mod tests {
+    use arrayvec::{ArrayString, ArrayVec};
+
+    use super::*;
+    use crate::{DecodeError, PbDecoder, PbWrite, Presence};
+
+    fn hex(data: &[&[u8]]) -> ArrayString<128> {
+        let mut writer = HexWriter::new(ArrayString::new());
+        for d in data {
+            writer.pb_write(d).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    fn base64(data: &[&[u8]]) -> ArrayString<128> {
+        let mut writer = Base64Writer::new(ArrayString::new());
+        for d in data {
+            writer.pb_write(d).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn hex_writer() {
+        assert_eq!(hex(&[]).as_str(), "");
+        assert_eq!(hex(&[&[0x00, 0x9A, 0xFF]]).as_str(), "009aff");
+        assert_eq!(
+            hex(&[&[0x01], &[], &[0x23, 0x45]]).as_str(),
+            hex(&[&[0x01, 0x23, 0x45]]).as_str()
+        );
+        // Longer than the internal buffer
+        let data: [u8; 40] = core::array::from_fn(|i| i as u8);
+        let text = hex(&[&data]);
+        assert_eq!(text.len(), 80);
+        assert!(text.starts_with("000102"));
+        assert!(text.ends_with("2627"));
+
+        let mut writer = HexWriter::new(ArrayString::<3>::new());
+        assert_eq!(writer.pb_write(&[1, 2]), Err(fmt::Error));
+    }
+
+    #[test]
+    fn base64_writer() {
+        assert_eq!(base64(&[]).as_str(), "");
+        assert_eq!(base64(&[b"f"]).as_str(), "Zg==");
+        assert_eq!(base64(&[b"fo"]).as_str(), "Zm8=");
+        assert_eq!(base64(&[b"foo"]).as_str(), "Zm9v");
+        assert_eq!(base64(&[b"foobar"]).as_str(), "Zm9vYmFy");
+        assert_eq!(base64(&[&[0xFB, 0xFF]]).as_str(), "+/8=");
+
+        // Output doesn't depend on how the data is split across writes
+        let data: [u8; 50] = core::array::from_fn(|i| (i * 37) as u8);
+        let expected = base64(&[&data]);
+        for split in 0..data.len() {
+            let (a, b) = data.split_at(split);
+            assert_eq!(base64(&[a, b]), expected);
+            let (b, c) = b.split_at(b.len().min(1));
+            assert_eq!(base64(&[a, b, &[], c]), expected);
+        }
+    }
+
+    #[test]
+    fn decode() {
+        let mut buf = [0; 8];
+        assert_eq!(decode_hex("", &mut buf), Ok(0));
+        assert_eq!(decode_hex("009aFF", &mut buf), Ok(3));
+        assert_eq!(&buf[..3], &[0x00, 0x9A, 0xFF]);
+        assert_eq!(decode_hex(" 01 23\r\n", &mut buf), Ok(2));
+        assert_eq!(&buf[..2], &[0x01, 0x23]);
+        assert_eq!(decode_hex("0g", &mut buf), Err(TextError::InvalidChar));
+        assert_eq!(decode_hex("012", &mut buf), Err(TextError::InvalidLength));
+        assert_eq!(
+            decode_hex("000000", &mut buf[..2]),
+            Err(TextError::Capacity)
+        );
+
+        assert_eq!(decode_base64("", &mut buf), Ok(0));
+        assert_eq!(decode_base64("Zm9vYmFy", &mut buf), Ok(6));
+        assert_eq!(&buf[..6], b"foobar");
+        assert_eq!(decode_base64("Zg==", &mut buf), Ok(1));
+        assert_eq!(&buf[..1], b"f");
+        assert_eq!(decode_base64("Zm8", &mut buf), Ok(2));
+        assert_eq!(&buf[..2], b"fo");
+        assert_eq!(decode_base64("+/8=\n", &mut buf), Ok(2));
+        assert_eq!(&buf[..2], &[0xFB, 0xFF]);
+        assert_eq!(decode_base64("Zm.v", &mut buf), Err(TextError::InvalidChar));
+        assert_eq!(
+            decode_base64("Zg==Zg", &mut buf),
+            Err(TextError::InvalidChar)
+        );
+        assert_eq!(
+            decode_base64("Zm9vY", &mut buf),
+            Err(TextError::InvalidLength)
+        );
+        assert_eq!(
+            decode_base64("Zm9v", &mut buf[..2]),
+            Err(TextError::Capacity)
+        );
+
+        // Round trip through the writers
+        let data: [u8; 50] = core::array::from_fn(|i| (i * 37) as u8);
+        let mut buf = [0; 50];
+        assert_eq!(decode_hex(&hex(&[&data]), &mut buf), Ok(50));
+        assert_eq!(buf, data);
+        assert_eq!(decode_base64(&base64(&[&data]), &mut buf), Ok(50));
+        assert_eq!(buf, data);
+    }
+
+    /// Reader that returns the text a few characters at a time
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl PbRead for Trickle<'_> {
+        type Error = ();
+
+        fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+            Ok(&self.0[..self.0.len().min(self.1)])
+        }
+
+        fn pb_advance(&mut self, bytes: usize) {
+            self.0 = &self.0[bytes..];
+        }
+    }
+
+    fn read_all<R: PbRead>(mut reader: R) -> Result<ArrayVec<u8, 64>, R::Error> {
+        let mut out = ArrayVec::new();
+        loop {
+            let chunk = reader.pb_read_chunk()?;
+            if chunk.is_empty() {
+                return Ok(out);
+            }
+            // Only consume part of the chunk to exercise partial advances
+            let n = chunk.len().min(3);
+            out.try_extend_from_slice(&chunk[..n]).unwrap();
+            reader.pb_advance(n);
+        }
+    }
+
+    #[test]
+    fn readers() {
+        let data: [u8; 50] = core::array::from_fn(|i| (i * 37) as u8);
+        let hex = hex(&[&data]);
+        let base64 = base64(&[&data]);
+        for step in [1, 2, 5, 7, 200] {
+            let reader = HexReader::new(Trickle(hex.as_bytes(), step));
+            assert_eq!(read_all(reader).unwrap().as_slice(), data);
+            let reader = Base64Reader::new(Trickle(base64.as_bytes(), step));
+            assert_eq!(read_all(reader).unwrap().as_slice(), data);
+        }
+
+        let reader = HexReader::new(" 01\r\n0a ".as_bytes());
+        assert_eq!(read_all(reader).unwrap().as_slice(), &[0x01, 0x0A]);
+        let reader = Base64Reader::new("Zm9v\nYg==".as_bytes());
+        assert_eq!(read_all(reader).unwrap().as_slice(), b"foob");
+        assert!(read_all(HexReader::new("    ".as_bytes()))
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            read_all(HexReader::new("01x2".as_bytes())),
+            Err(TextReadError::Text(TextError::InvalidChar))
+        );
+        assert_eq!(
+            read_all(HexReader::new("012".as_bytes())),
+            Err(TextReadError::Text(TextError::InvalidLength))
+        );
+        assert_eq!(
+            read_all(Base64Reader::new("Zm9vY".as_bytes())),
+            Err(TextReadError::Text(TextError::InvalidLength))
+        );
+    }
+
+    #[test]
+    fn decode_from_text() {
+        let mut decoder = PbDecoder::new(HexReader::new("96 01 03 616263".as_bytes()));
+        assert_eq!(decoder.decode_varint32(), Ok(150));
+        let mut s = ArrayString::<4>::new();
+        decoder.decode_string(&mut s, Presence::Explicit).unwrap();
+        assert_eq!(s.as_str(), "abc");
+        assert_eq!(decoder.decode_varint32(), Err(DecodeError::UnexpectedEof));
+
+        let mut decoder = PbDecoder::new(HexReader::new("9g".as_bytes()));
+        assert_eq!(
+            decoder.decode_varint32(),
+            Err(DecodeError::Reader(TextReadError::Text(
+                TextError::InvalidChar
+            )))
+        );
+    }
+
+    #[test]
+    fn error_source() {
+        use std::error::Error;
+
+        let mut decoder = PbDecoder::new(HexReader::new("9g".as_bytes()));
+        let err = decoder.decode_varint32().unwrap_err();
+        // Source chain goes from the decode error down to the text error
+        let source = err.source().unwrap();
+        assert!(source.is::<TextReadError<never::Never>>());
+        let source = source.source().unwrap();
+        assert_eq!(source.downcast_ref(), Some(&TextError::InvalidChar));
+        assert!(source.source().is_none());
+    }
+
+    #[test]
+    fn float_bits_through_text() {
+        use crate::PbEncoder;
+
+        let f32_bits = [0x7FC00001, 0x80000000, 0x00000001];
+        let f64_bits = [0xFFF8000000000BAD, 0x8000000000000000, 0x000FFFFFFFFFFFFF];
+        let mut hex = PbEncoder::new(HexWriter::new(ArrayString::<128>::new()));
+        let mut base64 = PbEncoder::new(Base64Writer::new(ArrayString::<128>::new()));
+        for (&b32, &b64) in f32_bits.iter().zip(&f64_bits) {
+            hex.encode_float(f32::from_bits(b32)).unwrap();
+            hex.encode_double(f64::from_bits(b64)).unwrap();
+            base64.encode_float(f32::from_bits(b32)).unwrap();
+            base64.encode_double(f64::from_bits(b64)).unwrap();
+        }
+        let hex = hex.into_writer().into_inner();
+        let base64 = base64.into_writer().finish().unwrap();
+
+        let mut hex = PbDecoder::new(HexReader::new(hex.as_bytes()));
+        let mut base64 = PbDecoder::new(Base64Reader::new(base64.as_bytes()));
+        for (&b32, &b64) in f32_bits.iter().zip(&f64_bits) {
+            assert_eq!(hex.decode_float().unwrap().to_bits(), b32);
+            assert_eq!(hex.decode_double().unwrap().to_bits(), b64);
+            assert_eq!(base64.decode_float().unwrap().to_bits(), b32);
+            assert_eq!(base64.decode_double().unwrap().to_bits(), b64);
+        }
+    }
+}
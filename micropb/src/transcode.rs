@@ -0,0 +1,522 @@
+//! Re-encoding of Protobuf messages into a canonical encoding, for gateways that forward messages
+//! between peers with different Protobuf implementations.
+//!
+//! [`Transcoder`] walks the wire data of a message with the same raw field walker as
+//! [`decode_fields`](crate::decode_fields), rather than decoding it into a generated message type.
+//! Instead of a full schema, it's given a list of the fields whose encoding depends on their type,
+//! so all other fields, including unknown ones, are passed through. The fields are normalized as
+//! follows:
+//!
+//! - All tags and varints are re-encoded in their minimal form, so padded varints are shortened.
+//! - [`FieldKind::Int32`] fields have negative values sign-extended to 10 bytes, which translates
+//!   the 5-byte encoding used by some legacy encoders. With
+//!   [`legacy_int32`](Transcoder::legacy_int32), the translation goes the other way.
+//! - [`FieldKind::Packed`] fields have all of their elements, whether packed or expanded, combined
+//!   into a single packed record where the field first occurs. Empty packed records are dropped.
+//! - [`FieldKind::Message`] fields are transcoded recursively with their own list of fields.
+//!
+//! Fields whose wire type doesn't match their [`FieldKind`] are passed through like unlisted fields.
+//! Transcoding the output again returns the same bytes.
+//!
+//! # Example
+//!
+//! ```
+//! use micropb::transcode::{Elem, FieldKind, Transcoder};
+//! use micropb::{MessageEncode, PbEncoder};
+//!
+//! // Field 1 is an int32, field 2 is a repeated uint32, and field 3 is a submessage whose
+//! // field 1 is an int32
+//! static INNER: [(u32, FieldKind); 1] = [(1, FieldKind::Int32)];
+//! static FIELDS: [(u32, FieldKind); 3] = [
+//!     (1, FieldKind::Int32),
+//!     (2, FieldKind::Packed(Elem::Varint)),
+//!     (3, FieldKind::Message(&INNER)),
+//! ];
+//!
+//! // -1 as a 5-byte varint, followed by two expanded elements of field 2
+//! let input = [0x08, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F, 0x10, 0x01, 0x10, 0x02];
+//! let msg = Transcoder::new(&FIELDS).transcode(&input).unwrap();
+//!
+//! let mut encoder = PbEncoder::new(vec![]);
+//! msg.encode(&mut encoder).unwrap();
+//! let output = encoder.into_writer();
+//! assert_eq!(output.len(), msg.compute_size());
+//! assert_eq!(
+//!     output,
+//!     [0x08, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x12, 0x02, 0x01, 0x02]
+//! );
+//! ```
+
+use never::Never;
+
+use crate::{
+    DecodeError, MessageEncode, PbDecoder, PbEncoder, PbWrite, Tag, WireType, WireValue,
+    WIRE_TYPE_LEN,
+};
+
+/// Element type of a [`FieldKind::Packed`] field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Elem {
+    /// Varint element that isn't an `int32`, such as `uint32`, `int64`, `sint32`, or `bool`.
+    Varint,
+    /// `int32` or enum element, normalized like [`FieldKind::Int32`].
+    Int32,
+    /// Fixed 32-bit element, such as `fixed32` or `float`.
+    Fixed32,
+    /// Fixed 64-bit element, such as `fixed64` or `double`.
+    Fixed64,
+}
+
+impl Elem {
+    const fn wire_type(self) -> WireType {
+        match self {
+            Elem::Varint | Elem::Int32 => WireType::Varint,
+            Elem::Fixed32 => WireType::I32,
+            Elem::Fixed64 => WireType::I64,
+        }
+    }
+}
+
+/// How a field is normalized by a [`Transcoder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldKind<'s> {
+    /// `int32` or enum field that isn't packed.
+    Int32,
+    /// Repeated numeric field that should be packed.
+    Packed(Elem),
+    /// Message field, singular or repeated, along with the field numbers and kinds of the message.
+    Message(&'s [(u32, FieldKind<'s>)]),
+}
+
+/// Re-encodes messages into a canonical encoding. See the [module docs](self) for details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transcoder<'s> {
+    fields: &'s [(u32, FieldKind<'s>)],
+    legacy_int32: bool,
+    max_depth: Option<usize>,
+}
+
+impl<'s> Transcoder<'s> {
+    /// Create a transcoder for messages with the given field numbers and kinds. Fields that
+    /// aren't listed only have their varints minimized.
+    pub const fn new(fields: &'s [(u32, FieldKind<'s>)]) -> Self {
+        Self {
+            fields,
+            legacy_int32: false,
+            max_depth: None,
+        }
+    }
+
+    /// Encode negative `int32` values as 5-byte varints, for peers that expect the legacy encoding
+    /// produced by [`PbEncoder::encode_int32_legacy`]. Conforming decoders accept both encodings.
+    pub const fn legacy_int32(mut self, legacy_int32: bool) -> Self {
+        self.legacy_int32 = legacy_int32;
+        self
+    }
+
+    /// Limit how deeply [`FieldKind::Message`] fields can be nested, or remove the limit with
+    /// `None`, in the same way as `PbDecoder::set_max_depth`. Only needed for schemas that refer
+    /// to themselves, since the nesting depth is otherwise bounded by the schema. No limit by
+    /// default.
+    pub const fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Check that `input` is a valid encoded message, and wrap it in a [`Transcoded`] that encodes
+    /// the normalized form of the message.
+    ///
+    /// Returns an error if `input`, any of its submessages, or any of its packed records aren't
+    /// valid Protobuf wire data.
+    pub fn transcode<'a>(&self, input: &'a [u8]) -> Result<Transcoded<'a, 's>, DecodeError<Never>> {
+        self.validate(self.fields, input, 0)?;
+        Ok(Transcoded {
+            transcoder: *self,
+            input,
+        })
+    }
+
+    fn validate(
+        &self,
+        fields: &[(u32, FieldKind)],
+        buf: &[u8],
+        depth: usize,
+    ) -> Result<(), DecodeError<Never>> {
+        for field in Fields::new(buf) {
+            let (_, tag, value) = field?;
+            match (kind(fields, tag.field_num()), value) {
+                (Some(FieldKind::Message(fields)), WireValue::Len(bytes)) => {
+                    if self.max_depth.is_some_and(|max| depth >= max) {
+                        return Err(DecodeError::DepthLimit);
+                    }
+                    self.validate(fields, bytes, depth + 1)?;
+                }
+                (Some(FieldKind::Packed(elem)), WireValue::Len(bytes)) => {
+                    for e in PackedElems::new(elem, bytes) {
+                        e?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn int32(&self, v: u64) -> u64 {
+        if self.legacy_int32 {
+            v as u32 as u64
+        } else {
+            v as u32 as i32 as i64 as u64
+        }
+    }
+
+    fn encode_elem<W: PbWrite>(
+        &self,
+        elem: Elem,
+        v: u64,
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), W::Error> {
+        match elem {
+            Elem::Varint => encoder.encode_varint64(v),
+            Elem::Int32 => encoder.encode_varint64(self.int32(v)),
+            Elem::Fixed32 => encoder.encode_fixed32(v as u32),
+            Elem::Fixed64 => encoder.encode_fixed64(v),
+        }
+    }
+
+    // Only called on validated input, so decoding errors can't occur
+    fn encode_fields<W: PbWrite>(
+        &self,
+        fields: &[(u32, FieldKind)],
+        buf: &[u8],
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), W::Error> {
+        for (start, tag, value) in Fields::new(buf).map_while(Result::ok) {
+            let num = tag.field_num();
+            match (kind(fields, num), value) {
+                (Some(FieldKind::Int32), WireValue::Varint(v)) => {
+                    encoder.encode_tag(tag)?;
+                    encoder.encode_varint64(self.int32(v))?;
+                }
+
+                (Some(FieldKind::Packed(elem)), value) if is_elem(elem, &value) => {
+                    // All elements are written at the first occurrence of the field
+                    let seen = Fields::new(&buf[..start])
+                        .map_while(Result::ok)
+                        .any(|(_, tag, value)| tag.field_num() == num && is_elem(elem, &value));
+                    if seen {
+                        continue;
+                    }
+                    let len = counted(|encoder| {
+                        elems(elem, num, buf).try_for_each(|v| self.encode_elem(elem, v, encoder))
+                    });
+                    if len > 0 {
+                        encoder.encode_tag(Tag::from_parts(num, WIRE_TYPE_LEN))?;
+                        encoder.encode_varint32(len as u32)?;
+                        elems(elem, num, buf)
+                            .try_for_each(|v| self.encode_elem(elem, v, encoder))?;
+                    }
+                }
+
+                (Some(FieldKind::Message(fields)), WireValue::Len(bytes)) => {
+                    let len = counted(|encoder| self.encode_fields(fields, bytes, encoder));
+                    encoder.encode_tag(tag)?;
+                    encoder.encode_varint32(len as u32)?;
+                    self.encode_fields(fields, bytes, encoder)?;
+                }
+
+                (_, value) => {
+                    encoder.encode_tag(tag)?;
+                    match value {
+                        WireValue::Varint(v) => encoder.encode_varint64(v)?,
+                        WireValue::I64(v) => encoder.encode_fixed64(v)?,
+                        WireValue::Len(bytes) => encoder.encode_bytes(bytes)?,
+                        WireValue::I32(v) => encoder.encode_fixed32(v)?,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Normalized form of an encoded message, returned by [`Transcoder::transcode`].
+///
+/// It's encoded through [`MessageEncode`] like any other message, so it can also be written as a
+/// length-delimited record or through an async encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transcoded<'a, 's> {
+    transcoder: Transcoder<'s>,
+    input: &'a [u8],
+}
+
+impl MessageEncode for Transcoded<'_, '_> {
+    fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+        self.transcoder
+            .encode_fields(self.transcoder.fields, self.input, encoder)
+    }
+
+    fn compute_size(&self) -> usize {
+        counted(|encoder| self.encode(encoder))
+    }
+}
+
+fn kind<'s>(fields: &[(u32, FieldKind<'s>)], num: u32) -> Option<FieldKind<'s>> {
+    fields
+        .iter()
+        .find(|(n, _)| *n == num)
+        .map(|(_, kind)| *kind)
+}
+
+fn is_elem(elem: Elem, value: &WireValue) -> bool {
+    matches!(value, WireValue::Len(_)) || value.wire_type() == elem.wire_type()
+}
+
+/// Writer that discards its output, used to count the bytes written by an encoder
+struct Discard;
+
+impl PbWrite for Discard {
+    type Error = Never;
+
+    #[inline]
+    fn pb_write(&mut self, _data: &[u8]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+fn counted<F: FnOnce(&mut PbEncoder<Discard>) -> Result<(), Never>>(f: F) -> usize {
+    let mut encoder = PbEncoder::new(Discard);
+    let _ = f(&mut encoder);
+    encoder.bytes_written()
+}
+
+/// Iterator over all elements of the packed field `num` in `buf`, packed or expanded
+fn elems(elem: Elem, num: u32, buf: &[u8]) -> impl Iterator<Item = u64> + '_ {
+    Fields::new(buf)
+        .map_while(Result::ok)
+        .filter(move |(_, tag, value)| tag.field_num() == num && is_elem(elem, value))
+        .flat_map(move |(_, _, value)| {
+            let (single, packed) = match value {
+                WireValue::Len(bytes) => (None, bytes),
+                WireValue::Varint(v) | WireValue::I64(v) => (Some(v), &[][..]),
+                WireValue::I32(v) => (Some(v as u64), &[][..]),
+            };
+            single
+                .into_iter()
+                .chain(PackedElems::new(elem, packed).map_while(Result::ok))
+        })
+}
+
+/// Iterator over the fields of an encoded message, along with their offsets
+struct Fields<'a> {
+    decoder: PbDecoder<&'a [u8]>,
+    len: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self {
+            decoder: PbDecoder::new(buf),
+            len: buf.len(),
+        }
+    }
+
+    fn next_field(&mut self) -> Result<(usize, Tag, WireValue<'a>), DecodeError<Never>> {
+        let start = self.decoder.bytes_read();
+        let tag = self.decoder.decode_tag()?;
+        let wire_type = tag.typed_wire_type().ok_or(DecodeError::UnknownWireType)?;
+        let value = self.decoder.decode_wire_value(wire_type)?;
+        Ok((start, tag, value))
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(usize, Tag, WireValue<'a>), DecodeError<Never>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.decoder.bytes_read() >= self.len {
+            return None;
+        }
+        let res = self.next_field();
+        if res.is_err() {
+            // Stop after the first error
+            self.len = 0;
+        }
+        Some(res)
+    }
+}
+
+/// Iterator over the elements of a packed record
+struct PackedElems<'a> {
+    decoder: PbDecoder<&'a [u8]>,
+    len: usize,
+    elem: Elem,
+}
+
+impl<'a> PackedElems<'a> {
+    fn new(elem: Elem, buf: &'a [u8]) -> Self {
+        Self {
+            decoder: PbDecoder::new(buf),
+            len: buf.len(),
+            elem,
+        }
+    }
+}
+
+impl Iterator for PackedElems<'_> {
+    type Item = Result<u64, DecodeError<Never>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.decoder.bytes_read() >= self.len {
+            return None;
+        }
+        let res = match self.elem {
+            Elem::Varint | Elem::Int32 => self.decoder.decode_varint64(),
+            Elem::Fixed32 => self.decoder.decode_fixed32().map(u64::from),
+            Elem::Fixed64 => self.decoder.decode_fixed64(),
+        };
+        if res.is_err() {
+            self.len = 0;
+        }
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static INNER: [(u32, FieldKind); 2] =
+        [(1, FieldKind::Int32), (2, FieldKind::Packed(Elem::Fixed32))];
+    static FIELDS: [(u32, FieldKind); 4] = [
+        (1, FieldKind::Int32),
+        (2, FieldKind::Packed(Elem::Int32)),
+        (3, FieldKind::Message(&INNER)),
+        (4, FieldKind::Packed(Elem::Varint)),
+    ];
+    static RECURSIVE: [(u32, FieldKind); 1] = [(1, FieldKind::Message(&RECURSIVE))];
+
+    fn transcode(transcoder: Transcoder, input: &[u8]) -> Vec<u8> {
+        let msg = transcoder.transcode(input).unwrap();
+        let mut encoder = PbEncoder::new(vec![]);
+        msg.encode(&mut encoder).unwrap();
+        let output = encoder.into_writer();
+        assert_eq!(output.len(), msg.compute_size());
+        // Transcoding is idempotent
+        let again = transcoder.transcode(&output).unwrap();
+        let mut encoder = PbEncoder::new(vec![]);
+        again.encode(&mut encoder).unwrap();
+        assert_eq!(encoder.into_writer(), output);
+        output
+    }
+
+    #[test]
+    fn minimal_varints() {
+        let transcoder = Transcoder::new(&FIELDS);
+        // Padded tag and padded value of an unknown field, followed by an unknown fixed32 and a
+        // string, which are passed through
+        assert_eq!(
+            transcode(
+                transcoder,
+                &[0xA8, 0x80, 0x00, 0x96, 0x81, 0x80, 0x00, 0x3D, 1, 2, 3, 4, 0x32, 0x01, b'a']
+            ),
+            [0x28, 0x96, 0x01, 0x3D, 1, 2, 3, 4, 0x32, 0x01, b'a']
+        );
+        assert_eq!(transcode(transcoder, &[]), []);
+    }
+
+    #[test]
+    fn int32() {
+        let legacy = [0x08, 0xFE, 0xFF, 0xFF, 0xFF, 0x0F];
+        let canonical = [
+            0x08, 0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01,
+        ];
+        let transcoder = Transcoder::new(&FIELDS);
+        assert_eq!(transcode(transcoder, &legacy), canonical);
+        assert_eq!(transcode(transcoder, &canonical), canonical);
+        assert_eq!(transcode(transcoder.legacy_int32(true), &canonical), legacy);
+        assert_eq!(transcode(transcoder.legacy_int32(true), &legacy), legacy);
+        // Non-negative values are the same in both encodings
+        assert_eq!(transcode(transcoder, &[0x08, 0x85, 0x00]), [0x08, 0x05]);
+        // Unknown varint field isn't touched
+        assert_eq!(
+            transcode(transcoder, &[0x30, 0xFE, 0xFF, 0xFF, 0xFF, 0x0F]),
+            [0x30, 0xFE, 0xFF, 0xFF, 0xFF, 0x0F]
+        );
+    }
+
+    #[test]
+    fn packed() {
+        let transcoder = Transcoder::new(&FIELDS);
+        // Expanded elements of field 4 interleaved with field 1, then a packed record, then
+        // another expanded element
+        assert_eq!(
+            transcode(
+                transcoder,
+                &[0x20, 0x01, 0x08, 0x07, 0x20, 0x82, 0x00, 0x22, 0x02, 0x03, 0x04, 0x20, 0x05]
+            ),
+            [0x22, 0x05, 0x01, 0x02, 0x03, 0x04, 0x05, 0x08, 0x07]
+        );
+        // Packed int32 elements are normalized too
+        assert_eq!(
+            transcode(transcoder, &[0x12, 0x05, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F]),
+            [0x12, 0x0A, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]
+        );
+        // Empty packed records are dropped
+        assert_eq!(
+            transcode(transcoder, &[0x22, 0x00, 0x08, 0x01, 0x22, 0x00]),
+            [0x08, 0x01]
+        );
+        // Elements with the wrong wire type are passed through
+        assert_eq!(
+            transcode(transcoder, &[0x25, 1, 0, 0, 0, 0x20, 0x01]),
+            [0x25, 1, 0, 0, 0, 0x22, 0x01, 0x01]
+        );
+    }
+
+    #[test]
+    fn nested() {
+        let transcoder = Transcoder::new(&FIELDS);
+        // Submessage containing a legacy int32 and two expanded fixed32 elements
+        let input = [
+            0x1A, 0x10, 0x08, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F, 0x15, 1, 0, 0, 0, 0x15, 2, 0, 0, 0,
+        ];
+        assert_eq!(
+            transcode(transcoder, &input),
+            [
+                0x1A, 0x15, 0x08, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x12,
+                0x08, 1, 0, 0, 0, 2, 0, 0, 0
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let transcoder = Transcoder::new(&FIELDS);
+        assert_eq!(
+            transcoder.transcode(&[0x08]),
+            Err(DecodeError::UnexpectedEof)
+        );
+        assert_eq!(transcoder.transcode(&[0x0B]), Err(DecodeError::Deprecation));
+        // Truncated element in a packed record
+        assert_eq!(
+            transcoder.transcode(&[0x22, 0x01, 0x80]),
+            Err(DecodeError::UnexpectedEof)
+        );
+        // Invalid submessage
+        assert_eq!(
+            transcoder.transcode(&[0x1A, 0x01, 0x08]),
+            Err(DecodeError::UnexpectedEof)
+        );
+        // Invalid contents of unlisted length-delimited fields are passed through
+        assert!(transcoder.transcode(&[0x32, 0x01, 0x08]).is_ok());
+
+        let nested = [0x0A, 0x04, 0x0A, 0x02, 0x0A, 0x00];
+        let transcoder = Transcoder::new(&RECURSIVE);
+        assert!(transcoder.max_depth(Some(3)).transcode(&nested).is_ok());
+        assert_eq!(
+            transcoder.max_depth(Some(2)).transcode(&nested),
+            Err(DecodeError::DepthLimit)
+        );
+    }
+}
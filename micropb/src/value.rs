@@ -0,0 +1,1192 @@
+//! Representations of the `google.protobuf.Struct`, `google.protobuf.Value`, and
+//! `google.protobuf.ListValue` well-known types that don't require an allocator.
+//!
+//! These types are recursive, so generating them from `struct.proto` requires boxing, which isn't
+//! possible without `alloc`. Instead, the types in this module store the entire tree of values in
+//! a single flat [`PbVec`] of [`Node`]s, so `no_std` users can bound the number of values with a
+//! fixed-capacity vector and the length of each string with a fixed-capacity string type. Nodes
+//! are stored in depth-first order, with each node recording the size of its subtree.
+//!
+//! Values are read through [`ValueRef`], [`StructRef`], and [`ListRef`], and constructed through
+//! [`StructBuilder`] and [`ListBuilder`].
+//!
+//! To use these types in generated code, substitute them for the well-known types with
+//! `extern_type_path`:
+//! ```ignore
+//! gen.extern_type_path(".google.protobuf.Struct", "::micropb::value::HeaplessStruct<32, 16>");
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! use micropb::value::{ListValue, Scalar, Struct, ValueRef};
+//! # use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+//!
+//! let mut config = Struct::<String, Vec<_>>::default();
+//! let mut builder = config.builder();
+//! builder.insert("name", Scalar::String("sensor")).unwrap();
+//! builder
+//!     .insert_list("thresholds", |list| {
+//!         list.push(Scalar::Number(1.5))?;
+//!         list.push(Scalar::Number(3.0))
+//!     })
+//!     .unwrap();
+//! # let mut encoder = PbEncoder::new(vec![]);
+//! # config.encode(&mut encoder).unwrap();
+//! # let bytes = encoder.into_writer();
+//! # let mut config = Struct::<String, Vec<_>>::default();
+//! # config.decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len()).unwrap();
+//!
+//! assert_eq!(config.fields().get("name"), Some(ValueRef::String("sensor")));
+//! let Some(ValueRef::List(thresholds)) = config.fields().get("thresholds") else {
+//!     panic!()
+//! };
+//! assert_eq!(thresholds.get(1), Some(ValueRef::Number(3.0)));
+//! ```
+
+#![allow(clippy::result_unit_err)]
+
+use core::{fmt, marker::PhantomData, ops::DerefMut};
+
+use crate::container::{PbString, PbVec};
+#[cfg(feature = "encode")]
+use crate::{
+    size::sizeof_len_record, MessageEncode, PbEncoder, PbWrite, Tag, WIRE_TYPE_I64, WIRE_TYPE_LEN,
+    WIRE_TYPE_VARINT,
+};
+#[cfg(feature = "decode")]
+use crate::{DecodeError, MessageDecode, PbDecoder, PbRead, Presence};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind<S> {
+    Null,
+    Number(f64),
+    String(S),
+    Bool(bool),
+    Struct,
+    List,
+}
+
+impl<S> Kind<S> {
+    fn is_same_container(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Kind::Struct, Kind::Struct) | (Kind::List, Kind::List)
+        )
+    }
+}
+
+/// Single value in the tree of values stored by [`Struct`], [`ListValue`], and [`Value`].
+///
+/// This type is only exposed so that it can be used as the element type of the vector container.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node<S> {
+    /// Field name, only used for fields of a `Struct`
+    key: S,
+    kind: Kind<S>,
+    /// Number of descendants of this node, which are stored directly after it
+    span: usize,
+}
+
+impl<S: Default> Default for Node<S> {
+    fn default() -> Self {
+        Self {
+            key: S::default(),
+            kind: Kind::Null,
+            span: 0,
+        }
+    }
+}
+
+/// Scalar value, used to construct values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scalar<'a> {
+    /// `null`
+    Null,
+    /// Double-precision number
+    Number(f64),
+    /// String
+    String(&'a str),
+    /// Boolean
+    Bool(bool),
+}
+
+impl Scalar<'_> {
+    fn to_kind<S: PbString>(self) -> Result<Kind<S>, ()> {
+        Ok(match self {
+            Scalar::Null => Kind::Null,
+            Scalar::Number(n) => Kind::Number(n),
+            Scalar::String(s) => Kind::String(S::pb_from_str(s)?),
+            Scalar::Bool(b) => Kind::Bool(b),
+        })
+    }
+}
+
+/// Reference to a value, equivalent to `google.protobuf.Value`.
+#[derive(Debug, PartialEq)]
+pub enum ValueRef<'a, S> {
+    /// `null`
+    Null,
+    /// Double-precision number
+    Number(f64),
+    /// String
+    String(&'a str),
+    /// Boolean
+    Bool(bool),
+    /// Nested struct
+    Struct(StructRef<'a, S>),
+    /// Nested list
+    List(ListRef<'a, S>),
+}
+
+impl<S> Clone for ValueRef<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for ValueRef<'_, S> {}
+
+impl<'a, S: PbString> ValueRef<'a, S> {
+    fn new(kind: &'a Kind<S>, subtree: &'a [Node<S>]) -> Self {
+        match kind {
+            Kind::Null => ValueRef::Null,
+            Kind::Number(n) => ValueRef::Number(*n),
+            Kind::String(s) => ValueRef::String(s),
+            Kind::Bool(b) => ValueRef::Bool(*b),
+            Kind::Struct => ValueRef::Struct(StructRef { nodes: subtree }),
+            Kind::List => ValueRef::List(ListRef { nodes: subtree }),
+        }
+    }
+}
+
+/// Iterator over the direct children in a slice of nodes, along with their subtrees
+struct Children<'a, S> {
+    nodes: &'a [Node<S>],
+}
+
+impl<'a, S> Iterator for Children<'a, S> {
+    type Item = (&'a Node<S>, &'a [Node<S>]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, rest) = self.nodes.split_first()?;
+        let subtree = rest.get(..node.span).unwrap_or(rest);
+        self.nodes = rest.get(node.span..).unwrap_or_default();
+        Some((node, subtree))
+    }
+}
+
+/// Reference to the fields of a struct, equivalent to `google.protobuf.Struct`.
+#[derive(Debug, PartialEq)]
+pub struct StructRef<'a, S> {
+    nodes: &'a [Node<S>],
+}
+
+impl<S> Clone for StructRef<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for StructRef<'_, S> {}
+
+impl<'a, S: PbString> StructRef<'a, S> {
+    /// Iterate over the names and values of the fields, in the order they were inserted or
+    /// decoded.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, ValueRef<'a, S>)> {
+        Children { nodes: self.nodes }.map(|(node, subtree)| {
+            let key: &str = &node.key;
+            (key, ValueRef::new(&node.kind, subtree))
+        })
+    }
+
+    /// Get the value of the field named `key`.
+    ///
+    /// If there are multiple fields with the same name, the last one is returned, following the
+    /// Protobuf semantics for duplicate map keys.
+    pub fn get(&self, key: &str) -> Option<ValueRef<'a, S>> {
+        self.iter()
+            .filter(|(k, _)| *k == key)
+            .last()
+            .map(|(_, v)| v)
+    }
+
+    /// Number of fields in the struct.
+    pub fn len(&self) -> usize {
+        Children { nodes: self.nodes }.count()
+    }
+
+    /// Returns `true` if the struct has no fields.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+/// Reference to the elements of a list, equivalent to `google.protobuf.ListValue`.
+#[derive(Debug, PartialEq)]
+pub struct ListRef<'a, S> {
+    nodes: &'a [Node<S>],
+}
+
+impl<S> Clone for ListRef<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for ListRef<'_, S> {}
+
+impl<'a, S: PbString> ListRef<'a, S> {
+    /// Iterate over the elements of the list.
+    pub fn iter(&self) -> impl Iterator<Item = ValueRef<'a, S>> {
+        Children { nodes: self.nodes }.map(|(node, subtree)| ValueRef::new(&node.kind, subtree))
+    }
+
+    /// Get the element at index `idx`.
+    pub fn get(&self, idx: usize) -> Option<ValueRef<'a, S>> {
+        self.iter().nth(idx)
+    }
+
+    /// Number of elements in the list.
+    pub fn len(&self) -> usize {
+        Children { nodes: self.nodes }.count()
+    }
+
+    /// Returns `true` if the list has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+/// Drop all elements of `vec` past `len`.
+fn truncate<T, V: PbVec<T> + DerefMut<Target = [T]>>(vec: &mut V, len: usize) {
+    if let Some(tail) = vec.get_mut(len..) {
+        let tail: *mut [T] = tail;
+        // SAFETY: Shrinking the length is always valid. The elements past the new length are no
+        // longer part of the vector, so they're dropped exactly once. The container's storage
+        // isn't touched by `pb_set_len`, so the pointer to the tail remains valid.
+        unsafe {
+            vec.pb_set_len(len);
+            core::ptr::drop_in_place(tail);
+        }
+    }
+}
+
+/// Set the span of the node at `idx` to cover all nodes after it.
+fn close_node<S, V: DerefMut<Target = [Node<S>]>>(nodes: &mut V, idx: usize) {
+    let len = nodes.len();
+    if let Some(node) = nodes.get_mut(idx) {
+        node.span = len - idx - 1;
+    }
+}
+
+/// Push a node and return its index.
+fn push_node<S: Default, V: PbVec<Node<S>>>(
+    nodes: &mut V,
+    key: S,
+    kind: Kind<S>,
+) -> Result<usize, ()> {
+    nodes.pb_push(Node { key, kind, span: 0 })?;
+    Ok(nodes.len() - 1)
+}
+
+/// Push a struct or list node and fill it with `fill`, removing the node if anything fails.
+fn push_container<S, V, F>(nodes: &mut V, key: S, kind: Kind<S>, fill: F) -> Result<(), ()>
+where
+    S: Default,
+    V: PbVec<Node<S>> + DerefMut<Target = [Node<S>]>,
+    F: FnOnce(&mut V) -> Result<(), ()>,
+{
+    let idx = push_node(nodes, key, kind)?;
+    if fill(nodes).is_err() {
+        truncate(nodes, idx);
+        return Err(());
+    }
+    close_node(nodes, idx);
+    Ok(())
+}
+
+/// Builder for adding fields to a struct.
+///
+/// Methods return `Err(())` if a container is over capacity, in which case the struct is left
+/// unchanged.
+pub struct StructBuilder<'a, S, V> {
+    nodes: &'a mut V,
+    _string: PhantomData<S>,
+}
+
+impl<'a, S, V> StructBuilder<'a, S, V>
+where
+    S: PbString + Default,
+    V: PbVec<Node<S>> + DerefMut<Target = [Node<S>]>,
+{
+    fn new(nodes: &'a mut V) -> Self {
+        Self {
+            nodes,
+            _string: PhantomData,
+        }
+    }
+
+    /// Add a field with a scalar value.
+    pub fn insert(&mut self, key: &str, value: Scalar<'_>) -> Result<(), ()> {
+        push_node(self.nodes, S::pb_from_str(key)?, value.to_kind()?).map(|_| ())
+    }
+
+    /// Add a field containing a nested struct, whose fields are added by `fill`.
+    pub fn insert_struct<F>(&mut self, key: &str, fill: F) -> Result<(), ()>
+    where
+        F: FnOnce(&mut StructBuilder<'_, S, V>) -> Result<(), ()>,
+    {
+        push_container(self.nodes, S::pb_from_str(key)?, Kind::Struct, |nodes| {
+            fill(&mut StructBuilder::new(nodes))
+        })
+    }
+
+    /// Add a field containing a nested list, whose elements are added by `fill`.
+    pub fn insert_list<F>(&mut self, key: &str, fill: F) -> Result<(), ()>
+    where
+        F: FnOnce(&mut ListBuilder<'_, S, V>) -> Result<(), ()>,
+    {
+        push_container(self.nodes, S::pb_from_str(key)?, Kind::List, |nodes| {
+            fill(&mut ListBuilder::new(nodes))
+        })
+    }
+}
+
+/// Builder for adding elements to a list.
+///
+/// Methods return `Err(())` if a container is over capacity, in which case the list is left
+/// unchanged.
+pub struct ListBuilder<'a, S, V> {
+    nodes: &'a mut V,
+    _string: PhantomData<S>,
+}
+
+impl<'a, S, V> ListBuilder<'a, S, V>
+where
+    S: PbString + Default,
+    V: PbVec<Node<S>> + DerefMut<Target = [Node<S>]>,
+{
+    fn new(nodes: &'a mut V) -> Self {
+        Self {
+            nodes,
+            _string: PhantomData,
+        }
+    }
+
+    /// Add a scalar element.
+    pub fn push(&mut self, value: Scalar<'_>) -> Result<(), ()> {
+        push_node(self.nodes, S::default(), value.to_kind()?).map(|_| ())
+    }
+
+    /// Add a nested struct, whose fields are added by `fill`.
+    pub fn push_struct<F>(&mut self, fill: F) -> Result<(), ()>
+    where
+        F: FnOnce(&mut StructBuilder<'_, S, V>) -> Result<(), ()>,
+    {
+        push_container(self.nodes, S::default(), Kind::Struct, |nodes| {
+            fill(&mut StructBuilder::new(nodes))
+        })
+    }
+
+    /// Add a nested list, whose elements are added by `fill`.
+    pub fn push_list<F>(&mut self, fill: F) -> Result<(), ()>
+    where
+        F: FnOnce(&mut ListBuilder<'_, S, V>) -> Result<(), ()>,
+    {
+        push_container(self.nodes, S::default(), Kind::List, |nodes| {
+            fill(&mut ListBuilder::new(nodes))
+        })
+    }
+}
+
+/// `google.protobuf.Struct`, a map of field names to dynamically-typed values.
+///
+/// `S` is the string type used for field names and string values, and `V` is the vector type
+/// holding all values in the struct, including the values nested inside of the fields.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Struct<S, V> {
+    nodes: V,
+    _string: PhantomData<S>,
+}
+
+impl<S, V> Struct<S, V>
+where
+    S: PbString + Default,
+    V: PbVec<Node<S>> + DerefMut<Target = [Node<S>]>,
+{
+    /// Get a reference to the fields of the struct.
+    pub fn fields(&self) -> StructRef<'_, S> {
+        StructRef { nodes: &self.nodes }
+    }
+
+    /// Get a builder for adding fields to the struct.
+    pub fn builder(&mut self) -> StructBuilder<'_, S, V> {
+        StructBuilder::new(&mut self.nodes)
+    }
+
+    /// Remove all fields from the struct.
+    pub fn clear(&mut self) {
+        self.nodes.pb_clear();
+    }
+}
+
+/// `google.protobuf.ListValue`, a list of dynamically-typed values.
+///
+/// `S` is the string type used for string values and the field names of nested structs, and `V`
+/// is the vector type holding all values in the list, including nested values.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ListValue<S, V> {
+    nodes: V,
+    _string: PhantomData<S>,
+}
+
+impl<S, V> ListValue<S, V>
+where
+    S: PbString + Default,
+    V: PbVec<Node<S>> + DerefMut<Target = [Node<S>]>,
+{
+    /// Get a reference to the elements of the list.
+    pub fn values(&self) -> ListRef<'_, S> {
+        ListRef { nodes: &self.nodes }
+    }
+
+    /// Get a builder for adding elements to the list.
+    pub fn builder(&mut self) -> ListBuilder<'_, S, V> {
+        ListBuilder::new(&mut self.nodes)
+    }
+
+    /// Remove all elements from the list.
+    pub fn clear(&mut self) {
+        self.nodes.pb_clear();
+    }
+}
+
+/// `google.protobuf.Value`, a dynamically-typed value that's either null, a number, a string, a
+/// boolean, a struct, or a list.
+///
+/// `S` is the string type and `V` is the vector type holding the values nested inside of the
+/// value. Defaults to null.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Value<S, V> {
+    kind: Kind<S>,
+    nodes: V,
+}
+
+impl<S, V: Default> Default for Value<S, V> {
+    fn default() -> Self {
+        Self {
+            kind: Kind::Null,
+            nodes: V::default(),
+        }
+    }
+}
+
+impl<S, V> Value<S, V>
+where
+    S: PbString + Default,
+    V: PbVec<Node<S>> + DerefMut<Target = [Node<S>]>,
+{
+    /// Get a reference to the value.
+    pub fn get(&self) -> ValueRef<'_, S> {
+        ValueRef::new(&self.kind, &self.nodes)
+    }
+
+    /// Replace the value with a scalar.
+    pub fn set(&mut self, value: Scalar<'_>) -> Result<(), ()> {
+        self.kind = value.to_kind()?;
+        self.nodes.pb_clear();
+        Ok(())
+    }
+
+    /// Replace the value with a struct, whose fields are added by `fill`.
+    ///
+    /// If `fill` fails, the value is set to an empty struct.
+    pub fn set_struct<F>(&mut self, fill: F) -> Result<(), ()>
+    where
+        F: FnOnce(&mut StructBuilder<'_, S, V>) -> Result<(), ()>,
+    {
+        self.kind = Kind::Struct;
+        self.nodes.pb_clear();
+        let res = fill(&mut StructBuilder::new(&mut self.nodes));
+        if res.is_err() {
+            self.nodes.pb_clear();
+        }
+        res
+    }
+
+    /// Replace the value with a list, whose elements are added by `fill`.
+    ///
+    /// If `fill` fails, the value is set to an empty list.
+    pub fn set_list<F>(&mut self, fill: F) -> Result<(), ()>
+    where
+        F: FnOnce(&mut ListBuilder<'_, S, V>) -> Result<(), ()>,
+    {
+        self.kind = Kind::List;
+        self.nodes.pb_clear();
+        let res = fill(&mut ListBuilder::new(&mut self.nodes));
+        if res.is_err() {
+            self.nodes.pb_clear();
+        }
+        res
+    }
+}
+
+#[cfg(feature = "container-heapless-0_8")]
+/// [`Struct`] with at most `N` values in total and strings of at most `L` bytes, using `heapless`
+/// containers.
+pub type HeaplessStruct<const N: usize, const L: usize> =
+    Struct<heapless::String<L>, heapless::Vec<Node<heapless::String<L>>, N>>;
+
+#[cfg(feature = "container-heapless-0_8")]
+/// [`ListValue`] with at most `N` values in total and strings of at most `L` bytes, using
+/// `heapless` containers.
+pub type HeaplessListValue<const N: usize, const L: usize> =
+    ListValue<heapless::String<L>, heapless::Vec<Node<heapless::String<L>>, N>>;
+
+#[cfg(feature = "container-heapless-0_8")]
+/// [`Value`] with at most `N` nested values and strings of at most `L` bytes, using `heapless`
+/// containers.
+pub type HeaplessValue<const N: usize, const L: usize> =
+    Value<heapless::String<L>, heapless::Vec<Node<heapless::String<L>>, N>>;
+
+impl<S: PbString> fmt::Display for ValueRef<'_, S> {
+    /// Format the value as JSON
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueRef::Null => f.write_str("null"),
+            ValueRef::Number(n) => write!(f, "{n}"),
+            ValueRef::String(s) => write!(f, "{s:?}"),
+            ValueRef::Bool(b) => write!(f, "{b}"),
+            ValueRef::Struct(s) => {
+                f.write_str("{")?;
+                for (i, (k, v)) in s.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{k:?}:{v}")?;
+                }
+                f.write_str("}")
+            }
+            ValueRef::List(l) => {
+                f.write_str("[")?;
+                for (i, v) in l.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{v}")?;
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "decode")]
+mod decode {
+    use super::*;
+
+    /// Push an empty node, or return `None` if the vector is full and capacity errors are ignored
+    fn push_decoded<S, V, R>(
+        nodes: &mut V,
+        decoder: &mut PbDecoder<R>,
+    ) -> Result<Option<usize>, DecodeError<R::Error>>
+    where
+        S: Default,
+        V: PbVec<Node<S>>,
+        R: PbRead,
+    {
+        match nodes.pb_push(Node::default()) {
+            Ok(()) => Ok(Some(nodes.len() - 1)),
+            Err(()) => decoder.handle_repeated_overflow().map(|_| None),
+        }
+    }
+
+    /// Decode the fields of a `Struct` message, appending them to `nodes`
+    pub(super) fn decode_struct<S, V, R>(
+        nodes: &mut V,
+        decoder: &mut PbDecoder<R>,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>>
+    where
+        S: PbString + Default,
+        V: PbVec<Node<S>> + DerefMut<Target = [Node<S>]>,
+        R: PbRead,
+    {
+        let before = decoder.bytes_read();
+        while decoder.bytes_read() - before < len {
+            let tag = decoder.decode_tag()?;
+            match tag.field_num() {
+                0 => return Err(DecodeError::ZeroField),
+                1 => decoder.decode_len_record(|len, _, decoder| {
+                    let Some(idx) = push_decoded(nodes, decoder)? else {
+                        return decoder.skip_bytes(len);
+                    };
+                    decode_entry(nodes, idx, decoder, len)
+                })?,
+                _ => decoder.skip_wire_value(tag.wire_type())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode a map entry of a `Struct` into the node at `idx`
+    fn decode_entry<S, V, R>(
+        nodes: &mut V,
+        idx: usize,
+        decoder: &mut PbDecoder<R>,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>>
+    where
+        S: PbString + Default,
+        V: PbVec<Node<S>> + DerefMut<Target = [Node<S>]>,
+        R: PbRead,
+    {
+        let before = decoder.bytes_read();
+        while decoder.bytes_read() - before < len {
+            let tag = decoder.decode_tag()?;
+            match tag.field_num() {
+                0 => return Err(DecodeError::ZeroField),
+                1 => {
+                    if let Some(node) = nodes.get_mut(idx) {
+                        decoder.decode_string(&mut node.key, Presence::Explicit)?;
+                    }
+                }
+                2 => decoder.decode_nested(|len, decoder| {
+                    decode_value(nodes, Some(idx), &mut Kind::Null, decoder, len)
+                })?,
+                _ => decoder.skip_wire_value(tag.wire_type())?,
+            }
+        }
+        close_node(nodes, idx);
+        Ok(())
+    }
+
+    /// Decode the elements of a `ListValue` message, appending them to `nodes`
+    pub(super) fn decode_list<S, V, R>(
+        nodes: &mut V,
+        decoder: &mut PbDecoder<R>,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>>
+    where
+        S: PbString + Default,
+        V: PbVec<Node<S>> + DerefMut<Target = [Node<S>]>,
+        R: PbRead,
+    {
+        let before = decoder.bytes_read();
+        while decoder.bytes_read() - before < len {
+            let tag = decoder.decode_tag()?;
+            match tag.field_num() {
+                0 => return Err(DecodeError::ZeroField),
+                1 => decoder.decode_nested(|len, decoder| {
+                    let Some(idx) = push_decoded(nodes, decoder)? else {
+                        return decoder.skip_bytes(len);
+                    };
+                    decode_value(nodes, Some(idx), &mut Kind::Null, decoder, len)
+                })?,
+                _ => decoder.skip_wire_value(tag.wire_type())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode a `Value` message into the node at `idx`, or into `root` if `idx` is `None`.
+    ///
+    /// The subtree of the value consists of all nodes after `idx`, since it's always the last
+    /// value being decoded.
+    pub(super) fn decode_value<S, V, R>(
+        nodes: &mut V,
+        idx: Option<usize>,
+        root: &mut Kind<S>,
+        decoder: &mut PbDecoder<R>,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>>
+    where
+        S: PbString + Default,
+        V: PbVec<Node<S>> + DerefMut<Target = [Node<S>]>,
+        R: PbRead,
+    {
+        let start = idx.map_or(0, |i| i + 1);
+        // Setting a different kind of value replaces the old value, while nested structs and
+        // lists that appear multiple times are merged
+        let mut set_kind = |nodes: &mut V, kind: Kind<S>| {
+            let cur = match idx.and_then(|i| nodes.get_mut(i)) {
+                Some(node) => &mut node.kind,
+                None => &mut *root,
+            };
+            if !cur.is_same_container(&kind) {
+                *cur = kind;
+                truncate(nodes, start);
+            }
+        };
+
+        let before = decoder.bytes_read();
+        while decoder.bytes_read() - before < len {
+            let tag = decoder.decode_tag()?;
+            match tag.field_num() {
+                0 => return Err(DecodeError::ZeroField),
+                1 => {
+                    decoder.decode_int32()?;
+                    set_kind(nodes, Kind::Null);
+                }
+                2 => {
+                    let n = decoder.decode_double()?;
+                    set_kind(nodes, Kind::Number(n));
+                }
+                3 => {
+                    let mut s = S::default();
+                    decoder.decode_string(&mut s, Presence::Explicit)?;
+                    set_kind(nodes, Kind::String(s));
+                }
+                4 => {
+                    let b = decoder.decode_bool()?;
+                    set_kind(nodes, Kind::Bool(b));
+                }
+                5 => {
+                    set_kind(nodes, Kind::Struct);
+                    decoder.decode_nested(|len, decoder| decode_struct(nodes, decoder, len))?;
+                }
+                6 => {
+                    set_kind(nodes, Kind::List);
+                    decoder.decode_nested(|len, decoder| decode_list(nodes, decoder, len))?;
+                }
+                _ => decoder.skip_wire_value(tag.wire_type())?,
+            }
+        }
+        if let Some(idx) = idx {
+            close_node(nodes, idx);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<S, V> MessageDecode for Struct<S, V>
+where
+    S: PbString + Default,
+    V: PbVec<Node<S>> + DerefMut<Target = [Node<S>]>,
+{
+    fn decode<R: PbRead>(
+        &mut self,
+        decoder: &mut PbDecoder<R>,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        decode::decode_struct(&mut self.nodes, decoder, len)
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<S, V> MessageDecode for ListValue<S, V>
+where
+    S: PbString + Default,
+    V: PbVec<Node<S>> + DerefMut<Target = [Node<S>]>,
+{
+    fn decode<R: PbRead>(
+        &mut self,
+        decoder: &mut PbDecoder<R>,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        decode::decode_list(&mut self.nodes, decoder, len)
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<S, V> MessageDecode for Value<S, V>
+where
+    S: PbString + Default,
+    V: PbVec<Node<S>> + DerefMut<Target = [Node<S>]>,
+{
+    fn decode<R: PbRead>(
+        &mut self,
+        decoder: &mut PbDecoder<R>,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        decode::decode_value(&mut self.nodes, None, &mut self.kind, decoder, len)
+    }
+}
+
+#[cfg(feature = "encode")]
+mod encode {
+    use super::*;
+
+    const fn tag(field_num: u32, wire_type: u8) -> u32 {
+        Tag::from_parts(field_num, wire_type).varint()
+    }
+
+    // All tags used in these messages fit in 1 byte
+    const TAG_LEN: usize = 1;
+
+    fn sizeof_entry<S: PbString>(node: &Node<S>, subtree: &[Node<S>]) -> usize {
+        TAG_LEN
+            + sizeof_len_record(node.key.len())
+            + TAG_LEN
+            + sizeof_len_record(sizeof_value(&node.kind, subtree))
+    }
+
+    pub(super) fn sizeof_struct<S: PbString>(nodes: &[Node<S>]) -> usize {
+        Children { nodes }
+            .map(|(node, subtree)| TAG_LEN + sizeof_len_record(sizeof_entry(node, subtree)))
+            .sum()
+    }
+
+    pub(super) fn sizeof_list<S: PbString>(nodes: &[Node<S>]) -> usize {
+        Children { nodes }
+            .map(|(node, subtree)| TAG_LEN + sizeof_len_record(sizeof_value(&node.kind, subtree)))
+            .sum()
+    }
+
+    pub(super) fn sizeof_value<S: PbString>(kind: &Kind<S>, subtree: &[Node<S>]) -> usize {
+        TAG_LEN
+            + match kind {
+                Kind::Null | Kind::Bool(_) => 1,
+                Kind::Number(_) => 8,
+                Kind::String(s) => sizeof_len_record(s.len()),
+                Kind::Struct => sizeof_len_record(sizeof_struct(subtree)),
+                Kind::List => sizeof_len_record(sizeof_list(subtree)),
+            }
+    }
+
+    pub(super) fn encode_struct<S: PbString, W: PbWrite>(
+        nodes: &[Node<S>],
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), W::Error> {
+        for (node, subtree) in (Children { nodes }) {
+            encoder.encode_varint32(tag(1, WIRE_TYPE_LEN))?;
+            encoder.encode_varint32(sizeof_entry(node, subtree) as u32)?;
+            encoder.encode_varint32(tag(1, WIRE_TYPE_LEN))?;
+            encoder.encode_string(&node.key)?;
+            encoder.encode_varint32(tag(2, WIRE_TYPE_LEN))?;
+            encoder.encode_varint32(sizeof_value(&node.kind, subtree) as u32)?;
+            encode_value(&node.kind, subtree, encoder)?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn encode_list<S: PbString, W: PbWrite>(
+        nodes: &[Node<S>],
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), W::Error> {
+        for (node, subtree) in (Children { nodes }) {
+            encoder.encode_varint32(tag(1, WIRE_TYPE_LEN))?;
+            encoder.encode_varint32(sizeof_value(&node.kind, subtree) as u32)?;
+            encode_value(&node.kind, subtree, encoder)?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn encode_value<S: PbString, W: PbWrite>(
+        kind: &Kind<S>,
+        subtree: &[Node<S>],
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), W::Error> {
+        match kind {
+            Kind::Null => {
+                encoder.encode_varint32(tag(1, WIRE_TYPE_VARINT))?;
+                encoder.encode_varint32(0)
+            }
+            Kind::Number(n) => {
+                encoder.encode_varint32(tag(2, WIRE_TYPE_I64))?;
+                encoder.encode_double(*n)
+            }
+            Kind::String(s) => {
+                encoder.encode_varint32(tag(3, WIRE_TYPE_LEN))?;
+                encoder.encode_string(s)
+            }
+            Kind::Bool(b) => {
+                encoder.encode_varint32(tag(4, WIRE_TYPE_VARINT))?;
+                encoder.encode_bool(*b)
+            }
+            Kind::Struct => {
+                encoder.encode_varint32(tag(5, WIRE_TYPE_LEN))?;
+                encoder.encode_varint32(sizeof_struct(subtree) as u32)?;
+                encode_struct(subtree, encoder)
+            }
+            Kind::List => {
+                encoder.encode_varint32(tag(6, WIRE_TYPE_LEN))?;
+                encoder.encode_varint32(sizeof_list(subtree) as u32)?;
+                encode_list(subtree, encoder)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<S: PbString, V: PbVec<Node<S>>> MessageEncode for Struct<S, V> {
+    fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+        encode::encode_struct(&self.nodes, encoder)
+    }
+
+    fn compute_size(&self) -> usize {
+        encode::sizeof_struct(&self.nodes)
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<S: PbString, V: PbVec<Node<S>>> MessageEncode for ListValue<S, V> {
+    fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+        encode::encode_list(&self.nodes, encoder)
+    }
+
+    fn compute_size(&self) -> usize {
+        encode::sizeof_list(&self.nodes)
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<S: PbString, V: PbVec<Node<S>>> MessageEncode for Value<S, V> {
+    fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+        encode::encode_value(&self.kind, &self.nodes, encoder)
+    }
+
+    fn compute_size(&self) -> usize {
+        encode::sizeof_value(&self.kind, &self.nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrayvec::{ArrayString, ArrayVec};
+
+    use super::*;
+
+    type Str = ArrayString<8>;
+    type Nodes = ArrayVec<Node<Str>, 10>;
+
+    fn encode<M: MessageEncode>(msg: &M) -> ArrayVec<u8, 128> {
+        let mut encoder = PbEncoder::new(ArrayVec::new());
+        msg.encode(&mut encoder).unwrap();
+        let bytes = encoder.into_writer();
+        assert_eq!(bytes.len(), msg.compute_size());
+        bytes
+    }
+
+    fn decode<M: MessageDecode + Default>(bytes: &[u8]) -> Result<M, DecodeError<never::Never>> {
+        let mut msg = M::default();
+        msg.merge_from_slice(bytes)?;
+        Ok(msg)
+    }
+
+    fn sample() -> Struct<Str, Nodes> {
+        let mut s = Struct::default();
+        let mut builder = s.builder();
+        builder.insert("a", Scalar::Number(1.0)).unwrap();
+        builder
+            .insert_struct("b", |b| {
+                b.insert("c", Scalar::String("x"))?;
+                b.insert_list("d", |d| {
+                    d.push(Scalar::Bool(true))?;
+                    d.push_list(|_| Ok(()))?;
+                    d.push(Scalar::Null)
+                })
+            })
+            .unwrap();
+        builder.insert("e", Scalar::Bool(false)).unwrap();
+        s
+    }
+
+    #[test]
+    fn read() {
+        let s = sample();
+        let fields = s.fields();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields.get("a"), Some(ValueRef::Number(1.0)));
+        assert_eq!(fields.get("e"), Some(ValueRef::Bool(false)));
+        assert_eq!(fields.get("c"), None);
+
+        let Some(ValueRef::Struct(b)) = fields.get("b") else {
+            panic!()
+        };
+        assert_eq!(b.len(), 2);
+        assert_eq!(b.get("c"), Some(ValueRef::String("x")));
+        let Some(ValueRef::List(d)) = b.get("d") else {
+            panic!()
+        };
+        assert_eq!(d.len(), 3);
+        assert_eq!(d.get(0), Some(ValueRef::Bool(true)));
+        assert!(matches!(d.get(1), Some(ValueRef::List(l)) if l.is_empty()));
+        assert_eq!(d.get(2), Some(ValueRef::Null));
+        assert_eq!(d.get(3), None);
+
+        let mut json = ArrayString::<64>::new();
+        fmt::write(&mut json, format_args!("{}", ValueRef::Struct(fields))).unwrap();
+        assert_eq!(
+            json.as_str(),
+            r#"{"a":1,"b":{"c":"x","d":[true,[],null]},"e":false}"#
+        );
+    }
+
+    #[test]
+    fn builder_capacity() {
+        let mut s = sample();
+        let before = s.clone();
+        // Nested values that don't fit leave the struct unchanged
+        assert_eq!(
+            s.builder().insert_list("f", |f| {
+                f.push(Scalar::Null)?;
+                f.push(Scalar::Null)
+            }),
+            Err(())
+        );
+        assert_eq!(s, before);
+        assert_eq!(s.builder().insert("too long key", Scalar::Null), Err(()));
+        assert_eq!(s, before);
+        s.builder().insert("f", Scalar::Null).unwrap();
+        assert_eq!(s.fields().len(), 4);
+
+        let mut v = Value::<Str, Nodes>::default();
+        assert_eq!(v.get(), ValueRef::Null);
+        v.set_list(|l| l.push(Scalar::Number(2.0))).unwrap();
+        assert!(matches!(v.get(), ValueRef::List(l) if l.len() == 1));
+        v.set(Scalar::String("s")).unwrap();
+        assert_eq!(v.get(), ValueRef::String("s"));
+    }
+
+    #[test]
+    fn encoding() {
+        let mut s = Struct::<Str, Nodes>::default();
+        s.builder().insert("a", Scalar::Number(1.0)).unwrap();
+        assert_eq!(
+            encode(&s).as_slice(),
+            &[0x0A, 14, 0x0A, 1, b'a', 0x12, 9, 0x11, 0, 0, 0, 0, 0, 0, 0xF0, 0x3F]
+        );
+
+        let mut l = ListValue::<Str, Nodes>::default();
+        l.builder().push(Scalar::Null).unwrap();
+        l.builder()
+            .push_struct(|s| s.insert("b", Scalar::Bool(true)))
+            .unwrap();
+        assert_eq!(
+            encode(&l).as_slice(),
+            &[0x0A, 2, 0x08, 0, 0x0A, 11, 0x2A, 9, 0x0A, 7, 0x0A, 1, b'b', 0x12, 2, 0x20, 1]
+        );
+
+        let s = sample();
+        let bytes = encode(&s);
+        assert_eq!(decode::<Struct<Str, Nodes>>(&bytes), Ok(s));
+        assert_eq!(decode::<ListValue<Str, Nodes>>(&encode(&l)), Ok(l));
+
+        let mut v = Value::<Str, Nodes>::default();
+        v.set_struct(|s| s.insert("a", Scalar::Number(-2.5)))
+            .unwrap();
+        assert_eq!(
+            encode(&v).as_slice(),
+            &[0x2A, 16, 0x0A, 14, 0x0A, 1, b'a', 0x12, 9, 0x11, 0, 0, 0, 0, 0, 0, 0x04, 0xC0]
+        );
+        assert_eq!(decode::<Value<Str, Nodes>>(&encode(&v)), Ok(v));
+    }
+
+    #[test]
+    fn decode_merge() {
+        // Struct fields are appended
+        let a = sample();
+        let mut b = Struct::<Str, Nodes>::default();
+        b.builder().insert("a", Scalar::Number(2.0)).unwrap();
+        let mut merged = a.clone();
+        merged.merge_from_slice(&encode(&b)).unwrap();
+        assert_eq!(merged.fields().len(), 4);
+        assert_eq!(merged.fields().get("a"), Some(ValueRef::Number(2.0)));
+
+        // A later value of a different kind replaces the old value, and frees its nested values
+        let mut v = Value::<Str, Nodes>::default();
+        v.set_list(|l| {
+            l.push(Scalar::Null)?;
+            l.push(Scalar::Null)
+        })
+        .unwrap();
+        let mut s = Value::<Str, Nodes>::default();
+        s.set_struct(|s| s.insert("k", Scalar::Null)).unwrap();
+        let mut decoded = v.clone();
+        decoded.merge_from_slice(&encode(&s)).unwrap();
+        assert_eq!(decoded, s);
+        decoded.merge_from_slice(&[0x20, 1]).unwrap();
+        assert_eq!(decoded.get(), ValueRef::Bool(true));
+        assert!(decoded.nodes.is_empty());
+
+        // Lists that appear multiple times within a value are merged
+        let bytes = [encode(&v), encode(&v)].concat();
+        let decoded = decode::<Value<Str, Nodes>>(&bytes).unwrap();
+        assert!(matches!(decoded.get(), ValueRef::List(l) if l.len() == 4));
+
+        // Replacing a nested value only affects its own subtree
+        let bytes = [
+            0x0A, 2, 0x08, 0, // null
+            0x0A, 4, 0x32, 2, 0x0A, 0, // [null]
+            0x0A, 2, 0x20, 1, // true
+        ];
+        let mut list = decode::<ListValue<Str, Nodes>>(&bytes).unwrap();
+        assert_eq!(list.values().len(), 3);
+        // Second occurrence of the last element turns it into a number
+        list.merge_from_slice(&[0x0A, 2, 0x20, 0]).unwrap();
+        assert_eq!(list.values().len(), 4);
+        let values: ArrayVec<_, 4> = list.values().iter().collect();
+        assert_eq!(values[0], ValueRef::Null);
+        assert!(matches!(values[1], ValueRef::List(l) if l.get(0) == Some(ValueRef::Null)));
+        assert_eq!(values[2], ValueRef::Bool(true));
+        assert_eq!(values[3], ValueRef::Bool(false));
+    }
+
+    #[test]
+    fn decode_errors() {
+        let bytes = encode(&sample());
+        // Not enough room for all the nested values
+        assert_eq!(
+            decode::<Struct<Str, ArrayVec<Node<Str>, 4>>>(&bytes),
+            Err(DecodeError::Capacity)
+        );
+        let mut s = Struct::<Str, ArrayVec<Node<Str>, 4>>::default();
+        let mut decoder = PbDecoder::new(bytes.as_slice());
+        decoder.ignore_repeated_cap_err = true;
+        s.decode(&mut decoder, bytes.len()).unwrap();
+        assert!(s.fields().len() < 3);
+        assert_eq!(s.fields().get("a"), Some(ValueRef::Number(1.0)));
+
+        // String too long
+        let mut long = Struct::<ArrayString<16>, ArrayVec<Node<ArrayString<16>>, 4>>::default();
+        long.builder()
+            .insert("k", Scalar::String("very long string"))
+            .unwrap();
+        assert_eq!(
+            decode::<Struct<Str, Nodes>>(&encode(&long)),
+            Err(DecodeError::Capacity)
+        );
+        assert_eq!(
+            decode::<Value<Str, Nodes>>(&[0x00]),
+            Err(DecodeError::ZeroField)
+        );
+    }
+
+    #[cfg(feature = "decode-limits")]
+    #[test]
+    fn decode_depth() {
+        fn decode_limited<M: MessageDecode + Default>(
+            bytes: &[u8],
+            max_depth: usize,
+        ) -> Result<M, DecodeError<never::Never>> {
+            let mut msg = M::default();
+            let mut decoder = PbDecoder::new(bytes);
+            decoder.set_max_depth(Some(max_depth));
+            msg.decode(&mut decoder, bytes.len())?;
+            Ok(msg)
+        }
+
+        // [[]], where the list, its element, and the inner list are each one level deeper
+        let bytes = [0x32, 4, 0x0A, 2, 0x32, 0];
+        assert!(decode_limited::<Value<Str, Nodes>>(&bytes, 3).is_ok());
+        assert_eq!(
+            decode_limited::<Value<Str, Nodes>>(&bytes, 2),
+            Err(DecodeError::DepthLimit)
+        );
+
+        // {"k": {}}, where the entry's value and the inner struct are each one level deeper
+        let bytes = [0x0A, 7, 0x0A, 1, b'k', 0x12, 2, 0x2A, 0];
+        assert!(decode_limited::<Struct<Str, Nodes>>(&bytes, 2).is_ok());
+        assert_eq!(
+            decode_limited::<Struct<Str, Nodes>>(&bytes, 1),
+            Err(DecodeError::DepthLimit)
+        );
+        assert_eq!(
+            decode_limited::<ListValue<Str, Nodes>>(&[0x0A, 4, 0x32, 2, 0x0A, 0], 1),
+            Err(DecodeError::DepthLimit)
+        );
+    }
+}
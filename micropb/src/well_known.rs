@@ -0,0 +1,390 @@
+//! Message implementations that let Rust types stand in for Protobuf well-known types.
+//!
+//! The wrapper types in `google/protobuf/wrappers.proto`, such as `google.protobuf.Int32Value`,
+//! only exist to give scalar values explicit presence. This module implements `MessageDecode` and
+//! `MessageEncode` on the wrapped Rust types themselves, so substituting `i32` for
+//! `google.protobuf.Int32Value` generates an optional `i32` field, which is `Option<i32>` with
+//! `OptionalRepr::Option`.
+//!
+//! | Protobuf type                 | Rust type                                                  |
+//! |-------------------------------|------------------------------------------------------------|
+//! | `google.protobuf.DoubleValue` | `f64`                                                      |
+//! | `google.protobuf.FloatValue`  | `f32`                                                      |
+//! | `google.protobuf.Int64Value`  | `i64`                                                      |
+//! | `google.protobuf.UInt64Value` | `u64`                                                      |
+//! | `google.protobuf.Int32Value`  | `i32`                                                      |
+//! | `google.protobuf.UInt32Value` | `u32`                                                      |
+//! | `google.protobuf.BoolValue`   | `bool`                                                     |
+//! | `google.protobuf.StringValue` | `String`, `heapless::String`, `ArrayString`, `FixedString` |
+//! | `google.protobuf.BytesValue`  | `Vec<u8>`, `heapless::Vec<u8>`, `ArrayVec<u8>`, `FixedVec<u8>` |
+//! | `google.protobuf.Duration`    | [`core::time::Duration`]                                   |
+//! | `google.protobuf.Timestamp`   | [`core::time::Duration`] since the Unix epoch              |
+//!
+//! The 64-bit types, including `Duration` and `Timestamp`, require the `enable-64bit` feature.
+//! `StringValue` and `BytesValue` are implemented for the containers enabled by feature flags,
+//! except for `heapless` 0.7 and 0.9.
+//!
+//! `core::time::Duration` can't be negative, so decoding a negative `Duration` or a `Timestamp`
+//! before 1970 returns `DecodeError::OutOfRange`. To convert a decoded `Timestamp` into a
+//! `SystemTime`, add it to `SystemTime::UNIX_EPOCH`.
+//!
+//! To use these types in generated code, substitute them for the well-known types with
+//! `extern_type_path`, or with `Generator::use_well_known_types` in `micropb-gen`, which
+//! substitutes everything in the table except for `StringValue` and `BytesValue`:
+//! ```ignore
+//! gen.use_well_known_types();
+//! gen.extern_type_path(".google.protobuf.StringValue", "::micropb::heapless::String<32>");
+//! ```
+
+#[cfg(any(feature = "decode", feature = "encode"))]
+use crate::field::ImplicitPresence;
+#[cfg(feature = "encode")]
+use crate::{
+    size::sizeof_len_record, MessageEncode, PbEncoder, PbWrite, Tag, WIRE_TYPE_I32, WIRE_TYPE_I64,
+    WIRE_TYPE_LEN, WIRE_TYPE_VARINT,
+};
+#[cfg(feature = "decode")]
+use crate::{DecodeError, MessageDecode, PbDecoder, PbRead, Presence};
+
+/// Implement the message traits for a wrapper type with a single field of number 1, which has
+/// implicit presence
+macro_rules! impl_wrapper {
+    (
+        [$($gen:tt)*] $t:ty,
+        wire_type: $wire_type:ident,
+        is_default: |$v:ident| $is_default:expr,
+        decode: |$decoder:ident, $mut_ref:ident| $decode:expr,
+        encode: |$encoder:ident| $encode:expr,
+        sizeof: $sizeof:expr $(,)?
+    ) => {
+        #[cfg(feature = "decode")]
+        impl<$($gen)*> MessageDecode for $t {
+            fn decode<R: PbRead>(
+                &mut self,
+                $decoder: &mut PbDecoder<R>,
+                len: usize,
+            ) -> Result<(), DecodeError<R::Error>> {
+                let before = $decoder.bytes_read();
+                while $decoder.bytes_read() - before < len {
+                    let tag = $decoder.decode_tag()?;
+                    match tag.field_num() {
+                        0 => return Err(DecodeError::ZeroField),
+                        1 => {
+                            let $mut_ref = &mut *self;
+                            $decode
+                        }
+                        _ => $decoder.skip_wire_value(tag.wire_type())?,
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        #[cfg(feature = "encode")]
+        impl<$($gen)*> MessageEncode for $t {
+            fn encode<W: PbWrite>(&self, $encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+                let $v = self;
+                if !$is_default {
+                    $encoder.encode_tag(Tag::from_parts(1, $wire_type))?;
+                    // Bound first, since calling a closure where it's written is linted
+                    let mut encode = $encode;
+                    encode($v)?;
+                }
+                Ok(())
+            }
+
+            fn compute_size(&self) -> usize {
+                let $v = self;
+                if $is_default {
+                    0
+                } else {
+                    let sizeof = $sizeof;
+                    // Tag of field 1 always fits in 1 byte
+                    1 + sizeof($v)
+                }
+            }
+        }
+    };
+}
+
+/// Implement the message traits for a wrapper type over a scalar, decoded and encoded with the
+/// given methods
+macro_rules! impl_scalar_wrapper {
+    ($t:ty, $wire_type:ident, $decode:ident, $encode:ident, $sizeof:expr) => {
+        impl_wrapper!(
+            [] $t,
+            wire_type: $wire_type,
+            is_default: |v| ImplicitPresence::is_default_value(v),
+            decode: |decoder, mut_ref| {
+                let val = decoder.$decode()?;
+                // Zero values of implicit presence fields are ignored, like in generated code
+                if !ImplicitPresence::is_default_value(&val) {
+                    *mut_ref = val;
+                }
+            },
+            encode: |encoder| |v: &$t| encoder.$encode(*v),
+            sizeof: $sizeof,
+        );
+    };
+}
+
+impl_scalar_wrapper!(f32, WIRE_TYPE_I32, decode_float, encode_float, |_| 4);
+impl_scalar_wrapper!(f64, WIRE_TYPE_I64, decode_double, encode_double, |_| 8);
+impl_scalar_wrapper!(
+    i32,
+    WIRE_TYPE_VARINT,
+    decode_int32,
+    encode_int32,
+    |v: &i32| { crate::size::sizeof_int32(*v) }
+);
+impl_scalar_wrapper!(
+    u32,
+    WIRE_TYPE_VARINT,
+    decode_varint32,
+    encode_varint32,
+    |v: &u32| { crate::size::sizeof_varint32(*v) }
+);
+impl_scalar_wrapper!(bool, WIRE_TYPE_VARINT, decode_bool, encode_bool, |_| 1);
+#[cfg(feature = "enable-64bit")]
+impl_scalar_wrapper!(
+    i64,
+    WIRE_TYPE_VARINT,
+    decode_int64,
+    encode_int64,
+    |v: &i64| { crate::size::sizeof_int64(*v) }
+);
+#[cfg(feature = "enable-64bit")]
+impl_scalar_wrapper!(
+    u64,
+    WIRE_TYPE_VARINT,
+    decode_varint64,
+    encode_varint64,
+    |v: &u64| { crate::size::sizeof_varint64(*v) }
+);
+
+#[cfg(feature = "strings")]
+macro_rules! impl_string_wrapper {
+    ([$($gen:tt)*] $t:ty) => {
+        impl_wrapper!(
+            [$($gen)*] $t,
+            wire_type: WIRE_TYPE_LEN,
+            is_default: |v| v.is_empty(),
+            decode: |decoder, mut_ref| decoder.decode_string(mut_ref, Presence::Implicit)?,
+            encode: |encoder| |v: &$t| encoder.encode_string(v),
+            sizeof: |v: &$t| sizeof_len_record(v.len()),
+        );
+    };
+}
+
+macro_rules! impl_bytes_wrapper {
+    ([$($gen:tt)*] $t:ty) => {
+        impl_wrapper!(
+            [$($gen)*] $t,
+            wire_type: WIRE_TYPE_LEN,
+            is_default: |v| v.is_empty(),
+            decode: |decoder, mut_ref| decoder.decode_bytes(mut_ref, Presence::Implicit)?,
+            encode: |encoder| |v: &$t| encoder.encode_bytes(v),
+            sizeof: |v: &$t| sizeof_len_record(v.len()),
+        );
+    };
+}
+
+#[cfg(feature = "strings")]
+impl_string_wrapper!([const N: usize] crate::container::FixedString<N>);
+impl_bytes_wrapper!([const N: usize] crate::container::FixedVec<u8, N>);
+#[cfg(all(feature = "container-arrayvec-0_7", feature = "strings"))]
+impl_string_wrapper!([const N: usize] arrayvec::ArrayString<N>);
+#[cfg(feature = "container-arrayvec-0_7")]
+impl_bytes_wrapper!([const N: usize] arrayvec::ArrayVec<u8, N>);
+#[cfg(all(feature = "container-heapless-0_8", feature = "strings"))]
+impl_string_wrapper!([const N: usize] heapless::String<N>);
+#[cfg(feature = "container-heapless-0_8")]
+impl_bytes_wrapper!([const N: usize] heapless::Vec<u8, N>);
+#[cfg(all(feature = "alloc", feature = "strings"))]
+impl_string_wrapper!([] alloc::string::String);
+#[cfg(feature = "alloc")]
+impl_bytes_wrapper!([] alloc::vec::Vec<u8>);
+
+#[cfg(all(feature = "decode", feature = "enable-64bit"))]
+/// Decodes `google.protobuf.Duration` or `google.protobuf.Timestamp`, which have the same
+/// fields. Fields missing from the wire keep their existing values.
+impl MessageDecode for core::time::Duration {
+    fn decode<R: PbRead>(
+        &mut self,
+        decoder: &mut PbDecoder<R>,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let mut seconds = i64::try_from(self.as_secs()).unwrap_or(i64::MAX);
+        let mut nanos = self.subsec_nanos() as i32;
+        let before = decoder.bytes_read();
+        while decoder.bytes_read() - before < len {
+            let tag = decoder.decode_tag()?;
+            match tag.field_num() {
+                0 => return Err(DecodeError::ZeroField),
+                1 => seconds = decoder.decode_int64()?,
+                2 => nanos = decoder.decode_int32()?,
+                _ => decoder.skip_wire_value(tag.wire_type())?,
+            }
+        }
+
+        let (Ok(seconds), Ok(nanos)) = (u64::try_from(seconds), u32::try_from(nanos)) else {
+            return Err(DecodeError::OutOfRange);
+        };
+        if nanos >= 1_000_000_000 {
+            return Err(DecodeError::OutOfRange);
+        }
+        *self = core::time::Duration::new(seconds, nanos);
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "encode", feature = "enable-64bit"))]
+/// Encodes `google.protobuf.Duration` or `google.protobuf.Timestamp`. Seconds beyond the range
+/// of `int64` are saturated.
+impl MessageEncode for core::time::Duration {
+    fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+        let seconds = i64::try_from(self.as_secs()).unwrap_or(i64::MAX);
+        let nanos = self.subsec_nanos() as i32;
+        if seconds != 0 {
+            encoder.encode_tag(Tag::from_parts(1, WIRE_TYPE_VARINT))?;
+            encoder.encode_int64(seconds)?;
+        }
+        if nanos != 0 {
+            encoder.encode_tag(Tag::from_parts(2, WIRE_TYPE_VARINT))?;
+            encoder.encode_int32(nanos)?;
+        }
+        Ok(())
+    }
+
+    fn compute_size(&self) -> usize {
+        let seconds = i64::try_from(self.as_secs()).unwrap_or(i64::MAX);
+        let nanos = self.subsec_nanos() as i32;
+        // Tags of both fields fit in 1 byte
+        let mut size = 0;
+        if seconds != 0 {
+            size += 1 + crate::size::sizeof_int64(seconds);
+        }
+        if nanos != 0 {
+            size += 1 + crate::size::sizeof_int32(nanos);
+        }
+        size
+    }
+}
+
+#[cfg(all(test, feature = "encode", feature = "decode"))]
+mod tests {
+    use core::time::Duration;
+
+    use super::*;
+
+    fn roundtrip<M: MessageEncode + MessageDecode + Default + PartialEq + core::fmt::Debug>(
+        msg: &M,
+        expected: &[u8],
+    ) {
+        let mut encoder = PbEncoder::new(vec![]);
+        msg.encode(&mut encoder).unwrap();
+        let bytes = encoder.into_writer();
+        assert_eq!(bytes, expected);
+        assert_eq!(msg.compute_size(), bytes.len());
+
+        let mut decoded = M::default();
+        decoded
+            .decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len())
+            .unwrap();
+        assert_eq!(&decoded, msg);
+    }
+
+    fn decode<M: MessageDecode + Default>(bytes: &[u8]) -> Result<M, DecodeError<never::Never>> {
+        let mut msg = M::default();
+        msg.decode(&mut PbDecoder::new(bytes), bytes.len())?;
+        Ok(msg)
+    }
+
+    #[test]
+    fn scalar_wrappers() {
+        roundtrip(&150i32, &[0x08, 0x96, 0x01]);
+        roundtrip(
+            &-1i32,
+            &[
+                0x08, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01,
+            ],
+        );
+        roundtrip(&150u32, &[0x08, 0x96, 0x01]);
+        roundtrip(&true, &[0x08, 0x01]);
+        roundtrip(&1.0f32, &[0x0D, 0x00, 0x00, 0x80, 0x3F]);
+        roundtrip(&1.0f64, &[0x09, 0, 0, 0, 0, 0, 0, 0xF0, 0x3F]);
+        roundtrip(&150i64, &[0x08, 0x96, 0x01]);
+        roundtrip(&150u64, &[0x08, 0x96, 0x01]);
+
+        // Default values have empty encodings
+        roundtrip(&0i32, &[]);
+        roundtrip(&false, &[]);
+        roundtrip(&0.0f32, &[]);
+        roundtrip(&-0.0f64, &[0x09, 0, 0, 0, 0, 0, 0, 0, 0x80]);
+
+        // Unknown fields are skipped
+        assert_eq!(decode::<u32>(&[0x10, 0x05, 0x08, 0x03]), Ok(3));
+        assert_eq!(decode::<u32>(&[0x00]), Err(DecodeError::ZeroField));
+    }
+
+    #[test]
+    fn string_bytes_wrappers() {
+        roundtrip(&String::from("abc"), &[0x0A, 0x03, b'a', b'b', b'c']);
+        roundtrip(&String::new(), &[]);
+        roundtrip(&vec![1u8, 2], &[0x0A, 0x02, 0x01, 0x02]);
+        roundtrip(
+            &arrayvec::ArrayString::<4>::from("ab").unwrap(),
+            &[0x0A, 0x02, b'a', b'b'],
+        );
+        roundtrip(
+            &heapless::Vec::<u8, 4>::from_slice(&[7]).unwrap(),
+            &[0x0A, 0x01, 0x07],
+        );
+        let mut fixed = crate::container::FixedString::<4>::new();
+        fixed.push_str("ab").unwrap();
+        roundtrip(&fixed, &[0x0A, 0x02, b'a', b'b']);
+
+        assert_eq!(
+            decode::<arrayvec::ArrayString<2>>(&[0x0A, 0x03, b'a', b'b', b'c']),
+            Err(DecodeError::Capacity)
+        );
+    }
+
+    #[test]
+    fn duration() {
+        roundtrip(&Duration::new(150, 0), &[0x08, 0x96, 0x01]);
+        roundtrip(&Duration::new(0, 5), &[0x10, 0x05]);
+        roundtrip(&Duration::new(1, 5), &[0x08, 0x01, 0x10, 0x05]);
+        roundtrip(&Duration::ZERO, &[]);
+
+        // Negative seconds and nanos, or nanos that overflow into seconds
+        assert_eq!(
+            decode::<Duration>(&[0x08, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]),
+            Err(DecodeError::OutOfRange)
+        );
+        assert_eq!(
+            decode::<Duration>(&[0x10, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]),
+            Err(DecodeError::OutOfRange)
+        );
+        assert_eq!(
+            decode::<Duration>(&[0x10, 0x80, 0x94, 0xEB, 0xDC, 0x03]),
+            Err(DecodeError::OutOfRange)
+        );
+
+        // Fields missing from the wire are left as-is
+        let mut dur = Duration::new(3, 7);
+        dur.decode(&mut PbDecoder::new([0x08, 0x01].as_slice()), 2)
+            .unwrap();
+        assert_eq!(dur, Duration::new(1, 7));
+
+        // Seconds are saturated when encoding
+        let mut encoder = PbEncoder::new(vec![]);
+        Duration::MAX.encode(&mut encoder).unwrap();
+        let bytes = encoder.into_writer();
+        assert_eq!(Duration::MAX.compute_size(), bytes.len());
+        assert_eq!(
+            decode::<Duration>(&bytes),
+            Ok(Duration::new(i64::MAX as u64, 999_999_999))
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Low-level helpers for the Protobuf wire format.
+//!
+//! These are the ZigZag and varint routines used internally by [`PbEncoder`](crate::PbEncoder)
+//! and [`PbDecoder`](crate::PbDecoder), exposed as `const fn`s for custom framing, offline tooling,
+//! and compile-time size calculations. Unlike the encoder and decoder, they operate on plain
+//! integers and byte arrays, so they're available regardless of feature flags.
+//!
+//! ```
+//! use micropb::wire::*;
+//!
+//! const LEN_PREFIX: ([u8; MAX_VARINT_LEN], usize) = encode_varint32(300);
+//! assert_eq!(&LEN_PREFIX.0[..LEN_PREFIX.1], &[0xAC, 0x02]);
+//! assert_eq!(decode_varint32(&[0xAC, 0x02, 0xFF]), Some((300, 2)));
+//!
+//! assert_eq!(zigzag_encode32(-2), 3);
+//! assert_eq!(zigzag_decode32(3), -2);
+//! assert_eq!(varint32_len(zigzag_encode32(-65)), 2);
+//! ```
+
+/// Maximum number of bytes in a varint.
+pub const MAX_VARINT_LEN: usize = 10;
+
+#[inline]
+/// Map a signed integer to an unsigned integer with ZigZag encoding, as done for `sint32`.
+///
+/// Small negative numbers are mapped to small unsigned numbers, so they take up fewer bytes as
+/// varints.
+pub const fn zigzag_encode32(i: i32) -> u32 {
+    ((i << 1) ^ (i >> 31)) as u32
+}
+
+#[inline]
+/// Reverse [`zigzag_encode32`].
+pub const fn zigzag_decode32(u: u32) -> i32 {
+    ((u >> 1) as i32) ^ -((u & 1) as i32)
+}
+
+#[inline]
+/// Map a signed integer to an unsigned integer with ZigZag encoding, as done for `sint64`.
+pub const fn zigzag_encode64(i: i64) -> u64 {
+    ((i << 1) ^ (i >> 63)) as u64
+}
+
+#[inline]
+/// Reverse [`zigzag_encode64`].
+pub const fn zigzag_decode64(u: u64) -> i64 {
+    ((u >> 1) as i64) ^ -((u & 1) as i64)
+}
+
+/// Calculate the number of bytes taken by a 32-bit varint.
+pub const fn varint32_len(v: u32) -> usize {
+    match v {
+        0x0..=0x7F => 1,
+        0x80..=0x3FFF => 2,
+        0x4000..=0x1FFFFF => 3,
+        0x200000..=0xFFFFFFF => 4,
+        _ => 5,
+    }
+}
+
+/// Calculate the number of bytes taken by a 64-bit varint.
+pub const fn varint64_len(v: u64) -> usize {
+    const U32_MAX: u64 = u32::MAX as u64;
+    const U32_OVER_MAX: u64 = U32_MAX + 1;
+    match v {
+        0x0..=U32_MAX => varint32_len(v as u32),
+        U32_OVER_MAX..=0x7FFFFFFFF => 5,
+        0x0800000000..=0x3FFFFFFFFFF => 6,
+        0x040000000000..=0x1FFFFFFFFFFFF => 7,
+        0x02000000000000..=0xFFFFFFFFFFFFFF => 8,
+        0x0100000000000000..=0x7FFFFFFFFFFFFFFF => 9,
+        _ => 10,
+    }
+}
+
+/// Encode a 32-bit varint, returning the buffer and the number of bytes of it that are used.
+pub const fn encode_varint32(v: u32) -> ([u8; MAX_VARINT_LEN], usize) {
+    encode_varint64(v as u64)
+}
+
+/// Encode a 64-bit varint, returning the buffer and the number of bytes of it that are used.
+pub const fn encode_varint64(mut v: u64) -> ([u8; MAX_VARINT_LEN], usize) {
+    let mut buf = [0; MAX_VARINT_LEN];
+    let mut len = 0;
+    loop {
+        let b = (v & 0x7F) as u8;
+        v >>= 7;
+        if v == 0 {
+            buf[len] = b;
+            return (buf, len + 1);
+        }
+        buf[len] = b | 0x80;
+        len += 1;
+    }
+}
+
+/// Decode a 32-bit varint from the start of `buf`, returning the value and the number of bytes
+/// it took up.
+///
+/// Like [`PbDecoder::decode_varint32`](crate::PbDecoder::decode_varint32), varints of up to 10
+/// bytes are accepted and truncated to 32 bits, since negative `int32` values are encoded as
+/// 64-bit varints. Returns `None` if `buf` ends before the varint does, or if the varint is
+/// longer than 10 bytes.
+pub const fn decode_varint32(buf: &[u8]) -> Option<(u32, usize)> {
+    match decode_varint64(buf) {
+        Some((v, len)) => Some((v as u32, len)),
+        None => None,
+    }
+}
+
+/// Decode a 64-bit varint from the start of `buf`, returning the value and the number of bytes
+/// it took up.
+///
+/// Returns `None` if `buf` ends before the varint does, or if the varint is longer than 10
+/// bytes.
+pub const fn decode_varint64(buf: &[u8]) -> Option<(u64, usize)> {
+    let mut varint = 0;
+    let mut i = 0;
+    while i < MAX_VARINT_LEN && i < buf.len() {
+        let b = buf[i];
+        varint |= ((b & 0x7F) as u64) << (i * 7);
+        i += 1;
+        if b & 0x80 == 0 {
+            return Some((varint, i));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zigzag() {
+        for (i, u) in [(0, 0), (-1, 1), (1, 2), (-2, 3), (i32::MAX, u32::MAX - 1)] {
+            assert_eq!(zigzag_encode32(i), u);
+            assert_eq!(zigzag_decode32(u), i);
+        }
+        assert_eq!(zigzag_encode32(i32::MIN), u32::MAX);
+        assert_eq!(zigzag_encode64(i64::MIN), u64::MAX);
+        assert_eq!(zigzag_decode64(u64::MAX - 1), i64::MAX);
+    }
+
+    #[test]
+    fn varint_roundtrip() {
+        for v in [0, 1, 127, 128, 300, 0x1FFFFF, u32::MAX as u64, u64::MAX] {
+            let (buf, len) = encode_varint64(v);
+            assert_eq!(len, varint64_len(v));
+            assert_eq!(decode_varint64(&buf[..len]), Some((v, len)));
+            // Truncated input
+            assert_eq!(decode_varint64(&buf[..len - 1]), None);
+        }
+
+        // Negative int32 values are 10 bytes long and get truncated
+        let (buf, len) = encode_varint64(-5i64 as u64);
+        assert_eq!(decode_varint32(&buf[..len]), Some((-5i32 as u32, 10)));
+        // Varint that's too long
+        assert_eq!(decode_varint64(&[0xFF; 11]), None);
+    }
+}
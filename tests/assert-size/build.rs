@@ -0,0 +1,29 @@
+use micropb_gen::{Config, Generator};
+
+fn main() {
+    let mut gen = Generator::new();
+    gen.use_container_heapless()
+        .configure(".Data.s", Config::new().max_bytes(8))
+        .configure(".Data.b", Config::new().max_bytes(8))
+        .configure(".List.list", Config::new().max_len(4))
+        .configure(".NumList.list", Config::new().max_len(8))
+        .configure(".StrList.list", Config::new().max_len(4))
+        .configure(".StrList.list.elem", Config::new().max_bytes(8))
+        .configure(".FixedList.list", Config::new().max_len(8))
+        .configure(".EnumList.list", Config::new().max_len(8))
+        .configure(".Map.mapping", Config::new().max_len(4))
+        .configure(".Map.mapping.key", Config::new().max_bytes(8))
+        .configure(".Map.mapping.value", Config::new().max_bytes(8))
+        .add_protoc_arg("-I../basic-proto")
+        .compile_protos(
+            &[
+                "../basic-proto/proto/basic.proto",
+                "../basic-proto/proto/nested.proto",
+                "../basic-proto/proto/collections.proto",
+                "../basic-proto/proto/map.proto",
+            ],
+            std::env::var("OUT_DIR").unwrap() + "/assert_size.rs",
+        )
+        .unwrap();
+    println!("cargo:rerun-if-changed=../basic-proto/proto");
+}
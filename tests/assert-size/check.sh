@@ -0,0 +1,46 @@
+#!/usr/bin/env bash
+# Builds every binary in src/bin for thumbv7em and checks that the amount of .text it adds over
+# the baseline binary stays within the limit recorded in limits.txt.
+#
+# After an intentional size change, run with BLESS=1 to record the new sizes, plus some headroom,
+# as the limits.
+set -euo pipefail
+cd "$(dirname "$0")"
+
+TARGET=${TARGET:-thumbv7em-none-eabihf}
+HEADROOM_PERCENT=5
+HOST=$(rustc -vV | sed -n 's/^host: //p')
+LLVM_SIZE=${LLVM_SIZE:-$(rustc --print sysroot)/lib/rustlib/$HOST/bin/llvm-size}
+
+cargo build --release --bins --target "$TARGET"
+
+text_size() {
+    "$LLVM_SIZE" -A "target/$TARGET/release/$1" | awk '$1 == ".text" { print $2 }'
+}
+
+baseline=$(text_size baseline)
+status=0
+blessed=""
+while read -r bin limit; do
+    # Skip comments and blank lines
+    if [[ -z "$bin" || "$bin" == \#* ]]; then
+        continue
+    fi
+    size=$(($(text_size "$bin") - baseline))
+    if [[ -n "${BLESS:-}" ]]; then
+        limit=$((size + size * HEADROOM_PERCENT / 100))
+        blessed+="$bin $limit"$'\n'
+    fi
+    if ((size > limit)); then
+        echo "FAIL $bin: $size bytes of .text exceeds the limit of $limit bytes"
+        status=1
+    else
+        echo "ok   $bin: $size bytes of .text (limit $limit bytes)"
+    fi
+done < limits.txt
+
+if [[ -n "${BLESS:-}" ]]; then
+    { grep '^#' limits.txt; printf '%s' "$blessed"; } > limits.txt.new
+    mv limits.txt.new limits.txt
+fi
+exit $status
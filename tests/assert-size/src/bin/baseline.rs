@@ -0,0 +1,6 @@
+#![no_std]
+#![no_main]
+
+use assert_size as _;
+
+assert_size::entry!();
@@ -0,0 +1,6 @@
+#![no_std]
+#![no_main]
+
+use assert_size::proto::*;
+
+assert_size::entry!(List, NumList, StrList, FixedList, EnumList);
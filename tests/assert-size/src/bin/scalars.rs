@@ -0,0 +1,4 @@
+#![no_std]
+#![no_main]
+
+assert_size::entry!(assert_size::proto::basic_::BasicTypes);
@@ -0,0 +1,55 @@
+//! Code size harness for generated code.
+//!
+//! Each binary in `src/bin` decodes and encodes a set of generated messages, and `check.sh`
+//! compares the size of its `.text` section against the `baseline` binary, which contains no
+//! Protobuf code at all.
+#![no_std]
+
+use core::hint::black_box;
+
+use micropb::{heapless::Vec, MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+pub mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/assert_size.rs"));
+}
+
+static INPUT: [u8; 64] = [0; 64];
+
+/// Decode, size, and encode a message, hiding the input and output from the optimizer so that
+/// none of the logic can be removed.
+#[inline(never)]
+pub fn round_trip<M: MessageDecode + MessageEncode + Default>() {
+    let input = black_box(INPUT.as_slice());
+    let mut msg = M::default();
+    let mut decoder = PbDecoder::new(input);
+    if msg.decode(&mut decoder, input.len()).is_err() {
+        return;
+    }
+
+    black_box(msg.compute_size());
+    let mut encoder = PbEncoder::new(Vec::<u8, 256>::new());
+    if black_box(&msg).encode(&mut encoder).is_ok() {
+        black_box(encoder.into_writer());
+    }
+}
+
+/// Define the entry point of a size test binary, which calls [`round_trip`] on every message type.
+#[macro_export]
+macro_rules! entry {
+    ($($msg:ty),* $(,)?) => {
+        #[no_mangle]
+        pub extern "C" fn _start() -> ! {
+            loop {
+                $($crate::round_trip::<$msg>();)*
+                core::hint::black_box(());
+            }
+        }
+    };
+}
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
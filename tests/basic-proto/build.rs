@@ -1,5 +1,5 @@
 use micropb_gen::{
-    config::{CustomField, IntSize, OptionalRepr},
+    config::{CustomField, ImplicitEncode, IntSize, OptionalRepr, RepeatedOverflow, UnknownEnum},
     Config, EncodeDecode, Generator,
 };
 
@@ -17,6 +17,17 @@ fn no_config() {
         .unwrap();
 }
 
+fn no_debug() {
+    let mut generator = Generator::new();
+    generator.debug_impls(false);
+    generator
+        .compile_protos(
+            &["proto/basic.proto", "proto/nested.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/no_debug.rs",
+        )
+        .unwrap();
+}
+
 fn boxed_and_option() {
     let mut generator = Generator::new();
     generator.use_container_alloc();
@@ -184,6 +195,56 @@ fn container_arrayvec() {
         .unwrap();
 }
 
+fn container_fixed() {
+    let mut generator = Generator::new();
+    generator.use_container_fixed();
+    generator.configure(".Data.s", Config::new().max_bytes(3));
+    generator.configure(".Data.b", Config::new().max_bytes(1024));
+    generator.configure(".List.list", Config::new().max_len(2));
+    generator.configure(".NumList.list", Config::new().max_len(2));
+    generator.configure(".StrList.list", Config::new().max_len(3));
+    generator.configure(".StrList.list.elem", Config::new().max_bytes(2));
+    generator.configure(".FixedList.list", Config::new().max_len(2));
+    generator.configure(".EnumList.list", Config::new().max_len(2));
+
+    generator
+        .compile_protos(
+            &["proto/collections.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/container_fixed.rs",
+        )
+        .unwrap();
+}
+
+fn container_aligned() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".Data.s", Config::new().max_bytes(3));
+    generator.configure(
+        ".Data.b",
+        Config::new()
+            .vec_type("::micropb::container::AlignedVec32")
+            .max_bytes(5),
+    );
+    generator.configure(
+        ".NumList.list",
+        Config::new()
+            .vec_type("::micropb::container::AlignedVec")
+            .max_len(2),
+    );
+    generator.configure(".List.list", Config::new().max_len(2));
+    generator.configure(".StrList.list", Config::new().max_len(3));
+    generator.configure(".StrList.list.elem", Config::new().max_bytes(2));
+    generator.configure(".FixedList.list", Config::new().max_len(2));
+    generator.configure(".EnumList.list", Config::new().max_len(2));
+
+    generator
+        .compile_protos(
+            &["proto/collections.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/container_aligned.rs",
+        )
+        .unwrap();
+}
+
 fn container_alloc() {
     let mut generator = Generator::new();
     generator.use_container_alloc();
@@ -197,6 +258,93 @@ fn container_alloc() {
         .unwrap();
 }
 
+fn container_bytes() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(
+        ".Data.b",
+        Config::new().vec_type("::micropb::container::SharedBytes"),
+    );
+
+    generator
+        .compile_protos(
+            &["proto/collections.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/container_bytes.rs",
+        )
+        .unwrap();
+}
+
+fn field_order() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator
+        .compile_protos(
+            &[
+                "proto/basic.proto",
+                "proto/nested.proto",
+                "proto/collections.proto",
+                "proto/map.proto",
+            ],
+            std::env::var("OUT_DIR").unwrap() + "/field_order.rs",
+        )
+        .unwrap();
+}
+
+fn clear() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".nested.Nested.inner", Config::new().boxed(true));
+
+    generator
+        .compile_protos(
+            &[
+                "proto/basic.proto",
+                "proto/nested.proto",
+                "proto/collections.proto",
+                "proto/map.proto",
+            ],
+            std::env::var("OUT_DIR").unwrap() + "/clear.rs",
+        )
+        .unwrap();
+}
+
+fn heap_size() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc().heap_size_impls(true);
+    generator.configure(".nested.Nested.inner_msg", Config::new().boxed(true));
+
+    generator
+        .compile_protos(
+            &[
+                "proto/basic.proto",
+                "proto/nested.proto",
+                "proto/collections.proto",
+                "proto/map.proto",
+            ],
+            std::env::var("OUT_DIR").unwrap() + "/heap_size.rs",
+        )
+        .unwrap();
+}
+
+fn roundtrip_tests() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless().roundtrip_tests(true);
+    generator.configure(".", Config::new().max_len(4).max_bytes(16));
+
+    generator
+        .compile_protos(
+            &[
+                "proto/basic.proto",
+                "proto/basic3.proto",
+                "proto/nested.proto",
+                "proto/collections.proto",
+                "proto/map.proto",
+            ],
+            std::env::var("OUT_DIR").unwrap() + "/roundtrip_tests.rs",
+        )
+        .unwrap();
+}
+
 fn custom_field() {
     let mut generator = Generator::new();
     generator.configure(
@@ -247,6 +395,21 @@ fn custom_field() {
         .unwrap();
 }
 
+fn custom_oneof() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(
+        ".nested.Nested.inner",
+        Config::new().custom_field(CustomField::Type("crate::custom_oneof::Router".to_owned())),
+    );
+    generator
+        .compile_protos(
+            &["proto/basic.proto", "proto/nested.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/custom_oneof.rs",
+        )
+        .unwrap();
+}
+
 fn implicit_presence() {
     let mut generator = Generator::new();
     generator.use_container_alloc();
@@ -258,6 +421,66 @@ fn implicit_presence() {
         .unwrap();
 }
 
+fn implicit_encode() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(
+        ".NonOptional",
+        Config::new().implicit_encode(ImplicitEncode::Always),
+    );
+    generator.configure(
+        ".NonOptional.st",
+        Config::new().implicit_encode(ImplicitEncode::Never),
+    );
+    generator
+        .compile_protos(
+            &["proto/implicit_presence.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/implicit_encode.rs",
+        )
+        .unwrap();
+}
+
+fn mixed_syntax() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator
+        .compile_protos(
+            &[
+                "proto/mixed_common.proto",
+                "proto/mixed2.proto",
+                "proto/mixed3.proto",
+            ],
+            std::env::var("OUT_DIR").unwrap() + "/mixed_syntax.rs",
+        )
+        .unwrap();
+}
+
+fn implicit_presence_custom() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(
+        ".NonOptional.uint32_num",
+        Config::new()
+            .custom_field(CustomField::Type(
+                "crate::implicit_presence_custom::Millivolts".to_owned(),
+            ))
+            .implicit_presence(true),
+    );
+    // Without implicit presence, the custom field is always passed to `FieldEncode`
+    generator.configure(
+        ".NonOptional.int32_num",
+        Config::new().custom_field(CustomField::Type(
+            "crate::implicit_presence_custom::Millivolts".to_owned(),
+        )),
+    );
+    generator
+        .compile_protos(
+            &["proto/implicit_presence.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/implicit_presence_custom.rs",
+        )
+        .unwrap();
+}
+
 fn extern_import() {
     let mut gen1 = Generator::new();
     gen1.compile_protos(
@@ -344,6 +567,17 @@ fn default_str_escape() {
         .unwrap();
 }
 
+fn float() {
+    let mut generator = Generator::new();
+    generator
+        .use_container_alloc()
+        .compile_protos(
+            &["proto/float.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/float.rs",
+        )
+        .unwrap();
+}
+
 fn extension() {
     let mut generator = Generator::new();
     generator
@@ -354,21 +588,416 @@ fn extension() {
         .unwrap();
 }
 
+fn unit_struct() {
+    let mut generator = Generator::new();
+    generator.configure(".", Config::new().unit_struct(true));
+    generator
+        .compile_protos(
+            &["proto/basic3.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/unit_struct.rs",
+        )
+        .unwrap();
+}
+
+fn default_instance() {
+    let mut generator = Generator::new();
+    generator.configure(".basic.BasicTypes", Config::new().default_instance(true));
+    generator.configure(".nested.Nested", Config::new().default_instance(true));
+    generator
+        .compile_protos(
+            &["proto/basic.proto", "proto/nested.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/default_instance.rs",
+        )
+        .unwrap();
+}
+
+fn encode_changed() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".", Config::new().encode_changed(true));
+    generator.configure(".nested.Nested.basic", Config::new().boxed(true));
+    generator
+        .compile_protos(
+            &[
+                "proto/basic.proto",
+                "proto/basic3.proto",
+                "proto/nested.proto",
+                "proto/collections.proto",
+                "proto/map.proto",
+            ],
+            std::env::var("OUT_DIR").unwrap() + "/encode_changed.rs",
+        )
+        .unwrap();
+}
+
+fn merge_impl() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".", Config::new().merge_impl(true).max_len(2).max_bytes(8));
+    generator.configure(".nested.Nested.basic", Config::new().boxed(true));
+    generator.configure(
+        ".NumList.list",
+        Config::new().repeated_overflow(RepeatedOverflow::DropOldest),
+    );
+    generator.configure(
+        ".FixedList.list",
+        Config::new().repeated_overflow(RepeatedOverflow::DropNewest),
+    );
+    generator
+        .compile_protos(
+            &[
+                "proto/basic.proto",
+                "proto/basic3.proto",
+                "proto/nested.proto",
+                "proto/collections.proto",
+                "proto/map.proto",
+            ],
+            std::env::var("OUT_DIR").unwrap() + "/merge_impl.rs",
+        )
+        .unwrap();
+}
+
+fn opaque() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".nested.Nested.basic", Config::new().opaque(true));
+    generator.configure(
+        ".nested.Nested.inner_msg",
+        Config::new()
+            .opaque(true)
+            .vec_type("::micropb::heapless::Vec")
+            .max_bytes(4),
+    );
+    generator
+        .compile_protos(
+            &["proto/basic.proto", "proto/nested.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/opaque.rs",
+        )
+        .unwrap();
+}
+
+fn truncate_string() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(
+        ".Data.s",
+        Config::new().max_bytes(3).truncate_on_overflow(true),
+    );
+    generator.configure(".Data.b", Config::new().max_bytes(5));
+    generator.configure(".StrList.list", Config::new().max_len(3));
+    generator.configure(
+        ".StrList.list.elem",
+        Config::new().max_bytes(2).truncate_on_overflow(true),
+    );
+    generator.configure(".", Config::new().max_len(2));
+    generator
+        .compile_protos(
+            &["proto/collections.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/truncate_string.rs",
+        )
+        .unwrap();
+}
+
+fn repeated_overflow() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".", Config::new().max_len(2).max_bytes(4));
+    generator.configure(
+        ".NumList.list",
+        Config::new().repeated_overflow(RepeatedOverflow::DropOldest),
+    );
+    generator.configure(
+        ".FixedList.list",
+        Config::new().repeated_overflow(RepeatedOverflow::DropNewest),
+    );
+    generator.configure(
+        ".StrList.list",
+        Config::new().repeated_overflow(RepeatedOverflow::DropOldest),
+    );
+    generator
+        .compile_protos(
+            &["proto/collections.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/repeated_overflow.rs",
+        )
+        .unwrap();
+}
+
+fn packed_enum() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".Palette", Config::new().max_len(4));
+    generator.configure(
+        ".Palette.drop",
+        Config::new().unknown_enum(UnknownEnum::Drop),
+    );
+    generator.configure(
+        ".Palette.reject",
+        Config::new().unknown_enum(UnknownEnum::Error),
+    );
+    generator
+        .compile_protos(
+            &["proto/packed_enum.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/packed_enum.rs",
+        )
+        .unwrap();
+}
+
+fn unknown_fields() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".", Config::new().max_len(4).max_bytes(8));
+    generator.configure(
+        ".unknown_fields.ReadingV1",
+        Config::new().unknown_fields(true),
+    );
+    generator.configure(
+        ".unknown_fields.ReadingV1._unknown",
+        Config::new().max_bytes(12),
+    );
+    generator
+        .compile_protos(
+            &["proto/unknown_fields.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/unknown_fields.rs",
+        )
+        .unwrap();
+}
+
+fn required_fields() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".", Config::new().max_bytes(8).enforce_required(true));
+    generator.configure(
+        ".required.Settings.inner",
+        Config::new().optional_repr(OptionalRepr::Option),
+    );
+    generator.configure(".required.Loose", Config::new().enforce_required(false));
+    generator
+        .compile_protos(
+            &["proto/required.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/required_fields.rs",
+        )
+        .unwrap();
+}
+
+fn reject_unknown_fields() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".", Config::new().max_len(4).max_bytes(8));
+    generator.configure(
+        ".unknown_fields.ReadingV1",
+        Config::new().reject_unknown_fields(true),
+    );
+    generator
+        .compile_protos(
+            &["proto/unknown_fields.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/reject_unknown_fields.rs",
+        )
+        .unwrap();
+}
+
+fn field_path_errors() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".", Config::new().field_path_errors(true));
+    generator
+        .compile_protos(
+            &["proto/basic.proto", "proto/nested.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/field_path_errors.rs",
+        )
+        .unwrap();
+}
+
+fn legacy_int32() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".", Config::new().legacy_int32(true));
+    generator
+        .compile_protos(
+            &["proto/basic.proto", "proto/nested.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/legacy_int32.rs",
+        )
+        .unwrap();
+}
+
+fn enum_names() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".basic.Enum", Config::new().enum_names(true));
+    generator
+        .compile_protos(
+            &["proto/basic.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/enum_names.rs",
+        )
+        .unwrap();
+}
+
+fn raw_field() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(
+        ".nested.Nested.basic",
+        Config::new().custom_field(CustomField::Type(
+            "::micropb::callback::RawField<Vec<u8>>".to_owned(),
+        )),
+    );
+    generator.configure(
+        ".basic.BasicTypes",
+        Config::new().unknown_handler("::micropb::callback::RawField<Vec<u8>>"),
+    );
+    generator
+        .compile_protos(
+            &["proto/basic.proto", "proto/nested.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/raw_field.rs",
+        )
+        .unwrap();
+}
+
+fn well_known_struct() {
+    // Reference types generated from `struct.proto` using heap-allocated containers
+    let mut gen1 = Generator::new();
+    gen1.use_container_alloc();
+    gen1.compile_protos(
+        &[
+            "proto/well_known_struct.proto",
+            "google/protobuf/struct.proto",
+        ],
+        std::env::var("OUT_DIR").unwrap() + "/well_known_struct_alloc.rs",
+    )
+    .unwrap();
+
+    let mut gen2 = Generator::new();
+    gen2.use_container_heapless();
+    gen2.extern_type_path(
+        ".google.protobuf.Struct",
+        "::micropb::value::HeaplessStruct<16, 16>",
+    )
+    .extern_type_path(
+        ".google.protobuf.Value",
+        "::micropb::value::HeaplessValue<16, 16>",
+    )
+    .configure(".wkt.Config.name", Config::new().max_bytes(16))
+    .compile_protos(
+        &["proto/well_known_struct.proto"],
+        std::env::var("OUT_DIR").unwrap() + "/well_known_struct_value.rs",
+    )
+    .unwrap();
+}
+
+fn well_known_wrappers() {
+    // Reference types generated from the well-known type definitions
+    let mut gen1 = Generator::new();
+    gen1.use_container_alloc();
+    gen1.compile_protos(
+        &[
+            "proto/well_known_wrappers.proto",
+            "google/protobuf/duration.proto",
+            "google/protobuf/timestamp.proto",
+            "google/protobuf/wrappers.proto",
+        ],
+        std::env::var("OUT_DIR").unwrap() + "/well_known_wrappers_alloc.rs",
+    )
+    .unwrap();
+
+    let mut gen2 = Generator::new();
+    gen2.use_well_known_types()
+        .extern_type_path(".google.protobuf.StringValue", "::std::string::String")
+        .extern_type_path(
+            ".google.protobuf.BytesValue",
+            "::micropb::heapless::Vec<u8, 8>",
+        )
+        .configure(
+            ".wkt.Reading",
+            Config::new().optional_repr(OptionalRepr::Option),
+        )
+        .compile_protos(
+            &["proto/well_known_wrappers.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/well_known_wrappers_std.rs",
+        )
+        .unwrap();
+}
+
+fn borrowed() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".borrowed", Config::new().borrowed(true));
+    generator
+        .compile_protos(
+            &["proto/borrowed.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/borrowed.rs",
+        )
+        .unwrap();
+}
+
 fn main() {
     no_config();
+    no_debug();
     boxed_and_option();
     int_type();
     skip();
     keyword_fields();
     container_heapless();
     container_arrayvec();
+    container_aligned();
+    container_fixed();
     container_alloc();
+    container_bytes();
     custom_field();
+    custom_oneof();
+    field_order();
     implicit_presence();
+    implicit_encode();
+    mixed_syntax();
+    implicit_presence_custom();
     extern_import();
     lifetime_fields();
     recursive();
     conflicting_names();
     default_str_escape();
     extension();
+    float();
+    unit_struct();
+    encode_changed();
+    merge_impl();
+    default_instance();
+    opaque();
+    raw_field();
+    repeated_overflow();
+    packed_enum();
+    unknown_fields();
+    reject_unknown_fields();
+    field_path_errors();
+    legacy_int32();
+    enum_names();
+    required_fields();
+    truncate_string();
+    well_known_struct();
+    well_known_wrappers();
+    borrowed();
+    heap_size();
+    clear();
+    deterministic_encode();
+    roundtrip_tests();
+}
+
+fn deterministic_encode() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(
+        ".",
+        Config::new()
+            .deterministic_encode(true)
+            .encode_changed(true)
+            .max_len(4)
+            .max_bytes(8),
+    );
+    generator.configure(
+        ".deterministic.Record.tags.key",
+        Config::new().string_type("::micropb::container::FixedString"),
+    );
+    generator
+        .compile_protos(
+            &["proto/deterministic.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/deterministic_encode.rs",
+        )
+        .unwrap();
 }
@@ -0,0 +1,84 @@
+use micropb::MessageDecodeBorrowed;
+
+use crate::encode;
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/borrowed.rs"));
+}
+
+use proto::borrowed_::*;
+
+fn contains(buf: &[u8], slice: &[u8]) -> bool {
+    buf.as_ptr_range().contains(&slice.as_ptr())
+}
+
+#[test]
+fn borrowed_types() {
+    let frame = Frame::default();
+    let _: &str = frame.name;
+    let _: &[u8] = frame.payload;
+    let _: Option<&&str> = frame.label();
+    let _: &Vec<&[u8]> = &frame.chunks;
+    let _: &std::collections::BTreeMap<&str, &[u8]> = &frame.attrs;
+    if let Some(Frame_::Kind::Text(text)) = frame.kind {
+        let _: &str = text;
+    }
+    let _: Header = frame.header;
+
+    let envelope = Envelope::default();
+    let _: Frame<'_> = envelope.frame;
+    let _: Vec<Frame<'_>> = envelope.frames;
+}
+
+#[test]
+fn borrowed_decode() {
+    let mut frame = Frame {
+        name: "frame",
+        payload: &[1, 2, 3],
+        chunks: vec![b"ab", b""],
+        kind: Some(Frame_::Kind::Text("txt")),
+        ..Default::default()
+    };
+    frame.set_label("lbl");
+    frame.attrs.insert("key", b"val");
+    frame.set_header(Header { seq: 5 });
+    let mut envelope = Envelope::default();
+    envelope.set_frame(frame.clone());
+    envelope.frames = vec![frame.clone(), Frame::default()];
+    envelope.set_header(Header { seq: 1 });
+    let buf = encode(&envelope);
+
+    let mut decoded = Envelope::default();
+    decoded.merge_from_borrowed(&buf).unwrap();
+    assert_eq!(decoded, envelope);
+
+    // String and bytes fields point into the encoded buffer
+    let frame = &decoded.frames[0];
+    assert!(contains(&buf, frame.name.as_bytes()));
+    assert!(contains(&buf, frame.payload));
+    assert!(contains(&buf, frame.label().unwrap().as_bytes()));
+    assert!(contains(&buf, frame.chunks[0]));
+    assert!(contains(&buf, frame.attrs["key"]));
+    let Some(Frame_::Kind::Text(text)) = frame.kind else {
+        panic!("wrong oneof variant");
+    };
+    assert!(contains(&buf, text.as_bytes()));
+
+    // Round trip produces the same bytes
+    assert_eq!(encode(&decoded), buf);
+}
+
+#[test]
+fn borrowed_decode_error() {
+    let mut frame = Frame::default();
+    assert_eq!(
+        frame.merge_from_borrowed(&[0x0A, 0x02, 0xFF, 0xFF]),
+        Err(micropb::DecodeError::Utf8)
+    );
+    assert_eq!(
+        frame.merge_from_borrowed(&[0x12, 0x03, 0x01]),
+        Err(micropb::DecodeError::UnexpectedEof)
+    );
+}
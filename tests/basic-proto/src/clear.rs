@@ -0,0 +1,84 @@
+use micropb::{MessagePool, PoolReset};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/clear.rs"));
+}
+
+use proto::{basic_, nested_};
+
+#[test]
+fn scalars() {
+    let mut basic = basic_::BasicTypes::default();
+    basic.set_int32_num(12);
+    basic.set_flt(2.0);
+    basic.set_boolean(true);
+    basic.set_enumeration(basic_::Enum::Two);
+    basic.clear();
+    // Fields are reset to their custom defaults
+    assert_eq!(basic, basic_::BasicTypes::default());
+    assert_eq!(basic.int32_num, -5);
+    assert_eq!(basic.flt, 1.0);
+    assert!(basic.int32_num().is_none());
+}
+
+#[test]
+fn containers() {
+    let mut data = proto::Data::default();
+    data.set_s("hello".to_owned());
+    data.set_b(vec![1, 2, 3]);
+    let mut list = proto::List::default();
+    list.list.push(data);
+    list.list.push(proto::Data::default());
+    list.clear();
+    assert!(list.list.is_empty());
+    assert!(list.list.capacity() >= 2);
+
+    // Strings with custom defaults are reset to the default
+    let mut data = proto::Data::default();
+    data.set_s("hello".to_owned());
+    data.clear();
+    assert_eq!(data, proto::Data::default());
+    assert_eq!(data.s, "a\n\0");
+
+    let mut map = proto::Map::default();
+    map.mapping.insert("a".to_owned(), vec![1]);
+    map.clear();
+    assert!(map.mapping.is_empty());
+}
+
+#[test]
+fn nested() {
+    let mut basic = basic_::BasicTypes::default();
+    basic.set_int32_num(1);
+    let mut nested = nested_::Nested::default();
+    nested.set_basic(basic);
+    nested.inner = Some(Box::new(nested_::Nested_::Inner::Scalar(true)));
+    nested.clear();
+    assert_eq!(nested, nested_::Nested::default());
+    // The submessage is cleared as well, not just its presence
+    assert_eq!(nested.basic, basic_::BasicTypes::default());
+}
+
+/// Resets pooled lists with the generated `clear`
+struct ClearList;
+
+impl PoolReset<proto::NumList> for ClearList {
+    fn reset(msg: &mut proto::NumList) {
+        msg.clear();
+    }
+}
+
+#[test]
+fn pool_reset() {
+    static POOL: MessagePool<proto::NumList, 1, ClearList> = MessagePool::new();
+    let mut list = POOL.checkout().unwrap();
+    list.list.extend([1, 2, 3]);
+    let ptr = list.list.as_ptr();
+    drop(list);
+
+    let list = POOL.checkout().unwrap();
+    assert!(list.list.is_empty());
+    assert_eq!(list.list.as_ptr(), ptr);
+}
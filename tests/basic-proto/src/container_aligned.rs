@@ -0,0 +1,49 @@
+use micropb::{
+    container::{AlignedVec, AlignedVec32},
+    MessageDecode, MessageEncode, PbDecoder, PbEncoder, PbVec,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/container_aligned.rs"));
+}
+
+#[test]
+fn aligned_types() {
+    let data = proto::Data::default();
+    assert_eq!(data.b.as_slice(), &[0x0, 0xFF]);
+    assert_eq!(data.b.capacity(), 5);
+    let _: AlignedVec32<u8, 5> = data.b;
+
+    let list = proto::NumList::default();
+    assert_eq!(list.list.capacity(), 2);
+    let _: AlignedVec<u32, 2> = list.list;
+}
+
+#[test]
+fn decode_aligned() {
+    let mut data = proto::Data::default();
+    let mut decoder = PbDecoder::new([0x12, 4, 0x01, 0x02, 0x03, 0x04].as_slice());
+    let len = decoder.as_reader().len();
+    data.decode(&mut decoder, len).unwrap();
+    assert_eq!(data.b().map(|b| b.as_slice()), Some(&[1, 2, 3, 4][..]));
+    assert_eq!(data.b.as_ptr() as usize % 32, 0);
+
+    let mut decoder = PbDecoder::new([0x12, 6, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06].as_slice());
+    let len = decoder.as_reader().len();
+    assert_eq!(
+        data.decode(&mut decoder, len),
+        Err(micropb::DecodeError::Capacity)
+    );
+}
+
+#[test]
+fn encode_aligned() {
+    let mut data = proto::Data::default();
+    data.set_b(AlignedVec32::pb_from_slice(&[0x10, 0x20]).unwrap());
+    let mut encoder = PbEncoder::new(AlignedVec::<u8, 16>::new());
+    data.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.as_writer().as_slice(), &[0x12, 2, 0x10, 0x20]);
+    assert_eq!(data.compute_size(), 4);
+}
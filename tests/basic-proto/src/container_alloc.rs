@@ -3,7 +3,7 @@ use std::{
     mem::{size_of, size_of_val},
 };
 
-use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+use micropb::{DecodeError, DuplicateKey, MessageDecode, MessageEncode, PbDecoder, PbEncoder};
 
 mod proto {
     #![allow(clippy::all)]
@@ -43,6 +43,19 @@ fn map() {
     let _: BTreeMap<String, Vec<u8>> = map.mapping;
 }
 
+#[test]
+fn is_default() {
+    let mut list = proto::List::default();
+    assert!(list.is_default());
+    list.list.push(proto::Data::default());
+    assert!(!list.is_default());
+
+    let mut map = proto::Map::default();
+    assert!(map.is_default());
+    map.mapping.insert("a".to_owned(), vec![]);
+    assert!(!map.is_default());
+}
+
 #[test]
 fn decode_string_bytes() {
     let mut data = proto::Data::default();
@@ -149,6 +162,28 @@ fn decode_repeated() {
     assert_eq!(list.list[0], Default::default());
 }
 
+#[test]
+fn decode_error_offset() {
+    let mut list = proto::List::default();
+    let bytes = [
+        0x0A, 3, 0x0A, 1, b'a', // field 1
+        0x0A, 3, 0x0A, 1, 0xFF, // field 1 with invalid UTF-8
+    ];
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    let err = list
+        .decode(&mut decoder, bytes.len())
+        .map_err(|e| decoder.error_at(e))
+        .unwrap_err();
+    assert_eq!(err.error, DecodeError::Utf8);
+    assert_eq!(err.offset, 10);
+    // Offset of the string field inside the second element
+    assert_eq!(err.field_offset, 7);
+    assert_eq!(
+        err.to_string(),
+        "string is not valid UTF-8 at byte 10 (in field starting at byte 7)"
+    );
+}
+
 #[test]
 fn encode_repeated() {
     let mut list = proto::List::default();
@@ -281,6 +316,28 @@ fn encode_packed_enums() {
     assert_eq!(encoder.into_writer(), &[0x0A, 4, 0x01, 0x02, 0x96, 0x01]);
 }
 
+#[test]
+fn negative_packed_enums() {
+    let enumlist = proto::EnumList {
+        list: vec![proto::Enum(-1), proto::Enum(0), proto::Enum(-3)],
+    };
+    let bytes = [
+        0x0A, 21, // field 1
+        0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // -1
+        0x00, // 0
+        0xFD, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // -3
+    ];
+    assert_eq!(enumlist.compute_size(), bytes.len());
+    let mut encoder = PbEncoder::new(vec![]);
+    enumlist.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), bytes);
+
+    let mut decoded = proto::EnumList::default();
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    decoded.decode(&mut decoder, bytes.len()).unwrap();
+    assert_eq!(decoded, enumlist);
+}
+
 #[test]
 fn decode_map() {
     let mut map = proto::Map::default();
@@ -310,6 +367,29 @@ fn decode_map() {
     assert_eq!(map.mapping["ac"], &[0x02, 0x01, 0x02]);
 }
 
+#[test]
+fn decode_map_duplicate_keys() {
+    let bytes = [
+        0x0A, 7, 0x0A, 2, b'a', b'c', 0x12, 1, 0x02, // field 1
+        0x0A, 7, 0x0A, 2, b'a', b'c', 0x12, 1, 0x03, // same key
+    ];
+
+    let mut map = proto::Map::default();
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    decoder.duplicate_keys = DuplicateKey::FirstWins;
+    map.decode(&mut decoder, bytes.len()).unwrap();
+    assert_eq!(map.mapping.len(), 1);
+    assert_eq!(map.mapping["ac"], &[0x02]);
+
+    let mut map = proto::Map::default();
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    decoder.duplicate_keys = DuplicateKey::Error;
+    assert_eq!(
+        map.decode(&mut decoder, bytes.len()),
+        Err(DecodeError::DuplicateKey)
+    );
+}
+
 #[test]
 fn encode_map() {
     let mut map = proto::Map::default();
@@ -333,3 +413,90 @@ fn encode_map() {
         ]
     );
 }
+
+#[test]
+fn split_repeated() {
+    let list: Vec<_> = (0..20u8)
+        .map(|i| {
+            let mut data = proto::Data::default();
+            data.set_s("x".repeat(i as usize));
+            data
+        })
+        .collect();
+    let full = proto::List { list: list.clone() };
+
+    let mut decoded = proto::List::default();
+    for chunk in micropb::split::message_chunks(1, &list, 0, 32) {
+        let chunk = proto::List {
+            list: chunk.unwrap().to_vec(),
+        };
+        let mut encoder = PbEncoder::new(vec![]);
+        chunk.encode(&mut encoder).unwrap();
+        let bytes = encoder.into_writer();
+        assert!(bytes.len() <= 32);
+        assert_eq!(bytes.len(), chunk.compute_size());
+        decoded.merge_from_slice(&bytes).unwrap();
+    }
+    assert_eq!(decoded, full);
+
+    let mut chunks = micropb::split::message_chunks(1, &list, 0, 16);
+    assert!(chunks.by_ref().take_while(Result::is_ok).count() > 1);
+    assert_eq!(chunks.next(), None);
+}
+
+#[test]
+fn memory_budget() {
+    use micropb::{DecodeError, PbRead};
+
+    // Returns the remaining budget
+    fn decode<'a, M: MessageDecode>(
+        msg: &mut M,
+        budget: usize,
+        bytes: &'a [u8],
+    ) -> Result<usize, DecodeError<<&'a [u8] as PbRead>::Error>> {
+        let mut decoder = PbDecoder::new(bytes);
+        decoder.set_memory_budget(Some(budget));
+        msg.decode(&mut decoder, bytes.len())?;
+        Ok(decoder.memory_budget().unwrap())
+    }
+
+    // Unpacked and packed elements are charged their inline size
+    let nums = [0x08, 1, 0x08, 2, 0x0A, 2, 3, 4];
+    let mut numlist = proto::NumList::default();
+    assert_eq!(decode(&mut numlist, 4, &nums), Ok(0));
+    assert_eq!(numlist.list, &[1, 2, 3, 4]);
+    let mut numlist = proto::NumList::default();
+    assert_eq!(
+        decode(&mut numlist, 3, &nums),
+        Err(DecodeError::MemoryBudget)
+    );
+    assert_eq!(numlist.list, &[1, 2, 3]);
+
+    // Strings are charged their length on top of the element size
+    let mut strlist = proto::StrList::default();
+    let budget = size_of::<String>() + 3;
+    assert_eq!(
+        decode(&mut strlist, budget, &[0x0A, 3, b'a', b'b', b'c']),
+        Ok(0)
+    );
+
+    // Map entries are charged the size of the key and value
+    let entry = [0x0A, 7, 0x0A, 2, b'a', b'b', 0x12, 1, 0x01];
+    let budget = size_of::<(String, Vec<u8>)>() + 3;
+    let mut map = proto::Map::default();
+    assert_eq!(decode(&mut map, budget + 1, &entry), Ok(1));
+    let mut map = proto::Map::default();
+    assert_eq!(
+        decode(&mut map, budget - 1, &entry),
+        Err(DecodeError::MemoryBudget)
+    );
+    assert!(map.mapping.is_empty());
+
+    // Large length prefixes are rejected before anything is allocated
+    let mut data = proto::Data::default();
+    assert_eq!(
+        decode(&mut data, 100, &[0x0A, 0xFF, 0xFF, 0xFF, 0x7F]),
+        Err(DecodeError::MemoryBudget)
+    );
+    assert_eq!(data.s, "a\n\0");
+}
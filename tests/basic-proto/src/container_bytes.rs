@@ -0,0 +1,43 @@
+use micropb::{
+    bytes::{Bytes, BytesMut},
+    container::SharedBytes,
+    MessageDecode, MessageEncode, PbDecoder, PbEncoder,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/container_bytes.rs"));
+}
+
+#[test]
+fn shared_types() {
+    let data = proto::Data::default();
+    assert_eq!(&*data.b, &[0x0, 0xFF]);
+    let _: SharedBytes = data.b;
+}
+
+#[test]
+fn decode_shared() {
+    let mut data = proto::Data::default();
+    let mut decoder = PbDecoder::new([0x12, 3, 0x01, 0x02, 0x03].as_slice());
+    let len = decoder.as_reader().len();
+    data.decode(&mut decoder, len).unwrap();
+    assert_eq!(data.b().map(|b| &**b), Some(&[1, 2, 3][..]));
+
+    // Cloning the message shares the payload of the field
+    let clone = data.clone();
+    assert!(core::ptr::eq(clone.b.as_ptr(), data.b.as_ptr()));
+    let payload: Bytes = data.b.into_bytes();
+    assert!(core::ptr::eq(payload.as_ptr(), clone.b.as_ptr()));
+}
+
+#[test]
+fn encode_shared() {
+    let mut data = proto::Data::default();
+    data.set_b(SharedBytes::from(Bytes::from_static(&[0x10, 0x20])));
+    let mut encoder = PbEncoder::new(BytesMut::new());
+    data.encode(&mut encoder).unwrap();
+    assert_eq!(&encoder.into_writer()[..], &[0x12, 2, 0x10, 0x20]);
+    assert_eq!(data.compute_size(), 4);
+}
@@ -0,0 +1,40 @@
+use micropb::{
+    container::{FixedString, FixedVec},
+    MessageDecode, MessageEncode, PbDecoder, PbEncoder,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/container_fixed.rs"));
+}
+
+#[test]
+fn fixed_types() {
+    let data = proto::Data::default();
+    assert!(data.s().is_none());
+    assert!(data.b().is_none());
+    assert_eq!(data.s, "a\n\0");
+    assert_eq!(data.b.as_slice(), &[0x0, 0xFF]);
+    assert_eq!(data.s.capacity(), 3);
+    assert_eq!(data.b.capacity(), 1024);
+    let _: FixedString<3> = data.s;
+    let _: FixedVec<u8, 1024> = data.b;
+
+    let list = proto::StrList::default();
+    let _: FixedVec<FixedString<2>, 3> = list.list;
+}
+
+#[test]
+fn decode_encode_fixed() {
+    let bytes = [0x0A, 0x02, b'x', b'y', 0x12, 0x03, 0x01, 0x02, 0x03];
+    let mut data = proto::Data::default();
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    data.decode(&mut decoder, bytes.len()).unwrap();
+    assert_eq!(data.s().unwrap(), "xy");
+    assert_eq!(data.b().unwrap().as_slice(), &[1, 2, 3]);
+
+    let mut encoder = PbEncoder::new(vec![]);
+    data.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), bytes);
+}
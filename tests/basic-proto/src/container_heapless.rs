@@ -1,6 +1,6 @@
 use std::mem::{size_of, size_of_val};
 
-use micropb::{DecodeError, MessageDecode, PbDecoder};
+use micropb::{DecodeError, MessageDecode, MessageEncode, PbDecoder, PbEncoder};
 
 mod proto {
     #![allow(clippy::all)]
@@ -21,6 +21,23 @@ fn string_bytes() {
     let _: micropb::heapless::Vec<u8, 5> = data.b;
 }
 
+#[test]
+fn capacity_consts() {
+    assert_eq!(proto::Data::S_MAX_BYTES, 3);
+    assert_eq!(proto::Data::B_MAX_BYTES, 5);
+    assert_eq!(proto::List::LIST_MAX_LEN, 2);
+    assert_eq!(proto::StrList::LIST_MAX_LEN, 3);
+    assert_eq!(proto::StrList::LIST_MAX_BYTES, 2);
+    assert_eq!(proto::Map::MAPPING_MAX_LEN, 8);
+
+    // Related buffers can be sized from the constants
+    let list = proto::StrList::default();
+    let _: micropb::heapless::Vec<
+        micropb::heapless::String<{ proto::StrList::LIST_MAX_BYTES }>,
+        { proto::StrList::LIST_MAX_LEN },
+    > = list.list;
+}
+
 #[test]
 fn repeated() {
     let list = proto::List::default();
@@ -117,6 +134,54 @@ fn decode_repeated_cap() {
     assert_eq!(list.list[1].s, "b");
 }
 
+#[test]
+fn top_level_repeated() {
+    let mut list = proto::List::default();
+    for s in ["a", "b"] {
+        let mut data = proto::Data::default();
+        data.set_s(s.try_into().unwrap());
+        list.list.push(data).unwrap();
+    }
+
+    // Should have the same encoding as the wrapper message
+    let mut encoder = PbEncoder::new(micropb::heapless::Vec::<u8, 20>::new());
+    encoder.encode_repeated_messages(1, &list.list).unwrap();
+    let mut expected = PbEncoder::new(micropb::heapless::Vec::<u8, 20>::new());
+    list.encode(&mut expected).unwrap();
+    assert_eq!(encoder.as_writer(), expected.as_writer());
+    assert_eq!(
+        micropb::size::sizeof_repeated_messages(1, &list.list),
+        list.compute_size()
+    );
+
+    let mut vec = micropb::heapless::Vec::<proto::Data, 2>::new();
+    let mut decoder = PbDecoder::new(encoder.as_writer().as_slice());
+    let len = decoder.as_reader().len();
+    decoder.decode_repeated_messages(1, &mut vec, len).unwrap();
+    assert_eq!(vec, list.list);
+
+    // Fields with other numbers are skipped
+    vec.clear();
+    let mut decoder = PbDecoder::new(
+        [
+            0x12, 3, 0x0A, 1, b'x', // field 2
+            0x0A, 3, 0x0A, 1, b'a', // field 1
+        ]
+        .as_slice(),
+    );
+    let len = decoder.as_reader().len();
+    decoder.decode_repeated_messages(1, &mut vec, len).unwrap();
+    assert_eq!(vec.len(), 1);
+    assert_eq!(vec[0].s, "a");
+
+    let mut decoder = PbDecoder::new(encoder.as_writer().as_slice());
+    let len = decoder.as_reader().len();
+    assert_eq!(
+        decoder.decode_repeated_messages(1, &mut vec, len),
+        Err(DecodeError::Capacity)
+    );
+}
+
 #[test]
 fn decode_repeated_cap_inner() {
     let mut list = proto::StrList::default();
@@ -0,0 +1,91 @@
+//! A custom field configured on a oneof replaces the generated oneof enum, so the handler receives
+//! every variant with its tag and can process it without constructing the enum.
+
+use micropb::{
+    DecodeError, FieldDecode, FieldEncode, MessageDecode, PbDecoder, PbEncoder, PbRead, PbWrite,
+    Tag,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/custom_oneof.rs"));
+}
+
+use proto::{basic_, nested_};
+
+/// Routes each variant of `Nested.inner` to its own queue
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Router {
+    enums: Vec<basic_::Enum>,
+    inner_msgs: Vec<nested_::Nested_::InnerMsg>,
+    scalars: Vec<bool>,
+    ignored: usize,
+}
+
+impl FieldDecode for Router {
+    fn decode_field<R: PbRead>(
+        &mut self,
+        tag: Tag,
+        decoder: &mut PbDecoder<R>,
+    ) -> Result<bool, DecodeError<R::Error>> {
+        match tag.field_num() {
+            2 => self.enums.push(decoder.decode_enum()?),
+            3 => {
+                let len = decoder.decode_varint32()? as usize;
+                self.inner_msgs.push(decoder.decode_message(len)?);
+            }
+            4 => {
+                decoder.skip_wire_value(tag.wire_type())?;
+                self.ignored += 1;
+            }
+            5 => self.scalars.push(decoder.decode_bool()?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+// The variants are consumed by the queues, so there's nothing to encode
+impl FieldEncode for Router {
+    fn encode_fields<W: PbWrite>(&self, _encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+        Ok(())
+    }
+
+    fn compute_fields_size(&self) -> usize {
+        0
+    }
+}
+
+#[test]
+fn route_variants() {
+    let mut nested = nested_::Nested::default();
+    let _: &Router = &nested.inner;
+    nested
+        .merge_from_slice(&[
+            0x10, 0x01, // Field 2 (enumeration)
+            0x28, 0x01, // Field 5 (scalar)
+            0x1A, 0x02, 0x08, 0x02, // Field 3 (inner_msg)
+            0x20, 0x00, // Field 4 (inner_enum)
+            0x28, 0x00, // Field 5 (scalar)
+            0x1A, 0x02, 0x10, 0x04, // Field 3 (inner_msg)
+        ])
+        .unwrap();
+
+    // Every variant is kept, rather than only the last one like with a oneof enum
+    assert_eq!(nested.inner.enums, [basic_::Enum::One]);
+    assert_eq!(nested.inner.scalars, [true, false]);
+    assert_eq!(nested.inner.inner_msgs.len(), 2);
+    assert_eq!(nested.inner.inner_msgs[0].val(), Some(&1));
+    assert_eq!(nested.inner.inner_msgs[1].val2(), Some(&2));
+    assert_eq!(nested.inner.ignored, 1);
+}
+
+#[test]
+fn encode_without_oneof() {
+    let mut nested = nested_::Nested::default();
+    nested.inner.scalars.push(true);
+    let mut encoder = PbEncoder::new(vec![]);
+    micropb::MessageEncode::encode(&nested, &mut encoder).unwrap();
+    assert!(encoder.into_writer().is_empty());
+}
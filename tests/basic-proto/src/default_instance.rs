@@ -0,0 +1,38 @@
+use micropb::DefaultInstance;
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/default_instance.rs"));
+}
+
+use proto::{basic_, nested_};
+
+#[test]
+fn default_instance() {
+    let instance = basic_::BasicTypes::default_instance();
+    assert_eq!(instance, &basic_::BasicTypes::default());
+    assert_eq!(instance.int32_num, -5);
+    assert!(core::ptr::eq(
+        instance,
+        basic_::BasicTypes::default_instance()
+    ));
+    assert_eq!(
+        nested_::Nested::default_instance(),
+        &nested_::Nested::default()
+    );
+}
+
+#[test]
+fn unset_field_or_default() {
+    let mut nested = nested_::Nested::default();
+    // Reading an unset submessage returns the shared default instance
+    let basic = basic_::BasicTypes::or_default(nested.basic());
+    assert!(core::ptr::eq(basic, basic_::BasicTypes::default_instance()));
+    assert_eq!(basic.int32_num, -5);
+
+    let mut basic = basic_::BasicTypes::default();
+    basic.set_int32_num(7);
+    nested.set_basic(basic);
+    assert_eq!(basic_::BasicTypes::or_default(nested.basic()).int32_num, 7);
+}
@@ -0,0 +1,85 @@
+use micropb::{container::FixedString, MessageDecode, MessageEncode, PbEncoder};
+
+use crate::encode;
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/deterministic_encode.rs"));
+}
+
+use proto::deterministic_::{Record, Record_};
+
+fn key(s: &str) -> FixedString<8> {
+    let mut key = FixedString::new();
+    key.push_str(s).unwrap();
+    key
+}
+
+#[test]
+fn field_order() {
+    let mut record = Record::default();
+    record.name.push_str("ab").unwrap();
+    record.id = 2;
+    record.value = Some(Record_::Value::Num(1));
+    assert_eq!(
+        encode(&record),
+        [0x08, 0x01, 0x10, 0x02, 0x1A, 0x02, b'a', b'b']
+    );
+
+    // Oneof variants are placed by their own field numbers
+    record.value = Some(Record_::Value::Flag(true));
+    assert_eq!(
+        encode(&record),
+        [0x10, 0x02, 0x1A, 0x02, b'a', b'b', 0x30, 0x01]
+    );
+}
+
+#[test]
+fn map_order() {
+    let mut record = Record::default();
+    record.counts.insert(3, 30).unwrap();
+    record.counts.insert(1, 10).unwrap();
+    record.tags.insert(key("b"), 2).unwrap();
+    record.tags.insert(key("a"), 1).unwrap();
+    let data = encode(&record);
+    assert_eq!(
+        data,
+        [
+            0x22, 0x05, 0x0A, 0x01, b'a', 0x10, 0x01, // tags["a"]
+            0x22, 0x05, 0x0A, 0x01, b'b', 0x10, 0x02, // tags["b"]
+            0x2A, 0x04, 0x08, 0x01, 0x10, 0x0A, // counts[1]
+            0x2A, 0x04, 0x08, 0x03, 0x10, 0x1E, // counts[3]
+        ]
+    );
+
+    // Insertion order doesn't affect the output
+    let mut other = Record::default();
+    other.counts.insert(1, 10).unwrap();
+    other.counts.insert(3, 30).unwrap();
+    other.tags.insert(key("a"), 1).unwrap();
+    other.tags.insert(key("b"), 2).unwrap();
+    assert_eq!(encode(&other), data);
+
+    let mut decoded = Record::default();
+    decoded.merge_from_slice(&data).unwrap();
+    assert_eq!(decoded, record);
+}
+
+#[test]
+fn encode_changed_order() {
+    let base = Record::default();
+    let mut record = Record {
+        value: Some(Record_::Value::Flag(true)),
+        id: 2,
+        ..Default::default()
+    };
+    record.counts.insert(1, 10).unwrap();
+
+    let mut encoder = PbEncoder::new(vec![]);
+    record.encode_changed(&base, &mut encoder).unwrap();
+    assert_eq!(
+        encoder.into_writer(),
+        [0x10, 0x02, 0x2A, 0x04, 0x08, 0x01, 0x10, 0x0A, 0x30, 0x01]
+    );
+}
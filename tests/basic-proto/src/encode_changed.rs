@@ -0,0 +1,144 @@
+use micropb::{MessageDecode, MessageEncode, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/encode_changed.rs"));
+}
+
+use proto::{basic3_, basic_, nested_};
+
+fn encode_patch<M: MessageEncode>(msg: &M, base: &M) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode_changed(base, &mut encoder).unwrap();
+    let patch = encoder.into_writer();
+    assert_eq!(patch.len(), msg.compute_changed_size(base));
+    patch
+}
+
+/// Check that applying the patch onto the base reproduces the message
+fn roundtrip<M: MessageEncode + MessageDecode + Clone + PartialEq + std::fmt::Debug>(
+    msg: &M,
+    base: &M,
+) -> Vec<u8> {
+    let patch = encode_patch(msg, base);
+    let mut patched = base.clone();
+    patched.apply_patch(&patch).unwrap();
+    assert_eq!(&patched, msg);
+    patch
+}
+
+#[test]
+fn unchanged() {
+    let mut basic = basic_::BasicTypes::default();
+    basic.set_int32_num(12);
+    basic.set_flt(2.0);
+    assert!(encode_patch(&basic, &basic).is_empty());
+
+    let list = proto::NumList {
+        list: vec![1, 2, 3],
+    };
+    assert!(encode_patch(&list, &list).is_empty());
+}
+
+#[test]
+fn optional_fields() {
+    let mut base = basic_::BasicTypes::default();
+    base.set_int32_num(12);
+    base.set_uint32_num(1);
+
+    let mut basic = base.clone();
+    basic.set_uint32_num(2);
+    basic.set_boolean(true);
+    assert_eq!(roundtrip(&basic, &base), [0x18, 0x02, 0x58, 0x01]);
+
+    // Clearing a field can't be expressed in a patch
+    basic.clear_int32_num();
+    basic.clear_uint32_num();
+    assert_eq!(encode_patch(&basic, &base), [0x58, 0x01]);
+}
+
+#[test]
+fn implicit_presence() {
+    let base = basic3_::NonOptional { non_opt: 5 };
+    let msg = basic3_::NonOptional { non_opt: 6 };
+    assert_eq!(roundtrip(&msg, &base), [0x08, 0x06]);
+
+    // Zero values are ignored when decoding, so resetting the field can't be expressed
+    let msg = basic3_::NonOptional { non_opt: 0 };
+    assert!(encode_patch(&msg, &base).is_empty());
+}
+
+#[test]
+fn nested_message() {
+    let mut inner = basic_::BasicTypes::default();
+    inner.set_int32_num(1);
+    inner.set_dbl(0.5);
+    let mut base = nested_::Nested::default();
+    base.set_basic(inner.clone());
+
+    // Only the changed field of the submessage is encoded
+    let mut msg = base.clone();
+    msg.mut_basic().unwrap().set_int32_num(2);
+    assert_eq!(roundtrip(&msg, &base), [0x0A, 0x02, 0x08, 0x02]);
+
+    // Submessages not present in the base are encoded in full
+    let base = nested_::Nested::default();
+    let patch = roundtrip(&msg, &base);
+    assert_eq!(patch.len(), msg.compute_size());
+}
+
+#[test]
+fn oneof() {
+    let base = nested_::Nested {
+        inner: Some(nested_::Nested_::Inner::Scalar(true)),
+        ..Default::default()
+    };
+    assert!(encode_patch(&base, &base).is_empty());
+
+    let msg = nested_::Nested {
+        inner: Some(nested_::Nested_::Inner::InnerEnum(
+            nested_::Nested_::InnerEnum::Val,
+        )),
+        ..Default::default()
+    };
+    assert_eq!(roundtrip(&msg, &base), [0x20, 0x00]);
+}
+
+#[test]
+fn repeated_append() {
+    let base = proto::NumList { list: vec![1, 2] };
+    let mut msg = base.clone();
+    msg.list.push(3);
+    // Only the new element is encoded
+    assert_eq!(roundtrip(&msg, &base), [0x08, 0x03]);
+
+    let base = proto::FixedList { list: vec![1] };
+    let mut msg = base.clone();
+    msg.list.push(2);
+    assert_eq!(roundtrip(&msg, &base), [0x0A, 0x04, 0x02, 0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn repeated_replace() {
+    let base = proto::NumList { list: vec![1, 2] };
+    let msg = proto::NumList { list: vec![3] };
+    // The whole field is encoded, which gets appended to the base
+    let patch = encode_patch(&msg, &base);
+    assert_eq!(patch, [0x08, 0x03]);
+    let mut patched = base.clone();
+    patched.apply_patch(&patch).unwrap();
+    assert_eq!(patched.list, [1, 2, 3]);
+}
+
+#[test]
+fn map() {
+    let mut base = proto::Map::default();
+    base.mapping.insert("a".to_owned(), vec![1]);
+    assert!(encode_patch(&base, &base).is_empty());
+
+    let mut msg = base.clone();
+    msg.mapping.insert("b".to_owned(), vec![2]);
+    let patch = roundtrip(&msg, &base);
+    assert_eq!(patch.len(), msg.compute_size());
+}
@@ -0,0 +1,26 @@
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/enum_names.rs"));
+}
+
+use proto::basic_::Enum;
+
+#[test]
+fn values() {
+    assert_eq!(Enum::VALUES, &[Enum::Two, Enum::One, Enum::Zero, Enum::Neg]);
+}
+
+#[test]
+fn names() {
+    assert_eq!(Enum::Two.name(), Some("ENUM_TWO"));
+    assert_eq!(Enum::Neg.name(), Some("ENUM_NEG"));
+    assert_eq!(Enum(5).name(), None);
+
+    for val in Enum::VALUES {
+        assert_eq!(Enum::from_name(val.name().unwrap()), Some(*val));
+    }
+    assert_eq!(Enum::from_name("ENUM_ZERO"), Some(Enum::Zero));
+    assert_eq!(Enum::from_name("enum_zero"), None);
+    assert_eq!(Enum::from_name("Zero"), None);
+}
@@ -31,6 +31,7 @@ impl MessageDecode for Empty {
 }
 
 #[test]
+#[allow(unused_assignments)]
 fn imported_types() {
     let mut nested = proto::nested_::Nested::default();
     let _basic: Empty = nested.basic;
@@ -0,0 +1,336 @@
+//! The Protobuf spec allows fields to appear on the wire in any order, singular fields to appear
+//! multiple times (the last occurrence wins), and repeated fields to be split across multiple
+//! records. These tests feed the generated decoders with such inputs and compare the results
+//! against the spec semantics.
+
+use std::{collections::BTreeMap, fmt::Debug};
+
+use micropb::{MessageDecode, MessageEncode, PbDecoder};
+
+use crate::{decode, encode};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/field_order.rs"));
+}
+
+mod proto3 {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/implicit_presence.rs"));
+}
+
+use proto::nested_::{Nested, Nested_};
+
+/// Split an encoded message into the encodings of its top-level fields, including the tags
+fn split_fields(bytes: &[u8]) -> Vec<&[u8]> {
+    let mut decoder = PbDecoder::new(bytes);
+    let mut fields = vec![];
+    while !decoder.as_reader().is_empty() {
+        let start = decoder.bytes_read();
+        let tag = decoder.decode_tag().unwrap();
+        decoder.skip_wire_value(tag.wire_type()).unwrap();
+        fields.push(&bytes[start..decoder.bytes_read()]);
+    }
+    fields
+}
+
+/// Deterministic set of orderings of `n` elements: reversed, every rotation, and a number of
+/// pseudo-random shuffles
+fn orderings(n: usize) -> Vec<Vec<usize>> {
+    let mut out = vec![(0..n).rev().collect()];
+    for r in 1..n {
+        out.push((0..n).map(|i| (i + r) % n).collect());
+    }
+
+    let mut state = 0x2545F491u32;
+    for _ in 0..32 {
+        let mut order: Vec<_> = (0..n).collect();
+        // Fisher-Yates shuffle driven by xorshift
+        for i in (1..n).rev() {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            order.swap(i, state as usize % (i + 1));
+        }
+        out.push(order);
+    }
+    out
+}
+
+/// Decode the encoding of `msg` with its fields in many different orders, which should always
+/// yield the original message
+fn assert_any_order<M>(msg: &M)
+where
+    M: MessageEncode + MessageDecode + Default + PartialEq + Debug,
+{
+    let bytes = encode(msg);
+    let fields = split_fields(&bytes);
+    assert!(fields.len() > 1);
+    for order in orderings(fields.len()) {
+        let shuffled: Vec<u8> = order.iter().flat_map(|&i| fields[i]).copied().collect();
+        assert_eq!(&decode::<M>(&shuffled), msg, "field order {order:?}");
+    }
+}
+
+fn basic_types(offset: i32) -> proto::basic_::BasicTypes {
+    let mut basic = proto::basic_::BasicTypes::default();
+    basic.set_int32_num(-100 + offset);
+    basic.set_int64_num(-(1 << 40) + offset as i64);
+    basic.set_uint32_num(150 + offset as u32);
+    basic.set_uint64_num((1 << 50) + offset as u64);
+    basic.set_sint32_num(-3 - offset);
+    basic.set_sint64_num(-(1 << 35) - offset as i64);
+    basic.set_fixed32_num(0xDEADBEEF - offset as u32);
+    basic.set_fixed64_num(0x0123456789ABCDEF + offset as u64);
+    basic.set_sfixed32_num(-0x1234 + offset);
+    basic.set_sfixed64_num(-0x12345678 + offset as i64);
+    basic.set_boolean(offset % 2 == 0);
+    basic.set_flt(-1.5 + offset as f32);
+    basic.set_dbl(2.25 + offset as f64);
+    basic.set_enumeration(if offset % 2 == 0 {
+        proto::basic_::Enum::Two
+    } else {
+        proto::basic_::Enum::Zero
+    });
+    basic
+}
+
+fn data(s: &str, b: &[u8]) -> proto::Data {
+    let mut data = proto::Data::default();
+    data.set_s(s.to_owned());
+    data.set_b(b.to_owned());
+    data
+}
+
+#[test]
+fn scalars_any_order() {
+    assert_any_order(&basic_types(0));
+    assert_any_order(&basic_types(7));
+}
+
+#[test]
+fn proto3_any_order() {
+    let non_opt = proto3::NonOptional {
+        int32_num: -3,
+        int64_num: 1 << 33,
+        uint32_num: 3,
+        uint64_num: 3,
+        sint32_num: -3,
+        sint64_num: 3,
+        fixed32_num: 3,
+        fixed64_num: 3,
+        sfixed32_num: -3,
+        sfixed64_num: 3,
+        boolean: true,
+        flt: 3.5,
+        dbl: -3.5,
+        enumeration: proto3::Enum::Two,
+        st: String::from("stuff"),
+        bt: vec![0x01, 0x02],
+    };
+    assert_any_order(&non_opt);
+
+    let mut opt = proto3::Optional::default();
+    opt.set_int32_num(0);
+    opt.set_sint64_num(-9);
+    opt.set_boolean(false);
+    opt.set_flt(0.0);
+    opt.set_enumeration(proto3::Enum::Zero);
+    opt.set_st(String::new());
+    opt.set_bt(vec![7]);
+    assert_any_order(&opt);
+}
+
+#[test]
+fn nested_any_order() {
+    let mut nested = Nested::default();
+    nested.set_basic(basic_types(1));
+    let mut inner = Nested_::InnerMsg::default();
+    inner.set_val(-20);
+    inner.set_val2(30);
+    nested.inner = Some(Nested_::Inner::InnerMsg(inner));
+    assert_any_order(&nested);
+
+    // Fields of the submessage can also be in any order
+    assert_any_order(&nested.basic);
+}
+
+#[test]
+fn duplicate_scalars_last_wins() {
+    let first = basic_types(0);
+    let second = basic_types(3);
+    let bytes = [encode(&first), encode(&second)].concat();
+    assert_eq!(decode::<proto::basic_::BasicTypes>(&bytes), second);
+
+    // Fields that only appear in the first occurrence are kept
+    let mut partial = proto::basic_::BasicTypes::default();
+    partial.set_sint32_num(44);
+    partial.set_dbl(-0.5);
+    let bytes = [encode(&first), encode(&partial)].concat();
+    let mut expected = first.clone();
+    expected.set_sint32_num(44);
+    expected.set_dbl(-0.5);
+    assert_eq!(decode::<proto::basic_::BasicTypes>(&bytes), expected);
+
+    // Same applies when each field is duplicated right after itself
+    let first_fields = encode(&first);
+    let second_fields = encode(&second);
+    let interleaved: Vec<u8> = split_fields(&first_fields)
+        .into_iter()
+        .zip(split_fields(&second_fields))
+        .flat_map(|(a, b)| [a, b].concat())
+        .collect();
+    assert_eq!(decode::<proto::basic_::BasicTypes>(&interleaved), second);
+}
+
+#[test]
+fn duplicate_submessages_merge() {
+    let mut first = Nested::default();
+    first.set_basic(basic_types(0));
+    let mut second = Nested::default();
+    second.set_basic(proto::basic_::BasicTypes::default());
+    second.basic.set_uint32_num(9);
+    second.basic.set_flt(4.0);
+
+    // Fields set in either occurrence of the submessage are kept, with the later one winning
+    let mut expected = first.clone();
+    expected.basic.set_uint32_num(9);
+    expected.basic.set_flt(4.0);
+    let bytes = [encode(&first), encode(&second)].concat();
+    assert_eq!(decode::<Nested>(&bytes), expected);
+
+    // Same goes for submessages in oneofs
+    let with_inner = |val, val2| {
+        let mut inner = Nested_::InnerMsg::default();
+        if let Some(val) = val {
+            inner.set_val(val);
+        }
+        if let Some(val2) = val2 {
+            inner.set_val2(val2);
+        }
+        Nested {
+            inner: Some(Nested_::Inner::InnerMsg(inner)),
+            ..Default::default()
+        }
+    };
+    let bytes = [
+        encode(&with_inner(Some(1), None)),
+        encode(&with_inner(None, Some(2))),
+    ]
+    .concat();
+    assert_eq!(decode::<Nested>(&bytes), with_inner(Some(1), Some(2)));
+
+    // A submessage that's been cleared doesn't merge with its old contents
+    let mut msg = first.clone();
+    msg.clear_basic();
+    msg.merge_from_slice(&encode(&second)).unwrap();
+    assert_eq!(msg, second);
+
+    let mut msg = first.clone();
+    msg.merge_from_slice(&encode(&second)).unwrap();
+    assert_eq!(msg, expected);
+}
+
+#[test]
+fn oneof_last_wins() {
+    let with_inner = |inner| Nested {
+        inner: Some(inner),
+        ..Default::default()
+    };
+    let scalar = with_inner(Nested_::Inner::Scalar(true));
+    let inner_enum = with_inner(Nested_::Inner::InnerEnum(Nested_::InnerEnum(5)));
+    let enumeration = with_inner(Nested_::Inner::Enumeration(proto::basic_::Enum::One));
+
+    let bytes = [encode(&scalar), encode(&inner_enum)].concat();
+    assert_eq!(decode::<Nested>(&bytes), inner_enum);
+    let bytes = [encode(&inner_enum), encode(&scalar)].concat();
+    assert_eq!(decode::<Nested>(&bytes), scalar);
+    let bytes = [encode(&scalar), encode(&enumeration), encode(&inner_enum)].concat();
+    assert_eq!(decode::<Nested>(&bytes), inner_enum);
+}
+
+#[test]
+fn split_repeated() {
+    // Repeated messages and strings are concatenated across records
+    let a = proto::List {
+        list: vec![data("a", b"1"), data("b", b"2")],
+    };
+    let b = proto::List {
+        list: vec![data("c", b"3")],
+    };
+    let bytes = [encode(&a), encode(&b)].concat();
+    assert_eq!(
+        decode::<proto::List>(&bytes).list,
+        [a.list.as_slice(), b.list.as_slice()].concat()
+    );
+
+    let a = proto::StrList {
+        list: vec!["x".to_owned()],
+    };
+    let b = proto::StrList {
+        list: vec!["y".to_owned(), "z".to_owned()],
+    };
+    let bytes = [encode(&b), encode(&a), encode(&b)].concat();
+    assert_eq!(
+        decode::<proto::StrList>(&bytes).list,
+        ["y", "z", "x", "y", "z"]
+    );
+
+    // Unpacked fields accept packed records as well, and vice versa
+    let unpacked = encode(&proto::NumList { list: vec![1, 2] });
+    assert_eq!(unpacked, [0x08, 1, 0x08, 2]);
+    let packed = [0x0A, 3, 3, 0x96, 0x01].as_slice();
+    let bytes = [unpacked.as_slice(), packed, &[0x0A, 0], &unpacked].concat();
+    let bytes = [bytes.as_slice(), &[0x0A, 1, 4]].concat();
+    assert_eq!(
+        decode::<proto::NumList>(&bytes).list,
+        [1, 2, 3, 150, 1, 2, 4]
+    );
+
+    let packed = encode(&proto::FixedList { list: vec![1, 2] });
+    assert_eq!(packed[0], 0x0A);
+    let unpacked = [0x0D, 0xEF, 0xBE, 0xAD, 0xDE].as_slice();
+    let bytes = [packed.as_slice(), unpacked, &packed].concat();
+    assert_eq!(
+        decode::<proto::FixedList>(&bytes).list,
+        [1, 2, 0xDEADBEEF, 1, 2]
+    );
+}
+
+#[test]
+fn split_repeated_with_other_fields() {
+    // Unknown fields in between the records of a repeated field don't disrupt the field
+    let a = encode(&proto::NumList { list: vec![5] });
+    let unknown = [0x10, 0x01, 0x1A, 0x02, 0xAA, 0xBB, 0x25, 0, 0, 0, 0].as_slice();
+    let bytes = [a.as_slice(), unknown, &a, unknown].concat();
+    assert_eq!(decode::<proto::NumList>(&bytes).list, [5, 5]);
+}
+
+#[test]
+fn split_map() {
+    let a = proto::Map {
+        mapping: BTreeMap::from([("k1".to_owned(), vec![1]), ("k2".to_owned(), vec![2])]),
+    };
+    let b = proto::Map {
+        mapping: BTreeMap::from([("k1".to_owned(), vec![3]), ("k3".to_owned(), vec![4])]),
+    };
+    let bytes = [encode(&a), encode(&b)].concat();
+    // Entries are merged, with the last value winning for duplicate keys
+    assert_eq!(
+        decode::<proto::Map>(&bytes).mapping,
+        BTreeMap::from([
+            ("k1".to_owned(), vec![3]),
+            ("k2".to_owned(), vec![2]),
+            ("k3".to_owned(), vec![4]),
+        ])
+    );
+
+    // Fields within a map entry can also be in any order
+    let entry = [0x0A, 6, 0x12, 1, 9, 0x0A, 1, b'k'].as_slice();
+    assert_eq!(
+        decode::<proto::Map>(entry).mapping,
+        BTreeMap::from([("k".to_owned(), vec![9])])
+    );
+}
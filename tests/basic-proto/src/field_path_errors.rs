@@ -0,0 +1,47 @@
+use micropb::{DecodeError, MessageDecode, PbDecoder, PbRead};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/field_path_errors.rs"));
+}
+
+fn decode_err(bytes: &[u8]) -> (DecodeError<<&[u8] as PbRead>::Error>, Vec<u32>, String) {
+    let mut nested = proto::nested_::Nested::default();
+    let mut decoder = PbDecoder::new(bytes);
+    let err = nested
+        .decode(&mut decoder, bytes.len())
+        .map_err(|e| decoder.error_ctx(e))
+        .unwrap_err();
+    let path = err.path.iter().collect();
+    let msg = err.to_string();
+    (err.error, path, msg)
+}
+
+#[test]
+fn nested_field_path() {
+    // fixed32 in `basic` is cut off
+    let (err, path, msg) = decode_err(&[0x0A, 0x03, 0x3D, 0x01, 0x02]);
+    assert_eq!(err, DecodeError::UnexpectedEof);
+    assert_eq!(path, [1, 7]);
+    assert_eq!(msg, "unexpected end of input in field 1.7");
+
+    // Varint in `inner_msg` oneof field is cut off
+    let (err, path, _) = decode_err(&[0x1A, 0x02, 0x08, 0x80]);
+    assert_eq!(err, DecodeError::UnexpectedEof);
+    assert_eq!(path, [3, 1]);
+}
+
+#[test]
+fn top_level_field_path() {
+    // Length of `basic` goes past the end of the input
+    let (err, path, _) = decode_err(&[0x0A, 0x05]);
+    assert_eq!(err, DecodeError::UnexpectedEof);
+    assert_eq!(path, [1]);
+
+    // Tag itself is cut off, so no field is being decoded yet
+    let (err, path, msg) = decode_err(&[0x80]);
+    assert_eq!(err, DecodeError::UnexpectedEof);
+    assert!(path.is_empty());
+    assert_eq!(msg, "unexpected end of input");
+}
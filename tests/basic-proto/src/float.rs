@@ -0,0 +1,66 @@
+use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/float.rs"));
+}
+
+use proto::float_::Floats;
+
+#[test]
+fn float_defaults() {
+    let floats = Floats::default();
+    assert_eq!(floats.pos_inf, f32::INFINITY);
+    assert_eq!(floats.neg_inf, f32::NEG_INFINITY);
+    assert!(floats.nan.is_nan());
+    assert_eq!(floats.neg_zero.to_bits(), (-0.0f32).to_bits());
+    assert!(floats.dbl_nan.is_nan());
+    assert_eq!(floats.dbl_neg_zero.to_bits(), (-0.0f64).to_bits());
+    // Subnormal
+    assert_eq!(floats.dbl_exp, 1e-310);
+    assert!(!floats.dbl_exp.is_normal());
+}
+
+#[test]
+fn float_bits_roundtrip() {
+    let f32_bits = [
+        0x7FC00001, // quiet NaN with payload
+        0xFFA00005, // negative signalling NaN with payload
+        0x80000000, // -0.0
+        0x00000001, // smallest subnormal
+        0x807FFFFF, // largest negative subnormal
+        0x7F800000, // infinity
+    ];
+    let f64_bits = [
+        0x7FF8000000000001,
+        0xFFF0000000000BAD,
+        0x8000000000000000,
+        0x0000000000000001,
+        0x800FFFFFFFFFFFFF,
+        0xFFF0000000000000,
+    ];
+
+    let mut floats = Floats::default();
+    for (&b32, &b64) in f32_bits.iter().zip(&f64_bits) {
+        floats.set_nan(f32::from_bits(b32));
+        floats.set_dbl_nan(f64::from_bits(b64));
+        floats.list.push(f32::from_bits(b32));
+        floats.dbl_list.push(f64::from_bits(b64));
+    }
+
+    let mut encoder = PbEncoder::new(vec![]);
+    floats.encode(&mut encoder).unwrap();
+    let bytes = encoder.into_writer();
+    assert_eq!(bytes.len(), floats.compute_size());
+
+    let mut decoded = Floats::default();
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    decoded.decode(&mut decoder, bytes.len()).unwrap();
+    assert_eq!(decoded.nan().unwrap().to_bits(), 0x7F800000);
+    assert_eq!(decoded.dbl_nan().unwrap().to_bits(), 0xFFF0000000000000);
+    let list: Vec<_> = decoded.list.iter().map(|f| f.to_bits()).collect();
+    assert_eq!(list, f32_bits);
+    let dbl_list: Vec<_> = decoded.dbl_list.iter().map(|f| f.to_bits()).collect();
+    assert_eq!(dbl_list, f64_bits);
+}
@@ -0,0 +1,81 @@
+use core::mem::size_of;
+
+use micropb::{HeapSize, MessageDecode, PbDecoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/heap_size.rs"));
+}
+
+fn data(s_cap: usize, b_cap: usize) -> proto::Data {
+    let mut data = proto::Data {
+        s: String::with_capacity(s_cap),
+        b: Vec::with_capacity(b_cap),
+        ..Default::default()
+    };
+    data._has.set_s();
+    data
+}
+
+#[test]
+fn fields() {
+    assert_eq!(data(10, 4).heap_bytes_used(), 14);
+    assert_eq!(data(0, 0).heap_bytes_used(), 0);
+    assert_eq!(proto::Enum::Zero.heap_bytes_used(), 0);
+    assert_eq!(proto::FixedList::default().heap_bytes_used(), 0);
+}
+
+#[test]
+fn nested() {
+    let mut list = proto::List {
+        list: Vec::with_capacity(3),
+    };
+    list.list.push(data(5, 0));
+    list.list.push(data(1, 2));
+    assert_eq!(list.heap_bytes_used(), 3 * size_of::<proto::Data>() + 8);
+
+    let mut map = proto::Map::default();
+    map.mapping
+        .insert(String::with_capacity(3), Vec::with_capacity(7));
+    assert_eq!(
+        map.heap_bytes_used(),
+        size_of::<(String, Vec<u8>)>() + 3 + 7
+    );
+}
+
+#[test]
+fn oneof() {
+    let mut nested = proto::nested_::Nested::default();
+    assert_eq!(nested.heap_bytes_used(), 0);
+    nested.inner = Some(proto::nested_::Nested_::Inner::Scalar(true));
+    assert_eq!(nested.heap_bytes_used(), 0);
+    nested.inner = Some(proto::nested_::Nested_::Inner::InnerMsg(Box::default()));
+    assert_eq!(
+        nested.heap_bytes_used(),
+        size_of::<proto::nested_::Nested_::InnerMsg>()
+    );
+}
+
+#[test]
+fn decoded() {
+    let mut list = proto::List::default();
+    let mut decoder = PbDecoder::new(
+        [
+            0x0A, 0x05, 0x0A, 0x03, b'a', b'b', b'c', // Data { s: "abc" }
+            0x0A, 0x04, 0x12, 0x02, 0x01, 0x02, // Data { b: [1, 2] }
+        ]
+        .as_slice(),
+    );
+    let len = decoder.as_reader().len();
+    list.decode(&mut decoder, len).unwrap();
+
+    let expected = list.list.capacity() * size_of::<proto::Data>()
+        + list
+            .list
+            .iter()
+            .map(|d| d.s.capacity() + d.b.capacity())
+            .sum::<usize>();
+    assert_eq!(list.heap_bytes_used(), expected);
+    assert!(expected >= 2 * size_of::<proto::Data>() + 5);
+}
@@ -0,0 +1,61 @@
+use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/implicit_encode.rs"));
+}
+
+#[test]
+fn always_encode_defaults() {
+    // All fields except `st` are encoded even though they're zero
+    let non_opt = proto::NonOptional::default();
+    let bytes = [
+        0x08, 0x00, // field 1
+        0x10, 0x00, // field 2
+        0x18, 0x00, // field 3
+        0x20, 0x00, // field 4
+        0x28, 0x00, // field 5
+        0x30, 0x00, // field 6
+        0x3D, 0x00, 0x00, 0x00, 0x00, // field 7
+        0x41, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 8
+        0x4D, 0x00, 0x00, 0x00, 0x00, // field 9
+        0x51, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 10
+        0x58, 0x00, // field 11
+        0x65, 0x00, 0x00, 0x00, 0x00, // field 12
+        0x69, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 13
+        0x70, 0x00, // field 14
+        0x82, 0x01, 0x00, // field 16
+    ];
+    assert_eq!(non_opt.compute_size(), bytes.len());
+    let mut encoder = PbEncoder::new(vec![]);
+    non_opt.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), bytes);
+}
+
+#[test]
+fn never_encode() {
+    let non_opt = proto::NonOptional {
+        st: String::from("axe"),
+        ..Default::default()
+    };
+    let size = proto::NonOptional::default().compute_size();
+    assert_eq!(non_opt.compute_size(), size);
+    let mut encoder = PbEncoder::new(vec![]);
+    non_opt.encode(&mut encoder).unwrap();
+    assert!(!encoder.into_writer().contains(&b'x'));
+
+    // The field is still decoded
+    let mut decoded = proto::NonOptional::default();
+    let bytes = [0x7A, 3, b'a', b'x', b'e'];
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    decoded.decode(&mut decoder, bytes.len()).unwrap();
+    assert_eq!(decoded, non_opt);
+}
+
+#[test]
+fn explicit_presence_unaffected() {
+    // Config is only on `NonOptional`, so optional fields aren't touched
+    let opt = proto::Optional::default();
+    assert_eq!(opt.compute_size(), 0);
+}
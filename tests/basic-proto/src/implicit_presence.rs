@@ -86,6 +86,52 @@ fn encode_implicit_presence() {
     assert_eq!(encoder.into_writer(), &[]);
 }
 
+#[test]
+fn implicit_presence_neg_zero() {
+    // -0.0 isn't the default value, so it's encoded and keeps its sign after decoding
+    let mut non_opt = proto::NonOptional {
+        flt: -0.0,
+        dbl: -0.0,
+        ..Default::default()
+    };
+    assert!(!non_opt.is_default());
+    let bytes = [
+        0x65, 0x00, 0x00, 0x00, 0x80, // field 12
+        0x69, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, // field 13
+    ];
+    assert_eq!(non_opt.compute_size(), bytes.len());
+    let mut encoder = PbEncoder::new(vec![]);
+    non_opt.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), bytes);
+
+    non_opt = proto::NonOptional::default();
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    non_opt.decode(&mut decoder, bytes.len()).unwrap();
+    assert_eq!(non_opt.flt.to_bits(), 0x80000000);
+    assert_eq!(non_opt.dbl.to_bits(), 0x8000000000000000);
+}
+
+#[test]
+fn is_default_implicit_presence() {
+    let mut non_opt = proto::NonOptional::default();
+    assert!(non_opt.is_default());
+    non_opt.st.push('a');
+    assert!(!non_opt.is_default());
+    non_opt.st.clear();
+    non_opt.enumeration = proto::Enum::One;
+    assert!(!non_opt.is_default());
+    non_opt.enumeration = proto::Enum::Zero;
+    non_opt.flt = 0.5;
+    assert!(!non_opt.is_default());
+    non_opt.flt = 0.0;
+    assert!(non_opt.is_default());
+
+    let mut opt = proto::Optional::default();
+    assert!(opt.is_default());
+    opt.set_bt(vec![]);
+    assert!(!opt.is_default());
+}
+
 #[test]
 fn decode_explicit_presence() {
     let mut opt = proto::Optional {
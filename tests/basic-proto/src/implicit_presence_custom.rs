@@ -0,0 +1,74 @@
+use micropb::{
+    field::ImplicitPresence, FieldDecode, FieldEncode, MessageDecode, MessageEncode, PbDecoder,
+    PbEncoder, Tag, WIRE_TYPE_VARINT,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/implicit_presence_custom.rs"));
+}
+
+/// Newtype over a scalar that's substituted in for both field 1 and field 3
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Millivolts {
+    field_num: u32,
+    val: u16,
+}
+
+impl ImplicitPresence for Millivolts {
+    fn is_default_value(&self) -> bool {
+        self.val.is_default_value()
+    }
+}
+
+impl FieldDecode for Millivolts {
+    fn decode_field<R: micropb::PbRead>(
+        &mut self,
+        tag: Tag,
+        decoder: &mut PbDecoder<R>,
+    ) -> Result<bool, micropb::DecodeError<R::Error>> {
+        self.field_num = tag.field_num();
+        self.val = decoder.decode_varint32()? as u16;
+        Ok(true)
+    }
+}
+
+// Always writes out the field, even if the value is 0
+impl FieldEncode for Millivolts {
+    fn encode_fields<W: micropb::PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), W::Error> {
+        encoder.encode_tag(Tag::from_parts(self.field_num, WIRE_TYPE_VARINT))?;
+        encoder.encode_varint32(self.val as u32)
+    }
+
+    fn compute_fields_size(&self) -> usize {
+        1 + micropb::size::sizeof_varint32(self.val as u32)
+    }
+}
+
+#[test]
+fn custom_implicit_presence() {
+    let mut msg = proto::NonOptional::default();
+    msg.int32_num.field_num = 1;
+    msg.uint32_num.field_num = 3;
+    // Only the field without implicit presence is encoded
+    assert_eq!(msg.compute_size(), 2);
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), [0x08, 0x00]);
+
+    msg.uint32_num.val = 3300;
+    assert_eq!(msg.compute_size(), 5);
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    let bytes = encoder.into_writer();
+    assert_eq!(bytes, [0x08, 0x00, 0x18, 0xE4, 0x19]);
+
+    let mut decoded = proto::NonOptional::default();
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    decoded.decode(&mut decoder, bytes.len()).unwrap();
+    assert_eq!(decoded, msg);
+}
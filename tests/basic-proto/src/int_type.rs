@@ -15,6 +15,26 @@ fn enum_int_type() {
     assert_eq!(size_of::<proto::basic_::Enum>(), 1);
 }
 
+#[test]
+fn negative_enum_int_type() {
+    // Narrow enum types are sign-extended to 10-byte varints, same as i32 enums
+    let bytes = [
+        0x70, 0x9C, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // field 14
+    ];
+    let mut basic = proto::basic_::BasicTypes::default();
+    basic.set_enumeration(proto::basic_::Enum(-100));
+    assert_eq!(basic.compute_size(), bytes.len());
+    let mut encoder = PbEncoder::new(vec![]);
+    basic.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), &bytes);
+
+    let mut decoded = proto::basic_::BasicTypes::default();
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    decoded.decode(&mut decoder, bytes.len()).unwrap();
+    assert_eq!(decoded.enumeration(), Some(&proto::basic_::Enum(-100)));
+    assert_eq!(decoded.enumeration(), basic.enumeration());
+}
+
 #[test]
 fn field_int_type() {
     let basic = proto::basic_::BasicTypes::default();
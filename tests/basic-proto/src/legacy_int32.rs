@@ -0,0 +1,59 @@
+use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/legacy_int32.rs"));
+}
+
+#[test]
+fn encode_negative_int32() {
+    let mut basic = proto::basic_::BasicTypes::default();
+    basic.set_int32_num(-2);
+    // int64 fields still use the standard encoding
+    basic.set_int64_num(-2);
+
+    let bytes = [
+        0x08, 0xFE, 0xFF, 0xFF, 0xFF, 0x0F, // field 1
+        0x10, 0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // field 2
+    ];
+    assert_eq!(basic.compute_size(), bytes.len());
+    let mut encoder = PbEncoder::new(vec![]);
+    basic.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), bytes);
+}
+
+#[test]
+fn nested_len() {
+    let mut basic = proto::basic_::BasicTypes::default();
+    basic.set_int32_num(i32::MIN);
+    let mut nested = proto::nested_::Nested::default();
+    nested.set_basic(basic);
+
+    // Length prefix of the submessage accounts for the shorter encoding
+    let bytes = [0x0A, 0x06, 0x08, 0x80, 0x80, 0x80, 0x80, 0x08];
+    assert_eq!(nested.compute_size(), bytes.len());
+    let mut encoder = PbEncoder::new(vec![]);
+    nested.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), bytes);
+
+    let mut decoded = proto::nested_::Nested::default();
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    decoded.decode(&mut decoder, bytes.len()).unwrap();
+    assert_eq!(decoded, nested);
+}
+
+#[test]
+fn decode_both_encodings() {
+    for bytes in [
+        &[0x08, 0xFB, 0xFF, 0xFF, 0xFF, 0x0F][..],
+        &[
+            0x08, 0xFB, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01,
+        ],
+    ] {
+        let mut basic = proto::basic_::BasicTypes::default();
+        let mut decoder = PbDecoder::new(bytes);
+        basic.decode(&mut decoder, bytes.len()).unwrap();
+        assert_eq!(basic.int32_num(), Some(&-5));
+    }
+}
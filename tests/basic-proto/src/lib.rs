@@ -1,34 +1,118 @@
 extern crate alloc;
 
+/// Encode `msg` into a new buffer, checking that its size matches `compute_size`
+#[cfg(test)]
+fn encode<M: micropb::MessageEncode>(msg: &M) -> Vec<u8> {
+    let mut encoder = micropb::PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    let bytes = encoder.into_writer();
+    assert_eq!(bytes.len(), msg.compute_size());
+    bytes
+}
+
+/// Decode a new message from all of `bytes`
+#[cfg(test)]
+fn decode<M: micropb::MessageDecode + Default>(bytes: &[u8]) -> M {
+    let mut msg = M::default();
+    let mut decoder = micropb::PbDecoder::new(bytes);
+    msg.decode(&mut decoder, bytes.len()).unwrap();
+    assert!(decoder.as_reader().is_empty());
+    msg
+}
+
+#[cfg(test)]
+mod borrowed;
 #[cfg(test)]
 mod boxed_and_option;
 #[cfg(test)]
+mod clear;
+#[cfg(test)]
 mod conflicting_names;
 #[cfg(test)]
+mod container_aligned;
+#[cfg(test)]
 mod container_alloc;
 #[cfg(test)]
 mod container_arrayvec;
 #[cfg(test)]
+mod container_bytes;
+#[cfg(test)]
+mod container_fixed;
+#[cfg(test)]
 mod container_heapless;
 #[cfg(test)]
 mod custom_field;
 #[cfg(test)]
+mod custom_oneof;
+#[cfg(test)]
+mod default_instance;
+#[cfg(test)]
 mod default_str_escape;
 #[cfg(test)]
+mod deterministic_encode;
+#[cfg(test)]
+mod encode_changed;
+#[cfg(test)]
+mod enum_names;
+#[cfg(test)]
 mod extension;
 #[cfg(test)]
 mod extern_import;
 #[cfg(test)]
+mod field_order;
+#[cfg(test)]
+mod field_path_errors;
+#[cfg(test)]
+mod float;
+#[cfg(test)]
+mod heap_size;
+#[cfg(test)]
+mod implicit_encode;
+#[cfg(test)]
 mod implicit_presence;
 #[cfg(test)]
+mod implicit_presence_custom;
+#[cfg(test)]
 mod int_type;
 #[cfg(test)]
 mod keyword_fields;
 #[cfg(test)]
+mod legacy_int32;
+#[cfg(test)]
 mod lifetime_fields;
 #[cfg(test)]
+mod merge_impl;
+#[cfg(test)]
+mod mixed_syntax;
+#[cfg(test)]
 mod no_config;
 #[cfg(test)]
+mod no_debug;
+#[cfg(test)]
+mod opaque;
+#[cfg(test)]
+mod packed_enum;
+#[cfg(test)]
+mod raw_field;
+#[cfg(test)]
 mod recursive;
 #[cfg(test)]
+mod reject_unknown_fields;
+#[cfg(test)]
+mod repeated_overflow;
+#[cfg(test)]
+mod required_fields;
+#[cfg(test)]
+mod roundtrip_tests;
+#[cfg(test)]
 mod skip;
+#[cfg(test)]
+mod truncate_string;
+#[cfg(test)]
+mod unit_struct;
+#[cfg(test)]
+mod unknown_fields;
+#[cfg(test)]
+mod well_known_struct;
+#[cfg(test)]
+mod well_known_wrappers;
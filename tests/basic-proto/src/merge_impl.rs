@@ -0,0 +1,180 @@
+use micropb::heapless;
+use micropb::{MessageDecode, MessageEncode, MessageMerge, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/merge_impl.rs"));
+}
+
+use proto::{basic3_, basic_, nested_};
+
+/// Check that merging gives the same result as decoding `other` on top of `msg`
+fn merged<M: MessageMerge + MessageEncode + MessageDecode + Clone + PartialEq + std::fmt::Debug>(
+    msg: &M,
+    other: &M,
+) -> M {
+    let mut merged = msg.clone();
+    merged.merge(other).unwrap();
+
+    let mut encoder = PbEncoder::new(heapless::Vec::<u8, 64>::new());
+    other.encode(&mut encoder).unwrap();
+    let mut decoded = msg.clone();
+    decoded.merge_from_slice(&encoder.into_writer()).unwrap();
+    assert_eq!(merged, decoded);
+    merged
+}
+
+#[test]
+fn optional_fields() {
+    let mut msg = basic_::BasicTypes::default();
+    msg.set_int32_num(12);
+    msg.set_uint32_num(1);
+    let mut other = basic_::BasicTypes::default();
+    other.set_uint32_num(2);
+    other.set_boolean(true);
+
+    let msg = merged(&msg, &other);
+    assert_eq!(msg.int32_num(), Some(&12));
+    assert_eq!(msg.uint32_num(), Some(&2));
+    assert_eq!(msg.boolean(), Some(&true));
+    assert_eq!(msg.flt(), None);
+}
+
+#[test]
+fn implicit_presence() {
+    let msg = basic3_::NonOptional { non_opt: 5 };
+    assert_eq!(
+        merged(&msg, &basic3_::NonOptional { non_opt: 6 }).non_opt,
+        6
+    );
+    // Zero values don't overwrite
+    assert_eq!(
+        merged(&msg, &basic3_::NonOptional { non_opt: 0 }).non_opt,
+        5
+    );
+}
+
+#[test]
+fn nested_message() {
+    let mut inner = basic_::BasicTypes::default();
+    inner.set_int32_num(1);
+    let mut msg = nested_::Nested::default();
+    msg.set_basic(inner);
+
+    let mut inner = basic_::BasicTypes::default();
+    inner.set_dbl(0.5);
+    let mut other = nested_::Nested::default();
+    other.set_basic(inner);
+
+    // Submessages set in both messages are merged
+    let msg = merged(&msg, &other);
+    let basic = msg.basic().unwrap();
+    assert_eq!(basic.int32_num(), Some(&1));
+    assert_eq!(basic.dbl(), Some(&0.5));
+
+    // Submessages only set in the other message are copied
+    let msg = merged(&nested_::Nested::default(), &other);
+    assert_eq!(msg.basic(), other.basic());
+}
+
+#[test]
+fn oneof() {
+    let msg = nested_::Nested {
+        inner: Some(nested_::Nested_::Inner::InnerMsg(
+            nested_::Nested_::InnerMsg {
+                val: 1,
+                _has: nested_::Nested_::InnerMsg_::_Hazzer::default().init_val(),
+                ..Default::default()
+            },
+        )),
+        ..Default::default()
+    };
+    let mut inner = nested_::Nested_::InnerMsg::default();
+    inner.set_val2(2);
+    let other = nested_::Nested {
+        inner: Some(nested_::Nested_::Inner::InnerMsg(inner)),
+        ..Default::default()
+    };
+
+    // Same message variant is merged
+    let merged_msg = merged(&msg, &other);
+    let Some(nested_::Nested_::Inner::InnerMsg(inner)) = &merged_msg.inner else {
+        panic!("wrong variant");
+    };
+    assert_eq!(inner.val(), Some(&1));
+    assert_eq!(inner.val2(), Some(&2));
+
+    // Different variant is overwritten
+    let other = nested_::Nested {
+        inner: Some(nested_::Nested_::Inner::Scalar(true)),
+        ..Default::default()
+    };
+    assert_eq!(merged(&msg, &other).inner, other.inner);
+    // Unset oneof doesn't overwrite
+    assert_eq!(merged(&msg, &nested_::Nested::default()).inner, msg.inner);
+}
+
+#[test]
+fn repeated() {
+    let mut msg = proto::List::default();
+    msg.list.push(proto::Data::default()).unwrap();
+    let msg = merged(&msg, &msg);
+    assert_eq!(msg.list.len(), 2);
+
+    // Full containers return an error by default
+    let mut merged = msg.clone();
+    assert!(merged.merge(&msg).is_err());
+}
+
+#[test]
+fn repeated_overflow() {
+    let msg = proto::NumList {
+        list: heapless::Vec::from_slice(&[1, 2]).unwrap(),
+    };
+    let other = proto::NumList {
+        list: heapless::Vec::from_slice(&[3]).unwrap(),
+    };
+    assert_eq!(merged(&msg, &other).list, [2, 3]);
+    let msg = proto::NumList {
+        list: heapless::Vec::from_slice(&[1]).unwrap(),
+    };
+    let other = proto::NumList {
+        list: heapless::Vec::from_slice(&[3, 4]).unwrap(),
+    };
+    assert_eq!(merged(&msg, &other).list, [3, 4]);
+
+    let msg = proto::FixedList {
+        list: heapless::Vec::from_slice(&[1, 2]).unwrap(),
+    };
+    let other = proto::FixedList {
+        list: heapless::Vec::from_slice(&[3]).unwrap(),
+    };
+    assert_eq!(merged(&msg, &other).list, [1, 2]);
+}
+
+#[test]
+fn map() {
+    let key = |k| heapless::String::try_from(k).unwrap();
+    let val = |v| heapless::Vec::from_slice(&[v]).unwrap();
+    let mut msg = proto::Map::default();
+    msg.mapping.insert(key("a"), val(1)).unwrap();
+    let mut other = proto::Map::default();
+    other.mapping.insert(key("a"), val(2)).unwrap();
+    other.mapping.insert(key("b"), val(3)).unwrap();
+
+    let msg = merged(&msg, &other);
+    assert_eq!(msg.mapping.len(), 2);
+    assert_eq!(msg.mapping[&key("a")], [2]);
+}
+
+#[test]
+fn decode_merges_message_fields() {
+    // A message field that occurs twice in the input is merged, rather than replaced
+    let mut msg = nested_::Nested::default();
+    msg.merge_from_slice(&[0x0A, 0x02, 0x08, 0x02, 0x0A, 0x02, 0x18, 0x03])
+        .unwrap();
+    let basic = msg.basic().unwrap();
+    assert_eq!(basic.int32_num(), Some(&2));
+    assert_eq!(basic.uint32_num(), Some(&3));
+}
@@ -0,0 +1,85 @@
+use crate::{decode, encode};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/mixed_syntax.rs"));
+}
+
+use proto::{mixed2_::*, mixed3_::*, mixed_common_::*};
+
+#[test]
+fn defaults() {
+    // proto2 defaults apply to proto2 fields, even if their type comes from a proto3 file
+    let holder = Holder::default();
+    assert_eq!(holder.num, 5);
+    assert_eq!(holder.num(), None);
+    assert_eq!(holder.level, Level::High);
+    // First declared value of a proto2 enum
+    assert_eq!(holder.mode, Mode::Auto);
+
+    let sample = Sample::default();
+    assert_eq!(sample.value, 0);
+    assert_eq!(sample.level, Level::Unspecified);
+
+    // Nested proto2 messages keep their defaults inside proto3 messages
+    let outer = Outer::default();
+    assert!(!outer._has.holder());
+    assert_eq!(outer.holder.num, 5);
+}
+
+#[test]
+fn proto3_in_proto2() {
+    let mut sample = Sample::default();
+    sample.set_opt(0);
+    sample.list = vec![1, 2];
+    let mut holder = Holder::default();
+    holder.set_sample(sample);
+    holder.set_num(0);
+    holder.list = vec![1, 2];
+
+    let bytes = encode(&holder);
+    assert_eq!(
+        bytes,
+        [
+            // Sample uses implicit presence for `value`, and packs `list`
+            0x0A, 0x06, 0x10, 0x00, 0x1A, 0x02, 0x01, 0x02,
+            // Holder has explicit presence for `num`, and doesn't pack `list`
+            0x10, 0x00, 0x28, 0x01, 0x28, 0x02,
+        ]
+    );
+    assert_eq!(decode::<Holder>(&bytes), holder);
+}
+
+#[test]
+fn proto2_in_proto3() {
+    let mut holder = Holder::default();
+    holder.set_num(0);
+    let mut outer = Outer::default();
+    outer.set_holder(holder);
+    outer.list = vec![3];
+
+    // Holder keeps explicit presence for `num`, while Outer omits `num` and packs `list`
+    let bytes = encode(&outer);
+    assert_eq!(bytes, [0x0A, 0x02, 0x10, 0x00, 0x1A, 0x01, 0x03]);
+    assert_eq!(decode::<Outer>(&bytes), outer);
+}
+
+#[test]
+fn packed_and_unpacked_accepted() {
+    // Repeated fields are decoded regardless of whether they're packed
+    assert_eq!(decode::<Holder>(&[0x2A, 0x02, 0x01, 0x02]).list, [1, 2]);
+    assert_eq!(decode::<Sample>(&[0x18, 0x01, 0x18, 0x02]).list, [1, 2]);
+}
+
+#[test]
+fn open_enums() {
+    // Unknown enum values are kept for both proto2 and proto3 enums
+    let holder: Holder = decode(&[0x18, 0x07, 0x20, 0x09]);
+    assert_eq!(holder.level(), Some(&Level(7)));
+    assert_eq!(holder.mode(), Some(&Mode(9)));
+    assert_eq!(encode(&holder), [0x18, 0x07, 0x20, 0x09]);
+
+    let sample: Sample = decode(&[0x20, 0x07]);
+    assert_eq!(sample.level, Level(7));
+}
@@ -115,6 +115,16 @@ fn nested_msg() {
     }
 }
 
+#[test]
+fn nesting_depth() {
+    assert_eq!(proto::basic_::BasicTypes::MAX_NESTING_DEPTH, Some(1));
+    assert_eq!(
+        proto::nested_::Nested_::InnerMsg::MAX_NESTING_DEPTH,
+        Some(1)
+    );
+    assert_eq!(proto::nested_::Nested::MAX_NESTING_DEPTH, Some(2));
+}
+
 #[test]
 fn proto3() {
     let non_opt = proto::basic3_::NonOptional::default();
@@ -319,6 +329,43 @@ fn encode_enum() {
     assert_eq!(encoder.into_writer(), &[0x70, 0x82, 0x01]);
 }
 
+#[test]
+fn negative_enum() {
+    const NEG_ONE: [u8; 10] = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+    const MIN: [u8; 10] = [0x80, 0x80, 0x80, 0x80, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+    // Known and unknown negative values are sign-extended to 10-byte varints
+    for (value, varint) in [
+        (proto::basic_::Enum::Neg, NEG_ONE),
+        (proto::basic_::Enum(-2), {
+            let mut v = NEG_ONE;
+            v[0] = 0xFE;
+            v
+        }),
+        (proto::basic_::Enum(i32::MIN), MIN),
+    ] {
+        let mut expected = vec![0x70];
+        expected.extend_from_slice(&varint);
+
+        let mut basic = proto::basic_::BasicTypes::default();
+        basic.set_enumeration(value);
+        assert_eq!(basic.compute_size(), 11);
+        let mut encoder = PbEncoder::new(vec![]);
+        basic.encode(&mut encoder).unwrap();
+        assert_eq!(encoder.into_writer(), expected);
+
+        let mut decoded = proto::basic_::BasicTypes::default();
+        let mut decoder = PbDecoder::new(expected.as_slice());
+        decoded.decode(&mut decoder, expected.len()).unwrap();
+        assert_eq!(decoded.enumeration(), Some(&value));
+    }
+
+    // Peers that truncate negative enums to 5-byte varints are still decoded correctly
+    let mut basic = proto::basic_::BasicTypes::default();
+    let mut decoder = PbDecoder::new([0x70, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F].as_slice());
+    basic.decode(&mut decoder, 6).unwrap();
+    assert_eq!(basic.enumeration(), Some(&proto::basic_::Enum::Neg));
+}
+
 #[test]
 fn decode_nested() {
     let mut nested = proto::nested_::Nested::default();
@@ -469,3 +516,56 @@ fn decode_errors() {
     );
     assert_eq!(decoder.bytes_read(), 4);
 }
+
+#[test]
+fn field_sizes() {
+    let mut nested = proto::nested_::Nested::default();
+    assert_eq!(nested.field_size_basic(), 0);
+    assert_eq!(nested.field_size_inner(), 0);
+
+    nested.set_basic(proto::basic_::BasicTypes::default());
+    nested.basic.set_int32_num(14);
+    nested.basic.set_flt(1.0);
+    assert_eq!(nested.basic.field_size_int32_num(), 2);
+    assert_eq!(nested.basic.field_size_flt(), 5);
+    assert_eq!(nested.basic.field_size_dbl(), 0);
+    assert_eq!(nested.field_size_basic(), 9);
+
+    nested.inner = Some(proto::nested_::Nested_::Inner::Scalar(false));
+    assert_eq!(nested.field_size_inner(), 2);
+    assert_eq!(
+        nested.compute_size(),
+        nested.field_size_basic() + nested.field_size_inner()
+    );
+
+    let mut non_opt = proto::basic3_::NonOptional::default();
+    assert_eq!(non_opt.field_size_non_opt(), 0);
+    non_opt.non_opt = 150;
+    assert_eq!(non_opt.field_size_non_opt(), 3);
+}
+
+#[test]
+fn is_default() {
+    let mut nested = proto::nested_::Nested::default();
+    assert!(nested.is_default());
+    // Setting a submessage makes it present, even if the submessage itself is default
+    nested.set_basic(proto::basic_::BasicTypes::default());
+    assert!(nested.basic.is_default());
+    assert!(!nested.is_default());
+    nested.clear_basic();
+    nested.inner = Some(proto::nested_::Nested_::Inner::Scalar(false));
+    assert!(!nested.is_default());
+
+    let mut basic = proto::basic_::BasicTypes::default();
+    basic.set_int32_num(0);
+    assert!(!basic.is_default());
+
+    assert!(proto::basic3_::ZST::default().is_default());
+    let mut non_opt = proto::basic3_::NonOptional::default();
+    assert!(non_opt.is_default());
+    non_opt.non_opt = 1;
+    assert!(!non_opt.is_default());
+    non_opt.non_opt = 0;
+    assert!(non_opt.is_default());
+    assert_eq!(non_opt.compute_size(), 0);
+}
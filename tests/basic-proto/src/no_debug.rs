@@ -0,0 +1,42 @@
+use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/no_debug.rs"));
+}
+
+// Only one impl applies to types without `Debug`. If the type implements `Debug`, the call to
+// `check` becomes ambiguous and fails to compile.
+trait AmbiguousIfDebug<A> {
+    fn check() {}
+}
+impl<T: ?Sized> AmbiguousIfDebug<()> for T {}
+impl<T: ?Sized + core::fmt::Debug> AmbiguousIfDebug<u8> for T {}
+
+#[test]
+fn no_debug_impls() {
+    <proto::basic_::Enum as AmbiguousIfDebug<_>>::check();
+    <proto::basic_::BasicTypes as AmbiguousIfDebug<_>>::check();
+    <proto::basic_::BasicTypes_::_Hazzer as AmbiguousIfDebug<_>>::check();
+    <proto::nested_::Nested as AmbiguousIfDebug<_>>::check();
+    <proto::nested_::Nested_::Inner as AmbiguousIfDebug<_>>::check();
+    <proto::nested_::Nested_::InnerEnum as AmbiguousIfDebug<_>>::check();
+}
+
+#[test]
+fn round_trip() {
+    let mut nested = proto::nested_::Nested::default();
+    nested.set_basic(proto::basic_::BasicTypes::default());
+    nested.basic.set_int32_num(-3);
+    nested.inner = Some(proto::nested_::Nested_::Inner::Scalar(true));
+
+    let mut encoder = PbEncoder::new(vec![]);
+    nested.encode(&mut encoder).unwrap();
+    let mut decoder = PbDecoder::new(encoder.as_writer().as_slice());
+    let mut decoded = proto::nested_::Nested::default();
+    decoded
+        .decode(&mut decoder, encoder.as_writer().len())
+        .unwrap();
+    assert!(decoded == nested);
+}
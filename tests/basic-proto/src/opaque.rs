@@ -0,0 +1,67 @@
+use micropb::{container::Opaque, MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/opaque.rs"));
+}
+
+use proto::{basic_, nested_};
+
+#[test]
+fn opaque_types() {
+    let nested = nested_::Nested::default();
+    let _: Opaque<Vec<u8>> = nested.basic;
+    let _: Option<&Opaque<Vec<u8>>> = nested.basic();
+    if let Some(nested_::Nested_::Inner::InnerMsg(msg)) = nested.inner {
+        let _: Opaque<micropb::heapless::Vec<u8, 4>> = msg;
+    }
+}
+
+#[test]
+fn opaque_decode() {
+    // Payload is stored as-is without being decoded
+    let bytes = [0x0A, 0x03, 0x08, 0x96, 0x01, 0x28, 0x01];
+    let mut nested = nested_::Nested::default();
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    nested.decode(&mut decoder, bytes.len()).unwrap();
+    assert_eq!(nested.inner, Some(nested_::Nested_::Inner::Scalar(true)));
+    let body = nested.basic().unwrap();
+    assert_eq!(body.as_ref(), [0x08, 0x96, 0x01]);
+
+    let basic: basic_::BasicTypes = body.decode_as().unwrap();
+    assert_eq!(basic.int32_num(), Some(&150));
+
+    let mut encoder = PbEncoder::new(vec![]);
+    nested.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), bytes);
+}
+
+#[test]
+fn opaque_decode_as_error() {
+    let body = Opaque(vec![0x08]);
+    assert_eq!(
+        body.decode_as::<basic_::BasicTypes>(),
+        Err(micropb::DecodeError::UnexpectedEof)
+    );
+}
+
+#[test]
+fn opaque_set_message() {
+    let mut basic = basic_::BasicTypes::default();
+    basic.set_boolean(true);
+    let mut nested = nested_::Nested::default();
+    let mut body = Opaque::default();
+    body.set_message(&basic).unwrap();
+    nested.set_basic(body);
+    assert_eq!(nested.compute_size(), 4);
+
+    let mut encoder = PbEncoder::new(vec![]);
+    nested.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), [0x0A, 0x02, 0x58, 0x01]);
+
+    // Payload doesn't fit into the fixed-capacity container
+    let mut inner = Opaque(micropb::heapless::Vec::<u8, 4>::new());
+    basic.set_int32_num(-1);
+    assert!(inner.set_message(&basic).is_err());
+}
@@ -0,0 +1,69 @@
+use micropb::{DecodeError, MessageDecode, MessageEncode, PbDecoder, PbEncoder, PbEnum, PbRead};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/packed_enum.rs"));
+}
+
+use proto::*;
+
+fn decode(bytes: &[u8]) -> Result<(Palette, usize), DecodeError<<&[u8] as PbRead>::Error>> {
+    let mut palette = Palette::default();
+    let mut decoder = PbDecoder::new(bytes);
+    palette.decode(&mut decoder, bytes.len())?;
+    Ok((palette, decoder.stats().unknown_enums))
+}
+
+#[test]
+fn known_values() {
+    assert!(Color::Red.is_known());
+    assert!(Color::Blue.is_known());
+    assert!(!Color(3).is_known());
+    assert_eq!(Color::from_i32(-1), Color(-1));
+    assert_eq!(Color::Green.to_i32(), 1);
+}
+
+#[test]
+fn encode_packed() {
+    let mut palette = Palette::default();
+    palette.keep.push(Color::Green).unwrap();
+    palette.keep.push(Color(-1)).unwrap();
+    palette.drop.push(Color::Blue).unwrap();
+
+    let mut encoder = PbEncoder::new(vec![]);
+    palette.encode(&mut encoder).unwrap();
+    let bytes = encoder.into_writer();
+    assert_eq!(
+        bytes,
+        [
+            0x0A, 0x0B, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            0x01, // keep
+            0x12, 0x01, 0x02, // drop
+        ]
+    );
+    assert_eq!(palette.compute_size(), bytes.len());
+    assert_eq!(decode(&bytes).unwrap(), (palette, 0));
+}
+
+#[test]
+fn unknown_policies() {
+    // Unknown values are kept by default
+    let (palette, unknown) = decode(&[0x0A, 0x03, 0x01, 0x07, 0x02]).unwrap();
+    assert_eq!(palette.keep, [Color::Green, Color(7), Color::Blue]);
+    assert_eq!(unknown, 0);
+
+    // Packed and unpacked elements are both filtered
+    let (palette, unknown) =
+        decode(&[0x12, 0x03, 0x01, 0x07, 0x02, 0x10, 0x09, 0x10, 0x00]).unwrap();
+    assert_eq!(palette.drop, [Color::Green, Color::Blue, Color::Red]);
+    assert_eq!(unknown, 2);
+
+    let (palette, _) = decode(&[0x1A, 0x02, 0x00, 0x01]).unwrap();
+    assert_eq!(palette.reject, [Color::Red, Color::Green]);
+    assert_eq!(
+        decode(&[0x1A, 0x02, 0x00, 0x03]),
+        Err(DecodeError::UnknownEnum)
+    );
+    assert_eq!(decode(&[0x18, 0x05]), Err(DecodeError::UnknownEnum));
+}
@@ -0,0 +1,40 @@
+use micropb::callback::RawField;
+
+use crate::{decode, encode};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/raw_field.rs"));
+}
+
+use proto::{basic_, nested_};
+
+#[test]
+fn raw_field_passthrough() {
+    let _: RawField<Vec<u8>> = nested_::Nested::default().basic;
+
+    // The submessage has a non-canonical length prefix, which is kept as-is
+    let bytes = [0x0A, 0x82, 0x00, 0x08, 0x01, 0x28, 0x01];
+    let nested: nested_::Nested = decode(&bytes);
+    assert_eq!(nested.basic.as_bytes(), [0x0A, 0x82, 0x00, 0x08, 0x01]);
+    assert_eq!(nested.inner, Some(nested_::Nested_::Inner::Scalar(true)));
+    assert_eq!(encode(&nested), bytes);
+}
+
+#[test]
+fn raw_unknown_fields() {
+    // Unknown fields 20 and 30 are preserved in their original order
+    let bytes = [0xA0, 0x01, 0x05, 0x08, 0x03, 0xF2, 0x01, 0x01, 0xFF];
+    let basic: basic_::BasicTypes = decode(&bytes);
+    assert_eq!(basic.int32_num(), Some(&3));
+    assert_eq!(
+        basic._unknown.as_bytes(),
+        [0xA0, 0x01, 0x05, 0xF2, 0x01, 0x01, 0xFF]
+    );
+    // Known fields are encoded before unknown fields
+    assert_eq!(
+        encode(&basic),
+        [0x08, 0x03, 0xA0, 0x01, 0x05, 0xF2, 0x01, 0x01, 0xFF]
+    );
+}
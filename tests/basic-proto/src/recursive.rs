@@ -1,3 +1,4 @@
+use micropb::{DecodeError, MessageDecode, PbDecoder};
 use proto::Recursive;
 
 mod proto {
@@ -21,3 +22,69 @@ fn recursive_types() {
         }
     }
 }
+
+#[test]
+fn nesting_depth() {
+    assert_eq!(Recursive::MAX_NESTING_DEPTH, None);
+}
+
+/// Encode `depth` levels of messages nested in the `recursive` field
+fn nested(depth: usize) -> Vec<u8> {
+    let mut buf = vec![];
+    for _ in 0..depth {
+        buf.splice(0..0, [0x0A, buf.len() as u8]);
+    }
+    buf
+}
+
+#[test]
+fn max_depth() {
+    let buf = nested(5);
+    let mut decoder = PbDecoder::new(buf.as_slice());
+    decoder.set_max_depth(Some(5));
+    assert_eq!(decoder.max_depth(), Some(5));
+    let mut msg = Recursive::default();
+    msg.decode(&mut decoder, buf.len()).unwrap();
+    assert!(msg.recursive.unwrap().recursive.is_some());
+
+    let mut decoder = PbDecoder::new(buf.as_slice());
+    decoder.set_max_depth(Some(4));
+    assert_eq!(
+        Recursive::default().decode(&mut decoder, buf.len()),
+        Err(DecodeError::DepthLimit)
+    );
+
+    // The depth is tracked across messages, so the decoder can be reused
+    let buf = [nested(2), nested(2)].concat();
+    let mut decoder = PbDecoder::new(buf.as_slice());
+    decoder.set_max_depth(Some(2));
+    let mut msg = Recursive::default();
+    msg.decode(&mut decoder, buf.len()).unwrap();
+
+    // Length-delimited top-level messages count as a level
+    let buf = [vec![6], nested(3)].concat();
+    let mut decoder = PbDecoder::new(buf.as_slice());
+    decoder.set_max_depth(Some(3));
+    assert_eq!(
+        Recursive::default().decode_len_delimited(&mut decoder),
+        Err(DecodeError::DepthLimit)
+    );
+}
+
+#[test]
+fn max_depth_oneof() {
+    // Messages nested in the `rec` oneof variant
+    let buf = [0x12, 0x04, 0x12, 0x02, 0x18, 0x01];
+    let mut decoder = PbDecoder::new(buf.as_slice());
+    decoder.set_max_depth(Some(1));
+    assert_eq!(
+        Recursive::default().decode(&mut decoder, buf.len()),
+        Err(DecodeError::DepthLimit)
+    );
+
+    let mut decoder = PbDecoder::new(buf.as_slice());
+    decoder.set_max_depth(Some(2));
+    Recursive::default()
+        .decode(&mut decoder, buf.len())
+        .unwrap();
+}
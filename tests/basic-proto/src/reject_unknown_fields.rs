@@ -0,0 +1,54 @@
+use micropb::{DecodeError, MessageDecode, PbDecoder};
+
+use crate::encode;
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/reject_unknown_fields.rs"));
+}
+
+use proto::unknown_fields_::*;
+
+fn decode_v1(bytes: &[u8]) -> Result<ReadingV1, DecodeError<<&[u8] as micropb::PbRead>::Error>> {
+    let mut msg = ReadingV1::default();
+    msg.decode(&mut PbDecoder::new(bytes), bytes.len())?;
+    Ok(msg)
+}
+
+#[test]
+fn known_fields() {
+    let bytes = encode(&Reading {
+        id: 3,
+        ..Default::default()
+    });
+    assert_eq!(decode_v1(&bytes).unwrap().id, 3);
+}
+
+#[test]
+fn reject_newer_fields() {
+    let reading = Reading {
+        id: 3,
+        sensor: micropb::heapless::String::try_from("temp").unwrap(),
+        ..Default::default()
+    };
+    assert_eq!(
+        decode_v1(&encode(&reading)),
+        Err(DecodeError::UnknownField(2))
+    );
+    // Field 9 isn't in either version of the message
+    assert_eq!(
+        decode_v1(&[0x08, 0x01, 0x48, 0x00]),
+        Err(DecodeError::UnknownField(9))
+    );
+}
+
+#[test]
+fn other_messages_skip_unknown_fields() {
+    // Reading doesn't reject unknown fields, so field 9 is skipped
+    let mut msg = Reading::default();
+    let bytes = [0x08, 0x01, 0x48, 0x00];
+    msg.decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len())
+        .unwrap();
+    assert_eq!(msg.id, 1);
+}
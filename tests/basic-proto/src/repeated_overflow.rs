@@ -0,0 +1,73 @@
+use micropb::{DecodeError, MessageDecode, PbDecoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/repeated_overflow.rs"));
+}
+
+/// Decode the message and return the number of dropped elements
+fn decode<M: MessageDecode>(msg: &mut M, bytes: &[u8]) -> usize {
+    let mut decoder = PbDecoder::new(bytes);
+    msg.decode(&mut decoder, bytes.len()).unwrap();
+    decoder.stats().dropped_elements
+}
+
+#[test]
+fn drop_oldest() {
+    let mut list = proto::NumList::default();
+    // Unpacked elements
+    assert_eq!(decode(&mut list, &[0x08, 0x01, 0x08, 0x02, 0x08, 0x03]), 1);
+    assert_eq!(list.list, [2, 3]);
+    // Packed elements
+    assert_eq!(decode(&mut list, &[0x0A, 0x03, 0x04, 0x05, 0x06]), 3);
+    assert_eq!(list.list, [5, 6]);
+
+    // Overflowing by an odd number of elements still keeps the most recent ones in order
+    let mut list = proto::NumList::default();
+    let bytes = [0x08, 0x01, 0x08, 0x02, 0x08, 0x03, 0x08, 0x04, 0x08, 0x05];
+    assert_eq!(decode(&mut list, &bytes), 3);
+    assert_eq!(list.list, [4, 5]);
+    // Packed and unpacked elements of the same field
+    let mut list = proto::NumList::default();
+    let bytes = [
+        0x08, 0x01, 0x08, 0x02, 0x08, 0x03, 0x0A, 0x01, 0x04, 0x08, 0x07,
+    ];
+    assert_eq!(decode(&mut list, &bytes), 3);
+    assert_eq!(list.list, [4, 7]);
+
+    let mut list = proto::StrList::default();
+    let dropped = decode(
+        &mut list,
+        &[0x0A, 0x01, b'a', 0x0A, 0x01, b'b', 0x0A, 0x01, b'c'],
+    );
+    assert_eq!(dropped, 1);
+    assert_eq!(list.list, ["b", "c"]);
+}
+
+#[test]
+fn drop_newest() {
+    let mut list = proto::FixedList::default();
+    let dropped = decode(&mut list, &[0x0A, 0x0C, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0]);
+    assert_eq!(dropped, 1);
+    assert_eq!(list.list, [1, 2]);
+}
+
+#[test]
+fn overflow_error() {
+    // Fields without an overflow policy still fail
+    let mut list = proto::EnumList::default();
+    let bytes = [0x0A, 0x03, 0x00, 0x00, 0x00];
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    assert_eq!(
+        list.decode(&mut decoder, bytes.len()),
+        Err(DecodeError::Capacity)
+    );
+
+    // Ignored capacity errors are also counted
+    let mut list = proto::EnumList::default();
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    decoder.ignore_repeated_cap_err = true;
+    list.decode(&mut decoder, bytes.len()).unwrap();
+    assert_eq!(decoder.stats().dropped_elements, 1);
+}
@@ -0,0 +1,109 @@
+use micropb::{
+    DecodeError, DecodeStatus, MessageDecode, MessageEncode, PbDecoder, PbEncoder,
+    ResumablePbDecoder,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/required_fields.rs"));
+}
+
+use proto::required_::*;
+
+fn decode<M: MessageDecode + Default>(
+    bytes: &[u8],
+) -> Result<M, DecodeError<<&[u8] as micropb::PbRead>::Error>> {
+    let mut msg = M::default();
+    msg.merge_from_slice(bytes)?;
+    Ok(msg)
+}
+
+#[test]
+fn is_initialized() {
+    let mut settings = Settings::default();
+    assert!(!settings.is_initialized());
+    assert_eq!(settings.missing_required_field(), Some(1));
+
+    settings.set_version(2);
+    assert_eq!(settings.missing_required_field(), Some(3));
+    settings.set_inner(Inner::default());
+    assert!(settings.is_initialized());
+
+    // Only the message's own fields are checked
+    assert!(!settings.inner.as_ref().unwrap().is_initialized());
+    settings.clear_version();
+    assert_eq!(settings.missing_required_field(), Some(1));
+}
+
+#[test]
+fn decode_enforces_required() {
+    let mut settings = Settings::default();
+    settings.set_version(2);
+    let mut inner = Inner::default();
+    inner.set_id(-1);
+    settings.set_inner(inner);
+    let mut encoder = PbEncoder::new(vec![]);
+    settings.encode(&mut encoder).unwrap();
+    let bytes = encoder.into_writer();
+    assert_eq!(decode::<Settings>(&bytes), Ok(settings));
+
+    // Missing inner message
+    assert_eq!(
+        decode::<Settings>(&[0x08, 0x02]),
+        Err(DecodeError::MissingRequired(3))
+    );
+    // Nested message is missing its required field
+    assert_eq!(
+        decode::<Settings>(&[0x08, 0x02, 0x1A, 0x00]),
+        Err(DecodeError::MissingRequired(1))
+    );
+}
+
+#[test]
+fn decode_not_enforced() {
+    let loose: Loose = decode(&[]).unwrap();
+    assert!(!loose.is_initialized());
+}
+
+#[test]
+fn partial_decode_not_checked() {
+    // `decode` may only see some of the fields, so it doesn't check them
+    let mut settings = Settings::default();
+    settings
+        .decode(&mut PbDecoder::new([0x08, 0x02].as_slice()), 2)
+        .unwrap();
+    assert_eq!(
+        settings.check_required::<()>(),
+        Err(DecodeError::MissingRequired(3))
+    );
+
+    // Neither does applying a patch
+    settings.apply_patch(&[0x08, 0x03]).unwrap();
+    assert_eq!(settings.version, 3);
+}
+
+#[test]
+fn resumable_decode() {
+    // Settings with version 2 and an inner message with ID 1
+    let data = [0x06, 0x08, 0x02, 0x1A, 0x02, 0x08, 0x01];
+    for chunk_len in 1..=data.len() {
+        let mut scratch = [0; 4];
+        let mut decoder = ResumablePbDecoder::new(&mut scratch);
+        let mut settings = Settings::default();
+        let mut status = DecodeStatus::NeedMoreData;
+        for chunk in data.chunks(chunk_len) {
+            status = decoder.feed(&mut settings, chunk).unwrap();
+        }
+        assert!(matches!(status, DecodeStatus::Done { .. }));
+        assert!(settings.is_initialized());
+    }
+
+    // Checked once the message is complete
+    let mut scratch = [0; 4];
+    let mut decoder = ResumablePbDecoder::new(&mut scratch);
+    assert_eq!(
+        decoder.feed(&mut Settings::default(), &[0x02, 0x08, 0x02]),
+        Err(DecodeError::MissingRequired(3))
+    );
+}
@@ -0,0 +1,7 @@
+// The generated code contains a round-trip test for each message, which runs as part of this
+// module.
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/roundtrip_tests.rs"));
+}
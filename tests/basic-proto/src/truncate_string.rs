@@ -0,0 +1,34 @@
+use micropb::{DecodeError, MessageDecode, PbDecoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/truncate_string.rs"));
+}
+
+#[test]
+fn truncate_string() {
+    let mut data = proto::Data::default();
+    let mut decoder = PbDecoder::new([0x0A, 0x05, b'a', b'b', b'c', b'd', b'e'].as_slice());
+    data.decode(&mut decoder, 7).unwrap();
+    assert_eq!(data.s().unwrap(), "abc");
+
+    // Multi-byte characters are not split
+    let mut decoder = PbDecoder::new([0x0A, 0x04, b'a', b'b', 0xD0, 0x97].as_slice());
+    data.decode(&mut decoder, 6).unwrap();
+    assert_eq!(data.s().unwrap(), "ab");
+    assert_eq!(decoder.stats().truncated_strings, 1);
+
+    // Bytes fields aren't affected
+    let mut decoder = PbDecoder::new([0x12, 0x06, 1, 2, 3, 4, 5, 6].as_slice());
+    assert_eq!(data.decode(&mut decoder, 8), Err(DecodeError::Capacity));
+}
+
+#[test]
+fn truncate_repeated_string() {
+    let mut list = proto::StrList::default();
+    let bytes = [0x0A, 0x03, b'a', b'b', b'c', 0x0A, 0x01, b'd'];
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    list.decode(&mut decoder, bytes.len()).unwrap();
+    assert_eq!(list.list, ["ab", "d"]);
+}
@@ -0,0 +1,43 @@
+use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/unit_struct.rs"));
+}
+
+use proto::basic3_::{Optional, ZST};
+
+#[test]
+fn unit_struct_size() {
+    let _: ZST = ZST;
+    assert!(ZST.is_default());
+    assert_eq!(std::mem::size_of::<ZST>(), 0);
+    assert_eq!(std::mem::size_of::<Option<ZST>>(), 1);
+    // Both ZST fields only take up their presence bits in the hazzer
+    assert_eq!(
+        std::mem::size_of::<Optional>(),
+        std::mem::size_of::<(i32, u8)>()
+    );
+}
+
+#[test]
+fn unit_struct_decode() {
+    let mut msg = Optional::default();
+    // Contents of the ZST message aren't parsed, so even an invalid field is skipped
+    let mut decoder = PbDecoder::new([0x12, 0x02, 0x00, 0x00, 0x08, 0x01].as_slice());
+    msg.decode(&mut decoder, 6).unwrap();
+    assert_eq!(msg.zst_opt(), Some(&ZST));
+    assert_eq!(msg.zst(), None);
+    assert_eq!(msg.opt(), Some(&1));
+}
+
+#[test]
+fn unit_struct_encode() {
+    let mut msg = Optional::default();
+    msg.set_zst(ZST);
+    assert_eq!(msg.compute_size(), 2);
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), [0x1A, 0x00]);
+}
@@ -0,0 +1,70 @@
+use micropb::{DecodeError, MessageDecode, PbDecoder};
+
+use crate::encode;
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/unknown_fields.rs"));
+}
+
+use proto::unknown_fields_::*;
+
+fn reading() -> Reading {
+    let mut reading = Reading {
+        id: 7,
+        sensor: micropb::heapless::String::try_from("temp").unwrap(),
+        ..Default::default()
+    };
+    reading.samples.extend_from_slice(&[-1, 20, 300]).unwrap();
+    reading
+}
+
+#[test]
+fn forward_newer_fields() {
+    let reading = reading();
+    let bytes = encode(&reading);
+
+    let mut old = ReadingV1::default();
+    old.decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len())
+        .unwrap();
+    assert_eq!(old.id, 7);
+    assert!(!old._unknown.is_empty());
+
+    // The older message can be modified and forwarded without losing the newer fields
+    old.id = 8;
+    let forwarded = encode(&old);
+    let mut decoded = Reading::default();
+    decoded
+        .decode(&mut PbDecoder::new(forwarded.as_slice()), forwarded.len())
+        .unwrap();
+    assert_eq!(decoded, Reading { id: 8, ..reading });
+}
+
+#[test]
+fn unknown_overflow() {
+    let mut reading = reading();
+    reading.sensor = micropb::heapless::String::try_from("humidity").unwrap();
+    let bytes = encode(&reading);
+
+    let mut old = ReadingV1::default();
+    assert_eq!(
+        old.decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len()),
+        Err(DecodeError::Capacity)
+    );
+
+    // Fields that don't fit are dropped whole, so the rest can still be forwarded
+    let mut old = ReadingV1::default();
+    let mut decoder = PbDecoder::new(bytes.as_slice());
+    decoder.ignore_repeated_cap_err = true;
+    old.decode(&mut decoder, bytes.len()).unwrap();
+    assert_eq!(decoder.stats().dropped_elements, 1);
+
+    let forwarded = encode(&old);
+    let mut decoded = Reading::default();
+    decoded
+        .decode(&mut PbDecoder::new(forwarded.as_slice()), forwarded.len())
+        .unwrap();
+    assert_eq!(decoded.sensor, "humidity");
+    assert!(decoded.samples.is_empty());
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+
+use micropb::{
+    value::{Scalar, ValueRef},
+    MessageEncode,
+};
+
+use crate::{decode, encode};
+
+mod alloc_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/well_known_struct_alloc.rs"));
+}
+
+mod value_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/well_known_struct_value.rs"));
+}
+
+use alloc_proto::google_::protobuf_::{ListValue, NullValue, Struct, Value, Value_::Kind};
+
+fn value(kind: Kind) -> Value {
+    Value { kind: Some(kind) }
+}
+
+fn alloc_config() -> alloc_proto::wkt_::Config {
+    let mut config = alloc_proto::wkt_::Config {
+        name: "sensor".to_owned(),
+        _has: alloc_proto::wkt_::Config_::_Hazzer::default()
+            .init_settings()
+            .init_extra(),
+        ..Default::default()
+    };
+    // Keys are inserted in sorted order, since the generated map type encodes its entries sorted
+    config.settings = Struct {
+        fields: BTreeMap::from([
+            ("enabled".to_owned(), value(Kind::BoolValue(true))),
+            (
+                "limits".to_owned(),
+                value(Kind::StructValue(Struct {
+                    fields: BTreeMap::from([
+                        ("max".to_owned(), value(Kind::NumberValue(30.5))),
+                        ("min".to_owned(), value(Kind::NumberValue(-2.0))),
+                    ]),
+                })),
+            ),
+            (
+                "offset".to_owned(),
+                value(Kind::NullValue(NullValue::NullValue)),
+            ),
+            (
+                "targets".to_owned(),
+                value(Kind::ListValue(ListValue {
+                    values: vec![
+                        value(Kind::StringValue("a".to_owned())),
+                        value(Kind::ListValue(ListValue::default())),
+                    ],
+                })),
+            ),
+        ]),
+    };
+    config.extra = value(Kind::StringValue("extra".to_owned()));
+    config
+}
+
+fn value_config() -> value_proto::wkt_::Config {
+    let mut config = value_proto::wkt_::Config {
+        _has: value_proto::wkt_::Config_::_Hazzer::default()
+            .init_settings()
+            .init_extra(),
+        ..Default::default()
+    };
+    config.name.push_str("sensor").unwrap();
+    let mut builder = config.settings.builder();
+    builder.insert("enabled", Scalar::Bool(true)).unwrap();
+    builder
+        .insert_struct("limits", |limits| {
+            limits.insert("max", Scalar::Number(30.5))?;
+            limits.insert("min", Scalar::Number(-2.0))
+        })
+        .unwrap();
+    builder.insert("offset", Scalar::Null).unwrap();
+    builder
+        .insert_list("targets", |targets| {
+            targets.push(Scalar::String("a"))?;
+            targets.push_list(|_| Ok(()))
+        })
+        .unwrap();
+    config.extra.set(Scalar::String("extra")).unwrap();
+    config
+}
+
+#[test]
+fn same_encoding() {
+    let alloc_config = alloc_config();
+    let value_config = value_config();
+    let bytes = encode(&alloc_config);
+    assert!(bytes.len() > 100);
+    assert_eq!(encode(&value_config), bytes);
+    assert_eq!(alloc_config.compute_size(), bytes.len());
+    assert_eq!(value_config.compute_size(), bytes.len());
+}
+
+#[test]
+fn decode_from_generated() {
+    let config: value_proto::wkt_::Config = decode(&encode(&alloc_config()));
+    assert_eq!(config, value_config());
+
+    let settings = config.settings.fields();
+    assert_eq!(settings.len(), 4);
+    assert_eq!(settings.get("enabled"), Some(ValueRef::Bool(true)));
+    assert_eq!(settings.get("offset"), Some(ValueRef::Null));
+    let Some(ValueRef::Struct(limits)) = settings.get("limits") else {
+        panic!("limits should be a struct");
+    };
+    assert_eq!(limits.get("min"), Some(ValueRef::Number(-2.0)));
+    let Some(ValueRef::List(targets)) = settings.get("targets") else {
+        panic!("targets should be a list");
+    };
+    assert_eq!(targets.get(0), Some(ValueRef::String("a")));
+    assert!(matches!(targets.get(1), Some(ValueRef::List(l)) if l.is_empty()));
+    assert_eq!(config.extra.get(), ValueRef::String("extra"));
+}
+
+#[test]
+fn decode_into_generated() {
+    let config: alloc_proto::wkt_::Config = decode(&encode(&value_config()));
+    assert_eq!(config, alloc_config());
+}
@@ -0,0 +1,96 @@
+use core::time::Duration;
+
+use micropb::{MessageDecode, PbDecoder};
+
+use crate::{decode, encode};
+
+mod alloc_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/well_known_wrappers_alloc.rs"));
+}
+
+mod std_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/well_known_wrappers_std.rs"));
+}
+
+use alloc_proto::google_::protobuf_;
+
+#[test]
+fn substituted_types() {
+    let reading = std_proto::wkt_::Reading::default();
+    let _: Option<f64> = reading.dbl;
+    let _: Option<f32> = reading.flt;
+    let _: Option<i64> = reading.int64_num;
+    let _: Option<u64> = reading.uint64_num;
+    let _: Option<i32> = reading.int32_num;
+    let _: Option<u32> = reading.uint32_num;
+    let _: Option<bool> = reading.boolean;
+    let _: Option<String> = reading.st;
+    let _: Option<micropb::heapless::Vec<u8, 8>> = reading.bt;
+    let _: Option<Duration> = reading.elapsed;
+    let _: Option<Duration> = reading.time;
+}
+
+#[test]
+fn wire_compat() {
+    let mut reference = alloc_proto::wkt_::Reading::default();
+    reference.set_dbl(protobuf_::DoubleValue { value: 1.5 });
+    reference.set_flt(protobuf_::FloatValue { value: -2.0 });
+    reference.set_int64_num(protobuf_::Int64Value { value: -3 });
+    reference.set_uint64_num(protobuf_::UInt64Value { value: 4 });
+    // Zero values inside a present wrapper
+    reference.set_int32_num(protobuf_::Int32Value { value: 0 });
+    reference.set_uint32_num(protobuf_::UInt32Value { value: 6 });
+    reference.set_boolean(protobuf_::BoolValue { value: true });
+    reference.set_st(protobuf_::StringValue {
+        value: "volts".to_owned(),
+    });
+    reference.set_bt(protobuf_::BytesValue { value: vec![1, 2] });
+    reference.set_elapsed(protobuf_::Duration {
+        seconds: 90,
+        nanos: 500,
+    });
+    reference.set_time(protobuf_::Timestamp {
+        seconds: 1_700_000_000,
+        nanos: 0,
+    });
+    let bytes = encode(&reference);
+
+    let reading: std_proto::wkt_::Reading = decode(&bytes);
+    assert_eq!(reading.dbl, Some(1.5));
+    assert_eq!(reading.flt, Some(-2.0));
+    assert_eq!(reading.int64_num, Some(-3));
+    assert_eq!(reading.uint64_num, Some(4));
+    assert_eq!(reading.int32_num, Some(0));
+    assert_eq!(reading.uint32_num, Some(6));
+    assert_eq!(reading.boolean, Some(true));
+    assert_eq!(reading.st.as_deref(), Some("volts"));
+    assert_eq!(reading.bt.as_deref(), Some([1, 2].as_slice()));
+    assert_eq!(reading.elapsed, Some(Duration::new(90, 500)));
+    assert_eq!(reading.time, Some(Duration::from_secs(1_700_000_000)));
+    assert_eq!(encode(&reading), bytes);
+
+    // Unset wrappers stay unset
+    let empty: std_proto::wkt_::Reading = decode(&[]);
+    assert_eq!(empty.int32_num, None);
+    assert!(encode(&empty).is_empty());
+}
+
+#[test]
+fn negative_duration() {
+    let mut reference = alloc_proto::wkt_::Reading::default();
+    reference.set_elapsed(protobuf_::Duration {
+        seconds: -1,
+        nanos: 0,
+    });
+    let bytes = encode(&reference);
+
+    let mut reading = std_proto::wkt_::Reading::default();
+    assert_eq!(
+        reading.decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len()),
+        Err(micropb::DecodeError::OutOfRange)
+    );
+}
@@ -0,0 +1,25 @@
+use micropb_gen::{Config, Generator};
+
+fn main() {
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+
+    // prost output for the same proto, included under `crate::prost_pb`
+    prost_build::Config::new()
+        .include_file("prost_pb.rs")
+        .compile_protos(&["proto/sample.proto"], &["proto"])
+        .unwrap();
+
+    let mut gen = Generator::new();
+    gen.use_container_alloc()
+        // Fixed-capacity field, so that conversions from prost can fail
+        .configure(
+            ".demo.v1.Sample.tags",
+            Config::new()
+                .vec_type("::micropb::heapless::Vec")
+                .max_len(2),
+        )
+        .prost_conversions("crate::prost_pb")
+        .add_protoc_arg("-Iproto")
+        .compile_protos(&["sample.proto"], out_dir + "/micropb.rs")
+        .unwrap();
+}
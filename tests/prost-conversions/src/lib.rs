@@ -0,0 +1,102 @@
+// The generated conversions use `::alloc`, which std crates need to declare
+extern crate alloc;
+
+mod prost_pb {
+    #![allow(clippy::all)]
+    include!(concat!(env!("OUT_DIR"), "/prost_pb.rs"));
+}
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/micropb.rs"));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::prost_pb::demo::v1 as prost_v1;
+    use crate::proto::demo_::v1_ as micropb_v1;
+
+    fn prost_sample() -> prost_v1::Sample {
+        prost_v1::Sample {
+            id: 7,
+            name: "probe".to_owned(),
+            raw: vec![0xDE, 0xAD],
+            readings: vec![
+                prost_v1::sample::Reading {
+                    value: -40,
+                    unit: prost_v1::Unit::Celsius as i32,
+                },
+                prost_v1::sample::Reading {
+                    value: 300,
+                    unit: prost_v1::Unit::Kelvin as i32,
+                },
+            ],
+            labels: HashMap::from([(1, "room".to_owned()), (2, "rack".to_owned())]),
+            source: Some(prost_v1::sample::Source::Address("10.0.0.2".to_owned())),
+            scale: Some(0.5),
+            tags: vec![3, 4],
+        }
+    }
+
+    #[test]
+    fn from_prost() {
+        let sample = prost_sample();
+        let converted = micropb_v1::Sample::try_from(&sample).unwrap();
+        assert_eq!(converted.id, 7);
+        assert_eq!(converted.name, "probe");
+        assert_eq!(converted.raw, [0xDE, 0xAD]);
+        assert_eq!(converted.readings.len(), 2);
+        assert_eq!(converted.readings[0].value, -40);
+        assert_eq!(converted.readings[1].unit, micropb_v1::Unit::Kelvin);
+        assert_eq!(converted.labels.get(&2).map(String::as_str), Some("rack"));
+        assert_eq!(
+            converted.source,
+            Some(micropb_v1::Sample_::Source::Address("10.0.0.2".to_owned()))
+        );
+        assert_eq!(converted.scale(), Some(&0.5));
+        assert_eq!(converted.tags, [3, 4]);
+
+        // Converting back gives the original message
+        assert_eq!(prost_v1::Sample::try_from(&converted).unwrap(), sample);
+    }
+
+    #[test]
+    fn from_micropb() {
+        let mut sample = micropb_v1::Sample {
+            id: 9,
+            name: "gateway".to_owned(),
+            ..Default::default()
+        };
+        sample.readings.push(micropb_v1::Sample_::Reading {
+            value: 12,
+            unit: micropb_v1::Unit::Celsius,
+        });
+        sample.labels.insert(5, "roof".to_owned());
+        sample.source = Some(micropb_v1::Sample_::Source::Channel(3));
+        sample.set_scale(2.0);
+
+        let converted = prost_v1::Sample::try_from(&sample).unwrap();
+        assert_eq!(converted.id, 9);
+        assert_eq!(converted.name, "gateway");
+        assert_eq!(converted.readings[0].value, 12);
+        assert_eq!(converted.labels[&5], "roof");
+        assert_eq!(converted.source, Some(prost_v1::sample::Source::Channel(3)));
+        assert_eq!(converted.scale, Some(2.0));
+
+        assert_eq!(micropb_v1::Sample::try_from(&converted).unwrap(), sample);
+    }
+
+    #[test]
+    fn capacity() {
+        // `tags` holds at most 2 elements on the micropb side
+        let mut sample = prost_sample();
+        sample.tags.push(5);
+        assert_eq!(
+            micropb_v1::Sample::try_from(&sample),
+            Err(micropb::DecodeError::Capacity)
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use std::{fmt::Write, fs, path::Path};
+
+use micropb_gen::{Config, Generator};
+
+const PROTOS: &[&str] = &[
+    "proto/basic.proto",
+    "proto/basic3.proto",
+    "proto/nested.proto",
+    "proto/collections.proto",
+    "proto/map.proto",
+    "proto/recursive.proto",
+];
+
+fn compile(mut generator: Generator, out_file: &str) {
+    generator.configure(".Recursive.recursive", Config::new().boxed(true));
+    generator.configure(".Recursive.of", Config::new().boxed(true));
+    generator
+        .add_protoc_arg("-I../basic-proto")
+        .compile_protos(PROTOS, std::env::var("OUT_DIR").unwrap() + out_file)
+        .unwrap();
+}
+
+fn alloc() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    compile(generator, "/alloc.rs");
+}
+
+fn fixed() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".", Config::new().max_len(4).max_bytes(8));
+    compile(generator, "/fixed.rs");
+}
+
+/// Convert a fully-qualified Protobuf message name, such as `nested.Nested.InnerMsg`, into the
+/// path of the generated type relative to the output module, such as `nested_::Nested_::InnerMsg`
+fn rust_path(msg_name: &str) -> String {
+    let (parents, name) = msg_name.rsplit_once('.').unwrap_or(("", msg_name));
+    parents
+        .split('.')
+        .filter(|p| !p.is_empty())
+        .map(|p| format!("{p}_::"))
+        .chain([name.to_owned()])
+        .collect()
+}
+
+fn test_name(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Generate a test case for each file in `corpus/<message name>/`
+fn corpus() {
+    let corpus_dir = Path::new("corpus").canonicalize().unwrap();
+    println!("cargo:rerun-if-changed={}", corpus_dir.display());
+
+    let mut dirs: Vec<_> = fs::read_dir(&corpus_dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.is_dir())
+        .collect();
+    dirs.sort();
+
+    let mut out = String::new();
+    for dir in dirs {
+        let msg_name = dir.file_name().unwrap().to_str().unwrap();
+        let mut files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.is_file())
+            .collect();
+        files.sort();
+
+        for file in files {
+            let case = file.file_stem().unwrap().to_str().unwrap();
+            writeln!(
+                out,
+                "corpus_test!({}_{}, {}, {:?});",
+                test_name(msg_name),
+                test_name(case),
+                rust_path(msg_name),
+                file.display(),
+            )
+            .unwrap();
+        }
+    }
+    fs::write(std::env::var("OUT_DIR").unwrap() + "/corpus.rs", out).unwrap();
+}
+
+fn main() {
+    alloc();
+    fixed();
+    corpus();
+}
@@ -0,0 +1,78 @@
+//! Regression tests for decoding inputs collected from fuzzing and field failures.
+//!
+//! Each file in `corpus/<message name>/` is decoded into the message named by its directory, such
+//! as `nested.Nested`, with both heap-allocated and fixed-capacity containers. Decoding may fail,
+//! but it must not panic, and messages that decode successfully must round-trip.
+
+extern crate alloc;
+
+#[cfg(test)]
+use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+mod alloc_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/alloc.rs"));
+}
+
+mod fixed_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/fixed.rs"));
+}
+
+/// Bounds the recursion of nested inputs, so they can't overflow the test thread's stack
+#[cfg(test)]
+const MAX_DEPTH: usize = 100;
+
+#[cfg(test)]
+fn encode<M: MessageEncode>(msg: &M) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    let encoded = encoder.into_writer();
+    assert_eq!(encoded.len(), msg.compute_size());
+    encoded
+}
+
+#[cfg(test)]
+fn check<M: MessageDecode + MessageEncode + Default>(input: &[u8]) {
+    let mut msg = M::default();
+    let mut decoder = PbDecoder::new(input);
+    decoder.set_max_depth(Some(MAX_DEPTH));
+    // Malformed inputs are expected to be rejected
+    if msg.decode(&mut decoder, input.len()).is_err() {
+        return;
+    }
+
+    // Compare the encodings rather than the messages, since NaN floats aren't equal to themselves
+    let encoded = encode(&msg);
+    let mut decoded = M::default();
+    decoded.merge_from_slice(&encoded).unwrap();
+    assert_eq!(encode(&decoded), encoded);
+}
+
+#[cfg(test)]
+macro_rules! corpus_test {
+    ($test:ident, $msg:path, $file:literal) => {
+        mod $test {
+            use super::*;
+
+            const INPUT: &[u8] = include_bytes!($file);
+
+            #[test]
+            fn alloc() {
+                use crate::alloc_proto::*;
+                check::<$msg>(INPUT);
+            }
+
+            #[test]
+            fn fixed() {
+                use crate::fixed_proto::*;
+                check::<$msg>(INPUT);
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+include!(concat!(env!("OUT_DIR"), "/corpus.rs"));